@@ -0,0 +1,40 @@
+//! Search benchmarks
+//!
+//! run with `cargo bench --features bench --bench search`
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use swingmusic::core::SearchLib;
+use swingmusic::fixtures::{load_synthetic_library, LibrarySize};
+
+const SIZES: &[usize] = &[1_000, 10_000, 50_000];
+const SEED: u64 = 42;
+
+/// Queries from the fixture word list, a typo and a miss
+const QUERIES: &[(&str, &str)] = &[
+    ("word", "velvet"),
+    ("phrase", "midnight harbor"),
+    ("prefix", "sat"),
+    ("typo", "thundr"),
+    ("miss", "zzqx"),
+];
+
+fn bench_search(c: &mut Criterion) {
+    for &size in SIZES {
+        load_synthetic_library(LibrarySize::tracks(size), SEED);
+
+        let mut group = c.benchmark_group(format!("search/{size}"));
+        for (name, query) in QUERIES {
+            group.bench_function(format!("tracks/{name}"), |b| {
+                b.iter(|| SearchLib::search_tracks(black_box(query), 50))
+            });
+            group.bench_function(format!("all/{name}"), |b| {
+                b.iter(|| SearchLib::search_all(black_box(query), 50, 20, 20))
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_search);
+criterion_main!(benches);
@@ -0,0 +1,69 @@
+//! Serialization benchmarks for the track and card payloads the api returns
+//!
+//! run with `cargo bench --features bench --bench serialization`
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+use swingmusic::api::favorites::{serialize_album_card, serialize_artist_card, serialize_track};
+use swingmusic::fixtures::{load_synthetic_library, LibrarySize};
+use swingmusic::serializers::TrackResponse;
+use swingmusic::stores::{AlbumStore, ArtistStore, TrackStore};
+
+/// Items in a page, matching the default page size of the list endpoints
+const PAGE: usize = 50;
+const SEED: u64 = 42;
+
+fn bench_serialization(c: &mut Criterion) {
+    load_synthetic_library(LibrarySize::tracks(10_000), SEED);
+
+    let tracks: Vec<_> = TrackStore::get().get_all().into_iter().take(PAGE).collect();
+    let albums: Vec<_> = AlbumStore::get().get_all().into_iter().take(PAGE).collect();
+    let artists: Vec<_> = ArtistStore::get()
+        .get_all()
+        .into_iter()
+        .take(PAGE)
+        .collect();
+
+    let mut group = c.benchmark_group("serialization");
+    group.throughput(Throughput::Elements(PAGE as u64));
+
+    group.bench_function("tracks", |b| {
+        b.iter(|| {
+            let items: Vec<_> = tracks.iter().map(|t| serialize_track(t, 1)).collect();
+            serde_json::to_vec(black_box(&items))
+        })
+    });
+    group.bench_function("track_responses", |b| {
+        b.iter(|| {
+            let items: Vec<_> = tracks
+                .iter()
+                .map(|t| TrackResponse::for_user(t.clone(), 1))
+                .collect();
+            serde_json::to_vec(black_box(&items))
+        })
+    });
+    group.bench_function("album_cards", |b| {
+        b.iter_batched_ref(
+            || albums.clone(),
+            |albums| {
+                let items: Vec<_> = albums.iter_mut().map(serialize_album_card).collect();
+                serde_json::to_vec(black_box(&items))
+            },
+            criterion::BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("artist_cards", |b| {
+        b.iter_batched_ref(
+            || artists.clone(),
+            |artists| {
+                let items: Vec<_> = artists.iter_mut().map(serialize_artist_card).collect();
+                serde_json::to_vec(black_box(&items))
+            },
+            criterion::BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_serialization);
+criterion_main!(benches);
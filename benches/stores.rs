@@ -0,0 +1,75 @@
+//! Store query benchmarks
+//!
+//! run with `cargo bench --features bench --bench stores`
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use swingmusic::core::sorting::{SortOrder, TrackSort};
+use swingmusic::core::SortLib;
+use swingmusic::fixtures::{generate_tracks, load_library, LibrarySize, ROOT_DIR};
+use swingmusic::stores::{AlbumStore, ArtistStore, FolderStore, TrackStore};
+
+const SIZES: &[usize] = &[1_000, 10_000, 50_000];
+const SEED: u64 = 42;
+
+fn bench_load(c: &mut Criterion) {
+    let mut group = c.benchmark_group("stores/load");
+    group.sample_size(10);
+
+    for &size in SIZES {
+        let tracks = generate_tracks(LibrarySize::tracks(size), SEED);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &tracks, |b, tracks| {
+            b.iter(|| load_library(tracks.clone()))
+        });
+    }
+    group.finish();
+}
+
+fn bench_queries(c: &mut Criterion) {
+    for &size in SIZES {
+        load_library(generate_tracks(LibrarySize::tracks(size), SEED));
+
+        let tracks = TrackStore::get().get_all();
+        let albums = AlbumStore::get().get_all();
+        let artists = ArtistStore::get().get_all();
+        let track = &tracks[tracks.len() / 2];
+        let album = &albums[albums.len() / 2];
+        let artist = &artists[artists.len() / 2];
+
+        let mut group = c.benchmark_group(format!("stores/{size}"));
+        group.bench_function("track_by_hash", |b| {
+            b.iter(|| TrackStore::get().get_by_hash(black_box(&track.trackhash)))
+        });
+        group.bench_function("tracks_by_album", |b| {
+            b.iter(|| TrackStore::get().get_by_album(black_box(&album.albumhash)))
+        });
+        group.bench_function("tracks_by_artist", |b| {
+            b.iter(|| TrackStore::get().get_by_artist(black_box(&artist.artisthash)))
+        });
+        group.bench_function("tracks_by_folder", |b| {
+            b.iter(|| TrackStore::get().get_by_folder(black_box(&track.folder)))
+        });
+        group.bench_function("albums_by_artist", |b| {
+            b.iter(|| AlbumStore::get().get_by_artist(black_box(&artist.artisthash)))
+        });
+        group.bench_function("artist_by_name", |b| {
+            b.iter(|| ArtistStore::get().get_by_name(black_box(&artist.name)))
+        });
+        group.bench_function("root_folders", |b| {
+            b.iter(|| FolderStore::get().get_children(black_box(ROOT_DIR)))
+        });
+        group.bench_function("all_tracks", |b| b.iter(|| TrackStore::get().get_all()));
+        group.bench_function("all_albums", |b| b.iter(|| AlbumStore::get().get_all()));
+        group.bench_function("sort_tracks_by_title", |b| {
+            b.iter_batched_ref(
+                || tracks.clone(),
+                |tracks| SortLib::sort_tracks(tracks, TrackSort::Title, SortOrder::Ascending),
+                criterion::BatchSize::LargeInput,
+            )
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_load, bench_queries);
+criterion_main!(benches);
@@ -0,0 +1,55 @@
+//! File streaming benchmarks for untranscoded track bodies
+//!
+//! run with `cargo bench --features bench --bench streaming`
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use futures::StreamExt;
+use std::io::Write;
+
+use swingmusic::core::file_cache::{file_stream, StreamTuning};
+
+/// Size of the served file, larger than the mmap cache threshold for small files
+const FILE_SIZE: usize = 64 * 1024 * 1024;
+const CHUNK_KIB: &[usize] = &[64, 256, 1024];
+
+fn bench_streaming(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().expect("tokio runtime");
+
+    let mut file = tempfile::NamedTempFile::new().expect("temp file");
+    let data: Vec<u8> = (0..FILE_SIZE).map(|i| (i % 251) as u8).collect();
+    file.write_all(&data).expect("write temp file");
+    file.flush().expect("flush temp file");
+    let path = file.path().to_path_buf();
+
+    let mut group = c.benchmark_group("streaming");
+    group.throughput(Throughput::Bytes(FILE_SIZE as u64));
+    group.sample_size(20);
+
+    for &kib in CHUNK_KIB {
+        for zero_copy in [true, false] {
+            let tuning = StreamTuning {
+                chunk_size: kib * 1024,
+                zero_copy,
+                sendfile: false,
+            };
+            let mode = if zero_copy { "mmap" } else { "read" };
+
+            group.bench_function(format!("{mode}/{kib}k"), |b| {
+                b.iter(|| {
+                    runtime.block_on(async {
+                        let mut body = file_stream(&path, 0, FILE_SIZE as u64, tuning).unwrap();
+                        let mut total = 0;
+                        while let Some(chunk) = body.next().await {
+                            total += chunk.unwrap().len();
+                        }
+                        assert_eq!(total, FILE_SIZE);
+                    })
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_streaming);
+criterion_main!(benches);
@@ -0,0 +1,35 @@
+//! Artist and genre tag values split with the default separators
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use swingmusic::config::UserConfig;
+use swingmusic::utils::artist_split_detector::split_artists_smart;
+use swingmusic::utils::parsers::{clean_title, parse_year, split_artists, split_genres};
+
+fuzz_target!(|value: &str| {
+    let config = UserConfig::default();
+
+    for parts in [
+        split_artists_smart(
+            value,
+            &config.artist_separators,
+            &config.artist_split_ignore_list,
+        ),
+        split_artists(
+            value,
+            &config.artist_separators,
+            &config.artist_split_ignore_list,
+        ),
+        split_genres(value, &config.genre_separators),
+    ] {
+        for part in parts {
+            assert!(!part.is_empty());
+            assert_eq!(part.trim(), part);
+        }
+    }
+
+    clean_title(value);
+    parse_year(value);
+});
@@ -0,0 +1,18 @@
+//! Lyrics parsed as lrc or plain text and shifted by an arbitrary offset
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use swingmusic::core::lyrics::LyricsLib;
+
+fuzz_target!(|input: (i64, &str)| {
+    let (ms, content) = input;
+
+    let lyrics = LyricsLib::parse_auto(content);
+    if lyrics.is_synced {
+        assert!(lyrics.lines.windows(2).all(|pair| pair[0].time <= pair[1].time));
+    }
+    LyricsLib::to_lrc(&lyrics);
+    LyricsLib::shift_lrc(content, ms);
+});
@@ -0,0 +1,12 @@
+//! Path normalization is stable once applied
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use swingmusic::utils::filesystem::normalize_path;
+
+fuzz_target!(|path: &str| {
+    let normalized = normalize_path(path);
+    assert_eq!(normalize_path(&normalized), normalized);
+});
@@ -0,0 +1,12 @@
+//! Arbitrary bytes read as an audio file the way a scan reads tags
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::path::Path;
+
+use swingmusic::core::indexer::extract_track_from_bytes;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = extract_track_from_bytes(data, Path::new("/music/fuzz/track.mp3"));
+});
@@ -0,0 +1,90 @@
+//! Server status route and the gate keeping library routes closed while loading
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{get, web, HttpResponse, Responder};
+use serde_json::json;
+use utoipa::OpenApi;
+
+use crate::core::indexer::ScanProgress;
+use crate::stores::readiness::{self, RETRY_AFTER_SECS};
+
+/// GET /about
+///
+/// Version, whether the library can be served and which stores are loaded
+#[utoipa::path(
+    responses((status = 200, description = "Success"))
+)]
+#[get("")]
+pub async fn about() -> impl Responder {
+    let scan = ScanProgress::state();
+
+    HttpResponse::Ok().json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "ready": readiness::is_ready(),
+        "stores": readiness::status(),
+        "scanning": scan.running,
+    }))
+}
+
+/// Answer 503 with a Retry-After header until the track store is loaded
+pub async fn require_library<B: MessageBody + 'static>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    if readiness::is_ready() {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    }
+
+    let response = HttpResponse::ServiceUnavailable()
+        .insert_header(("Retry-After", RETRY_AFTER_SECS.to_string()))
+        .json(json!({
+            "error": "The library is still loading",
+            "stores": readiness::status(),
+        }));
+    Ok(req.into_response(response).map_into_right_body())
+}
+
+/// OpenAPI description of the about routes
+#[derive(OpenApi)]
+#[openapi(paths(about,))]
+pub struct ApiDoc;
+
+/// Configure about routes
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(about);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::readiness::{mark_loaded, StoreKind};
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, App};
+
+    #[actix_web::test]
+    async fn test_library_routes_wait_for_tracks() {
+        let app = test::init_service(
+            App::new().service(
+                web::scope("/album")
+                    .wrap(from_fn(require_library))
+                    .route("", web::get().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let resp =
+            test::call_service(&app, test::TestRequest::get().uri("/album").to_request()).await;
+        assert_eq!(resp.status(), 503);
+        assert_eq!(resp.headers().get("Retry-After").unwrap(), "5");
+
+        mark_loaded(StoreKind::Tracks);
+        let resp =
+            test::call_service(&app, test::TestRequest::get().uri("/album").to_request()).await;
+        assert_eq!(resp.status(), 200);
+    }
+}
@@ -0,0 +1,469 @@
+//! Admin-only server maintenance routes
+
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use utoipa::{IntoParams, OpenApi};
+
+use crate::api::identity::{Admin, Authorized};
+use crate::config::{AlbumMergeRules, UserConfig};
+use crate::core::album_merge;
+use crate::core::artist_split::{self, SplitRequest};
+use crate::core::dashboard;
+use crate::core::fingerprint::{self, DEFAULT_DUPLICATE_SIMILARITY};
+use crate::core::indexer::ScanProgress;
+use crate::core::lossless::{self, Verdict};
+use crate::core::maintenance::{self, MaintenanceTasks};
+use crate::db::tables::FingerprintTable;
+use crate::stores::{ArtistStore, TrackStore};
+
+/// GET /admin/dashboard
+///
+/// Compact snapshot of the server for the built-in admin panel
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[get("/dashboard")]
+pub async fn server_dashboard(_admin: Authorized<Admin>) -> impl Responder {
+    // walking the cache directories can take a moment on large libraries
+    match web::block(dashboard::snapshot).await {
+        Ok(Ok(snapshot)) => HttpResponse::Ok().json(snapshot),
+        Ok(Err(e)) => HttpResponse::InternalServerError().json(json!({"msg": e.to_string()})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"msg": e.to_string()})),
+    }
+}
+
+/// GET /admin/db/maintenance
+///
+/// Database file sizes, the schedule and the report of the last run
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[get("/db/maintenance")]
+pub async fn maintenance_status(_admin: Authorized<Admin>) -> impl Responder {
+    let sizes = match maintenance::db_sizes() {
+        Ok(sizes) => sizes,
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"msg": e.to_string()})),
+    };
+    let interval = UserConfig::load()
+        .map(|c| c.db_maintenance_interval)
+        .unwrap_or(0);
+
+    HttpResponse::Ok().json(json!({
+        "running": maintenance::is_running(),
+        "sizes": sizes,
+        "interval_hours": interval,
+        "last_run": maintenance::last_run(),
+    }))
+}
+
+/// POST /admin/db/maintenance
+///
+/// Run vacuum, analyze and reindex, each can be turned off in the body
+#[utoipa::path(
+    request_body(content = Option<MaintenanceTasks>, content_type = "application/json"),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 409, description = "Conflict"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[post("/db/maintenance")]
+pub async fn run_maintenance(
+    _admin: Authorized<Admin>,
+    body: Option<web::Json<MaintenanceTasks>>,
+) -> impl Responder {
+    if maintenance::is_running() {
+        return HttpResponse::Conflict().json(json!({
+            "msg": "Database maintenance is already running"
+        }));
+    }
+
+    // vacuum holds a write lock on the whole database for its duration
+    if ScanProgress::state().running {
+        return HttpResponse::Conflict().json(json!({
+            "msg": "A library scan is running, try again once it finishes"
+        }));
+    }
+
+    let tasks = body.map(|b| b.into_inner()).unwrap_or_default();
+    match maintenance::run(tasks).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => HttpResponse::InternalServerError().json(json!({
+            "msg": e.to_string(),
+            "report": maintenance::last_run(),
+        })),
+    }
+}
+
+/// GET /admin/artists/splits
+///
+/// Every artist split rule in the order they are applied
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[get("/artists/splits")]
+pub async fn list_artist_splits(_admin: Authorized<Admin>) -> impl Responder {
+    HttpResponse::Ok().json(artist_split::rules())
+}
+
+/// GET /admin/artists/{artisthash}/split
+///
+/// Musicbrainz ids, folders and albums the artist's tracks could be split by
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 404, description = "Not found")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[get("/artists/{artisthash}/split")]
+pub async fn artist_split_hints(
+    _admin: Authorized<Admin>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let artisthash = path.into_inner();
+    let Some(artist) = ArtistStore::get().get_by_hash(&artisthash) else {
+        return HttpResponse::NotFound().json(json!({"msg": "Artist not found"}));
+    };
+    let splits: Vec<_> = artist_split::rules()
+        .into_iter()
+        .filter(|r| r.artisthash == artisthash || r.newhash == artisthash)
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "artist": {"artisthash": artist.artisthash, "name": artist.name},
+        "hints": artist_split::hints(&artisthash),
+        "splits": splits,
+    }))
+}
+
+/// POST /admin/artists/split
+///
+/// Move the tracks of an artist matching a musicbrainz id, folder or albums to a new artist
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[post("/artists/split")]
+pub async fn split_artist(
+    _admin: Authorized<Admin>,
+    body: web::Json<SplitRequest>,
+) -> impl Responder {
+    let rule = match artist_split::plan(&body) {
+        Ok(rule) => rule,
+        Err(msg) => return HttpResponse::BadRequest().json(json!({"msg": msg})),
+    };
+    match artist_split::create(rule).await {
+        Ok((split, moved)) => HttpResponse::Ok().json(json!({
+            "split": split,
+            "moved": moved,
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"msg": e.to_string()})),
+    }
+}
+
+/// DELETE /admin/artists/splits/{id}
+///
+/// Delete a split and merge its tracks back into the original artist
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Conflict"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[delete("/artists/splits/{id}")]
+pub async fn delete_artist_split(
+    _admin: Authorized<Admin>,
+    path: web::Path<i64>,
+) -> impl Responder {
+    let Some(rule) = artist_split::get(path.into_inner()) else {
+        return HttpResponse::NotFound().json(json!({"msg": "Split not found"}));
+    };
+    if let Some(child) = artist_split::dependent(&rule) {
+        return HttpResponse::Conflict().json(json!({
+            "msg": format!("Split {} depends on this one, delete it first", child.id)
+        }));
+    }
+
+    match artist_split::remove(rule.id).await {
+        Ok(moved) => HttpResponse::Ok().json(json!({"moved": moved})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"msg": e.to_string()})),
+    }
+}
+
+/// GET /admin/fingerprints
+///
+/// Whether fingerprinting is on, running, and how many files have a fingerprint
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[get("/fingerprints")]
+pub async fn fingerprint_status(_admin: Authorized<Admin>) -> impl Responder {
+    let enabled = UserConfig::load()
+        .map(|c| c.enable_fingerprinting)
+        .unwrap_or(false);
+    let count = match FingerprintTable::count().await {
+        Ok(count) => count,
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"msg": e.to_string()})),
+    };
+
+    HttpResponse::Ok().json(json!({
+        "enabled": enabled,
+        "running": fingerprint::is_running(),
+        "fingerprinted": count,
+        "tracks": TrackStore::get().count(),
+    }))
+}
+
+/// POST /admin/fingerprints/scan
+///
+/// Fingerprint new and changed files now instead of after the next scan
+#[utoipa::path(
+    responses(
+        (status = 202, description = "Accepted"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[post("/fingerprints/scan")]
+pub async fn run_fingerprints(_admin: Authorized<Admin>) -> impl Responder {
+    let enabled = UserConfig::load()
+        .map(|c| c.enable_fingerprinting)
+        .unwrap_or(false);
+    if !enabled {
+        return HttpResponse::BadRequest().json(json!({"msg": "Fingerprinting is turned off"}));
+    }
+
+    fingerprint::spawn_pass();
+    HttpResponse::Accepted().json(json!({"msg": "Fingerprinting started"}))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DuplicatesQuery {
+    #[serde(default = "default_similarity")]
+    pub similarity: f64,
+}
+
+fn default_similarity() -> f64 {
+    DEFAULT_DUPLICATE_SIMILARITY
+}
+
+/// GET /admin/fingerprints/duplicates
+///
+/// Groups of files with matching fingerprints, whatever their tags
+#[utoipa::path(
+    params(DuplicatesQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[get("/fingerprints/duplicates")]
+pub async fn fingerprint_duplicates(
+    _admin: Authorized<Admin>,
+    query: web::Query<DuplicatesQuery>,
+) -> impl Responder {
+    if !(0.5..=1.0).contains(&query.similarity) {
+        return HttpResponse::BadRequest()
+            .json(json!({"msg": "similarity must be between 0.5 and 1"}));
+    }
+
+    match fingerprint::duplicates(query.similarity).await {
+        Ok(groups) => HttpResponse::Ok().json(json!({
+            "count": groups.len(),
+            "groups": groups,
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"msg": e.to_string()})),
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct LosslessQuery {
+    /// files listed, `transcode` by default
+    #[serde(default = "default_verdict")]
+    pub verdict: String,
+}
+
+fn default_verdict() -> String {
+    Verdict::Transcode.as_str().to_string()
+}
+
+/// GET /admin/lossless
+///
+/// How many lossless files passed the spectral check and which ones look like
+/// transcodes from a lossy source
+#[utoipa::path(
+    params(LosslessQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[get("/lossless")]
+pub async fn lossless_report(
+    _admin: Authorized<Admin>,
+    query: web::Query<LosslessQuery>,
+) -> impl Responder {
+    let Some(verdict) = Verdict::from_name(&query.verdict) else {
+        return HttpResponse::BadRequest().json(json!({
+            "msg": "verdict must be lossless, transcode, uncertain or failed"
+        }));
+    };
+
+    match lossless::report(verdict).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"msg": e.to_string()})),
+    }
+}
+
+/// POST /admin/lossless/scan
+///
+/// Check new and changed lossless files in the background
+#[utoipa::path(
+    responses(
+        (status = 202, description = "Accepted"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[post("/lossless/scan")]
+pub async fn run_lossless_checks(_admin: Authorized<Admin>) -> impl Responder {
+    lossless::spawn_pass();
+    HttpResponse::Accepted().json(json!({"msg": "Lossless check started"}))
+}
+
+/// POST /admin/albums/merge-preview
+///
+/// Albums the merge rules would merge, the posted rules are applied over the
+/// saved ones so changes can be previewed before saving them
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[post("/albums/merge-preview")]
+pub async fn album_merge_preview(
+    _admin: Authorized<Admin>,
+    body: Option<web::Json<Value>>,
+) -> impl Responder {
+    let (enabled, saved) = {
+        let config = UserConfig::global();
+        let config = config.read();
+        (config.merge_albums, config.album_merge_rules)
+    };
+
+    let mut merged = serde_json::to_value(saved).unwrap_or_default();
+    if let (Some(target), Some(patch)) = (
+        merged.as_object_mut(),
+        body.as_ref().and_then(|b| b.as_object()),
+    ) {
+        for (k, v) in patch {
+            target.insert(k.clone(), v.clone());
+        }
+    }
+    let rules = match serde_json::from_value::<AlbumMergeRules>(merged) {
+        Ok(rules) => rules.normalized(),
+        Err(e) => return HttpResponse::BadRequest().json(json!({"msg": e.to_string()})),
+    };
+
+    let groups = album_merge::preview(&rules);
+    let albums: usize = groups.iter().map(|g| g.members.len()).sum();
+    HttpResponse::Ok().json(json!({
+        "enabled": enabled,
+        "rules": rules,
+        "count": groups.len(),
+        "albums": albums,
+        "groups": groups,
+    }))
+}
+
+/// OpenAPI description of the admin routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    server_dashboard,
+    maintenance_status,
+    run_maintenance,
+    list_artist_splits,
+    artist_split_hints,
+    split_artist,
+    delete_artist_split,
+    fingerprint_status,
+    run_fingerprints,
+    fingerprint_duplicates,
+    lossless_report,
+    run_lossless_checks,
+    album_merge_preview,
+))]
+pub struct ApiDoc;
+
+/// Configure admin routes
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(server_dashboard)
+        .service(maintenance_status)
+        .service(run_maintenance)
+        .service(list_artist_splits)
+        .service(artist_split_hints)
+        .service(split_artist)
+        .service(delete_artist_split)
+        .service(fingerprint_status)
+        .service(run_fingerprints)
+        .service(fingerprint_duplicates)
+        .service(lossless_report)
+        .service(run_lossless_checks)
+        .service(album_merge_preview);
+}
@@ -1,18 +1,23 @@
 //! Album API routes (upstream-compatible)
 
-use actix_web::{get, post, web, HttpResponse, Responder};
+use actix_web::http::header::ContentDisposition;
+use actix_web::{get, post, put, web, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::collections::{HashMap, HashSet};
-
-use crate::core::{AlbumLib, SortLib};
-use crate::db::tables::SimilarArtistTable;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::api::identity::{Authorized, CurrentUser, Download, EditTags};
+use crate::api::imgserver::{insert_image_hints, CardImage};
+use crate::core::archive;
+use crate::core::bulk_edit::{self, AlbumTagEdit};
+use crate::core::gapless;
+use crate::core::{album_stats, AlbumLib, SortLib};
+use crate::db::tables::{DiscoveryTable, SimilarArtistTable};
 use crate::models::{Album, Track};
-use crate::stores::{AlbumStore, TrackStore};
+use crate::stores::{AlbumStore, PlayStatsStore, TrackStore};
 use crate::utils::hashing::create_hash;
 
-const USER_ID: i64 = 0;
-
 /// Album response
 #[derive(Debug, Serialize)]
 pub struct AlbumResponse {
@@ -24,6 +29,8 @@ pub struct AlbumResponse {
     pub count: i32,
     pub image: String,
     pub color: Option<String>,
+    pub color_dark: Option<String>,
+    pub color_light: Option<String>,
     pub is_favorite: bool,
     pub genres: Vec<String>,
 }
@@ -58,7 +65,7 @@ pub struct StatItem {
 }
 
 /// Query parameters for album list
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct AlbumListQuery {
     pub page: Option<usize>,
     pub limit: Option<usize>,
@@ -69,14 +76,14 @@ fn default_album_limit() -> i64 {
     6
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct AlbumInfoBody {
     pub albumhash: String,
     #[serde(default = "default_album_limit", alias = "albumlimit")]
     pub limit: i64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct MoreFromArtistsBody {
     pub albumartists: Vec<String>,
     pub base_title: String,
@@ -84,13 +91,13 @@ pub struct MoreFromArtistsBody {
     pub limit: i64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct AlbumVersionsBody {
     pub og_album_title: String,
     pub albumhash: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct SimilarAlbumsQuery {
     pub artisthash: String,
     #[serde(default = "default_album_limit", alias = "albumlimit")]
@@ -98,13 +105,22 @@ pub struct SimilarAlbumsQuery {
 }
 
 /// Get all albums
+#[utoipa::path(
+    params(AlbumListQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("")]
-pub async fn get_albums(query: web::Query<AlbumListQuery>) -> impl Responder {
+pub async fn get_albums(user: CurrentUser, query: web::Query<AlbumListQuery>) -> impl Responder {
     let page = query.page.unwrap_or(0);
     let limit = query.limit.unwrap_or(50);
     let sort = query.sort.as_deref().unwrap_or("title:asc");
 
     let mut albums = AlbumStore::get().get_all();
+    PlayStatsStore::get().personalize_albums(user.id, &mut albums);
 
     // Sort albums
     let (sort_by, sort_order) = SortLib::parse_album_sort(sort);
@@ -133,7 +149,9 @@ pub async fn get_albums(query: web::Query<AlbumListQuery>) -> impl Responder {
             } else {
                 Some(a.color.clone())
             },
-            is_favorite: a.is_favorite(USER_ID),
+            color_dark: (!a.color_dark.is_empty()).then(|| a.color_dark.clone()),
+            color_light: (!a.color_light.is_empty()).then(|| a.color_light.clone()),
+            is_favorite: a.is_favorite(user.id),
             genres: a.genre_names(),
         })
         .collect();
@@ -147,8 +165,16 @@ pub async fn get_albums(query: web::Query<AlbumListQuery>) -> impl Responder {
 }
 
 /// Upstream-compatible album info (POST /album)
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[post("")]
-pub async fn get_album_info(body: web::Json<AlbumInfoBody>) -> impl Responder {
+pub async fn get_album_info(user: CurrentUser, body: web::Json<AlbumInfoBody>) -> impl Responder {
     let albumhash = &body.albumhash;
     let limit = body.limit.max(0) as usize;
 
@@ -156,7 +182,10 @@ pub async fn get_album_info(body: web::Json<AlbumInfoBody>) -> impl Responder {
         return HttpResponse::NotFound().json(json!({"error": "Album not found"}));
     };
 
-    let tracks = TrackStore::get().get_by_album(albumhash);
+    // a grouped single opens its virtual album
+    let mut tracks = TrackStore::get().get_by_album(&album.albumhash);
+    PlayStatsStore::get().personalize_tracks(user.id, &mut tracks);
+    PlayStatsStore::get().personalize_albums(user.id, std::slice::from_mut(&mut album));
 
     album.trackcount = tracks.len() as i32;
     album.duration = tracks.iter().map(|t| t.duration).sum();
@@ -184,15 +213,20 @@ pub async fn get_album_info(body: web::Json<AlbumInfoBody>) -> impl Responder {
 
     let stats = build_track_group_stats(&tracks, true);
 
+    let discovered_at = DiscoveryTable::get(user.id, "album", &album.albumhash)
+        .await
+        .unwrap_or_default();
+
     let mut info = serde_json::to_value(&album).unwrap_or_else(|_| json!({}));
     if let Some(map) = info.as_object_mut() {
-        map.insert("is_favorite".to_string(), json!(album.is_favorite(USER_ID)));
+        map.insert("is_favorite".to_string(), json!(album.is_favorite(user.id)));
+        map.insert("discovered_at".to_string(), json!(discovered_at));
         map.remove("help_text");
     }
 
     let serialized_tracks: Vec<_> = tracks
         .iter()
-        .map(|t| serialize_track_for_album(t, false))
+        .map(|t| serialize_track_for_album(t, user.id, false))
         .collect();
 
     let more_from = get_more_from_artist_inner(MoreFromArtistsBody {
@@ -210,10 +244,7 @@ pub async fn get_album_info(body: web::Json<AlbumInfoBody>) -> impl Responder {
         albumhash: albumhash.clone(),
     });
 
-    let copyright = tracks
-        .first()
-        .and_then(|t| t.copyright.clone())
-        .unwrap_or_default();
+    let details = AlbumLib::details(&tracks);
 
     HttpResponse::Ok().json(json!({
         "stats": stats,
@@ -221,8 +252,12 @@ pub async fn get_album_info(body: web::Json<AlbumInfoBody>) -> impl Responder {
         "extra": {
             "track_total": track_total,
             "avg_bitrate": avg_bitrate,
+            "filesize": details.filesize,
+            "codec": details.codec,
         },
-        "copyright": copyright,
+        "copyright": details.copyright.unwrap_or_default(),
+        "label": details.label,
+        "discs": details.discs,
         "tracks": serialized_tracks,
         "more_from": more_from,
         "other_versions": other_versions,
@@ -230,8 +265,16 @@ pub async fn get_album_info(body: web::Json<AlbumInfoBody>) -> impl Responder {
 }
 
 /// Get album by hash (legacy GET)
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/{albumhash}")]
-pub async fn get_album(path: web::Path<String>) -> impl Responder {
+pub async fn get_album(user: CurrentUser, path: web::Path<String>) -> impl Responder {
     let albumhash = path.into_inner();
 
     match AlbumStore::get().get_by_hash(&albumhash) {
@@ -260,7 +303,9 @@ pub async fn get_album(path: web::Path<String>) -> impl Responder {
                     } else {
                         Some(album.color.clone())
                     },
-                    is_favorite: album.is_favorite(USER_ID),
+                    color_dark: (!album.color_dark.is_empty()).then(|| album.color_dark.clone()),
+                    color_light: (!album.color_light.is_empty()).then(|| album.color_light.clone()),
+                    is_favorite: album.is_favorite(user.id),
                     genres: album.genre_names(),
                 },
                 tracks: tracks
@@ -286,6 +331,9 @@ pub async fn get_album(path: web::Path<String>) -> impl Responder {
 }
 
 /// Get album tracks
+#[utoipa::path(
+    responses((status = 200, description = "Success"))
+)]
 #[get("/{albumhash}/tracks")]
 pub async fn get_album_tracks(path: web::Path<String>) -> impl Responder {
     let albumhash = path.into_inner();
@@ -307,19 +355,144 @@ pub async fn get_album_tracks(path: web::Path<String>) -> impl Responder {
     HttpResponse::Ok().json(response)
 }
 
+/// Listening stats of the current user for an album
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/{albumhash}/stats")]
+pub async fn get_album_stats(user: CurrentUser, path: web::Path<String>) -> impl Responder {
+    let albumhash = path.into_inner();
+
+    if AlbumStore::get().get_by_hash(&albumhash).is_none() {
+        return HttpResponse::NotFound().json(json!({
+            "error": "Album not found"
+        }));
+    }
+
+    let tracks = AlbumLib::get_tracks(&albumhash);
+    match album_stats::for_album(user.id, &albumhash, &tracks).await {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(e) => {
+            tracing::warn!("Failed to load listening stats for {}: {}", albumhash, e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to load album stats"
+            }))
+        }
+    }
+}
+
+/// Download the original files of an album as a zip built while it is sent
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/{albumhash}/download")]
+pub async fn download_album(
+    _user: Authorized<Download>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let Some(album) = AlbumStore::get().get_by_hash(&path.into_inner()) else {
+        return HttpResponse::NotFound().json(json!({"error": "Album not found"}));
+    };
+
+    let mut tracks = TrackStore::get().get_by_album(&album.albumhash);
+    SortLib::sort_tracks_album_order(&mut tracks);
+    tracks.retain(|t| std::path::Path::new(&t.filepath).is_file());
+    if tracks.is_empty() {
+        return HttpResponse::NotFound().json(json!({"error": "No files to download"}));
+    }
+
+    let title = format!("{} - {}", album.albumartist(), album.title);
+    HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header(ContentDisposition::attachment(archive::archive_name(
+            &title,
+        )))
+        .streaming(archive::zip_stream(archive::album_entries(&tracks)))
+}
+
+/// Write album tags to every track of an album
+///
+/// the album hash changes when the title or album artist does, so the new one
+/// is returned
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[put("/{albumhash}/tags")]
+pub async fn update_album_tags(
+    _user: Authorized<EditTags>,
+    path: web::Path<String>,
+    body: web::Json<AlbumTagEdit>,
+) -> impl Responder {
+    let albumhash = path.into_inner();
+    if AlbumStore::get().get_by_hash(&albumhash).is_none() {
+        return HttpResponse::NotFound().json(json!({
+            "error": "Album not found"
+        }));
+    }
+
+    let edit = body.into_inner().cleaned();
+    if edit.is_empty() {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "No tags to update"
+        }));
+    }
+
+    match bulk_edit::edit_album(&albumhash, edit).await {
+        Ok(tracks) => HttpResponse::Ok().json(json!({
+            "albumhash": tracks.first().map(|t| t.albumhash.clone()).unwrap_or(albumhash),
+            "updated": tracks.len(),
+        })),
+        Err(e) => {
+            tracing::error!("failed to update tags of album {}: {:#}", albumhash, e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("{:#}", e)
+            }))
+        }
+    }
+}
+
 /// Get more albums from the given artists (upstream parity)
+#[utoipa::path(
+    responses((status = 200, description = "Success"))
+)]
 #[post("/from-artist")]
 pub async fn get_more_from_artist(body: web::Json<MoreFromArtistsBody>) -> impl Responder {
     HttpResponse::Ok().json(json!(get_more_from_artist_inner(body.into_inner())))
 }
 
 /// Get other versions of the given album (upstream parity)
+#[utoipa::path(
+    responses((status = 200, description = "Success"))
+)]
 #[post("/other-versions")]
 pub async fn get_album_versions(body: web::Json<AlbumVersionsBody>) -> impl Responder {
     HttpResponse::Ok().json(json!(get_album_versions_inner(body.into_inner())))
 }
 
 /// Get similar albums based on similar artists
+#[utoipa::path(
+    params(SimilarAlbumsQuery),
+    responses((status = 200, description = "Success"))
+)]
 #[get("/similar")]
 pub async fn get_similar_albums(query: web::Query<SimilarAlbumsQuery>) -> impl Responder {
     let limit = query.limit.max(0) as usize;
@@ -406,11 +579,12 @@ fn serialize_album_card(album: &Album) -> serde_json::Value {
         }
 
         map.insert("type".to_string(), json!("album"));
+        insert_image_hints(map, CardImage::Thumbnail);
     }
     value
 }
 
-fn serialize_track_for_album(track: &Track, remove_disc: bool) -> serde_json::Value {
+fn serialize_track_for_album(track: &Track, user_id: i64, remove_disc: bool) -> serde_json::Value {
     let mut value = serde_json::to_value(track).unwrap_or_else(|_| json!({}));
     if let Some(map) = value.as_object_mut() {
         let mut to_remove: HashSet<String> = [
@@ -475,8 +649,13 @@ fn serialize_track_for_album(track: &Track, remove_disc: bool) -> serde_json::Va
         // Add computed fields that must be present in the output
         map.insert(
             "is_favorite".to_string(),
-            serde_json::Value::Bool(track.is_favorite(USER_ID)),
+            serde_json::Value::Bool(track.is_favorite(user_id)),
+        );
+        map.insert(
+            "gapless_with_next".to_string(),
+            serde_json::Value::Bool(gapless::gapless_with_next(&track.trackhash)),
         );
+        insert_image_hints(map, CardImage::Thumbnail);
     }
 
     value
@@ -713,11 +892,30 @@ fn artisthash_is_in_album(hash: &str, album: &Album) -> bool {
     album.artisthashes.iter().any(|h| h == hash)
 }
 
+/// OpenAPI description of the album routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    get_albums,
+    get_album,
+    get_album_tracks,
+    get_album_stats,
+    download_album,
+    update_album_tags,
+    get_album_info,
+    get_more_from_artist,
+    get_album_versions,
+    get_similar_albums,
+))]
+pub struct ApiDoc;
+
 /// Configure album routes
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(get_albums)
         .service(get_album)
         .service(get_album_tracks)
+        .service(get_album_stats)
+        .service(download_album)
+        .service(update_album_tags)
         .service(get_album_info)
         .service(get_more_from_artist)
         .service(get_album_versions)
@@ -1,14 +1,20 @@
 //! Artist API routes
 
-use actix_web::{get, web, HttpResponse, Responder};
+use actix_multipart::Multipart;
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
 use chrono::{DateTime, Datelike, Utc};
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use utoipa::{IntoParams, OpenApi, ToSchema};
 
-use crate::core::{ArtistLib, SortLib};
-use crate::db::tables::SimilarArtistTable;
+use crate::api::identity::{Authorized, CurrentUser, EditTags};
+use crate::api::imgserver::{insert_image_hints, CardImage};
+use crate::config::ArtistImageProvider;
+use crate::core::{artist_stats, bulk_edit, images, similarity, ArtistLib, SortLib, TrackSources};
+use crate::db::tables::DiscoveryTable;
 use crate::models::{Album, Artist, Track};
-use crate::stores::{AlbumStore, ArtistStore, TrackStore};
+use crate::stores::{AlbumStore, ArtistStore, PlayStatsStore, TrackStore};
 
 /// Artist response
 #[derive(Debug, Serialize)]
@@ -17,6 +23,8 @@ pub struct ArtistResponse {
     pub name: String,
     pub image: String,
     pub color: Option<String>,
+    pub color_dark: Option<String>,
+    pub color_light: Option<String>,
     pub is_favorite: bool,
     pub trackcount: i32,
     pub albumcount: i32,
@@ -41,7 +49,7 @@ pub struct ArtistInfoResponse {
 }
 
 /// Query parameters for artist list
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct ArtistListQuery {
     pub page: Option<usize>,
     pub limit: Option<usize>,
@@ -49,7 +57,7 @@ pub struct ArtistListQuery {
 }
 
 /// query parameters for get_artist endpoint
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct GetArtistQuery {
     /// the number of tracks to return. -1 means all tracks
     pub tracklimit: Option<i32>,
@@ -59,27 +67,54 @@ pub struct GetArtistQuery {
     pub all: Option<bool>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct ArtistAlbumsQuery {
     pub limit: Option<usize>,
     pub albumlimit: Option<usize>,
     pub all: Option<bool>,
 }
 
+/// query parameters for the artist tracks endpoint
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ArtistTracksQuery {
+    /// include tracks on albums by other artists
+    #[serde(default = "default_true")]
+    pub appearances: bool,
+    /// include tracks on compilations
+    #[serde(default = "default_true")]
+    pub compilations: bool,
+    /// group the tracks by the album they come from
+    #[serde(default)]
+    pub group: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
 /// query parameters for similar artists endpoint
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct SimilarArtistsQuery {
     pub limit: Option<usize>,
 }
 
 /// Get all artists
+#[utoipa::path(
+    params(ArtistListQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("")]
-pub async fn get_artists(query: web::Query<ArtistListQuery>) -> impl Responder {
+pub async fn get_artists(user: CurrentUser, query: web::Query<ArtistListQuery>) -> impl Responder {
     let page = query.page.unwrap_or(0);
     let limit = query.limit.unwrap_or(50);
     let sort = query.sort.as_deref().unwrap_or("name:asc");
 
     let mut artists = ArtistStore::get().get_all();
+    PlayStatsStore::get().personalize_artists(user.id, &mut artists);
 
     // Sort artists
     let (sort_by, sort_order) = SortLib::parse_artist_sort(sort);
@@ -97,13 +132,15 @@ pub async fn get_artists(query: web::Query<ArtistListQuery>) -> impl Responder {
             } else {
                 Some(a.color.clone())
             };
-            let is_fav = a.is_favorite(1);
+            let is_fav = a.is_favorite(user.id);
             let genres = a.genre_names();
             ArtistResponse {
                 artisthash: a.artisthash,
                 name: a.name,
                 image: a.image,
                 color: color_val,
+                color_dark: (!a.color_dark.is_empty()).then(|| a.color_dark.clone()),
+                color_light: (!a.color_light.is_empty()).then(|| a.color_light.clone()),
                 is_favorite: is_fav,
                 trackcount: a.trackcount,
                 albumcount: a.albumcount,
@@ -121,8 +158,18 @@ pub async fn get_artists(query: web::Query<ArtistListQuery>) -> impl Responder {
 }
 
 /// Get artist by hash
+#[utoipa::path(
+    params(GetArtistQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/{artisthash}")]
 pub async fn get_artist(
+    user: CurrentUser,
     path: web::Path<String>,
     query: web::Query<GetArtistQuery>,
 ) -> impl Responder {
@@ -138,8 +185,9 @@ pub async fn get_artist(
             } else {
                 Some(artist.color.clone())
             };
-            let is_fav = artist.is_favorite(1);
+            let is_fav = artist.is_favorite(user.id);
             let mut tracks = TrackStore::get().get_by_artist(&artisthash);
+            PlayStatsStore::get().personalize_tracks(user.id, &mut tracks);
             tracks.sort_by(|a, b| {
                 b.date
                     .cmp(&a.date)
@@ -166,13 +214,23 @@ pub async fn get_artist(
             let tracks_limited: Vec<_> = tracks
                 .iter()
                 .take(limit)
-                .map(|t| serialize_track_with_help(t))
+                .map(|t| serialize_track_with_help(t, user.id))
                 .collect();
 
             let genres = build_genres_with_decade(&artist);
             let stats = get_track_group_stats(&tracks, false);
+            let listening = match artist_stats::for_artist(user.id, &artisthash, &tracks).await {
+                Ok(listening) => Some(listening),
+                Err(e) => {
+                    tracing::warn!("Failed to load listening stats for {}: {}", artisthash, e);
+                    None
+                }
+            };
             let albums_grouped =
                 get_artist_albums_inner(&artisthash, albumlimit, return_all_albums);
+            let discovered_at = DiscoveryTable::get(user.id, "artist", &artisthash)
+                .await
+                .unwrap_or_default();
 
             HttpResponse::Ok().json(serde_json::json!({
                 "artist": {
@@ -180,15 +238,19 @@ pub async fn get_artist(
                     "name": artist.name,
                     "image": artist.image,
                     "color": color_val,
+                    "color_dark": (!artist.color_dark.is_empty()).then(|| artist.color_dark.clone()),
+                    "color_light": (!artist.color_light.is_empty()).then(|| artist.color_light.clone()),
                     "is_favorite": is_fav,
                     "duration": duration,
                     "trackcount": tcount as i32,
                     "albumcount": artist.albumcount,
                     "genres": genres,
+                    "discovered_at": discovered_at,
                 },
                 "tracks": tracks_limited,
                 "albums": albums_grouped,
                 "stats": stats,
+                "listening": listening,
             }))
         }
         None => HttpResponse::NotFound().json(serde_json::json!({
@@ -198,6 +260,10 @@ pub async fn get_artist(
 }
 
 /// Get artist albums
+#[utoipa::path(
+    params(ArtistAlbumsQuery),
+    responses((status = 200, description = "Success"))
+)]
 #[get("/{artisthash}/albums")]
 pub async fn get_artist_albums(
     path: web::Path<String>,
@@ -211,21 +277,279 @@ pub async fn get_artist_albums(
     HttpResponse::Ok().json(albums)
 }
 
+/// OpenAPI description of the artist routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    get_artists,
+    get_artist,
+    get_artist_tracks,
+    get_artist_albums,
+    get_similar_artists,
+    get_related_artists,
+    rename_artist,
+    fetch_artist_image,
+    upload_artist_image,
+    reset_artist_image,
+))]
+pub struct ApiDoc;
+
 /// Configure artist routes
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(get_artists)
         .service(get_artist)
         .service(get_artist_tracks)
         .service(get_artist_albums)
-        .service(get_similar_artists);
+        .service(get_similar_artists)
+        .service(get_related_artists)
+        .service(rename_artist)
+        .service(fetch_artist_image)
+        .service(upload_artist_image)
+        .service(reset_artist_image);
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RenameArtistBody {
+    pub name: String,
+}
+
+/// Rename an artist in the tags of all its tracks
+///
+/// the artist hash follows the name, so the new one is returned
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[put("/{artisthash}/rename")]
+pub async fn rename_artist(
+    _user: Authorized<EditTags>,
+    path: web::Path<String>,
+    body: web::Json<RenameArtistBody>,
+) -> impl Responder {
+    let artisthash = path.into_inner();
+    let artist = match ArtistStore::get().get_by_hash(&artisthash) {
+        Some(a) => a,
+        None => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Artist not found"
+            }))
+        }
+    };
+
+    let name = body.name.trim();
+    if name.is_empty() || name == artist.name {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Give a new artist name"
+        }));
+    }
+
+    match bulk_edit::rename_artist(&artisthash, name).await {
+        Ok(tracks) => {
+            let newhash = tracks
+                .iter()
+                .flat_map(|t| t.artists.iter().chain(&t.albumartists))
+                .find(|a| a.name == name)
+                .map(|a| a.artisthash.clone());
+            HttpResponse::Ok().json(serde_json::json!({
+                "artisthash": newhash,
+                "updated": tracks.len(),
+            }))
+        }
+        Err(e) => {
+            tracing::error!("failed to rename artist {}: {:#}", artisthash, e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("{:#}", e)
+            }))
+        }
+    }
+}
+
+/// Largest artist image accepted from an upload
+const MAX_ARTIST_IMAGE_BYTES: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ArtistImageBody {
+    pub provider: ArtistImageProvider,
+}
+
+/// New image of an artist, the version busts client caches of the old one
+fn artist_image_response(artisthash: &str, provider: Option<&str>) -> HttpResponse {
+    let artist = ArtistStore::get().get_by_hash(artisthash);
+    let image = artist
+        .as_ref()
+        .map(|a| a.image.clone())
+        .filter(|i| !i.is_empty())
+        .map(|i| format!("{}?v={}", i, Utc::now().timestamp()))
+        .unwrap_or_default();
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "image": image,
+        "provider": provider,
+        "color": artist.map(|a| a.color).unwrap_or_default(),
+    }))
+}
+
+fn artist_image_error(artisthash: &str, e: anyhow::Error) -> HttpResponse {
+    tracing::error!("failed to update image of artist {}: {:#}", artisthash, e);
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "error": format!("{:#}", e)
+    }))
+}
+
+/// Replace an artist's image with the one a provider has
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[put("/{artisthash}/image")]
+pub async fn fetch_artist_image(
+    _user: Authorized<EditTags>,
+    path: web::Path<String>,
+    body: web::Json<ArtistImageBody>,
+) -> impl Responder {
+    let artisthash = path.into_inner();
+    if ArtistStore::get().get_by_hash(&artisthash).is_none() {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Artist not found"
+        }));
+    }
+
+    match images::fetch_artist_image_from(&artisthash, body.provider).await {
+        Ok(true) => artist_image_response(&artisthash, Some(body.provider.as_str())),
+        Ok(false) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("{} has no image of this artist", body.provider.as_str())
+        })),
+        Err(e) => artist_image_error(&artisthash, e),
+    }
+}
+
+/// Upload an image to use for an artist
+#[utoipa::path(
+    request_body(content_type = "multipart/form-data", description = "Image file upload"),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found"),
+        (status = 413, description = "Payload too large")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[post("/{artisthash}/image")]
+pub async fn upload_artist_image(
+    _user: Authorized<EditTags>,
+    path: web::Path<String>,
+    mut payload: Multipart,
+) -> impl Responder {
+    let artisthash = path.into_inner();
+    if ArtistStore::get().get_by_hash(&artisthash).is_none() {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Artist not found"
+        }));
+    }
+
+    let mut image: Option<Vec<u8>> = None;
+    while let Some(Ok(mut field)) = payload.next().await {
+        let is_image = field.content_disposition().get_name() == Some("image");
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field.next().await {
+            let Ok(data) = chunk else {
+                continue;
+            };
+            if bytes.len() + data.len() > MAX_ARTIST_IMAGE_BYTES {
+                return HttpResponse::PayloadTooLarge().json(serde_json::json!({
+                    "error": format!("Images are limited to {} bytes", MAX_ARTIST_IMAGE_BYTES)
+                }));
+            }
+            bytes.extend_from_slice(&data);
+        }
+
+        if is_image {
+            image = Some(bytes);
+        }
+    }
+
+    let Some(image) = image.filter(|i| !i.is_empty()) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Send the image in an image field"
+        }));
+    };
+    if image::guess_format(&image).is_err() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Unsupported image format"
+        }));
+    }
+
+    match images::set_artist_image(&artisthash, &image).await {
+        Ok(()) => artist_image_response(&artisthash, None),
+        Err(e) => artist_image_error(&artisthash, e),
+    }
+}
+
+/// Drop an artist's image and look it up again in the configured provider order
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[delete("/{artisthash}/image")]
+pub async fn reset_artist_image(
+    _user: Authorized<EditTags>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let artisthash = path.into_inner();
+    if ArtistStore::get().get_by_hash(&artisthash).is_none() {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Artist not found"
+        }));
+    }
+
+    match images::reset_artist_image(&artisthash).await {
+        Ok(provider) => artist_image_response(&artisthash, provider.map(|p| p.as_str())),
+        Err(e) => artist_image_error(&artisthash, e),
+    }
 }
 
 /// Get artist tracks (all)
+#[utoipa::path(
+    params(ArtistTracksQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/{artisthash}/tracks")]
-pub async fn get_artist_tracks(path: web::Path<String>) -> impl Responder {
+pub async fn get_artist_tracks(
+    user: CurrentUser,
+    path: web::Path<String>,
+    query: web::Query<ArtistTracksQuery>,
+) -> impl Responder {
     let artisthash = path.into_inner();
+    let sources = TrackSources {
+        appearances: query.appearances,
+        compilations: query.compilations,
+    };
 
-    let mut tracks = ArtistLib::get_tracks(&artisthash);
+    let mut tracks = ArtistLib::get_tracks_from(&artisthash, sources);
+    PlayStatsStore::get().personalize_tracks(user.id, &mut tracks);
     tracks.sort_by(|a, b| {
         b.date
             .cmp(&a.date)
@@ -233,15 +557,48 @@ pub async fn get_artist_tracks(path: web::Path<String>) -> impl Responder {
             .then_with(|| a.disc.cmp(&b.disc))
             .then_with(|| a.track.cmp(&b.track))
     });
+
+    if query.group {
+        let albums = AlbumStore::get();
+        let groups = ArtistLib::group_by_album(tracks)
+            .into_iter()
+            .map(|(albumhash, tracks)| {
+                let album = albums.get_by_hash(&albumhash);
+                let album_type = album.as_ref().map(|a| a.album_type);
+                let is_appearance = !tracks[0]
+                    .albumartists
+                    .iter()
+                    .any(|a| a.artisthash == artisthash);
+                let tracks = tracks
+                    .iter()
+                    .map(|t| serialize_track_with_help(t, user.id))
+                    .collect::<Vec<_>>();
+
+                serde_json::json!({
+                    "albumhash": albumhash,
+                    "album": album.map(|mut a| serialize_album_card(&mut a)),
+                    "album_type": album_type,
+                    "is_appearance": is_appearance,
+                    "tracks": tracks,
+                })
+            })
+            .collect::<Vec<_>>();
+        return HttpResponse::Ok().json(groups);
+    }
+
     let tracks = tracks
         .into_iter()
-        .map(|t| serialize_track_with_help(&t))
+        .map(|t| serialize_track_with_help(&t, user.id))
         .collect::<Vec<_>>();
 
     HttpResponse::Ok().json(tracks)
 }
 
 /// get similar artists
+#[utoipa::path(
+    params(SimilarArtistsQuery),
+    responses((status = 200, description = "Success"))
+)]
 #[get("/{artisthash}/similar")]
 pub async fn get_similar_artists(
     path: web::Path<String>,
@@ -250,8 +607,8 @@ pub async fn get_similar_artists(
     let artisthash = path.into_inner();
     let limit = query.limit.unwrap_or(7);
 
-    let similar = match SimilarArtistTable::get_similar(&artisthash).await {
-        Ok(list) => list,
+    let similar = match similarity::similar_artists(&artisthash).await {
+        Ok(list) => list.into_iter().map(|s| s.artisthash).collect::<Vec<_>>(),
         Err(e) => {
             tracing::warn!("failed to get similar artists for {}: {}", artisthash, e);
             Vec::new()
@@ -284,6 +641,60 @@ pub async fn get_similar_artists(
     HttpResponse::Ok().json(serialized)
 }
 
+/// artists related to an artist, most similar first
+///
+/// falls back to similarity computed from the local library and scrobbles
+/// when no last.fm data is stored for the artist
+#[utoipa::path(
+    params(SimilarArtistsQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    )
+)]
+#[get("/{artisthash}/related")]
+pub async fn get_related_artists(
+    path: web::Path<String>,
+    query: web::Query<SimilarArtistsQuery>,
+) -> impl Responder {
+    let artisthash = path.into_inner();
+    let limit = query.limit.unwrap_or(10);
+
+    if ArtistStore::get().get_by_hash(&artisthash).is_none() {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Artist not found"
+        }));
+    }
+
+    let similar = match similarity::similar_artists(&artisthash).await {
+        Ok(list) => list,
+        Err(e) => {
+            tracing::warn!("failed to get related artists for {}: {}", artisthash, e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Could not compute related artists"
+            }));
+        }
+    };
+
+    let store = ArtistStore::get();
+    let related: Vec<_> = similar
+        .iter()
+        .filter_map(|s| {
+            let mut artist = store.get_by_hash(&s.artisthash)?;
+            let mut card = serialize_artist_card(&mut artist);
+            if let Some(map) = card.as_object_mut() {
+                map.insert("weight".to_string(), serde_json::json!(s.weight));
+                map.insert("local".to_string(), serde_json::json!(s.local));
+            }
+            Some(card)
+        })
+        .take(limit)
+        .collect();
+
+    HttpResponse::Ok().json(related)
+}
+
 fn get_artist_albums_inner(artisthash: &str, limit: usize, return_all: bool) -> serde_json::Value {
     let entry = match ArtistStore::get().get_by_hash(artisthash) {
         Some(e) => e,
@@ -299,10 +710,8 @@ fn get_artist_albums_inner(artisthash: &str, limit: usize, return_all: bool) ->
             .push(track);
     }
 
-    let mut albums_all: Vec<Album> = grouped_tracks
-        .keys()
-        .filter_map(|h| AlbumStore::get().get_by_hash(h))
-        .collect();
+    let hashes: Vec<String> = grouped_tracks.keys().cloned().collect();
+    let mut albums_all: Vec<Album> = AlbumStore::get().get_by_hashes(&hashes);
     albums_all.sort_by(|a, b| b.date.cmp(&a.date));
 
     let mut res: serde_json::Map<String, serde_json::Value> = serde_json::Map::new();
@@ -410,6 +819,7 @@ fn serialize_artist_card(artist: &mut Artist) -> serde_json::Value {
         "type".to_string(),
         serde_json::Value::String("artist".to_string()),
     );
+    insert_image_hints(&mut map, CardImage::Artist);
     serde_json::Value::Object(map)
 }
 
@@ -459,10 +869,11 @@ fn serialize_album_card(album: &mut Album) -> serde_json::Map<String, serde_json
         "type".to_string(),
         serde_json::Value::String("album".to_string()),
     );
+    insert_image_hints(&mut map, CardImage::Thumbnail);
     map
 }
 
-fn serialize_track_with_help(track: &Track) -> serde_json::Value {
+fn serialize_track_with_help(track: &Track, user_id: i64) -> serde_json::Value {
     let mut map = serde_json::to_value(track)
         .unwrap_or_else(|_| serde_json::json!({}))
         .as_object()
@@ -515,7 +926,7 @@ fn serialize_track_with_help(track: &Track) -> serde_json::Value {
 
     map.insert(
         "is_favorite".to_string(),
-        serde_json::Value::Bool(track.is_favorite(1)),
+        serde_json::Value::Bool(track.is_favorite(user_id)),
     );
     let help = if track.playcount == 0 {
         "unplayed".to_string()
@@ -528,6 +939,7 @@ fn serialize_track_with_help(track: &Track) -> serde_json::Value {
     };
     map.insert("help_text".to_string(), serde_json::Value::String(help));
 
+    insert_image_hints(&mut map, CardImage::Thumbnail);
     serde_json::Value::Object(map)
 }
 
@@ -6,22 +6,31 @@ use anyhow::Result as AnyResult;
 use once_cell::sync::Lazy;
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use std::collections::{BTreeMap, HashMap};
 
-use crate::config::UserConfig;
-use crate::db::tables::UserTable;
+use crate::api::identity::{optional_user, require_user, Admin, Authorized, CurrentUser};
+use crate::config::{OidcSettings, UserConfig};
+use crate::core::oidc;
+use crate::db::tables::{PreferenceTable, UserTable};
 use crate::models::{User, UserRole};
 use crate::utils::auth::{create_jwt, hash_password, verify_jwt, verify_password, UserIdentity};
 
 const ACCESS_MAX_AGE: i64 = 30 * 24 * 3600; // 30 days in seconds
 const REFRESH_MAX_AGE: i64 = 30 * 24 * 3600;
 
+/// preferences a user can keep
+const MAX_PREFERENCES: usize = 200;
+const MAX_PREFERENCE_KEY_LEN: usize = 64;
+/// bytes of a preference value as JSON
+const MAX_PREFERENCE_VALUE_LEN: usize = 16 * 1024;
+
 /// global pair token storage one code at a time consumed once
 static PAIR_TOKENS: Lazy<RwLock<HashMap<String, TokenResponse>>> =
     Lazy::new(|| RwLock::new(HashMap::new()));
 
 /// login request
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct LoginRequest {
     pub username: String,
     pub password: String,
@@ -36,17 +45,17 @@ pub struct TokenResponse {
     pub maxage: i64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct PairQuery {
     pub code: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct UsersQuery {
     pub simplified: Option<bool>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct UpdateProfileRequest {
     pub id: Option<i64>,
     pub email: Option<String>,
@@ -55,7 +64,7 @@ pub struct UpdateProfileRequest {
     pub roles: Option<Vec<String>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateUserRequest {
     pub id: Option<i64>,
     pub email: Option<String>,
@@ -64,12 +73,41 @@ pub struct CreateUserRequest {
     pub roles: Option<Vec<String>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct DeleteUserRequest {
     pub username: String,
 }
 
+/// preferences to save, a null value clears the key
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdatePreferencesRequest {
+    #[schema(value_type = Object)]
+    pub preferences: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct OidcLoginQuery {
+    /// path to open once signed in
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
 /// login endpoint
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    )
+)]
 #[post("/login")]
 pub async fn login(body: web::Json<LoginRequest>) -> impl Responder {
     match UserTable::get_by_username(&body.username).await {
@@ -108,6 +146,13 @@ pub async fn login(body: web::Json<LoginRequest>) -> impl Responder {
 }
 
 /// refresh token expects refresh token in authorization header
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    )
+)]
 #[post("/refresh")]
 pub async fn refresh_token(req: HttpRequest) -> impl Responder {
     let token = match bearer_token(&req) {
@@ -145,6 +190,14 @@ pub async fn refresh_token(req: HttpRequest) -> impl Responder {
 }
 
 /// get a pair code auth required via cookie
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 #[get("/getpaircode")]
 pub async fn get_pair_code(req: HttpRequest) -> impl Responder {
     let user = match require_user(&req).await {
@@ -188,6 +241,13 @@ pub async fn get_pair_code(req: HttpRequest) -> impl Responder {
 }
 
 /// pair with a code one time use
+#[utoipa::path(
+    params(PairQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request")
+    )
+)]
 #[get("/pair")]
 pub async fn pair_with_code(query: web::Query<PairQuery>) -> impl Responder {
     let code = &query.code;
@@ -206,6 +266,17 @@ pub async fn pair_with_code(query: web::Query<PairQuery>) -> impl Responder {
 }
 
 /// update profile current user or specified id honoring admin rules
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 #[put("/profile/update")]
 pub async fn update_profile(
     req: HttpRequest,
@@ -332,12 +403,21 @@ pub async fn update_profile(
 }
 
 /// create a new user admin only
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 #[post("/profile/create")]
-pub async fn create_user(req: HttpRequest, body: web::Json<CreateUserRequest>) -> impl Responder {
-    if let Err(resp) = require_admin(&req).await.map(|_| ()) {
-        return resp;
-    }
-
+pub async fn create_user(
+    _admin: Authorized<Admin>,
+    body: web::Json<CreateUserRequest>,
+) -> impl Responder {
     if body.username.is_empty() || body.password.is_empty() {
         return HttpResponse::BadRequest().json(serde_json::json!({
             "msg": "Username and password are required"
@@ -383,12 +463,18 @@ pub async fn create_user(req: HttpRequest, body: web::Json<CreateUserRequest>) -
 }
 
 /// create guest user admin only
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 #[post("/profile/guest/create")]
-pub async fn create_guest(req: HttpRequest) -> impl Responder {
-    if let Err(resp) = require_admin(&req).await.map(|_| ()) {
-        return resp;
-    }
-
+pub async fn create_guest(_admin: Authorized<Admin>) -> impl Responder {
     if let Ok(Some(_)) = UserTable::get_by_username("guest").await {
         return HttpResponse::BadRequest().json(serde_json::json!({
             "msg": "Guest user already exists"
@@ -419,13 +505,21 @@ pub async fn create_guest(req: HttpRequest) -> impl Responder {
 }
 
 /// delete user admin only
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 #[delete("/profile/delete")]
-pub async fn delete_user(req: HttpRequest, body: web::Json<DeleteUserRequest>) -> impl Responder {
-    let current_user = match require_admin(&req).await {
-        Ok(u) => u,
-        Err(resp) => return resp,
-    };
-
+pub async fn delete_user(
+    current_user: Authorized<Admin>,
+    body: web::Json<DeleteUserRequest>,
+) -> impl Responder {
     if body.username == current_user.username {
         return HttpResponse::BadRequest().json(serde_json::json!({
             "msg": "Sorry! you cannot delete yourselfu"
@@ -461,10 +555,129 @@ pub async fn delete_user(req: HttpRequest, body: web::Json<DeleteUserRequest>) -
     }
 }
 
+/// client preferences of the current user
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/profile/preferences")]
+pub async fn get_preferences(user: CurrentUser) -> impl Responder {
+    match PreferenceTable::all(user.id).await {
+        Ok(preferences) => HttpResponse::Ok().json(serde_json::json!({
+            "preferences": preferences
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "msg": "Failed to load preferences"
+        })),
+    }
+}
+
+/// save client preferences of the current user keys left out are kept
+#[utoipa::path(
+    request_body = UpdatePreferencesRequest,
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[put("/profile/preferences")]
+pub async fn update_preferences(
+    user: CurrentUser,
+    body: web::Json<UpdatePreferencesRequest>,
+) -> impl Responder {
+    let mut changes = BTreeMap::new();
+    for (key, value) in body.into_inner().preferences {
+        if let Err(msg) = validate_preference(&key, &value) {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "msg": msg }));
+        }
+        changes.insert(key, (!value.is_null()).then_some(value));
+    }
+
+    let current = match PreferenceTable::all(user.id).await {
+        Ok(current) => current,
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "msg": "Failed to load preferences"
+            }))
+        }
+    };
+    let count = current
+        .keys()
+        .filter(|key| !changes.contains_key(*key))
+        .count()
+        + changes.values().filter(|value| value.is_some()).count();
+    if count > MAX_PREFERENCES {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "msg": format!("At most {} preferences can be saved", MAX_PREFERENCES)
+        }));
+    }
+
+    let updated = chrono::Utc::now().timestamp();
+    if PreferenceTable::update(user.id, &changes, updated)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "msg": "Failed to save preferences"
+        }));
+    }
+
+    let mut preferences = current;
+    for (key, value) in changes {
+        match value {
+            Some(value) => preferences.insert(key, value),
+            None => preferences.remove(&key),
+        };
+    }
+    HttpResponse::Ok().json(serde_json::json!({ "preferences": preferences }))
+}
+
+/// clear a client preference of the current user
+#[utoipa::path(
+    params(("key" = String, Path, description = "Preference key")),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[delete("/profile/preferences/{key}")]
+pub async fn delete_preference(user: CurrentUser, path: web::Path<String>) -> impl Responder {
+    match PreferenceTable::delete(user.id, &path).await {
+        Ok(true) => HttpResponse::Ok().json(serde_json::json!({
+            "msg": "Preference cleared"
+        })),
+        Ok(false) => HttpResponse::NotFound().json(serde_json::json!({
+            "msg": "Preference not set"
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "msg": "Failed to clear preference"
+        })),
+    }
+}
+
 /// get all users optional auth admin sees settings
+#[utoipa::path(
+    params(UsersQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/users")]
 pub async fn get_users(req: HttpRequest, query: web::Query<UsersQuery>) -> impl Responder {
-    let current_user = match auth_user_optional(&req).await {
+    let current_user = match optional_user(&req).await {
         Ok(u) => u,
         Err(resp) => return resp,
     };
@@ -528,16 +741,142 @@ pub async fn get_users(req: HttpRequest, query: web::Query<UsersQuery>) -> impl
 }
 
 /// get logged in user empty object if not logged in
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/user")]
 pub async fn get_logged_in_user(req: HttpRequest) -> impl Responder {
-    match auth_user_optional(&req).await {
+    match optional_user(&req).await {
         Ok(Some(user)) => HttpResponse::Ok().json(user_to_public_value(&user)),
         Ok(None) => HttpResponse::Ok().json(serde_json::json!({})),
         Err(resp) => resp,
     }
 }
 
+/// whether sso login is available and the label of its button
+#[utoipa::path(
+    responses((status = 200, description = "Success"))
+)]
+#[get("/oidc")]
+pub async fn oidc_info() -> impl Responder {
+    match oidc_settings() {
+        Some(settings) => HttpResponse::Ok().json(serde_json::json!({
+            "enabled": true,
+            "name": settings.name,
+            "login_url": "/auth/oidc/login",
+        })),
+        None => HttpResponse::Ok().json(serde_json::json!({
+            "enabled": false,
+        })),
+    }
+}
+
+/// start an sso login, redirects to the provider
+#[utoipa::path(
+    params(OidcLoginQuery),
+    responses(
+        (status = 302, description = "Redirect to the provider"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    )
+)]
+#[get("/oidc/login")]
+pub async fn oidc_login(req: HttpRequest, query: web::Query<OidcLoginQuery>) -> impl Responder {
+    let Some(settings) = oidc_settings() else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "msg": "SSO login is not set up"
+        }));
+    };
+
+    let redirect_uri = if settings.redirect_url.is_empty() {
+        let info = req.connection_info();
+        format!("{}://{}/auth/oidc/callback", info.scheme(), info.host())
+    } else {
+        settings.redirect_url.clone()
+    };
+    let next = query.next.as_deref().unwrap_or("/");
+
+    match oidc::start_login(&settings, &redirect_uri, next).await {
+        Ok(url) => HttpResponse::Found()
+            .insert_header(("Location", url))
+            .finish(),
+        Err(e) => {
+            tracing::warn!("could not start sso login: {:#}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "msg": format!("Could not start the SSO login: {:#}", e)
+            }))
+        }
+    }
+}
+
+/// sso login callback, signs the user in and redirects back into the app
+#[utoipa::path(
+    params(OidcCallbackQuery),
+    responses(
+        (status = 302, description = "Signed in, redirect into the app"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    )
+)]
+#[get("/oidc/callback")]
+pub async fn oidc_callback(query: web::Query<OidcCallbackQuery>) -> impl Responder {
+    let Some(settings) = oidc_settings() else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "msg": "SSO login is not set up"
+        }));
+    };
+
+    if let Some(error) = &query.error {
+        let reason = query.error_description.as_deref().unwrap_or(error);
+        return HttpResponse::Unauthorized().json(serde_json::json!({
+            "msg": format!("The provider refused the login: {}", reason)
+        }));
+    }
+    let (Some(code), Some(state)) = (&query.code, &query.state) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "msg": "Missing code or state"
+        }));
+    };
+
+    let (user, next) = match oidc::finish_login(&settings, state, code).await {
+        Ok(result) => result,
+        Err(e) => {
+            tracing::warn!("sso login failed: {:#}", e);
+            return HttpResponse::Unauthorized().json(serde_json::json!({
+                "msg": format!("SSO login failed: {:#}", e)
+            }));
+        }
+    };
+
+    let config = match UserConfig::load() {
+        Ok(cfg) => cfg,
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "msg": "Failed to load config"
+            }))
+        }
+    };
+    match create_tokens(&user, &config.server_id) {
+        Ok(tokens) => HttpResponse::Found()
+            .cookie(build_access_cookie(&tokens.accesstoken))
+            .insert_header(("Location", next))
+            .finish(),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "msg": "Failed to create token"
+        })),
+    }
+}
+
 /// logout
+#[utoipa::path(
+    responses((status = 200, description = "Success"))
+)]
 #[get("/logout")]
 pub async fn logout() -> impl Responder {
     let cookie = Cookie::build("access_token_cookie", "")
@@ -553,6 +892,12 @@ pub async fn logout() -> impl Responder {
 
 // helpers
 
+/// the sso settings when logins can be started
+fn oidc_settings() -> Option<OidcSettings> {
+    let settings = UserConfig::load().ok()?.oidc.normalized();
+    settings.is_usable().then_some(settings)
+}
+
 fn build_access_cookie(token: &str) -> Cookie<'static> {
     Cookie::build("access_token_cookie", token.to_string())
         .path("/")
@@ -603,62 +948,6 @@ fn create_tokens_with_identity(
     })
 }
 
-async fn require_user(req: &HttpRequest) -> Result<User, HttpResponse> {
-    match auth_user_optional(req).await? {
-        Some(user) => Ok(user),
-        None => Err(HttpResponse::Unauthorized().json(serde_json::json!({
-            "msg": "Not authenticated"
-        }))),
-    }
-}
-
-async fn require_admin(req: &HttpRequest) -> Result<User, HttpResponse> {
-    let user = require_user(req).await?;
-    if user.roles.contains(&UserRole::Admin) {
-        Ok(user)
-    } else {
-        Err(HttpResponse::Forbidden().json(serde_json::json!({
-            "msg": "Only admins can do that!"
-        })))
-    }
-}
-
-async fn auth_user_optional(req: &HttpRequest) -> Result<Option<User>, HttpResponse> {
-    let token = match access_token(req) {
-        Ok(Some(t)) => t,
-        Ok(None) => return Ok(None),
-        Err(resp) => return Err(resp),
-    };
-
-    let config = match UserConfig::load() {
-        Ok(cfg) => cfg,
-        Err(_) => {
-            return Err(HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Config error"
-            })));
-        }
-    };
-
-    let claims = match verify_jwt(&token, &config.server_id, Some("access")) {
-        Ok(c) => c,
-        Err(_) => {
-            return Err(HttpResponse::Unauthorized().json(serde_json::json!({
-                "msg": "Invalid token"
-            })));
-        }
-    };
-
-    match UserTable::get_by_id(claims.sub.id).await {
-        Ok(Some(user)) => Ok(Some(user)),
-        Ok(None) => Err(HttpResponse::Unauthorized().json(serde_json::json!({
-            "msg": "Invalid token"
-        }))),
-        Err(_) => Err(HttpResponse::InternalServerError().json(serde_json::json!({
-            "msg": "Database error"
-        }))),
-    }
-}
-
 fn bearer_token(req: &HttpRequest) -> Result<Option<String>, HttpResponse> {
     match req.headers().get("Authorization") {
         Some(header_value) => {
@@ -687,14 +976,6 @@ fn bearer_token(req: &HttpRequest) -> Result<Option<String>, HttpResponse> {
     }
 }
 
-fn access_token(req: &HttpRequest) -> Result<Option<String>, HttpResponse> {
-    if let Some(cookie) = req.cookie("access_token_cookie") {
-        return Ok(Some(cookie.value().to_string()));
-    }
-
-    bearer_token(req)
-}
-
 fn parse_roles(role_names: &[String]) -> Vec<UserRole> {
     role_names
         .iter()
@@ -704,17 +985,35 @@ fn parse_roles(role_names: &[String]) -> Vec<UserRole> {
 
 fn user_to_public_value(user: &User) -> serde_json::Value {
     let roles: Vec<String> = user.roles.iter().map(|r| r.as_str().to_string()).collect();
+    let permissions: Vec<&str> = user.permissions().iter().map(|p| p.as_str()).collect();
     serde_json::json!({
         "id": user.id,
         "username": user.username,
         "image": user.image,
         "roles": roles,
+        "permissions": permissions,
         "firstname": user.firstname,
         "email": user.email,
         "extra": user.extra,
     })
 }
 
+/// keys are short names like `theme` or `home.layout`, values any JSON up to a size
+fn validate_preference(key: &str, value: &serde_json::Value) -> Result<(), String> {
+    let valid_key = !key.is_empty()
+        && key.len() <= MAX_PREFERENCE_KEY_LEN
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if !valid_key {
+        return Err(format!("Invalid preference key: {}", key));
+    }
+    if value.to_string().len() > MAX_PREFERENCE_VALUE_LEN {
+        return Err(format!("Preference {} is too large", key));
+    }
+    Ok(())
+}
+
 fn user_to_simplified_value(user: &User) -> serde_json::Value {
     serde_json::json!({
         "id": user.id,
@@ -723,6 +1022,29 @@ fn user_to_simplified_value(user: &User) -> serde_json::Value {
     })
 }
 
+/// OpenAPI description of the auth routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    login,
+    refresh_token,
+    get_pair_code,
+    pair_with_code,
+    update_profile,
+    create_user,
+    create_guest,
+    delete_user,
+    get_users,
+    get_logged_in_user,
+    get_preferences,
+    update_preferences,
+    delete_preference,
+    oidc_info,
+    oidc_login,
+    oidc_callback,
+    logout,
+))]
+pub struct ApiDoc;
+
 /// configure auth routes
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(login)
@@ -735,5 +1057,29 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
         .service(delete_user)
         .service(get_users)
         .service(get_logged_in_user)
+        .service(get_preferences)
+        .service(update_preferences)
+        .service(delete_preference)
+        .service(oidc_info)
+        .service(oidc_login)
+        .service(oidc_callback)
         .service(logout);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_preference() {
+        let value = serde_json::json!("dark");
+        assert!(validate_preference("theme", &value).is_ok());
+        assert!(validate_preference("home.layout-v2_1", &value).is_ok());
+        assert!(validate_preference("", &value).is_err());
+        assert!(validate_preference("a b", &value).is_err());
+        assert!(validate_preference(&"k".repeat(MAX_PREFERENCE_KEY_LEN + 1), &value).is_err());
+
+        let large = serde_json::json!("x".repeat(MAX_PREFERENCE_VALUE_LEN));
+        assert!(validate_preference("theme", &large).is_err());
+    }
+}
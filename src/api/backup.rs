@@ -8,14 +8,19 @@ use actix_web::{delete, get, post, web, HttpResponse, Responder};
 use directories::UserDirs;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Map, Value};
+use utoipa::{OpenApi, ToSchema};
 
+use crate::api::identity::CurrentUser;
 use crate::config::Paths;
-use crate::db::tables::{CollectionTable, FavoriteTable, PlaylistTable, ScrobbleTable};
+use crate::core::colorlib::ColorLib;
+use crate::core::PlaylistLib;
+use crate::db::tables::{
+    CollectionTable, DiscoveryTable, FavoriteTable, PlaylistImageTable, PlaylistTable,
+    ScrobbleTable,
+};
 use crate::models::{Favorite, Playlist, TrackLog};
 use crate::utils::dates::timestamp_to_relative;
 
-const USER_ID: i64 = 0;
-
 #[derive(Debug, Serialize)]
 struct BackupCreateResponse {
     name: String,
@@ -26,13 +31,13 @@ struct BackupCreateResponse {
     collections: usize,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 struct RestoreBackupBody {
     #[serde(default)]
     backup_dir: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 struct DeleteBackupBody {
     backup_dir: String,
 }
@@ -47,8 +52,16 @@ struct BackupListItem {
     collections: usize,
 }
 
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[post("/create")]
-pub async fn create_backup() -> impl Responder {
+pub async fn create_backup(user: CurrentUser) -> impl Responder {
     let backup_root = backup_root();
     if let Err(e) = fs::create_dir_all(&backup_root) {
         eprintln!("{}", e);
@@ -68,7 +81,7 @@ pub async fn create_backup() -> impl Responder {
     }
 
     // Favorites
-    let favorites: Vec<Favorite> = match FavoriteTable::all(Some(USER_ID)).await {
+    let favorites: Vec<Favorite> = match FavoriteTable::all(Some(user.id)).await {
         Ok(f) => f,
         Err(e) => {
             eprintln!("{}", e);
@@ -84,7 +97,7 @@ pub async fn create_backup() -> impl Responder {
 
     // Scrobbles
     let scrobbles: Vec<TrackLog> = match ScrobbleTable::get_all().await {
-        Ok(s) => s,
+        Ok(s) => s.into_iter().filter(|s| s.userid == user.id).collect(),
         Err(e) => {
             eprintln!("{}", e);
             return HttpResponse::InternalServerError()
@@ -102,7 +115,7 @@ pub async fn create_backup() -> impl Responder {
     }
 
     // Playlists
-    let playlists: Vec<Playlist> = match PlaylistTable::all(Some(USER_ID)).await {
+    let playlists: Vec<Playlist> = match PlaylistTable::all(Some(user.id)).await {
         Ok(p) => p,
         Err(e) => {
             eprintln!("{}", e);
@@ -221,8 +234,20 @@ pub async fn create_backup() -> impl Responder {
     })
 }
 
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[post("/restore")]
-pub async fn restore_backup(body: web::Json<RestoreBackupBody>) -> impl Responder {
+pub async fn restore_backup(
+    user: CurrentUser,
+    body: web::Json<RestoreBackupBody>,
+) -> impl Responder {
     let backup_root = backup_root();
     let mut restored: Vec<String> = Vec::new();
 
@@ -233,7 +258,7 @@ pub async fn restore_backup(body: web::Json<RestoreBackupBody>) -> impl Responde
                 .json(json!({"msg": format!("Backup '{}' not found", dir)}));
         }
 
-        if let Err(e) = restore_from_dir(&target).await {
+        if let Err(e) = restore_from_dir(&target, user.id).await {
             eprintln!("{}", e);
             return HttpResponse::InternalServerError()
                 .json(json!({"msg": "Failed! An error occured"}));
@@ -255,7 +280,7 @@ pub async fn restore_backup(body: web::Json<RestoreBackupBody>) -> impl Responde
         entries.sort_by(|a, b| b.file_name().cmp(&a.file_name()));
 
         for dir in entries {
-            if let Err(e) = restore_from_dir(&dir).await {
+            if let Err(e) = restore_from_dir(&dir, user.id).await {
                 eprintln!("{}", e);
                 return HttpResponse::InternalServerError()
                     .json(json!({"msg": "Failed! An error occured"}));
@@ -273,6 +298,9 @@ pub async fn restore_backup(body: web::Json<RestoreBackupBody>) -> impl Responde
     HttpResponse::Ok().json(json!({"msg": "Restored successfully", "backups": restored}))
 }
 
+#[utoipa::path(
+    responses((status = 200, description = "Success"))
+)]
 #[get("/list")]
 pub async fn list_backups() -> impl Responder {
     let backup_root = backup_root();
@@ -343,6 +371,13 @@ pub async fn list_backups() -> impl Responder {
     HttpResponse::Ok().json(json!({"backups": backups}))
 }
 
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    )
+)]
 #[delete("/delete")]
 pub async fn delete_backup(body: web::Json<DeleteBackupBody>) -> impl Responder {
     let backup_root = backup_root();
@@ -362,6 +397,11 @@ pub async fn delete_backup(body: web::Json<DeleteBackupBody>) -> impl Responder
     HttpResponse::Ok().json(json!({"msg": format!("Backup '{}' deleted", body.backup_dir)}))
 }
 
+/// OpenAPI description of the backup routes
+#[derive(OpenApi)]
+#[openapi(paths(create_backup, restore_backup, list_backups, delete_backup,))]
+pub struct ApiDoc;
+
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(create_backup)
         .service(restore_backup)
@@ -376,22 +416,27 @@ fn backup_root() -> PathBuf {
         .join("swingmusic.backup")
 }
 
-async fn restore_from_dir(dir: &Path) -> anyhow::Result<()> {
+async fn restore_from_dir(dir: &Path, user_id: i64) -> anyhow::Result<()> {
     let data_file = dir.join("data.json");
     let file = fs::File::open(&data_file)?;
     let data: Value = serde_json::from_reader(file)?;
 
-    restore_favorites(data.get("favorites").cloned().unwrap_or(json!([]))).await?;
-    restore_playlists(dir, data.get("playlists").cloned().unwrap_or(json!([]))).await?;
-    restore_scrobbles(data.get("scrobbles").cloned().unwrap_or(json!([]))).await?;
+    restore_favorites(data.get("favorites").cloned().unwrap_or(json!([])), user_id).await?;
+    restore_playlists(
+        dir,
+        data.get("playlists").cloned().unwrap_or(json!([])),
+        user_id,
+    )
+    .await?;
+    restore_scrobbles(data.get("scrobbles").cloned().unwrap_or(json!([])), user_id).await?;
     restore_collections(data.get("collections").cloned().unwrap_or(json!([]))).await?;
 
     Ok(())
 }
 
-async fn restore_favorites(favs: Value) -> anyhow::Result<()> {
+async fn restore_favorites(favs: Value, user_id: i64) -> anyhow::Result<()> {
     let favorites: Vec<Favorite> = serde_json::from_value(favs).unwrap_or_default();
-    let mut existing: HashSet<(String, String)> = FavoriteTable::all(Some(USER_ID))
+    let mut existing: HashSet<(String, String)> = FavoriteTable::all(Some(user_id))
         .await?
         .into_iter()
         .map(|f| (f.favorite_type.as_str().to_string(), f.hash.clone()))
@@ -404,7 +449,7 @@ async fn restore_favorites(favs: Value) -> anyhow::Result<()> {
         }
 
         if let Err(e) =
-            FavoriteTable::add_with_extra(&fav.hash, fav.favorite_type, USER_ID, &fav.extra).await
+            FavoriteTable::add_with_extra(&fav.hash, fav.favorite_type, user_id, &fav.extra).await
         {
             eprintln!("{}", e);
         } else {
@@ -415,7 +460,7 @@ async fn restore_favorites(favs: Value) -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn restore_playlists(dir: &Path, playlists: Value) -> anyhow::Result<()> {
+async fn restore_playlists(dir: &Path, playlists: Value, user_id: i64) -> anyhow::Result<()> {
     let playlists: Vec<Map<String, Value>> = playlists
         .as_array()
         .cloned()
@@ -424,7 +469,7 @@ async fn restore_playlists(dir: &Path, playlists: Value) -> anyhow::Result<()> {
         .filter_map(|v| v.as_object().cloned())
         .collect();
 
-    let existing: HashSet<String> = PlaylistTable::all(Some(USER_ID))
+    let existing: HashSet<String> = PlaylistTable::all(Some(user_id))
         .await?
         .into_iter()
         .map(|p| p.name)
@@ -444,13 +489,17 @@ async fn restore_playlists(dir: &Path, playlists: Value) -> anyhow::Result<()> {
             }
             map.remove("_score");
 
-            let playlist: Playlist = serde_json::from_value(Value::Object(map.clone()))
-                .unwrap_or_else(|_| Playlist::new(name.clone(), Some(USER_ID)));
+            let mut playlist: Playlist = serde_json::from_value(Value::Object(map.clone()))
+                .unwrap_or_else(|_| Playlist::new(name.clone(), Some(user_id)));
+            playlist.userid = Some(user_id);
 
-            if let Err(e) = PlaylistTable::insert(&playlist).await {
-                eprintln!("{}", e);
-                continue;
-            }
+            let id = match PlaylistTable::insert(&playlist).await {
+                Ok(id) => id,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    continue;
+                }
+            };
 
             if let (Some(paths), Some(img)) =
                 (paths.as_ref(), map.get("image").and_then(|v| v.as_str()))
@@ -459,7 +508,11 @@ async fn restore_playlists(dir: &Path, playlists: Value) -> anyhow::Result<()> {
                 let dest = paths.playlist_images_dir().join(img);
                 if src.exists() {
                     let _ = fs::create_dir_all(dest.parent().unwrap_or_else(|| Path::new(".")));
-                    let _ = fs::copy(&src, &dest);
+                    if fs::copy(&src, &dest).is_ok() {
+                        let color = PlaylistLib::image_color(img).await;
+                        let variants = ColorLib::variants(&color);
+                        let _ = PlaylistImageTable::insert(id, img, &color, &variants).await;
+                    }
                 }
             }
 
@@ -470,7 +523,7 @@ async fn restore_playlists(dir: &Path, playlists: Value) -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn restore_scrobbles(scrobbles: Value) -> anyhow::Result<()> {
+async fn restore_scrobbles(scrobbles: Value, user_id: i64) -> anyhow::Result<()> {
     let scrobbles: Vec<Map<String, Value>> = scrobbles
         .as_array()
         .cloned()
@@ -482,6 +535,7 @@ async fn restore_scrobbles(scrobbles: Value) -> anyhow::Result<()> {
     let existing_logs = ScrobbleTable::get_all().await.unwrap_or_default();
     let mut existing_keys: HashSet<String> = existing_logs
         .iter()
+        .filter(|s| s.userid == user_id)
         .map(|s| format!("{}.{}", s.trackhash, s.timestamp))
         .collect();
 
@@ -503,14 +557,14 @@ async fn restore_scrobbles(scrobbles: Value) -> anyhow::Result<()> {
                 .get("source")
                 .and_then(|v| v.as_str())
                 .unwrap_or("unknown");
-            let userid = scrobble
-                .get("userid")
-                .and_then(|v| v.as_i64())
-                .unwrap_or(USER_ID);
+            let device = scrobble
+                .get("device")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
             let extra = scrobble.get("extra").cloned().unwrap_or(json!({}));
 
             if let Err(e) = ScrobbleTable::add_with_extra(
-                trackhash, timestamp, duration, source, userid, &extra,
+                trackhash, timestamp, duration, source, user_id, device, &extra,
             )
             .await
             {
@@ -521,6 +575,9 @@ async fn restore_scrobbles(scrobbles: Value) -> anyhow::Result<()> {
         }
     }
 
+    // restored plays may predate what was first played here
+    DiscoveryTable::backfill().await?;
+
     Ok(())
 }
 
@@ -3,21 +3,15 @@
 use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use utoipa::{OpenApi, ToSchema};
 
 use crate::api::getall::{to_album_card_map, to_artist_card_map};
+use crate::core::recipes::{generated_key, Recipes};
 use crate::db::tables::CollectionTable;
+use crate::models::CollectionItem;
 use crate::stores::{AlbumStore, ArtistStore};
 use crate::utils::hashing::create_hash;
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct CollectionItem {
-    #[serde(rename = "type")]
-    pub item_type: String,
-    pub hash: String,
-    #[serde(default)]
-    pub help_text: Option<String>,
-}
-
 #[derive(Debug, Serialize)]
 pub struct CollectionResponse {
     pub id: i64,
@@ -27,25 +21,31 @@ pub struct CollectionResponse {
     pub userid: i64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateCollectionRequest {
     pub name: String,
     pub description: String,
     pub items: Vec<CollectionItem>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct UpdateCollectionRequest {
     pub name: String,
     #[serde(default)]
     pub description: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CollectionItemRequest {
     pub item: CollectionItem,
 }
 
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 500, description = "Server error")
+    )
+)]
 #[get("")]
 pub async fn get_collections() -> impl Responder {
     match CollectionTable::get_all().await {
@@ -68,6 +68,13 @@ pub async fn get_collections() -> impl Responder {
     }
 }
 
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    )
+)]
 #[get("/{id}")]
 pub async fn get_collection(path: web::Path<i64>) -> impl Responder {
     let id = path.into_inner();
@@ -91,6 +98,13 @@ pub async fn get_collection(path: web::Path<i64>) -> impl Responder {
     }
 }
 
+#[utoipa::path(
+    responses(
+        (status = 201, description = "Created"),
+        (status = 400, description = "Invalid request"),
+        (status = 500, description = "Server error")
+    )
+)]
 #[post("")]
 pub async fn create_collection(body: web::Json<CreateCollectionRequest>) -> impl Responder {
     let validated = match validate_page_items(&body.items, &[]) {
@@ -113,6 +127,13 @@ pub async fn create_collection(body: web::Json<CreateCollectionRequest>) -> impl
     }
 }
 
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    )
+)]
 #[put("/{id}")]
 pub async fn update_collection(
     path: web::Path<i64>,
@@ -153,6 +174,12 @@ pub async fn update_collection(
     }))
 }
 
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 500, description = "Server error")
+    )
+)]
 #[delete("/{id}")]
 pub async fn delete_collection(path: web::Path<i64>) -> impl Responder {
     let id = path.into_inner();
@@ -164,6 +191,31 @@ pub async fn delete_collection(path: web::Path<i64>) -> impl Responder {
     }
 }
 
+/// Rebuild the genre, decade and record label hubs now instead of waiting
+/// for the nightly run
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 500, description = "Server error")
+    )
+)]
+#[post("/generate")]
+pub async fn generate_collections() -> impl Responder {
+    match Recipes::refresh_library_hubs().await {
+        Ok(count) => HttpResponse::Ok().json(json!({ "count": count })),
+        Err(e) => HttpResponse::InternalServerError()
+            .json(json!({ "error": format!("Failed to generate collections: {}", e) })),
+    }
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    )
+)]
 #[post("/{id}/items")]
 pub async fn add_collection_item(
     path: web::Path<i64>,
@@ -192,13 +244,9 @@ pub async fn add_collection_item(
     items.extend(new_items);
 
     let settings_str = serde_json::to_string(&items).unwrap_or_else(|_| "[]".to_string());
-    if let Err(e) = CollectionTable::update(
-        id,
-        None,
-        Some(&settings_str),
-        collection.extra_data.as_deref(),
-    )
-    .await
+    let extra_str = detach_extra(collection.extra_data);
+    if let Err(e) =
+        CollectionTable::update(id, None, Some(&settings_str), extra_str.as_deref()).await
     {
         return HttpResponse::InternalServerError()
             .json(json!({ "error": format!("Failed to update collection: {}", e) }));
@@ -207,6 +255,13 @@ pub async fn add_collection_item(
     HttpResponse::Ok().json(json!({ "message": "Items added to collection" }))
 }
 
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    )
+)]
 #[delete("/{id}/items")]
 pub async fn remove_collection_item(
     path: web::Path<i64>,
@@ -227,14 +282,10 @@ pub async fn remove_collection_item(
     let items = parse_items(&collection.settings);
     let updated = remove_page_items(&items, &body.item);
     let settings_str = serde_json::to_string(&updated).unwrap_or_else(|_| "[]".to_string());
+    let extra_str = detach_extra(collection.extra_data);
 
-    if let Err(e) = CollectionTable::update(
-        id,
-        None,
-        Some(&settings_str),
-        collection.extra_data.as_deref(),
-    )
-    .await
+    if let Err(e) =
+        CollectionTable::update(id, None, Some(&settings_str), extra_str.as_deref()).await
     {
         return HttpResponse::InternalServerError()
             .json(json!({ "error": format!("Failed to update collection: {}", e) }));
@@ -243,8 +294,23 @@ pub async fn remove_collection_item(
     HttpResponse::Ok().json(json!({ "message": "Item removed from collection" }))
 }
 
+/// OpenAPI description of the collections routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    get_collections,
+    generate_collections,
+    get_collection,
+    create_collection,
+    update_collection,
+    delete_collection,
+    add_collection_item,
+    remove_collection_item,
+))]
+pub struct ApiDoc;
+
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(get_collections)
+        .service(generate_collections)
         .service(get_collection)
         .service(create_collection)
         .service(update_collection)
@@ -263,6 +329,60 @@ fn parse_extra(extra: Option<String>) -> Value {
         .unwrap_or_else(|| json!({}))
 }
 
+/// Drop the hub key so the nightly rebuild leaves an edited hub alone
+fn detach_extra(extra: Option<String>) -> Option<String> {
+    if generated_key(extra.as_deref()).is_none() {
+        return extra;
+    }
+
+    let mut extra = parse_extra(extra);
+    if let Some(map) = extra.as_object_mut() {
+        map.remove("generated");
+        map.remove("kind");
+    }
+    serde_json::to_string(&extra).ok()
+}
+
+/// Homepage sections for the generated hubs, one of each kind
+///
+/// the hub shown for a kind changes every day
+pub async fn homepage_hub_sections(limit: usize) -> Vec<Value> {
+    let Ok(collections) = CollectionTable::get_all().await else {
+        return Vec::new();
+    };
+
+    let mut by_kind: std::collections::BTreeMap<String, Vec<_>> = Default::default();
+    for collection in collections {
+        let extra = parse_extra(collection.extra_data.clone());
+        if !extra["generated"].is_string() {
+            continue;
+        }
+        let kind = extra["kind"].as_str().unwrap_or_default().to_string();
+        by_kind.entry(kind).or_default().push((collection, extra));
+    }
+
+    let day = chrono::Utc::now().timestamp() / 86400;
+    let mut sections = Vec::new();
+    for (kind, mut hubs) in by_kind {
+        hubs.sort_by_key(|(c, _)| c.id);
+        let (collection, extra) = &hubs[day as usize % hubs.len()];
+        let mut items = recover_page_items(&parse_items(&collection.settings), true);
+        items.truncate(limit);
+        if items.is_empty() {
+            continue;
+        }
+        sections.push(json!({
+            format!("{}_hub", kind): {
+                "title": collection.name,
+                "description": extra["description"],
+                "collectionid": collection.id,
+                "items": items,
+            }
+        }));
+    }
+    sections
+}
+
 fn validate_page_items(
     items: &[CollectionItem],
     existing: &[CollectionItem],
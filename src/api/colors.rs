@@ -1,10 +1,36 @@
 //! Colors API routes limited to upstream parity
 
-use actix_web::{get, web, HttpResponse, Responder};
+use actix_multipart::Multipart;
+use actix_web::{get, post, web, HttpResponse, Responder};
+use futures::StreamExt;
+use serde::Deserialize;
+use utoipa::{IntoParams, OpenApi};
 
+use crate::core::colorlib::ColorLib;
 use crate::stores::AlbumStore;
 
+/// Largest image accepted for color extraction
+const MAX_EXTRACT_IMAGE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Palette colors returned when the request doesn't ask for a number
+const DEFAULT_PALETTE_SIZE: usize = 5;
+
+/// Most palette colors a request can ask for
+const MAX_PALETTE_SIZE: usize = 16;
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ExtractQuery {
+    /// number of palette colors, 5 by default
+    pub count: Option<usize>,
+}
+
 /// Upstream: GET /colors/album/<albumhash>
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 404, description = "Not found")
+    )
+)]
 #[get("/album/{albumhash}")]
 pub async fn get_album_color(path: web::Path<String>) -> impl Responder {
     let albumhash = path.into_inner();
@@ -13,12 +39,100 @@ pub async fn get_album_color(path: web::Path<String>) -> impl Responder {
     match album {
         Some(a) if !a.color.is_empty() => HttpResponse::Ok().json(serde_json::json!({
             "color": a.color,
+            "color_dark": a.color_dark,
+            "color_light": a.color_light,
+        })),
+        _ => HttpResponse::NotFound().json(serde_json::json!({
+            "color": "",
+            "color_dark": "",
+            "color_light": "",
         })),
-        _ => HttpResponse::NotFound().json(serde_json::json!({ "color": "" })),
     }
 }
 
+/// Colors of an uploaded image, to preview theming before the image is saved
+#[utoipa::path(
+    params(ExtractQuery),
+    request_body(content_type = "multipart/form-data", description = "Image file upload"),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 413, description = "Payload too large")
+    )
+)]
+#[post("/extract")]
+pub async fn extract_colors(
+    query: web::Query<ExtractQuery>,
+    mut payload: Multipart,
+) -> impl Responder {
+    let mut image: Option<Vec<u8>> = None;
+    while let Some(Ok(mut field)) = payload.next().await {
+        let is_image = field.content_disposition().get_name() == Some("image");
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field.next().await {
+            let Ok(data) = chunk else {
+                continue;
+            };
+            if bytes.len() + data.len() > MAX_EXTRACT_IMAGE_BYTES {
+                return HttpResponse::PayloadTooLarge().json(serde_json::json!({
+                    "error": format!("Images are limited to {} bytes", MAX_EXTRACT_IMAGE_BYTES)
+                }));
+            }
+            bytes.extend_from_slice(&data);
+        }
+
+        if is_image {
+            image = Some(bytes);
+        }
+    }
+
+    let Some(image) = image.filter(|i| !i.is_empty()) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Send the image in an image field"
+        }));
+    };
+
+    let count = query
+        .count
+        .unwrap_or(DEFAULT_PALETTE_SIZE)
+        .clamp(1, MAX_PALETTE_SIZE);
+    let extracted = web::block(move || ColorLib::extract_palette(&image, count)).await;
+    let (color, palette) = match extracted {
+        Ok(Ok(colors)) => colors,
+        _ => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Unsupported image format"
+            }))
+        }
+    };
+
+    let variants = ColorLib::variants(&color);
+    let palette: Vec<serde_json::Value> = palette
+        .iter()
+        .map(|hex| {
+            serde_json::json!({
+                "color": hex,
+                "text_color": ColorLib::get_text_color(hex),
+            })
+        })
+        .collect();
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "color": color,
+        "color_dark": variants.dark,
+        "color_light": variants.light,
+        "text_color": ColorLib::get_text_color(&color),
+        "palette": palette,
+    }))
+}
+
+/// OpenAPI description of the colors routes
+#[derive(OpenApi)]
+#[openapi(paths(get_album_color, extract_colors))]
+pub struct ApiDoc;
+
 /// Configure color routes
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(get_album_color);
+    cfg.service(get_album_color).service(extract_colors);
 }
@@ -0,0 +1,374 @@
+//! DLNA/UPnP device description and SOAP control routes
+//!
+//! only answers while the DLNA server is running (see `core::dlna`).
+
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use regex::Regex;
+use utoipa::OpenApi;
+
+use crate::core::dlna::{
+    browse, system_update_id, xml_escape, xml_unescape, BrowseFlag, DlnaServer, CONNECTION_MANAGER,
+    CONTENT_DIRECTORY,
+};
+
+const XML_CONTENT_TYPE: &str = "text/xml; charset=\"utf-8\"";
+
+/// Audio formats advertised by the ConnectionManager
+const SOURCE_PROTOCOLS: &str = "http-get:*:audio/mpeg:*,http-get:*:audio/flac:*,http-get:*:audio/ogg:*,http-get:*:audio/mp4:*,http-get:*:audio/aac:*,http-get:*:audio/wav:*,http-get:*:audio/webm:*,http-get:*:audio/opus:*";
+
+const CONTENT_DIRECTORY_SCPD: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<scpd xmlns="urn:schemas-upnp-org:service-1-0">
+  <specVersion><major>1</major><minor>0</minor></specVersion>
+  <actionList>
+    <action>
+      <name>Browse</name>
+      <argumentList>
+        <argument><name>ObjectID</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_ObjectID</relatedStateVariable></argument>
+        <argument><name>BrowseFlag</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_BrowseFlag</relatedStateVariable></argument>
+        <argument><name>Filter</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_Filter</relatedStateVariable></argument>
+        <argument><name>StartingIndex</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_Index</relatedStateVariable></argument>
+        <argument><name>RequestedCount</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_Count</relatedStateVariable></argument>
+        <argument><name>SortCriteria</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_SortCriteria</relatedStateVariable></argument>
+        <argument><name>Result</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_Result</relatedStateVariable></argument>
+        <argument><name>NumberReturned</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_Count</relatedStateVariable></argument>
+        <argument><name>TotalMatches</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_Count</relatedStateVariable></argument>
+        <argument><name>UpdateID</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_UpdateID</relatedStateVariable></argument>
+      </argumentList>
+    </action>
+    <action>
+      <name>GetSearchCapabilities</name>
+      <argumentList>
+        <argument><name>SearchCaps</name><direction>out</direction><relatedStateVariable>SearchCapabilities</relatedStateVariable></argument>
+      </argumentList>
+    </action>
+    <action>
+      <name>GetSortCapabilities</name>
+      <argumentList>
+        <argument><name>SortCaps</name><direction>out</direction><relatedStateVariable>SortCapabilities</relatedStateVariable></argument>
+      </argumentList>
+    </action>
+    <action>
+      <name>GetSystemUpdateID</name>
+      <argumentList>
+        <argument><name>Id</name><direction>out</direction><relatedStateVariable>SystemUpdateID</relatedStateVariable></argument>
+      </argumentList>
+    </action>
+  </actionList>
+  <serviceStateTable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_ObjectID</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_Result</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_BrowseFlag</name><dataType>string</dataType>
+      <allowedValueList><allowedValue>BrowseMetadata</allowedValue><allowedValue>BrowseDirectChildren</allowedValue></allowedValueList>
+    </stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_Filter</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_SortCriteria</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_Index</name><dataType>ui4</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_Count</name><dataType>ui4</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_UpdateID</name><dataType>ui4</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>SearchCapabilities</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>SortCapabilities</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="yes"><name>SystemUpdateID</name><dataType>ui4</dataType></stateVariable>
+  </serviceStateTable>
+</scpd>"#;
+
+const CONNECTION_MANAGER_SCPD: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<scpd xmlns="urn:schemas-upnp-org:service-1-0">
+  <specVersion><major>1</major><minor>0</minor></specVersion>
+  <actionList>
+    <action>
+      <name>GetProtocolInfo</name>
+      <argumentList>
+        <argument><name>Source</name><direction>out</direction><relatedStateVariable>SourceProtocolInfo</relatedStateVariable></argument>
+        <argument><name>Sink</name><direction>out</direction><relatedStateVariable>SinkProtocolInfo</relatedStateVariable></argument>
+      </argumentList>
+    </action>
+    <action>
+      <name>GetCurrentConnectionIDs</name>
+      <argumentList>
+        <argument><name>ConnectionIDs</name><direction>out</direction><relatedStateVariable>CurrentConnectionIDs</relatedStateVariable></argument>
+      </argumentList>
+    </action>
+    <action>
+      <name>GetCurrentConnectionInfo</name>
+      <argumentList>
+        <argument><name>ConnectionID</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_ConnectionID</relatedStateVariable></argument>
+        <argument><name>RcsID</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_RcsID</relatedStateVariable></argument>
+        <argument><name>AVTransportID</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_AVTransportID</relatedStateVariable></argument>
+        <argument><name>ProtocolInfo</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_ProtocolInfo</relatedStateVariable></argument>
+        <argument><name>PeerConnectionManager</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_ConnectionManager</relatedStateVariable></argument>
+        <argument><name>PeerConnectionID</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_ConnectionID</relatedStateVariable></argument>
+        <argument><name>Direction</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_Direction</relatedStateVariable></argument>
+        <argument><name>Status</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_ConnectionStatus</relatedStateVariable></argument>
+      </argumentList>
+    </action>
+  </actionList>
+  <serviceStateTable>
+    <stateVariable sendEvents="yes"><name>SourceProtocolInfo</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="yes"><name>SinkProtocolInfo</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="yes"><name>CurrentConnectionIDs</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_ConnectionStatus</name><dataType>string</dataType>
+      <allowedValueList><allowedValue>OK</allowedValue><allowedValue>ContentFormatMismatch</allowedValue><allowedValue>InsufficientBandwidth</allowedValue><allowedValue>UnreliableChannel</allowedValue><allowedValue>Unknown</allowedValue></allowedValueList>
+    </stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_ConnectionManager</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_Direction</name><dataType>string</dataType>
+      <allowedValueList><allowedValue>Input</allowedValue><allowedValue>Output</allowedValue></allowedValueList>
+    </stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_ProtocolInfo</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_ConnectionID</name><dataType>i4</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_AVTransportID</name><dataType>i4</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_RcsID</name><dataType>i4</dataType></stateVariable>
+  </serviceStateTable>
+</scpd>"#;
+
+/// device description
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 404, description = "Not found")
+    )
+)]
+#[get("/description.xml")]
+pub async fn device_description() -> impl Responder {
+    match DlnaServer::get() {
+        Some(server) => xml_response(server.description()),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// ContentDirectory service description
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 404, description = "Not found")
+    )
+)]
+#[get("/ContentDirectory.xml")]
+pub async fn content_directory_scpd() -> impl Responder {
+    if DlnaServer::get().is_none() {
+        return HttpResponse::NotFound().finish();
+    }
+    xml_response(CONTENT_DIRECTORY_SCPD.to_string())
+}
+
+/// ConnectionManager service description
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 404, description = "Not found")
+    )
+)]
+#[get("/ConnectionManager.xml")]
+pub async fn connection_manager_scpd() -> impl Responder {
+    if DlnaServer::get().is_none() {
+        return HttpResponse::NotFound().finish();
+    }
+    xml_response(CONNECTION_MANAGER_SCPD.to_string())
+}
+
+/// ContentDirectory SOAP actions
+#[utoipa::path(
+    request_body(content = String, content_type = "text/xml"),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 404, description = "Not found")
+    )
+)]
+#[post("/control/ContentDirectory")]
+pub async fn content_directory_control(req: HttpRequest, body: String) -> impl Responder {
+    let Some(server) = DlnaServer::get() else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    let action = soap_action(&req);
+    match action.as_str() {
+        "Browse" => {
+            let object_id = soap_arg(&body, "ObjectID").unwrap_or_else(|| "0".to_string());
+            let Some(flag) = soap_arg(&body, "BrowseFlag").and_then(|f| BrowseFlag::parse(&f))
+            else {
+                return soap_fault(402, "Invalid Args");
+            };
+            let start = soap_arg(&body, "StartingIndex")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0);
+            let count = soap_arg(&body, "RequestedCount")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0);
+
+            // links use the address the client reached us on
+            let info = req.connection_info();
+            let base_url = match info.host() {
+                "" => server.base_url.clone(),
+                host => format!("{}://{}", info.scheme(), host),
+            };
+
+            match browse(&object_id, flag, start, count, &base_url) {
+                Some(result) => soap_response(
+                    CONTENT_DIRECTORY,
+                    "Browse",
+                    &[
+                        ("Result", xml_escape(&result.didl)),
+                        ("NumberReturned", result.number_returned.to_string()),
+                        ("TotalMatches", result.total_matches.to_string()),
+                        ("UpdateID", system_update_id().to_string()),
+                    ],
+                ),
+                None => soap_fault(701, "No such object"),
+            }
+        }
+        "GetSearchCapabilities" => {
+            soap_response(CONTENT_DIRECTORY, &action, &[("SearchCaps", String::new())])
+        }
+        "GetSortCapabilities" => {
+            soap_response(CONTENT_DIRECTORY, &action, &[("SortCaps", String::new())])
+        }
+        "GetSystemUpdateID" => soap_response(
+            CONTENT_DIRECTORY,
+            &action,
+            &[("Id", system_update_id().to_string())],
+        ),
+        _ => soap_fault(401, "Invalid Action"),
+    }
+}
+
+/// ConnectionManager SOAP actions
+#[utoipa::path(
+    request_body(content = String, content_type = "text/xml"),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 404, description = "Not found")
+    )
+)]
+#[post("/control/ConnectionManager")]
+pub async fn connection_manager_control(req: HttpRequest, body: String) -> impl Responder {
+    if DlnaServer::get().is_none() {
+        return HttpResponse::NotFound().finish();
+    }
+
+    let action = soap_action(&req);
+    match action.as_str() {
+        "GetProtocolInfo" => soap_response(
+            CONNECTION_MANAGER,
+            &action,
+            &[
+                ("Source", SOURCE_PROTOCOLS.to_string()),
+                ("Sink", String::new()),
+            ],
+        ),
+        "GetCurrentConnectionIDs" => soap_response(
+            CONNECTION_MANAGER,
+            &action,
+            &[("ConnectionIDs", "0".to_string())],
+        ),
+        "GetCurrentConnectionInfo" => {
+            if soap_arg(&body, "ConnectionID").as_deref() != Some("0") {
+                return soap_fault(706, "Invalid connection reference");
+            }
+            soap_response(
+                CONNECTION_MANAGER,
+                &action,
+                &[
+                    ("RcsID", "-1".to_string()),
+                    ("AVTransportID", "-1".to_string()),
+                    ("ProtocolInfo", String::new()),
+                    ("PeerConnectionManager", String::new()),
+                    ("PeerConnectionID", "-1".to_string()),
+                    ("Direction", "Output".to_string()),
+                    ("Status", "OK".to_string()),
+                ],
+            )
+        }
+        _ => soap_fault(401, "Invalid Action"),
+    }
+}
+
+/// event subscriptions are accepted but no events are sent
+pub async fn event_subscription(req: HttpRequest) -> impl Responder {
+    if DlnaServer::get().is_none() {
+        return HttpResponse::NotFound().finish();
+    }
+
+    match req.method().as_str() {
+        "SUBSCRIBE" => {
+            let sid = req
+                .headers()
+                .get("SID")
+                .and_then(|v| v.to_str().ok())
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| format!("uuid:{}", uuid::Uuid::new_v4()));
+            HttpResponse::Ok()
+                .insert_header(("SID", sid))
+                .insert_header(("TIMEOUT", "Second-1800"))
+                .finish()
+        }
+        "UNSUBSCRIBE" => HttpResponse::Ok().finish(),
+        _ => HttpResponse::MethodNotAllowed().finish(),
+    }
+}
+
+/// OpenAPI description of the dlna routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    device_description,
+    content_directory_scpd,
+    connection_manager_scpd,
+    content_directory_control,
+    connection_manager_control,
+))]
+pub struct ApiDoc;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(device_description)
+        .service(content_directory_scpd)
+        .service(connection_manager_scpd)
+        .service(content_directory_control)
+        .service(connection_manager_control)
+        .service(web::resource("/event/{service}").route(web::route().to(event_subscription)));
+}
+
+fn xml_response(body: String) -> HttpResponse {
+    HttpResponse::Ok().content_type(XML_CONTENT_TYPE).body(body)
+}
+
+/// Action name from the SOAPACTION header ("urn:...:1#Browse")
+fn soap_action(req: &HttpRequest) -> String {
+    req.headers()
+        .get("SOAPACTION")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim_matches('"').rsplit('#').next())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Value of an argument in a SOAP request body
+fn soap_arg(body: &str, name: &str) -> Option<String> {
+    let pattern = format!(
+        r"(?s)<(?:\w+:)?{name}(?:\s[^>]*)?>(.*?)</(?:\w+:)?{name}>",
+        name = regex::escape(name)
+    );
+    let re = Regex::new(&pattern).ok()?;
+    re.captures(body)
+        .and_then(|c| c.get(1))
+        .map(|m| xml_unescape(m.as_str().trim()))
+}
+
+fn soap_response(service: &str, action: &str, args: &[(&str, String)]) -> HttpResponse {
+    let mut body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body><u:{}Response xmlns:u="{}">"#,
+        action, service
+    );
+    for (name, value) in args {
+        body.push_str(&format!("<{name}>{value}</{name}>"));
+    }
+    body.push_str(&format!("</u:{}Response></s:Body></s:Envelope>", action));
+    xml_response(body)
+}
+
+fn soap_fault(code: u32, description: &str) -> HttpResponse {
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body><s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring><detail><UPnPError xmlns="urn:schemas-upnp-org:control-1-0"><errorCode>{}</errorCode><errorDescription>{}</errorDescription></UPnPError></detail></s:Fault></s:Body></s:Envelope>"#,
+        code, description
+    );
+    HttpResponse::InternalServerError()
+        .content_type(XML_CONTENT_TYPE)
+        .body(body)
+}
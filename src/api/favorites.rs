@@ -1,19 +1,22 @@
 //! Favorites API routes aligned with upstream Python behavior
 
 use actix_web::{get, post, web, HttpResponse, Responder};
+use chrono::{DateTime, Datelike};
 use serde::Deserialize;
 use serde_json::{json, Map, Value};
+use utoipa::{IntoParams, OpenApi, ToSchema};
 
+use crate::api::identity::CurrentUser;
+use crate::api::imgserver::{insert_image_hints, CardImage};
 use crate::db::tables::FavoriteTable;
 use crate::models::{Album, Artist, Favorite, FavoriteType, Track};
 use crate::stores::{AlbumStore, ArtistStore, TrackStore};
 use crate::utils::dates::timestamp_to_relative;
 use crate::utils::extras::get_extra_info;
 
-const USER_ID: i64 = 0;
 const API_CARD_LIMIT: i64 = 6;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
 pub struct FavoritesAddBody {
     pub hash: String,
     #[serde(rename = "type")]
@@ -28,7 +31,7 @@ fn default_start() -> i64 {
     API_CARD_LIMIT
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct GetAllOfTypeQuery {
     #[serde(default = "default_start")]
     pub start: i64,
@@ -36,7 +39,7 @@ pub struct GetAllOfTypeQuery {
     pub limit: i64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct GetAllFavoritesQuery {
     #[serde(default = "default_limit")]
     pub track_limit: i64,
@@ -46,38 +49,81 @@ pub struct GetAllFavoritesQuery {
     pub artist_limit: i64,
 }
 
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct TimelineQuery {
+    /// only favorites added in this year, all of them when missing
+    pub year: Option<i32>,
+    /// most items listed per month, -1 lists all of them
+    #[serde(default = "default_timeline_limit")]
+    pub limit: i64,
+}
+
+fn default_timeline_limit() -> i64 {
+    -1
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[post("/add")]
-pub async fn add_favorite(body: web::Json<FavoritesAddBody>) -> impl Responder {
+pub async fn add_favorite(user: CurrentUser, body: web::Json<FavoritesAddBody>) -> impl Responder {
     let extra = get_extra_info(&body.hash, body.favorite_type.as_str());
 
     if let Err(e) =
-        FavoriteTable::add_with_extra(&body.hash, body.favorite_type, USER_ID, &extra).await
+        FavoriteTable::add_with_extra(&body.hash, body.favorite_type, user.id, &extra).await
     {
         eprintln!("{}", e);
         return HttpResponse::InternalServerError()
             .json(json!({"msg": "Failed! An error occured"}));
     }
 
-    update_store_favorite(&body.hash, body.favorite_type, true);
+    update_store_favorite(&body.hash, body.favorite_type, user.id, true);
     HttpResponse::Ok().json(json!({"msg": "Added to favorites"}))
 }
 
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[post("/remove")]
-pub async fn remove_favorite(body: web::Json<FavoritesAddBody>) -> impl Responder {
-    if let Err(e) = FavoriteTable::remove(&body.hash, body.favorite_type, USER_ID).await {
+pub async fn remove_favorite(
+    user: CurrentUser,
+    body: web::Json<FavoritesAddBody>,
+) -> impl Responder {
+    if let Err(e) = FavoriteTable::remove(&body.hash, body.favorite_type, user.id).await {
         eprintln!("{}", e);
         return HttpResponse::InternalServerError()
             .json(json!({"msg": "Failed! An error occured"}));
     }
 
-    update_store_favorite(&body.hash, body.favorite_type, false);
+    update_store_favorite(&body.hash, body.favorite_type, user.id, false);
     HttpResponse::Ok().json(json!({"msg": "Removed from favorites"}))
 }
 
+#[utoipa::path(
+    params(GetAllOfTypeQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/albums")]
-pub async fn get_favorite_albums(query: web::Query<GetAllOfTypeQuery>) -> impl Responder {
+pub async fn get_favorite_albums(
+    user: CurrentUser,
+    query: web::Query<GetAllOfTypeQuery>,
+) -> impl Responder {
     let (favorites, total) =
-        match get_favorites_by_type(FavoriteType::Album, query.start, query.limit).await {
+        match get_favorites_by_type(user.id, FavoriteType::Album, query.start, query.limit).await {
             Ok(res) => res,
             Err(resp) => return resp,
         };
@@ -92,10 +138,21 @@ pub async fn get_favorite_albums(query: web::Query<GetAllOfTypeQuery>) -> impl R
     HttpResponse::Ok().json(json!({"albums": albums, "total": total}))
 }
 
+#[utoipa::path(
+    params(GetAllOfTypeQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/tracks")]
-pub async fn get_favorite_tracks(query: web::Query<GetAllOfTypeQuery>) -> impl Responder {
+pub async fn get_favorite_tracks(
+    user: CurrentUser,
+    query: web::Query<GetAllOfTypeQuery>,
+) -> impl Responder {
     let (favorites, total) =
-        match get_favorites_by_type(FavoriteType::Track, query.start, query.limit).await {
+        match get_favorites_by_type(user.id, FavoriteType::Track, query.start, query.limit).await {
             Ok(res) => res,
             Err(resp) => return resp,
         };
@@ -104,19 +161,36 @@ pub async fn get_favorite_tracks(query: web::Query<GetAllOfTypeQuery>) -> impl R
     let tracks = TrackStore::get().get_by_hashes(&hashes);
     let tracks: Vec<Value> = tracks
         .iter()
-        .map(|t| Value::Object(serialize_track(t)))
+        .map(|t| Value::Object(serialize_track(t, user.id)))
         .collect();
 
     HttpResponse::Ok().json(json!({"tracks": tracks, "total": total}))
 }
 
+#[utoipa::path(
+    params(GetAllOfTypeQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/artists")]
-pub async fn get_favorite_artists(query: web::Query<GetAllOfTypeQuery>) -> impl Responder {
-    let (favorites, total) =
-        match get_favorites_by_type(FavoriteType::Artist, query.start, query.limit).await {
-            Ok(res) => res,
-            Err(resp) => return resp,
-        };
+pub async fn get_favorite_artists(
+    user: CurrentUser,
+    query: web::Query<GetAllOfTypeQuery>,
+) -> impl Responder {
+    let (favorites, total) = match get_favorites_by_type(
+        user.id,
+        FavoriteType::Artist,
+        query.start,
+        query.limit,
+    )
+    .await
+    {
+        Ok(res) => res,
+        Err(resp) => return resp,
+    };
 
     let hashes: Vec<String> = favorites.iter().map(|f| f.hash.clone()).collect();
     let artists = ArtistStore::get().get_by_hashes(&hashes);
@@ -128,9 +202,21 @@ pub async fn get_favorite_artists(query: web::Query<GetAllOfTypeQuery>) -> impl
     HttpResponse::Ok().json(json!({"artists": artists, "total": total}))
 }
 
+#[utoipa::path(
+    params(GetAllFavoritesQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("")]
-pub async fn get_all_favorites(query: web::Query<GetAllFavoritesQuery>) -> impl Responder {
-    let favorites = match FavoriteTable::all(Some(USER_ID)).await {
+pub async fn get_all_favorites(
+    user: CurrentUser,
+    query: web::Query<GetAllFavoritesQuery>,
+) -> impl Responder {
+    let favorites = match FavoriteTable::all(Some(user.id)).await {
         Ok(f) => f,
         Err(e) => {
             eprintln!("{}", e);
@@ -197,7 +283,7 @@ pub async fn get_all_favorites(query: web::Query<GetAllFavoritesQuery>) -> impl
 
     let serialized_tracks: Vec<Value> = tracks
         .iter()
-        .map(|t| Value::Object(serialize_track(t)))
+        .map(|t| Value::Object(serialize_track(t, user.id)))
         .collect();
     let serialized_albums: Vec<Value> = albums
         .clone()
@@ -241,7 +327,7 @@ pub async fn get_all_favorites(query: web::Query<GetAllFavoritesQuery>) -> impl
             }
             FavoriteType::Track => {
                 if let Some(track) = tracks.iter().find(|t| t.trackhash == fav.hash) {
-                    let mut map = serialize_track(track);
+                    let mut map = serialize_track(track, user.id);
                     map.insert("help_text".to_string(), Value::String("track".to_string()));
                     map.insert(
                         "time".to_string(),
@@ -268,9 +354,70 @@ pub async fn get_all_favorites(query: web::Query<GetAllFavoritesQuery>) -> impl
     }))
 }
 
+/// GET /favorites/timeline
+///
+/// Favorites grouped by the month they were added in, newest month first
+#[utoipa::path(
+    params(TimelineQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/timeline")]
+pub async fn get_favorites_timeline(
+    user: CurrentUser,
+    query: web::Query<TimelineQuery>,
+) -> impl Responder {
+    let favorites = match FavoriteTable::all(Some(user.id)).await {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("{}", e);
+            return HttpResponse::InternalServerError()
+                .json(json!({"msg": "Failed! An error occured"}));
+        }
+    };
+
+    let favorites: Vec<Favorite> = favorites
+        .into_iter()
+        .filter(|f| query.year.is_none_or(|year| favorite_year(f) == Some(year)))
+        .collect();
+
+    let months = favorites_by_month(&favorites);
+    let mut total = 0;
+    let months: Vec<Value> = months
+        .into_iter()
+        .filter_map(|(month, favs)| {
+            let month = serialize_timeline_month(&month, &favs, query.limit, user.id)?;
+            total += month["count"]["total"].as_u64().unwrap_or(0);
+            Some(month)
+        })
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "year": query.year,
+        "months": months,
+        "total": total,
+    }))
+}
+
+#[utoipa::path(
+    params(FavoritesAddBody),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/check")]
-pub async fn check_favorite(query: web::Query<FavoritesAddBody>) -> impl Responder {
-    match FavoriteTable::exists(&query.hash, query.favorite_type, USER_ID).await {
+pub async fn check_favorite(
+    user: CurrentUser,
+    query: web::Query<FavoritesAddBody>,
+) -> impl Responder {
+    match FavoriteTable::exists(&query.hash, query.favorite_type, user.id).await {
         Ok(is_favorite) => HttpResponse::Ok().json(json!({"is_favorite": is_favorite})),
         Err(e) => {
             eprintln!("{}", e);
@@ -279,6 +426,20 @@ pub async fn check_favorite(query: web::Query<FavoritesAddBody>) -> impl Respond
     }
 }
 
+/// OpenAPI description of the favorites routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    add_favorite,
+    remove_favorite,
+    get_favorite_albums,
+    get_favorite_tracks,
+    get_favorite_artists,
+    get_all_favorites,
+    get_favorites_timeline,
+    check_favorite,
+))]
+pub struct ApiDoc;
+
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(add_favorite)
         .service(remove_favorite)
@@ -286,23 +447,113 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
         .service(get_favorite_tracks)
         .service(get_favorite_artists)
         .service(get_all_favorites)
+        .service(get_favorites_timeline)
         .service(check_favorite);
 }
 
-fn update_store_favorite(hash: &str, fav_type: FavoriteType, favorite: bool) {
+fn update_store_favorite(hash: &str, fav_type: FavoriteType, user_id: i64, favorite: bool) {
     match fav_type {
-        FavoriteType::Track => TrackStore::get().mark_favorite(hash, favorite),
-        FavoriteType::Album => AlbumStore::get().mark_favorite(hash, favorite),
-        FavoriteType::Artist => ArtistStore::get().mark_favorite(hash, favorite),
+        FavoriteType::Track => TrackStore::get().mark_favorite(hash, user_id, favorite),
+        FavoriteType::Album => AlbumStore::get().mark_favorite(hash, user_id, favorite),
+        FavoriteType::Artist => ArtistStore::get().mark_favorite(hash, user_id, favorite),
+    }
+}
+
+fn favorite_year(fav: &Favorite) -> Option<i32> {
+    DateTime::from_timestamp(fav.timestamp, 0).map(|dt| dt.year())
+}
+
+/// Group favorites by the month they were added in as "YYYY-MM"
+///
+/// months come newest first and keep the order of the favorites inside them
+pub(crate) fn favorites_by_month(favorites: &[Favorite]) -> Vec<(String, Vec<&Favorite>)> {
+    let mut sorted: Vec<&Favorite> = favorites.iter().collect();
+    sorted.sort_by_key(|f| std::cmp::Reverse(f.timestamp));
+
+    let mut months: Vec<(String, Vec<&Favorite>)> = Vec::new();
+    for fav in sorted {
+        let Some(dt) = DateTime::from_timestamp(fav.timestamp, 0) else {
+            continue;
+        };
+        let month = dt.format("%Y-%m").to_string();
+
+        match months.last_mut() {
+            Some((last, favs)) if *last == month => favs.push(fav),
+            _ => months.push((month, vec![fav])),
+        }
     }
+
+    months
+}
+
+/// Serialize the favorites of a month, None when none of them is in the library
+fn serialize_timeline_month(
+    month: &str,
+    favorites: &[&Favorite],
+    limit: i64,
+    user_id: i64,
+) -> Option<Value> {
+    let track_store = TrackStore::get();
+    let album_store = AlbumStore::get();
+    let artist_store = ArtistStore::get();
+
+    let (mut tracks, mut albums, mut artists) = (0, 0, 0);
+    let mut items = Vec::new();
+    for fav in favorites {
+        let item = match fav.favorite_type {
+            FavoriteType::Track => track_store
+                .get_by_hash(&fav.hash)
+                .map(|t| serialize_track(&t, user_id)),
+            FavoriteType::Album => album_store
+                .get_by_hash(&fav.hash)
+                .map(|mut a| serialize_album_card(&mut a)),
+            FavoriteType::Artist => artist_store
+                .get_by_hash(&fav.hash)
+                .map(|mut a| serialize_artist_card(&mut a)),
+        };
+        let Some(item) = item else {
+            continue;
+        };
+
+        match fav.favorite_type {
+            FavoriteType::Track => tracks += 1,
+            FavoriteType::Album => albums += 1,
+            FavoriteType::Artist => artists += 1,
+        }
+
+        if limit < 0 || (items.len() as i64) < limit {
+            items.push(json!({
+                "type": fav.favorite_type.as_str(),
+                "timestamp": fav.timestamp,
+                "item": item,
+            }));
+        }
+    }
+
+    let total = tracks + albums + artists;
+    if total == 0 {
+        return None;
+    }
+
+    Some(json!({
+        "month": month,
+        "count": {
+            "tracks": tracks,
+            "albums": albums,
+            "artists": artists,
+            "total": total,
+        },
+        "items": items,
+    }))
 }
 
 async fn get_favorites_by_type(
+    user_id: i64,
     fav_type: FavoriteType,
     start: i64,
     limit: i64,
 ) -> Result<(Vec<Favorite>, i64), HttpResponse> {
-    let mut favorites: Vec<Favorite> = FavoriteTable::all(Some(USER_ID))
+    let mut favorites: Vec<Favorite> = FavoriteTable::all(Some(user_id))
         .await
         .map_err(|e| {
             eprintln!("{}", e);
@@ -333,7 +584,7 @@ async fn get_favorites_by_type(
     Ok((favorites, total))
 }
 
-fn serialize_track(track: &Track) -> Map<String, Value> {
+pub fn serialize_track(track: &Track, user_id: i64) -> Map<String, Value> {
     let mut map = serde_json::to_value(track)
         .unwrap_or_else(|_| json!({}))
         .as_object()
@@ -387,13 +638,14 @@ fn serialize_track(track: &Track) -> Map<String, Value> {
 
     map.insert(
         "is_favorite".to_string(),
-        Value::Bool(track.is_favorite(USER_ID)),
+        Value::Bool(track.is_favorite(user_id)),
     );
 
+    insert_image_hints(&mut map, CardImage::Thumbnail);
     map
 }
 
-fn serialize_album_card(album: &mut Album) -> Map<String, Value> {
+pub fn serialize_album_card(album: &mut Album) -> Map<String, Value> {
     let mut map = serde_json::to_value(album)
         .unwrap_or_else(|_| json!({}))
         .as_object()
@@ -433,10 +685,11 @@ fn serialize_album_card(album: &mut Album) -> Map<String, Value> {
     }
 
     map.insert("type".to_string(), Value::String("album".to_string()));
+    insert_image_hints(&mut map, CardImage::Thumbnail);
     map
 }
 
-fn serialize_artist_card(artist: &mut Artist) -> Map<String, Value> {
+pub fn serialize_artist_card(artist: &mut Artist) -> Map<String, Value> {
     let mut map = serde_json::to_value(artist)
         .unwrap_or_else(|_| json!({}))
         .as_object()
@@ -465,5 +718,43 @@ fn serialize_artist_card(artist: &mut Artist) -> Map<String, Value> {
     }
 
     map.insert("type".to_string(), Value::String("artist".to_string()));
+    insert_image_hints(&mut map, CardImage::Artist);
     map
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn favorite(hash: &str, timestamp: i64) -> Favorite {
+        Favorite {
+            id: 0,
+            hash: hash.to_string(),
+            favorite_type: FavoriteType::Track,
+            timestamp,
+            userid: 1,
+            extra: Value::Null,
+        }
+    }
+
+    #[test]
+    fn test_favorites_by_month() {
+        // 2024-01-31 23:59:59, 2024-02-01 00:00:00 and 2024-02-15
+        let favorites = vec![
+            favorite("a", 1706745599),
+            favorite("c", 1707955200),
+            favorite("b", 1706745600),
+        ];
+
+        let months = favorites_by_month(&favorites);
+        let months: Vec<(&str, Vec<&str>)> = months
+            .iter()
+            .map(|(m, favs)| (m.as_str(), favs.iter().map(|f| f.hash.as_str()).collect()))
+            .collect();
+
+        assert_eq!(
+            months,
+            vec![("2024-02", vec!["c", "b"]), ("2024-01", vec!["a"])]
+        );
+    }
+}
@@ -6,15 +6,22 @@ use std::time::UNIX_EPOCH;
 use actix_web::{get, post, web, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use utoipa::{IntoParams, OpenApi, ToSchema};
 
+use crate::api::identity::{Admin, Authorized, CurrentUser};
+use crate::api::imgserver::{insert_image_hints, CardImage};
 use crate::config::UserConfig;
-use crate::core::FolderLib;
+use crate::core::audiobooks;
+use crate::core::gapless;
+use crate::core::organizer;
+use crate::core::sorting::{CompoundSort, FolderSort, FolderSortFields, SortOrder, TrackSort};
+use crate::core::{FolderLib, SortLib};
 use crate::db::tables::{FavoriteTable, PlaylistTable, TrackTable};
-use crate::models::FavoriteType;
-use crate::stores::{FolderStore, TrackStore};
-use crate::utils::filesystem::{normalize_path, SUPPORTED_EXTENSIONS};
-
-const USER_ID: i64 = 0;
+use crate::models::{FavoriteType, Folder};
+use crate::stores::{FolderStore, PlayStatsStore, TrackStore};
+use crate::utils::filesystem::{
+    describe_io_error, dir_read_error, normalize_path, SUPPORTED_EXTENSIONS,
+};
 
 /// Folder response
 #[derive(Debug, Serialize)]
@@ -23,6 +30,9 @@ pub struct FolderResponse {
     pub path: String,
     pub is_sym: bool,
     pub trackcount: i32,
+    /// Why the server cannot list the folder, e.g. "permission denied"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 /// Track response (simplified)
@@ -41,6 +51,9 @@ pub struct FolderContentsResponse {
     pub subfolders: Vec<FolderResponse>,
     pub tracks: Vec<FolderTrackResponse>,
     pub breadcrumbs: Vec<BreadcrumbItem>,
+    /// Why the server cannot list the folder, its contents are then from the index
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 /// Breadcrumb item
@@ -64,9 +77,13 @@ fn path_is_symlink(path: &str) -> bool {
         .unwrap_or(false)
 }
 
+/// Folder entry for a directory, None for readable folders without tracks
+///
+/// unreadable folders are kept with their error so they do not pass for empty
 fn folder_entry_from_path(path: &str) -> Option<FolderResponse> {
     let trackcount = FolderLib::recursive_track_count(path) as i32;
-    if trackcount <= 0 {
+    let error = dir_read_error(Path::new(path));
+    if trackcount <= 0 && error.is_none() {
         return None;
     }
 
@@ -82,14 +99,27 @@ fn folder_entry_from_path(path: &str) -> Option<FolderResponse> {
         path: ensure_trailing_slash(path),
         is_sym: path_is_symlink(path),
         trackcount,
+        error,
     })
 }
 
+/// Folder entry for an indexed folder, with the error if it can no longer be listed
+fn folder_response(folder: Folder) -> FolderResponse {
+    let error = dir_read_error(Path::new(&folder.path));
+    FolderResponse {
+        name: folder.name,
+        path: folder.path,
+        is_sym: folder.is_sym,
+        trackcount: folder.trackcount,
+        error,
+    }
+}
+
 fn get_folders_from_paths(paths: &[String]) -> Vec<FolderResponse> {
     let counts = FolderStore::get().count_tracks_containing_paths(paths);
     counts
         .into_iter()
-        .filter(|(_, count)| *count > 0)
+        .filter(|(path, count)| *count > 0 || dir_read_error(Path::new(path)).is_some())
         .filter_map(|(path, trackcount)| {
             let entry = folder_entry_from_path(&path)?;
             Some(FolderResponse {
@@ -100,88 +130,23 @@ fn get_folders_from_paths(paths: &[String]) -> Vec<FolderResponse> {
         .collect()
 }
 
-fn sort_folders_for_folder(folders: &mut [FolderResponse], key: &str, reverse: bool) {
-    if key == "default" {
-        return;
+impl FolderSortFields for FolderResponse {
+    fn sort_name(&self) -> &str {
+        &self.name
     }
 
-    let comparator = |a: &FolderResponse, b: &FolderResponse| match key {
-        "name" => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
-        "trackcount" => a.trackcount.cmp(&b.trackcount),
-        "lastmod" => {
-            let lhs = std::fs::metadata(&a.path)
-                .and_then(|m| m.modified())
-                .ok()
-                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
-                .map(|d| d.as_secs())
-                .unwrap_or(0);
-            let rhs = std::fs::metadata(&b.path)
-                .and_then(|m| m.modified())
-                .ok()
-                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
-                .map(|d| d.as_secs())
-                .unwrap_or(0);
-            lhs.cmp(&rhs)
-        }
-        _ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
-    };
-
-    folders.sort_by(|a, b| {
-        if reverse {
-            comparator(b, a)
-        } else {
-            comparator(a, b)
-        }
-    });
-}
-
-fn sort_tracks_for_folder(tracks: &mut [crate::models::Track], key: &str, reverse: bool) {
-    if key == "default" {
-        return;
+    fn sort_path(&self) -> &str {
+        &self.path
     }
 
-    let comparator = |a: &crate::models::Track, b: &crate::models::Track| match key {
-        "album" => a.album.to_lowercase().cmp(&b.album.to_lowercase()),
-        "albumartists" => a
-            .albumartists
-            .get(0)
-            .map(|ar| ar.name.to_lowercase())
-            .cmp(&b.albumartists.get(0).map(|ar| ar.name.to_lowercase())),
-        "artists" => a
-            .artists
-            .get(0)
-            .map(|ar| ar.name.to_lowercase())
-            .cmp(&b.artists.get(0).map(|ar| ar.name.to_lowercase())),
-        "bitrate" => a.bitrate.cmp(&b.bitrate),
-        "date" => a.date.cmp(&b.date),
-        "disc" => {
-            let disc_cmp = a.disc.cmp(&b.disc);
-            if disc_cmp == std::cmp::Ordering::Equal {
-                a.track.cmp(&b.track)
-            } else {
-                disc_cmp
-            }
-        }
-        "duration" => a.duration.cmp(&b.duration),
-        "last_mod" => a.last_mod.cmp(&b.last_mod),
-        "lastplayed" => a.lastplayed.cmp(&b.lastplayed),
-        "playduration" => a.playduration.cmp(&b.playduration),
-        "playcount" => a.playcount.cmp(&b.playcount),
-        "title" => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
-        _ => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
-    };
-
-    tracks.sort_by(|a, b| {
-        if reverse {
-            comparator(b, a)
-        } else {
-            comparator(a, b)
-        }
-    });
+    fn sort_trackcount(&self) -> i32 {
+        self.trackcount
+    }
 }
 
 fn serialize_track_for_folder(
     track: &crate::models::Track,
+    user_id: i64,
     remove_disc: bool,
 ) -> serde_json::Value {
     let mut value = serde_json::to_value(track).unwrap_or_else(|_| json!({}));
@@ -240,8 +205,17 @@ fn serialize_track_for_folder(
 
         map.insert(
             "is_favorite".to_string(),
-            serde_json::Value::Bool(track.is_favorite(USER_ID)),
+            serde_json::Value::Bool(track.is_favorite(user_id)),
         );
+        map.insert(
+            "is_audiobook".to_string(),
+            serde_json::Value::Bool(audiobooks::is_audiobook(track)),
+        );
+        map.insert(
+            "gapless_with_next".to_string(),
+            serde_json::Value::Bool(gapless::gapless_with_next(&track.trackhash)),
+        );
+        insert_image_hints(map, CardImage::Thumbnail);
     }
 
     value
@@ -257,11 +231,17 @@ struct FolderTreeResult {
     folders: Vec<FolderResponse>,
     tracks: Vec<serde_json::Value>,
     total: usize,
+    /// tracks are in file order and left out of shuffles
+    audiobook: bool,
+    /// why the folder could not be listed, e.g. "permission denied"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
 }
 
 fn collect_files_and_dirs(
     path_str: &str,
     params: &FolderTreeRequest,
+    user_id: i64,
     skip_empty_folders: bool,
 ) -> FolderTreeResult {
     let path = PathBuf::from(path_str);
@@ -272,13 +252,17 @@ fn collect_files_and_dirs(
             folders: Vec::new(),
             tracks: Vec::new(),
             total: 0,
+            audiobook: false,
+            error: None,
         };
     }
 
     let mut dirs = Vec::new();
     let mut files = Vec::new();
 
-    if let Ok(entries) = std::fs::read_dir(&path) {
+    let entries = std::fs::read_dir(&path);
+    let error = entries.as_ref().err().map(describe_io_error);
+    if let Ok(entries) = entries {
         for entry in entries.flatten() {
             let entry_path = entry.path();
             let name = entry
@@ -333,8 +317,18 @@ fn collect_files_and_dirs(
             .filter_map(|p| store.get_by_path(p))
             .collect()
     };
+    PlayStatsStore::get().personalize_tracks(user_id, &mut tracks);
 
-    sort_tracks_for_folder(&mut tracks, &params.sorttracksby, params.tracksort_reverse);
+    let audiobook = audiobooks::is_audiobook_folder(path_str);
+    if audiobook {
+        audiobooks::sort_file_order(&mut tracks);
+    } else {
+        SortLib::sort_tracks_by(
+            &mut tracks,
+            &params.sorttracksby,
+            SortOrder::from_reverse(params.tracksort_reverse),
+        );
+    }
 
     let start = params.start.max(0) as usize;
     let limit = if params.limit < 0 {
@@ -352,7 +346,7 @@ fn collect_files_and_dirs(
 
     let serialized_tracks: Vec<_> = selected_tracks
         .iter()
-        .map(|t| serialize_track_for_folder(t, true))
+        .map(|t| serialize_track_for_folder(t, user_id, true))
         .collect();
 
     let mut folder_entries: Vec<FolderResponse> = if params.tracks_only {
@@ -363,18 +357,19 @@ fn collect_files_and_dirs(
             .collect()
     };
 
-    sort_folders_for_folder(
+    SortLib::sort_folders_by(
         &mut folder_entries,
         &params.sortfoldersby,
-        params.foldersort_reverse,
+        SortOrder::from_reverse(params.foldersort_reverse),
     );
 
     if skip_empty_folders
         && !params.tracks_only
         && folder_entries.len() == 1
+        && folder_entries[0].error.is_none()
         && serialized_tracks.is_empty()
     {
-        return collect_files_and_dirs(&folder_entries[0].path, params, true);
+        return collect_files_and_dirs(&folder_entries[0].path, params, user_id, true);
     }
 
     FolderTreeResult {
@@ -382,6 +377,8 @@ fn collect_files_and_dirs(
         folders: folder_entries,
         tracks: serialized_tracks,
         total,
+        audiobook,
+        error,
     }
 }
 
@@ -426,16 +423,19 @@ fn get_all_drives(is_win: bool) -> Vec<String> {
 }
 
 /// Request for upstream-compatible folder tree
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct FolderTreeRequest {
     #[serde(default = "default_folder_path")]
     pub folder: String,
-    #[serde(default = "default_sorttracksby")]
-    pub sorttracksby: String,
+    /// one key or a comma separated list, e.g. `album,disc,track`
+    #[serde(default)]
+    #[schema(value_type = String, example = "album,disc,track")]
+    pub sorttracksby: CompoundSort<TrackSort>,
     #[serde(default)]
     pub tracksort_reverse: bool,
     #[serde(default = "default_sortfoldersby")]
-    pub sortfoldersby: String,
+    #[schema(value_type = String, example = "last_mod")]
+    pub sortfoldersby: CompoundSort<FolderSort>,
     #[serde(default)]
     pub foldersort_reverse: bool,
     #[serde(default)]
@@ -450,12 +450,8 @@ fn default_folder_path() -> String {
     "$home".to_string()
 }
 
-fn default_sorttracksby() -> String {
-    "default".to_string()
-}
-
-fn default_sortfoldersby() -> String {
-    "lastmod".to_string()
+fn default_sortfoldersby() -> CompoundSort<FolderSort> {
+    FolderSort::LastMod.into()
 }
 
 fn default_limit() -> i64 {
@@ -463,7 +459,7 @@ fn default_limit() -> i64 {
 }
 
 /// Request for dir-browser (root selection)
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct DirBrowserRequest {
     #[serde(default = "default_root_dir")]
     pub folder: String,
@@ -474,24 +470,27 @@ fn default_root_dir() -> String {
 }
 
 /// Query for opening folder in file manager
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct OpenInFilesQuery {
     pub path: String,
 }
 
 /// Query for fetching tracks recursively
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct TracksInPathQuery {
     pub path: String,
 }
 
 /// Query parameters for folder
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct FolderQuery {
     pub path: Option<String>,
 }
 
 /// Get root directories
+#[utoipa::path(
+    responses((status = 200, description = "Success"))
+)]
 #[get("/roots")]
 pub async fn get_roots() -> impl Responder {
     let roots = FolderLib::get_root_dirs();
@@ -499,18 +498,20 @@ pub async fn get_roots() -> impl Responder {
     let folders: Vec<_> = roots
         .iter()
         .filter_map(|path| FolderLib::get_by_path(path))
-        .map(|f| FolderResponse {
-            name: f.name,
-            path: f.path,
-            is_sym: f.is_sym,
-            trackcount: f.trackcount,
-        })
+        .map(folder_response)
         .collect();
 
     HttpResponse::Ok().json(folders)
 }
 
 /// Get folder contents
+#[utoipa::path(
+    params(FolderQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request")
+    )
+)]
 #[get("")]
 pub async fn get_folder(query: web::Query<FolderQuery>) -> impl Responder {
     let path = match &query.path {
@@ -523,15 +524,11 @@ pub async fn get_folder(query: web::Query<FolderQuery>) -> impl Responder {
                 subfolders: roots
                     .iter()
                     .filter_map(|p| FolderLib::get_by_path(p))
-                    .map(|f| FolderResponse {
-                        name: f.name,
-                        path: f.path,
-                        is_sym: f.is_sym,
-                        trackcount: f.trackcount,
-                    })
+                    .map(folder_response)
                     .collect(),
                 tracks: Vec::new(),
                 breadcrumbs: Vec::new(),
+                error: None,
             });
         }
     };
@@ -544,26 +541,20 @@ pub async fn get_folder(query: web::Query<FolderQuery>) -> impl Responder {
     }
 
     // Get folder info
-    let folder = FolderLib::get_by_path(&path).map(|f| FolderResponse {
-        name: f.name,
-        path: f.path,
-        is_sym: f.is_sym,
-        trackcount: f.trackcount,
-    });
+    let folder = FolderLib::get_by_path(&path).map(folder_response);
 
     // Get subfolders
     let subfolders: Vec<_> = FolderLib::get_subfolders(&path)
         .into_iter()
-        .map(|f| FolderResponse {
-            name: f.name,
-            path: f.path,
-            is_sym: f.is_sym,
-            trackcount: f.trackcount,
-        })
+        .map(folder_response)
         .collect();
 
     // Get tracks
-    let tracks: Vec<_> = FolderLib::get_tracks(&path)
+    let mut tracks = FolderLib::get_tracks(&path);
+    if audiobooks::is_audiobook_folder(&path) {
+        audiobooks::sort_file_order(&mut tracks);
+    }
+    let tracks: Vec<_> = tracks
         .into_iter()
         .map(|t| FolderTrackResponse {
             trackhash: t.trackhash.clone(),
@@ -584,12 +575,24 @@ pub async fn get_folder(query: web::Query<FolderQuery>) -> impl Responder {
         subfolders,
         tracks,
         breadcrumbs,
+        error: dir_read_error(Path::new(&path)),
     })
 }
 
 /// Upstream-compatible folder tree (POST /folder)
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[post("")]
-pub async fn get_folder_tree(body: web::Json<FolderTreeRequest>) -> impl Responder {
+pub async fn get_folder_tree(
+    user: CurrentUser,
+    body: web::Json<FolderTreeRequest>,
+) -> impl Responder {
+    let user_id = user.id;
     let mut params = body.into_inner();
     let og_req_dir = params.folder.clone();
     let config = UserConfig::load().unwrap_or_default();
@@ -614,7 +617,7 @@ pub async fn get_folder_tree(body: web::Json<FolderTreeRequest>) -> impl Respond
         if parts.len() == 2 && !parts[1].is_empty() {
             let playlist_id: i64 = parts[1].parse().unwrap_or_default();
             match PlaylistTable::get_by_id(playlist_id).await {
-                Ok(Some(playlist)) => {
+                Ok(Some(playlist)) if playlist.can_view(user_id) => {
                     let start = params.start.max(0) as usize;
                     let limit = if params.limit < 0 {
                         playlist.trackhashes.len().saturating_sub(start)
@@ -632,7 +635,7 @@ pub async fn get_folder_tree(body: web::Json<FolderTreeRequest>) -> impl Respond
                     let tracks = TrackStore::get().get_by_hashes(&selected_hashes);
                     let serialized: Vec<_> = tracks
                         .iter()
-                        .map(|t| serialize_track_for_folder(t, true))
+                        .map(|t| serialize_track_for_folder(t, user_id, true))
                         .collect();
 
                     return HttpResponse::Ok().json(json!({
@@ -651,7 +654,7 @@ pub async fn get_folder_tree(body: web::Json<FolderTreeRequest>) -> impl Respond
             }
         }
 
-        let mut playlists = PlaylistTable::all(None).await.unwrap_or_default();
+        let mut playlists = PlaylistTable::visible(user_id).await.unwrap_or_default();
         playlists.sort_by(|a, b| b.last_updated.cmp(&a.last_updated));
         let folders: Vec<_> = playlists
             .into_iter()
@@ -660,6 +663,7 @@ pub async fn get_folder_tree(body: web::Json<FolderTreeRequest>) -> impl Respond
                 path: format!("$playlist/{}", p.id),
                 is_sym: false,
                 trackcount: p.count,
+                error: None,
             })
             .collect();
 
@@ -677,7 +681,7 @@ pub async fn get_folder_tree(body: web::Json<FolderTreeRequest>) -> impl Respond
             params.limit
         };
         let favorites =
-            FavoriteTable::get_by_type(FavoriteType::Track, USER_ID, params.start, limit)
+            FavoriteTable::get_by_type(FavoriteType::Track, user_id, params.start, limit)
                 .await
                 .unwrap_or_default();
 
@@ -685,7 +689,7 @@ pub async fn get_folder_tree(body: web::Json<FolderTreeRequest>) -> impl Respond
         let tracks = TrackStore::get().get_by_hashes(&trackhashes);
         let serialized: Vec<_> = tracks
             .iter()
-            .map(|t| serialize_track_for_folder(t, true))
+            .map(|t| serialize_track_for_folder(t, user_id, true))
             .collect();
 
         return HttpResponse::Ok().json(json!({
@@ -702,17 +706,18 @@ pub async fn get_folder_tree(body: web::Json<FolderTreeRequest>) -> impl Respond
         }
     }
 
-    let mut result = collect_files_and_dirs(&params.folder, &params, true);
+    let mut result = collect_files_and_dirs(&params.folder, &params, user_id, true);
 
     if og_req_dir == "$home" && config.show_playlists_in_folder_view {
         let favorites_item = FolderResponse {
             name: "Favorites".to_string(),
             path: "$favorites".to_string(),
             is_sym: false,
-            trackcount: FavoriteTable::count_tracks(USER_ID).await.unwrap_or(0) as i32,
+            trackcount: FavoriteTable::count_tracks(user_id).await.unwrap_or(0) as i32,
+            error: None,
         };
 
-        let playlists = PlaylistTable::all(None).await.unwrap_or_default();
+        let playlists = PlaylistTable::visible(user_id).await.unwrap_or_default();
         let playlist_sum: i32 = playlists.iter().map(|p| p.count).sum();
 
         let playlists_item = FolderResponse {
@@ -720,6 +725,7 @@ pub async fn get_folder_tree(body: web::Json<FolderTreeRequest>) -> impl Respond
             path: "$playlists".to_string(),
             is_sym: false,
             trackcount: playlist_sum,
+            error: None,
         };
 
         result.folders.insert(0, playlists_item);
@@ -730,6 +736,13 @@ pub async fn get_folder_tree(body: web::Json<FolderTreeRequest>) -> impl Respond
 }
 
 /// Get parent folder
+#[utoipa::path(
+    params(FolderQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request")
+    )
+)]
 #[get("/parent")]
 pub async fn get_parent(query: web::Query<FolderQuery>) -> impl Responder {
     let path = match &query.path {
@@ -760,6 +773,9 @@ pub async fn get_parent(query: web::Query<FolderQuery>) -> impl Responder {
 }
 
 /// List folders for root selection
+#[utoipa::path(
+    responses((status = 200, description = "Success"))
+)]
 #[post("/dir-browser")]
 pub async fn list_folders(body: web::Json<DirBrowserRequest>) -> impl Responder {
     let req_dir = body.folder.clone();
@@ -782,7 +798,9 @@ pub async fn list_folders(body: web::Json<DirBrowserRequest>) -> impl Responder
     }
 
     let mut folders = Vec::new();
-    if let Ok(entries) = std::fs::read_dir(&dir_path) {
+    let entries = std::fs::read_dir(&dir_path);
+    let error = entries.as_ref().err().map(describe_io_error);
+    if let Ok(entries) = entries {
         for entry in entries.flatten() {
             let path = entry.path();
             let name = entry
@@ -796,10 +814,15 @@ pub async fn list_folders(body: web::Json<DirBrowserRequest>) -> impl Responder
             }
 
             if path.is_dir() {
-                folders.push(json!({
+                let mut folder = json!({
                     "name": name,
                     "path": normalize_path_str(&path.to_string_lossy()),
-                }));
+                });
+                // flag folders the server cannot read before they are picked as roots
+                if let Some(error) = dir_read_error(&path) {
+                    folder["error"] = json!(error);
+                }
+                folders.push(folder);
             }
         }
     }
@@ -811,34 +834,213 @@ pub async fn list_folders(body: web::Json<DirBrowserRequest>) -> impl Responder
             .cmp(b["name"].as_str().unwrap_or(""))
     });
 
-    HttpResponse::Ok().json(json!({ "folders": folders }))
+    match error {
+        Some(error) => HttpResponse::Ok().json(json!({ "folders": folders, "error": error })),
+        None => HttpResponse::Ok().json(json!({ "folders": folders })),
+    }
 }
 
 /// Open path in file manager (no-op placeholder)
+#[utoipa::path(
+    params(OpenInFilesQuery),
+    responses((status = 200, description = "Success"))
+)]
 #[get("/show-in-files")]
 pub async fn open_in_file_manager(_query: web::Query<OpenInFilesQuery>) -> impl Responder {
     HttpResponse::Ok().json(json!({ "success": true }))
 }
 
 /// Get tracks in a path recursively (max 300)
+#[utoipa::path(
+    params(TracksInPathQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/tracks/all")]
-pub async fn get_tracks_in_path(query: web::Query<TracksInPathQuery>) -> impl Responder {
+pub async fn get_tracks_in_path(
+    user: CurrentUser,
+    query: web::Query<TracksInPathQuery>,
+) -> impl Responder {
+    let user_id = user.id;
     let path_prefix = normalize_path_str(&query.path);
     let mut tracks = TrackTable::get_by_folder_containing(&path_prefix)
         .await
         .unwrap_or_default();
 
+    if audiobooks::is_audiobook_folder(&path_prefix) {
+        audiobooks::sort_file_order(&mut tracks);
+    }
+
     // limit to 300 like upstream
     tracks.truncate(300);
 
     let serialized: Vec<_> = tracks
         .iter()
-        .map(|t| serialize_track_for_folder(t, true))
+        .map(|t| serialize_track_for_folder(t, user_id, true))
         .collect();
 
     HttpResponse::Ok().json(json!({ "tracks": serialized }))
 }
 
+/// Flag or unflag a folder as an audiobook
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AudiobookRequest {
+    pub path: String,
+    pub audiobook: bool,
+}
+
+/// Mark a folder as an audiobook (POST /folder/audiobook)
+///
+/// its tracks play in file order and are kept out of shuffles, mixes and stats
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[post("/audiobook")]
+pub async fn set_audiobook_folder(
+    _admin: Authorized<Admin>,
+    body: web::Json<AudiobookRequest>,
+) -> impl Responder {
+    let path = normalize_path_str(body.path.trim());
+    if path.is_empty() || !Path::new(&path).is_dir() {
+        return HttpResponse::BadRequest().json(json!({"error": "Folder does not exist"}));
+    }
+
+    match audiobooks::set_folder(&path, body.audiobook) {
+        Ok(folders) => HttpResponse::Ok().json(json!({
+            "path": path,
+            "audiobook": body.audiobook,
+            "audiobook_dirs": folders,
+        })),
+        Err(e) => HttpResponse::InternalServerError()
+            .json(json!({"error": format!("Failed to save settings: {}", e)})),
+    }
+}
+
+/// Rename a folder on disk
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RenameFolderRequest {
+    pub path: String,
+    /// new name of the folder, not a path
+    pub name: String,
+}
+
+/// Rename a folder and the paths of the tracks in it (POST /folder/rename)
+#[utoipa::path(
+    request_body = RenameFolderRequest,
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[post("/rename")]
+pub async fn rename_folder(
+    _admin: Authorized<Admin>,
+    body: web::Json<RenameFolderRequest>,
+) -> impl Responder {
+    let path = normalize_path_str(body.path.trim());
+
+    match organizer::rename_folder(&path, &body.name).await {
+        Ok((renamed, tracks)) => HttpResponse::Ok().json(json!({
+            "path": renamed,
+            "tracks": tracks.len(),
+        })),
+        Err(e) => HttpResponse::BadRequest().json(json!({"error": format!("{:#}", e)})),
+    }
+}
+
+/// File every track of a folder by a path template
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct OrganizeFolderRequest {
+    pub path: String,
+    /// path template, the configured one when missing
+    #[serde(default)]
+    pub template: Option<String>,
+    /// only report where the files would go
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Move the tracks of a folder and its subfolders to where the path template
+/// puts them (POST /folder/organize)
+#[utoipa::path(
+    request_body = OrganizeFolderRequest,
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 409, description = "Files can't be moved there"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[post("/organize")]
+pub async fn organize_folder(
+    _admin: Authorized<Admin>,
+    body: web::Json<OrganizeFolderRequest>,
+) -> impl Responder {
+    let path = normalize_path_str(body.path.trim());
+    if !FolderLib::is_valid_path(&path) {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "Path is not within configured root directories"
+        }));
+    }
+
+    let template = match &body.template {
+        Some(template) => template.clone(),
+        None => UserConfig::load().unwrap_or_default().file_template,
+    };
+    if let Err(e) = organizer::validate_template(&template) {
+        return HttpResponse::BadRequest().json(json!({"error": e.to_string()}));
+    }
+
+    let tracks = organizer::tracks_under(Path::new(&path));
+    let moves = match organizer::plan(&tracks, &template, &FolderLib::get_root_dirs()) {
+        Ok(moves) => moves,
+        Err(e) => return HttpResponse::Conflict().json(json!({"error": e.to_string()})),
+    };
+    if body.dry_run {
+        return HttpResponse::Ok().json(json!({"moves": moves, "dry_run": true}));
+    }
+
+    match organizer::apply(moves.clone()).await {
+        Ok(_) => HttpResponse::Ok().json(json!({"moves": moves, "dry_run": false})),
+        Err(e) => {
+            tracing::error!("Failed to organize {}: {:#}", path, e);
+            HttpResponse::InternalServerError().json(json!({"error": format!("{:#}", e)}))
+        }
+    }
+}
+
+/// OpenAPI description of the folder routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    get_roots,
+    get_folder,
+    get_folder_tree,
+    list_folders,
+    open_in_file_manager,
+    get_tracks_in_path,
+    set_audiobook_folder,
+    rename_folder,
+    organize_folder,
+    get_parent,
+))]
+pub struct ApiDoc;
+
 /// Configure folder routes
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(get_roots)
@@ -847,5 +1049,8 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
         .service(list_folders)
         .service(open_in_file_manager)
         .service(get_tracks_in_path)
+        .service(set_audiobook_folder)
+        .service(rename_folder)
+        .service(organize_folder)
         .service(get_parent);
 }
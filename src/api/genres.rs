@@ -0,0 +1,306 @@
+//! Genre API routes
+
+use actix_web::{get, web, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use utoipa::{IntoParams, OpenApi};
+
+use crate::api::getall::{to_album_card_map, to_artist_card_map};
+use crate::api::identity::CurrentUser;
+use crate::api::imgserver::{insert_image_hints, CardImage};
+use crate::api::track::serialize_for_user;
+use crate::core::genres::{
+    all_genres, genre_albums, genre_artists, genre_tracks, GenreArtistSort, GenreSort, GenreSummary,
+};
+use crate::core::sorting::{AlbumSort, CompoundSort, SortLib, SortOrder, TrackSort};
+use crate::stores::{AlbumStore, PlayStatsStore};
+
+/// Most tracks sent in one page
+const MAX_TRACK_PAGE: usize = 500;
+
+/// Genre path param
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
+pub struct GenrePath {
+    pub genrehash: String,
+}
+
+/// Paging and order of a genre's artists
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct GenreArtistsQuery {
+    #[serde(default)]
+    pub start: usize,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+    /// `tracks` (default) or `plays`
+    #[serde(default)]
+    pub sort: String,
+}
+
+/// Paging and order of the genre list
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct GenresQuery {
+    #[serde(default)]
+    pub start: usize,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+    /// `tracks` (default), `albums`, `artists`, `plays` or `name`
+    #[serde(default)]
+    pub sort: String,
+    /// flip the order, counts go from the largest and names from a
+    #[serde(default)]
+    pub reverse: bool,
+}
+
+/// Paging and order of a genre's tracks or albums
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct GenreItemsQuery {
+    #[serde(default)]
+    pub start: usize,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+    /// one key or a comma separated list, as taken by `/getall`
+    #[serde(default)]
+    pub sort: String,
+    #[serde(default)]
+    pub reverse: bool,
+}
+
+fn default_limit() -> usize {
+    20
+}
+
+/// GET /genres - genres in the library with their counts, image and colors
+#[utoipa::path(
+    params(GenresQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("")]
+pub async fn get_genres(query: web::Query<GenresQuery>) -> impl Responder {
+    let genres = all_genres(GenreSort::parse(&query.sort), query.reverse);
+    let total = genres.len();
+    let items: Vec<Value> = genres
+        .iter()
+        .skip(query.start)
+        .take(query.limit)
+        .map(genre_card)
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "genres": items,
+        "total": total,
+    }))
+}
+
+/// GET /genres/{genrehash}/tracks - tracks of a genre
+#[utoipa::path(
+    params(GenrePath, GenreItemsQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/{genrehash}/tracks")]
+pub async fn get_genre_tracks(
+    user: CurrentUser,
+    path: web::Path<GenrePath>,
+    query: web::Query<GenreItemsQuery>,
+) -> impl Responder {
+    let mut tracks: Vec<_> = genre_tracks(&path.genrehash)
+        .into_iter()
+        .map(Arc::unwrap_or_clone)
+        .collect();
+    let Some(genre) = tracks
+        .iter()
+        .flat_map(|t| &t.genres)
+        .find(|g| g.genrehash == path.genrehash)
+        .cloned()
+    else {
+        return HttpResponse::NotFound().json(json!({"error": "Genre not found"}));
+    };
+
+    PlayStatsStore::get().personalize_tracks(user.id, &mut tracks);
+    // the store has no order of its own, pages need one that holds between requests
+    tracks.sort_by(|a, b| a.filepath.cmp(&b.filepath));
+    let sorts = CompoundSort::<TrackSort>::parse(&query.sort);
+    SortLib::sort_tracks_by(&mut tracks, &sorts, SortOrder::from_reverse(query.reverse));
+
+    let total = tracks.len();
+    let page: Vec<_> = tracks
+        .into_iter()
+        .skip(query.start)
+        .take(query.limit.min(MAX_TRACK_PAGE))
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "genre": {"name": genre.name, "genrehash": genre.genrehash},
+        "tracks": serialize_for_user(page, user.id),
+        "total": total,
+    }))
+}
+
+/// GET /genres/{genrehash}/albums - albums with tracks in a genre
+#[utoipa::path(
+    params(GenrePath, GenreItemsQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/{genrehash}/albums")]
+pub async fn get_genre_albums(
+    user: CurrentUser,
+    path: web::Path<GenrePath>,
+    query: web::Query<GenreItemsQuery>,
+) -> impl Responder {
+    let Some((genre, mut albums)) = genre_albums(&path.genrehash) else {
+        return HttpResponse::NotFound().json(json!({"error": "Genre not found"}));
+    };
+
+    PlayStatsStore::get().personalize_albums(user.id, &mut albums);
+    let sorts = CompoundSort::<AlbumSort>::parse(&query.sort);
+    SortLib::sort_albums_by(&mut albums, &sorts, SortOrder::from_reverse(query.reverse));
+
+    let total = albums.len();
+    let albums: Vec<Value> = albums
+        .into_iter()
+        .skip(query.start)
+        .take(query.limit)
+        .map(|mut album| Value::Object(to_album_card_map(&mut album)))
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "genre": {"name": genre.name, "genrehash": genre.genrehash},
+        "albums": albums,
+        "total": total,
+    }))
+}
+
+/// A genre list entry, pictured by its highest ranked album with colors
+fn genre_card(summary: &GenreSummary) -> Value {
+    let mut map = Map::new();
+    map.insert("name".to_string(), json!(summary.genre.name));
+    map.insert("genrehash".to_string(), json!(summary.genre.genrehash));
+    map.insert("trackcount".to_string(), json!(summary.trackcount));
+    map.insert("albumcount".to_string(), json!(summary.albumcount));
+    map.insert("artistcount".to_string(), json!(summary.artistcount));
+    map.insert("playcount".to_string(), json!(summary.playcount));
+    map.insert("duration".to_string(), json!(summary.duration));
+
+    let store = AlbumStore::get();
+    let colored = summary.albumhashes.iter().find_map(|hash| {
+        store
+            .get_color(hash)
+            .filter(|(color, _)| !color.is_empty())
+            .map(|color| (hash, color))
+    });
+    let albumhash = colored
+        .as_ref()
+        .map(|(hash, _)| *hash)
+        .or_else(|| summary.albumhashes.first());
+    if let Some(albumhash) = albumhash {
+        map.insert("albumhash".to_string(), json!(albumhash));
+        map.insert("image".to_string(), json!(format!("{}.webp", albumhash)));
+        insert_image_hints(&mut map, CardImage::Thumbnail);
+    }
+    let (color, variants) = colored.map(|(_, c)| c).unwrap_or_default();
+    map.insert("color".to_string(), json!(color));
+    map.insert("color_dark".to_string(), json!(variants.dark));
+    map.insert("color_light".to_string(), json!(variants.light));
+
+    Value::Object(map)
+}
+
+/// GET /genres/{genrehash}/artists - artists of a genre ranked by track count
+/// or plays, with the image standing for the genre
+#[utoipa::path(
+    params(GenrePath, GenreArtistsQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/{genrehash}/artists")]
+pub async fn get_genre_artists(
+    user: CurrentUser,
+    path: web::Path<GenrePath>,
+    query: web::Query<GenreArtistsQuery>,
+) -> impl Responder {
+    let sort = GenreArtistSort::parse(&query.sort);
+    let Some(genre) = genre_artists(&path.genrehash, user.id, sort) else {
+        return HttpResponse::NotFound().json(json!({"error": "Genre not found"}));
+    };
+
+    let mut info = json!({
+        "name": genre.genre.name,
+        "genrehash": genre.genre.genrehash,
+        "trackcount": genre.trackcount,
+        "artistcount": genre.artists.len(),
+    });
+    if let (Some(artist), Some(map)) = (genre.representative(), info.as_object_mut()) {
+        map.insert("artisthash".to_string(), json!(artist.artisthash));
+        map.insert("image".to_string(), json!(artist.image));
+        map.insert("color".to_string(), json!(artist.color));
+        map.insert("color_dark".to_string(), json!(artist.color_dark));
+        map.insert("color_light".to_string(), json!(artist.color_light));
+        insert_image_hints(map, CardImage::Artist);
+    }
+
+    let total = genre.artists.len();
+    let artists: Vec<Value> = genre
+        .artists
+        .into_iter()
+        .skip(query.start)
+        .take(query.limit)
+        .map(|mut entry| {
+            let mut map = to_artist_card_map(&mut entry.artist);
+            let count = entry.trackcount;
+            map.insert("genre_trackcount".to_string(), json!(count));
+            map.insert("genre_playcount".to_string(), json!(entry.playcount));
+            let help = match sort {
+                GenreArtistSort::Plays => plural(entry.playcount as usize, "play"),
+                GenreArtistSort::Tracks => plural(count, "track"),
+            };
+            map.insert("help_text".to_string(), json!(help));
+            Value::Object(map)
+        })
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "genre": info,
+        "artists": artists,
+        "total": total,
+    }))
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+/// OpenAPI description of the genre routes
+#[derive(OpenApi)]
+#[openapi(paths(get_genres, get_genre_tracks, get_genre_albums, get_genre_artists))]
+pub struct ApiDoc;
+
+/// Configure genre routes
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_genres)
+        .service(get_genre_tracks)
+        .service(get_genre_albums)
+        .service(get_genre_artists);
+}
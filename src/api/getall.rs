@@ -1,164 +1,314 @@
 //! GetAll API routes - match upstream Flask `/getall/<itemtype>` behavior
 
 use actix_web::{get, web, HttpResponse, Responder};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
 use chrono::{Datelike, TimeZone, Utc};
 use serde::Deserialize;
 use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use utoipa::{IntoParams, OpenApi};
 
-use crate::stores::{AlbumStore, ArtistStore};
+use crate::api::identity::CurrentUser;
+use crate::api::imgserver::{insert_image_hints, CardImage};
+use crate::api::track::serialize_for_user;
+use crate::core::sorting::{AlbumSort, ArtistSort, CompoundSort, SortOrder, TrackSort};
+use crate::core::SortLib;
+use crate::models::{Album, Artist, Track};
+use crate::stores::{AlbumStore, ArtistStore, PlayStatsStore, TrackStore};
 use crate::utils::dates::{seconds_to_human_readable, timestamp_to_relative};
 
+/// Most items sent in one page
+const MAX_PAGE: usize = 1000;
+
 /// Query parameters (aligned with Python defaults/types)
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct GetAllQuery {
     #[serde(default)]
     pub start: usize,
     #[serde(default = "default_limit")]
     pub limit: usize,
-    #[serde(default = "default_sort")]
+    /// one key or a comma separated list, e.g. `albumartist,date:desc`
+    #[serde(default, alias = "sort")]
     pub sortby: String,
     #[serde(default = "default_reverse")]
     pub reverse: String,
+    /// `next_cursor` of the previous page, takes the place of `start`
+    pub cursor: Option<String>,
+    /// comma separated fields to send of each item, all of them when missing
+    pub fields: Option<String>,
 }
 
 fn default_limit() -> usize {
     6
 }
 
-fn default_sort() -> String {
-    "created_date".to_string()
-}
-
 fn default_reverse() -> String {
     "1".to_string()
 }
 
 /// Path param
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
 pub struct GetAllPath {
     pub itemtype: String,
 }
 
-/// GET /getall/<itemtype>
+/// GET /getall/<itemtype>, where itemtype is albums, artists or tracks
+#[utoipa::path(
+    params(GetAllPath, GetAllQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/{itemtype}")]
 pub async fn get_all_items(
+    user: CurrentUser,
     path: web::Path<GetAllPath>,
     query: web::Query<GetAllQuery>,
 ) -> impl Responder {
-    let is_albums = path.itemtype == "albums";
-    let is_artists = path.itemtype == "artists";
+    let order = SortOrder::from_reverse(query.reverse == "1");
+    let cursor = match query.cursor.as_deref().map(decode_cursor) {
+        Some(Some(cursor)) => Some(cursor),
+        Some(None) => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": "Invalid cursor",
+            }))
+        }
+        None => None,
+    };
+    let fields: Option<Vec<&str>> = query.fields.as_deref().map(|f| {
+        f.split(',')
+            .map(str::trim)
+            .filter(|f| !f.is_empty())
+            .collect()
+    });
 
-    if !is_albums && !is_artists {
-        return HttpResponse::BadRequest().json(json!({
-            "error": "Invalid itemtype. Valid types are 'albums' or 'artists'",
-        }));
-    }
+    let limit = query.limit.min(MAX_PAGE);
 
-    let start = query.start;
-    let limit = query.limit;
-    let reverse = query.reverse == "1";
-    let sort = query.sortby.as_str();
-
-    if is_albums {
-        let mut items = AlbumStore::get().get_all();
-        sort_albums(&mut items, sort, reverse);
-        let total = items.len();
-        let slice = items
-            .into_iter()
-            .skip(start)
-            .take(limit)
-            .collect::<Vec<_>>();
-        let mapped = slice
-            .into_iter()
-            .map(|mut a| {
-                let mut map = to_album_card_map(&mut a);
-                if let Some(help) = album_help_text(sort, &a) {
-                    map.insert("help_text".to_string(), Value::String(help));
-                }
-                Value::Object(map)
-            })
-            .collect::<Vec<_>>();
-
-        return HttpResponse::Ok().json(json!({
-            "items": mapped,
-            "total": total,
-        }));
-    }
+    let (items, total, next_cursor) = match path.itemtype.as_str() {
+        "albums" => {
+            let sorts = CompoundSort::<AlbumSort>::parse(&query.sortby);
+            let items = sorted_albums(user.id, &sorts, order);
+            let sort = sorts.primary();
 
-    let mut items = ArtistStore::get().get_all();
-    sort_artists(&mut items, sort, reverse);
-    let total = items.len();
-    let slice = items
-        .into_iter()
-        .skip(start)
-        .take(limit)
-        .collect::<Vec<_>>();
-    let mapped = slice
+            let start = page_start(&items, cursor.as_ref(), query.start, |a| &a.albumhash);
+            let page = Page::of(&items, start, limit, |a| &a.albumhash);
+            let mapped = items[page.range()]
+                .iter()
+                .map(|a| a.as_ref().clone())
+                .map(|mut a| {
+                    let mut map = to_album_card_map(&mut a);
+                    if let Some(help) = album_help_text(sort, &a) {
+                        map.insert("help_text".to_string(), Value::String(help));
+                    }
+                    map
+                })
+                .collect::<Vec<_>>();
+            (mapped, items.len(), page.next_cursor)
+        }
+        "artists" => {
+            let sorts = CompoundSort::<ArtistSort>::parse(&query.sortby);
+            let items = sorted_artists(user.id, &sorts, order);
+            let sort = sorts.primary();
+
+            let start = page_start(&items, cursor.as_ref(), query.start, |a| &a.artisthash);
+            let page = Page::of(&items, start, limit, |a| &a.artisthash);
+            let mapped = items[page.range()]
+                .iter()
+                .map(|a| a.as_ref().clone())
+                .map(|mut a| {
+                    let mut map = to_artist_card_map(&mut a);
+                    if let Some(help) = artist_help_text(sort, &a) {
+                        map.insert("help_text".to_string(), Value::String(help));
+                    }
+                    map
+                })
+                .collect::<Vec<_>>();
+            (mapped, items.len(), page.next_cursor)
+        }
+        "tracks" => {
+            let sorts = CompoundSort::<TrackSort>::parse(&query.sortby);
+            let items = sorted_tracks(user.id, &sorts, order);
+
+            let start = page_start(&items, cursor.as_ref(), query.start, |t| &t.trackhash);
+            let page = Page::of(&items, start, limit, |t| &t.trackhash);
+            let tracks = items[page.range()]
+                .iter()
+                .map(|t| t.as_ref().clone())
+                .collect();
+            let mapped = serialize_for_user(tracks, user.id)
+                .into_iter()
+                .filter_map(|v| match v {
+                    Value::Object(map) => Some(map),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            (mapped, items.len(), page.next_cursor)
+        }
+        _ => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": "Invalid itemtype. Valid types are 'albums', 'artists' or 'tracks'",
+            }));
+        }
+    };
+
+    let items: Vec<Value> = items
         .into_iter()
-        .map(|mut a| {
-            let mut map = to_artist_card_map(&mut a);
-            if let Some(help) = artist_help_text(sort, &a) {
-                map.insert("help_text".to_string(), Value::String(help));
-            }
-            Value::Object(map)
+        .map(|map| match &fields {
+            Some(fields) => Value::Object(project(map, fields)),
+            None => Value::Object(map),
         })
-        .collect::<Vec<_>>();
+        .collect();
 
     HttpResponse::Ok().json(json!({
-        "items": mapped,
+        "items": items,
         "total": total,
+        "next_cursor": next_cursor,
     }))
 }
 
-fn sort_albums(items: &mut [crate::models::Album], sort: &str, reverse: bool) {
-    items.sort_by(|a, b| {
-        let ord = match sort {
-            "duration" => a.duration.cmp(&b.duration),
-            "created_date" => a.created_date.cmp(&b.created_date),
-            "playcount" => a.playcount.cmp(&b.playcount),
-            "playduration" => a.playduration.cmp(&b.playduration),
-            "lastplayed" => a.lastplayed.cmp(&b.lastplayed),
-            "trackcount" => a.trackcount.cmp(&b.trackcount),
-            "date" => a.date.cmp(&b.date),
-            "albumartists" => a
-                .albumartists
-                .get(0)
-                .and_then(|ar| Some(ar.name.to_lowercase()))
-                .cmp(
-                    &b.albumartists
-                        .get(0)
-                        .and_then(|ar| Some(ar.name.to_lowercase())),
-                ),
-            "title" => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
-            _ => a.created_date.cmp(&b.created_date),
-        };
-        if reverse {
-            ord.reverse()
-        } else {
-            ord
-        }
+/// Every album in the order of the request, only the albums the user's own
+/// stats change are copied and only when the order depends on them
+fn sorted_albums(
+    user_id: i64,
+    sorts: &CompoundSort<AlbumSort>,
+    order: SortOrder,
+) -> Vec<Arc<Album>> {
+    let mut albums = AlbumStore::get().get_all_shared();
+    let personal = sorts.keys().iter().any(|k| {
+        matches!(
+            k.by,
+            AlbumSort::LastPlayed | AlbumSort::PlayCount | AlbumSort::PlayDuration
+        )
     });
+    if personal {
+        PlayStatsStore::get().personalize_shared_albums(user_id, &mut albums);
+    }
+    SortLib::sort_albums_by(&mut albums, sorts, order);
+    albums
 }
 
-fn sort_artists(items: &mut [crate::models::Artist], sort: &str, reverse: bool) {
-    items.sort_by(|a, b| {
-        let ord = match sort {
-            "duration" => a.duration.cmp(&b.duration),
-            "created_date" => a.created_date.cmp(&b.created_date),
-            "playcount" => a.playcount.cmp(&b.playcount),
-            "playduration" => a.playduration.cmp(&b.playduration),
-            "lastplayed" => a.lastplayed.cmp(&b.lastplayed),
-            "trackcount" => a.trackcount.cmp(&b.trackcount),
-            "albumcount" => a.albumcount.cmp(&b.albumcount),
-            "name" => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
-            _ => a.created_date.cmp(&b.created_date),
-        };
-        if reverse {
-            ord.reverse()
-        } else {
-            ord
+/// Every artist in the order of the request, copied like [`sorted_albums`]
+fn sorted_artists(
+    user_id: i64,
+    sorts: &CompoundSort<ArtistSort>,
+    order: SortOrder,
+) -> Vec<Arc<Artist>> {
+    let mut artists = ArtistStore::get().get_all_shared();
+    let personal = sorts.keys().iter().any(|k| {
+        matches!(
+            k.by,
+            ArtistSort::LastPlayed | ArtistSort::PlayCount | ArtistSort::PlayDuration
+        )
+    });
+    if personal {
+        PlayStatsStore::get().personalize_shared_artists(user_id, &mut artists);
+    }
+    SortLib::sort_artists_by(&mut artists, sorts, order);
+    artists
+}
+
+/// Every track in the order of the request, copied like [`sorted_albums`]
+fn sorted_tracks(
+    user_id: i64,
+    sorts: &CompoundSort<TrackSort>,
+    order: SortOrder,
+) -> Vec<Arc<Track>> {
+    let mut tracks = TrackStore::get().get_all_shared();
+
+    let keys = sorts.keys();
+    let personal = keys.iter().any(|k| {
+        matches!(
+            k.by,
+            TrackSort::LastPlayed
+                | TrackSort::PlayCount
+                | TrackSort::PlayDuration
+                | TrackSort::Rating
+        )
+    });
+    if personal {
+        PlayStatsStore::get().personalize_shared_tracks(user_id, &mut tracks);
+    }
+
+    // the store has no order of its own, pages need one that holds between requests
+    if keys.iter().all(|k| k.by == TrackSort::Default) {
+        let order = keys.first().and_then(|k| k.order).unwrap_or(order);
+        tracks.sort_by(|a, b| order.apply(a.filepath.cmp(&b.filepath)));
+    } else {
+        SortLib::sort_tracks_by(&mut tracks, sorts, order);
+    }
+    tracks
+}
+
+/// A page of a sorted list and the cursor of the page after it
+struct Page {
+    start: usize,
+    end: usize,
+    next_cursor: Option<String>,
+}
+
+impl Page {
+    fn of<T>(items: &[T], start: usize, limit: usize, hash: impl Fn(&T) -> &String) -> Self {
+        let start = start.min(items.len());
+        let end = start.saturating_add(limit).min(items.len());
+        let next_cursor =
+            (end < items.len() && end > start).then(|| encode_cursor(end, hash(&items[end - 1])));
+        Self {
+            start,
+            end,
+            next_cursor,
         }
+    }
+
+    fn range(&self) -> std::ops::Range<usize> {
+        self.start..self.end
+    }
+}
+
+/// Cursors name the last item sent and where the next page started then, so
+/// pages don't shift when items before them come or go
+fn encode_cursor(offset: usize, hash: &str) -> String {
+    URL_SAFE_NO_PAD.encode(format!("{}:{}", offset, hash))
+}
+
+fn decode_cursor(cursor: &str) -> Option<(usize, String)> {
+    let decoded = String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()?;
+    let (offset, hash) = decoded.split_once(':')?;
+    Some((offset.parse().ok()?, hash.to_string()))
+}
+
+/// Index the page starts at, right after the cursor's item when it is still
+/// there and at the cursor's offset when it is gone
+fn page_start<T>(
+    items: &[T],
+    cursor: Option<&(usize, String)>,
+    start: usize,
+    hash: impl Fn(&T) -> &String,
+) -> usize {
+    match cursor {
+        Some((offset, last)) => items
+            .iter()
+            .position(|item| hash(item) == last)
+            .map_or(*offset, |i| i + 1),
+        None => start,
+    }
+}
+
+/// Keep the requested fields of an item, with its type and hash so it can
+/// still be told apart
+fn project(mut map: Map<String, Value>, fields: &[&str]) -> Map<String, Value> {
+    map.retain(|key, _| {
+        fields.contains(&key.as_str())
+            || matches!(
+                key.as_str(),
+                "type" | "albumhash" | "artisthash" | "trackhash"
+            )
     });
+    map
 }
 
 pub fn to_album_card_map(album: &mut crate::models::Album) -> Map<String, Value> {
@@ -229,6 +379,7 @@ pub fn to_album_card_map(album: &mut crate::models::Album) -> Map<String, Value>
 
     value.insert("type".to_string(), Value::String("album".to_string()));
 
+    insert_image_hints(&mut value, CardImage::Thumbnail);
     value
 }
 
@@ -260,12 +411,13 @@ pub fn to_artist_card_map(artist: &mut crate::models::Artist) -> Map<String, Val
     }
 
     map.insert("type".to_string(), Value::String("artist".to_string()));
+    insert_image_hints(&mut map, CardImage::Artist);
     map
 }
 
-fn album_help_text(sort: &str, album: &crate::models::Album) -> Option<String> {
+fn album_help_text(sort: AlbumSort, album: &crate::models::Album) -> Option<String> {
     match sort {
-        "date" => {
+        AlbumSort::Date => {
             if album.date > 0 {
                 let year = Utc.timestamp_opt(album.date as i64, 0).single()?.year();
                 Some(year.to_string())
@@ -273,55 +425,55 @@ fn album_help_text(sort: &str, album: &crate::models::Album) -> Option<String> {
                 None
             }
         }
-        "created_date" => Some(timestamp_to_relative(album.created_date)),
-        "trackcount" => Some(format!(
+        AlbumSort::CreatedDate => Some(timestamp_to_relative(album.created_date)),
+        AlbumSort::TrackCount => Some(format!(
             "{} track{}",
             format_number(album.trackcount as i64),
             if album.trackcount == 1 { "" } else { "s" }
         )),
-        "duration" => Some(seconds_to_human_readable(album.duration as i64)),
-        "playcount" => Some(format!(
+        AlbumSort::Duration => Some(seconds_to_human_readable(album.duration as i64)),
+        AlbumSort::PlayCount => Some(format!(
             "{} play{}",
             format_number(album.playcount as i64),
             if album.playcount == 1 { "" } else { "s" }
         )),
-        "lastplayed" => {
+        AlbumSort::LastPlayed => {
             if album.playduration == 0 {
                 Some("Never played".to_string())
             } else {
                 Some(timestamp_to_relative(album.lastplayed))
             }
         }
-        "playduration" => Some(seconds_to_human_readable(album.playduration as i64)),
+        AlbumSort::PlayDuration => Some(seconds_to_human_readable(album.playduration as i64)),
         _ => None,
     }
 }
 
-fn artist_help_text(sort: &str, artist: &crate::models::Artist) -> Option<String> {
+fn artist_help_text(sort: ArtistSort, artist: &crate::models::Artist) -> Option<String> {
     match sort {
-        "trackcount" => Some(format!(
+        ArtistSort::TrackCount => Some(format!(
             "{} track{}",
             format_number(artist.trackcount as i64),
             if artist.trackcount == 1 { "" } else { "s" }
         )),
-        "albumcount" => Some(format!(
+        ArtistSort::AlbumCount => Some(format!(
             "{} album{}",
             format_number(artist.albumcount as i64),
             if artist.albumcount == 1 { "" } else { "s" }
         )),
-        "playcount" => Some(format!(
+        ArtistSort::PlayCount => Some(format!(
             "{} play{}",
             format_number(artist.playcount as i64),
             if artist.playcount == 1 { "" } else { "s" }
         )),
-        "lastplayed" => {
+        ArtistSort::LastPlayed => {
             if artist.playduration == 0 {
                 Some("Never played".to_string())
             } else {
                 Some(timestamp_to_relative(artist.lastplayed))
             }
         }
-        "playduration" => Some(seconds_to_human_readable(artist.playduration as i64)),
+        ArtistSort::PlayDuration => Some(seconds_to_human_readable(artist.playduration as i64)),
         _ => None,
     }
 }
@@ -341,7 +493,62 @@ fn format_number(n: i64) -> String {
     }
 }
 
+/// OpenAPI description of the getall routes
+#[derive(OpenApi)]
+#[openapi(paths(get_all_items,))]
+pub struct ApiDoc;
+
 /// Configure getall routes
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(get_all_items);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hashes(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_cursor_roundtrip() {
+        let cursor = encode_cursor(40, "abc123");
+        assert_eq!(decode_cursor(&cursor), Some((40, "abc123".to_string())));
+        assert_eq!(decode_cursor("not a cursor"), None);
+    }
+
+    #[test]
+    fn test_pages_follow_the_last_item() {
+        let items = hashes(&["a", "b", "c", "d", "e"]);
+        let page = Page::of(&items, 0, 2, |h| h);
+        assert_eq!(page.range(), 0..2);
+        let cursor = decode_cursor(page.next_cursor.as_deref().unwrap()).unwrap();
+
+        // an item before the cursor went away, the next page still starts after b
+        let items = hashes(&["a", "b", "d", "e"]);
+        let start = page_start(&items, Some(&cursor), 0, |h| h);
+        assert_eq!(&items[start], "d");
+
+        // the cursor's own item went away, the offset takes over
+        let items = hashes(&["a", "c", "d", "e"]);
+        assert_eq!(page_start(&items, Some(&cursor), 0, |h| h), 2);
+
+        let page = Page::of(&items, 2, 2, |h| h);
+        assert_eq!(page.range(), 2..4);
+        assert!(page.next_cursor.is_none());
+    }
+
+    #[test]
+    fn test_project_keeps_identity() {
+        let item = json!({"type": "track", "trackhash": "h", "title": "t", "bitrate": 320});
+        let Value::Object(map) = item else {
+            unreachable!()
+        };
+        let map = project(map, &["title"]);
+        assert_eq!(
+            Value::Object(map),
+            json!({"type": "track", "trackhash": "h", "title": "t"})
+        );
+    }
+}
@@ -1,17 +1,18 @@
 //! Home API routes - homepage sections
 
-use crate::config::UserConfig;
-use crate::core::recipes::{ArtistStats, Recipes, RecentlyPlayedItem};
-use crate::db::tables::{MixTable, ScrobbleTable};
+use crate::api::identity::CurrentUser;
+use crate::api::imgserver::{insert_image_hints, CardImage};
+use crate::api::playlist::LIKED_PLAYLIST;
+use crate::core::popularity::{self, PopularityKind};
+use crate::core::recipes::{AlbumCompletion, ArtistStats, Recipes, RecentlyPlayedItem};
+use crate::db::tables::{DeviceFilter, FavoriteTable, MixTable, PageTable, ScrobbleTable};
 use crate::models::Mix;
 use crate::stores::{AlbumStore, ArtistStore, TrackStore};
-use crate::utils::auth::verify_jwt;
-use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use actix_web::{get, put, web, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi, ToSchema};
 use serde_json::{json, Value};
 
-const DEFAULT_USER_ID: i64 = 1;
-
 /// Homepage section response
 #[derive(Debug, Serialize)]
 pub struct HomeSectionResponse {
@@ -22,15 +23,76 @@ pub struct HomeSectionResponse {
     pub order_index: i32,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct LimitQuery {
     pub limit: Option<usize>,
 }
 
+/// Recently played query, `device` is a comma separated list of devices to
+/// keep and `-device` entries to leave out
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct RecentlyPlayedQuery {
+    pub limit: Option<usize>,
+    pub device: Option<String>,
+}
+
+/// Sections the homepage can show and their titles, in the default order
+pub const HOME_SECTIONS: &[(&str, &str)] = &[
+    ("recently_played", "Recently played"),
+    ("unfinished_albums", "Albums you haven't finished"),
+    ("artist_mixes", "Artist mixes for you"),
+    ("custom_mixes", "Mixes for you"),
+    ("daily_mixes", "Your Daily Mixes"),
+    ("top_streamed_weekly_artists", "Top artists this week"),
+    ("top_streamed_monthly_artists", "Top artists this month"),
+    ("trending_tracks", "Trending in your library"),
+    ("trending_artists", "Trending artists"),
+    ("trending_albums", "Trending albums"),
+    ("because_you_listened_to_artist", "Because you listened to"),
+    ("artists_you_might_like", "Artists you might like"),
+    ("genre_hub", "Genre collections"),
+    ("decade_hub", "Decade collections"),
+    ("label_hub", "Record label collections"),
+    ("recently_added", "Recently added"),
+];
+
+/// page_type of the rows holding homepage layouts
+const LAYOUT_PAGE_TYPE: &str = "home_section";
+
+/// Position and visibility of one homepage section
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct HomeLayoutSection {
+    pub id: String,
+    #[serde(default = "default_visible")]
+    pub visible: bool,
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct HomeLayoutBody {
+    pub sections: Vec<HomeLayoutSection>,
+}
+
+/// OpenAPI description of the home routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    get_recently_added_items,
+    get_recently_played_items,
+    get_home_layout,
+    update_home_layout,
+    nothome_homepage,
+))]
+pub struct ApiDoc;
+
 /// Configure home routes
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(get_recently_added_items)
         .service(get_recently_played_items)
+        .service(get_home_layout)
+        .service(update_home_layout)
         .service(nothome_homepage);
 }
 
@@ -42,16 +104,82 @@ pub fn configure_upstream(cfg: &mut web::ServiceConfig) {
 }
 
 /// GET / (under /nothome) — return homepage items matching upstream format
+#[utoipa::path(
+    params(LimitQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/")]
-async fn nothome_homepage(req: HttpRequest, query: web::Query<LimitQuery>) -> impl Responder {
+async fn nothome_homepage(user: CurrentUser, query: web::Query<LimitQuery>) -> impl Responder {
     let limit = query.limit.unwrap_or(9);
-    let user_id = resolve_user_id(&req).await.unwrap_or(DEFAULT_USER_ID);
+    let user_id = user.id;
     let payload = build_upstream_homepage_items(limit, user_id).await;
+    let layout = load_layout(user_id).await;
+
+    HttpResponse::Ok().json(apply_layout(payload, &layout))
+}
+
+/// GET /layout - the user's homepage section order and visibility
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/layout")]
+async fn get_home_layout(user: CurrentUser) -> impl Responder {
+    HttpResponse::Ok().json(layout_response(&load_layout(user.id).await))
+}
+
+/// PUT /layout - save the user's homepage section order and visibility
+///
+/// sections left out of the body keep their default place and stay visible
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[put("/layout")]
+async fn update_home_layout(user: CurrentUser, body: web::Json<HomeLayoutBody>) -> impl Responder {
+    let body = body.into_inner();
+    if let Some(unknown) = body.sections.iter().find(|s| section_title(&s.id).is_none()) {
+        return HttpResponse::BadRequest()
+            .json(json!({ "error": format!("Unknown homepage section: {}", unknown.id) }));
+    }
+
+    let layout = merge_layout(body.sections);
+    for (index, section) in layout.iter().enumerate() {
+        if let Err(e) = PageTable::upsert(
+            LAYOUT_PAGE_TYPE,
+            &section.id,
+            &layout_page_id(user.id, &section.id),
+            index as i32,
+            "{}",
+            section.visible,
+        )
+        .await
+        {
+            return HttpResponse::InternalServerError()
+                .json(json!({ "error": format!("Failed to save homepage layout: {}", e) }));
+        }
+    }
 
-    HttpResponse::Ok().json(payload)
+    HttpResponse::Ok().json(layout_response(&layout))
 }
 
 /// GET /recents/added (under /nothome)
+#[utoipa::path(
+    params(LimitQuery),
+    responses((status = 200, description = "Success"))
+)]
 #[get("/recents/added")]
 async fn get_recently_added_items(query: web::Query<LimitQuery>) -> impl Responder {
     let limit = query.limit.unwrap_or(9) as usize;
@@ -60,36 +188,25 @@ async fn get_recently_added_items(query: web::Query<LimitQuery>) -> impl Respond
 }
 
 /// GET /recents/played (under /nothome)
+#[utoipa::path(
+    params(RecentlyPlayedQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/recents/played")]
-async fn get_recently_played_items(req: HttpRequest, query: web::Query<LimitQuery>) -> impl Responder {
+async fn get_recently_played_items(
+    user: CurrentUser,
+    query: web::Query<RecentlyPlayedQuery>,
+) -> impl Responder {
     let limit = query.limit.unwrap_or(9) as usize;
-    let user_id = resolve_user_id(&req).await.unwrap_or(DEFAULT_USER_ID);
-    let items = build_recently_played(limit, user_id).await;
+    let devices = DeviceFilter::parse(query.device.as_deref());
+    let items = build_recently_played(limit, user.id, &devices).await;
     HttpResponse::Ok().json(json!({ "items": items }))
 }
 
-// resolve user id from jwt token
-async fn resolve_user_id(req: &HttpRequest) -> Option<i64> {
-    let header = req.headers().get("Authorization")?;
-    let header_str = header.to_str().ok()?.trim();
-    if header_str.is_empty() {
-        return None;
-    }
-
-    let token = if let Some(rest) = header_str.strip_prefix("Bearer ") {
-        rest
-    } else {
-        header_str
-    };
-    if token.is_empty() {
-        return None;
-    }
-
-    let config = UserConfig::load().ok()?;
-    let claims = verify_jwt(token, &config.server_id, Some("access")).ok()?;
-    Some(claims.sub.id)
-}
-
 // build the upstream-compatible homepage payload with all sections
 async fn build_upstream_homepage_items(limit: usize, user_id: i64) -> Vec<Value> {
     let mut sections: Vec<Value> = Vec::new();
@@ -100,7 +217,7 @@ async fn build_upstream_homepage_items(limit: usize, user_id: i64) -> Vec<Value>
     // 1. recently played section (tracks, albums, artists, mixes, folders, playlists, favorites)
     let recently_played = Recipes::recently_played_items(limit, user_id).await;
     if !recently_played.is_empty() {
-        let items = recover_recently_played_items(&recently_played);
+        let items = recover_recently_played_items(&recently_played, user_id).await;
         if !items.is_empty() {
             sections.push(json!({
                 "recently_played": {
@@ -112,7 +229,27 @@ async fn build_upstream_homepage_items(limit: usize, user_id: i64) -> Vec<Value>
         }
     }
 
-    // 2. artist mixes for you
+    // 2. albums the user started but never finished
+    let unfinished: Vec<Value> = Recipes::unfinished_albums(limit, user_id)
+        .iter()
+        .map(|completion| {
+            json!({
+                "type": "album",
+                "item": serialize_unfinished_album(completion, user_id),
+            })
+        })
+        .collect();
+    if !unfinished.is_empty() {
+        sections.push(json!({
+            "unfinished_albums": {
+                "title": "Albums you haven't finished",
+                "description": "Pick up where you left off",
+                "items": unfinished,
+            }
+        }));
+    }
+
+    // 3. artist mixes for you
     let artist_mixes = Recipes::generate_artist_mixes(limit, user_id).await;
     if !artist_mixes.is_empty() {
         let items: Vec<Value> = artist_mixes
@@ -134,7 +271,7 @@ async fn build_upstream_homepage_items(limit: usize, user_id: i64) -> Vec<Value>
         }));
     }
 
-    // 3. custom mixes (track-based mixes from database)
+    // 4. custom mixes (track-based mixes from database)
     if let Ok(db_mixes) = MixTable::all(0).await {
         // filter to track-type mixes (those starting with 't')
         let track_mixes: Vec<&Mix> = db_mixes
@@ -164,8 +301,8 @@ async fn build_upstream_homepage_items(limit: usize, user_id: i64) -> Vec<Value>
         }
     }
 
-    // 4. daily mixes (spotify-style personalized playlists)
-    let daily_mixes = Recipes::generate_daily_mixes(6, user_id).await;
+    // 5. daily mixes (spotify-style personalized playlists)
+    let daily_mixes = Recipes::daily_mixes(user_id).await.unwrap_or_default();
     if !daily_mixes.is_empty() {
         let items: Vec<Value> = daily_mixes
             .into_iter()
@@ -186,7 +323,7 @@ async fn build_upstream_homepage_items(limit: usize, user_id: i64) -> Vec<Value>
         }));
     }
 
-    // 5. top artists this week
+    // 6. top artists this week
     let weekly_artists = Recipes::top_artists_weekly(limit, user_id).await;
     if !weekly_artists.is_empty() {
         let items: Vec<Value> = weekly_artists
@@ -211,7 +348,7 @@ async fn build_upstream_homepage_items(limit: usize, user_id: i64) -> Vec<Value>
         }
     }
 
-    // 6. top artists this month
+    // 7. top artists this month
     let monthly_artists = Recipes::top_artists_monthly(limit, user_id).await;
     if !monthly_artists.is_empty() {
         let items: Vec<Value> = monthly_artists
@@ -236,19 +373,29 @@ async fn build_upstream_homepage_items(limit: usize, user_id: i64) -> Vec<Value>
         }
     }
 
-    // 7. because you listened to (based on top artist)
+    // 8. trending tracks, artists and albums by decaying popularity
+    for kind in [PopularityKind::Track, PopularityKind::Artist, PopularityKind::Album] {
+        if let Some(section) = build_trending_section(kind, limit, user_id).await {
+            sections.push(section);
+        }
+    }
+
+    // 9. because you listened to (based on top artist)
     if let Some(first_artist) = Recipes::top_artists_in_period(7, 1, user_id).await.first() {
         if let Some(similar_section) = build_because_you_listened_section(&first_artist.artisthash, limit).await {
             sections.push(similar_section);
         }
     }
 
-    // 8. artists you might like
+    // 10. artists you might like
     if let Some(artists_section) = build_artists_you_might_like(limit, user_id).await {
         sections.push(artists_section);
     }
 
-    // 9. recently added albums (always last)
+    // 11. genre, decade and record label hubs
+    sections.extend(crate::api::collections::homepage_hub_sections(limit).await);
+
+    // 12. recently added albums (last by default)
     let mut albums = album_store.get_all();
     albums.sort_by(|a, b| b.created_date.cmp(&a.created_date));
     let recently_added_albums: Vec<Value> = albums
@@ -274,7 +421,7 @@ async fn build_upstream_homepage_items(limit: usize, user_id: i64) -> Vec<Value>
 }
 
 // recover recently played items to full objects
-fn recover_recently_played_items(items: &[RecentlyPlayedItem]) -> Vec<Value> {
+async fn recover_recently_played_items(items: &[RecentlyPlayedItem], user_id: i64) -> Vec<Value> {
     let track_store = TrackStore::get();
     let album_store = AlbumStore::get();
     let artist_store = ArtistStore::get();
@@ -287,6 +434,7 @@ fn recover_recently_played_items(items: &[RecentlyPlayedItem]) -> Vec<Value> {
                 if let Some(track) = track_store.get_by_hash(&item.hash) {
                     let mut track_json = serde_json::to_value(&track).unwrap_or_default();
                     add_help_text(&mut track_json, &item.item_type, item.timestamp);
+                    set_favorite_flag(&mut track_json, track.is_favorite(user_id));
                     Some(json!({
                         "type": "track",
                         "item": track_json,
@@ -299,6 +447,7 @@ fn recover_recently_played_items(items: &[RecentlyPlayedItem]) -> Vec<Value> {
                 if let Some(album) = album_store.get_by_hash(&item.hash) {
                     let mut album_json = serde_json::to_value(&album).unwrap_or_default();
                     add_help_text(&mut album_json, &item.item_type, item.timestamp);
+                    set_favorite_flag(&mut album_json, album.is_favorite(user_id));
                     Some(json!({
                         "type": "album",
                         "item": album_json,
@@ -311,6 +460,7 @@ fn recover_recently_played_items(items: &[RecentlyPlayedItem]) -> Vec<Value> {
                 if let Some(artist) = artist_store.get_by_hash(&item.hash) {
                     let mut artist_json = serde_json::to_value(&artist).unwrap_or_default();
                     add_help_text(&mut artist_json, &item.item_type, item.timestamp);
+                    set_favorite_flag(&mut artist_json, artist.is_favorite(user_id));
                     Some(json!({
                         "type": "artist",
                         "item": artist_json,
@@ -332,8 +482,11 @@ fn recover_recently_played_items(items: &[RecentlyPlayedItem]) -> Vec<Value> {
                 }))
             }
             "playlist" => {
-                // for custom playlists like recentlyadded/recentlyplayed
-                let is_custom = item.hash == "recentlyadded" || item.hash == "recentlyplayed";
+                // for custom playlists like recentlyadded/recentlyplayed/liked
+                let is_custom = matches!(
+                    item.hash.as_str(),
+                    "recentlyadded" | "recentlyplayed" | LIKED_PLAYLIST
+                );
                 Some(json!({
                     "type": "playlist",
                     "item": {
@@ -355,8 +508,8 @@ fn recover_recently_played_items(items: &[RecentlyPlayedItem]) -> Vec<Value> {
                 }))
             }
             "favorite" => {
-                let count = count_favorites();
-                let image = get_favorite_image();
+                let count = FavoriteTable::count_tracks(user_id).await.unwrap_or(0);
+                let image = get_favorite_image(user_id).await;
                 Some(json!({
                     "type": "favorite",
                     "item": {
@@ -385,6 +538,14 @@ fn add_help_text(json: &mut Value, help_text: &str, timestamp: i64) {
     }
 }
 
+// replace the favorite user list with the current user's flag
+fn set_favorite_flag(json: &mut Value, is_favorite: bool) {
+    if let Some(obj) = json.as_object_mut() {
+        obj.remove("fav_userids");
+        obj.insert("is_favorite".to_string(), json!(is_favorite));
+    }
+}
+
 fn timestamp_to_time_passed(timestamp: i64) -> String {
     let now = chrono::Utc::now().timestamp();
     let diff = now - timestamp;
@@ -441,50 +602,76 @@ fn serialize_mix_for_homepage(mix: &Mix) -> Value {
         None
     };
 
+    let (color, variants) = Recipes::mix_colors(mix);
+
     json!({
         "id": mix.mixid,
         "title": mix.title,
         "description": mix.description,
         "trackcount": mix.trackhashes.len(),
         "image": image,
+        "color": color,
+        "color_dark": variants.dark,
+        "color_light": variants.light,
         "saved": mix.saved,
     })
 }
 
+/// Album card with how much of the album the user played
+fn serialize_unfinished_album(completion: &AlbumCompletion, user_id: i64) -> Value {
+    let help_text = format!(
+        "{} of {} tracks played",
+        completion.played, completion.total
+    );
+    let mut value = serde_json::to_value(&completion.album).unwrap_or_default();
+    set_favorite_flag(&mut value, completion.album.is_favorite(user_id));
+    if let Some(map) = value.as_object_mut() {
+        map.insert("completion".to_string(), json!(completion.percent()));
+        map.insert("played_tracks".to_string(), json!(completion.played));
+        map.insert("help_text".to_string(), json!(help_text));
+    }
+    value
+}
+
 fn serialize_artist_for_homepage(artist: &crate::models::Artist, stats: &ArtistStats) -> Value {
-    json!({
+    let mut value = json!({
         "artisthash": artist.artisthash,
         "name": artist.name,
         "image": artist.image,
+        "color": artist.color,
         "trackcount": artist.trackcount,
         "albumcount": artist.albumcount,
         "play_count": stats.play_count,
         "help_text": format!("{} plays", stats.play_count),
-    })
+    });
+    if let Some(map) = value.as_object_mut() {
+        insert_image_hints(map, CardImage::Artist);
+    }
+    value
 }
 
 fn count_tracks_in_folder(path: &str) -> usize {
     TrackStore::get()
-        .get_all()
+        .get_all_shared()
         .iter()
         .filter(|t| t.filepath.starts_with(path))
         .count()
 }
 
-fn count_favorites() -> i64 {
-    // count favorite tracks - would need FavoritesTable but return 0 for now
-    0
-}
-
-fn get_favorite_image() -> Option<String> {
-    None
+// image of the user's most recently favorited track
+async fn get_favorite_image(user_id: i64) -> Option<String> {
+    let trackhash = FavoriteTable::get_recent_track_hash(user_id).await.ok()??;
+    TrackStore::get()
+        .get_by_hash(&trackhash)
+        .map(|t| t.image)
+        .filter(|image| !image.is_empty())
 }
 
 async fn build_because_you_listened_section(artisthash: &str, limit: usize) -> Option<Value> {
     let artist = ArtistStore::get().get_by_hash(artisthash)?;
 
     // get tracks by this artist to find genres
-    let artist_tracks = TrackStore::get().get_by_artist(artisthash);
+    let artist_tracks = TrackStore::get().get_shared_by_artist(artisthash);
     if artist_tracks.is_empty() {
         return None;
     }
@@ -533,6 +720,62 @@ async fn build_because_you_listened_section(artisthash: &str, limit: usize) -> O
     }))
 }
 
+async fn build_trending_section(kind: PopularityKind, limit: usize, user_id: i64) -> Option<Value> {
+    let scores = popularity::trending(user_id, kind, limit).await.ok()?;
+
+    let items: Vec<Value> = scores
+        .iter()
+        .filter_map(|score| {
+            let (mut item, favorite) = match kind {
+                PopularityKind::Track => {
+                    let track = TrackStore::get().get_by_hash(&score.hash)?;
+                    (serde_json::to_value(&track).ok()?, track.is_favorite(user_id))
+                }
+                PopularityKind::Artist => {
+                    let artist = ArtistStore::get().get_by_hash(&score.hash)?;
+                    (serde_json::to_value(&artist).ok()?, artist.is_favorite(user_id))
+                }
+                PopularityKind::Album => {
+                    let album = AlbumStore::get().get_by_hash(&score.hash)?;
+                    (serde_json::to_value(&album).ok()?, album.is_favorite(user_id))
+                }
+            };
+            set_favorite_flag(&mut item, favorite);
+            Some(json!({ "type": kind, "item": item }))
+        })
+        .collect();
+
+    if items.is_empty() {
+        return None;
+    }
+
+    let (id, title, description) = match kind {
+        PopularityKind::Track => (
+            "trending_tracks",
+            "Trending in your library",
+            "Tracks you have been playing the most lately",
+        ),
+        PopularityKind::Artist => (
+            "trending_artists",
+            "Trending artists",
+            "Artists you have been playing the most lately",
+        ),
+        PopularityKind::Album => (
+            "trending_albums",
+            "Trending albums",
+            "Albums you have been playing the most lately",
+        ),
+    };
+
+    Some(json!({
+        id: {
+            "title": title,
+            "description": description,
+            "items": items,
+        }
+    }))
+}
+
 async fn build_artists_you_might_like(limit: usize, user_id: i64) -> Option<Value> {
     // get top artists to find genres they belong to
     let top_artists = Recipes::top_artists_in_period(30, 5, user_id).await;
@@ -546,7 +789,7 @@ async fn build_artists_you_might_like(limit: usize, user_id: i64) -> Option<Valu
 
     for stats in &top_artists {
         top_artist_hashes.insert(stats.artisthash.clone());
-        let tracks = TrackStore::get().get_by_artist(&stats.artisthash);
+        let tracks = TrackStore::get().get_shared_by_artist(&stats.artisthash);
         for track in tracks {
             for hash in &track.genrehashes {
                 genre_hashes.insert(hash.clone());
@@ -601,7 +844,7 @@ async fn build_artists_you_might_like(limit: usize, user_id: i64) -> Option<Valu
 }
 
 fn build_recently_added_items(limit: usize) -> Vec<Value> {
-    let mut tracks = TrackStore::get().get_all();
+    let mut tracks = TrackStore::get().get_all_shared();
     tracks.sort_by(|a, b| b.last_mod.cmp(&a.last_mod));
     tracks
         .into_iter()
@@ -617,11 +860,12 @@ fn build_recently_added_items(limit: usize) -> Vec<Value> {
         .collect()
 }
 
-async fn build_recently_played(limit: usize, user_id: i64) -> Vec<Value> {
+async fn build_recently_played(limit: usize, user_id: i64, devices: &DeviceFilter) -> Vec<Value> {
     let mut items = Vec::new();
     let mut seen = std::collections::HashSet::new();
 
-    if let Ok(entries) = ScrobbleTable::get_paginated(user_id, 0, (limit as i64 * 5).max(50)).await {
+    let scan = (limit as i64 * 5).max(50);
+    if let Ok(entries) = ScrobbleTable::get_paginated_on(user_id, devices, 0, scan).await {
         for entry in entries {
             if items.len() >= limit {
                 break;
@@ -636,9 +880,164 @@ async fn build_recently_played(limit: usize, user_id: i64) -> Vec<Value> {
                 "type": "track",
                 "hash": entry.trackhash,
                 "timestamp": entry.timestamp,
+                "device": entry.device,
             }));
         }
     }
 
     items
 }
+
+fn section_title(id: &str) -> Option<&'static str> {
+    HOME_SECTIONS
+        .iter()
+        .find(|(section, _)| *section == id)
+        .map(|(_, title)| *title)
+}
+
+fn layout_page_id(user_id: i64, section: &str) -> String {
+    format!("home:{}:{}", user_id, section)
+}
+
+/// The saved layout of a user, the default layout when nothing is saved
+async fn load_layout(user_id: i64) -> Vec<HomeLayoutSection> {
+    let rows = PageTable::get_by_type(LAYOUT_PAGE_TYPE, &layout_page_id(user_id, ""))
+        .await
+        .unwrap_or_default();
+
+    merge_layout(
+        rows.into_iter()
+            .map(|row| HomeLayoutSection {
+                id: row.page_name,
+                visible: row.active,
+            })
+            .collect(),
+    )
+}
+
+/// Complete a saved layout with every known section
+///
+/// unknown and repeated sections are dropped, and a section missing from the
+/// layout is placed after the section that precedes it by default
+fn merge_layout(saved: Vec<HomeLayoutSection>) -> Vec<HomeLayoutSection> {
+    let mut layout: Vec<HomeLayoutSection> = Vec::with_capacity(HOME_SECTIONS.len());
+    for section in saved {
+        if section_title(&section.id).is_some() && !layout.iter().any(|s| s.id == section.id) {
+            layout.push(section);
+        }
+    }
+
+    for (index, (id, _)) in HOME_SECTIONS.iter().enumerate() {
+        if layout.iter().any(|s| s.id == *id) {
+            continue;
+        }
+        let position = index
+            .checked_sub(1)
+            .and_then(|prev| layout.iter().position(|s| s.id == HOME_SECTIONS[prev].0))
+            .map(|p| p + 1)
+            .unwrap_or(0);
+        layout.insert(
+            position,
+            HomeLayoutSection {
+                id: id.to_string(),
+                visible: true,
+            },
+        );
+    }
+
+    layout
+}
+
+fn layout_response(layout: &[HomeLayoutSection]) -> Value {
+    let sections: Vec<Value> = layout
+        .iter()
+        .map(|section| {
+            json!({
+                "id": section.id,
+                "title": section_title(&section.id).unwrap_or_default(),
+                "visible": section.visible,
+            })
+        })
+        .collect();
+    json!({ "sections": sections })
+}
+
+/// Order homepage sections by the layout and drop the hidden ones
+fn apply_layout(sections: Vec<Value>, layout: &[HomeLayoutSection]) -> Vec<Value> {
+    let rank = |section: &Value| {
+        let id = section
+            .as_object()
+            .and_then(|o| o.keys().next())
+            .map(String::as_str)
+            .unwrap_or_default();
+        layout.iter().position(|s| s.id == id)
+    };
+
+    let mut ranked: Vec<(usize, Value)> = sections
+        .into_iter()
+        .filter_map(|section| match rank(&section) {
+            Some(index) if !layout[index].visible => None,
+            Some(index) => Some((index, section)),
+            None => Some((layout.len(), section)),
+        })
+        .collect();
+    ranked.sort_by_key(|(index, _)| *index);
+    ranked.into_iter().map(|(_, section)| section).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(id: &str, visible: bool) -> HomeLayoutSection {
+        HomeLayoutSection {
+            id: id.to_string(),
+            visible,
+        }
+    }
+
+    #[test]
+    fn test_merge_layout() {
+        let layout = merge_layout(vec![
+            section("recently_added", true),
+            section("daily_mixes", false),
+            section("nonsense", true),
+            section("recently_added", false),
+        ]);
+
+        assert_eq!(layout.len(), HOME_SECTIONS.len());
+        // missing sections follow the section before them by default
+        let ids: Vec<&str> = layout.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(
+            &ids[..6],
+            &[
+                "recently_played",
+                "unfinished_albums",
+                "artist_mixes",
+                "custom_mixes",
+                "recently_added",
+                "daily_mixes",
+            ]
+        );
+        assert!(layout[4].visible);
+        assert!(!layout[5].visible);
+    }
+
+    #[test]
+    fn test_apply_layout() {
+        let layout = merge_layout(vec![
+            section("recently_added", true),
+            section("recently_played", false),
+        ]);
+        let sections = vec![
+            json!({ "recently_played": {} }),
+            json!({ "genre_hub": {} }),
+            json!({ "recently_added": {} }),
+        ];
+
+        let ordered = apply_layout(sections, &layout);
+        assert_eq!(ordered.len(), 2);
+        assert!(ordered[0].get("recently_added").is_some());
+        assert!(ordered[1].get("genre_hub").is_some());
+    }
+}
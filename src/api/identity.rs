@@ -0,0 +1,375 @@
+//! Request identity resolution shared by all API routes
+//!
+//! handlers take a [`CurrentUser`] argument to scope favorites, play stats and
+//! playlists to the authenticated user. requests without a token are turned
+//! away unless `singleUserMode` is set, then they act as the default admin.
+//!
+//! routes that change the library or other users' data take an
+//! [`Authorized`] argument instead, which resolves the user and checks the
+//! role or permission its [`Access`] type names before the handler runs.
+
+use actix_web::dev::Payload;
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use futures::future::LocalBoxFuture;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+use crate::config::UserConfig;
+use crate::db::tables::UserTable;
+use crate::models::{Permission, User};
+use crate::utils::auth::verify_jwt;
+
+/// User id requests without an access token act as in single-user mode
+pub const DEFAULT_USER_ID: i64 = 1;
+
+/// The user a request is acting on behalf of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CurrentUser {
+    pub id: i64,
+}
+
+impl CurrentUser {
+    /// Resolve the current user, anonymous requests only get the default
+    /// user in single-user mode
+    pub async fn resolve(req: &HttpRequest) -> Result<Self, HttpResponse> {
+        let id = match optional_user(req).await? {
+            Some(user) => user.id,
+            None if single_user_mode() => DEFAULT_USER_ID,
+            None => {
+                return Err(HttpResponse::Unauthorized().json(json!({"msg": "Not authenticated"})))
+            }
+        };
+        Ok(Self { id })
+    }
+}
+
+/// Whether requests without a token act as the default user, off when the
+/// config can't be read
+fn single_user_mode() -> bool {
+    UserConfig::load().is_ok_and(|config| config.single_user_mode)
+}
+
+/// Error wrapper so identity failures can be returned from an extractor
+#[derive(Debug)]
+pub struct IdentityError(HttpResponse);
+
+impl fmt::Display for IdentityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "identity error: {}", self.0.status())
+    }
+}
+
+impl ResponseError for IdentityError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        self.0.status()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut builder = HttpResponse::build(self.0.status());
+        match self.0.status().as_u16() {
+            401 => builder.json(json!({"msg": "Invalid or missing token"})),
+            _ => builder.json(json!({"msg": "Failed to resolve user"})),
+        }
+    }
+}
+
+impl FromRequest for CurrentUser {
+    type Error = IdentityError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move { CurrentUser::resolve(&req).await.map_err(IdentityError) })
+    }
+}
+
+/// Read the access token from the cookie or the authorization header
+pub fn access_token(req: &HttpRequest) -> Result<Option<String>, HttpResponse> {
+    if let Some(cookie) = req.cookie("access_token_cookie") {
+        return Ok(Some(cookie.value().to_string()));
+    }
+
+    match req.headers().get("Authorization") {
+        Some(header_value) => {
+            let header_str = header_value.to_str().unwrap_or("").trim();
+            let token = header_str.strip_prefix("Bearer ").unwrap_or(header_str);
+
+            if token.is_empty() {
+                return Err(
+                    HttpResponse::Unauthorized().json(json!({ "error": "Invalid token format" }))
+                );
+            }
+
+            Ok(Some(token.to_string()))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Resolve the authenticated user, if the request carries an access token
+///
+/// an invalid token or a token for a deleted user is rejected rather than
+/// silently treated as anonymous
+pub async fn optional_user(req: &HttpRequest) -> Result<Option<User>, HttpResponse> {
+    let token = match access_token(req)? {
+        Some(t) => t,
+        None => return Ok(None),
+    };
+
+    let config = UserConfig::load()
+        .map_err(|_| HttpResponse::InternalServerError().json(json!({"error": "Config error"})))?;
+
+    let claims = verify_jwt(&token, &config.server_id, Some("access"))
+        .map_err(|_| HttpResponse::Unauthorized().json(json!({"msg": "Invalid token"})))?;
+
+    match UserTable::get_by_id(claims.sub.id).await {
+        Ok(Some(user)) => Ok(Some(user)),
+        Ok(None) => Err(HttpResponse::Unauthorized().json(json!({"msg": "Invalid token"}))),
+        Err(_) => Err(HttpResponse::InternalServerError().json(json!({"msg": "Database error"}))),
+    }
+}
+
+/// Resolve the authenticated user, rejecting anonymous requests
+pub async fn require_user(req: &HttpRequest) -> Result<User, HttpResponse> {
+    match optional_user(req).await? {
+        Some(user) => Ok(user),
+        None => Err(HttpResponse::Unauthorized().json(json!({"msg": "Not authenticated"}))),
+    }
+}
+
+/// What a route asks of the user making the request
+pub trait Access {
+    /// Permission the user needs, `None` for routes only admins may use
+    const PERMISSION: Option<Permission>;
+}
+
+/// Routes only admins may use
+pub struct Admin;
+
+impl Access for Admin {
+    const PERMISSION: Option<Permission> = None;
+}
+
+/// Routes that write tags, names or artwork of library items
+pub struct EditTags;
+
+impl Access for EditTags {
+    const PERMISSION: Option<Permission> = Some(Permission::EditTags);
+}
+
+/// Routes that remove tracks or files from the library
+pub struct DeleteFiles;
+
+impl Access for DeleteFiles {
+    const PERMISSION: Option<Permission> = Some(Permission::DeleteFiles);
+}
+
+/// Routes that create, change or delete playlists
+pub struct ManagePlaylists;
+
+impl Access for ManagePlaylists {
+    const PERMISSION: Option<Permission> = Some(Permission::ManagePlaylists);
+}
+
+/// Routes that hand out original files
+pub struct Download;
+
+impl Access for Download {
+    const PERMISSION: Option<Permission> = Some(Permission::Download);
+}
+
+/// Why a request was refused by an [`Authorized`] extractor
+#[derive(Debug)]
+pub struct AccessDenied {
+    status: StatusCode,
+    msg: String,
+}
+
+impl AccessDenied {
+    fn new(status: StatusCode, msg: impl Into<String>) -> Self {
+        Self {
+            status,
+            msg: msg.into(),
+        }
+    }
+}
+
+impl fmt::Display for AccessDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "access denied: {}", self.msg)
+    }
+}
+
+impl ResponseError for AccessDenied {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(json!({ "msg": self.msg }))
+    }
+}
+
+/// A user allowed to use a route, see [`Access`]
+pub struct Authorized<A: Access> {
+    pub user: User,
+    access: PhantomData<A>,
+}
+
+impl<A: Access> Authorized<A> {
+    /// Resolve the user and check they may use the route
+    pub async fn resolve(req: &HttpRequest) -> Result<Self, AccessDenied> {
+        let user = match optional_user(req).await {
+            Ok(Some(user)) => user,
+            Ok(None) => {
+                anonymous_user(single_user_mode(), UserTable::get_by_id(DEFAULT_USER_ID)).await?
+            }
+            Err(resp) if resp.status() == StatusCode::UNAUTHORIZED => {
+                return Err(AccessDenied::new(StatusCode::UNAUTHORIZED, "Invalid token"))
+            }
+            Err(resp) => return Err(AccessDenied::new(resp.status(), "Failed to resolve user")),
+        };
+        Self::check(user)
+    }
+
+    /// Let the user through when they may use the route
+    fn check(user: User) -> Result<Self, AccessDenied> {
+        let allowed = match A::PERMISSION {
+            Some(permission) => user.can(permission),
+            None => user.is_admin(),
+        };
+        if !allowed {
+            let msg = match A::PERMISSION {
+                Some(permission) => format!("You need the {} permission", permission.as_str()),
+                None => "Only admins can do that!".to_string(),
+            };
+            return Err(AccessDenied::new(StatusCode::FORBIDDEN, msg));
+        }
+
+        Ok(Self {
+            user,
+            access: PhantomData,
+        })
+    }
+}
+
+/// User an anonymous request acts as, the default user in single-user mode
+/// like [`CurrentUser`] and nobody otherwise
+async fn anonymous_user(
+    single_user: bool,
+    default_user: impl std::future::Future<Output = anyhow::Result<Option<User>>>,
+) -> Result<User, AccessDenied> {
+    let unauthenticated = || AccessDenied::new(StatusCode::UNAUTHORIZED, "Not authenticated");
+    if !single_user {
+        return Err(unauthenticated());
+    }
+    match default_user.await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(unauthenticated()),
+        Err(_) => Err(AccessDenied::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to resolve user",
+        )),
+    }
+}
+
+impl<A: Access> Deref for Authorized<A> {
+    type Target = User;
+
+    fn deref(&self) -> &User {
+        &self.user
+    }
+}
+
+impl<A: Access + 'static> FromRequest for Authorized<A> {
+    type Error = AccessDenied;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move { Self::resolve(&req).await })
+    }
+}
+
+/// Longest device identifier kept on a play
+pub const MAX_DEVICE_LEN: usize = 64;
+
+/// Clean up a client supplied device identifier, control characters are
+/// dropped and long names cut short
+pub fn clean_device(device: &str) -> String {
+    device
+        .trim()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_DEVICE_LEN)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// The device a request comes from, read from the `X-Device-Id` header or a
+/// `device` query parameter for players that cannot set headers
+pub fn request_device(req: &HttpRequest) -> String {
+    if let Some(device) = req
+        .headers()
+        .get("X-Device-Id")
+        .and_then(|v| v.to_str().ok())
+    {
+        return clean_device(device);
+    }
+
+    web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .ok()
+        .and_then(|query| query.get("device").map(|d| clean_device(d)))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[actix_web::test]
+    async fn test_anonymous_requests_are_refused() {
+        let req = TestRequest::default().to_http_request();
+        let resp = CurrentUser::resolve(&req).await.unwrap_err();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn test_anonymous_requests_get_no_permissions() {
+        let req = TestRequest::default().to_http_request();
+        let status = |denied: AccessDenied| denied.status_code();
+
+        let denied = Authorized::<ManagePlaylists>::resolve(&req).await.err();
+        assert_eq!(denied.map(status), Some(StatusCode::UNAUTHORIZED));
+        let denied = Authorized::<Download>::resolve(&req).await.err();
+        assert_eq!(denied.map(status), Some(StatusCode::UNAUTHORIZED));
+        let denied = Authorized::<Admin>::resolve(&req).await.err();
+        assert_eq!(denied.map(status), Some(StatusCode::UNAUTHORIZED));
+    }
+
+    #[actix_web::test]
+    async fn test_single_user_mode_acts_as_the_default_user() {
+        let admin = || async { Ok(Some(User::admin("admin".into(), String::new()))) };
+        let status = |denied: AccessDenied| denied.status_code();
+
+        let user = anonymous_user(true, admin()).await.unwrap();
+        assert!(Authorized::<ManagePlaylists>::check(user.clone()).is_ok());
+        assert!(Authorized::<Download>::check(user.clone()).is_ok());
+        assert!(Authorized::<Admin>::check(user).is_ok());
+
+        let denied = anonymous_user(false, admin()).await.err();
+        assert_eq!(denied.map(status), Some(StatusCode::UNAUTHORIZED));
+        let denied = anonymous_user(true, async { Ok(None) }).await.err();
+        assert_eq!(denied.map(status), Some(StatusCode::UNAUTHORIZED));
+
+        // a default user without the permission is still refused
+        let denied = Authorized::<EditTags>::check(User::guest()).err();
+        assert_eq!(denied.map(status), Some(StatusCode::FORBIDDEN));
+    }
+}
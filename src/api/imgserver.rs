@@ -1,22 +1,42 @@
 //! Image server API routes
 
 use actix_files::NamedFile;
-use actix_web::{get, web, HttpResponse, Responder};
+use actix_web::http::header::{
+    HttpDate, LastModified, ACCEPT, CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, VARY,
+};
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use lru::LruCache;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
 use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use utoipa::{IntoParams, OpenApi};
+use xxhash_rust::xxh3::xxh3_128;
 
-use crate::config::Paths;
+use crate::config::{
+    CoverSource, Paths, ThumbnailSettings, LG_ARTIST_IMG_SIZE, MD_ARTIST_IMG_SIZE,
+    SM_ARTIST_IMG_SIZE,
+};
+use crate::core::images::{
+    album_thumbnail, find_folder_image, image_cache_max_age, thumbnail_path, thumbnail_settings,
+    ThumbnailFormat, ORIGINAL_ARTWORK_EXTENSIONS,
+};
 use crate::core::Tagger;
-use crate::stores::TrackStore;
+use crate::stores::{AlbumStore, TrackStore};
 
 /// Image query params
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct ImageQuery {
     pub w: Option<u32>, // Width
     pub h: Option<u32>, // Height
+    /// Longest side, takes priority over w and h
+    pub size: Option<u32>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct ThumbQuery {
     #[serde(default)]
     pub pathhash: String,
@@ -25,101 +45,368 @@ pub struct ThumbQuery {
 #[derive(Clone, Copy)]
 struct ThumbSpec {
     size_label: &'static str,
-    max_px: u32,
-}
-
-impl ThumbSpec {
-    fn path<'a>(&self, paths: &'a Paths, name: &str) -> PathBuf {
-        paths.thumbnails_dir(self.size_label).join(name)
-    }
 }
 
 const THUMB_LG: ThumbSpec = ThumbSpec {
     size_label: "large",
-    max_px: 512,
 };
 const THUMB_MD: ThumbSpec = ThumbSpec {
     size_label: "medium",
-    max_px: 256,
 };
 const THUMB_SM: ThumbSpec = ThumbSpec {
     size_label: "small",
-    max_px: 96,
 };
 const THUMB_XS: ThumbSpec = ThumbSpec {
     size_label: "xsmall",
-    max_px: 64,
 };
 
+/// Route a card image is served from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CardImage {
+    /// album art, used by album and track cards
+    Thumbnail,
+    Artist,
+}
+
+impl CardImage {
+    /// Label, url and width of the small, medium and large variants of an image
+    fn variants(self, image: &str, thumbs: &ThumbnailSettings) -> [(&'static str, String, u32); 3] {
+        match self {
+            CardImage::Thumbnail => [
+                (
+                    "small",
+                    format!("/img/thumbnail/small/{image}"),
+                    thumbs.small,
+                ),
+                (
+                    "medium",
+                    format!("/img/thumbnail/medium/{image}"),
+                    thumbs.medium,
+                ),
+                ("large", format!("/img/thumbnail/{image}"), thumbs.large),
+            ],
+            CardImage::Artist => [
+                (
+                    "small",
+                    format!("/img/artist/small/{image}"),
+                    SM_ARTIST_IMG_SIZE,
+                ),
+                (
+                    "medium",
+                    format!("/img/artist/medium/{image}"),
+                    MD_ARTIST_IMG_SIZE,
+                ),
+                ("large", format!("/img/artist/{image}"), LG_ARTIST_IMG_SIZE),
+            ],
+        }
+    }
+}
+
+/// Add the variant urls of a card's image and a srcset built from them
+///
+/// cards without a color, such as tracks, get the colors of their album
+pub(crate) fn insert_image_hints(map: &mut Map<String, Value>, kind: CardImage) {
+    let image = map.get("image").and_then(Value::as_str).unwrap_or("");
+    if !image.is_empty() {
+        let variants = kind.variants(image, &thumbnail_settings());
+        let srcset = variants
+            .iter()
+            .map(|(_, url, width)| format!("{url} {width}w"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let urls: Map<String, Value> = variants
+            .into_iter()
+            .map(|(label, url, _)| (label.to_string(), Value::String(url)))
+            .collect();
+
+        map.insert("image_variants".to_string(), Value::Object(urls));
+        map.insert("srcset".to_string(), Value::String(srcset));
+    }
+
+    let has_color = map
+        .get("color")
+        .and_then(Value::as_str)
+        .is_some_and(|c| !c.is_empty());
+    if kind != CardImage::Thumbnail || has_color {
+        return;
+    }
+
+    let album_color = map
+        .get("albumhash")
+        .and_then(Value::as_str)
+        .and_then(|hash| AlbumStore::get().get_color(hash))
+        .filter(|(color, _)| !color.is_empty());
+    if let Some((color, variants)) = album_color {
+        map.insert("color".to_string(), Value::String(color));
+        map.insert("color_dark".to_string(), Value::String(variants.dark));
+        map.insert("color_light".to_string(), Value::String(variants.light));
+    }
+}
+
 /// Get album image
+#[utoipa::path(
+    params(ImageQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 404, description = "Not found")
+    )
+)]
 #[get("/album/{hash}")]
 pub async fn get_album_image(
     path: web::Path<String>,
     query: web::Query<ImageQuery>,
+    req: HttpRequest,
 ) -> impl Responder {
     let hash = path.into_inner();
-    let paths = match Paths::get() {
-        Ok(p) => p,
-        Err(e) => {
-            return HttpResponse::InternalServerError().body(format!("Paths not initialized: {e}"))
+
+    match album_thumbnail(&hash, "", "large", ThumbnailFormat::WebP).await {
+        Some(image_path) => match query.resize() {
+            Some(resize) => {
+                serve_resized_image(&image_path, resize, CachePolicy::Immutable, &req).await
+            }
+            None => serve_image_file(&image_path, CachePolicy::Immutable, &req).await,
+        },
+        None => HttpResponse::NotFound().body("Album image not found"),
+    }
+}
+
+/// Largest embedded or folder artwork served by the original endpoint
+const MAX_ORIGINAL_ARTWORK_BYTES: usize = 32 * 1024 * 1024;
+
+/// Size budget for the on-disk original artwork cache
+const ORIGINAL_ARTWORK_CACHE_BYTES: u64 = 512 * 1024 * 1024;
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct OriginalImageQuery {
+    /// Serve as an attachment instead of inline
+    #[serde(default)]
+    pub download: bool,
+}
+
+enum OriginalArtwork {
+    Found(PathBuf),
+    Missing,
+    TooLarge(usize),
+}
+
+/// Get full resolution album artwork
+#[utoipa::path(
+    params(OriginalImageQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 404, description = "Not found"),
+        (status = 413, description = "Payload too large"),
+        (status = 500, description = "Server error")
+    )
+)]
+#[get("/album/{hash}/original")]
+pub async fn get_album_image_original(
+    path: web::Path<String>,
+    query: web::Query<OriginalImageQuery>,
+    req: actix_web::HttpRequest,
+) -> impl Responder {
+    let hash = path.into_inner();
+    if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return HttpResponse::BadRequest().body("Invalid album hash");
+    }
+
+    let artwork = match web::block(move || load_original_artwork(&hash)).await {
+        Ok(Ok(artwork)) => artwork,
+        Ok(Err(e)) => {
+            return HttpResponse::InternalServerError().body(format!("Failed to read artwork: {e}"))
         }
+        Err(_) => return HttpResponse::InternalServerError().body("Failed to read artwork"),
     };
 
-    // Try different extensions
-    for ext in &["webp", "jpg", "jpeg", "png"] {
-        let image_path = paths
-            .album_images("large")
-            .join(format!("{}.{}", hash, ext));
+    let file_path = match artwork {
+        OriginalArtwork::Found(p) => p,
+        OriginalArtwork::Missing => return HttpResponse::NotFound().body("Album image not found"),
+        OriginalArtwork::TooLarge(size) => {
+            return HttpResponse::PayloadTooLarge().body(format!(
+                "Artwork is {} bytes, the limit is {} bytes",
+                size, MAX_ORIGINAL_ARTWORK_BYTES
+            ))
+        }
+    };
 
-        if image_path.exists() {
-            if query.w.is_some() || query.h.is_some() {
-                // Resize image
-                return serve_resized_image(&image_path, query.w, query.h).await;
-            } else {
-                return match std::fs::read(&image_path) {
-                    Ok(bytes) => HttpResponse::Ok()
-                        .content_type(
-                            mime_guess::from_path(&image_path)
-                                .first_or_octet_stream()
-                                .essence_str(),
-                        )
-                        .body(bytes),
-                    Err(_) => HttpResponse::NotFound().body("Image not found"),
-                };
-            }
+    let Some(validators) = file_validators(&file_path) else {
+        return HttpResponse::NotFound().body("Album image not found");
+    };
+    if is_fresh(&req, &validators) {
+        return not_modified(&validators, CachePolicy::Immutable);
+    }
+
+    // our validators replace the ones derived from the file's inode and mtime
+    let file = match NamedFile::open(&file_path) {
+        Ok(f) => f.use_etag(false).use_last_modified(false),
+        Err(_) => return HttpResponse::NotFound().body("Album image not found"),
+    };
+
+    let disposition = if query.download {
+        actix_web::http::header::DispositionType::Attachment
+    } else {
+        actix_web::http::header::DispositionType::Inline
+    };
+    let filename = file_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut response = file
+        .set_content_disposition(actix_web::http::header::ContentDisposition {
+            disposition,
+            parameters: vec![actix_web::http::header::DispositionParam::Filename(
+                filename,
+            )],
+        })
+        .into_response(&req);
+    if let Ok(value) = actix_web::http::header::HeaderValue::from_str(&validators.etag) {
+        response.headers_mut().insert(ETAG, value);
+    }
+    if let Some(modified) = validators.modified {
+        if let Ok(value) =
+            actix_web::http::header::HeaderValue::from_str(&HttpDate::from(modified).to_string())
+        {
+            response
+                .headers_mut()
+                .insert(actix_web::http::header::LAST_MODIFIED, value);
+        }
+    }
+    if let Ok(value) =
+        actix_web::http::header::HeaderValue::from_str(&CachePolicy::Immutable.header())
+    {
+        response.headers_mut().insert(CACHE_CONTROL, value);
+    }
+    response
+}
+
+/// Locate the cached original artwork for an album, extracting it on a miss
+fn load_original_artwork(albumhash: &str) -> anyhow::Result<OriginalArtwork> {
+    let paths = Paths::get()?;
+    let cache_dir = paths.artwork_cache_dir();
+
+    for ext in ORIGINAL_ARTWORK_EXTENSIONS {
+        let cached = cache_dir.join(format!("{}.{}", albumhash, ext));
+        if cached.exists() {
+            return Ok(OriginalArtwork::Found(cached));
+        }
+    }
+
+    let tracks = TrackStore::get().get_by_album(albumhash);
+    let Some(first) = tracks.first() else {
+        return Ok(OriginalArtwork::Missing);
+    };
+
+    let embedded = || {
+        tracks
+            .iter()
+            .find_map(|t| Tagger::read_cover(Path::new(&t.filepath)).ok().flatten())
+    };
+    let folder =
+        || find_folder_image(Path::new(&first.filepath)).and_then(|p| std::fs::read(p).ok());
+    let data = match thumbnail_settings().cover_source {
+        CoverSource::Embedded => embedded().or_else(folder),
+        CoverSource::Folder => folder().or_else(embedded),
+    };
+
+    let Some(data) = data else {
+        return Ok(OriginalArtwork::Missing);
+    };
+
+    if data.len() > MAX_ORIGINAL_ARTWORK_BYTES {
+        return Ok(OriginalArtwork::TooLarge(data.len()));
+    }
+
+    let ext = match image::guess_format(&data) {
+        Ok(image::ImageFormat::Jpeg) => "jpg",
+        Ok(image::ImageFormat::Png) => "png",
+        Ok(image::ImageFormat::WebP) => "webp",
+        Ok(image::ImageFormat::Gif) => "gif",
+        Ok(image::ImageFormat::Bmp) => "bmp",
+        _ => return Ok(OriginalArtwork::Missing),
+    };
+
+    std::fs::create_dir_all(&cache_dir)?;
+    let dest = cache_dir.join(format!("{}.{}", albumhash, ext));
+    let tmp = dest.with_extension(format!("{}.tmp", ext));
+    std::fs::write(&tmp, &data)?;
+    std::fs::rename(&tmp, &dest)?;
+
+    if let Err(e) = prune_cache(&cache_dir, ORIGINAL_ARTWORK_CACHE_BYTES) {
+        tracing::warn!("Failed to prune artwork cache: {}", e);
+    }
+
+    Ok(OriginalArtwork::Found(dest))
+}
+
+/// Evict the least recently used files once a cache directory exceeds its budget
+fn prune_cache(dir: &Path, budget: u64) -> anyhow::Result<()> {
+    let mut entries: Vec<(PathBuf, u64, std::time::SystemTime)> = std::fs::read_dir(dir)?
+        .flatten()
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok()?;
+            let used = meta.accessed().or_else(|_| meta.modified()).ok()?;
+            Some((entry.path(), meta.len(), used))
+        })
+        .collect();
+
+    let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+    if total <= budget {
+        return Ok(());
+    }
+
+    entries.sort_by_key(|(_, _, used)| *used);
+    for (path, size, _) in entries {
+        if total <= budget {
+            break;
+        }
+        if std::fs::remove_file(&path).is_ok() {
+            total = total.saturating_sub(size);
         }
     }
 
-    HttpResponse::NotFound().body("Album image not found")
+    Ok(())
 }
 
 /// Get artist image (large)
+#[utoipa::path(
+    params(ImageQuery),
+    responses((status = 200, description = "Success"))
+)]
 #[get("/artist/{hash}")]
 pub async fn get_artist_image(
     path: web::Path<String>,
     query: web::Query<ImageQuery>,
+    req: HttpRequest,
 ) -> impl Responder {
-    serve_artist_image_size(&path.into_inner(), "large", query.w, query.h).await
+    serve_artist_image_size(&path.into_inner(), "large", query.resize(), &req).await
 }
 
 /// Get small artist image (96px)
+#[utoipa::path(
+    responses((status = 200, description = "Success"))
+)]
 #[get("/artist/small/{imgpath}")]
-pub async fn get_artist_image_small(path: web::Path<String>) -> impl Responder {
-    serve_artist_image_size(&path.into_inner(), "small", None, None).await
+pub async fn get_artist_image_small(path: web::Path<String>, req: HttpRequest) -> impl Responder {
+    serve_artist_image_size(&path.into_inner(), "small", None, &req).await
 }
 
 /// Get medium artist image (256px)
+#[utoipa::path(
+    responses((status = 200, description = "Success"))
+)]
 #[get("/artist/medium/{imgpath}")]
-pub async fn get_artist_image_medium(path: web::Path<String>) -> impl Responder {
-    serve_artist_image_size(&path.into_inner(), "medium", None, None).await
+pub async fn get_artist_image_medium(path: web::Path<String>, req: HttpRequest) -> impl Responder {
+    serve_artist_image_size(&path.into_inner(), "medium", None, &req).await
 }
 
 /// Helper to serve artist images from a specific size folder
 async fn serve_artist_image_size(
     imgpath: &str,
     size: &str,
-    width: Option<u32>,
-    height: Option<u32>,
+    resize: Option<Resize>,
+    req: &HttpRequest,
 ) -> HttpResponse {
     let paths = match Paths::get() {
         Ok(p) => p,
@@ -142,19 +429,10 @@ async fn serve_artist_image_size(
             .join(format!("{}.{}", hash, ext));
 
         if image_path.exists() {
-            if width.is_some() || height.is_some() {
-                return serve_resized_image(&image_path, width, height).await;
+            if let Some(resize) = resize {
+                return serve_resized_image(&image_path, resize, CachePolicy::Immutable, req).await;
             } else {
-                return match std::fs::read(&image_path) {
-                    Ok(bytes) => HttpResponse::Ok()
-                        .content_type(
-                            mime_guess::from_path(&image_path)
-                                .first_or_octet_stream()
-                                .essence_str(),
-                        )
-                        .body(bytes),
-                    Err(_) => HttpResponse::NotFound().body("Image not found"),
-                };
+                return serve_image_file(&image_path, CachePolicy::Immutable, req).await;
             }
         }
     }
@@ -164,10 +442,18 @@ async fn serve_artist_image_size(
 }
 
 /// Get track thumbnail (embedded art)
+#[utoipa::path(
+    params(ImageQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 404, description = "Not found")
+    )
+)]
 #[get("/track/{hash}")]
 pub async fn get_track_image(
     path: web::Path<String>,
     query: web::Query<ImageQuery>,
+    req: HttpRequest,
 ) -> impl Responder {
     let hash = path.into_inner();
 
@@ -191,51 +477,58 @@ pub async fn get_track_image(
                 "image/webp"
             };
 
-            if query.w.is_some() || query.h.is_some() {
-                // Resize
-                return serve_resized_bytes(&data, mime, query.w, query.h).await;
+            // embedded art changes whenever the file is retagged, so revalidate
+            let validators = Validators {
+                etag: content_etag(&data),
+                modified: std::fs::metadata(track_path)
+                    .and_then(|m| m.modified())
+                    .ok(),
+            };
+
+            if let Some(resize) = query.resize() {
+                let png = mime == "image/png";
+                return serve_resized(
+                    move || Some(data),
+                    png,
+                    resize,
+                    &validators,
+                    CachePolicy::Revalidate,
+                    &req,
+                )
+                .await;
             }
 
-            HttpResponse::Ok().content_type(mime).body(data)
+            if is_fresh(&req, &validators) {
+                return not_modified(&validators, CachePolicy::Revalidate);
+            }
+            image_response(mime, data, &validators, CachePolicy::Revalidate)
         }
         _ => {
             // Fall back to album image
-            let paths = match Paths::get() {
-                Ok(p) => p,
-                Err(e) => {
-                    return HttpResponse::InternalServerError()
-                        .body(format!("Paths not initialized: {e}"))
-                }
-            };
-            let album_image = paths
-                .album_images("large")
-                .join(format!("{}.webp", track.albumhash));
+            let album_image =
+                album_thumbnail(&track.albumhash, "", "large", ThumbnailFormat::WebP).await;
 
-            if album_image.exists() {
-                if query.w.is_some() || query.h.is_some() {
-                    return serve_resized_image(&album_image, query.w, query.h).await;
+            match (album_image, query.resize()) {
+                (Some(image), Some(resize)) => {
+                    serve_resized_image(&image, resize, CachePolicy::Immutable, &req).await
                 }
-
-                match std::fs::read(&album_image) {
-                    Ok(bytes) => HttpResponse::Ok()
-                        .content_type(
-                            mime_guess::from_path(&album_image)
-                                .first_or_octet_stream()
-                                .essence_str(),
-                        )
-                        .body(bytes),
-                    Err(_) => HttpResponse::NotFound().body("Image not found"),
-                }
-            } else {
-                HttpResponse::NotFound().body("No image available")
+                (Some(image), None) => serve_image_file(&image, CachePolicy::Immutable, &req).await,
+                (None, _) => HttpResponse::NotFound().body("No image available"),
             }
         }
     }
 }
 
 /// Get playlist image
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    )
+)]
 #[get("/playlist/{id}")]
-pub async fn get_playlist_image(path: web::Path<i64>) -> impl Responder {
+pub async fn get_playlist_image(path: web::Path<i64>, req: HttpRequest) -> impl Responder {
     let id = path.into_inner();
     let paths = match Paths::get() {
         Ok(p) => p,
@@ -247,82 +540,351 @@ pub async fn get_playlist_image(path: web::Path<i64>) -> impl Responder {
     for ext in &["webp", "jpg", "jpeg", "png"] {
         let image_path = paths.playlist_images_dir().join(format!("{}.{}", id, ext));
 
+        // playlist covers are replaced under the same url, so revalidate
         if image_path.exists() {
-            return match std::fs::read(&image_path) {
-                Ok(bytes) => HttpResponse::Ok()
-                    .content_type(
-                        mime_guess::from_path(&image_path)
-                            .first_or_octet_stream()
-                            .essence_str(),
-                    )
-                    .body(bytes),
-                Err(_) => HttpResponse::NotFound().body("Image not found"),
-            };
+            return serve_image_file(&image_path, CachePolicy::Revalidate, &req).await;
         }
     }
 
     HttpResponse::NotFound().body("Playlist image not found")
 }
 
-/// Serve resized image from path
+/// Serve a resized copy of an image file
 async fn serve_resized_image(
-    path: &PathBuf,
-    width: Option<u32>,
-    height: Option<u32>,
+    path: &Path,
+    resize: Resize,
+    policy: CachePolicy,
+    req: &HttpRequest,
 ) -> HttpResponse {
-    let data = match std::fs::read(path) {
-        Ok(d) => d,
-        Err(_) => return HttpResponse::NotFound().body("Failed to read image"),
+    let Some(source) = file_validators(path) else {
+        return HttpResponse::NotFound().body("Failed to read image");
     };
 
-    let mime = if path.extension().map(|e| e == "png").unwrap_or(false) {
-        "image/png"
-    } else {
-        "image/jpeg"
-    };
-
-    serve_resized_bytes(&data, mime, width, height).await
+    let png = path.extension().map(|e| e == "png").unwrap_or(false);
+    let path = path.to_path_buf();
+    serve_resized(
+        move || std::fs::read(path).ok(),
+        png,
+        resize,
+        &source,
+        policy,
+        req,
+    )
+    .await
 }
 
-/// Serve resized image from bytes
-async fn serve_resized_bytes(
-    data: &[u8],
-    mime: &str,
-    width: Option<u32>,
-    height: Option<u32>,
+/// Serve a resized copy of an image, reusing the disk cache when possible
+///
+/// `load` is only called when the copy has to be built
+async fn serve_resized(
+    load: impl FnOnce() -> Option<Vec<u8>>,
+    png: bool,
+    resize: Resize,
+    source: &Validators,
+    policy: CachePolicy,
+    req: &HttpRequest,
 ) -> HttpResponse {
-    let img = match image::load_from_memory(data) {
-        Ok(i) => i,
-        Err(_) => return HttpResponse::NotFound().body("Failed to load image"),
+    let validators = Validators {
+        etag: variant_etag(&source.etag, resize),
+        modified: source.modified,
     };
+    if is_fresh(req, &validators) {
+        return not_modified(&validators, policy);
+    }
 
-    let resized = match (width, height) {
-        (Some(w), Some(h)) => img.thumbnail_exact(w, h),
-        (Some(w), None) => img.thumbnail(w, img.height()),
-        (None, Some(h)) => img.thumbnail(img.width(), h),
-        (None, None) => img,
+    let (mime, ext, format) = if png {
+        ("image/png", "png", image::ImageFormat::Png)
+    } else {
+        ("image/jpeg", "jpg", image::ImageFormat::Jpeg)
     };
 
-    let mut buf = Vec::new();
-    let format = if mime == "image/png" {
-        image::ImageFormat::Png
-    } else {
-        image::ImageFormat::Jpeg
+    // copies are named after the source's content hash, a changed source never hits a stale copy
+    let cached = Paths::get().ok().map(|paths| {
+        paths.resized_image_cache_dir().join(format!(
+            "{}-{}.{}",
+            source.etag.trim_matches('"'),
+            resize.key(),
+            ext
+        ))
+    });
+    if let Some(bytes) = cached.as_ref().and_then(|p| std::fs::read(p).ok()) {
+        return image_response(mime, bytes, &validators, policy);
+    }
+
+    let Some(data) = load() else {
+        return HttpResponse::NotFound().body("Failed to read image");
+    };
+    let buf = match web::block(move || encode_resized(&data, resize, format)).await {
+        Ok(Some(buf)) => buf,
+        _ => return HttpResponse::InternalServerError().body("Failed to resize image"),
     };
 
-    if resized
+    if let Some(path) = cached {
+        if let Err(e) = store_resized(&path, &buf) {
+            tracing::warn!("Failed to cache resized image: {}", e);
+        }
+    }
+
+    image_response(mime, buf, &validators, policy)
+}
+
+fn encode_resized(data: &[u8], resize: Resize, format: image::ImageFormat) -> Option<Vec<u8>> {
+    let img = image::load_from_memory(data).ok()?;
+    let mut buf = Vec::new();
+    resize
+        .apply(img)
         .write_to(&mut std::io::Cursor::new(&mut buf), format)
-        .is_err()
-    {
-        return HttpResponse::InternalServerError().body("Failed to encode image");
+        .ok()?;
+    Some(buf)
+}
+
+fn store_resized(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    std::fs::create_dir_all(dir)?;
+
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path)?;
+
+    prune_cache(dir, RESIZED_IMAGE_CACHE_BYTES)
+}
+
+/// Size budget for the on-disk cache of resized images
+const RESIZED_IMAGE_CACHE_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Smallest and largest side a resized image can be requested at
+const MIN_RESIZE_PX: u32 = 16;
+const MAX_RESIZE_PX: u32 = 4096;
+
+/// Dimensions of a resized image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resize {
+    Exact(u32, u32),
+    Width(u32),
+    Height(u32),
+    /// longest side, keeps the aspect ratio
+    Fit(u32),
+}
+
+impl Resize {
+    /// Suffix naming the resized copy in etags and the disk cache
+    fn key(self) -> String {
+        match self {
+            Resize::Exact(w, h) => format!("{w}x{h}"),
+            Resize::Width(w) => format!("w{w}"),
+            Resize::Height(h) => format!("h{h}"),
+            Resize::Fit(size) => format!("s{size}"),
+        }
+    }
+
+    fn apply(self, img: image::DynamicImage) -> image::DynamicImage {
+        match self {
+            Resize::Exact(w, h) => img.thumbnail_exact(w, h),
+            Resize::Width(w) => img.thumbnail(w, img.height()),
+            Resize::Height(h) => img.thumbnail(img.width(), h),
+            Resize::Fit(size) => img.thumbnail(size, size),
+        }
+    }
+}
+
+impl ImageQuery {
+    /// Requested resize, sides are clamped so the disk cache stays bounded
+    fn resize(&self) -> Option<Resize> {
+        let clamp = |px: u32| px.clamp(MIN_RESIZE_PX, MAX_RESIZE_PX);
+        match (self.size, self.w, self.h) {
+            (Some(size), _, _) => Some(Resize::Fit(clamp(size))),
+            (None, Some(w), Some(h)) => Some(Resize::Exact(clamp(w), clamp(h))),
+            (None, Some(w), None) => Some(Resize::Width(clamp(w))),
+            (None, None, Some(h)) => Some(Resize::Height(clamp(h))),
+            (None, None, None) => None,
+        }
     }
+}
+
+/// Image files whose content hash is remembered
+const ETAG_CACHE_SIZE: usize = 8192;
+
+/// Content hash of an image file, valid while its size and mtime are unchanged
+struct FileTag {
+    modified: Option<SystemTime>,
+    len: u64,
+    etag: String,
+}
+
+static FILE_TAGS: Lazy<Mutex<LruCache<PathBuf, FileTag>>> = Lazy::new(|| {
+    Mutex::new(LruCache::new(
+        NonZeroUsize::new(ETAG_CACHE_SIZE).expect("etag cache size is not zero"),
+    ))
+});
+
+/// How long clients may keep an image before asking again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CachePolicy {
+    /// content under the url only changes on a rebuild, cache for the configured max-age
+    Immutable,
+    /// content can be replaced under the same url, revalidate with the etag
+    Revalidate,
+}
+
+impl CachePolicy {
+    fn header(self) -> String {
+        match (self, image_cache_max_age()) {
+            (CachePolicy::Revalidate, _) | (_, 0) => "no-cache".to_string(),
+            (CachePolicy::Immutable, max_age) => {
+                format!("public, max-age={max_age}, immutable")
+            }
+        }
+    }
+}
+
+/// Validators sent with an image and checked against conditional requests
+#[derive(Debug, Clone)]
+struct Validators {
+    etag: String,
+    modified: Option<SystemTime>,
+}
 
-    HttpResponse::Ok().content_type(mime).body(buf)
+/// Strong etag from a hash of the image bytes
+fn content_etag(data: &[u8]) -> String {
+    format!("\"{:032x}\"", xxh3_128(data))
 }
 
+/// Validators of a file, the file is only hashed again after it changes
+fn file_validators(path: &Path) -> Option<Validators> {
+    let meta = std::fs::metadata(path).ok()?;
+    let modified = meta.modified().ok();
+    let len = meta.len();
+
+    if let Some(tag) = FILE_TAGS.lock().get(path) {
+        if tag.modified == modified && tag.len == len {
+            return Some(Validators {
+                etag: tag.etag.clone(),
+                modified,
+            });
+        }
+    }
+
+    let etag = content_etag(&std::fs::read(path).ok()?);
+    FILE_TAGS.lock().put(
+        path.to_path_buf(),
+        FileTag {
+            modified,
+            len,
+            etag: etag.clone(),
+        },
+    );
+    Some(Validators { etag, modified })
+}
+
+/// Etag of a resized copy of an image
+fn variant_etag(etag: &str, resize: Resize) -> String {
+    format!("\"{}-{}\"", etag.trim_matches('"'), resize.key())
+}
+
+/// Whether the client already has the image, weak validators compare equal too
+fn etag_matches(req: &HttpRequest, etag: &str) -> bool {
+    req.headers()
+        .get_all(IF_NONE_MATCH)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// Whether the client's copy is current
+///
+/// If-Modified-Since is only used when the client sent no etags
+fn is_fresh(req: &HttpRequest, validators: &Validators) -> bool {
+    if req.headers().contains_key(IF_NONE_MATCH) {
+        return etag_matches(req, &validators.etag);
+    }
+
+    let since = req
+        .headers()
+        .get(IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<HttpDate>().ok())
+        .map(SystemTime::from);
+    match (since, validators.modified) {
+        // http dates have whole second precision
+        (Some(since), Some(modified)) => unix_secs(modified) <= unix_secs(since),
+        _ => false,
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn not_modified(validators: &Validators, policy: CachePolicy) -> HttpResponse {
+    let mut response = HttpResponse::NotModified();
+    response
+        .insert_header((ETAG, validators.etag.clone()))
+        .insert_header((CACHE_CONTROL, policy.header()));
+    if let Some(modified) = validators.modified {
+        response.insert_header(LastModified(modified.into()));
+    }
+    response.finish()
+}
+
+fn image_response(
+    mime: &str,
+    body: Vec<u8>,
+    validators: &Validators,
+    policy: CachePolicy,
+) -> HttpResponse {
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type(mime)
+        .insert_header((ETAG, validators.etag.clone()))
+        .insert_header((CACHE_CONTROL, policy.header()));
+    if let Some(modified) = validators.modified {
+        response.insert_header(LastModified(modified.into()));
+    }
+    response.body(body)
+}
+
+/// Serve an image file, answering 304 when the client's copy is current
+async fn serve_image_file(path: &Path, policy: CachePolicy, req: &HttpRequest) -> HttpResponse {
+    let Some(validators) = file_validators(path) else {
+        return HttpResponse::NotFound().body("Image not found");
+    };
+    if is_fresh(req, &validators) {
+        return not_modified(&validators, policy);
+    }
+
+    match std::fs::read(path) {
+        Ok(bytes) => {
+            let mime = mime_guess::from_path(path).first_or_octet_stream();
+            image_response(mime.essence_str(), bytes, &validators, policy)
+        }
+        Err(_) => HttpResponse::NotFound().body("Image not found"),
+    }
+}
+
+/// OpenAPI description of the imgserver routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    get_album_image_original,
+    get_album_image,
+    get_artist_image,
+    get_artist_image_small,
+    get_artist_image_medium,
+    get_track_image,
+    get_playlist_image,
+    get_thumb_large,
+    get_thumb_medium,
+    get_thumb_small,
+    get_thumb_xsmall,
+))]
+pub struct ApiDoc;
+
 /// Configure image routes
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(get_album_image)
+    cfg.service(get_album_image_original)
+        .service(get_album_image)
         .service(get_artist_image)
         .service(get_artist_image_small)
         .service(get_artist_image_medium)
@@ -336,6 +898,10 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
 
 // -------- Thumbnail endpoints (upstream-compatible) --------
 
+#[utoipa::path(
+    params(ThumbQuery),
+    responses((status = 200, description = "Success"))
+)]
 #[get("/thumbnail/{imgpath}")]
 pub async fn get_thumb_large(
     path: web::Path<String>,
@@ -345,6 +911,10 @@ pub async fn get_thumb_large(
     serve_or_create_thumb(&path.into_inner(), THUMB_LG, &query.pathhash, &req).await
 }
 
+#[utoipa::path(
+    params(ThumbQuery),
+    responses((status = 200, description = "Success"))
+)]
 #[get("/thumbnail/medium/{imgpath}")]
 pub async fn get_thumb_medium(
     path: web::Path<String>,
@@ -354,6 +924,10 @@ pub async fn get_thumb_medium(
     serve_or_create_thumb(&path.into_inner(), THUMB_MD, &query.pathhash, &req).await
 }
 
+#[utoipa::path(
+    params(ThumbQuery),
+    responses((status = 200, description = "Success"))
+)]
 #[get("/thumbnail/small/{imgpath}")]
 pub async fn get_thumb_small(
     path: web::Path<String>,
@@ -363,6 +937,10 @@ pub async fn get_thumb_small(
     serve_or_create_thumb(&path.into_inner(), THUMB_SM, &query.pathhash, &req).await
 }
 
+#[utoipa::path(
+    params(ThumbQuery),
+    responses((status = 200, description = "Success"))
+)]
 #[get("/thumbnail/xsmall/{imgpath}")]
 pub async fn get_thumb_xsmall(
     path: web::Path<String>,
@@ -378,199 +956,131 @@ async fn serve_or_create_thumb(
     pathhash: &str,
     req: &actix_web::HttpRequest,
 ) -> HttpResponse {
-    let paths = match Paths::get() {
-        Ok(p) => p,
-        Err(e) => {
-            return HttpResponse::InternalServerError().body(format!("Paths not initialized: {e}"))
-        }
-    };
-
-    let target = spec.path(&paths, imgname);
-    if target.exists() {
-        return serve_named(&target, req).await;
-    }
-
-    if let Err(e) = std::fs::create_dir_all(target.parent().unwrap_or(Path::new("."))) {
-        return HttpResponse::InternalServerError()
-            .body(format!("Failed to prepare cache dir: {e}"));
-    }
-
-    // Try to build from existing large image first
-    match build_thumb_from_album_image(&paths, imgname, spec.max_px, &target).await {
-        Ok(true) => return serve_named(&target, req).await,
-        Ok(false) => {}
-        Err(_) => {}
-    }
-
-    // If no cached large image, try to extract from track using pathhash
-    if !pathhash.is_empty() {
-        if let Ok(true) =
-            extract_thumb_from_track(&paths, imgname, pathhash, spec.max_px, &target).await
-        {
-            return serve_named(&target, req).await;
-        }
-    }
-
-    HttpResponse::NotFound().body("Image not found")
-}
-
-async fn serve_named(path: &Path, req: &actix_web::HttpRequest) -> HttpResponse {
-    match NamedFile::open(path) {
-        Ok(file) => file.into_response(req),
-        Err(_) => HttpResponse::NotFound().body("Image not found"),
-    }
-}
-
-async fn build_thumb_from_album_image(
-    paths: &Paths,
-    imgname: &str,
-    max_px: u32,
-    target: &Path,
-) -> anyhow::Result<bool> {
-    let stem = Path::new(imgname)
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or(imgname);
-
-    // Try common extensions to locate original
-    let exts = ["webp", "jpg", "jpeg", "png"];
-    let mut source: Option<PathBuf> = None;
-    for ext in &exts {
-        let candidate = paths
-            .album_images("large")
-            .join(format!("{}.{}", stem, ext));
-        if candidate.exists() {
-            source = Some(candidate);
-            break;
-        }
-    }
-
-    let Some(source_path) = source else {
-        return Ok(false);
-    };
-
-    let data = std::fs::read(&source_path)?;
-    let img = image::load_from_memory(&data)?;
-    let resized = img.thumbnail(max_px, max_px);
-
-    let mut buf = Vec::new();
-    let format = match target.extension().and_then(|e| e.to_str()) {
-        Some("png") => image::ImageFormat::Png,
-        Some("jpg") | Some("jpeg") => image::ImageFormat::Jpeg,
-        _ => image::ImageFormat::WebP,
-    };
-
-    resized.write_to(&mut std::io::Cursor::new(&mut buf), format)?;
-    std::fs::write(target, buf)?;
-    Ok(true)
-}
-
-/// Extract thumbnail from track embedded art on-demand
-async fn extract_thumb_from_track(
-    paths: &Paths,
-    imgname: &str,
-    pathhash: &str,
-    max_px: u32,
-    target: &Path,
-) -> anyhow::Result<bool> {
-    use crate::utils::hashing::create_hash;
-
     let albumhash = Path::new(imgname)
         .file_stem()
         .and_then(|s| s.to_str())
         .unwrap_or(imgname);
 
-    // Find a track with matching albumhash and pathhash (folder hash)
-    let tracks = TrackStore::get().get_by_album(albumhash);
-
-    let matching_track = tracks.iter().find(|t| {
-        let folder_hash = create_hash(&[&t.folder], false);
-        folder_hash == pathhash || t.folder.contains(pathhash)
-    });
-
-    let track = match matching_track {
-        Some(t) => t,
-        None => tracks
-            .first()
-            .ok_or_else(|| anyhow::anyhow!("No track found"))?,
+    let Some(webp) =
+        album_thumbnail(albumhash, pathhash, spec.size_label, ThumbnailFormat::WebP).await
+    else {
+        return HttpResponse::NotFound().body("Image not found");
     };
 
-    // Try embedded cover first
-    let track_path = Path::new(&track.filepath);
-    let cover_bytes = Tagger::read_cover(track_path)
-        .ok()
+    // avif copies only exist when they are turned on, the url stays the same
+    let avif = thumbnail_settings()
+        .avif
+        .then(|| thumbnail_path(albumhash, spec.size_label, ThumbnailFormat::Avif))
         .flatten()
-        .or_else(|| find_folder_image(track_path));
+        .filter(|_| accepts_avif(req));
 
-    let Some(data) = cover_bytes else {
-        return Ok(false);
+    let mut response = match avif {
+        Some(avif) => serve_image_file(&avif, CachePolicy::Immutable, req).await,
+        None => serve_image_file(&webp, CachePolicy::Immutable, req).await,
     };
+    response.headers_mut().insert(
+        VARY,
+        actix_web::http::header::HeaderValue::from_static("Accept"),
+    );
+    response
+}
 
-    let img = image::load_from_memory(&data)?;
-    let resized = img.thumbnail(max_px, max_px);
+/// Whether the client lists avif in its Accept header
+fn accepts_avif(req: &HttpRequest) -> bool {
+    req.headers()
+        .get_all(ACCEPT)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|media| {
+            let mut parts = media.split(';').map(str::trim);
+            parts.next() == Some("image/avif") && !parts.any(|p| p == "q=0" || p == "q=0.0")
+        })
+}
 
-    let mut buf = Vec::new();
-    resized.write_to(
-        &mut std::io::Cursor::new(&mut buf),
-        image::ImageFormat::WebP,
-    )?;
-    std::fs::write(target, buf)?;
-
-    // Also save to large for future requests
-    let large_target = paths
-        .thumbnails_dir("large")
-        .join(format!("{}.webp", albumhash));
-    if !large_target.exists() {
-        let large_resized = img.thumbnail(512, 512);
-        let mut large_buf = Vec::new();
-        if large_resized
-            .write_to(
-                &mut std::io::Cursor::new(&mut large_buf),
-                image::ImageFormat::WebP,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thumbnail_variants() {
+        let image = "abc.webp?pathhash=def";
+        let variants = CardImage::Thumbnail.variants(image, &ThumbnailSettings::default());
+
+        assert_eq!(
+            variants[0],
+            (
+                "small",
+                "/img/thumbnail/small/abc.webp?pathhash=def".to_string(),
+                96
             )
-            .is_ok()
-        {
-            let _ = std::fs::write(&large_target, large_buf);
-        }
+        );
+        assert_eq!(variants[2].1, "/img/thumbnail/abc.webp?pathhash=def");
+        assert_eq!(
+            CardImage::Artist.variants("abc.webp", &ThumbnailSettings::default())[1].1,
+            "/img/artist/medium/abc.webp"
+        );
     }
 
-    Ok(true)
-}
-
-fn find_folder_image(track_path: &Path) -> Option<Vec<u8>> {
-    let folder = track_path.parent()?;
-    let mut images: Vec<PathBuf> = std::fs::read_dir(folder)
-        .ok()?
-        .filter_map(|e| e.ok())
-        .map(|e| e.path())
-        .filter(|p| {
-            p.is_file()
-                && p.extension()
-                    .and_then(|e| e.to_str())
-                    .map(|ext| {
-                        matches!(ext.to_lowercase().as_str(), "jpg" | "jpeg" | "png" | "webp")
-                    })
-                    .unwrap_or(false)
-        })
-        .collect();
-
-    if images.is_empty() {
-        return None;
+    #[test]
+    fn test_etag_matching() {
+        let etag = content_etag(b"image bytes");
+        let resized = variant_etag(&etag, Resize::Width(96));
+        assert_ne!(etag, resized);
+        assert_ne!(variant_etag(&etag, Resize::Fit(96)), resized);
+
+        let req = actix_web::test::TestRequest::default()
+            .insert_header((IF_NONE_MATCH, format!("\"other\", W/{resized}")))
+            .to_http_request();
+        assert!(etag_matches(&req, &resized));
+        assert!(!etag_matches(&req, &etag));
+
+        let req = actix_web::test::TestRequest::default().to_http_request();
+        assert!(!etag_matches(&req, &etag));
     }
 
-    // Prioritize common cover names
-    let priority = ["cover", "front", "folder", "album", "artwork", "back"];
-    images.sort_by_key(|p| {
-        let name = p
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("")
-            .to_lowercase();
-        priority
-            .iter()
-            .position(|pref| name.starts_with(pref))
-            .unwrap_or(priority.len())
-    });
+    #[test]
+    fn test_resize_query() {
+        let query = |size, w, h| ImageQuery { w, h, size };
+
+        assert_eq!(query(None, None, None).resize(), None);
+        assert_eq!(
+            query(Some(64), Some(10), None).resize(),
+            Some(Resize::Fit(64))
+        );
+        assert_eq!(
+            query(None, Some(1), Some(100_000)).resize(),
+            Some(Resize::Exact(MIN_RESIZE_PX, MAX_RESIZE_PX))
+        );
+        assert_eq!(
+            query(None, None, Some(96)).resize(),
+            Some(Resize::Height(96))
+        );
+    }
 
-    std::fs::read(images[0].clone()).ok()
+    #[test]
+    fn test_if_modified_since() {
+        let modified = UNIX_EPOCH + std::time::Duration::from_millis(1_700_000_000_500);
+        let validators = Validators {
+            etag: content_etag(b"image bytes"),
+            modified: Some(modified),
+        };
+        let request = |since: SystemTime| {
+            actix_web::test::TestRequest::default()
+                .insert_header((IF_MODIFIED_SINCE, HttpDate::from(since).to_string()))
+                .to_http_request()
+        };
+
+        assert!(is_fresh(&request(modified), &validators));
+        assert!(!is_fresh(
+            &request(modified - std::time::Duration::from_secs(60)),
+            &validators
+        ));
+
+        // a mismatched etag wins over the date
+        let req = actix_web::test::TestRequest::default()
+            .insert_header((IF_NONE_MATCH, "\"other\""))
+            .insert_header((IF_MODIFIED_SINCE, HttpDate::from(modified).to_string()))
+            .to_http_request();
+        assert!(!is_fresh(&req, &validators));
+    }
 }
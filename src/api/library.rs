@@ -0,0 +1,139 @@
+//! Library inbox routes
+//!
+//! the review queue of files dropped in the inbox folder that could not be
+//! filed on their own.
+
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::api::identity::{Admin, Authorized};
+use crate::core::inbox;
+
+/// Pick how a queued file is filed
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct FileInboxRequest {
+    /// MusicBrainz recording to tag the file with, its own tags are kept when missing
+    #[serde(default)]
+    pub recording_mbid: Option<String>,
+    #[serde(default)]
+    pub release_mbid: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DismissQuery {
+    /// delete the file instead of leaving it in the inbox
+    #[serde(default)]
+    pub delete: bool,
+}
+
+/// Files waiting for review (GET /library/inbox)
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[get("/inbox")]
+pub async fn get_inbox(_admin: Authorized<Admin>) -> impl Responder {
+    HttpResponse::Ok().json(json!({
+        "items": inbox::queue(),
+        "auto_file_score": inbox::AUTO_FILE_SCORE,
+    }))
+}
+
+/// File what was dropped in the inbox now instead of on the next check
+/// (POST /library/inbox/scan)
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "No inbox folder is set"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[post("/inbox/scan")]
+pub async fn scan_inbox(_admin: Authorized<Admin>) -> impl Responder {
+    match inbox::scan().await {
+        Ok(summary) => HttpResponse::Ok().json(summary),
+        Err(e) => HttpResponse::BadRequest().json(json!({"error": format!("{:#}", e)})),
+    }
+}
+
+/// File a queued file into the library (POST /library/inbox/{id}/file)
+#[utoipa::path(
+    params(("id" = String, Path, description = "Inbox item ID")),
+    request_body = FileInboxRequest,
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "File can't be filed")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[post("/inbox/{id}/file")]
+pub async fn file_inbox_item(
+    _admin: Authorized<Admin>,
+    path: web::Path<String>,
+    body: Option<web::Json<FileInboxRequest>>,
+) -> impl Responder {
+    let body = body.map(web::Json::into_inner).unwrap_or_default();
+
+    match inbox::file(
+        &path,
+        body.recording_mbid.as_deref(),
+        body.release_mbid.as_deref(),
+    )
+    .await
+    {
+        Ok(Some(track)) => HttpResponse::Ok().json(json!({
+            "trackhash": track.trackhash,
+            "filepath": track.filepath,
+        })),
+        Ok(None) => HttpResponse::NotFound().json(json!({"error": "File is not in the inbox"})),
+        Err(e) => HttpResponse::Conflict().json(json!({"error": format!("{:#}", e)})),
+    }
+}
+
+/// Take a file out of the review queue (DELETE /library/inbox/{id})
+#[utoipa::path(
+    params(("id" = String, Path, description = "Inbox item ID"), DismissQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[delete("/inbox/{id}")]
+pub async fn dismiss_inbox_item(
+    _admin: Authorized<Admin>,
+    path: web::Path<String>,
+    query: web::Query<DismissQuery>,
+) -> impl Responder {
+    match inbox::dismiss(&path, query.delete).await {
+        Ok(true) => HttpResponse::Ok().json(json!({"msg": "File dismissed"})),
+        Ok(false) => HttpResponse::NotFound().json(json!({"error": "File is not in the inbox"})),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": format!("{:#}", e)})),
+    }
+}
+
+/// OpenAPI description of the library routes
+#[derive(OpenApi)]
+#[openapi(paths(get_inbox, scan_inbox, file_inbox_item, dismiss_inbox_item))]
+pub struct ApiDoc;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_inbox)
+        .service(scan_inbox)
+        .service(file_inbox_item)
+        .service(dismiss_inbox_item);
+}
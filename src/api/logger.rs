@@ -1,35 +1,41 @@
 //! logger and stats api routes mirroring upstream flask behavior
 
-use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
-use chrono::{DateTime, Utc};
+use actix_web::http::header::ContentDisposition;
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Datelike, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Map, Value};
 use std::collections::{HashMap, HashSet};
-
-use crate::config::UserConfig;
-use crate::core::homepage::HomepageStore;
-use crate::db::tables::{FavoriteTable, ScrobbleTable};
-use crate::models::{Album, Artist, Track};
-use crate::plugins::LastFmPlugin;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::api::identity::{clean_device, request_device, CurrentUser};
+use crate::api::imgserver::{insert_image_hints, CardImage};
+use crate::api::track::serialize_for_user;
+use crate::core::history::{self, HistoryGrouping};
+use crate::core::playback::record_play;
+use crate::core::popularity::{self, PopularityKind};
+use crate::core::scrobble_export::{self, ExportFormat, ExportedPlay};
+use crate::core::{audiobooks, devices, mapstuff, wrapped};
+use crate::db::tables::{DeviceFilter, FavoriteTable, ScrobbleTable};
+use crate::models::{Album, Artist, Track, TrackLog};
 use crate::stores::{AlbumStore, ArtistStore, TrackStore};
-use crate::utils::auth::verify_jwt;
 use crate::utils::dates::{start_of_month, start_of_week, start_of_year};
-use crate::utils::extras::get_extra_info;
-
-const DEFAULT_USER_ID: i64 = 0;
 
 /// log track request payload
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct LogTrackRequest {
     pub trackhash: String,
     pub timestamp: i64,
     pub duration: i32,
     #[serde(default)]
     pub source: String,
+    /// device the track played on, the `X-Device-Id` header when missing
+    #[serde(default)]
+    pub device: Option<String>,
 }
 
 /// chart query params
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct ChartQuery {
     #[serde(default = "default_duration")]
     pub duration: String,
@@ -37,6 +43,63 @@ pub struct ChartQuery {
     pub limit: usize,
     #[serde(default = "default_order_by")]
     pub order_by: String,
+    /// comma separated devices to count, `-device` leaves one out
+    pub device: Option<String>,
+}
+
+/// device filter query params
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DeviceQuery {
+    /// comma separated devices to count, `-device` leaves one out
+    pub device: Option<String>,
+}
+
+/// scrobble export query params
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ExportQuery {
+    /// `lastfm-csv` or `listenbrainz-json`
+    pub format: String,
+    /// unix timestamp of the first play to export
+    pub start: Option<i64>,
+    /// unix timestamp of the last play to export
+    pub end: Option<i64>,
+    /// comma separated devices to export, `-device` leaves one out
+    pub device: Option<String>,
+}
+
+/// listening history query params
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct HistoryQuery {
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    #[serde(default = "default_history_limit")]
+    pub limit: i64,
+    /// unix timestamp of the oldest play to include
+    pub start: Option<i64>,
+    /// unix timestamp of the newest play to include
+    pub end: Option<i64>,
+    /// `day` or `session`, plain plays when missing
+    #[serde(default)]
+    pub group_by: String,
+    /// utc offset in seconds days are grouped in
+    #[serde(default)]
+    pub utc_offset: i32,
+    /// comma separated devices to include, `-device` leaves one out
+    pub device: Option<String>,
+}
+
+fn default_history_limit() -> i64 {
+    50
+}
+
+/// most plays sent in one history page
+const MAX_HISTORY_LIMIT: i64 = 500;
+
+/// mix exclusion of a device
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DeviceMixBody {
+    pub device: String,
+    pub exclude_from_mixes: bool,
 }
 
 fn default_duration() -> String {
@@ -51,6 +114,19 @@ fn default_order_by() -> String {
     "playduration".to_string()
 }
 
+/// trending query params
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct TrendingQuery {
+    #[serde(default = "default_trending_kind")]
+    pub kind: String,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+fn default_trending_kind() -> String {
+    "tracks".to_string()
+}
+
 /// stat item aligned with upstream
 #[derive(Debug, Serialize)]
 pub struct StatItem {
@@ -62,8 +138,22 @@ pub struct StatItem {
 }
 
 /// log a track play
+#[utoipa::path(
+    responses(
+        (status = 201, description = "Created"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[post("/track/log")]
-pub async fn log_track(req: HttpRequest, body: web::Json<LogTrackRequest>) -> impl Responder {
+pub async fn log_track(
+    req: HttpRequest,
+    user: CurrentUser,
+    body: web::Json<LogTrackRequest>,
+) -> impl Responder {
     if body.timestamp == 0 || body.duration < 5 {
         return HttpResponse::BadRequest().json(json!({"msg": "Invalid entry."}));
     }
@@ -75,19 +165,18 @@ pub async fn log_track(req: HttpRequest, body: web::Json<LogTrackRequest>) -> im
         }
     };
 
-    let user_id = match resolve_user_id(&req).await {
-        Ok(id) => id,
-        Err(resp) => return resp,
+    let device = match &body.device {
+        Some(device) => clean_device(device),
+        None => request_device(&req),
     };
 
-    let extra = get_extra_info(&body.trackhash, "track");
-    if let Err(e) = ScrobbleTable::add_with_extra(
-        &body.trackhash,
+    if let Err(e) = record_play(
+        user.id,
+        &track,
         body.timestamp,
         body.duration,
         &body.source,
-        user_id,
-        &extra,
+        &device,
     )
     .await
     {
@@ -95,50 +184,30 @@ pub async fn log_track(req: HttpRequest, body: web::Json<LogTrackRequest>) -> im
             .json(json!({"msg": format!("Failed to log track: {}", e)}));
     }
 
-    HomepageStore::get().update_recently_played(user_id).await;
-
-    let albumhash = track.albumhash.clone();
-    TrackStore::get().increment_play_stats(&body.trackhash, body.duration, body.timestamp);
-    AlbumStore::get().increment_play_stats(&albumhash, body.duration, body.timestamp);
-    for artisthash in &track.artisthashes {
-        ArtistStore::get().increment_play_stats(artisthash, body.duration, body.timestamp);
-    }
-
-    if LastFmPlugin::should_scrobble(track.duration, body.duration) {
-        if let Some(session_key) = lastfm_session_for_user(user_id) {
-            let plugin = LastFmPlugin::new();
-            let scrobble_track = track.clone();
-            let play_timestamp = body.timestamp;
-
-            tokio::spawn(async move {
-                if let Err(err) = plugin
-                    .scrobble(&scrobble_track, play_timestamp, &session_key)
-                    .await
-                {
-                    eprintln!("lastfm scrobble error: {}", err);
-                }
-            });
-        }
-    }
-
     HttpResponse::Created().json(json!({"msg": "recorded"}))
 }
 
 /// top tracks
+#[utoipa::path(
+    params(ChartQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/top-tracks")]
-pub async fn get_top_tracks(req: HttpRequest, query: web::Query<ChartQuery>) -> impl Responder {
-    let user_id = match resolve_user_id(&req).await {
-        Ok(id) => id,
-        Err(resp) => return resp,
-    };
+pub async fn get_top_tracks(user: CurrentUser, query: web::Query<ChartQuery>) -> impl Responder {
+    let user_id = user.id;
 
     let (start_time, end_time) = get_date_range(&query.duration);
     let previous_start_time = start_time - get_duration_in_seconds(&query.duration);
+    let devices = DeviceFilter::parse(query.device.as_deref());
 
     let (current_tracks, current_scrobbles, duration) =
-        get_tracks_in_period(user_id, start_time, end_time).await;
+        get_tracks_in_period(user_id, start_time, end_time, &devices).await;
     let (previous_tracks, previous_scrobbles, _) =
-        get_tracks_in_period(user_id, previous_start_time, start_time).await;
+        get_tracks_in_period(user_id, previous_start_time, start_time, &devices).await;
 
     let scrobble_trend = calculate_scrobble_trend(current_scrobbles, previous_scrobbles);
 
@@ -149,7 +218,7 @@ pub async fn get_top_tracks(req: HttpRequest, query: web::Query<ChartQuery>) ->
         .filter_map(|track| {
             let trend = calculate_track_trend(&track, &current_tracks, &previous_tracks);
             let help_text = get_help_text(track.playcount, track.playduration, &query.order_by);
-            let mut map = serialize_track_for_stats(&track);
+            let mut map = serialize_track_for_stats(&track, user_id);
             map.insert("trend".to_string(), trend);
             map.insert("help_text".to_string(), Value::String(help_text));
             Some(Value::Object(map))
@@ -166,21 +235,82 @@ pub async fn get_top_tracks(req: HttpRequest, query: web::Query<ChartQuery>) ->
     }))
 }
 
+/// tracks, artists or albums trending in the library, ranked by decaying popularity
+#[utoipa::path(
+    params(TrendingQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/trending")]
+pub async fn get_trending(user: CurrentUser, query: web::Query<TrendingQuery>) -> impl Responder {
+    let Some(kind) = PopularityKind::parse(&query.kind) else {
+        return HttpResponse::BadRequest().json(json!({"msg": "Invalid kind."}));
+    };
+
+    let scores = match popularity::trending(user.id, kind, query.limit).await {
+        Ok(scores) => scores,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(json!({"msg": format!("Failed to load trending items: {}", e)}));
+        }
+    };
+
+    let items: Vec<Value> = scores
+        .iter()
+        .filter_map(|score| {
+            let mut map = match kind {
+                PopularityKind::Track => {
+                    serialize_track_for_stats(&TrackStore::get().get_by_hash(&score.hash)?, user.id)
+                }
+                PopularityKind::Artist => {
+                    serialize_artist_card(&mut ArtistStore::get().get_by_hash(&score.hash)?)
+                }
+                PopularityKind::Album => {
+                    serialize_album_card(&mut AlbumStore::get().get_by_hash(&score.hash)?)
+                }
+            };
+            map.insert(
+                "popularity".to_string(),
+                json!((score.score * 100.0).round() / 100.0),
+            );
+            Some(Value::Object(map))
+        })
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "kind": kind,
+        "items": items,
+    }))
+}
+
 /// top artists
+#[utoipa::path(
+    params(ChartQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/top-artists")]
-pub async fn get_top_artists(req: HttpRequest, query: web::Query<ChartQuery>) -> impl Responder {
-    let user_id = match resolve_user_id(&req).await {
-        Ok(id) => id,
-        Err(resp) => return resp,
-    };
+pub async fn get_top_artists(user: CurrentUser, query: web::Query<ChartQuery>) -> impl Responder {
+    let user_id = user.id;
 
     let (start_time, end_time) = get_date_range(&query.duration);
     let previous_start_time = start_time - get_duration_in_seconds(&query.duration);
 
-    let current_artists = get_artists_in_period(user_id, start_time, end_time).await;
-    let previous_artists = get_artists_in_period(user_id, previous_start_time, start_time).await;
+    let devices = DeviceFilter::parse(query.device.as_deref());
 
-    let new_artists = calculate_new_artists(&current_artists, start_time, user_id).await;
+    let current_artists = get_artists_in_period(user_id, start_time, end_time, &devices).await;
+    let previous_artists =
+        get_artists_in_period(user_id, previous_start_time, start_time, &devices).await;
+
+    let new_artists = calculate_new_artists(&current_artists, start_time, user_id, &devices).await;
     let scrobble_trend =
         calculate_scrobble_trend(current_artists.len() as i32, previous_artists.len() as i32);
 
@@ -219,18 +349,26 @@ pub async fn get_top_artists(req: HttpRequest, query: web::Query<ChartQuery>) ->
 }
 
 /// top albums
+#[utoipa::path(
+    params(ChartQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/top-albums")]
-pub async fn get_top_albums(req: HttpRequest, query: web::Query<ChartQuery>) -> impl Responder {
-    let user_id = match resolve_user_id(&req).await {
-        Ok(id) => id,
-        Err(resp) => return resp,
-    };
+pub async fn get_top_albums(user: CurrentUser, query: web::Query<ChartQuery>) -> impl Responder {
+    let user_id = user.id;
 
     let (start_time, end_time) = get_date_range(&query.duration);
     let previous_start_time = start_time - get_duration_in_seconds(&query.duration);
 
-    let current_albums = get_albums_in_period(user_id, start_time, end_time).await;
-    let previous_albums = get_albums_in_period(user_id, previous_start_time, start_time).await;
+    let devices = DeviceFilter::parse(query.device.as_deref());
+
+    let current_albums = get_albums_in_period(user_id, start_time, end_time, &devices).await;
+    let previous_albums =
+        get_albums_in_period(user_id, previous_start_time, start_time, &devices).await;
 
     let new_albums = calculate_new_albums(&current_albums, &previous_albums);
     let scrobble_trend =
@@ -267,12 +405,18 @@ pub async fn get_top_albums(req: HttpRequest, query: web::Query<ChartQuery>) ->
 }
 
 /// stats dashboard
+#[utoipa::path(
+    params(DeviceQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/stats")]
-pub async fn get_stats(req: HttpRequest) -> impl Responder {
-    let user_id = match resolve_user_id(&req).await {
-        Ok(id) => id,
-        Err(resp) => return resp,
-    };
+pub async fn get_stats(user: CurrentUser, query: web::Query<DeviceQuery>) -> impl Responder {
+    let user_id = user.id;
+    let devices = DeviceFilter::parse(query.device.as_deref());
 
     let period = "week";
     let (start_time, end_time) = get_date_range(period);
@@ -285,7 +429,7 @@ pub async fn get_stats(req: HttpRequest) -> impl Responder {
         _ => "this week",
     };
 
-    let count = TrackStore::get().get_all().len() as i64;
+    let count = TrackStore::get().count() as i64;
     let total_tracks = StatItem {
         cssclass: "trackcount".to_string(),
         text: "in your library".to_string(),
@@ -294,7 +438,7 @@ pub async fn get_stats(req: HttpRequest) -> impl Responder {
     };
 
     let (tracks, playcount_total, playduration_total) =
-        get_tracks_in_period(user_id, start_time, end_time).await;
+        get_tracks_in_period(user_id, start_time, end_time, &devices).await;
 
     let playcount = StatItem {
         cssclass: "streams".to_string(),
@@ -369,58 +513,346 @@ pub async fn get_stats(req: HttpRequest) -> impl Responder {
     }))
 }
 
-/// configure logger routes
-pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(log_track)
-        .service(get_top_tracks)
-        .service(get_top_artists)
-        .service(get_top_albums)
-        .service(get_stats);
+/// devices the user has played on, with whether their plays feed mixes
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/devices")]
+pub async fn get_devices(user: CurrentUser) -> impl Responder {
+    let plays = match ScrobbleTable::devices(user.id).await {
+        Ok(plays) => plays,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(json!({"msg": format!("Failed to load devices: {}", e)}));
+        }
+    };
+    let excluded = devices::excluded_from_mixes(user.id).await;
+
+    let items: Vec<Value> = plays
+        .into_iter()
+        .map(|d| {
+            json!({
+                "excluded_from_mixes": excluded.contains(&d.device),
+                "device": d.device,
+                "plays": d.plays,
+                "last_played": d.last_played,
+            })
+        })
+        .collect();
+
+    HttpResponse::Ok().json(json!({ "devices": items }))
 }
 
-// helpers
+/// keep a device's plays out of the user's mixes or let them back in
+#[utoipa::path(
+    request_body = DeviceMixBody,
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[put("/devices")]
+pub async fn update_device(user: CurrentUser, body: web::Json<DeviceMixBody>) -> impl Responder {
+    let device = clean_device(&body.device);
+    if device.is_empty() {
+        return HttpResponse::BadRequest().json(json!({"msg": "Device is required."}));
+    }
 
-async fn resolve_user_id(req: &HttpRequest) -> Result<i64, HttpResponse> {
-    match optional_user_id(req).await? {
-        Some(id) => Ok(id),
-        None => Ok(DEFAULT_USER_ID),
+    match devices::set_excluded_from_mixes(user.id, &device, body.exclude_from_mixes).await {
+        Ok(excluded) => HttpResponse::Ok().json(json!({ "excluded_from_mixes": excluded })),
+        Err(e) => HttpResponse::InternalServerError()
+            .json(json!({"msg": format!("Failed to update device: {}", e)})),
     }
 }
 
-async fn optional_user_id(req: &HttpRequest) -> Result<Option<i64>, HttpResponse> {
-    let header = match req.headers().get("Authorization") {
-        Some(h) => h,
-        None => return Ok(None),
+/// listening history as a file scrobble services can import
+#[utoipa::path(
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/export")]
+pub async fn export_scrobbles(user: CurrentUser, query: web::Query<ExportQuery>) -> impl Responder {
+    let Some(format) = ExportFormat::from_str(&query.format) else {
+        return HttpResponse::BadRequest()
+            .json(json!({"msg": "Format must be lastfm-csv or listenbrainz-json."}));
     };
+    let devices = DeviceFilter::parse(query.device.as_deref());
+
+    let mut scrobbles = stats_scrobbles(
+        user.id,
+        query.start.unwrap_or(0),
+        query.end.unwrap_or(i64::MAX),
+        &devices,
+    )
+    .await;
+    // importers replay history in order, oldest play first
+    scrobbles.reverse();
 
-    let header_str = header.to_str().unwrap_or("").trim();
-    if header_str.is_empty() {
-        return Err(HttpResponse::Unauthorized().json(json!({"error": "Invalid token format"})));
+    let track_store = TrackStore::get();
+    let plays: Vec<ExportedPlay> = scrobbles
+        .iter()
+        .filter_map(|scrobble| {
+            let track = track_store.get_by_hash(&scrobble.trackhash);
+            ExportedPlay::new(scrobble, track.as_ref())
+        })
+        .collect();
+
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ContentDisposition::attachment(format!(
+            "swingmusic-scrobbles.{}",
+            format.extension()
+        )))
+        .body(scrobble_export::export(&plays, format))
+}
+
+/// listening history, newest play first
+#[utoipa::path(
+    params(HistoryQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/history")]
+pub async fn get_history(user: CurrentUser, query: web::Query<HistoryQuery>) -> impl Responder {
+    let Some(grouping) = HistoryGrouping::from_str(&query.group_by) else {
+        return HttpResponse::BadRequest().json(json!({"msg": "Group by must be day or session."}));
+    };
+    let before = match query.cursor.as_deref() {
+        Some(cursor) => match history::decode_cursor(cursor) {
+            Some(before) => Some(before),
+            None => return HttpResponse::BadRequest().json(json!({"msg": "Invalid cursor."})),
+        },
+        None => None,
+    };
+    let limit = query.limit.clamp(1, MAX_HISTORY_LIMIT);
+    let devices = DeviceFilter::parse(query.device.as_deref());
+
+    // one extra play tells whether another page follows
+    let mut plays = match ScrobbleTable::history_page(
+        user.id,
+        &devices,
+        query.start.unwrap_or(0),
+        query.end.unwrap_or(i64::MAX),
+        before,
+        limit + 1,
+    )
+    .await
+    {
+        Ok(plays) => plays,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(json!({"msg": format!("Failed to load history: {}", e)}))
+        }
+    };
+    let has_more = plays.len() as i64 > limit;
+    plays.truncate(limit as usize);
+    let next_cursor = plays
+        .last()
+        .filter(|_| has_more)
+        .map(history::encode_cursor);
+
+    let items = history_items(&plays, user.id);
+    if grouping == HistoryGrouping::None {
+        return HttpResponse::Ok().json(json!({
+            "items": items,
+            "next_cursor": next_cursor,
+        }));
     }
-    let token = if let Some(rest) = header_str.strip_prefix("Bearer ") {
-        rest
-    } else {
-        header_str
+
+    HttpResponse::Ok().json(json!({
+        "groups": history::group(&plays, items, grouping, query.utc_offset),
+        "next_cursor": next_cursor,
+    }))
+}
+
+/// remove a play from the user's history
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[delete("/history/{id}")]
+pub async fn delete_history_entry(user: CurrentUser, path: web::Path<i64>) -> impl Responder {
+    let play = match ScrobbleTable::delete(user.id, path.into_inner()).await {
+        Ok(Some(play)) => play,
+        Ok(None) => return HttpResponse::NotFound().json(json!({"msg": "Play not found."})),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(json!({"msg": format!("Failed to delete play: {}", e)}))
+        }
     };
-    if token.is_empty() {
-        return Err(HttpResponse::Unauthorized().json(json!({"error": "Invalid token format"})));
+
+    // play counts only ever grow as plays come in, rebuild them without this one
+    if let Ok(count) = ScrobbleTable::track_playcount(&play.trackhash).await {
+        TrackStore::get().set_play_count(&play.trackhash, count as i32);
+    }
+    if let Err(e) = mapstuff::map_user_scrobble_data(user.id).await {
+        tracing::warn!("Failed to rebuild play stats for user {}: {}", user.id, e);
+    }
+
+    HttpResponse::Ok().json(json!({"msg": "deleted", "id": play.id}))
+}
+
+/// history entries with their tracks, tracks gone from the library are null
+fn history_items(plays: &[TrackLog], user_id: i64) -> Vec<Value> {
+    let track_store = TrackStore::get();
+    let mut seen = HashSet::new();
+    let found: Vec<Track> = plays
+        .iter()
+        .filter(|play| seen.insert(play.trackhash.as_str()))
+        .filter_map(|play| track_store.get_by_hash(&play.trackhash))
+        .collect();
+    let hashes: Vec<String> = found.iter().map(|t| t.trackhash.clone()).collect();
+    let tracks: HashMap<String, Value> = hashes
+        .into_iter()
+        .zip(serialize_for_user(found, user_id))
+        .collect();
+
+    plays
+        .iter()
+        .map(|play| {
+            json!({
+                "id": play.id,
+                "trackhash": play.trackhash,
+                "timestamp": play.timestamp,
+                "duration": play.duration,
+                "source": play.source,
+                "device": play.device,
+                "track": tracks.get(&play.trackhash).cloned().unwrap_or(Value::Null),
+            })
+        })
+        .collect()
+}
+
+/// a user's year in review
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid year"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/wrapped/{year}")]
+pub async fn get_wrapped(user: CurrentUser, path: web::Path<i32>) -> impl Responder {
+    let year = path.into_inner();
+    if !valid_wrapped_year(year) {
+        return HttpResponse::BadRequest().json(json!({"msg": "Invalid year."}));
     }
 
-    let config = UserConfig::load()
-        .map_err(|_| HttpResponse::InternalServerError().json(json!({"error": "Config error"})))?;
+    match wrapped::for_year(user.id, year).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => HttpResponse::InternalServerError()
+            .json(json!({"msg": format!("Failed to build wrapped: {}", e)})),
+    }
+}
+
+/// a user's year in review as a shareable png
+#[utoipa::path(
+    responses(
+        (status = 200, description = "PNG image", content_type = "image/png"),
+        (status = 400, description = "Invalid year"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/wrapped/{year}/image")]
+pub async fn get_wrapped_image(user: CurrentUser, path: web::Path<i32>) -> impl Responder {
+    let year = path.into_inner();
+    if !valid_wrapped_year(year) {
+        return HttpResponse::BadRequest().json(json!({"msg": "Invalid year."}));
+    }
 
-    let claims = verify_jwt(token, &config.server_id, Some("access"))
-        .map_err(|_| HttpResponse::Unauthorized().json(json!({"msg": "Invalid token"})))?;
+    let report = match wrapped::for_year(user.id, year).await {
+        Ok(report) => report,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(json!({"msg": format!("Failed to build wrapped: {}", e)}))
+        }
+    };
+
+    match web::block(move || wrapped::render_image(&report)).await {
+        Ok(Ok(png)) => HttpResponse::Ok()
+            .content_type("image/png")
+            .insert_header(ContentDisposition::attachment(format!(
+                "swingmusic-wrapped-{}.png",
+                year
+            )))
+            .body(png),
+        Ok(Err(e)) => HttpResponse::InternalServerError()
+            .json(json!({"msg": format!("Failed to render wrapped: {}", e)})),
+        Err(e) => HttpResponse::InternalServerError()
+            .json(json!({"msg": format!("Failed to render wrapped: {}", e)})),
+    }
+}
 
-    let user_id = claims.sub.id;
-    Ok(Some(user_id))
+/// years from the unix epoch up to the current one
+fn valid_wrapped_year(year: i32) -> bool {
+    (1970..=Utc::now().year()).contains(&year)
 }
 
-fn lastfm_session_for_user(user_id: i64) -> Option<String> {
-    let config = UserConfig::load().ok()?;
-    config.get_lastfm_session_key(&user_id.to_string()).cloned()
+/// OpenAPI description of the logger routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    log_track,
+    get_top_tracks,
+    get_top_artists,
+    get_top_albums,
+    get_trending,
+    get_stats,
+    get_devices,
+    update_device,
+    export_scrobbles,
+    get_history,
+    delete_history_entry,
+    get_wrapped,
+    get_wrapped_image,
+))]
+pub struct ApiDoc;
+
+/// configure logger routes
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(log_track)
+        .service(get_top_tracks)
+        .service(get_top_artists)
+        .service(get_top_albums)
+        .service(get_trending)
+        .service(get_stats)
+        .service(get_devices)
+        .service(update_device)
+        .service(export_scrobbles)
+        .service(get_history)
+        .service(delete_history_entry)
+        .service(get_wrapped)
+        .service(get_wrapped_image);
 }
 
+// helpers
+
 fn get_help_text(playcount: i32, playduration: i32, order_by: &str) -> String {
     if order_by == "playcount" {
         if playcount == 0 {
@@ -604,10 +1036,33 @@ struct ArtistPeriod {
     tracks: HashMap<String, i32>,
 }
 
-async fn get_tracks_in_period(user_id: i64, start: i64, end: i64) -> (Vec<Track>, i32, i32) {
-    let scrobbles = ScrobbleTable::get_in_range(user_id, start, end)
+/// Scrobbles of a user in a period, audiobook plays do not count towards stats
+async fn stats_scrobbles(
+    user_id: i64,
+    start: i64,
+    end: i64,
+    devices: &DeviceFilter,
+) -> Vec<TrackLog> {
+    let track_store = TrackStore::get();
+    ScrobbleTable::get_in_range_on(user_id, start, end, devices)
         .await
-        .unwrap_or_default();
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|scrobble| {
+            track_store
+                .get_shared(&scrobble.trackhash)
+                .is_none_or(|track| !audiobooks::is_audiobook(&track))
+        })
+        .collect()
+}
+
+async fn get_tracks_in_period(
+    user_id: i64,
+    start: i64,
+    end: i64,
+    devices: &DeviceFilter,
+) -> (Vec<Track>, i32, i32) {
+    let scrobbles = stats_scrobbles(user_id, start, end, devices).await;
 
     let mut tracks: HashMap<String, Track> = HashMap::new();
     let mut duration = 0;
@@ -631,10 +1086,13 @@ async fn get_tracks_in_period(user_id: i64, start: i64, end: i64) -> (Vec<Track>
     (tracks.into_values().collect(), total, duration)
 }
 
-async fn get_artists_in_period(user_id: i64, start: i64, end: i64) -> Vec<ArtistPeriod> {
-    let scrobbles = ScrobbleTable::get_in_range(user_id, start, end)
-        .await
-        .unwrap_or_default();
+async fn get_artists_in_period(
+    user_id: i64,
+    start: i64,
+    end: i64,
+    devices: &DeviceFilter,
+) -> Vec<ArtistPeriod> {
+    let scrobbles = stats_scrobbles(user_id, start, end, devices).await;
 
     let mut artists: HashMap<String, ArtistPeriod> = HashMap::new();
 
@@ -665,10 +1123,13 @@ async fn get_artists_in_period(user_id: i64, start: i64, end: i64) -> Vec<Artist
     list
 }
 
-async fn get_albums_in_period(user_id: i64, start: i64, end: i64) -> Vec<Album> {
-    let scrobbles = ScrobbleTable::get_in_range(user_id, start, end)
-        .await
-        .unwrap_or_default();
+async fn get_albums_in_period(
+    user_id: i64,
+    start: i64,
+    end: i64,
+    devices: &DeviceFilter,
+) -> Vec<Album> {
+    let scrobbles = stats_scrobbles(user_id, start, end, devices).await;
 
     let mut albums: HashMap<String, Album> = HashMap::new();
 
@@ -694,24 +1155,23 @@ async fn calculate_new_artists(
     current_artists: &[ArtistPeriod],
     timestamp: i64,
     user_id: i64,
+    devices: &DeviceFilter,
 ) -> usize {
     let current_set: HashSet<String> = current_artists
         .iter()
         .map(|a| a.artisthash.clone())
         .collect();
 
-    let all_records = ScrobbleTable::get_in_range(user_id, 0, timestamp)
-        .await
-        .unwrap_or_default();
+    let all_records = stats_scrobbles(user_id, 0, timestamp, devices).await;
     let trackhashes: HashSet<String> = all_records.into_iter().map(|r| r.trackhash).collect();
 
     let mut previous_artists_set = HashSet::new();
     let track_store = TrackStore::get();
 
     for hash in trackhashes {
-        if let Some(track) = track_store.get_by_hash(&hash) {
-            for artist in track.artists {
-                previous_artists_set.insert(artist.artisthash);
+        if let Some(track) = track_store.get_shared(&hash) {
+            for artist in &track.artists {
+                previous_artists_set.insert(artist.artisthash.clone());
             }
         }
     }
@@ -729,7 +1189,7 @@ fn calculate_new_albums(current_albums: &[Album], previous_albums: &[Album]) ->
     current_set.difference(&previous_set).count()
 }
 
-fn serialize_track_for_stats(track: &Track) -> Map<String, Value> {
+fn serialize_track_for_stats(track: &Track, user_id: i64) -> Map<String, Value> {
     let mut map = serde_json::to_value(track)
         .unwrap_or_else(|_| json!({}))
         .as_object()
@@ -782,9 +1242,10 @@ fn serialize_track_for_stats(track: &Track) -> Map<String, Value> {
 
     map.insert(
         "is_favorite".to_string(),
-        Value::Bool(track.is_favorite(DEFAULT_USER_ID)),
+        Value::Bool(track.is_favorite(user_id)),
     );
 
+    insert_image_hints(&mut map, CardImage::Thumbnail);
     map
 }
 
@@ -831,6 +1292,7 @@ fn serialize_album_card(album: &mut Album) -> Map<String, Value> {
     }
 
     map.insert("type".to_string(), Value::String("album".to_string()));
+    insert_image_hints(&mut map, CardImage::Thumbnail);
     map
 }
 
@@ -863,5 +1325,6 @@ fn serialize_artist_card(artist: &mut Artist) -> Map<String, Value> {
     }
 
     map.insert("type".to_string(), Value::String("artist".to_string()));
+    insert_image_hints(&mut map, CardImage::Artist);
     map
 }
@@ -1,14 +1,23 @@
 //! lyrics api routes aligned with upstream flask behavior
 
-use actix_web::{post, web, HttpResponse, Responder};
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{get, post, put, web, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
-use std::fs;
 use std::path::Path;
+use utoipa::{IntoParams, OpenApi, ToSchema};
 
+use crate::api::identity::{Authorized, EditTags};
+use crate::api::settings::resolve_root_dirs;
+use crate::config::UserConfig;
 use crate::core::lyrics::LyricsLib;
+use crate::db::tables::TrackTable;
+use crate::models::Track;
 use crate::stores::TrackStore;
 
-#[derive(Debug, Deserialize)]
+/// largest shift accepted in one request
+const MAX_SHIFT_MS: i64 = 10 * 60 * 1000;
+
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct SendLyricsBody {
     pub trackhash: String,
     pub filepath: String,
@@ -32,13 +41,8 @@ fn resolve_lyrics(body: &SendLyricsBody) -> Option<LyricsResponse> {
         }
     }
 
-    // 1) .lrc / .rlrc
-    if let Some(lyrics) = get_lyrics_file(filepath) {
-        return Some(build_payload(lyrics, copyright));
-    }
-
-    // 2) tags
-    if let Some(lyrics) = get_lyrics_from_tags(trackhash) {
+    // 1) .lrc / .rlrc / .txt sidecars, then 2) lyrics embedded in the tags
+    if let Some(lyrics) = LyricsLib::local(Path::new(filepath)) {
         return Some(build_payload(lyrics, copyright));
     }
 
@@ -51,6 +55,9 @@ fn resolve_lyrics(body: &SendLyricsBody) -> Option<LyricsResponse> {
 }
 
 /// returns lyrics for a track (file, tags, duplicates)
+#[utoipa::path(
+    responses((status = 200, description = "Success"))
+)]
 #[post("")]
 pub async fn send_lyrics(body: web::Json<SendLyricsBody>) -> impl Responder {
     match resolve_lyrics(&body) {
@@ -60,43 +67,181 @@ pub async fn send_lyrics(body: web::Json<SendLyricsBody>) -> impl Responder {
 }
 
 /// check if lyrics exist for a track
+#[utoipa::path(
+    responses((status = 200, description = "Success"))
+)]
 #[post("/check")]
 pub async fn check_lyrics(body: web::Json<SendLyricsBody>) -> impl Responder {
     let exists = resolve_lyrics(&body).is_some();
     HttpResponse::Ok().json(serde_json::json!({ "exists": exists }))
 }
 
-fn get_lyrics_file(path: &str) -> Option<crate::core::lyrics::Lyrics> {
-    let track_path = Path::new(path);
-    let lrc_path = track_path.with_extension("lrc");
-    let rlrc_path = track_path.with_extension("rlrc");
+/// returns the lyrics of a track as a plain lrc or text file
+///
+/// meant for clients that read lyrics files directly instead of the json payload
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 404, description = "Not found")
+    )
+)]
+#[get("/file/{trackhash}")]
+pub async fn send_lyrics_file(path: web::Path<String>) -> impl Responder {
+    let Some(track) = TrackStore::get().get_by_hash(&path.into_inner()) else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Track not found" }));
+    };
 
-    if lrc_path.exists() {
-        if let Ok(content) = fs::read_to_string(&lrc_path) {
-            return Some(LyricsLib::parse_lrc(&content));
-        }
+    let track_path = Path::new(&track.filepath);
+    let Some(content) = LyricsLib::local_text(track_path) else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "No lyrics found" }));
+    };
+
+    let (extension, content_type) = if LyricsLib::is_lrc_format(&content) {
+        ("lrc", "text/x-lrc; charset=utf-8")
+    } else {
+        ("txt", "text/plain; charset=utf-8")
+    };
+    let stem = track_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| track.trackhash.clone());
+
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Inline,
+            parameters: vec![DispositionParam::Filename(format!(
+                "{}.{}",
+                stem, extension
+            ))],
+        })
+        .body(content)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SaveLyricsBody {
+    pub lyrics: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ShiftLyricsQuery {
+    pub ms: i64,
+}
+
+/// saves corrected lrc content to the sidecar file next to the track
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[put("/{trackhash}")]
+pub async fn save_lyrics(
+    _user: Authorized<EditTags>,
+    path: web::Path<String>,
+    body: web::Json<SaveLyricsBody>,
+) -> impl Responder {
+    let Some(track) = TrackStore::get().get_by_hash(&path.into_inner()) else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Track not found" }));
+    };
+    if body.lyrics.trim().is_empty() {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({ "error": "No lyrics provided" }));
     }
 
-    if rlrc_path.exists() {
-        if let Ok(content) = fs::read_to_string(&rlrc_path) {
-            return Some(LyricsLib::parse_lrc(&content));
-        }
+    write_sidecar(&track, &body.lyrics).await
+}
+
+/// moves every timestamp of a track's lyrics by `ms` and saves them to the sidecar
+#[utoipa::path(
+    params(ShiftLyricsQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[post("/{trackhash}/shift")]
+pub async fn shift_lyrics(
+    _user: Authorized<EditTags>,
+    path: web::Path<String>,
+    query: web::Query<ShiftLyricsQuery>,
+) -> impl Responder {
+    let Some(track) = TrackStore::get().get_by_hash(&path.into_inner()) else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Track not found" }));
+    };
+    if query.ms.abs() > MAX_SHIFT_MS {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({ "error": "Shift must be within 10 minutes" }));
     }
 
-    None
+    // a sidecar keeps its tags as written, embedded lyrics get written out on first shift
+    let track_path = Path::new(&track.filepath);
+    let content = LyricsLib::sidecar_path(track_path)
+        .and_then(|p| std::fs::read_to_string(p).ok())
+        .filter(|c| LyricsLib::is_lrc_format(c))
+        .or_else(|| {
+            LyricsLib::from_embedded(track_path)
+                .filter(|l| l.is_synced)
+                .map(|l| LyricsLib::to_lrc(&l))
+        });
+    let Some(content) = content else {
+        return HttpResponse::NotFound()
+            .json(serde_json::json!({ "error": "No synced lyrics to shift" }));
+    };
+
+    write_sidecar(&track, &LyricsLib::shift_lrc(&content, query.ms)).await
 }
 
-fn get_lyrics_from_tags(trackhash: &str) -> Option<crate::core::lyrics::Lyrics> {
-    let track = TrackStore::get().get_by_hash(trackhash)?;
-    if let Some(lyrics_val) = track.extra.get("lyrics") {
-        if let Some(text) = lyrics_val.as_str() {
-            if LyricsLib::is_lrc_format(text) {
-                return Some(LyricsLib::parse_lrc(text));
-            }
-            return Some(LyricsLib::parse_plain(text));
-        }
+async fn write_sidecar(track: &Track, content: &str) -> HttpResponse {
+    if !inside_root_dirs(Path::new(&track.filepath)) {
+        return HttpResponse::Forbidden()
+            .json(serde_json::json!({ "error": "Track is outside the root directories" }));
+    }
+    if let Err(e) = LyricsLib::save_sidecar(Path::new(&track.filepath), content) {
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": format!("Failed to save lyrics: {}", e)
+        }));
+    }
+    mark_has_lyrics(&track.trackhash).await;
+
+    let lyrics = LyricsLib::parse_auto(content);
+    HttpResponse::Ok().json(build_payload(
+        lyrics,
+        track.copyright.clone().unwrap_or_default(),
+    ))
+}
+
+/// whether a track's folder resolves inside one of the configured root directories
+fn inside_root_dirs(track_path: &Path) -> bool {
+    let Some(folder) = track_path
+        .parent()
+        .and_then(|p| std::fs::canonicalize(p).ok())
+    else {
+        return false;
+    };
+    let roots = UserConfig::load()
+        .map(|config| resolve_root_dirs(&config.root_dirs))
+        .unwrap_or_default();
+    roots
+        .iter()
+        .filter_map(|root| std::fs::canonicalize(root).ok())
+        .any(|root| folder.starts_with(root))
+}
+
+/// flags a track whose lyrics were just saved as a sidecar
+pub(crate) async fn mark_has_lyrics(trackhash: &str) {
+    TrackStore::get().set_has_lyrics(trackhash, true);
+    if let Err(e) = TrackTable::set_has_lyrics(trackhash, true).await {
+        tracing::warn!("failed to save lyrics flag for {}: {}", trackhash, e);
     }
-    None
 }
 
 fn get_lyrics_from_duplicates(
@@ -138,7 +283,16 @@ fn build_payload(lyrics: crate::core::lyrics::Lyrics, copyright: String) -> Lyri
     }
 }
 
+/// OpenAPI description of the lyrics routes
+#[derive(OpenApi)]
+#[openapi(paths(send_lyrics, check_lyrics, send_lyrics_file, save_lyrics, shift_lyrics,))]
+pub struct ApiDoc;
+
 /// configure lyrics routes
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(send_lyrics).service(check_lyrics);
+    cfg.service(send_lyrics)
+        .service(check_lyrics)
+        .service(send_lyrics_file)
+        .service(save_lyrics)
+        .service(shift_lyrics);
 }
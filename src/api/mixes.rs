@@ -1,353 +1,213 @@
 //! Mixes API routes
-
-use actix_web::{get, web, HttpResponse, Responder};
-use serde::{Deserialize, Serialize};
-
-use crate::core::recipes::Recipes;
-
-/// Mix response
-#[derive(Debug, Serialize)]
-pub struct MixResponse {
-    pub id: String,
-    pub name: String,
-    pub description: String,
-    pub track_count: usize,
-    pub image: Option<String>,
+//!
+//! stored artist radios, daily mixes and track mixes of the signed in user,
+//! the mixes plugin routes stay for upstream clients.
+
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use utoipa::{IntoParams, OpenApi};
+
+use crate::api::identity::CurrentUser;
+use crate::api::plugins_mixes::{
+    seconds_to_time_string, serialize_mix_compact, serialize_track_for_mix,
+};
+use crate::core::recipes::{Recipes, ARTIST_MIX_PREFIX, DAILY_MIX_PREFIX};
+use crate::db::tables::MixTable;
+use crate::models::Mix;
+use crate::stores::TrackStore;
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct MixListQuery {
+    /// artist, daily or track, every kind when missing
+    pub kind: Option<String>,
+    /// only mixes the user saved
+    #[serde(default)]
+    pub saved: bool,
+    #[serde(default)]
+    pub start: usize,
+    #[serde(default = "default_list_limit")]
+    pub limit: usize,
 }
 
-/// Mix track response
-#[derive(Debug, Serialize)]
-pub struct MixTrackResponse {
-    pub trackhash: String,
-    pub title: String,
-    pub artist: String,
-    pub album: String,
-    pub albumhash: String,
-    pub duration: i32,
-    pub image: String,
+fn default_list_limit() -> usize {
+    20
 }
 
-/// Get available mixes
-#[get("")]
-pub async fn get_mixes() -> impl Responder {
-    let mixes = Recipes::get_homepage_mixes().await;
-
-    let response: Vec<MixResponse> = mixes
-        .into_iter()
-        .map(|m| MixResponse {
-            id: m.id,
-            name: m.name,
-            description: m.description,
-            track_count: m.tracks.len(),
-            image: m.image,
-        })
-        .collect();
-
-    HttpResponse::Ok().json(response)
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct MixTracksQuery {
+    #[serde(default)]
+    pub start: usize,
+    #[serde(default = "default_tracks_limit")]
+    pub limit: usize,
 }
 
-/// Get recently played
-#[get("/recently-played")]
-pub async fn get_recently_played(query: web::Query<LimitQuery>) -> impl Responder {
-    let limit = query.limit.unwrap_or(20);
-    let tracks = Recipes::recently_played(limit).await;
-
-    let response: Vec<MixTrackResponse> = tracks
-        .into_iter()
-        .map(|t| {
-            let artist = t.artist();
-            MixTrackResponse {
-                trackhash: t.trackhash,
-                title: t.title,
-                artist,
-                album: t.album,
-                albumhash: t.albumhash,
-                duration: t.duration,
-                image: t.image,
-            }
-        })
-        .collect();
-
-    HttpResponse::Ok().json(serde_json::json!({
-        "tracks": response,
-        "count": response.len()
-    }))
+fn default_tracks_limit() -> usize {
+    50
 }
 
-/// Get recently added
-#[get("/recently-added")]
-pub async fn get_recently_added(query: web::Query<LimitQuery>) -> impl Responder {
-    let limit = query.limit.unwrap_or(20);
-    let tracks = Recipes::recently_added(limit);
+fn server_error(e: anyhow::Error) -> HttpResponse {
+    tracing::error!("Mix query failed: {}", e);
+    HttpResponse::InternalServerError().json(json!({"error": "Failed! An error occured"}))
+}
 
-    let response: Vec<MixTrackResponse> = tracks
-        .into_iter()
-        .map(|t| {
-            let artist = t.artist();
-            MixTrackResponse {
-                trackhash: t.trackhash,
-                title: t.title,
-                artist,
-                album: t.album,
-                albumhash: t.albumhash,
-                duration: t.duration,
-                image: t.image,
-            }
-        })
-        .collect();
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(json!({"error": "Mix not found"}))
+}
 
-    HttpResponse::Ok().json(serde_json::json!({
-        "tracks": response,
-        "count": response.len()
-    }))
+/// Mix ID prefix of a mix kind
+fn kind_prefix(kind: &str) -> Option<&'static str> {
+    match kind {
+        "artist" => Some(ARTIST_MIX_PREFIX),
+        "daily" => Some(DAILY_MIX_PREFIX),
+        "track" => Some("t"),
+        _ => None,
+    }
 }
 
-/// Get top streamed
-#[get("/top-streamed")]
-pub async fn get_top_streamed(query: web::Query<TopStreamedQuery>) -> impl Responder {
-    let days = query.days.unwrap_or(30);
-    let limit = query.limit.unwrap_or(20);
-    let tracks = Recipes::top_streamed(days, limit).await;
+/// Compact mix with its collage images
+fn serialize_mix_card(mix: &Mix) -> Value {
+    let mut value = serialize_mix_compact(mix, true);
+    value["images"] = json!(Recipes::mix_images(mix));
+    value["trackcount"] = json!(mix.trackhashes.len());
+    value
+}
 
-    let response: Vec<MixTrackResponse> = tracks
+/// GET /mixes?kind&saved&start&limit
+#[utoipa::path(
+    params(MixListQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("")]
+pub async fn list_mixes(user: CurrentUser, query: web::Query<MixListQuery>) -> impl Responder {
+    let prefix = match query.kind.as_deref() {
+        Some(kind) => match kind_prefix(kind) {
+            Some(prefix) => Some(prefix),
+            None => return HttpResponse::BadRequest().json(json!({"error": "Invalid mix kind"})),
+        },
+        None => None,
+    };
+
+    let mixes = match MixTable::all(user.id).await {
+        Ok(mixes) => mixes,
+        Err(e) => return server_error(e),
+    };
+    let mixes: Vec<Mix> = mixes
         .into_iter()
-        .map(|t| {
-            let artist = t.artist();
-            MixTrackResponse {
-                trackhash: t.trackhash,
-                title: t.title,
-                artist,
-                album: t.album,
-                albumhash: t.albumhash,
-                duration: t.duration,
-                image: t.image,
-            }
-        })
+        .filter(|m| prefix.is_none_or(|p| m.mixid.starts_with(p)))
+        .filter(|m| !query.saved || m.saved)
         .collect();
 
-    HttpResponse::Ok().json(serde_json::json!({
-        "tracks": response,
-        "count": response.len()
-    }))
-}
-
-/// Get artist mix
-#[get("/artist/{artisthash}")]
-pub async fn get_artist_mix(
-    path: web::Path<String>,
-    query: web::Query<LimitQuery>,
-) -> impl Responder {
-    let artisthash = path.into_inner();
-    let limit = query.limit.unwrap_or(30);
-
-    match Recipes::artist_mix(&artisthash, limit) {
-        Some(mix) => {
-            let tracks: Vec<MixTrackResponse> = mix
-                .tracks
-                .into_iter()
-                .map(|t| {
-                    let artist = t.artist();
-                    MixTrackResponse {
-                        trackhash: t.trackhash,
-                        title: t.title,
-                        artist,
-                        album: t.album,
-                        albumhash: t.albumhash,
-                        duration: t.duration,
-                        image: t.image,
-                    }
-                })
-                .collect();
+    let total = mixes.len();
+    let items: Vec<Value> = mixes
+        .iter()
+        .skip(query.start)
+        .take(query.limit.clamp(1, 100))
+        .map(serialize_mix_card)
+        .collect();
 
-            HttpResponse::Ok().json(serde_json::json!({
-                "id": mix.id,
-                "name": mix.name,
-                "description": mix.description,
-                "tracks": tracks,
-                "image": mix.image
-            }))
-        }
-        None => HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Artist not found or no tracks"
-        })),
-    }
+    HttpResponse::Ok().json(json!({"mixes": items, "total": total}))
 }
 
-/// Get "because you listened to" mix
-#[get("/because/{artisthash}")]
-pub async fn get_because_mix(
+/// GET /mixes/<mixid>?start&limit - a mix with a page of its tracks
+#[utoipa::path(
+    params(("mixid" = String, Path, description = "Mix ID"), MixTracksQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/{mixid}")]
+pub async fn get_mix_tracks(
+    user: CurrentUser,
     path: web::Path<String>,
-    query: web::Query<LimitQuery>,
+    query: web::Query<MixTracksQuery>,
 ) -> impl Responder {
-    let artisthash = path.into_inner();
-    let limit = query.limit.unwrap_or(30);
+    let mix = match Recipes::resolve_mix(&path, user.id).await {
+        Ok(Some(mix)) => mix,
+        Ok(None) => return not_found(),
+        Err(e) => return server_error(e),
+    };
+
+    // tracks that left the library are skipped
+    let tracks = TrackStore::get().get_by_hashes(&mix.trackhashes);
+    let duration: i64 = tracks.iter().map(|t| t.duration as i64).sum();
+    let page: Vec<Value> = tracks
+        .iter()
+        .skip(query.start)
+        .take(query.limit.clamp(1, 500))
+        .map(|t| serialize_track_for_mix(t, user.id))
+        .collect();
 
-    match Recipes::because_you_listened_to(&artisthash, limit).await {
-        Some(mix) => {
-            let tracks: Vec<MixTrackResponse> = mix
-                .tracks
-                .into_iter()
-                .map(|t| {
-                    let artist = t.artist();
-                    MixTrackResponse {
-                        trackhash: t.trackhash,
-                        title: t.title,
-                        artist,
-                        album: t.album,
-                        albumhash: t.albumhash,
-                        duration: t.duration,
-                        image: t.image,
-                    }
-                })
-                .collect();
+    let mut value = serialize_mix_card(&mix);
+    value["duration"] = json!(seconds_to_time_string(duration));
+    value["tracks"] = Value::Array(page);
+    value["total"] = json!(tracks.len());
 
-            HttpResponse::Ok().json(serde_json::json!({
-                "id": mix.id,
-                "name": mix.name,
-                "description": mix.description,
-                "tracks": tracks,
-                "image": mix.image
-            }))
-        }
-        None => HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Artist not found or no similar tracks"
-        })),
-    }
+    HttpResponse::Ok().json(value)
 }
 
-/// Get genre mix
-#[get("/genre/{genre}")]
-pub async fn get_genre_mix(
-    path: web::Path<String>,
-    query: web::Query<LimitQuery>,
-) -> impl Responder {
-    let genre = path.into_inner();
-    let limit = query.limit.unwrap_or(30);
-
-    match Recipes::genre_mix(&genre, limit) {
-        Some(mix) => {
-            let tracks: Vec<MixTrackResponse> = mix
-                .tracks
-                .into_iter()
-                .map(|t| {
-                    let artist = t.artist();
-                    MixTrackResponse {
-                        trackhash: t.trackhash,
-                        title: t.title,
-                        artist,
-                        album: t.album,
-                        albumhash: t.albumhash,
-                        duration: t.duration,
-                        image: t.image,
-                    }
-                })
-                .collect();
-
-            HttpResponse::Ok().json(serde_json::json!({
-                "id": mix.id,
-                "name": mix.name,
-                "description": mix.description,
-                "tracks": tracks,
-                "image": mix.image
-            }))
-        }
-        None => HttpResponse::NotFound().json(serde_json::json!({
-            "error": "No tracks found for this genre"
-        })),
+/// POST /mixes/<mixid>/regenerate - give a mix fresh tracks from its source
+#[utoipa::path(
+    params(("mixid" = String, Path, description = "Mix ID")),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Mix can not be regenerated")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[post("/{mixid}/regenerate")]
+pub async fn regenerate_mix(user: CurrentUser, path: web::Path<String>) -> impl Responder {
+    let mix = match Recipes::resolve_mix(&path, user.id).await {
+        Ok(Some(mix)) => mix,
+        Ok(None) => return not_found(),
+        Err(e) => return server_error(e),
+    };
+
+    match Recipes::regenerate_mix(&mix, user.id).await {
+        Ok(Some(mix)) => HttpResponse::Ok().json(serialize_mix_card(&mix)),
+        Ok(None) => HttpResponse::Conflict()
+            .json(json!({"error": "Not enough tracks are left to regenerate this mix"})),
+        Err(e) => server_error(e),
     }
 }
 
-/// Get decade mix
-#[get("/decade/{decade}")]
-pub async fn get_decade_mix(path: web::Path<i32>, query: web::Query<LimitQuery>) -> impl Responder {
-    let decade = path.into_inner();
-    let limit = query.limit.unwrap_or(30);
-
-    match Recipes::decade_mix(decade, limit) {
-        Some(mix) => {
-            let tracks: Vec<MixTrackResponse> = mix
-                .tracks
-                .into_iter()
-                .map(|t| {
-                    let artist = t.artist();
-                    MixTrackResponse {
-                        trackhash: t.trackhash,
-                        title: t.title,
-                        artist,
-                        album: t.album,
-                        albumhash: t.albumhash,
-                        duration: t.duration,
-                        image: t.image,
-                    }
-                })
-                .collect();
-
-            HttpResponse::Ok().json(serde_json::json!({
-                "id": mix.id,
-                "name": mix.name,
-                "description": mix.description,
-                "tracks": tracks,
-                "image": mix.image
-            }))
-        }
-        None => HttpResponse::NotFound().json(serde_json::json!({
-            "error": "No tracks found for this decade"
-        })),
+/// DELETE /mixes/<mixid>
+#[utoipa::path(
+    params(("mixid" = String, Path, description = "Mix ID")),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[delete("/{mixid}")]
+pub async fn delete_mix(user: CurrentUser, path: web::Path<String>) -> impl Responder {
+    match MixTable::delete(&path, user.id).await {
+        Ok(true) => HttpResponse::Ok().json(json!({"msg": "Mix deleted"})),
+        Ok(false) => not_found(),
+        Err(e) => server_error(e),
     }
 }
 
-/// Get random mix
-#[get("/random")]
-pub async fn get_random_mix(query: web::Query<LimitQuery>) -> impl Responder {
-    let limit = query.limit.unwrap_or(30);
-    let mix = Recipes::random_mix(limit);
-
-    let tracks: Vec<MixTrackResponse> = mix
-        .tracks
-        .into_iter()
-        .map(|t| {
-            let artist = t.artist();
-            MixTrackResponse {
-                trackhash: t.trackhash,
-                title: t.title,
-                artist,
-                album: t.album,
-                albumhash: t.albumhash,
-                duration: t.duration,
-                image: t.image,
-            }
-        })
-        .collect();
-
-    HttpResponse::Ok().json(serde_json::json!({
-        "id": mix.id,
-        "name": mix.name,
-        "description": mix.description,
-        "tracks": tracks,
-        "image": mix.image
-    }))
-}
-
-#[derive(Debug, Deserialize)]
-pub struct LimitQuery {
-    pub limit: Option<usize>,
-}
-
-#[derive(Debug, Deserialize)]
-pub struct TopStreamedQuery {
-    pub days: Option<i64>,
-    pub limit: Option<usize>,
-}
+/// OpenAPI description of the mixes routes
+#[derive(OpenApi)]
+#[openapi(paths(list_mixes, get_mix_tracks, regenerate_mix, delete_mix))]
+pub struct ApiDoc;
 
-/// Configure mixes routes
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(get_mixes)
-        .service(get_recently_played)
-        .service(get_recently_added)
-        .service(get_top_streamed)
-        .service(get_artist_mix)
-        .service(get_because_mix)
-        .service(get_genre_mix)
-        .service(get_decade_mix)
-        .service(get_random_mix);
+    cfg.service(list_mixes)
+        .service(get_mix_tracks)
+        .service(regenerate_mix)
+        .service(delete_mix);
 }
@@ -1,36 +1,65 @@
 //! REST API routes for SwingMusic
 
+pub mod about;
+pub mod admin;
 pub mod album;
 pub mod artist;
 pub mod auth;
 pub mod backup;
 pub mod collections;
 pub mod colors;
+pub mod dlna;
 pub mod favorites;
 pub mod folder;
+pub mod genres;
 pub mod getall;
 pub mod home;
+pub mod identity;
 pub mod imgserver;
+pub mod library;
 pub mod logger;
 pub mod lyrics;
+pub mod mixes;
+pub mod openapi;
 pub mod playlist;
 pub mod plugins;
 pub mod plugins_mixes;
+pub mod plugins_musicbrainz;
+pub mod podcasts;
+pub mod queue;
+pub mod radio;
+pub mod resolve;
 pub mod scrobble;
 pub mod search;
 pub mod settings;
 pub mod stream;
+pub mod sync;
 pub mod track;
 
+use actix_web::middleware::from_fn;
 use actix_web::web;
 
 /// Configure all API routes
+///
+/// routes serving the library answer 503 until the track store is loaded
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg
+        // Server status routes
+        .service(web::scope("/about").configure(about::configure))
+        // Admin maintenance routes
+        .service(web::scope("/admin").configure(admin::configure))
         // Album routes
-        .service(web::scope("/album").configure(album::configure))
+        .service(
+            web::scope("/album")
+                .wrap(from_fn(about::require_library))
+                .configure(album::configure),
+        )
         // Artist routes
-        .service(web::scope("/artist").configure(artist::configure))
+        .service(
+            web::scope("/artist")
+                .wrap(from_fn(about::require_library))
+                .configure(artist::configure),
+        )
         // Auth routes
         .service(web::scope("/auth").configure(auth::configure))
         // Backup routes
@@ -39,40 +68,106 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
         .service(web::scope("/collections").configure(collections::configure))
         // Colors routes
         .service(web::scope("/colors").configure(colors::configure))
+        // DLNA/UPnP media server routes
+        .service(web::scope("/dlna").configure(dlna::configure))
         // Favorites routes
-        .service(web::scope("/favorites").configure(favorites::configure))
+        .service(
+            web::scope("/favorites")
+                .wrap(from_fn(about::require_library))
+                .configure(favorites::configure),
+        )
         // Folder routes
-        .service(web::scope("/folder").configure(folder::configure))
+        .service(
+            web::scope("/folder")
+                .wrap(from_fn(about::require_library))
+                .configure(folder::configure),
+        )
+        // Genre routes
+        .service(
+            web::scope("/genres")
+                .wrap(from_fn(about::require_library))
+                .configure(genres::configure),
+        )
         // GetAll routes (for getting all tracks/albums/artists)
-        .service(web::scope("/getall").configure(getall::configure))
+        .service(
+            web::scope("/getall")
+                .wrap(from_fn(about::require_library))
+                .configure(getall::configure),
+        )
         // Home routes
-        .service(web::scope("/home").configure(home::configure))
+        .service(
+            web::scope("/home")
+                .wrap(from_fn(about::require_library))
+                .configure(home::configure),
+        )
         // Home routes (upstream prefix)
-        .service(web::scope("/nothome").configure(home::configure_upstream))
+        .service(
+            web::scope("/nothome")
+                .wrap(from_fn(about::require_library))
+                .configure(home::configure_upstream),
+        )
         // Image server routes
         .service(web::scope("/img").configure(imgserver::configure))
+        // Library inbox routes
+        .service(web::scope("/library").configure(library::configure))
         // Lyrics routes
         .service(web::scope("/lyrics").configure(lyrics::configure))
+        // Mixes routes
+        .service(web::scope("/mixes").configure(mixes::configure))
         // Playlist routes
         .service(web::scope("/playlist").configure(playlist::configure))
         // Playlist routes (upstream prefix)
         .service(web::scope("/playlists").configure(playlist::configure_upstream))
+        // Mixes plugin routes (registered before /plugins which would shadow them)
+        .service(web::scope("/plugins/mixes").configure(plugins_mixes::configure))
+        // MusicBrainz plugin routes
+        .service(web::scope("/plugins/musicbrainz").configure(plugins_musicbrainz::configure))
         // Plugin routes
         .service(web::scope("/plugins").configure(plugins::configure))
-        // Mixes plugin routes
-        .service(web::scope("/plugins/mixes").configure(plugins_mixes::configure))
+        // Podcast routes
+        .service(web::scope("/podcasts").configure(podcasts::configure))
+        // Queue continuation routes
+        .service(
+            web::scope("/queue")
+                .wrap(from_fn(about::require_library))
+                .configure(queue::configure),
+        )
+        // Internet radio routes
+        .service(web::scope("/radio").configure(radio::configure))
+        // Batch resolve routes
+        .service(
+            web::scope("/resolve")
+                .wrap(from_fn(about::require_library))
+                .configure(resolve::configure),
+        )
         // File routes (upstream legacy stream)
         .service(web::scope("/file").configure(stream::configure_file))
         // Search routes
-        .service(web::scope("/search").configure(search::configure))
+        .service(
+            web::scope("/search")
+                .wrap(from_fn(about::require_library))
+                .configure(search::configure),
+        )
         // Settings routes
         .service(web::scope("/settings").configure(settings::configure))
         // Settings routes (upstream prefix)
         .service(web::scope("/notsettings").configure(settings::configure_upstream))
         // Stream routes
         .service(web::scope("/stream").configure(stream::configure))
+        // Offline sync routes
+        .service(
+            web::scope("/sync")
+                .wrap(from_fn(about::require_library))
+                .configure(sync::configure),
+        )
         // Track routes
-        .service(web::scope("/track").configure(track::configure))
+        .service(
+            web::scope("/track")
+                .wrap(from_fn(about::require_library))
+                .configure(track::configure),
+        )
         // Logger/stats routes
-        .service(web::scope("/logger").configure(logger::configure));
+        .service(web::scope("/logger").configure(logger::configure))
+        // OpenAPI document and Swagger UI
+        .configure(openapi::configure);
 }
@@ -0,0 +1,132 @@
+//! OpenAPI document and Swagger UI
+//!
+//! every route module describes its handlers in an `ApiDoc`, they are nested
+//! here under the scope prefixes `api::configure` mounts them at. the upstream
+//! `/nothome` and `/playlists` aliases serve the same handlers as `/home` and
+//! `/playlist` and are left out so operation ids stay unique.
+
+use actix_web::web;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::api::{
+    about, admin, album, artist, auth, backup, collections, colors, dlna, favorites, folder,
+    genres, getall, home, imgserver, library, logger, lyrics, mixes, playlist, plugins,
+    plugins_mixes, plugins_musicbrainz, podcasts, queue, radio, resolve, search, settings, stream,
+    sync, track,
+};
+
+/// Where the generated document is served
+pub const SPEC_PATH: &str = "/openapi.json";
+
+/// Registers the bearer token and access token cookie schemes the routes
+/// refer to
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "cookie",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new("access_token_cookie"))),
+        );
+    }
+}
+
+/// The whole REST API
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "SwingMusic API"),
+    modifiers(&SecuritySchemes),
+    nest(
+        (path = "/about", api = about::ApiDoc, tags = ["about"]),
+        (path = "/admin", api = admin::ApiDoc, tags = ["admin"]),
+        (path = "/album", api = album::ApiDoc, tags = ["album"]),
+        (path = "/artist", api = artist::ApiDoc, tags = ["artist"]),
+        (path = "/auth", api = auth::ApiDoc, tags = ["auth"]),
+        (path = "/backup", api = backup::ApiDoc, tags = ["backup"]),
+        (path = "/collections", api = collections::ApiDoc, tags = ["collections"]),
+        (path = "/colors", api = colors::ApiDoc, tags = ["colors"]),
+        (path = "/dlna", api = dlna::ApiDoc, tags = ["dlna"]),
+        (path = "/favorites", api = favorites::ApiDoc, tags = ["favorites"]),
+        (path = "/folder", api = folder::ApiDoc, tags = ["folder"]),
+        (path = "/genres", api = genres::ApiDoc, tags = ["genres"]),
+        (path = "/getall", api = getall::ApiDoc, tags = ["getall"]),
+        (path = "/home", api = home::ApiDoc, tags = ["home"]),
+        (path = "/img", api = imgserver::ApiDoc, tags = ["images"]),
+        (path = "/library", api = library::ApiDoc, tags = ["library"]),
+        (path = "/lyrics", api = lyrics::ApiDoc, tags = ["lyrics"]),
+        (path = "/mixes", api = mixes::ApiDoc, tags = ["mixes"]),
+        (path = "/playlist", api = playlist::ApiDoc, tags = ["playlist"]),
+        (path = "/plugins/mixes", api = plugins_mixes::ApiDoc, tags = ["mixes"]),
+        (path = "/plugins/musicbrainz", api = plugins_musicbrainz::ApiDoc, tags = ["musicbrainz"]),
+        (path = "/plugins", api = plugins::ApiDoc, tags = ["plugins"]),
+        (path = "/podcasts", api = podcasts::ApiDoc, tags = ["podcasts"]),
+        (path = "/queue", api = queue::ApiDoc, tags = ["queue"]),
+        (path = "/radio", api = radio::ApiDoc, tags = ["radio"]),
+        (path = "/resolve", api = resolve::ApiDoc, tags = ["resolve"]),
+        (path = "/file", api = stream::FileApiDoc, tags = ["stream"]),
+        (path = "/search", api = search::ApiDoc, tags = ["search"]),
+        (path = "/settings", api = settings::ApiDoc, tags = ["settings"]),
+        (path = "/notsettings", api = settings::UpstreamApiDoc, tags = ["settings"]),
+        (path = "/stream", api = stream::ApiDoc, tags = ["stream"]),
+        (path = "/sync", api = sync::ApiDoc, tags = ["sync"]),
+        (path = "/track", api = track::ApiDoc, tags = ["track"]),
+        (path = "/logger", api = logger::ApiDoc, tags = ["logger"]),
+    )
+)]
+pub struct ApiDoc;
+
+/// Serve the document at `/openapi.json` and Swagger UI at `/docs`
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(SwaggerUi::new("/docs/{_:.*}").url(SPEC_PATH, ApiDoc::openapi()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_operation_ids_are_unique() {
+        let doc = ApiDoc::openapi();
+        let mut seen = HashSet::new();
+        let mut count = 0;
+
+        for (path, item) in &doc.paths.paths {
+            let operations = [
+                &item.get,
+                &item.put,
+                &item.post,
+                &item.delete,
+                &item.options,
+                &item.head,
+                &item.patch,
+                &item.trace,
+            ];
+            for operation in operations.into_iter().flatten() {
+                count += 1;
+                let id = operation.operation_id.clone().unwrap_or_default();
+                assert!(
+                    seen.insert(id.clone()),
+                    "duplicate operation id {} at {}",
+                    id,
+                    path
+                );
+            }
+        }
+
+        assert!(count > 150, "only {} operations documented", count);
+        assert!(doc.paths.paths.contains_key("/album/{albumhash}"));
+    }
+}
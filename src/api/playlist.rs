@@ -1,57 +1,115 @@
 //! Playlist API routes (aligned with upstream Flask `/playlists` endpoints)
 
 use actix_multipart::Multipart;
-use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+use actix_web::http::header::ContentDisposition;
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
 use futures::StreamExt;
 use image::imageops::FilterType;
 use image::{GenericImageView, ImageFormat};
 use serde::Deserialize;
+use std::collections::HashSet;
 use std::fs;
 use std::io::Write;
+use utoipa::{IntoParams, OpenApi, ToSchema};
 
+use crate::api::identity::{Authorized, CurrentUser, Download, ManagePlaylists};
+use crate::api::imgserver::{insert_image_hints, CardImage};
 use crate::config::Paths;
-use crate::core::PlaylistLib;
-use crate::db::tables::PlaylistTable;
-use crate::models::Playlist;
-use crate::stores::{AlbumStore, TrackStore};
+use crate::core::archive;
+use crate::core::colorlib::ColorLib;
+use crate::core::images::{album_thumbnail, ThumbnailFormat};
+use crate::core::playlistlib::{delete_image_files, PlaylistCombine, PlaylistFormat};
+use crate::core::sorting::{CompoundSort, SortOrder, TrackSort};
+use crate::core::track_filter::TrackFilter;
+use crate::core::{PlaylistLib, SortLib};
+use crate::db::tables::{FavoriteTable, PlaylistTable, ScrobbleTable, UserTable};
+use crate::models::{FavoriteType, Playlist};
+use crate::stores::{AlbumStore, PlayStatsStore, TrackStore};
 use crate::utils::auth::generate_random_string;
 use crate::utils::dates::date_to_relative;
 
-#[derive(Debug, Deserialize)]
+/// Number of recent scrobbles scanned for the recently played playlist
+const RECENTLY_PLAYED_SCAN: i64 = 200;
+
+/// Most tracks a filter expression can add in one request
+const MAX_QUERY_TRACKS: usize = 10_000;
+
+/// Matching tracks returned with a preview
+const QUERY_PREVIEW_TRACKS: usize = 10;
+
+/// Id of the virtual playlist holding the user's favorite tracks
+pub const LIKED_PLAYLIST: &str = "liked";
+
+/// Whether an id names a playlist built on request instead of a stored one
+fn is_custom_playlist(id: &str) -> bool {
+    matches!(id, "recentlyadded" | "recentlyplayed" | LIKED_PLAYLIST)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct SendAllQuery {
     #[serde(default)]
     pub no_images: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreatePlaylistBody {
     pub name: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct SaveAsPlaylistBody {
     pub itemtype: String,
     pub playlist_name: String,
     pub itemhash: String,
     #[serde(default)]
-    pub sortoptions: serde_json::Value,
+    pub sortoptions: Option<FolderSortOptions>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct AddItemBody {
     #[serde(default = "default_itemtype")]
     pub itemtype: String,
     pub itemhash: String,
     #[serde(default)]
-    pub sortoptions: Option<serde_json::Value>,
+    pub sortoptions: Option<FolderSortOptions>,
+}
+
+/// How the tracks of a folder are ordered when it is added to a playlist
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct FolderSortOptions {
+    #[serde(default)]
+    #[schema(value_type = String, example = "title")]
+    pub tracksortby: TrackSort,
+    #[serde(default)]
+    pub tracksortreverse: bool,
 }
 
 fn default_itemtype() -> String {
     "tracks".to_string()
 }
 
-#[derive(Debug, Deserialize)]
+/// Tracks to add by filter expression, see `core::track_filter` for the syntax
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AddQueryBody {
+    #[schema(example = "genre:jazz year:1955-1965 -is:explicit")]
+    pub query: String,
+    /// only count the matches and return a sample of them
+    #[serde(default)]
+    pub preview: bool,
+    /// order the tracks are appended in
+    #[serde(default = "default_query_sort")]
+    #[schema(value_type = String, example = "albumartists,date,disc")]
+    pub sortby: CompoundSort<TrackSort>,
+    #[serde(default)]
+    pub reverse: bool,
+}
+
+fn default_query_sort() -> CompoundSort<TrackSort> {
+    CompoundSort::parse("albumartists,date,disc")
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct GetPlaylistQuery {
     #[serde(default)]
     pub limit: i64,
@@ -61,22 +119,74 @@ pub struct GetPlaylistQuery {
     pub start: usize,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ExportPlaylistQuery {
+    #[serde(default = "default_export_format")]
+    pub format: String,
+    /// `path` writes absolute file paths, `url` writes streaming urls
+    #[serde(default = "default_export_location")]
+    pub location: String,
+}
+
+fn default_export_format() -> String {
+    "m3u".to_string()
+}
+
+fn default_export_location() -> String {
+    "path".to_string()
+}
+
+/// Who besides the owner can see and edit a playlist, missing fields are kept
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SharingBody {
+    #[serde(default)]
+    pub shared: Option<bool>,
+    #[serde(default)]
+    pub collaborators: Option<Vec<i64>>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct RemoveTracksBody {
     pub tracks: Vec<RemoveTrackItem>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct RemoveTrackItem {
     pub trackhash: String,
     pub index: usize,
 }
 
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CombinePlaylistBody {
+    /// id of the playlist combined in, `liked`, `recentlyplayed` and
+    /// `recentlyadded` work too
+    #[schema(example = "12")]
+    pub source: String,
+    /// `merge`, `subtract` or `intersect`
+    #[schema(example = "subtract")]
+    pub operation: String,
+    /// only count the changes
+    #[serde(default)]
+    pub preview: bool,
+}
+
 /// GET /playlists
+#[utoipa::path(
+    params(SendAllQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("")]
-pub async fn send_all_playlists(query: web::Query<SendAllQuery>) -> impl Responder {
+pub async fn send_all_playlists(
+    user: CurrentUser,
+    query: web::Query<SendAllQuery>,
+) -> impl Responder {
     let _ = query.no_images;
-    let playlists = match PlaylistLib::get_all().await {
+    let playlists = match PlaylistTable::visible(user.id).await {
         Ok(p) => p,
         Err(_) => {
             return HttpResponse::InternalServerError().json(serde_json::json!({
@@ -90,15 +200,7 @@ pub async fn send_all_playlists(query: web::Query<SendAllQuery>) -> impl Respond
 
     let data: Vec<_> = playlists
         .into_iter()
-        .map(|mut p| {
-            p.init();
-            let images = if !p.has_image {
-                first_4_images(None, Some(&p.trackhashes))
-            } else {
-                Vec::new()
-            };
-            serialize_playlist(&p, &images)
-        })
+        .map(|p| serialize_playlist_card(p, user.id))
         .collect();
 
     HttpResponse::Ok().json(serde_json::json!({
@@ -107,9 +209,22 @@ pub async fn send_all_playlists(query: web::Query<SendAllQuery>) -> impl Respond
 }
 
 /// POST /playlists/new
+#[utoipa::path(
+    responses(
+        (status = 201, description = "Created"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 409, description = "Conflict"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[post("/new")]
-pub async fn create_playlist(body: web::Json<CreatePlaylistBody>) -> impl Responder {
-    let userid = 1;
+pub async fn create_playlist(
+    user: Authorized<ManagePlaylists>,
+    body: web::Json<CreatePlaylistBody>,
+) -> impl Responder {
+    let userid = user.id;
     match PlaylistTable::name_exists(&body.name, userid).await {
         Ok(true) => {
             return HttpResponse::Conflict().json(serde_json::json!({
@@ -124,14 +239,14 @@ pub async fn create_playlist(body: web::Json<CreatePlaylistBody>) -> impl Respon
         _ => {}
     }
 
-    let playlist = Playlist::new(body.name.clone(), Some(userid));
+    let mut playlist = Playlist::new(body.name.clone(), Some(userid));
+    playlist.is_editable = true;
     match PlaylistTable::insert(&playlist).await {
-        Ok(_) => match PlaylistLib::get_all()
-            .await
-            .ok()
-            .and_then(|mut list| list.pop())
-        {
-            Some(p) => HttpResponse::Created().json(serde_json::json!({ "playlist": p })),
+        Ok(id) => match PlaylistTable::get_by_id(id).await.ok().flatten() {
+            Some(mut p) => {
+                p.is_editable = true;
+                HttpResponse::Created().json(serde_json::json!({ "playlist": p }))
+            }
             None => HttpResponse::Created().json(serde_json::json!({ "playlist": playlist })),
         },
         Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
@@ -141,8 +256,21 @@ pub async fn create_playlist(body: web::Json<CreatePlaylistBody>) -> impl Respon
 }
 
 /// POST /playlists/<playlistid>/add
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Conflict"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[post("/{playlistid}/add")]
 pub async fn add_item_to_playlist(
+    user: Authorized<ManagePlaylists>,
     path: web::Path<String>,
     body: web::Json<AddItemBody>,
 ) -> impl Responder {
@@ -155,8 +283,16 @@ pub async fn add_item_to_playlist(
         }
     };
 
-    let trackhashes =
-        resolve_item_trackhashes(&body.itemtype, &body.itemhash, body.sortoptions.as_ref());
+    if !matches!(editable_playlist(playlist_id, user.id).await, Ok(Some(_))) {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Playlist not found" }));
+    }
+
+    let trackhashes = resolve_item_trackhashes(
+        &body.itemtype,
+        &body.itemhash,
+        body.sortoptions.as_ref(),
+        user.id,
+    );
 
     if body.itemtype == "tracks" {
         if trackhashes.len() == 1 {
@@ -182,9 +318,128 @@ pub async fn add_item_to_playlist(
     HttpResponse::Ok().json(serde_json::json!({ "msg": "Done" }))
 }
 
+/// POST /playlists/<playlistid>/add-query
+///
+/// Append every track matching a filter expression, tracks already in the
+/// playlist are skipped. a preview returns the counts and a sample instead
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[post("/{playlistid}/add-query")]
+pub async fn add_query_to_playlist(
+    user: Authorized<ManagePlaylists>,
+    path: web::Path<String>,
+    body: web::Json<AddQueryBody>,
+) -> impl Responder {
+    let Ok(playlist_id) = path.parse::<i64>() else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Invalid playlist id"
+        }));
+    };
+
+    if !matches!(editable_playlist(playlist_id, user.id).await, Ok(Some(_))) {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Playlist not found" }));
+    }
+
+    let filter = match TrackFilter::parse(&body.query) {
+        Ok(filter) if filter.is_empty() => {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({ "error": "Query is empty" }))
+        }
+        Ok(filter) => filter,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string() }))
+        }
+    };
+
+    let mut tracks = TrackStore::get().get_all();
+    PlayStatsStore::get().personalize_tracks(user.id, &mut tracks);
+    let mut tracks = filter.apply(tracks, user.id);
+    SortLib::sort_tracks_by(
+        &mut tracks,
+        &body.sortby,
+        SortOrder::from_reverse(body.reverse),
+    );
+
+    let existing: HashSet<String> = match PlaylistTable::get_trackhashes(playlist_id).await {
+        Ok(trackhashes) => trackhashes.into_iter().collect(),
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to read playlist"
+            }))
+        }
+    };
+    let new_tracks: Vec<String> = tracks
+        .iter()
+        .filter(|t| !existing.contains(&t.trackhash))
+        .map(|t| t.trackhash.clone())
+        .collect();
+
+    if body.preview {
+        let sample: Vec<_> = tracks
+            .iter()
+            .take(QUERY_PREVIEW_TRACKS)
+            .map(|t| serialize_track_for_playlist(t, user.id))
+            .collect();
+        return HttpResponse::Ok().json(serde_json::json!({
+            "count": tracks.len(),
+            "new": new_tracks.len(),
+            "duration": tracks.iter().map(|t| t.duration as i64).sum::<i64>(),
+            "max": MAX_QUERY_TRACKS,
+            "tracks": sample,
+        }));
+    }
+
+    if new_tracks.len() > MAX_QUERY_TRACKS {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!(
+                "Query matches {} new tracks, at most {} can be added at once",
+                new_tracks.len(),
+                MAX_QUERY_TRACKS
+            )
+        }));
+    }
+
+    if !new_tracks.is_empty()
+        && PlaylistTable::add_tracks(playlist_id, &new_tracks)
+            .await
+            .is_err()
+    {
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to add to playlist"
+        }));
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "msg": "Done",
+        "count": tracks.len(),
+        "added": new_tracks.len(),
+    }))
+}
+
 /// GET /playlists/<playlistid>
+#[utoipa::path(
+    params(GetPlaylistQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/{playlistid}")]
 pub async fn get_playlist(
+    user: CurrentUser,
     path: web::Path<String>,
     query: web::Query<GetPlaylistQuery>,
 ) -> impl Responder {
@@ -195,34 +450,48 @@ pub async fn get_playlist(
         if query.start != 0 {
             return HttpResponse::Ok().json(serde_json::json!({ "tracks": [] }));
         }
-        let (playlist, tracks) = build_custom_playlist(&playlistid);
+        let (playlist, tracks) = build_custom_playlist(&playlistid, user.id).await;
         let images = first_4_images(Some(&tracks), None);
         return HttpResponse::Ok().json(serde_json::json!({
             "info": serialize_playlist(&playlist, &images),
-            "tracks": tracks.iter().map(|t| serialize_track_for_playlist(t)).collect::<Vec<_>>(),
+            "tracks": tracks
+                .iter()
+                .map(|t| serialize_track_for_playlist(t, user.id))
+                .collect::<Vec<_>>(),
         }));
     }
 
-    let pid: i64 = match playlistid.parse() {
-        Ok(v) => v,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "msg": "Playlist not found"
-            }))
-        }
-    };
-
-    let mut playlist = match PlaylistTable::get_by_id(pid).await {
-        Ok(Some(p)) => p,
-        Ok(None) => {
-            return HttpResponse::NotFound().json(serde_json::json!({
-                "msg": "Playlist not found"
-            }))
+    let mut playlist = if playlistid == LIKED_PLAYLIST {
+        match liked_playlist(user.id).await {
+            Ok(p) => p,
+            Err(_) => {
+                return HttpResponse::InternalServerError().json(serde_json::json!({
+                    "msg": "Database error"
+                }))
+            }
         }
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "msg": "Database error"
-            }))
+    } else {
+        let pid: i64 = match playlistid.parse() {
+            Ok(v) => v,
+            Err(_) => {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "msg": "Playlist not found"
+                }))
+            }
+        };
+
+        match visible_playlist(pid, user.id).await {
+            Ok(Some(p)) => p,
+            Ok(None) => {
+                return HttpResponse::NotFound().json(serde_json::json!({
+                    "msg": "Playlist not found"
+                }))
+            }
+            Err(_) => {
+                return HttpResponse::InternalServerError().json(serde_json::json!({
+                    "msg": "Database error"
+                }))
+            }
         }
     };
 
@@ -248,6 +517,7 @@ pub async fn get_playlist(
     playlist.count = tracks.len() as i32;
     playlist.images = Vec::new();
     playlist.last_updated = date_to_relative(&playlist.last_updated);
+    playlist.is_editable = playlist.can_edit(user.id);
     playlist.init();
 
     let images = first_4_images(None, Some(&playlist.trackhashes));
@@ -257,7 +527,7 @@ pub async fn get_playlist(
     } else {
         tracks
             .iter()
-            .map(|t| serialize_track_for_playlist(t))
+            .map(|t| serialize_track_for_playlist(t, user.id))
             .collect()
     };
 
@@ -267,9 +537,175 @@ pub async fn get_playlist(
     }))
 }
 
+/// GET /playlists/<playlistid>/export
+///
+/// Render the playlist as an m3u or xspf file other players can import
+#[utoipa::path(
+    params(ExportPlaylistQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/{playlistid}/export")]
+pub async fn export_playlist(
+    user: CurrentUser,
+    path: web::Path<String>,
+    query: web::Query<ExportPlaylistQuery>,
+    req: HttpRequest,
+) -> impl Responder {
+    let playlistid = path.into_inner();
+
+    let Some(format) = PlaylistFormat::parse(&query.format) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "msg": "Unsupported format, use m3u or xspf"
+        }));
+    };
+    let use_urls = match query.location.as_str() {
+        "path" => false,
+        "url" => true,
+        _ => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "msg": "Unsupported location, use path or url"
+            }))
+        }
+    };
+
+    let (playlist, tracks) = if is_custom_playlist(&playlistid) {
+        build_custom_playlist(&playlistid, user.id).await
+    } else {
+        let pid: i64 = match playlistid.parse() {
+            Ok(v) => v,
+            Err(_) => {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "msg": "Playlist not found"
+                }))
+            }
+        };
+
+        match visible_playlist(pid, user.id).await {
+            Ok(Some(p)) => {
+                let tracks = TrackStore::get().get_by_hashes(&p.trackhashes);
+                (p, tracks)
+            }
+            Ok(None) => {
+                return HttpResponse::NotFound().json(serde_json::json!({
+                    "msg": "Playlist not found"
+                }))
+            }
+            Err(_) => {
+                return HttpResponse::InternalServerError().json(serde_json::json!({
+                    "msg": "Database error"
+                }))
+            }
+        }
+    };
+
+    // links use the address the client reached us on
+    let info = req.connection_info();
+    let base_url = format!("{}://{}", info.scheme(), info.host());
+
+    let body = PlaylistLib::export(&playlist, &tracks, format, |track| {
+        if use_urls {
+            format!("{}/stream/{}", base_url, track.trackhash)
+        } else if format == PlaylistFormat::Xspf {
+            // xspf locations are uris
+            reqwest::Url::from_file_path(&track.filepath)
+                .map(|url| url.to_string())
+                .unwrap_or_else(|_| track.filepath.clone())
+        } else {
+            track.filepath.clone()
+        }
+    });
+
+    let filename: String = playlist
+        .name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | '"' | ':' | '*' | '?' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ContentDisposition::attachment(format!(
+            "{}.{}",
+            filename.trim(),
+            format.extension()
+        )))
+        .body(body)
+}
+
+/// GET /playlists/<playlistid>/download
+///
+/// Zip of the playlist's original files, built while it is sent
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/{playlistid}/download")]
+pub async fn download_playlist(
+    user: Authorized<Download>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let playlistid = path.into_inner();
+
+    let (playlist, mut tracks) = match playlist_with_tracks(&playlistid, user.id).await {
+        Ok(Some(found)) => found,
+        Ok(None) => {
+            return HttpResponse::NotFound()
+                .json(serde_json::json!({ "error": "Playlist not found" }))
+        }
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to read playlist"
+            }))
+        }
+    };
+
+    tracks.retain(|t| std::path::Path::new(&t.filepath).is_file());
+    if tracks.is_empty() {
+        return HttpResponse::NotFound()
+            .json(serde_json::json!({ "error": "No files to download" }));
+    }
+
+    HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header(ContentDisposition::attachment(archive::archive_name(
+            &playlist.name,
+        )))
+        .streaming(archive::zip_stream(archive::playlist_entries(&tracks)))
+}
+
 /// PUT /playlists/<playlistid>/update
+#[utoipa::path(
+    request_body(content_type = "multipart/form-data", description = "Image file upload"),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[put("/{playlistid}/update")]
 pub async fn update_playlist_info(
+    user: Authorized<ManagePlaylists>,
     path: web::Path<String>,
     mut payload: Multipart,
 ) -> impl Responder {
@@ -281,7 +717,7 @@ pub async fn update_playlist_info(
         }
     };
 
-    let mut playlist = match PlaylistTable::get_by_id(playlistid).await {
+    let mut playlist = match owned_playlist(playlistid, user.id).await {
         Ok(Some(p)) => p,
         _ => {
             return HttpResponse::NotFound().json(serde_json::json!({
@@ -333,16 +769,20 @@ pub async fn update_playlist_info(
     }
 
     let mut has_gif = false;
+    let mut new_image: Option<String> = None;
     if let Some(bytes) = image_bytes {
         match save_playlist_image(
             playlistid,
             &bytes,
             image_content_type.as_deref().unwrap_or("image/webp"),
-            playlist.image.as_deref(),
-        ) {
-            Ok((filename, is_gif)) => {
+        )
+        .await
+        {
+            Ok((filename, is_gif, color)) => {
+                new_image = Some(filename.clone());
                 has_gif = is_gif;
                 playlist.image = Some(filename);
+                set_image_color(&mut playlist, color);
                 playlist.settings.has_gif = is_gif;
                 playlist.has_image = true;
                 playlist.thumb = playlist
@@ -364,12 +804,21 @@ pub async fn update_playlist_info(
     }
 
     if PlaylistTable::update(&playlist).await.is_err() {
+        if let Some(img) = &new_image {
+            let _ = PlaylistLib::discard_image(playlistid, img).await;
+        }
         return HttpResponse::InternalServerError().json(serde_json::json!({
             "error": "Failed to update playlist"
         }));
     }
 
+    // the previous image is only released once the new one is committed
+    if let Some(img) = &new_image {
+        let _ = PlaylistLib::release_images(playlistid, Some(img)).await;
+    }
+
     playlist.last_updated = date_to_relative(&playlist.last_updated);
+    playlist.is_editable = true;
     playlist.init();
     playlist.clear_trackhashes();
     let images = if playlist.has_image {
@@ -382,8 +831,22 @@ pub async fn update_playlist_info(
 }
 
 /// POST /playlists/<playlistid>/pin_unpin
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[post("/{playlistid}/pin_unpin")]
-pub async fn pin_unpin_playlist(path: web::Path<String>) -> impl Responder {
+pub async fn pin_unpin_playlist(
+    user: Authorized<ManagePlaylists>,
+    path: web::Path<String>,
+) -> impl Responder {
     let playlistid: i64 = match path.parse() {
         Ok(v) => v,
         Err(_) => {
@@ -392,7 +855,7 @@ pub async fn pin_unpin_playlist(path: web::Path<String>) -> impl Responder {
         }
     };
 
-    let mut playlist = match PlaylistTable::get_by_id(playlistid).await {
+    let mut playlist = match owned_playlist(playlistid, user.id).await {
         Ok(Some(p)) => p,
         _ => {
             return HttpResponse::NotFound()
@@ -414,8 +877,21 @@ pub async fn pin_unpin_playlist(path: web::Path<String>) -> impl Responder {
 }
 
 /// DELETE /playlists/<playlistid>/remove-img
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[delete("/{playlistid}/remove-img")]
-pub async fn remove_playlist_image(path: web::Path<String>) -> impl Responder {
+pub async fn remove_playlist_image(
+    user: Authorized<ManagePlaylists>,
+    path: web::Path<String>,
+) -> impl Responder {
     let playlistid: i64 = match path.parse() {
         Ok(v) => v,
         Err(_) => {
@@ -424,16 +900,15 @@ pub async fn remove_playlist_image(path: web::Path<String>) -> impl Responder {
         }
     };
 
-    let mut playlist = match PlaylistTable::get_by_id(playlistid).await.ok().flatten() {
+    let mut playlist = match owned_playlist(playlistid, user.id).await.ok().flatten() {
         Some(p) => p,
         None => return HttpResponse::Ok().json(serde_json::json!({ "msg": "Done" })),
     };
 
-    if let Some(img) = &playlist.image {
-        let _ = delete_playlist_images(img);
-    }
-
     playlist.image = None;
+    playlist.color.clear();
+    playlist.color_dark.clear();
+    playlist.color_light.clear();
     playlist.thumb.clear();
     playlist.settings.has_gif = false;
     playlist.has_image = false;
@@ -442,7 +917,10 @@ pub async fn remove_playlist_image(path: web::Path<String>) -> impl Responder {
         return HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed" }));
     }
 
+    let _ = PlaylistLib::release_images(playlistid, None).await;
+
     playlist.last_updated = date_to_relative(&playlist.last_updated);
+    playlist.is_editable = true;
     playlist.init();
     let images = first_4_images(None, Some(&playlist.trackhashes));
 
@@ -450,9 +928,94 @@ pub async fn remove_playlist_image(path: web::Path<String>) -> impl Responder {
         .json(serde_json::json!({ "playlist": serialize_playlist(&playlist, &images) }))
 }
 
+/// PUT /playlists/<playlistid>/sharing
+///
+/// Only the owner can share a playlist or change its collaborators
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[put("/{playlistid}/sharing")]
+pub async fn set_playlist_sharing(
+    user: Authorized<ManagePlaylists>,
+    path: web::Path<String>,
+    body: web::Json<SharingBody>,
+) -> impl Responder {
+    let playlistid: i64 = match path.parse() {
+        Ok(v) => v,
+        Err(_) => {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({ "error": "Playlist not found" }))
+        }
+    };
+
+    let playlist = match owned_playlist(playlistid, user.id).await {
+        Ok(Some(p)) => p,
+        _ => {
+            return HttpResponse::NotFound()
+                .json(serde_json::json!({ "error": "Playlist not found" }))
+        }
+    };
+
+    let shared = body.shared.unwrap_or(playlist.shared);
+    let mut collaborators = body.collaborators.clone().unwrap_or(playlist.collaborators);
+    collaborators.retain(|id| *id != user.id);
+    collaborators.sort_unstable();
+    collaborators.dedup();
+
+    for id in &collaborators {
+        match UserTable::get_by_id(*id).await {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("User {} not found", id)
+                }))
+            }
+            Err(_) => {
+                return HttpResponse::InternalServerError()
+                    .json(serde_json::json!({ "error": "Database error" }))
+            }
+        }
+    }
+
+    if PlaylistTable::set_sharing(playlistid, shared, &collaborators)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError()
+            .json(serde_json::json!({ "error": "Failed to update sharing" }));
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "shared": shared,
+        "collaborators": collaborators,
+    }))
+}
+
 /// DELETE /playlists/<playlistid>/delete
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[delete("/{playlistid}/delete")]
-pub async fn remove_playlist(path: web::Path<String>) -> impl Responder {
+pub async fn remove_playlist(
+    user: Authorized<ManagePlaylists>,
+    path: web::Path<String>,
+) -> impl Responder {
     let playlistid: i64 = match path.parse() {
         Ok(v) => v,
         Err(_) => {
@@ -461,17 +1024,15 @@ pub async fn remove_playlist(path: web::Path<String>) -> impl Responder {
         }
     };
 
-    let user = PlaylistTable::get_by_id(playlistid)
-        .await
-        .ok()
-        .flatten()
-        .and_then(|p| p.userid)
-        .unwrap_or(1);
+    if !matches!(owned_playlist(playlistid, user.id).await, Ok(Some(_))) {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Playlist not found" }));
+    }
 
-    if PlaylistTable::delete(playlistid, user)
+    if PlaylistTable::delete(playlistid, user.id)
         .await
         .unwrap_or(false)
     {
+        let _ = PlaylistLib::release_images(playlistid, None).await;
         HttpResponse::Ok().json(serde_json::json!({ "msg": "Done" }))
     } else {
         HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed" }))
@@ -479,8 +1040,20 @@ pub async fn remove_playlist(path: web::Path<String>) -> impl Responder {
 }
 
 /// POST /playlists/<playlistid>/remove-tracks
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[post("/{playlistid}/remove-tracks")]
 pub async fn remove_tracks_from_playlist(
+    user: Authorized<ManagePlaylists>,
     path: web::Path<String>,
     body: web::Json<RemoveTracksBody>,
 ) -> impl Responder {
@@ -492,6 +1065,10 @@ pub async fn remove_tracks_from_playlist(
         }
     };
 
+    if !matches!(editable_playlist(playlistid, user.id).await, Ok(Some(_))) {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Playlist not found" }));
+    }
+
     let items: Vec<(usize, String)> = body
         .tracks
         .iter()
@@ -508,10 +1085,121 @@ pub async fn remove_tracks_from_playlist(
     HttpResponse::Ok().json(serde_json::json!({ "msg": "Done" }))
 }
 
+/// POST /playlists/<playlistid>/combine
+///
+/// Merge another playlist's tracks in without duplicates, remove the tracks it
+/// has or keep only those. a preview returns the counts without saving
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[post("/{playlistid}/combine")]
+pub async fn combine_playlists(
+    user: Authorized<ManagePlaylists>,
+    path: web::Path<String>,
+    body: web::Json<CombinePlaylistBody>,
+) -> impl Responder {
+    let Ok(playlist_id) = path.parse::<i64>() else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Invalid playlist id"
+        }));
+    };
+    let Some(operation) = PlaylistCombine::parse(&body.operation) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Operation must be merge, subtract or intersect"
+        }));
+    };
+
+    let source_id = body.source.trim();
+    if source_id == playlist_id.to_string() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "A playlist cannot be combined with itself"
+        }));
+    }
+
+    if !matches!(editable_playlist(playlist_id, user.id).await, Ok(Some(_))) {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Playlist not found" }));
+    }
+
+    let source: Vec<String> = if is_custom_playlist(source_id) {
+        let (_, tracks) = build_custom_playlist(source_id, user.id).await;
+        tracks.into_iter().map(|t| t.trackhash).collect()
+    } else {
+        let visible = match source_id.parse::<i64>() {
+            Ok(id) => visible_playlist(id, user.id).await,
+            Err(_) => Ok(None),
+        };
+        match visible {
+            Ok(Some(playlist)) => playlist.trackhashes,
+            Ok(None) => {
+                return HttpResponse::NotFound()
+                    .json(serde_json::json!({ "error": "Source playlist not found" }))
+            }
+            Err(_) => {
+                return HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": "Failed to read playlist"
+                }))
+            }
+        }
+    };
+
+    let target = match PlaylistTable::get_trackhashes(playlist_id).await {
+        Ok(trackhashes) => trackhashes,
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to read playlist"
+            }))
+        }
+    };
+    let combined = operation.apply(&target, &source);
+    let added = combined.len().saturating_sub(target.len());
+    let removed = target.len().saturating_sub(combined.len());
+
+    if !body.preview && combined != target {
+        let saved = match serde_json::to_string(&combined) {
+            Ok(json) => PlaylistTable::update_tracks(playlist_id, &json).await,
+            Err(e) => Err(e.into()),
+        };
+        if saved.is_err() {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to update playlist"
+            }));
+        }
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "msg": if body.preview { "Preview" } else { "Done" },
+        "count": combined.len(),
+        "added": added,
+        "removed": removed,
+    }))
+}
+
 /// POST /playlists/save-item
+#[utoipa::path(
+    responses(
+        (status = 201, description = "Created"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Conflict"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[post("/save-item")]
-pub async fn save_item_as_playlist(body: web::Json<SaveAsPlaylistBody>) -> impl Responder {
-    if PlaylistTable::name_exists(&body.playlist_name, 1)
+pub async fn save_item_as_playlist(
+    user: Authorized<ManagePlaylists>,
+    body: web::Json<SaveAsPlaylistBody>,
+) -> impl Responder {
+    if PlaylistTable::name_exists(&body.playlist_name, user.id)
         .await
         .unwrap_or(false)
     {
@@ -519,14 +1207,18 @@ pub async fn save_item_as_playlist(body: web::Json<SaveAsPlaylistBody>) -> impl
             .json(serde_json::json!({ "error": "Playlist already exists" }));
     }
 
-    let trackhashes =
-        resolve_item_trackhashes(&body.itemtype, &body.itemhash, Some(&body.sortoptions));
+    let trackhashes = resolve_item_trackhashes(
+        &body.itemtype,
+        &body.itemhash,
+        body.sortoptions.as_ref(),
+        user.id,
+    );
 
     if trackhashes.is_empty() {
         return HttpResponse::NotFound().json(serde_json::json!({ "error": "No tracks founds" }));
     }
 
-    let mut playlist = Playlist::new(body.playlist_name.clone(), Some(1));
+    let mut playlist = Playlist::new(body.playlist_name.clone(), Some(user.id));
     playlist.trackhashes = trackhashes.clone();
     playlist.count = trackhashes.len() as i32;
 
@@ -541,16 +1233,21 @@ pub async fn save_item_as_playlist(body: web::Json<SaveAsPlaylistBody>) -> impl
     };
 
     playlist.id = id;
+    playlist.is_editable = true;
 
     if body.itemtype != "folder" && body.itemtype != "tracks" {
-        if let Some(img) = copy_source_image(id, &body.itemtype, &body.itemhash) {
+        if let Some((img, color)) = copy_source_image(id, &body.itemtype, &body.itemhash).await {
             playlist.image = Some(img.clone());
+            set_image_color(&mut playlist, color);
             playlist.has_image = true;
             playlist.thumb = format!("thumb_{}", img);
         }
     }
 
     if PlaylistTable::update(&playlist).await.is_err() {
+        if let Some(img) = &playlist.image {
+            let _ = PlaylistLib::discard_image(id, img).await;
+        }
         return HttpResponse::InternalServerError()
             .json(serde_json::json!({ "error": "Playlist could not be created" }));
     }
@@ -562,39 +1259,29 @@ pub async fn save_item_as_playlist(body: web::Json<SaveAsPlaylistBody>) -> impl
 fn resolve_item_trackhashes(
     itemtype: &str,
     itemhash: &str,
-    sortoptions: Option<&serde_json::Value>,
+    sortoptions: Option<&FolderSortOptions>,
+    user_id: i64,
 ) -> Vec<String> {
     let store = TrackStore::get();
     match itemtype {
         "tracks" => itemhash.split(',').map(|s| s.to_string()).collect(),
         "folder" => {
-            let sortreverse = sortoptions
-                .and_then(|v| v.get("tracksortreverse"))
-                .and_then(|v| v.as_bool())
-                .unwrap_or(false);
-            let sortby = sortoptions
-                .and_then(|v| v.get("tracksortby"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("default");
+            let (sortby, reverse) = sortoptions
+                .map(|o| (o.tracksortby, o.tracksortreverse))
+                .unwrap_or_default();
 
             let mut tracks = store.get_by_folder(itemhash);
-            sort_tracks_py(&mut tracks, sortby, sortreverse);
+            SortLib::sort_tracks(&mut tracks, sortby, SortOrder::from_reverse(reverse));
             tracks.into_iter().map(|t| t.trackhash).collect()
         }
         "album" => {
             let mut tracks = store.get_by_album(itemhash);
-            tracks.sort_by(|a, b| {
-                let dc = a.disc.cmp(&b.disc);
-                if dc == std::cmp::Ordering::Equal {
-                    a.track.cmp(&b.track)
-                } else {
-                    dc
-                }
-            });
+            SortLib::sort_tracks_album_order(&mut tracks);
             tracks.into_iter().map(|t| t.trackhash).collect()
         }
         "artist" => {
             let mut tracks = store.get_by_artist(itemhash);
+            PlayStatsStore::get().personalize_tracks(user_id, &mut tracks);
             tracks.sort_by(|a, b| b.playcount.cmp(&a.playcount));
             tracks.into_iter().map(|t| t.trackhash).collect()
         }
@@ -602,10 +1289,58 @@ fn resolve_item_trackhashes(
     }
 }
 
+/// Load a playlist only if it belongs to the user
+async fn owned_playlist(playlistid: i64, user_id: i64) -> anyhow::Result<Option<Playlist>> {
+    Ok(PlaylistTable::get_by_id(playlistid)
+        .await?
+        .filter(|p| p.is_owner(user_id)))
+}
+
+/// A playlist whose tracks the user can change
+async fn editable_playlist(playlistid: i64, user_id: i64) -> anyhow::Result<Option<Playlist>> {
+    Ok(PlaylistTable::get_by_id(playlistid)
+        .await?
+        .filter(|p| p.can_edit(user_id)))
+}
+
+/// A playlist the user can see
+async fn visible_playlist(playlistid: i64, user_id: i64) -> anyhow::Result<Option<Playlist>> {
+    Ok(PlaylistTable::get_by_id(playlistid)
+        .await?
+        .filter(|p| p.can_view(user_id)))
+}
+
+/// A playlist and its tracks as `user_id` sees them, custom playlists included
+pub(crate) async fn playlist_with_tracks(
+    playlistid: &str,
+    user_id: i64,
+) -> anyhow::Result<Option<(Playlist, Vec<crate::models::Track>)>> {
+    if is_custom_playlist(playlistid) {
+        return Ok(Some(build_custom_playlist(playlistid, user_id).await));
+    }
+    let Ok(pid) = playlistid.parse::<i64>() else {
+        return Ok(None);
+    };
+    Ok(visible_playlist(pid, user_id).await?.map(|playlist| {
+        let tracks = TrackStore::get().get_by_hashes(&playlist.trackhashes);
+        (playlist, tracks)
+    }))
+}
+
+/// Set the custom image color of a playlist along with its variants
+fn set_image_color(playlist: &mut Playlist, color: String) {
+    let variants = ColorLib::variants(&color);
+    playlist.color = color;
+    playlist.color_dark = variants.dark;
+    playlist.color_light = variants.light;
+}
+
 #[derive(Clone)]
 struct ImgInfo {
     image: String,
     color: String,
+    color_dark: String,
+    color_light: String,
 }
 
 fn first_4_images(
@@ -638,6 +1373,8 @@ fn first_4_images(
         .map(|a| ImgInfo {
             image: a.image.clone(),
             color: a.color.clone(),
+            color_dark: a.color_dark.clone(),
+            color_light: a.color_light.clone(),
         })
         .collect();
 
@@ -665,12 +1402,50 @@ fn first_4_images(
     images
 }
 
-fn build_custom_playlist(name: &str) -> (Playlist, Vec<crate::models::Track>) {
+/// The user's favorite tracks as a playlist, most recently liked first
+///
+/// favorites of tracks no longer in the library are left out
+async fn liked_playlist(user_id: i64) -> anyhow::Result<Playlist> {
+    let favorites =
+        FavoriteTable::get_by_type(FavoriteType::Track, user_id, 0, i64::MAX / 4).await?;
+
+    let mut playlist = Playlist::new("Liked Songs".to_string(), None);
+    if let Some(latest) = favorites
+        .first()
+        .and_then(|f| chrono::DateTime::from_timestamp(f.timestamp, 0))
+    {
+        playlist.last_updated = latest.format("%Y-%m-%d %H:%M:%S").to_string();
+    }
+
+    let store = TrackStore::get();
+    playlist.trackhashes = favorites
+        .into_iter()
+        .map(|f| f.hash)
+        .filter(|hash| store.get_by_hash(hash).is_some())
+        .collect();
+    playlist.init();
+    Ok(playlist)
+}
+
+async fn build_custom_playlist(name: &str, user_id: i64) -> (Playlist, Vec<crate::models::Track>) {
     let store = TrackStore::get();
     let mut playlist = Playlist::new(name.to_string(), None);
 
-    let (tracks, images): (Vec<_>, Vec<_>) = if name == "recentlyplayed" {
-        let hashes = crate::stores::HomepageStore::get().get_recently_played();
+    let (tracks, images): (Vec<_>, Vec<_>) = if name == LIKED_PLAYLIST {
+        playlist = liked_playlist(user_id).await.unwrap_or(playlist);
+        let tracks = store.get_by_hashes(&playlist.trackhashes);
+        let imgs = first_4_images(Some(&tracks), None);
+        (tracks, imgs)
+    } else if name == "recentlyplayed" {
+        let mut hashes: Vec<String> = Vec::new();
+        for log in ScrobbleTable::get_paginated(user_id, 0, RECENTLY_PLAYED_SCAN)
+            .await
+            .unwrap_or_default()
+        {
+            if !hashes.contains(&log.trackhash) {
+                hashes.push(log.trackhash);
+            }
+        }
         let tracks = store.get_by_hashes(&hashes);
         let imgs = first_4_images(Some(&tracks), None);
         (tracks, imgs)
@@ -692,6 +1467,18 @@ fn build_custom_playlist(name: &str) -> (Playlist, Vec<crate::models::Track>) {
     (playlist, tracks)
 }
 
+/// Serialize a playlist card the way the playlists listing does
+pub(crate) fn serialize_playlist_card(mut playlist: Playlist, user_id: i64) -> serde_json::Value {
+    playlist.is_editable = playlist.can_edit(user_id);
+    playlist.init();
+    let images = if !playlist.has_image {
+        first_4_images(None, Some(&playlist.trackhashes))
+    } else {
+        Vec::new()
+    };
+    serialize_playlist(&playlist, &images)
+}
+
 fn serialize_playlist(playlist: &Playlist, images: &[ImgInfo]) -> serde_json::Value {
     let mut value = serde_json::to_value(playlist).unwrap_or_else(|_| serde_json::json!({}));
     if let Some(obj) = value.as_object_mut() {
@@ -700,7 +1487,18 @@ fn serialize_playlist(playlist: &Playlist, images: &[ImgInfo]) -> serde_json::Va
             serde_json::Value::Array(
                 images
                     .iter()
-                    .map(|i| serde_json::json!({"image": i.image, "color": i.color}))
+                    .map(|i| {
+                        let mut image = serde_json::json!({
+                            "image": i.image,
+                            "color": i.color,
+                            "color_dark": i.color_dark,
+                            "color_light": i.color_light,
+                        });
+                        if let Some(map) = image.as_object_mut() {
+                            insert_image_hints(map, CardImage::Thumbnail);
+                        }
+                        image
+                    })
                     .collect(),
             ),
         );
@@ -708,12 +1506,28 @@ fn serialize_playlist(playlist: &Playlist, images: &[ImgInfo]) -> serde_json::Va
             "pinned".to_string(),
             serde_json::json!(playlist.settings.pinned),
         );
+        if playlist.color.is_empty() {
+            // fall back to the colors of the first album in the collage
+            let fallback = images.iter().find(|i| !i.color.is_empty());
+            let (color, dark, light) = fallback
+                .map(|i| {
+                    (
+                        i.color.as_str(),
+                        i.color_dark.as_str(),
+                        i.color_light.as_str(),
+                    )
+                })
+                .unwrap_or_default();
+            obj.insert("color".to_string(), serde_json::json!(color));
+            obj.insert("color_dark".to_string(), serde_json::json!(dark));
+            obj.insert("color_light".to_string(), serde_json::json!(light));
+        }
         obj.remove("trackhashes");
     }
     value
 }
 
-fn serialize_track_for_playlist(track: &crate::models::Track) -> serde_json::Value {
+fn serialize_track_for_playlist(track: &crate::models::Track, user_id: i64) -> serde_json::Value {
     let mut value = serde_json::to_value(track).unwrap_or_else(|_| serde_json::json!({}));
     if let Some(map) = value.as_object_mut() {
         let mut to_remove: std::collections::HashSet<String> = [
@@ -769,88 +1583,57 @@ fn serialize_track_for_playlist(track: &crate::models::Track) -> serde_json::Val
 
         map.insert(
             "is_favorite".to_string(),
-            serde_json::Value::Bool(track.is_favorite(1)),
+            serde_json::Value::Bool(track.is_favorite(user_id)),
         );
+        insert_image_hints(map, CardImage::Thumbnail);
     }
 
     value
 }
 
-fn sort_tracks_py(tracks: &mut [crate::models::Track], key: &str, reverse: bool) {
-    if key == "default" {
-        if reverse {
-            tracks.reverse();
-        }
-        return;
-    }
-
-    // stable title sort as fallback
-    tracks.sort_by(|a, b| a.title.to_lowercase().cmp(&b.title.to_lowercase()));
-
-    match key {
-        "artists" | "artist" | "albumartists" => {
-            tracks.sort_by(|a, b| {
-                let an = a
-                    .artists
-                    .get(0)
-                    .map(|ar| ar.name.to_lowercase())
-                    .unwrap_or_default();
-                let bn = b
-                    .artists
-                    .get(0)
-                    .map(|ar| ar.name.to_lowercase())
-                    .unwrap_or_default();
-                an.cmp(&bn)
-            });
-        }
-        "album" => {
-            tracks.sort_by(|a, b| a.album.to_lowercase().cmp(&b.album.to_lowercase()));
-        }
-        "disc" => {
-            tracks.sort_by(|a, b| {
-                let alb = a.album.to_lowercase().cmp(&b.album.to_lowercase());
-                if alb == std::cmp::Ordering::Equal {
-                    (a.disc, a.track).cmp(&(b.disc, b.track))
-                } else {
-                    alb
-                }
-            });
-        }
-        "title" => {
-            tracks.sort_by(|a, b| a.title.to_lowercase().cmp(&b.title.to_lowercase()));
-        }
-        "last_mod" | "lastmod" => {
-            tracks.sort_by(|a, b| a.last_mod.cmp(&b.last_mod));
+/// Write a playlist image and its thumbnail and start tracking the new file
+///
+/// the previous image is left in place so the caller can release it after the
+/// playlist row has been updated. returns the filename, whether it is a gif and
+/// its dominant color.
+async fn save_playlist_image(
+    playlistid: i64,
+    bytes: &[u8],
+    content_type: &str,
+) -> anyhow::Result<(String, bool, String)> {
+    let (filename, is_gif) = match write_playlist_image(playlistid, bytes, content_type) {
+        Ok(saved) => saved,
+        Err((filename, e)) => {
+            delete_image_files(&filename);
+            return Err(e);
         }
-        _ => {}
-    }
+    };
 
-    if reverse {
-        tracks.reverse();
-    }
+    let color = PlaylistLib::track_image(playlistid, &filename).await?;
+    Ok((filename, is_gif, color))
 }
 
-fn save_playlist_image(
+fn write_playlist_image(
     playlistid: i64,
     bytes: &[u8],
     content_type: &str,
-    existing: Option<&str>,
-) -> anyhow::Result<(String, bool)> {
-    let paths = Paths::get()?;
-    let dir = paths.playlist_images_dir();
-    fs::create_dir_all(&dir)?;
-
+) -> Result<(String, bool), (String, anyhow::Error)> {
     let is_gif = content_type.to_lowercase().contains("gif");
 
     let ext = if is_gif { "gif" } else { "webp" };
     let random = generate_random_string(5);
     let filename = format!("{}{}.{}", playlistid, random, ext);
+    let fail = |e: anyhow::Error| (filename.clone(), e);
+
+    let paths = Paths::get().map_err(fail)?;
+    let dir = paths.playlist_images_dir();
+    fs::create_dir_all(&dir).map_err(|e| fail(e.into()))?;
 
     let filepath = dir.join(&filename);
 
     if is_gif {
-        let mut file = fs::File::create(&filepath)?;
-        file.write_all(bytes)?;
+        let mut file = fs::File::create(&filepath).map_err(|e| fail(e.into()))?;
+        file.write_all(bytes).map_err(|e| fail(e.into()))?;
     }
 
     let thumb_path = dir.join(format!("thumb_{}", filename));
@@ -861,29 +1644,16 @@ fn save_playlist_image(
             let _ = thumb.save(&thumb_path);
         }
     } else {
-        let img = image::load_from_memory(bytes)?;
+        let img = image::load_from_memory(bytes).map_err(|e| fail(e.into()))?;
         let thumb = resize_to_height(img.clone(), 250);
-        thumb.save(&thumb_path)?;
-        img.save_with_format(&filepath, ImageFormat::WebP)?;
-    }
-
-    if let Some(old) = existing {
-        let _ = delete_playlist_images(old);
+        thumb.save(&thumb_path).map_err(|e| fail(e.into()))?;
+        img.save_with_format(&filepath, ImageFormat::WebP)
+            .map_err(|e| fail(e.into()))?;
     }
 
     Ok((filename, is_gif))
 }
 
-fn delete_playlist_images(image_name: &str) -> anyhow::Result<()> {
-    let paths = Paths::get()?;
-    let dir = paths.playlist_images_dir();
-    let img_path = dir.join(image_name);
-    let thumb_path = dir.join(format!("thumb_{}", image_name));
-    let _ = fs::remove_file(img_path);
-    let _ = fs::remove_file(thumb_path);
-    Ok(())
-}
-
 fn resize_to_height(img: image::DynamicImage, height: u32) -> image::DynamicImage {
     let (w, h) = img.dimensions();
     if h == 0 {
@@ -894,12 +1664,17 @@ fn resize_to_height(img: image::DynamicImage, height: u32) -> image::DynamicImag
     img.resize_exact(new_w, height, FilterType::Lanczos3)
 }
 
-fn copy_source_image(playlist_id: i64, itemtype: &str, itemhash: &str) -> Option<String> {
-    let paths = Paths::get().ok()?;
+async fn copy_source_image(
+    playlist_id: i64,
+    itemtype: &str,
+    itemhash: &str,
+) -> Option<(String, String)> {
     let (source_path, content_type) = if itemtype == "artist" {
+        let paths = Paths::get().ok()?;
         (paths.get_artist_image_path(itemhash, "large"), "image/webp")
     } else {
-        (paths.get_thumbnail_path(itemhash, "large"), "image/webp")
+        let thumbnail = album_thumbnail(itemhash, "", "large", ThumbnailFormat::WebP).await?;
+        (thumbnail, "image/webp")
     };
 
     if !source_path.exists() {
@@ -907,22 +1682,49 @@ fn copy_source_image(playlist_id: i64, itemtype: &str, itemhash: &str) -> Option
     }
 
     let bytes = fs::read(&source_path).ok()?;
-    save_playlist_image(playlist_id, &bytes, content_type, None)
+    save_playlist_image(playlist_id, &bytes, content_type)
+        .await
         .ok()
-        .map(|(name, _)| name)
+        .map(|(name, _, color)| (name, color))
 }
 
+/// OpenAPI description of the playlist routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    send_all_playlists,
+    create_playlist,
+    add_item_to_playlist,
+    add_query_to_playlist,
+    get_playlist,
+    export_playlist,
+    download_playlist,
+    update_playlist_info,
+    pin_unpin_playlist,
+    remove_playlist_image,
+    remove_playlist,
+    set_playlist_sharing,
+    remove_tracks_from_playlist,
+    combine_playlists,
+    save_item_as_playlist,
+))]
+pub struct ApiDoc;
+
 /// Configure playlist routes
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(send_all_playlists)
         .service(create_playlist)
         .service(add_item_to_playlist)
+        .service(add_query_to_playlist)
         .service(get_playlist)
+        .service(export_playlist)
+        .service(download_playlist)
         .service(update_playlist_info)
         .service(pin_unpin_playlist)
         .service(remove_playlist_image)
         .service(remove_playlist)
+        .service(set_playlist_sharing)
         .service(remove_tracks_from_playlist)
+        .service(combine_playlists)
         .service(save_item_as_playlist);
 }
 
@@ -1,22 +1,30 @@
 //! plugin management routes matching upstream behavior
 
-use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use actix_web::{get, post, web, HttpResponse, Responder};
 use once_cell::sync::Lazy;
 use regex::Regex;
 use serde::Deserialize;
 use serde_json::json;
+use std::path::Path;
 use tracing::warn;
+use utoipa::{OpenApi, ToSchema};
 
+use crate::api::identity::{Admin, Authorized, CurrentUser};
+use crate::api::lyrics::mark_has_lyrics;
 use crate::config::UserConfig;
 use crate::core::lyrics::LyricsLib;
-use crate::db::tables::{PluginTable, UserTable};
-use crate::models::{User, UserRole};
+use crate::db::tables::PluginTable;
 use crate::plugins::{LastFmPlugin, LyricsPlugin};
 use crate::stores::TrackStore;
-use crate::utils::auth::verify_jwt;
 use crate::utils::hashing::create_hash;
 
 /// list all plugins
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 500, description = "Server error")
+    )
+)]
 #[get("")]
 pub async fn get_plugins() -> impl Responder {
     match PluginTable::get_all().await {
@@ -45,7 +53,7 @@ pub struct PluginBody {
     pub plugin: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct PluginActivateBody {
     pub plugin: String,
     #[serde(default)]
@@ -53,19 +61,25 @@ pub struct PluginActivateBody {
 }
 
 /// activate or deactivate a plugin (admin only)
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 #[post("/setactive")]
 pub async fn activate_deactivate_plugin(
-    req: HttpRequest,
+    _admin: Authorized<Admin>,
     body: web::Json<PluginActivateBody>,
 ) -> impl Responder {
     if body.plugin.is_empty() {
         return HttpResponse::BadRequest().json(json!({"error": "Missing plugin"}));
     }
 
-    if let Err(resp) = require_admin(&req).await {
-        return resp;
-    }
-
     if let Err(e) = PluginTable::set_active(&body.plugin, body.active).await {
         return HttpResponse::InternalServerError()
             .json(json!({ "error": format!("Failed to update plugin: {}", e) }));
@@ -74,26 +88,32 @@ pub async fn activate_deactivate_plugin(
     HttpResponse::Ok().json(json!({"message": "OK"}))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct PluginSettingsBody {
     pub plugin: String,
     pub settings: serde_json::Value,
 }
 
 /// update plugin settings (admin only)
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 #[post("/settings")]
 pub async fn update_plugin_settings(
-    req: HttpRequest,
+    _admin: Authorized<Admin>,
     body: web::Json<PluginSettingsBody>,
 ) -> impl Responder {
     if body.plugin.is_empty() || body.settings.is_null() {
         return HttpResponse::BadRequest().json(json!({"error": "Missing plugin or settings"}));
     }
 
-    if let Err(resp) = require_admin(&req).await {
-        return resp;
-    }
-
     let settings_str = serde_json::to_string(&body.settings).unwrap_or_else(|_| "{}".to_string());
     if let Err(e) = PluginTable::update_settings(&body.plugin, &settings_str).await {
         return HttpResponse::InternalServerError()
@@ -109,25 +129,30 @@ pub async fn update_plugin_settings(
     HttpResponse::Ok().json(json!({"status": "success", "settings": settings }))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct LastFmSessionBody {
     pub token: String,
 }
 
 /// create a lastfm session and persist session key
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[post("/lastfm/session/create")]
 pub async fn create_lastfm_session(
-    req: HttpRequest,
+    user: CurrentUser,
     body: web::Json<LastFmSessionBody>,
 ) -> impl Responder {
     if body.token.is_empty() {
         return HttpResponse::BadRequest().json(json!({"error": "Missing token"}));
     }
 
-    let user_id = match resolve_user_id(&req).await {
-        Ok(id) => id,
-        Err(resp) => return resp,
-    };
+    let user_id = user.id;
 
     let lastfm = LastFmPlugin::new();
     let session_key = lastfm.get_session_key(&body.token).await.ok();
@@ -143,12 +168,16 @@ pub async fn create_lastfm_session(
 }
 
 /// delete the stored lastfm session for the user
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[post("/lastfm/session/delete")]
-pub async fn delete_lastfm_session(req: HttpRequest) -> impl Responder {
-    let user_id = match resolve_user_id(&req).await {
-        Ok(id) => id,
-        Err(resp) => return resp,
-    };
+pub async fn delete_lastfm_session(user: CurrentUser) -> impl Responder {
+    let user_id = user.id;
 
     if let Ok(mut config) = UserConfig::load() {
         config.set_lastfm_session_key(user_id.to_string(), "".to_string());
@@ -158,7 +187,7 @@ pub async fn delete_lastfm_session(req: HttpRequest) -> impl Responder {
     HttpResponse::Ok().json(json!({"status": "success"}))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct LyricsSearchBody {
     pub trackhash: String,
     pub title: String,
@@ -170,8 +199,17 @@ pub struct LyricsSearchBody {
 }
 
 /// search lyrics using musixmatch plugin
+#[utoipa::path(
+    responses((status = 200, description = "Success"))
+)]
 #[post("/lyrics/search")]
 pub async fn search_lyrics(body: web::Json<LyricsSearchBody>) -> impl Responder {
+    // synced lyrics next to or inside the file make the online lookup unnecessary,
+    // plain ones do not since the search is how users find synced lyrics
+    if let Some(local) = LyricsLib::local(Path::new(&body.filepath)).filter(|l| l.is_synced) {
+        return HttpResponse::Ok().json(build_synced_response(&body.trackhash, &local));
+    }
+
     let plugin = LyricsPlugin::new();
     let track = TrackStore::get().get_by_hash(&body.trackhash);
 
@@ -181,6 +219,7 @@ pub async fn search_lyrics(body: web::Json<LyricsSearchBody>) -> impl Responder
                 Ok(Some(content)) => {
                     let lyrics = LyricsLib::parse_lrc(&content);
                     if lyrics.is_synced {
+                        mark_has_lyrics(&body.trackhash).await;
                         return HttpResponse::Ok()
                             .json(build_synced_response(&body.trackhash, &lyrics));
                     }
@@ -232,6 +271,7 @@ pub async fn search_lyrics(body: web::Json<LyricsSearchBody>) -> impl Responder
     };
 
     let response = if let Some(ref content) = lrc {
+        mark_has_lyrics(&body.trackhash).await;
         let lyrics = LyricsLib::parse_lrc(content);
         if lyrics.is_synced {
             build_synced_response(&body.trackhash, &lyrics)
@@ -332,6 +372,18 @@ fn parse_spotify_track_id(candidate: &str) -> Option<String> {
     None
 }
 
+/// OpenAPI description of the plugins routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    get_plugins,
+    activate_deactivate_plugin,
+    update_plugin_settings,
+    create_lastfm_session,
+    delete_lastfm_session,
+    search_lyrics,
+))]
+pub struct ApiDoc;
+
 /// configure plugin routes
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(get_plugins)
@@ -341,57 +393,3 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
         .service(delete_lastfm_session)
         .service(search_lyrics);
 }
-
-async fn resolve_user_id(req: &HttpRequest) -> Result<i64, HttpResponse> {
-    match optional_user(req).await? {
-        Some(user) => Ok(user.id),
-        None => Ok(0),
-    }
-}
-
-async fn require_admin(req: &HttpRequest) -> Result<User, HttpResponse> {
-    let user = match optional_user(req).await? {
-        Some(u) => u,
-        None => {
-            return Err(HttpResponse::Unauthorized().json(json!({"msg": "Not authenticated"})));
-        }
-    };
-
-    if user.roles.contains(&UserRole::Admin) {
-        Ok(user)
-    } else {
-        Err(HttpResponse::Forbidden().json(json!({"msg": "Only admins can do that!"})))
-    }
-}
-
-async fn optional_user(req: &HttpRequest) -> Result<Option<User>, HttpResponse> {
-    let header = match req.headers().get("Authorization") {
-        Some(h) => h,
-        None => return Ok(None),
-    };
-
-    let header_str = header.to_str().unwrap_or("").trim();
-    if header_str.is_empty() {
-        return Err(HttpResponse::Unauthorized().json(json!({"error": "Invalid token format"})));
-    }
-    let token = if let Some(rest) = header_str.strip_prefix("Bearer ") {
-        rest
-    } else {
-        header_str
-    };
-    if token.is_empty() {
-        return Err(HttpResponse::Unauthorized().json(json!({"error": "Invalid token format"})));
-    }
-
-    let config = UserConfig::load()
-        .map_err(|_| HttpResponse::InternalServerError().json(json!({"error": "Config error"})))?;
-
-    let claims = verify_jwt(token, &config.server_id, Some("access"))
-        .map_err(|_| HttpResponse::Unauthorized().json(json!({"msg": "Invalid token"})))?;
-
-    match UserTable::get_by_id(claims.sub.id).await {
-        Ok(Some(user)) => Ok(Some(user)),
-        Ok(None) => Err(HttpResponse::Unauthorized().json(json!({"msg": "Invalid token"}))),
-        Err(_) => Err(HttpResponse::InternalServerError().json(json!({"msg": "Database error"}))),
-    }
-}
@@ -1,29 +1,56 @@
 //! mixes plugin routes matching python upstream behavior
 
 use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::Local;
 use serde::Deserialize;
 use serde_json::{json, Map, Value};
+use utoipa::{IntoParams, OpenApi, ToSchema};
 
-use crate::config::UserConfig;
-use crate::db::tables::{MixTable, UserTable};
-use crate::models::{Mix, Track, User};
+use crate::api::identity::require_user;
+use crate::api::imgserver::{insert_image_hints, CardImage};
+use crate::core::recipes::{self, Mood, Recipes, DAILY_MIX_HISTORY_DAYS, DAILY_MIX_PREFIX};
+use crate::db::tables::MixTable;
+use crate::models::{Mix, Track};
 use crate::stores::TrackStore;
-use crate::utils::auth::verify_jwt;
 use crate::utils::dates::timestamp_to_relative;
 use crate::utils::hashing::create_hash;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
 pub struct MixTypePath {
     pub mixtype: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct MixQuery {
     pub mixid: String,
     pub sourcehash: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct MixHistoryQuery {
+    /// number of days to return, today included
+    #[serde(default = "default_history_days")]
+    pub days: i64,
+}
+
+fn default_history_days() -> i64 {
+    7
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
+pub struct MoodPath {
+    pub mood: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct MoodQuery {
+    /// tracks in the mix, the daily mix size when missing
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct SaveMixRequest {
     pub mixid: String,
     #[serde(rename = "type")]
@@ -32,6 +59,16 @@ pub struct SaveMixRequest {
 }
 
 /// GET /plugins/mixes/<mixtype>
+#[utoipa::path(
+    params(MixTypePath),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 #[get("/{mixtype}")]
 pub async fn get_mixes(req: HttpRequest, path: web::Path<MixTypePath>) -> impl Responder {
     let user = match require_user(&req).await {
@@ -48,14 +85,18 @@ pub async fn get_mixes(req: HttpRequest, path: web::Path<MixTypePath>) -> impl R
     };
 
     let mut items: Vec<Value> = Vec::new();
-    for mix in mixes {
+    // daily mixes have their own history route
+    for mix in mixes
+        .iter()
+        .filter(|m| !m.mixid.starts_with(DAILY_MIX_PREFIX))
+    {
         match path.mixtype.as_str() {
             "artists" => {
-                items.push(serialize_mix_compact(&mix, true));
+                items.push(serialize_mix_compact(mix, true));
             }
             "tracks" => {
                 // upstream wraps artist mixes into track mixes when available
-                items.push(serialize_mix_compact(&mix, true));
+                items.push(serialize_mix_compact(mix, true));
             }
             _ => {
                 return HttpResponse::BadRequest().json(json!({ "msg": "Invalid mix type" }));
@@ -81,6 +122,17 @@ pub async fn get_mixes(req: HttpRequest, path: web::Path<MixTypePath>) -> impl R
 }
 
 /// GET /plugins/mixes?mixid&sourcehash
+#[utoipa::path(
+    params(MixQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 #[get("")]
 pub async fn get_mix(req: HttpRequest, query: web::Query<MixQuery>) -> impl Responder {
     let user = match require_user(&req).await {
@@ -91,12 +143,19 @@ pub async fn get_mix(req: HttpRequest, query: web::Query<MixQuery>) -> impl Resp
     let mix_type = match query.mixid.chars().next() {
         Some('a') => "artist_mixes",
         Some('t') => "custom_mixes",
+        Some('d') => "daily_mixes",
         _ => {
             return HttpResponse::BadRequest().json(json!({ "msg": "Invalid mix ID" }));
         }
     };
 
-    let mix = match MixTable::get_by_sourcehash(&query.sourcehash, user.id).await {
+    // a daily mix seed repeats across days, only the mix ID picks the snapshot
+    let mix = if mix_type == "daily_mixes" {
+        MixTable::get_by_mixid(&query.mixid, user.id).await
+    } else {
+        MixTable::get_by_sourcehash(&query.sourcehash, user.id).await
+    };
+    let mix = match mix {
         Ok(Some(m)) => m,
         Ok(None) => return HttpResponse::NotFound().json(json!({ "msg": "Mix not found" })),
         Err(e) => {
@@ -110,7 +169,66 @@ pub async fn get_mix(req: HttpRequest, query: web::Query<MixQuery>) -> impl Resp
     HttpResponse::Ok().json(full)
 }
 
+/// GET /plugins/mixes/history?days - the daily mixes of the last days, newest day first
+#[utoipa::path(
+    params(MixHistoryQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[get("/history")]
+pub async fn get_mix_history(
+    req: HttpRequest,
+    query: web::Query<MixHistoryQuery>,
+) -> impl Responder {
+    let user = match require_user(&req).await {
+        Ok(u) => u,
+        Err(resp) => return resp,
+    };
+
+    let days = query.days.clamp(1, DAILY_MIX_HISTORY_DAYS);
+    let today = Local::now().date_naive();
+
+    let mut history: Vec<Value> = Vec::new();
+    for offset in 0..days {
+        let day = today - chrono::Duration::days(offset);
+        let prefix = Recipes::daily_mix_prefix(user.id, day);
+        let mixes = match MixTable::get_by_prefix(user.id, &prefix).await {
+            Ok(mixes) => mixes,
+            Err(e) => {
+                return HttpResponse::InternalServerError()
+                    .json(json!({ "error": format!("Failed to fetch mixes: {}", e) }))
+            }
+        };
+        if mixes.is_empty() {
+            continue;
+        }
+
+        history.push(json!({
+            "date": day.to_string(),
+            "mixes": mixes
+                .iter()
+                .map(|mix| serialize_mix_compact(mix, true))
+                .collect::<Vec<_>>(),
+        }));
+    }
+
+    HttpResponse::Ok().json(json!({ "history": history }))
+}
+
 /// POST /plugins/mixes/save
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 #[post("/save")]
 pub async fn save_mix(req: HttpRequest, body: web::Json<SaveMixRequest>) -> impl Responder {
     let user = match require_user(&req).await {
@@ -121,6 +239,8 @@ pub async fn save_mix(req: HttpRequest, body: web::Json<SaveMixRequest>) -> impl
     let state = match body.mix_type.as_str() {
         "artist" => MixTable::save_artist_mix(&body.sourcehash, user.id).await,
         "track" => MixTable::save_track_mix(&body.sourcehash, user.id).await,
+        // saved daily mixes outlive the history
+        "daily" => MixTable::toggle_saved(&body.mixid, user.id).await,
         _ => {
             return HttpResponse::BadRequest().json(json!({ "msg": "Invalid mix type" }));
         }
@@ -133,11 +253,101 @@ pub async fn save_mix(req: HttpRequest, body: web::Json<SaveMixRequest>) -> impl
     }
 }
 
+/// GET /plugins/mixes/moods - the mood mixes the library has tagged tracks for
+#[utoipa::path(
+    params(MoodQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[get("/moods")]
+pub async fn get_mood_mixes(req: HttpRequest, query: web::Query<MoodQuery>) -> impl Responder {
+    let user = match require_user(&req).await {
+        Ok(u) => u,
+        Err(resp) => return resp,
+    };
+
+    let limit = query
+        .limit
+        .unwrap_or_else(Recipes::mood_mix_size)
+        .clamp(1, 200);
+    let items: Vec<Value> = Recipes::mood_mixes(limit, user.id)
+        .await
+        .iter()
+        .map(|(mood, mix)| {
+            let mut item = serialize_mood_mix(*mood, mix, user.id);
+            if let Some(map) = item.as_object_mut() {
+                map.remove("tracks");
+            }
+            item
+        })
+        .collect();
+
+    HttpResponse::Ok().json(items)
+}
+
+/// GET /plugins/mixes/moods/<mood>?limit - a fresh high-energy, chill or focus mix
+#[utoipa::path(
+    params(MoodPath, MoodQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[get("/moods/{mood}")]
+pub async fn get_mood_mix(
+    req: HttpRequest,
+    path: web::Path<MoodPath>,
+    query: web::Query<MoodQuery>,
+) -> impl Responder {
+    let user = match require_user(&req).await {
+        Ok(u) => u,
+        Err(resp) => return resp,
+    };
+
+    let Some(mood) = Mood::parse(&path.mood) else {
+        return HttpResponse::BadRequest().json(json!({ "msg": "Invalid mood" }));
+    };
+
+    let limit = query
+        .limit
+        .unwrap_or_else(Recipes::mood_mix_size)
+        .clamp(1, 200);
+    match Recipes::mood_mix(mood, limit, user.id).await {
+        Some(mix) => HttpResponse::Ok().json(serialize_mood_mix(mood, &mix, user.id)),
+        None => HttpResponse::NotFound()
+            .json(json!({ "msg": "Not enough tracks are tagged for this mood" })),
+    }
+}
+
+/// OpenAPI description of the plugins mixes routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    get_mix_history,
+    get_mood_mixes,
+    get_mood_mix,
+    get_mixes,
+    get_mix,
+    save_mix,
+))]
+pub struct ApiDoc;
+
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(get_mixes).service(get_mix).service(save_mix);
+    // history and moods are registered before the mix type route so they are not taken for one
+    cfg.service(get_mix_history)
+        .service(get_mood_mixes)
+        .service(get_mood_mix)
+        .service(get_mixes)
+        .service(get_mix)
+        .service(save_mix);
 }
 
-fn serialize_mix_compact(mix: &Mix, convert_time: bool) -> Value {
+pub(crate) fn serialize_mix_compact(mix: &Mix, convert_time: bool) -> Value {
     let trackshash = create_hash(
         &mix.trackhashes
             .iter()
@@ -158,6 +368,7 @@ fn serialize_mix_compact(mix: &Mix, convert_time: bool) -> Value {
     map.insert("userid".to_string(), json!(mix.userid));
     map.insert("sourcehash".to_string(), json!(mix.sourcehash));
     map.insert("extra".to_string(), clean_extra(mix.extra.clone()));
+    insert_mix_colors(&mut map, mix);
 
     if convert_time {
         map.insert(
@@ -187,6 +398,7 @@ fn serialize_mix_full(mix: &Mix, _is_custom: bool, user_id: i64) -> Value {
     map.insert("timestamp".to_string(), json!(mix.timestamp));
     map.insert("saved".to_string(), json!(mix.saved));
     map.insert("extra".to_string(), clean_extra(mix.extra.clone()));
+    insert_mix_colors(&mut map, mix);
     map.insert(
         "duration".to_string(),
         json!(seconds_to_time_string(total_duration as i64)),
@@ -196,6 +408,35 @@ fn serialize_mix_full(mix: &Mix, _is_custom: bool, user_id: i64) -> Value {
     Value::Object(map)
 }
 
+fn serialize_mood_mix(mood: Mood, mix: &recipes::Mix, user_id: i64) -> Value {
+    let hashes: Vec<&str> = mix.tracks.iter().map(|t| t.trackhash.as_str()).collect();
+    let total_duration: i64 = mix.tracks.iter().map(|t| t.duration as i64).sum();
+    let tracks: Vec<Value> = mix
+        .tracks
+        .iter()
+        .map(|t| serialize_track_for_mix(t, user_id))
+        .collect();
+
+    json!({
+        "id": mix.id,
+        "mood": mood.as_str(),
+        "title": mix.name,
+        "description": mix.description,
+        "type": "mix",
+        "trackshash": create_hash(&hashes, true),
+        "trackcount": mix.tracks.len(),
+        "duration": seconds_to_time_string(total_duration),
+        "tracks": tracks,
+    })
+}
+
+fn insert_mix_colors(map: &mut Map<String, Value>, mix: &Mix) {
+    let (color, variants) = Recipes::mix_colors(mix);
+    map.insert("color".to_string(), json!(color));
+    map.insert("color_dark".to_string(), json!(variants.dark));
+    map.insert("color_light".to_string(), json!(variants.light));
+}
+
 fn clean_extra(extra: Value) -> Value {
     match extra {
         Value::Object(mut obj) => {
@@ -207,7 +448,7 @@ fn clean_extra(extra: Value) -> Value {
     }
 }
 
-fn serialize_track_for_mix(track: &Track, user_id: i64) -> Value {
+pub(crate) fn serialize_track_for_mix(track: &Track, user_id: i64) -> Value {
     let mut value = serde_json::to_value(track).unwrap_or_else(|_| json!({}));
     if let Some(map) = value.as_object_mut() {
         let mut to_remove: std::collections::HashSet<String> = [
@@ -265,12 +506,13 @@ fn serialize_track_for_mix(track: &Track, user_id: i64) -> Value {
             "is_favorite".to_string(),
             Value::Bool(track.is_favorite(user_id)),
         );
+        insert_image_hints(map, CardImage::Thumbnail);
     }
 
     value
 }
 
-fn seconds_to_time_string(seconds: i64) -> String {
+pub(crate) fn seconds_to_time_string(seconds: i64) -> String {
     let hours = seconds / 3600;
     let minutes = (seconds % 3600) / 60;
     let secs = seconds % 60;
@@ -280,75 +522,3 @@ fn seconds_to_time_string(seconds: i64) -> String {
         format!("{:02}:{:02}", minutes, secs)
     }
 }
-
-async fn require_user(req: &HttpRequest) -> Result<User, HttpResponse> {
-    let token = match access_token(req) {
-        Ok(Some(t)) => t,
-        Ok(None) => {
-            return Err(HttpResponse::Unauthorized().json(json!({
-                "msg": "Not authenticated"
-            })));
-        }
-        Err(resp) => return Err(resp),
-    };
-
-    let config = match UserConfig::load() {
-        Ok(cfg) => cfg,
-        Err(_) => {
-            return Err(HttpResponse::InternalServerError().json(json!({
-                "error": "Config error"
-            })));
-        }
-    };
-
-    let claims = match verify_jwt(&token, &config.server_id, Some("access")) {
-        Ok(c) => c,
-        Err(_) => {
-            return Err(HttpResponse::Unauthorized().json(json!({
-                "msg": "Invalid token"
-            })));
-        }
-    };
-
-    match UserTable::get_by_id(claims.sub.id).await {
-        Ok(Some(user)) => Ok(user),
-        Ok(None) => Err(HttpResponse::Unauthorized().json(json!({
-            "msg": "Invalid token"
-        }))),
-        Err(_) => Err(HttpResponse::InternalServerError().json(json!({
-            "msg": "Database error"
-        }))),
-    }
-}
-
-fn access_token(req: &HttpRequest) -> Result<Option<String>, HttpResponse> {
-    if let Some(cookie) = req.cookie("access_token_cookie") {
-        return Ok(Some(cookie.value().to_string()));
-    }
-
-    match req.headers().get("Authorization") {
-        Some(header_value) => {
-            let header_str = header_value.to_str().unwrap_or("").trim();
-            if header_str.is_empty() {
-                return Err(
-                    HttpResponse::Unauthorized().json(json!({ "error": "Invalid token format" }))
-                );
-            }
-
-            let token = if let Some(rest) = header_str.strip_prefix("Bearer ") {
-                rest
-            } else {
-                header_str
-            };
-
-            if token.is_empty() {
-                return Err(
-                    HttpResponse::Unauthorized().json(json!({ "error": "Invalid token format" }))
-                );
-            }
-
-            Ok(Some(token.to_string()))
-        }
-        None => Ok(None),
-    }
-}
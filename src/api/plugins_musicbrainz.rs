@@ -0,0 +1,206 @@
+//! musicbrainz plugin routes for matching tracks and applying corrected metadata
+
+use actix_web::{post, web, HttpResponse, Responder};
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::json;
+use std::path::Path;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::api::identity::{Authorized, CurrentUser, EditTags};
+use crate::core::populate::reindex_track_files;
+use crate::db::tables::MbidTable;
+use crate::models::{ArtistRefItem, Track};
+use crate::plugins::musicbrainz::{MatchCandidate, MbArtist};
+use crate::plugins::MusicBrainzPlugin;
+use crate::stores::{AlbumStore, ArtistStore, TrackStore};
+use crate::utils::hashing::create_hash;
+
+const DEFAULT_MATCH_LIMIT: usize = 5;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MatchRequest {
+    pub trackhash: String,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ApplyMatchRequest {
+    pub trackhash: String,
+    pub recording_mbid: String,
+    #[serde(default)]
+    pub release_mbid: Option<String>,
+    /// write the corrected tags and ids to the file
+    #[serde(default)]
+    pub write_tags: bool,
+}
+
+/// POST /plugins/musicbrainz/match
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found"),
+        (status = 502, description = "Upstream service failed")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[post("/match")]
+pub async fn find_matches(_user: CurrentUser, body: web::Json<MatchRequest>) -> impl Responder {
+    let plugin = match load_plugin().await {
+        Ok(p) => p,
+        Err(resp) => return resp,
+    };
+
+    let Some(track) = TrackStore::get().get_by_hash(&body.trackhash) else {
+        return HttpResponse::NotFound().json(json!({"error": "Track not found"}));
+    };
+
+    let limit = body.limit.unwrap_or(DEFAULT_MATCH_LIMIT).clamp(1, 25);
+    match plugin.find_matches(&track, limit).await {
+        Ok(matches) => HttpResponse::Ok().json(json!({
+            "trackhash": track.trackhash,
+            "mbid": track.mbid,
+            "matches": matches,
+        })),
+        Err(e) => HttpResponse::BadGateway()
+            .json(json!({"error": format!("MusicBrainz lookup failed: {}", e)})),
+    }
+}
+
+/// POST /plugins/musicbrainz/match/apply (admin only)
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error"),
+        (status = 502, description = "Upstream service failed")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[post("/match/apply")]
+pub async fn apply_match(
+    _user: Authorized<EditTags>,
+    body: web::Json<ApplyMatchRequest>,
+) -> impl Responder {
+    let plugin = match load_plugin().await {
+        Ok(p) => p,
+        Err(resp) => return resp,
+    };
+
+    let Some(track) = TrackStore::get().get_by_hash(&body.trackhash) else {
+        return HttpResponse::NotFound().json(json!({"error": "Track not found"}));
+    };
+
+    let candidate = match plugin
+        .get_recording(&body.recording_mbid, body.release_mbid.as_deref())
+        .await
+    {
+        Ok(c) => c,
+        Err(e) => {
+            return HttpResponse::BadGateway()
+                .json(json!({"error": format!("MusicBrainz lookup failed: {}", e)}))
+        }
+    };
+
+    match apply_candidate(track, &candidate, body.write_tags).await {
+        Ok(track) => HttpResponse::Ok().json(json!({
+            "trackhash": track.trackhash,
+            "albumhash": track.albumhash,
+            "mbid": track.mbid,
+            "match": candidate,
+        })),
+        Err(e) => HttpResponse::InternalServerError()
+            .json(json!({"error": format!("Failed to apply match: {}", e)})),
+    }
+}
+
+/// OpenAPI description of the plugins musicbrainz routes
+#[derive(OpenApi)]
+#[openapi(paths(find_matches, apply_match,))]
+pub struct ApiDoc;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(find_matches).service(apply_match);
+}
+
+async fn load_plugin() -> Result<MusicBrainzPlugin, HttpResponse> {
+    match MusicBrainzPlugin::load().await {
+        Ok(Some(plugin)) => Ok(plugin),
+        Ok(None) => {
+            Err(HttpResponse::BadRequest()
+                .json(json!({"error": "MusicBrainz plugin is not active"})))
+        }
+        Err(e) => Err(HttpResponse::InternalServerError()
+            .json(json!({"error": format!("Failed to load plugin: {}", e)}))),
+    }
+}
+
+/// Optionally retag the file, then store the ids on the track, album and artists
+async fn apply_candidate(
+    mut track: Track,
+    candidate: &MatchCandidate,
+    write_tags: bool,
+) -> Result<Track> {
+    if write_tags {
+        write_candidate_tags(&track.filepath, candidate).await?;
+
+        // retagging can change the hashes so continue with the reindexed track
+        let reindexed = reindex_track_files(std::slice::from_ref(&track.filepath)).await?;
+        if let Some(updated) = reindexed.into_iter().find(|t| t.filepath == track.filepath) {
+            track = updated;
+        }
+    }
+
+    track.mbid = candidate.recording_mbid.clone();
+    MbidTable::upsert(&track.trackhash, "track", &candidate.recording_mbid).await?;
+    TrackStore::get().set_mbid(&track.trackhash, &candidate.recording_mbid);
+
+    if !candidate.release_mbid.is_empty() {
+        MbidTable::upsert(&track.albumhash, "album", &candidate.release_mbid).await?;
+        AlbumStore::get().set_mbid(&track.albumhash, &candidate.release_mbid);
+    }
+
+    let mut matched = match_artists(&track.artists, &candidate.artists);
+    matched.extend(match_artists(&track.albumartists, &candidate.albumartists));
+    for (artisthash, mbid) in matched {
+        MbidTable::upsert(&artisthash, "artist", &mbid).await?;
+        ArtistStore::get().set_mbid(&artisthash, &mbid);
+    }
+
+    Ok(track)
+}
+
+async fn write_candidate_tags(filepath: &str, candidate: &MatchCandidate) -> Result<()> {
+    let path = Path::new(filepath).to_path_buf();
+    let candidate = candidate.clone();
+
+    tokio::task::spawn_blocking(move || candidate.write_tags(&path)).await?
+}
+
+/// Pair library artists with credited artists by name, or by position when the
+/// credit lists line up but the names differ
+fn match_artists(library: &[ArtistRefItem], credited: &[MbArtist]) -> Vec<(String, String)> {
+    let by_name: Vec<(String, String)> = library
+        .iter()
+        .filter_map(|artist| {
+            credited
+                .iter()
+                .find(|c| create_hash(&[&c.name], true) == artist.artisthash)
+                .map(|c| (artist.artisthash.clone(), c.mbid.clone()))
+        })
+        .collect();
+
+    if by_name.is_empty() && library.len() == credited.len() {
+        return library
+            .iter()
+            .zip(credited)
+            .map(|(artist, c)| (artist.artisthash.clone(), c.mbid.clone()))
+            .collect();
+    }
+
+    by_name
+}
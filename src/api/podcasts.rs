@@ -0,0 +1,390 @@
+//! Podcast API routes
+
+use actix_files::NamedFile;
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::api::identity::CurrentUser;
+use crate::core::podcasts;
+use crate::db::tables::PodcastTable;
+use crate::models::{EpisodeProgress, Podcast, PodcastEpisode};
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SubscribeBody {
+    pub url: String,
+}
+
+fn default_limit() -> i64 {
+    50
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct EpisodesQuery {
+    #[serde(default)]
+    pub start: i64,
+    /// -1 returns every episode
+    #[serde(default = "default_limit")]
+    pub limit: i64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ProgressBody {
+    /// playback position in seconds
+    pub position: i64,
+    #[serde(default)]
+    pub completed: Option<bool>,
+}
+
+fn server_error(e: anyhow::Error) -> HttpResponse {
+    tracing::error!("Podcast query failed: {}", e);
+    HttpResponse::InternalServerError().json(json!({"error": "Failed! An error occured"}))
+}
+
+fn not_found(what: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(json!({"error": format!("{} not found", what)}))
+}
+
+/// Podcast owned by the user, or the response to send instead
+async fn owned_podcast(id: i64, userid: i64) -> Result<Podcast, HttpResponse> {
+    match PodcastTable::get(id, userid).await {
+        Ok(Some(podcast)) => Ok(podcast),
+        Ok(None) => Err(not_found("Podcast")),
+        Err(e) => Err(server_error(e)),
+    }
+}
+
+/// Episode of a podcast owned by the user, or the response to send instead
+async fn owned_episode(id: i64, userid: i64) -> Result<PodcastEpisode, HttpResponse> {
+    match PodcastTable::get_episode(id).await {
+        Ok(Some((episode, owner))) if owner == userid => Ok(episode),
+        Ok(_) => Err(not_found("Episode")),
+        Err(e) => Err(server_error(e)),
+    }
+}
+
+fn serialize_episode(episode: &PodcastEpisode, progress: Option<&EpisodeProgress>) -> Value {
+    let mut value = serde_json::to_value(episode).unwrap_or_else(|_| json!({}));
+    if let Some(map) = value.as_object_mut() {
+        let progress = progress.copied().unwrap_or_default();
+        map.insert("position".to_string(), json!(progress.position));
+        map.insert("completed".to_string(), json!(progress.completed));
+        map.insert(
+            "downloaded".to_string(),
+            json!(podcasts::local_file(episode).is_some()),
+        );
+        map.insert(
+            "downloading".to_string(),
+            json!(podcasts::is_downloading(episode.id)),
+        );
+    }
+    value
+}
+
+/// GET /podcasts
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("")]
+pub async fn list_podcasts(user: CurrentUser) -> impl Responder {
+    let podcasts = match PodcastTable::all(Some(user.id)).await {
+        Ok(p) => p,
+        Err(e) => return server_error(e),
+    };
+
+    let mut items = Vec::with_capacity(podcasts.len());
+    for podcast in podcasts {
+        let count = PodcastTable::episode_count(podcast.id).await.unwrap_or(0);
+        let mut value = serde_json::to_value(&podcast).unwrap_or_else(|_| json!({}));
+        if let Some(map) = value.as_object_mut() {
+            map.insert("episode_count".to_string(), json!(count));
+        }
+        items.push(value);
+    }
+
+    HttpResponse::Ok().json(json!({"podcasts": items}))
+}
+
+/// POST /podcasts
+///
+/// Subscribe to an RSS feed
+#[utoipa::path(
+    responses(
+        (status = 201, description = "Created"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[post("")]
+pub async fn subscribe(user: CurrentUser, body: web::Json<SubscribeBody>) -> impl Responder {
+    match podcasts::subscribe(user.id, &body.url).await {
+        Ok((podcast, count)) => HttpResponse::Created().json(json!({
+            "podcast": podcast,
+            "episode_count": count,
+        })),
+        Err(e) => HttpResponse::BadRequest().json(json!({"error": e.to_string()})),
+    }
+}
+
+/// GET /podcasts/{id}
+///
+/// Podcast details with a page of its episodes and the user's progress
+#[utoipa::path(
+    params(EpisodesQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/{id}")]
+pub async fn get_podcast(
+    user: CurrentUser,
+    path: web::Path<i64>,
+    query: web::Query<EpisodesQuery>,
+) -> impl Responder {
+    let podcast = match owned_podcast(path.into_inner(), user.id).await {
+        Ok(p) => p,
+        Err(response) => return response,
+    };
+
+    let episodes = match PodcastTable::episodes(podcast.id, query.start, query.limit).await {
+        Ok(e) => e,
+        Err(e) => return server_error(e),
+    };
+    let total = PodcastTable::episode_count(podcast.id).await.unwrap_or(0);
+    let progress = PodcastTable::progress(user.id, podcast.id)
+        .await
+        .unwrap_or_default();
+
+    let episodes: Vec<Value> = episodes
+        .iter()
+        .map(|e| serialize_episode(e, progress.get(&e.id)))
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "podcast": podcast,
+        "episodes": episodes,
+        "total": total,
+    }))
+}
+
+/// DELETE /podcasts/{id}
+///
+/// Unsubscribe, removing the episodes and downloaded files
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[delete("/{id}")]
+pub async fn unsubscribe(user: CurrentUser, path: web::Path<i64>) -> impl Responder {
+    let podcast = match owned_podcast(path.into_inner(), user.id).await {
+        Ok(p) => p,
+        Err(response) => return response,
+    };
+
+    match podcasts::unsubscribe(&podcast).await {
+        Ok(()) => HttpResponse::Ok().json(json!({"msg": "Unsubscribed"})),
+        Err(e) => server_error(e),
+    }
+}
+
+/// POST /podcasts/{id}/refresh
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 502, description = "Upstream service failed")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[post("/{id}/refresh")]
+pub async fn refresh_podcast(user: CurrentUser, path: web::Path<i64>) -> impl Responder {
+    let mut podcast = match owned_podcast(path.into_inner(), user.id).await {
+        Ok(p) => p,
+        Err(response) => return response,
+    };
+
+    match podcasts::refresh(&mut podcast).await {
+        Ok(new) => HttpResponse::Ok().json(json!({"podcast": podcast, "new_episodes": new})),
+        Err(e) => HttpResponse::BadGateway()
+            .json(json!({"error": format!("Failed to refresh feed: {}", e)})),
+    }
+}
+
+/// GET /podcasts/episodes/{id}/stream
+///
+/// Serve the downloaded file, or proxy the episode with range support
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error"),
+        (status = 502, description = "Upstream service failed")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/episodes/{id}/stream")]
+pub async fn stream_episode(
+    user: CurrentUser,
+    path: web::Path<i64>,
+    req: HttpRequest,
+) -> impl Responder {
+    let episode = match owned_episode(path.into_inner(), user.id).await {
+        Ok(e) => e,
+        Err(response) => return response,
+    };
+
+    if let Some(file) = podcasts::local_file(&episode) {
+        return match NamedFile::open_async(file).await {
+            Ok(named) => named.into_response(&req),
+            Err(_) => HttpResponse::InternalServerError().body("Failed to open episode"),
+        };
+    }
+
+    let range = req.headers().get("Range").and_then(|v| v.to_str().ok());
+    let upstream = match podcasts::open_remote(&episode, range).await {
+        Ok(r) => r,
+        Err(e) => {
+            return HttpResponse::BadGateway()
+                .json(json!({"error": format!("Episode unavailable: {}", e)}))
+        }
+    };
+
+    let status = actix_web::http::StatusCode::from_u16(upstream.status().as_u16())
+        .unwrap_or(actix_web::http::StatusCode::OK);
+    let mut response = HttpResponse::build(status);
+    for name in [
+        "content-type",
+        "content-length",
+        "content-range",
+        "accept-ranges",
+    ] {
+        if let Some(value) = upstream.headers().get(name).and_then(|v| v.to_str().ok()) {
+            response.insert_header((name, value.to_string()));
+        }
+    }
+    if !upstream.headers().contains_key("content-type") && !episode.mime_type.is_empty() {
+        response.insert_header(("content-type", episode.mime_type.clone()));
+    }
+
+    response.streaming(podcasts::response_stream(upstream))
+}
+
+/// POST /podcasts/episodes/{id}/download
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 202, description = "Accepted"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[post("/episodes/{id}/download")]
+pub async fn download_episode(user: CurrentUser, path: web::Path<i64>) -> impl Responder {
+    let episode = match owned_episode(path.into_inner(), user.id).await {
+        Ok(e) => e,
+        Err(response) => return response,
+    };
+
+    if podcasts::local_file(&episode).is_some() {
+        return HttpResponse::Ok().json(json!({"msg": "Episode already downloaded"}));
+    }
+    if !podcasts::spawn_download(episode) {
+        return HttpResponse::Ok().json(json!({"msg": "Episode is downloading"}));
+    }
+    HttpResponse::Accepted().json(json!({"msg": "Download started"}))
+}
+
+/// DELETE /podcasts/episodes/{id}/download
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[delete("/episodes/{id}/download")]
+pub async fn remove_download(user: CurrentUser, path: web::Path<i64>) -> impl Responder {
+    let episode = match owned_episode(path.into_inner(), user.id).await {
+        Ok(e) => e,
+        Err(response) => return response,
+    };
+
+    match podcasts::remove_download(&episode).await {
+        Ok(()) => HttpResponse::Ok().json(json!({"msg": "Download removed"})),
+        Err(e) => server_error(e),
+    }
+}
+
+/// PUT /podcasts/episodes/{id}/progress
+///
+/// Save the user's playback position, completed defaults to reaching the end
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[put("/episodes/{id}/progress")]
+pub async fn set_progress(
+    user: CurrentUser,
+    path: web::Path<i64>,
+    body: web::Json<ProgressBody>,
+) -> impl Responder {
+    let episode = match owned_episode(path.into_inner(), user.id).await {
+        Ok(e) => e,
+        Err(response) => return response,
+    };
+
+    let position = body.position.max(0);
+    let reached_end = episode.duration > 0 && position >= episode.duration;
+    let progress = EpisodeProgress {
+        position,
+        completed: body.completed.unwrap_or(reached_end),
+        updated: chrono::Utc::now().timestamp(),
+    };
+
+    match PodcastTable::set_progress(user.id, episode.id, &progress).await {
+        Ok(()) => HttpResponse::Ok().json(json!({"progress": progress})),
+        Err(e) => server_error(e),
+    }
+}
+
+/// OpenAPI description of the podcasts routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    list_podcasts,
+    subscribe,
+    stream_episode,
+    download_episode,
+    remove_download,
+    set_progress,
+    get_podcast,
+    unsubscribe,
+    refresh_podcast,
+))]
+pub struct ApiDoc;
+
+/// Configure podcast routes
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_podcasts)
+        .service(subscribe)
+        .service(stream_episode)
+        .service(download_episode)
+        .service(remove_download)
+        .service(set_progress)
+        .service(get_podcast)
+        .service(unsubscribe)
+        .service(refresh_podcast);
+}
@@ -0,0 +1,91 @@
+//! Queue API routes
+//!
+//! clients that run out of queued tracks ask for the next batch here instead
+//! of building radio style continuations themselves.
+
+use actix_web::{post, web, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashSet;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::api::identity::CurrentUser;
+use crate::api::track::serialize_for_user;
+use crate::core::recipes::{Recipes, QUEUE_SEED_TRACKS};
+
+/// Largest batch a single request returns
+const MAX_BATCH: usize = 100;
+
+/// Queue continuation request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ContinueQueueBody {
+    /// trackhashes played last, oldest first
+    #[serde(default)]
+    pub recent: Vec<String>,
+    /// token the queue was started from, like `al:<albumhash>` or `favorite`
+    #[serde(default)]
+    pub source: String,
+    /// trackhashes still queued, they are not suggested again
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+fn default_limit() -> usize {
+    20
+}
+
+/// POST /queue/continue - the next batch of tracks for a queue that ran out
+#[utoipa::path(
+    request_body = ContinueQueueBody,
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[post("/continue")]
+pub async fn continue_queue(
+    user: CurrentUser,
+    body: web::Json<ContinueQueueBody>,
+) -> impl Responder {
+    let body = body.into_inner();
+    if body.recent.is_empty() && body.source.trim().is_empty() {
+        return HttpResponse::BadRequest()
+            .json(json!({"msg": "Send the recently played tracks or the queue source"}));
+    }
+
+    // only the latest plays steer the continuation, older ones just stay out
+    let mut exclude: HashSet<String> = body.exclude.into_iter().collect();
+    let seed_from = body.recent.len().saturating_sub(QUEUE_SEED_TRACKS);
+    exclude.extend(body.recent[..seed_from].iter().cloned());
+
+    let limit = body.limit.clamp(1, MAX_BATCH);
+    let tracks = Recipes::continue_queue(
+        &body.recent[seed_from..],
+        body.source.trim(),
+        &exclude,
+        limit,
+        user.id,
+    )
+    .await;
+
+    let tracks = serialize_for_user(tracks, user.id);
+    HttpResponse::Ok().json(json!({
+        "source": body.source,
+        "count": tracks.len(),
+        "tracks": tracks,
+    }))
+}
+
+/// OpenAPI description of the queue routes
+#[derive(OpenApi)]
+#[openapi(paths(continue_queue))]
+pub struct ApiDoc;
+
+/// Configure queue routes
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(continue_queue);
+}
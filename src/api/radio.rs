@@ -0,0 +1,267 @@
+//! Internet radio API routes
+
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::api::identity::{Admin, Authorized, CurrentUser};
+use crate::core::radio;
+use crate::db::tables::RadioTable;
+use crate::models::RadioStation;
+
+/// Station fields sent when adding or editing a station
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct StationBody {
+    pub name: Option<String>,
+    pub url: Option<String>,
+    pub homepage: Option<String>,
+    pub image: Option<String>,
+    pub genre: Option<String>,
+}
+
+impl StationBody {
+    /// Copy the fields that were sent onto a station
+    fn apply(self, station: &mut RadioStation) -> Result<(), HttpResponse> {
+        if let Some(name) = self.name {
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(HttpResponse::BadRequest()
+                    .json(json!({"error": "Station name cannot be empty"})));
+            }
+            station.name = name.to_string();
+        }
+        if let Some(url) = self.url {
+            match radio::validate_url(&url) {
+                Ok(url) => station.url = url.to_string(),
+                Err(e) => {
+                    return Err(HttpResponse::BadRequest().json(json!({"error": e.to_string()})))
+                }
+            }
+        }
+        if let Some(homepage) = self.homepage {
+            station.homepage = homepage.trim().to_string();
+        }
+        if let Some(image) = self.image {
+            station.image = image.trim().to_string();
+        }
+        if let Some(genre) = self.genre {
+            station.genre = genre.trim().to_string();
+        }
+        Ok(())
+    }
+}
+
+fn server_error(e: anyhow::Error) -> HttpResponse {
+    tracing::error!("Radio station query failed: {}", e);
+    HttpResponse::InternalServerError().json(json!({"error": "Failed! An error occured"}))
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(json!({"error": "Station not found"}))
+}
+
+/// GET /radio
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("")]
+pub async fn list_stations(user: CurrentUser) -> impl Responder {
+    match RadioTable::all(user.id).await {
+        Ok(stations) => HttpResponse::Ok().json(json!({"stations": stations})),
+        Err(e) => server_error(e),
+    }
+}
+
+/// POST /radio
+#[utoipa::path(
+    responses(
+        (status = 201, description = "Created"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[post("")]
+pub async fn add_station(admin: Authorized<Admin>, body: web::Json<StationBody>) -> impl Responder {
+    let body = body.into_inner();
+    if body.name.is_none() || body.url.is_none() {
+        return HttpResponse::BadRequest()
+            .json(json!({"error": "Station name and url are required"}));
+    }
+
+    let mut station = RadioStation::new(String::new(), String::new(), admin.user.id);
+    if let Err(response) = body.apply(&mut station) {
+        return response;
+    }
+
+    match RadioTable::insert(&station).await {
+        Ok(id) => {
+            station.id = id;
+            HttpResponse::Created().json(json!({"station": station}))
+        }
+        Err(e) => server_error(e),
+    }
+}
+
+/// GET /radio/{id}
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/{id}")]
+pub async fn get_station(user: CurrentUser, path: web::Path<i64>) -> impl Responder {
+    match RadioTable::get(path.into_inner(), user.id).await {
+        Ok(Some(station)) => {
+            let now_playing = radio::now_playing(station.id);
+            HttpResponse::Ok().json(json!({"station": station, "now_playing": now_playing}))
+        }
+        Ok(None) => not_found(),
+        Err(e) => server_error(e),
+    }
+}
+
+/// PUT /radio/{id}
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[put("/{id}")]
+pub async fn update_station(
+    admin: Authorized<Admin>,
+    path: web::Path<i64>,
+    body: web::Json<StationBody>,
+) -> impl Responder {
+    let mut station = match RadioTable::get(path.into_inner(), admin.user.id).await {
+        Ok(Some(station)) => station,
+        Ok(None) => return not_found(),
+        Err(e) => return server_error(e),
+    };
+
+    let url = station.url.clone();
+    if let Err(response) = body.into_inner().apply(&mut station) {
+        return response;
+    }
+
+    match RadioTable::update(&station).await {
+        Ok(true) => {
+            if station.url != url {
+                radio::forget(station.id);
+            }
+            HttpResponse::Ok().json(json!({"station": station}))
+        }
+        Ok(false) => not_found(),
+        Err(e) => server_error(e),
+    }
+}
+
+/// DELETE /radio/{id}
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[delete("/{id}")]
+pub async fn delete_station(user: CurrentUser, path: web::Path<i64>) -> impl Responder {
+    let id = path.into_inner();
+    match RadioTable::delete(id, user.id).await {
+        Ok(true) => {
+            radio::forget(id);
+            HttpResponse::Ok().json(json!({"msg": "Station deleted"}))
+        }
+        Ok(false) => not_found(),
+        Err(e) => server_error(e),
+    }
+}
+
+/// GET /radio/{id}/stream
+///
+/// Proxy the station audio, the icy metadata is kept for `/nowplaying`
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 502, description = "Upstream service failed")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/{id}/stream")]
+pub async fn stream_station(user: CurrentUser, path: web::Path<i64>) -> impl Responder {
+    let station = match RadioTable::get(path.into_inner(), user.id).await {
+        Ok(Some(station)) => station,
+        Ok(None) => return not_found(),
+        Err(e) => return server_error(e),
+    };
+
+    let stream = match radio::open(&station.url).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            tracing::warn!("Failed to open radio station {}: {}", station.url, e);
+            return HttpResponse::BadGateway()
+                .json(json!({"error": format!("Station unavailable: {}", e)}));
+        }
+    };
+
+    HttpResponse::Ok()
+        .insert_header(("Content-Type", stream.content_type.clone()))
+        .insert_header(("Cache-Control", "no-cache, no-store"))
+        .streaming(radio::audio_stream(station.id, stream))
+}
+
+/// GET /radio/{id}/nowplaying
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/{id}/nowplaying")]
+pub async fn station_now_playing(user: CurrentUser, path: web::Path<i64>) -> impl Responder {
+    match RadioTable::get(path.into_inner(), user.id).await {
+        Ok(Some(station)) => {
+            HttpResponse::Ok().json(json!({"now_playing": radio::now_playing(station.id)}))
+        }
+        Ok(None) => not_found(),
+        Err(e) => server_error(e),
+    }
+}
+
+/// OpenAPI description of the radio routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    list_stations,
+    add_station,
+    get_station,
+    update_station,
+    delete_station,
+    stream_station,
+    station_now_playing,
+))]
+pub struct ApiDoc;
+
+/// Configure radio routes
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_stations)
+        .service(add_station)
+        .service(get_station)
+        .service(update_station)
+        .service(delete_station)
+        .service(stream_station)
+        .service(station_now_playing);
+}
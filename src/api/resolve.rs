@@ -0,0 +1,123 @@
+//! Resolve API route - cards for hashes of mixed types in one request
+
+use actix_web::{post, web, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use utoipa::{OpenApi, ToSchema};
+
+use crate::api::favorites::{serialize_album_card, serialize_artist_card, serialize_track};
+use crate::api::identity::CurrentUser;
+use crate::api::playlist::serialize_playlist_card;
+use crate::db::tables::PlaylistTable;
+use crate::stores::{AlbumStore, ArtistStore, PlayStatsStore, TrackStore};
+
+/// Most items resolved in one request
+const MAX_RESOLVE_ITEMS: usize = 200;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ResolveBody {
+    pub items: Vec<ResolveItem>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ResolveItem {
+    #[serde(rename = "type")]
+    pub item_type: String,
+    pub hash: String,
+}
+
+/// POST /resolve
+///
+/// items come back in request order, an item that no longer exists or has an
+/// unknown type resolves to null
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[post("")]
+pub async fn resolve_items(user: CurrentUser, body: web::Json<ResolveBody>) -> impl Responder {
+    if body.items.len() > MAX_RESOLVE_ITEMS {
+        return HttpResponse::BadRequest().json(json!({
+            "error": format!("At most {} items can be resolved at once", MAX_RESOLVE_ITEMS)
+        }));
+    }
+
+    let mut items = Vec::with_capacity(body.items.len());
+    for item in &body.items {
+        let resolved = resolve(item, user.id).await;
+        items.push(json!({
+            "type": item.item_type,
+            "hash": item.hash,
+            "item": resolved,
+        }));
+    }
+
+    HttpResponse::Ok().json(json!({ "items": items }))
+}
+
+async fn resolve(item: &ResolveItem, user_id: i64) -> Option<Value> {
+    let stats = PlayStatsStore::get();
+
+    match item.item_type.as_str() {
+        "track" => {
+            let mut tracks = vec![TrackStore::get().get_by_hash(&item.hash)?];
+            stats.personalize_tracks(user_id, &mut tracks);
+            Some(Value::Object(serialize_track(&tracks[0], user_id)))
+        }
+        "album" => {
+            let mut albums = vec![AlbumStore::get().get_by_hash(&item.hash)?];
+            stats.personalize_albums(user_id, &mut albums);
+            let is_favorite = albums[0].is_favorite(user_id);
+            let mut card = serialize_album_card(&mut albums[0]);
+            card.insert("is_favorite".to_string(), json!(is_favorite));
+            Some(Value::Object(card))
+        }
+        "artist" => {
+            let mut artists = vec![ArtistStore::get().get_by_hash(&item.hash)?];
+            stats.personalize_artists(user_id, &mut artists);
+            let is_favorite = artists[0].is_favorite(user_id);
+            let mut card = serialize_artist_card(&mut artists[0]);
+            card.insert("is_favorite".to_string(), json!(is_favorite));
+            Some(Value::Object(card))
+        }
+        "playlist" => {
+            let id = item.hash.parse::<i64>().ok()?;
+            let playlist = PlaylistTable::get_by_id(id).await.ok().flatten()?;
+            if !playlist.can_view(user_id) {
+                return None;
+            }
+            Some(serialize_playlist_card(playlist, user_id))
+        }
+        "folder" => {
+            let path = item.hash.as_str();
+            let count = TrackStore::get().count_by_folder(path);
+            if count == 0 {
+                return None;
+            }
+            let name = std::path::Path::new(path)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.to_string());
+            Some(json!({
+                "path": path,
+                "name": name,
+                "trackcount": count,
+            }))
+        }
+        _ => None,
+    }
+}
+
+/// OpenAPI description of the resolve routes
+#[derive(OpenApi)]
+#[openapi(paths(resolve_items,))]
+pub struct ApiDoc;
+
+/// Configure resolve routes
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(resolve_items);
+}
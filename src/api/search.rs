@@ -4,15 +4,18 @@
 
 use actix_web::{get, web, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi};
 
+use crate::api::identity::CurrentUser;
+use crate::config::UserConfig;
 use crate::core::SearchLib;
 use crate::models::{Album, Artist, Track};
-use crate::stores::{AlbumStore, TrackStore};
+use crate::stores::{AlbumStore, PlayStatsStore, TrackStore};
 
 const SEARCH_COUNT: usize = 30;
 
 /// search query parameters for get top results
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct TopResultsQuery {
     pub q: String,
     #[serde(default = "default_top_limit")]
@@ -24,7 +27,7 @@ fn default_top_limit() -> usize {
 }
 
 /// search query parameters for load more results
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct SearchLoadMoreQuery {
     pub q: String,
     pub itemtype: String,
@@ -38,6 +41,13 @@ fn default_search_limit() -> usize {
     SEARCH_COUNT
 }
 
+/// weight of the user's listening history in result ranking
+fn personal_boost() -> f64 {
+    UserConfig::load()
+        .map(|c| c.search_personal_boost)
+        .unwrap_or(0.0)
+}
+
 /// serialized track for search results
 #[derive(Debug, Clone, Serialize)]
 pub struct TrackSearchResult {
@@ -56,8 +66,10 @@ pub struct TrackSearchResult {
     pub is_favorite: bool,
 }
 
-impl From<Track> for TrackSearchResult {
-    fn from(track: Track) -> Self {
+impl TrackSearchResult {
+    /// serialize a track with the favorite flag of the given user
+    pub fn for_user(track: Track, user_id: i64) -> Self {
+        let is_favorite = track.is_favorite(user_id);
         let image = if track.image.is_empty() {
             format!("{}.webp", track.albumhash)
         } else {
@@ -77,7 +89,7 @@ impl From<Track> for TrackSearchResult {
             og_title: if track.og_title.is_empty() { None } else { Some(track.og_title) },
             filepath: track.filepath,
             bitrate: track.bitrate,
-            is_favorite: false,
+            is_favorite,
         }
     }
 }
@@ -90,6 +102,8 @@ pub struct AlbumSearchResult {
     pub albumartists: Vec<ArtistRefResult>,
     pub image: String,
     pub color: String,
+    pub color_dark: String,
+    pub color_light: String,
     #[serde(rename = "type")]
     pub album_type: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -98,8 +112,10 @@ pub struct AlbumSearchResult {
     pub is_favorite: bool,
 }
 
-impl From<Album> for AlbumSearchResult {
-    fn from(album: Album) -> Self {
+impl AlbumSearchResult {
+    /// serialize an album with the favorite flag of the given user
+    pub fn for_user(album: Album, user_id: i64) -> Self {
+        let is_favorite = album.is_favorite(user_id);
         let image = if album.image.is_empty() {
             format!("{}.webp", album.albumhash)
         } else {
@@ -114,9 +130,11 @@ impl From<Album> for AlbumSearchResult {
             }).collect(),
             image,
             color: album.color,
+            color_dark: album.color_dark,
+            color_light: album.color_light,
             album_type: album.album_type.to_string(),
             help_text: if album.help_text.is_empty() { None } else { Some(album.help_text) },
-            is_favorite: false,
+            is_favorite,
         }
     }
 }
@@ -137,8 +155,10 @@ pub struct ArtistSearchResult {
     pub is_favorite: bool,
 }
 
-impl From<Artist> for ArtistSearchResult {
-    fn from(artist: Artist) -> Self {
+impl ArtistSearchResult {
+    /// serialize an artist with the favorite flag of the given user
+    pub fn for_user(artist: Artist, user_id: i64) -> Self {
+        let is_favorite = artist.is_favorite(user_id);
         let image = if artist.image.is_empty() {
             format!("{}.webp", artist.artisthash)
         } else {
@@ -151,7 +171,7 @@ impl From<Artist> for ArtistSearchResult {
             albumcount: Some(artist.albumcount),
             trackcount: Some(artist.trackcount),
             help_text: if artist.help_text.is_empty() { None } else { Some(artist.help_text) },
-            is_favorite: false,
+            is_favorite,
         }
     }
 }
@@ -212,8 +232,17 @@ impl ScoredItem {
 /// get top results
 /// 
 /// returns the top results for the given query matching upstream behavior
+#[utoipa::path(
+    params(TopResultsQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/top")]
-pub async fn get_top_results(query: web::Query<TopResultsQuery>) -> impl Responder {
+pub async fn get_top_results(user: CurrentUser, query: web::Query<TopResultsQuery>) -> impl Responder {
     if query.q.is_empty() {
         return HttpResponse::BadRequest().json(serde_json::json!({"error": "No query provided"}));
     }
@@ -222,9 +251,10 @@ pub async fn get_top_results(query: web::Query<TopResultsQuery>) -> impl Respond
     let tracks_limit = 4;
 
     // search all stores individually as each type has different scoring needs
-    let track_results = SearchLib::search_tracks(&query.q, 150);
-    let album_results = SearchLib::search_albums(&query.q, limit);
-    let artist_results = SearchLib::search_artists(&query.q, limit);
+    let boost = personal_boost();
+    let track_results = SearchLib::search_tracks_for_user(&query.q, 150, user.id, boost);
+    let album_results = SearchLib::search_albums_for_user(&query.q, limit, user.id, boost);
+    let artist_results = SearchLib::search_artists_for_user(&query.q, limit, user.id, boost);
 
     // combine all results and sort by score
     let mut all_results: Vec<ScoredItem> = Vec::new();
@@ -261,20 +291,24 @@ pub async fn get_top_results(query: web::Query<TopResultsQuery>) -> impl Respond
         ScoredItem::Album(album, _) => {
             // if top result is an album, get tracks from that album
             let store = TrackStore::get();
-            let album_tracks = store.get_by_album(&album.albumhash);
-            let mut sorted_tracks: Vec<Track> = album_tracks.into_iter()
+            let album_tracks = store.get_shared_by_album(&album.albumhash);
+            let mut sorted_tracks: Vec<Track> = album_tracks.iter()
                 .take(tracks_limit)
+                .map(|t| t.as_ref().clone())
                 .collect();
+            PlayStatsStore::get().personalize_tracks(user.id, &mut sorted_tracks);
             sorted_tracks.sort_by(|a, b| b.playduration.cmp(&a.playduration));
             top_tracks = sorted_tracks;
         }
         ScoredItem::Artist(artist, _) => {
             // if top result is an artist, get tracks and albums from that artist
             let track_store = TrackStore::get();
-            let artist_tracks = track_store.get_by_artist(&artist.artisthash);
-            let mut sorted_tracks: Vec<Track> = artist_tracks.into_iter()
+            let artist_tracks = track_store.get_shared_by_artist(&artist.artisthash);
+            let mut sorted_tracks: Vec<Track> = artist_tracks.iter()
                 .take(tracks_limit)
+                .map(|t| t.as_ref().clone())
                 .collect();
+            PlayStatsStore::get().personalize_tracks(user.id, &mut sorted_tracks);
             sorted_tracks.sort_by(|a, b| b.playduration.cmp(&a.playduration));
             top_tracks = sorted_tracks;
 
@@ -320,31 +354,31 @@ pub async fn get_top_results(query: web::Query<TopResultsQuery>) -> impl Respond
 
     // serialize results
     let tracks_serialized: Vec<TrackSearchResult> = top_tracks.into_iter()
-        .map(|t| t.into())
+        .map(|t| TrackSearchResult::for_user(t, user.id))
         .collect();
 
     let albums_serialized: Vec<AlbumSearchResult> = top_albums.into_iter()
-        .map(|a| a.into())
+        .map(|a| AlbumSearchResult::for_user(a, user.id))
         .collect();
 
     let artists_serialized: Vec<ArtistSearchResult> = artist_results.into_iter()
-        .map(|r| r.item.into())
+        .map(|r| ArtistSearchResult::for_user(r.item, user.id))
         .collect();
 
     // serialize top result with type field
     let top_result_json = match &all_results[0] {
         ScoredItem::Track(track, _) => {
-            let mut result = serde_json::to_value(TrackSearchResult::from(track.clone())).unwrap();
+            let mut result = serde_json::to_value(TrackSearchResult::for_user(track.clone(), user.id)).unwrap();
             result.as_object_mut().unwrap().insert("type".to_string(), serde_json::json!("track"));
             result
         }
         ScoredItem::Album(album, _) => {
-            let mut result = serde_json::to_value(AlbumSearchResult::from(album.clone())).unwrap();
+            let mut result = serde_json::to_value(AlbumSearchResult::for_user(album.clone(), user.id)).unwrap();
             result.as_object_mut().unwrap().insert("type".to_string(), serde_json::json!("album"));
             result
         }
         ScoredItem::Artist(artist, _) => {
-            let mut result = serde_json::to_value(ArtistSearchResult::from(artist.clone())).unwrap();
+            let mut result = serde_json::to_value(ArtistSearchResult::for_user(artist.clone(), user.id)).unwrap();
             result.as_object_mut().unwrap().insert("type".to_string(), serde_json::json!("artist"));
             result
         }
@@ -361,20 +395,30 @@ pub async fn get_top_results(query: web::Query<TopResultsQuery>) -> impl Respond
 /// search items with pagination
 ///
 /// find tracks, albums or artists from a search query with pagination support
+#[utoipa::path(
+    params(SearchLoadMoreQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("")]
-pub async fn search_items(query: web::Query<SearchLoadMoreQuery>) -> impl Responder {
+pub async fn search_items(user: CurrentUser, query: web::Query<SearchLoadMoreQuery>) -> impl Responder {
     if query.q.is_empty() {
         return HttpResponse::BadRequest().json(serde_json::json!({"error": "No query provided"}));
     }
 
+    let boost = personal_boost();
     match query.itemtype.as_str() {
         "tracks" => {
-            let all_results = SearchLib::search_tracks(&query.q, 150);
+            let all_results = SearchLib::search_tracks_for_user(&query.q, 150, user.id, boost);
             let total = all_results.len();
             let results: Vec<TrackSearchResult> = all_results.into_iter()
                 .skip(query.start)
                 .take(query.limit)
-                .map(|r| r.item.into())
+                .map(|r| TrackSearchResult::for_user(r.item, user.id))
                 .collect();
             let more = total > query.start + query.limit;
             
@@ -384,12 +428,12 @@ pub async fn search_items(query: web::Query<SearchLoadMoreQuery>) -> impl Respon
             })
         }
         "albums" => {
-            let all_results = SearchLib::search_albums(&query.q, 150);
+            let all_results = SearchLib::search_albums_for_user(&query.q, 150, user.id, boost);
             let total = all_results.len();
             let results: Vec<AlbumSearchResult> = all_results.into_iter()
                 .skip(query.start)
                 .take(query.limit)
-                .map(|r| r.item.into())
+                .map(|r| AlbumSearchResult::for_user(r.item, user.id))
                 .collect();
             let more = total > query.start + query.limit;
             
@@ -399,12 +443,12 @@ pub async fn search_items(query: web::Query<SearchLoadMoreQuery>) -> impl Respon
             })
         }
         "artists" => {
-            let all_results = SearchLib::search_artists(&query.q, 150);
+            let all_results = SearchLib::search_artists_for_user(&query.q, 150, user.id, boost);
             let total = all_results.len();
             let results: Vec<ArtistSearchResult> = all_results.into_iter()
                 .skip(query.start)
                 .take(query.limit)
-                .map(|r| r.item.into())
+                .map(|r| ArtistSearchResult::for_user(r.item, user.id))
                 .collect();
             let more = total > query.start + query.limit;
             
@@ -421,6 +465,14 @@ pub async fn search_items(query: web::Query<SearchLoadMoreQuery>) -> impl Respon
     }
 }
 
+/// OpenAPI description of the search routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    get_top_results,
+    search_items,
+))]
+pub struct ApiDoc;
+
 /// configure search routes
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(get_top_results)
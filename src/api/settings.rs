@@ -1,12 +1,29 @@
 //! Settings API routes
 
 use actix_web::{get, post, put, web, HttpRequest, HttpResponse, Responder};
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 use tracing::{error, info, warn};
 
-use crate::config::UserConfig;
-use crate::db::tables::{PluginTable, UserTable};
-use crate::utils::auth::verify_jwt;
+use crate::api::identity::{Admin, Authorized, CurrentUser};
+use crate::config::{
+    AlbumMergeRules, ArtistImageSettings, MixSettings, OidcSettings, SplitField,
+    ThumbnailSettings, UserConfig, WatchdogRootOptions,
+};
+use crate::core::file_cache::{self, MAX_STREAM_CHUNK_KIB, MIN_STREAM_CHUNK_KIB};
+use crate::core::indexer::{split_tag, ScanChanges, ScanKind, ScanProgress};
+use crate::core::{fingerprint, inbox, organizer};
+use crate::core::search::MAX_SEARCH_PERSONAL_BOOST;
+use crate::db::tables::{PluginTable, ScanHistoryTable};
+
+/// how often scan status websockets check for changes
+const SCAN_STATUS_PUSH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// wait before rescanning roots that had files still being copied
+const DEFERRED_SCAN_DELAY: Duration = Duration::from_secs(30);
 
 /// Settings response
 #[derive(Debug, Serialize)]
@@ -27,13 +44,19 @@ impl From<&UserConfig> for SettingsResponse {
 }
 
 /// Update settings request
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct UpdateSettingsRequest {
     pub root_dirs: Option<Vec<String>>,
     pub artist_separators: Option<Vec<String>>,
 }
 
 /// Get settings
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 500, description = "Server error")
+    )
+)]
 #[get("")]
 pub async fn get_settings() -> impl Responder {
     match UserConfig::load() {
@@ -45,8 +68,20 @@ pub async fn get_settings() -> impl Responder {
 }
 
 /// Update settings
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 #[put("")]
-pub async fn update_settings(body: web::Json<UpdateSettingsRequest>) -> impl Responder {
+pub async fn update_settings(
+    _admin: Authorized<Admin>,
+    body: web::Json<UpdateSettingsRequest>,
+) -> impl Responder {
     let mut config = match UserConfig::load() {
         Ok(c) => c,
         Err(e) => {
@@ -77,8 +112,21 @@ pub async fn update_settings(body: web::Json<UpdateSettingsRequest>) -> impl Res
 }
 
 /// Add root directory
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 #[post("/root-dirs")]
-pub async fn add_root_dir(body: web::Json<AddRootDirRequest>) -> impl Responder {
+pub async fn add_root_dir(
+    _admin: Authorized<Admin>,
+    body: web::Json<AddRootDirRequest>,
+) -> impl Responder {
     let mut config = match UserConfig::load() {
         Ok(c) => c,
         Err(e) => {
@@ -104,6 +152,8 @@ pub async fn add_root_dir(body: web::Json<AddRootDirRequest>) -> impl Responder
                 "error": format!("Failed to save settings: {}", e)
             }));
         }
+
+        spawn_root_dirs_update(config.clone(), vec![body.path.clone()], Vec::new());
     }
 
     HttpResponse::Ok().json(serde_json::json!({
@@ -113,19 +163,31 @@ pub async fn add_root_dir(body: web::Json<AddRootDirRequest>) -> impl Responder
 }
 
 /// Add root dir request
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct AddRootDirRequest {
     pub path: String,
 }
 
 /// Remove root directory
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct RemoveRootDirRequest {
     pub path: String,
 }
 
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 #[post("/root-dirs/remove")]
-pub async fn remove_root_dir(body: web::Json<RemoveRootDirRequest>) -> impl Responder {
+pub async fn remove_root_dir(
+    _admin: Authorized<Admin>,
+    body: web::Json<RemoveRootDirRequest>,
+) -> impl Responder {
     let mut config = match UserConfig::load() {
         Ok(c) => c,
         Err(e) => {
@@ -135,6 +197,7 @@ pub async fn remove_root_dir(body: web::Json<RemoveRootDirRequest>) -> impl Resp
         }
     };
 
+    let was_root = config.root_dirs.contains(&body.path);
     config.root_dirs.retain(|d| d != &body.path);
 
     if let Err(e) = config.save() {
@@ -143,6 +206,10 @@ pub async fn remove_root_dir(body: web::Json<RemoveRootDirRequest>) -> impl Resp
         }));
     }
 
+    if was_root {
+        spawn_root_dirs_update(config.clone(), Vec::new(), vec![body.path.clone()]);
+    }
+
     HttpResponse::Ok().json(serde_json::json!({
         "message": "Root directory removed",
         "root_dirs": config.root_dirs
@@ -150,8 +217,17 @@ pub async fn remove_root_dir(body: web::Json<RemoveRootDirRequest>) -> impl Resp
 }
 
 /// Trigger library rescan
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 #[post("/rescan")]
-pub async fn rescan_library() -> impl Responder {
+pub async fn rescan_library(_admin: Authorized<Admin>) -> impl Responder {
     match UserConfig::load() {
         Ok(config) => {
             if config.root_dirs.is_empty() {
@@ -173,25 +249,123 @@ pub async fn rescan_library() -> impl Responder {
     }
 }
 
+/// Query for paging through the scan history
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ScanHistoryQuery {
+    #[serde(default)]
+    pub start: i64,
+    #[serde(default = "default_scan_history_limit")]
+    pub limit: i64,
+}
+
+fn default_scan_history_limit() -> i64 {
+    20
+}
+
+/// Summaries of finished library scans, newest first
+#[utoipa::path(
+    params(ScanHistoryQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 500, description = "Server error")
+    )
+)]
+#[get("/scan-history")]
+pub async fn scan_history(query: web::Query<ScanHistoryQuery>) -> impl Responder {
+    let limit = query.limit.clamp(1, crate::db::tables::MAX_SCAN_HISTORY);
+    let scans = match ScanHistoryTable::recent(query.start, limit).await {
+        Ok(scans) => scans,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Failed to load scan history: {}", e)
+            }))
+        }
+    };
+    let total = ScanHistoryTable::count().await.unwrap_or(0);
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "scans": scans,
+        "total": total,
+    }))
+}
+
+/// Health and recent events of the per-root file watchers
+#[utoipa::path(
+    responses((status = 200, description = "Success"))
+)]
+#[get("/watchdog-status")]
+pub async fn watchdog_status() -> impl Responder {
+    let enabled = UserConfig::load()
+        .map(|c| c.enable_watchdog)
+        .unwrap_or(false);
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "enabled": enabled,
+        "watchers": crate::core::watchdogg::watcher_statuses(),
+    }))
+}
+
+/// OpenAPI description of the settings routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    get_settings,
+    update_settings,
+    add_root_dir,
+    remove_root_dir,
+    rescan_library,
+    scan_history,
+    watchdog_status,
+))]
+pub struct ApiDoc;
+
+/// OpenAPI description of the settings upstream prefix routes not shared with the main prefix
+#[derive(OpenApi)]
+#[openapi(paths(
+    add_root_dirs,
+    get_root_dirs_upstream,
+    get_all_settings_upstream,
+    trigger_scan_upstream,
+    scan_status_upstream,
+    scan_status_ws,
+    update_config_upstream,
+    preview_split,
+))]
+pub struct UpstreamApiDoc;
+
 /// Configure settings routes
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(get_settings)
         .service(update_settings)
         .service(add_root_dir)
         .service(remove_root_dir)
-        .service(rescan_library);
+        .service(rescan_library)
+        .service(scan_history)
+        .service(watchdog_status);
 }
 
 // ---------- Upstream-compatible routes under /notsettings ----------
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct AddRootDirsBody {
     pub new_dirs: Vec<String>,
     pub removed: Vec<String>,
 }
 
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 304, description = "Not modified"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 #[post("/add-root-dirs")]
-pub async fn add_root_dirs(body: web::Json<AddRootDirsBody>) -> impl Responder {
+pub async fn add_root_dirs(
+    _admin: Authorized<Admin>,
+    body: web::Json<AddRootDirsBody>,
+) -> impl Responder {
     let mut config = match UserConfig::load() {
         Ok(c) => c,
         Err(_) => {
@@ -220,9 +394,9 @@ pub async fn add_root_dirs(body: web::Json<AddRootDirsBody>) -> impl Responder {
     }
 
     if incoming_home {
-        config.root_dirs = vec![home_token];
+        config.root_dirs = vec![home_token.clone()];
         let _ = config.save();
-        spawn_library_scan(config, false);
+        spawn_root_dirs_update(config, vec![home_token], db_dirs);
         return HttpResponse::Ok().json(serde_json::json!({ "root_dirs": vec!["$home"] }));
     }
 
@@ -237,8 +411,9 @@ pub async fn add_root_dirs(body: web::Json<AddRootDirsBody>) -> impl Responder {
     }
 
     let mut updated_dirs = db_dirs
-        .into_iter()
+        .iter()
         .filter(|d| !removed_dirs.contains(d))
+        .cloned()
         .collect::<Vec<_>>();
 
     for dir in new_dirs.drain(..) {
@@ -255,13 +430,28 @@ pub async fn add_root_dirs(body: web::Json<AddRootDirsBody>) -> impl Responder {
         }));
     }
 
-    spawn_library_scan(config, false);
+    let added: Vec<String> = updated_dirs
+        .iter()
+        .filter(|d| !db_dirs.contains(d))
+        .cloned()
+        .collect();
+    let removed: Vec<String> = db_dirs
+        .into_iter()
+        .filter(|d| !updated_dirs.contains(d))
+        .collect();
+    spawn_root_dirs_update(config, added, removed);
 
     HttpResponse::Ok().json(serde_json::json!({
         "root_dirs": updated_dirs
     }))
 }
 
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 500, description = "Server error")
+    )
+)]
 #[get("/get-root-dirs")]
 pub async fn get_root_dirs_upstream() -> impl Responder {
     match UserConfig::load() {
@@ -274,8 +464,16 @@ pub async fn get_root_dirs_upstream() -> impl Responder {
     }
 }
 
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 #[get("")]
-pub async fn get_all_settings_upstream(req: HttpRequest) -> impl Responder {
+pub async fn get_all_settings_upstream(user: CurrentUser) -> impl Responder {
     let config = match UserConfig::load() {
         Ok(cfg) => cfg,
         Err(_) => {
@@ -290,6 +488,7 @@ pub async fn get_all_settings_upstream(req: HttpRequest) -> impl Responder {
     if let Some(obj) = config_value.as_object_mut() {
         for key in [
             "artistSeparators",
+            "albumArtistSeparators",
             "artistSplitIgnoreList",
             "genreSeparators",
         ] {
@@ -332,24 +531,55 @@ pub async fn get_all_settings_upstream(req: HttpRequest) -> impl Responder {
 
     // expose only current user's lastfm session key
     if let Some(obj) = config_value.as_object_mut() {
-        if let Some(user_id) = resolve_user_id(&req).await {
-            let key = config
-                .lastfm_session_keys
-                .get(&user_id.to_string())
-                .cloned()
-                .unwrap_or_default();
-            obj.insert("lastfmSessionKey".to_string(), serde_json::json!(key));
-        } else {
-            obj.insert("lastfmSessionKey".to_string(), serde_json::json!(""));
-        }
+        let key = config
+            .lastfm_session_keys
+            .get(&user.id.to_string())
+            .cloned()
+            .unwrap_or_default();
+        obj.insert("lastfmSessionKey".to_string(), serde_json::json!(key));
         obj.remove("lastfmSessionKeys");
+        hide_secrets(obj, &config);
     }
 
     HttpResponse::Ok().json(config_value)
 }
 
+/// Swap write only secrets for flags saying whether they are set, partial
+/// updates keep the saved ones
+fn hide_secrets(obj: &mut serde_json::Map<String, serde_json::Value>, config: &UserConfig) {
+    if let Some(oidc) = obj.get_mut("oidc").and_then(|v| v.as_object_mut()) {
+        oidc.remove("clientSecret");
+        oidc.insert(
+            "hasClientSecret".to_string(),
+            serde_json::json!(!config.oidc.client_secret.is_empty()),
+        );
+    }
+
+    if let Some(images) = obj.get_mut("artistImages").and_then(|v| v.as_object_mut()) {
+        images.remove("spotifyClientSecret");
+        images.remove("fanartApiKey");
+        images.insert(
+            "hasSpotifyClientSecret".to_string(),
+            serde_json::json!(!config.artist_images.spotify_client_secret.is_empty()),
+        );
+        images.insert(
+            "hasFanartApiKey".to_string(),
+            serde_json::json!(!config.artist_images.fanart_api_key.is_empty()),
+        );
+    }
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 #[get("/trigger-scan")]
-pub async fn trigger_scan_upstream() -> impl Responder {
+pub async fn trigger_scan_upstream(_admin: Authorized<Admin>) -> impl Responder {
     match UserConfig::load() {
         Ok(config) => {
             if config.root_dirs.is_empty() {
@@ -373,14 +603,79 @@ pub async fn trigger_scan_upstream() -> impl Responder {
     }))
 }
 
-#[derive(Debug, Deserialize)]
+#[utoipa::path(
+    responses((status = 200, description = "Success"))
+)]
+#[get("/scan-status")]
+pub async fn scan_status_upstream() -> impl Responder {
+    HttpResponse::Ok().json(ScanProgress::state())
+}
+
+/// Push the scan state over a websocket whenever it changes
+#[utoipa::path(
+    responses((status = 101, description = "Switching to a websocket"))
+)]
+#[get("/scan-status/ws")]
+pub async fn scan_status_ws(
+    req: HttpRequest,
+    body: web::Payload,
+) -> actix_web::Result<HttpResponse> {
+    let (response, mut session, mut messages) = actix_ws::handle(&req, body)?;
+
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(SCAN_STATUS_PUSH_INTERVAL);
+        let mut last_sent = String::new();
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let state = serde_json::to_string(&ScanProgress::state()).unwrap_or_default();
+                    if state != last_sent {
+                        if session.text(state.clone()).await.is_err() {
+                            return;
+                        }
+                        last_sent = state;
+                    }
+                }
+                message = messages.next() => match message {
+                    Some(Ok(actix_ws::Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            return;
+                        }
+                    }
+                    Some(Ok(actix_ws::Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+
+        let _ = session.close(None).await;
+    });
+
+    Ok(response)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct UpdateConfigBody {
     pub key: String,
     pub value: serde_json::Value,
 }
 
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 #[put("/update")]
-pub async fn update_config_upstream(body: web::Json<UpdateConfigBody>) -> impl Responder {
+pub async fn update_config_upstream(
+    _admin: Authorized<Admin>,
+    body: web::Json<UpdateConfigBody>,
+) -> impl Responder {
     let mut config = match UserConfig::load() {
         Ok(c) => c,
         Err(_) => {
@@ -395,16 +690,91 @@ pub async fn update_config_upstream(body: web::Json<UpdateConfigBody>) -> impl R
     let val = body.value.clone();
     let mut updated = true;
     let mut needs_reindex = false;
+    let mut reload_albums = false;
+    let mut needs_thumbnail_refresh = false;
+    let mut restart_watchers = false;
+    let mut run_fingerprints = false;
+    let mut run_gapless = false;
+    let mut refresh_hubs = false;
+    let mut fetch_artist_images = false;
 
     match key {
         "usersOnLogin" => config.users_on_login = val.as_bool().unwrap_or(config.users_on_login),
         "enableGuest" => config.enable_guest = val.as_bool().unwrap_or(config.enable_guest),
         "enableWatchdog" => {
-            config.enable_watchdog = val.as_bool().unwrap_or(config.enable_watchdog)
+            config.enable_watchdog = val.as_bool().unwrap_or(config.enable_watchdog);
+            restart_watchers = true;
+        }
+        "watchdogRoots" => {
+            match serde_json::from_value::<HashMap<String, WatchdogRootOptions>>(val.clone()) {
+                Ok(roots) => {
+                    config.watchdog_roots = roots;
+                    restart_watchers = true;
+                }
+                Err(_) => updated = false,
+            }
         }
+        "enableDlna" => config.enable_dlna = val.as_bool().unwrap_or(config.enable_dlna),
+        "scrobbleOnDisconnect" => {
+            config.scrobble_on_disconnect = val.as_bool().unwrap_or(config.scrobble_on_disconnect)
+        }
+        "dlnaName" => match val.as_str().map(str::trim) {
+            Some(name) if !name.is_empty() => config.dlna_name = name.to_string(),
+            _ => updated = false,
+        },
         "enablePeriodicScans" => {
             config.enable_periodic_scans = val.as_bool().unwrap_or(config.enable_periodic_scans)
         }
+        "dbMaintenanceInterval" => match val.as_u64().and_then(|v| u32::try_from(v).ok()) {
+            Some(hours) => config.db_maintenance_interval = hours,
+            None => updated = false,
+        },
+        "searchPersonalBoost" => match val.as_f64() {
+            Some(boost) if (0.0..=MAX_SEARCH_PERSONAL_BOOST).contains(&boost) => {
+                config.search_personal_boost = boost
+            }
+            _ => updated = false,
+        },
+        "enableFingerprinting" => {
+            config.enable_fingerprinting =
+                val.as_bool().unwrap_or(config.enable_fingerprinting);
+            run_fingerprints = config.enable_fingerprinting;
+        }
+        "enableGaplessAnalysis" => {
+            config.enable_gapless_analysis =
+                val.as_bool().unwrap_or(config.enable_gapless_analysis);
+            run_gapless = true;
+        }
+        "enableLibraryHubs" => {
+            config.enable_library_hubs = val.as_bool().unwrap_or(config.enable_library_hubs);
+            refresh_hubs = true;
+        }
+        "fpcalcPath" => match val.as_str().map(str::trim) {
+            Some(path) if fingerprint::validate_fpcalc(path).is_ok() => {
+                config.fpcalc_path = path.to_string()
+            }
+            _ => updated = false,
+        },
+        "fileTemplate" => match val.as_str().map(str::trim) {
+            Some(template) if organizer::validate_template(template).is_ok() => {
+                config.file_template = template.to_string()
+            }
+            _ => updated = false,
+        },
+        "inboxDir" => match val.as_str().map(str::trim) {
+            Some("") => config.inbox_dir = String::new(),
+            Some(dir) if inbox::validate_dir(dir, &config.root_dirs).is_ok() => {
+                config.inbox_dir = dir.to_string()
+            }
+            _ => updated = false,
+        },
+        "importFolderPlaylists" => {
+            config.import_folder_playlists = val.as_bool().unwrap_or(config.import_folder_playlists)
+        }
+        "trashRetentionDays" => match val.as_u64().and_then(|v| u32::try_from(v).ok()) {
+            Some(days) => config.trash_retention_days = days,
+            None => updated = false,
+        },
         "rootDirs" => {
             if let Some(arr) = val.as_array() {
                 config.root_dirs = arr
@@ -426,6 +796,20 @@ pub async fn update_config_upstream(body: web::Json<UpdateConfigBody>) -> impl R
                 updated = false;
             }
         }
+        "albumArtistSeparators" => match string_set(&val) {
+            Some(separators) => {
+                config.album_artist_separators = separators;
+                needs_reindex = true;
+            }
+            None => updated = false,
+        },
+        "genreSeparators" => match string_set(&val) {
+            Some(separators) => {
+                config.genre_separators = separators;
+                needs_reindex = true;
+            }
+            None => updated = false,
+        },
         "artistSplitIgnoreList" => {
             if let Some(arr) = val.as_array() {
                 config.artist_split_ignore_list = arr
@@ -445,9 +829,33 @@ pub async fn update_config_upstream(body: web::Json<UpdateConfigBody>) -> impl R
             config.remove_remaster_info = val.as_bool().unwrap_or(config.remove_remaster_info);
             needs_reindex = true;
         }
-        "mergeAlbums" => {
-            config.merge_albums = val.as_bool().unwrap_or(config.merge_albums);
-            needs_reindex = true;
+        "mergeAlbums" => match val.as_bool() {
+            Some(enabled) => {
+                reload_albums = enabled != config.merge_albums;
+                config.merge_albums = enabled;
+            }
+            None => updated = false,
+        },
+        "albumMergeRules" => {
+            // merge partial updates into the current rules
+            let mut merged = serde_json::to_value(config.album_merge_rules).unwrap_or_default();
+            if let (Some(target), Some(patch)) = (merged.as_object_mut(), val.as_object()) {
+                for (k, v) in patch {
+                    target.insert(k.clone(), v.clone());
+                }
+            }
+            match serde_json::from_value::<AlbumMergeRules>(merged) {
+                Ok(rules) if val.is_object() => {
+                    let rules = rules.normalized();
+                    // catalog numbers are only read from the tags on a full rescan
+                    needs_reindex = config.merge_albums
+                        && rules.match_catalog_number
+                        && !config.album_merge_rules.match_catalog_number;
+                    reload_albums = config.merge_albums && rules != config.album_merge_rules;
+                    config.album_merge_rules = rules;
+                }
+                _ => updated = false,
+            }
         }
         "cleanAlbumTitle" => {
             config.clean_album_title = val.as_bool().unwrap_or(config.clean_album_title);
@@ -457,6 +865,91 @@ pub async fn update_config_upstream(body: web::Json<UpdateConfigBody>) -> impl R
             config.show_albums_as_singles = val.as_bool().unwrap_or(config.show_albums_as_singles);
             needs_reindex = true;
         }
+        "groupSingles" => match val.as_bool() {
+            Some(enabled) => {
+                reload_albums = enabled != config.group_singles;
+                config.group_singles = enabled;
+            }
+            None => updated = false,
+        },
+        "thumbnails" => {
+            // merge partial updates into the current thumbnail settings
+            let mut merged = serde_json::to_value(config.thumbnails).unwrap_or_default();
+            if let (Some(target), Some(patch)) = (merged.as_object_mut(), val.as_object()) {
+                for (k, v) in patch {
+                    target.insert(k.clone(), v.clone());
+                }
+            }
+            match serde_json::from_value::<ThumbnailSettings>(merged) {
+                Ok(thumbnails) if val.is_object() => {
+                    let thumbnails = thumbnails.normalized();
+                    needs_thumbnail_refresh = thumbnails != config.thumbnails;
+                    config.thumbnails = thumbnails;
+                }
+                _ => updated = false,
+            }
+        }
+        "imageCacheMaxAge" => match val.as_u64().and_then(|v| u32::try_from(v).ok()) {
+            Some(seconds) => config.image_cache_max_age = seconds,
+            None => updated = false,
+        },
+        "streamChunkSize" => match val.as_u64().and_then(|v| u32::try_from(v).ok()) {
+            Some(kib) if (MIN_STREAM_CHUNK_KIB..=MAX_STREAM_CHUNK_KIB).contains(&kib) => {
+                config.stream_chunk_size = kib
+            }
+            _ => updated = false,
+        },
+        "streamZeroCopy" => match val.as_bool() {
+            Some(enabled) => config.stream_zero_copy = enabled,
+            None => updated = false,
+        },
+        "streamSendfile" => match val.as_bool() {
+            Some(enabled) => config.stream_sendfile = enabled,
+            None => updated = false,
+        },
+        "mixes" => {
+            // merge partial updates into the current mix settings
+            let mut merged = serde_json::to_value(config.mixes).unwrap_or_default();
+            if let (Some(target), Some(patch)) = (merged.as_object_mut(), val.as_object()) {
+                for (k, v) in patch {
+                    target.insert(k.clone(), v.clone());
+                }
+            }
+            match serde_json::from_value::<MixSettings>(merged) {
+                Ok(mixes) if val.is_object() => config.mixes = mixes.normalized(),
+                _ => updated = false,
+            }
+        }
+        "artistImages" => {
+            // merge partial updates into the current artist image settings
+            let mut merged = serde_json::to_value(&config.artist_images).unwrap_or_default();
+            if let (Some(target), Some(patch)) = (merged.as_object_mut(), val.as_object()) {
+                for (k, v) in patch {
+                    target.insert(k.clone(), v.clone());
+                }
+            }
+            match serde_json::from_value::<ArtistImageSettings>(merged) {
+                Ok(settings) if val.is_object() => {
+                    let settings = settings.normalized();
+                    fetch_artist_images = settings != config.artist_images;
+                    config.artist_images = settings;
+                }
+                _ => updated = false,
+            }
+        }
+        "oidc" => {
+            // merge partial updates into the current sso settings
+            let mut merged = serde_json::to_value(&config.oidc).unwrap_or_default();
+            if let (Some(target), Some(patch)) = (merged.as_object_mut(), val.as_object()) {
+                for (k, v) in patch {
+                    target.insert(k.clone(), v.clone());
+                }
+            }
+            match serde_json::from_value::<OidcSettings>(merged) {
+                Ok(settings) if val.is_object() => config.oidc = settings.normalized(),
+                _ => updated = false,
+            }
+        }
         _ => {
             updated = false;
         }
@@ -473,9 +966,62 @@ pub async fn update_config_upstream(body: web::Json<UpdateConfigBody>) -> impl R
             "msg": "Failed to save config"
         }));
     }
+    // album building reads the shared copy
+    *UserConfig::global().write() = config.clone();
+
+    if restart_watchers {
+        crate::core::watchdogg::sync_watchers(&config);
+    }
+
+    if key == "imageCacheMaxAge" {
+        crate::core::images::set_image_cache_max_age(config.image_cache_max_age);
+    }
+
+    if matches!(key, "streamChunkSize" | "streamZeroCopy" | "streamSendfile") {
+        file_cache::set_stream_tuning(&config);
+    }
 
     if needs_reindex {
         spawn_library_scan(config, true);
+    } else if reload_albums {
+        // albums are regrouped from the tracks already indexed
+        actix_web::rt::spawn(async {
+            if let Err(e) = reload_library().await {
+                error!("Library reload after album grouping change failed: {}", e);
+            }
+        });
+    } else if run_fingerprints {
+        crate::core::fingerprint::spawn_pass();
+    } else if run_gapless {
+        crate::core::gapless::spawn_pass();
+    }
+
+    if refresh_hubs {
+        actix_web::rt::spawn(async {
+            match crate::core::recipes::Recipes::refresh_library_hubs().await {
+                Ok(count) => info!("Rebuilt {} library hubs", count),
+                Err(e) => error!("Library hub rebuild failed: {}", e),
+            }
+        });
+    }
+
+    if needs_thumbnail_refresh {
+        actix_web::rt::spawn(async {
+            match crate::core::images::refresh_thumbnails().await {
+                Ok(count) => info!("Regenerated thumbnails for {} albums", count),
+                Err(e) => error!("Thumbnail regeneration failed: {}", e),
+            }
+        });
+    }
+
+    if fetch_artist_images {
+        // artists nothing was found for get a lookup with the new providers
+        actix_web::rt::spawn(async {
+            match crate::core::images::download_artist_images().await {
+                Ok(count) => info!("Downloaded {} artist images", count),
+                Err(e) => error!("Artist image lookup failed: {}", e),
+            }
+        });
     }
 
     HttpResponse::Ok().json(serde_json::json!({
@@ -483,12 +1029,89 @@ pub async fn update_config_upstream(body: web::Json<UpdateConfigBody>) -> impl R
     }))
 }
 
+/// Strings of a json array as a set, `None` when the value is not an array
+fn string_set(val: &serde_json::Value) -> Option<HashSet<String>> {
+    Some(
+        val.as_array()?
+            .iter()
+            .filter_map(|v| v.as_str().map(str::to_string))
+            .collect(),
+    )
+}
+
+/// A tag value to split and the separators to try
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PreviewSplitBody {
+    /// the tag value as read from a file
+    pub value: String,
+    /// `artist`, `albumartist` or `genre`
+    #[schema(value_type = String)]
+    pub field: SplitField,
+    /// separators to try instead of the saved ones
+    pub separators: Option<Vec<String>>,
+}
+
+/// Show how a tag value splits, with the saved separators and with the ones
+/// given, so separator changes can be checked before a reindex
+#[utoipa::path(
+    request_body = PreviewSplitBody,
+    responses(
+        (status = 200, description = "Success"),
+        (status = 500, description = "Server error")
+    )
+)]
+#[post("/preview-split")]
+pub async fn preview_split(body: web::Json<PreviewSplitBody>) -> impl Responder {
+    let config = match UserConfig::load() {
+        Ok(c) => c,
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "msg": "Failed to load config"
+            }));
+        }
+    };
+
+    let saved = config.separators_for(body.field);
+    let tried: HashSet<String> = match &body.separators {
+        Some(separators) => separators.iter().cloned().collect(),
+        None => saved.clone(),
+    };
+    let split = |separators: &HashSet<String>| {
+        split_tag(
+            &body.value,
+            body.field,
+            separators,
+            &config.artist_split_ignore_list,
+        )
+    };
+    let current = split(saved);
+    let parts = split(&tried);
+    let sorted = |set: &HashSet<String>| {
+        let mut values: Vec<String> = set.iter().cloned().collect();
+        values.sort();
+        values
+    };
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "field": body.field,
+        "value": body.value,
+        "separators": sorted(&tried),
+        "parts": parts,
+        "current_separators": sorted(saved),
+        "current_parts": current,
+        "changed": parts != current,
+    }))
+}
+
 pub fn configure_upstream(cfg: &mut web::ServiceConfig) {
     cfg.service(add_root_dirs)
         .service(get_root_dirs_upstream)
         .service(get_all_settings_upstream)
         .service(trigger_scan_upstream)
-        .service(update_config_upstream);
+        .service(scan_status_upstream)
+        .service(scan_status_ws)
+        .service(update_config_upstream)
+        .service(preview_split);
 }
 
 // ---------- Scan helpers ----------
@@ -499,6 +1122,8 @@ struct ScanStats {
     updated: usize,
     removed: usize,
     total: usize,
+    /// files still being copied, left for the follow up scan
+    deferred: usize,
 }
 
 fn spawn_library_scan(config: UserConfig, force: bool) {
@@ -514,27 +1139,79 @@ fn spawn_library_scan(config: UserConfig, force: bool) {
 }
 
 async fn run_library_scan(config: UserConfig, force: bool) -> anyhow::Result<ScanStats> {
-    use anyhow::anyhow;
-    use std::collections::{HashMap, HashSet};
-    use std::path::PathBuf;
-    use std::time::UNIX_EPOCH;
+    let root_dirs = resolve_root_dirs(&config.root_dirs);
+    if root_dirs.is_empty() {
+        return Err(anyhow::anyhow!("No root directories configured"));
+    }
 
-    use crate::core::images::{
-        cache_album_images, download_artist_images, extract_album_colors, extract_artist_colors,
-    };
+    scan_root_dirs(&config, root_dirs, force).await
+}
+
+/// Scan the given resolved root directories, leaving tracks under other roots alone
+async fn scan_root_dirs(
+    config: &UserConfig,
+    root_dirs: Vec<String>,
+    force: bool,
+) -> anyhow::Result<ScanStats> {
     use crate::core::indexer::Indexer;
-    use crate::core::mapstuff::{map_colors, map_favorites, map_scrobble_data};
-    use crate::db::tables::TrackTable;
-    use crate::stores::{AlbumStore, ArtistStore, FolderStore, TrackStore};
+
+    let artist_seps = config.artist_separators.iter().cloned().collect();
+    let indexer = Indexer::new(root_dirs.clone(), artist_seps)
+        .with_exclude_dirs(&config.exclude_dirs)
+        .with_progress(false);
+    let kind = if force {
+        ScanKind::Full
+    } else {
+        ScanKind::Incremental
+    };
+    let progress = ScanProgress::begin(indexer.root_dirs(), kind)
+        .ok_or_else(|| anyhow::anyhow!("A library scan is already running"))?;
+
+    let result = scan_and_index(&indexer, &progress, force).await;
+    match &result {
+        Ok(stats) if stats.deferred > 0 => schedule_deferred_scan(config.clone(), root_dirs),
+        Ok(_) => {}
+        Err(e) => progress.fail(&e.to_string()),
+    }
+    result
+}
+
+/// Rescan roots once files that were still being copied had time to settle
+fn schedule_deferred_scan(config: UserConfig, root_dirs: Vec<String>) {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    // one follow up at a time, it defers again if copies are still running
+    static PENDING: AtomicBool = AtomicBool::new(false);
+    if PENDING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    actix_web::rt::spawn(async move {
+        tokio::time::sleep(DEFERRED_SCAN_DELAY).await;
+        PENDING.store(false, Ordering::SeqCst);
+
+        // a scan started meanwhile already picked the files up or deferred them again
+        if ScanProgress::state().running {
+            return;
+        }
+        match scan_root_dirs(&config, root_dirs, false).await {
+            Ok(stats) => info!(
+                "Deferred scan complete: {} added, {} updated, {} still copying",
+                stats.added, stats.updated, stats.deferred
+            ),
+            Err(e) => warn!("Deferred scan failed: {}", e),
+        }
+    });
+}
+
+/// Resolve `$home` and normalize configured root directories
+pub(crate) fn resolve_root_dirs(dirs: &[String]) -> Vec<String> {
     use crate::utils::filesystem::normalize_path;
 
-    let home_dir = directories::UserDirs::new()
-        .and_then(|u| Some(u.home_dir().to_path_buf()))
-        .map(|p| normalize_path(&p.to_string_lossy()));
+    let home_dir =
+        directories::UserDirs::new().map(|u| normalize_path(&u.home_dir().to_string_lossy()));
 
-    let root_dirs: Vec<String> = config
-        .root_dirs
-        .iter()
+    dirs.iter()
         .filter_map(|d| {
             if d == "$home" {
                 home_dir.clone()
@@ -542,53 +1219,122 @@ async fn run_library_scan(config: UserConfig, force: bool) -> anyhow::Result<Sca
                 Some(normalize_path(d))
             }
         })
-        .collect();
+        .collect()
+}
 
-    if root_dirs.is_empty() {
-        return Err(anyhow!("No root directories configured"));
-    }
+/// Whether a file path sits inside any of the resolved root directories
+fn is_under_any(filepath: &str, roots: &[String]) -> bool {
+    let path = std::path::PathBuf::from(crate::utils::filesystem::normalize_path(filepath));
+    roots.iter().any(|root| path.starts_with(root))
+}
 
-    let artist_seps = config.artist_separators.iter().cloned().collect();
-    let indexer = Indexer::new(root_dirs, artist_seps).with_progress(false);
+/// Walk the roots, reindex new and changed files and reload the stores
+async fn scan_and_index(
+    indexer: &crate::core::indexer::Indexer,
+    progress: &crate::core::indexer::ScanGuard,
+    force: bool,
+) -> anyhow::Result<ScanStats> {
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+    use std::time::UNIX_EPOCH;
+
+    use crate::core::indexer::{next_batch, ScanPhase, SCAN_BATCH_SIZE};
+    use crate::db::tables::TrackTable;
+    use crate::utils::filesystem::normalize_path;
 
     // Scan filesystem
-    let scanned_paths: Vec<PathBuf> = indexer.scan_files();
+    let scanned_roots = indexer.scan_roots();
     let mut seen_norm: HashSet<String> = HashSet::new();
+    let mut deferred = 0usize;
 
-    // Existing tracks keyed by normalized path -> (raw path, track)
+    // Existing tracks under the scanned roots keyed by normalized path -> (raw path, track)
     let existing_tracks = TrackTable::all().await?;
     let mut existing_by_norm: HashMap<String, (String, crate::models::Track)> = HashMap::new();
     for track in existing_tracks {
         let norm = normalize_path(&track.filepath);
+        if !indexer
+            .root_dirs()
+            .iter()
+            .any(|root| std::path::Path::new(&norm).starts_with(root))
+        {
+            continue;
+        }
         existing_by_norm.insert(norm, (track.filepath.clone(), track));
     }
+    let mut to_reindex: Vec<(usize, PathBuf)> = Vec::new();
+    let mut playlist_files: Vec<PathBuf> = Vec::new();
+
+    for (root, found) in scanned_roots.into_iter().enumerate() {
+        progress.set_found(root, found.files.len());
+        progress.add_skipped(root, &found);
+        let queued_before = to_reindex.len();
+        playlist_files.extend(found.playlists);
+
+        // files still being copied keep their current rows until the next pass
+        deferred += found.deferred.len();
+        for path in &found.deferred {
+            seen_norm.insert(normalize_path(&path.to_string_lossy()));
+        }
 
-    let mut to_reindex: Vec<PathBuf> = Vec::new();
+        for path in found.files {
+            let norm = normalize_path(&path.to_string_lossy());
 
-    for path in &scanned_paths {
-        let norm = normalize_path(&path.to_string_lossy());
-        seen_norm.insert(norm.clone());
+            let file_mtime = std::fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0);
 
-        let file_mtime = std::fs::metadata(path)
-            .and_then(|m| m.modified())
-            .ok()
-            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
-            .map(|d| d.as_secs() as i64)
-            .unwrap_or(0);
+            let needs_reindex = if force {
+                true
+            } else {
+                match existing_by_norm.get(&norm) {
+                    Some((_, existing)) => existing.last_mod != file_mtime,
+                    None => true,
+                }
+            };
 
-        let needs_reindex = if force {
-            true
-        } else {
-            match existing_by_norm.get(&norm) {
-                Some((_, existing)) => existing.last_mod != file_mtime,
-                None => true,
+            seen_norm.insert(norm);
+            if needs_reindex {
+                to_reindex.push((root, path));
             }
-        };
+        }
+
+        progress.add_queued(root, to_reindex.len() - queued_before);
+    }
+
+    // Reindex changed/new files, writing each batch as soon as it is read
+    let mut stream = indexer.extract_streaming(to_reindex, Some(progress.handle()))?;
+    let mut updated = 0usize;
+    let mut added = 0usize;
+
+    loop {
+        let mut batch = next_batch(&mut stream.tracks, SCAN_BATCH_SIZE).await;
+        if batch.is_empty() {
+            break;
+        }
 
-        if needs_reindex {
-            to_reindex.push(path.clone());
+        let mut updated_paths: Vec<String> = Vec::new();
+        for track in &mut batch {
+            let norm = normalize_path(&track.filepath);
+            if let Some((raw, existing)) = existing_by_norm.get(&norm) {
+                // Preserve play stats
+                track.lastplayed = existing.lastplayed;
+                track.playcount = existing.playcount;
+                track.playduration = existing.playduration;
+                updated_paths.push(raw.clone());
+            } else {
+                added += 1;
+            }
         }
+
+        TrackTable::replace_many(&updated_paths, &batch).await?;
+        updated += updated_paths.len();
+        progress.add_written(batch.len());
     }
+    // Only prune once every file was read
+    stream.finish().await?;
 
     // Paths removed from disk
     let removed_paths: Vec<String> = existing_by_norm
@@ -602,33 +1348,62 @@ async fn run_library_scan(config: UserConfig, force: bool) -> anyhow::Result<Sca
         info!("Removed {} missing tracks from database", removed_count);
     }
 
-    // Reindex changed/new files
-    let mut reindexed_tracks = indexer.reindex_files(&to_reindex)?;
-    let mut updated_paths: Vec<String> = Vec::new();
-    let mut added = 0usize;
+    // Reload in-memory stores and mappings (parity with startup)
+    progress.set_phase(ScanPhase::Finalizing);
+    reload_library().await?;
+    sync_folder_playlists(&playlist_files, indexer.root_dirs()).await;
+    crate::core::fingerprint::spawn_pass();
+    crate::core::gapless::spawn_pass();
 
-    for track in &mut reindexed_tracks {
-        let norm = normalize_path(&track.filepath);
-        if let Some((raw, existing)) = existing_by_norm.get(&norm) {
-            // Preserve play stats
-            track.lastplayed = existing.lastplayed;
-            track.playcount = existing.playcount;
-            track.playduration = existing.playduration;
-            updated_paths.push(raw.clone());
-        } else {
-            added += 1;
+    let total = match TrackTable::count().await {
+        Ok(count) => count as usize,
+        Err(e) => {
+            warn!("Failed to count tracks after scan: {}", e);
+            0
         }
-    }
+    };
+
+    progress.set_changes(ScanChanges {
+        added,
+        updated,
+        removed: removed_paths.len(),
+        total,
+    });
 
-    if !updated_paths.is_empty() {
-        TrackTable::remove_by_filepaths(&updated_paths).await?;
+    Ok(ScanStats {
+        added,
+        updated,
+        removed: removed_paths.len(),
+        total,
+        deferred,
+    })
+}
+
+/// Bring the playlists of m3u files in line with the files, when importing
+/// them is turned on
+async fn sync_folder_playlists(files: &[std::path::PathBuf], roots: &[std::path::PathBuf]) {
+    if !UserConfig::load().is_ok_and(|c| c.import_folder_playlists) {
+        return;
     }
 
-    if !reindexed_tracks.is_empty() {
-        TrackTable::insert_many(&reindexed_tracks).await?;
+    match crate::core::folder_playlists::sync(files, roots).await {
+        Ok(s) if s.created + s.updated + s.removed > 0 => info!(
+            "Synced folder playlists (created: {}, updated: {}, removed: {})",
+            s.created, s.updated, s.removed
+        ),
+        Ok(_) => {}
+        Err(e) => warn!("Failed to sync folder playlists: {}", e),
     }
+}
+
+/// Reload stores, images and mappings after the track table changed
+async fn reload_library() -> anyhow::Result<()> {
+    use crate::core::images::{
+        cache_album_images, download_artist_images, extract_album_colors, extract_artist_colors,
+    };
+    use crate::core::mapstuff::{map_colors, map_favorites, map_mbids, map_scrobble_data};
+    use crate::stores::{AlbumStore, ArtistStore, FolderStore, TrackStore};
 
-    // Reload in-memory stores and mappings (parity with startup)
     TrackStore::load_all_tracks().await?;
     AlbumStore::load_albums().await?;
     ArtistStore::load_artists().await?;
@@ -644,51 +1419,123 @@ async fn run_library_scan(config: UserConfig, force: bool) -> anyhow::Result<Sca
     let _ = extract_artist_colors().await;
     map_favorites().await?;
     map_colors().await?;
+    map_mbids().await?;
     map_scrobble_data().await?;
+    crate::core::dlna::notify_library_changed();
 
-    let total = match TrackTable::count().await {
-        Ok(count) => count as usize,
-        Err(e) => {
-            warn!("Failed to count tracks after scan: {}", e);
-            0
-        }
-    };
+    Ok(())
+}
 
-    Ok(ScanStats {
-        added,
-        updated: updated_paths.len(),
-        removed: removed_paths.len(),
-        total,
-    })
+/// Watch and scan added roots and hide tracks of removed ones in the background
+fn spawn_root_dirs_update(config: UserConfig, added: Vec<String>, removed: Vec<String>) {
+    if added.is_empty() && removed.is_empty() {
+        return;
+    }
+
+    actix_web::rt::spawn(async move {
+        crate::core::watchdogg::sync_watchers(&config);
+
+        match apply_root_dirs_update(&config, &added, &removed).await {
+            Ok(Some(stats)) => info!(
+                "Scanned added root directories (added: {}, updated: {}, removed: {}, total: {})",
+                stats.added, stats.updated, stats.removed, stats.total
+            ),
+            Ok(None) => info!("Root directories updated"),
+            Err(e) => error!("Failed to apply root directory changes: {}", e),
+        }
+    });
 }
-async fn resolve_user_id(req: &HttpRequest) -> Option<i64> {
-    // prefer access token cookie
-    let token = if let Some(cookie) = req.cookie("access_token_cookie") {
-        Some(cookie.value().to_string())
-    } else {
-        match req.headers().get("Authorization") {
-            Some(header_value) => {
-                let header_str = header_value.to_str().unwrap_or("").trim();
-                if header_str.is_empty() {
-                    None
-                } else if let Some(rest) = header_str.strip_prefix("Bearer ") {
-                    if rest.is_empty() {
-                        None
-                    } else {
-                        Some(rest.to_string())
-                    }
-                } else {
-                    Some(header_str.to_string())
-                }
-            }
-            None => None,
+
+/// Apply root directory changes without rescanning the untouched roots
+///
+/// tracks of a removed root are soft removed so adding the root back later
+/// restores them with their play stats
+async fn apply_root_dirs_update(
+    config: &UserConfig,
+    added: &[String],
+    removed: &[String],
+) -> anyhow::Result<Option<ScanStats>> {
+    use crate::db::tables::TrackTable;
+
+    let remaining = resolve_root_dirs(&config.root_dirs);
+    let added_roots = resolve_root_dirs(added);
+    let removed_roots = resolve_root_dirs(removed);
+
+    let mut hidden = 0;
+    if !removed_roots.is_empty() {
+        // a track stays visible while another root still covers it
+        let paths: Vec<String> = TrackTable::all()
+            .await?
+            .into_iter()
+            .map(|t| t.filepath)
+            .filter(|p| is_under_any(p, &removed_roots) && !is_under_any(p, &remaining))
+            .collect();
+        hidden = TrackTable::soft_remove_by_filepaths(&paths).await?;
+        info!("Hid {} tracks of removed root directories", hidden);
+    }
+
+    if added_roots.is_empty() {
+        if hidden > 0 {
+            reload_library().await?;
         }
-    }?;
+        return Ok(None);
+    }
+
+    let restore: Vec<String> = TrackTable::removed_filepaths()
+        .await?
+        .into_iter()
+        .filter(|p| is_under_any(p, &added_roots))
+        .collect();
+    let restored = TrackTable::restore_by_filepaths(&restore).await?;
+    if restored > 0 {
+        info!("Restored {} tracks of re-added root directories", restored);
+    }
+
+    // a scan of the previous roots may still be running
+    while ScanProgress::state().running {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+
+    scan_root_dirs(config, added_roots, false).await.map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+
+    #[actix_web::test]
+    async fn test_anonymous_config_requests_are_refused() {
+        let app = test::init_service(
+            App::new().service(web::scope("/notsettings").configure(configure_upstream)),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri("/notsettings/update")
+            .set_json(serde_json::json!({"key": "fpcalcPath", "value": "/bin/sh"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
+        let req = test::TestRequest::get().uri("/notsettings").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+    }
 
-    let config = UserConfig::load().ok()?;
-    let claims = verify_jwt(&token, &config.server_id, Some("access")).ok()?;
-    let user = UserTable::get_by_id(claims.sub.id)
-        .await
-        .ok()??;
-    Some(user.id)
+    #[actix_web::test]
+    async fn test_secrets_are_not_sent() {
+        let mut config = UserConfig::default();
+        config.oidc.client_secret = "sso".to_string();
+        config.artist_images.spotify_client_secret = "spotify".to_string();
+
+        let mut value = serde_json::to_value(&config).unwrap();
+        hide_secrets(value.as_object_mut().unwrap(), &config);
+        assert_eq!(value["oidc"]["hasClientSecret"], true);
+        assert_eq!(value["artistImages"]["hasSpotifyClientSecret"], true);
+        assert_eq!(value["artistImages"]["hasFanartApiKey"], false);
+        assert!(value["oidc"].get("clientSecret").is_none());
+        assert!(value["artistImages"].get("spotifyClientSecret").is_none());
+        assert!(value["artistImages"].get("fanartApiKey").is_none());
+    }
 }
@@ -1,39 +1,54 @@
 //! Audio streaming API routes
 
-use actix_files::NamedFile;
-use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use actix_web::{get, post, web, HttpRequest, HttpResponse, HttpResponseBuilder, Responder};
 use serde::Deserialize;
-use std::io::{Read, Seek, SeekFrom};
+use utoipa::{IntoParams, OpenApi, ToSchema};
 use std::path::{Component, Path, PathBuf};
 
+use crate::api::identity::{request_device, CurrentUser};
 use crate::config::UserConfig;
+use crate::core::file_cache::{self, check_conditional_request, stream_tuning, CachedFileMetadata};
+use crate::core::playback::{CountedBody, StreamGuard};
 use crate::core::silence::SilenceDetector;
-use crate::core::transcode::{AudioFormat, Quality, Transcoder};
+use crate::core::transcode::{AudioFormat, CachedTranscode, Quality, TranscodeCache};
+use crate::models::Track;
 use crate::stores::TrackStore;
 use crate::utils::filesystem::normalize_path;
 
 /// Stream query parameters
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct StreamQuery {
     pub format: Option<String>,
     pub quality: Option<String>,
+    /// target bitrate in kbps, overrides the quality preset
+    pub bitrate: Option<u32>,
 }
 
+/// how long to wait for more transcoded data before polling again
+const TAIL_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
 /// Legacy stream query parameters (filepath passthrough, no ranges)
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct LegacyStreamQuery {
     pub filepath: String,
     pub quality: Option<String>,
     pub container: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct SilenceBody {
     pub ending_file: String,
     pub starting_file: String,
 }
 
 /// Stream track by hash
+#[utoipa::path(
+    params(StreamQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 404, description = "Not found")
+    )
+)]
 #[get("/{trackhash}")]
 pub async fn stream_track(
     path: web::Path<String>,
@@ -52,6 +67,25 @@ pub async fn stream_track(
         }
     };
 
+    let response = serve_track(&track, &query, &req).await;
+    if !response.status().is_success() {
+        return response;
+    }
+
+    // follow the playback so plays can be logged if the client never does
+    let response = match open_stream_session(&track, &req).await {
+        Some(guard) => response
+            .map_body(|_, body| guard.wrap(body))
+            .map_into_boxed_body(),
+        None => response,
+    };
+    response
+        .map_body(|_, body| CountedBody::new(body))
+        .map_into_boxed_body()
+}
+
+/// Serve a track file, transcoding when requested or when browsers can't play it
+async fn serve_track(track: &Track, query: &StreamQuery, req: &HttpRequest) -> HttpResponse {
     let file_path = Path::new(&track.filepath);
 
     if !file_path.exists() {
@@ -60,28 +94,23 @@ pub async fn stream_track(
         }));
     }
 
-    // determine quality from query param (shared across explicit and auto transcode)
-    let quality = match query.quality.as_deref() {
-        Some("low") => Quality::Low,
-        Some("medium") => Quality::Medium,
-        Some("high") => Quality::High,
-        Some("best") => Quality::Best,
-        _ => Quality::Best,
-    };
+    // determine bitrate from query params (shared across explicit and auto transcode)
+    let bitrate = query.bitrate.unwrap_or_else(|| {
+        query
+            .quality
+            .as_deref()
+            .and_then(Quality::from_str)
+            .unwrap_or(Quality::Best)
+            .bitrate()
+    });
 
     // explicit transcode request via ?format=xxx
-    if let Some(format_str) = &query.format {
-        if let Some(format) = AudioFormat::from_str(format_str) {
-            match Transcoder::transcode_to_bytes(file_path, format, quality) {
-                Ok(data) => {
-                    return HttpResponse::Ok()
-                        .content_type(format.mime_type())
-                        .body(data);
-                }
-                Err(e) => {
-                    tracing::error!("transcoding failed: {}", e);
-                    // fall through to auto-transcode or raw serving
-                }
+    if let Some(format) = query.format.as_deref().and_then(AudioFormat::from_str) {
+        match TranscodeCache::get_or_start(&track.trackhash, file_path, format, bitrate).await {
+            Ok(cached) => return serve_transcode(cached, format, req).await,
+            Err(e) => {
+                tracing::error!("transcoding failed: {}", e);
+                // fall through to auto-transcode or raw serving
             }
         }
     }
@@ -99,12 +128,8 @@ pub async fn stream_track(
             target.extension()
         );
 
-        match Transcoder::transcode_to_bytes(file_path, target, quality) {
-            Ok(data) => {
-                return HttpResponse::Ok()
-                    .content_type(target.mime_type())
-                    .body(data);
-            }
+        match TranscodeCache::get_or_start(&track.trackhash, file_path, target, bitrate).await {
+            Ok(cached) => return serve_transcode(cached, target, req).await,
             Err(e) => {
                 tracing::error!("auto-transcode failed for {}: {}", file_path.display(), e);
                 // last resort: serve raw file and hope the client can deal with it
@@ -113,65 +138,221 @@ pub async fn stream_track(
     }
 
     // serve original file with range request support (browser-compatible formats)
-    serve_file_with_ranges(file_path, &req).await
+    let content_type = AudioFormat::mime_type_for_extension(file_ext);
+    if stream_tuning().sendfile {
+        if let Some(mut response) = sendfile_response(file_path, content_type, req) {
+            return response.finish();
+        }
+    }
+    serve_file_with_ranges(file_path, content_type, req).await
 }
 
-/// Serve file with HTTP range request support
-async fn serve_file_with_ranges(file_path: &Path, req: &HttpRequest) -> HttpResponse {
-    let file = match std::fs::File::open(file_path) {
+/// Join the listening session for this client when scrobble on disconnect is enabled
+async fn open_stream_session(track: &Track, req: &HttpRequest) -> Option<StreamGuard> {
+    let config = UserConfig::load().ok()?;
+    if !config.scrobble_on_disconnect {
+        return None;
+    }
+
+    let user = CurrentUser::resolve(req).await.ok()?;
+    let client = format!(
+        "{}|{}",
+        req.connection_info().realip_remote_addr().unwrap_or(""),
+        req.headers()
+            .get("User-Agent")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("")
+    );
+
+    let covers_end = match req.headers().get("Range").and_then(|v| v.to_str().ok()) {
+        Some(range) => {
+            let file_size = std::fs::metadata(&track.filepath).map(|m| m.len()).unwrap_or(0);
+            range_reaches_end(range, file_size)
+        }
+        None => true,
+    };
+
+    Some(StreamGuard::open(
+        client,
+        request_device(req),
+        user.id,
+        track,
+        covers_end,
+    ))
+}
+
+/// Whether a range request asks for the bytes up to the end of the file
+fn range_reaches_end(range_header: &str, file_size: u64) -> bool {
+    let Some(spec) = range_header.strip_prefix("bytes=") else {
+        return true;
+    };
+
+    match spec.split_once('-') {
+        Some((_, "")) => true,
+        Some((_, end)) => end
+            .trim()
+            .parse::<u64>()
+            .map(|end| end + 1 >= file_size)
+            .unwrap_or(true),
+        None => true,
+    }
+}
+
+/// Serve a cached or in-progress transcode
+///
+/// a finished transcode is a regular file with full range support. while ffmpeg is
+/// still running, plain playback tails the growing file and any range request waits
+/// for the shared job to finish so the byte offsets are final.
+async fn serve_transcode(
+    cached: CachedTranscode,
+    format: AudioFormat,
+    req: &HttpRequest,
+) -> HttpResponse {
+    let job = match cached {
+        CachedTranscode::Ready(path) => {
+            return serve_file_with_ranges(&path, format.mime_type(), req).await;
+        }
+        CachedTranscode::InProgress(job) => job,
+    };
+
+    let wants_range = req
+        .headers()
+        .get("Range")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim() != "bytes=0-")
+        .unwrap_or(false);
+
+    if wants_range {
+        return match job.wait().await {
+            Ok(path) => serve_file_with_ranges(&path, format.mime_type(), req).await,
+            Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Transcoding failed: {}", e)
+            })),
+        };
+    }
+
+    if let Err(e) = job.started().await {
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": format!("Transcoding failed: {}", e)
+        }));
+    }
+    let file = match tokio::fs::File::open(&job.path).await {
         Ok(f) => f,
-        Err(_) => return HttpResponse::InternalServerError().body("Failed to open file"),
+        Err(_) => return HttpResponse::InternalServerError().body("Failed to open transcode"),
     };
 
-    let metadata = match file.metadata() {
+    let body = futures::stream::unfold((file, job), |(mut file, job)| async move {
+        use tokio::io::AsyncReadExt;
+
+        let mut buffer = vec![0u8; stream_tuning().chunk_size];
+        loop {
+            // sample the state before reading so the final bytes are never skipped
+            let running = job.is_running();
+            match file.read(&mut buffer).await {
+                Ok(0) if running => tokio::time::sleep(TAIL_POLL_INTERVAL).await,
+                Ok(0) => return None,
+                Ok(n) => {
+                    buffer.truncate(n);
+                    return Some((Ok(web::Bytes::from(buffer)), (file, job)));
+                }
+                Err(e) => return Some((Err(e), (file, job))),
+            }
+        }
+    });
+
+    HttpResponse::Ok()
+        .insert_header(("Content-Type", format.mime_type()))
+        .insert_header(("Accept-Ranges", "bytes"))
+        .streaming(body)
+}
+
+/// Serve file with HTTP range request support
+///
+/// the body is streamed in chunks sized by the stream settings so large
+/// ranges are never read into memory at once
+async fn serve_file_with_ranges(
+    file_path: &Path,
+    content_type: &str,
+    req: &HttpRequest,
+) -> HttpResponse {
+    let metadata = match std::fs::metadata(file_path)
+        .and_then(|m| CachedFileMetadata::from_metadata(&m))
+    {
         Ok(m) => m,
         Err(_) => return HttpResponse::InternalServerError().body("Failed to get file metadata"),
     };
 
-    let file_size = metadata.len();
-
-    // determine content type using centralized extension -> mime mapping
-    let content_type = file_path
-        .extension()
-        .and_then(|e| e.to_str())
-        .map(AudioFormat::mime_type_for_extension)
-        .unwrap_or("application/octet-stream");
+    let if_none_match = req
+        .headers()
+        .get("If-None-Match")
+        .and_then(|v| v.to_str().ok());
+    let if_modified_since = req
+        .headers()
+        .get("If-Modified-Since")
+        .and_then(|v| v.to_str().ok());
 
-    // Check for Range header
-    if let Some(range_header) = req.headers().get("Range") {
-        let range_str = range_header.to_str().unwrap_or("");
+    if check_conditional_request(if_none_match, if_modified_since, &metadata) {
+        return HttpResponse::NotModified()
+            .insert_header(("ETag", metadata.etag.as_str()))
+            .insert_header(("Last-Modified", metadata.last_modified_http()))
+            .finish();
+    }
 
-        if let Some(range) = parse_range(range_str, file_size) {
-            let (start, end) = range;
-            let length = end - start + 1;
+    let file_size = metadata.size;
+    let range = req
+        .headers()
+        .get("Range")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|range| parse_range(range, file_size));
 
-            let mut file = file;
-            if file.seek(SeekFrom::Start(start)).is_err() {
-                return HttpResponse::InternalServerError().body("Failed to seek in file");
-            }
+    let (start, length) = match range {
+        Some((start, end)) => (start, end - start + 1),
+        None => (0, file_size),
+    };
 
-            let mut buffer = vec![0u8; length as usize];
-            if file.read_exact(&mut buffer).is_err() {
-                return HttpResponse::InternalServerError().body("Failed to read file");
-            }
+    let body = match file_cache::file_stream(file_path, start, length, stream_tuning()) {
+        Ok(body) => body,
+        Err(_) => return HttpResponse::InternalServerError().body("Failed to open file"),
+    };
 
-            return HttpResponse::PartialContent()
-                .insert_header(("Content-Type", content_type))
-                .insert_header(("Content-Length", length.to_string()))
-                .insert_header((
-                    "Content-Range",
-                    format!("bytes {}-{}/{}", start, end, file_size),
-                ))
-                .insert_header(("Accept-Ranges", "bytes"))
-                .body(buffer);
+    let mut response = match range {
+        Some((start, end)) => {
+            let mut response = HttpResponse::PartialContent();
+            response.insert_header((
+                "Content-Range",
+                format!("bytes {}-{}/{}", start, end, file_size),
+            ));
+            response
         }
-    }
+        None => HttpResponse::Ok(),
+    };
 
-    // Serve full file
-    match NamedFile::open(file_path) {
-        Ok(named_file) => named_file.into_response(req),
-        Err(_) => HttpResponse::InternalServerError().body("Failed to serve file"),
-    }
+    response
+        .insert_header(("Content-Type", content_type))
+        .insert_header(("Accept-Ranges", "bytes"))
+        .insert_header(("ETag", metadata.etag.as_str()))
+        .insert_header(("Last-Modified", metadata.last_modified_http()))
+        .no_chunking(length)
+        .streaming(body)
+}
+
+/// Delegate a file to the reverse proxy when it announced x-sendfile support
+fn sendfile_response(
+    file_path: &Path,
+    content_type: &str,
+    req: &HttpRequest,
+) -> Option<HttpResponseBuilder> {
+    let sendfile_type = req.headers().get("X-Sendfile-Type")?.to_str().ok()?;
+    let header_name = match sendfile_type {
+        "X-Accel-Redirect" => "X-Accel-Redirect", // nginx
+        _ => "X-Sendfile",                        // apache/lighttpd
+    };
+
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header((header_name, file_path.to_string_lossy().as_ref()))
+        .insert_header(("Content-Type", content_type));
+    Some(response)
 }
 
 /// Parse HTTP Range header
@@ -195,6 +376,10 @@ fn parse_range(range_header: &str, file_size: u64) -> Option<(u64, u64)> {
         parts[0].parse().ok()?
     };
 
+    if file_size == 0 {
+        return None;
+    }
+
     let end = if parts[1].is_empty() {
         file_size - 1
     } else {
@@ -209,6 +394,12 @@ fn parse_range(range_header: &str, file_size: u64) -> Option<(u64, u64)> {
 }
 
 /// Get track info for streaming
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 404, description = "Not found")
+    )
+)]
 #[get("/{trackhash}/info")]
 pub async fn stream_info(path: web::Path<String>) -> impl Responder {
     let trackhash = path.into_inner();
@@ -254,6 +445,14 @@ pub async fn stream_info(path: web::Path<String>) -> impl Responder {
 /// - cached path resolution to avoid repeated lookups
 /// - pre-computed root directory validation
 /// - x-sendfile header support for reverse proxies
+#[utoipa::path(
+    params(LegacyStreamQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 404, description = "Not found")
+    )
+)]
 #[get("/{trackhash}/legacy")]
 pub async fn stream_track_legacy(
     path: web::Path<String>,
@@ -377,8 +576,6 @@ async fn serve_file_optimized(
     cache: &std::sync::Arc<crate::core::file_cache::FileCache>,
     req: &HttpRequest,
 ) -> HttpResponse {
-    use crate::core::file_cache::check_conditional_request;
-
     // get cached metadata for etag/last-modified
     let metadata = match cache.get_metadata(file_path) {
         Ok(m) => m,
@@ -408,31 +605,21 @@ async fn serve_file_optimized(
 
     // check for x-sendfile support (reverse proxy optimization)
     // if the x-sendfile-type header is present, delegate to nginx/apache
-    if let Some(sendfile_type) = req.headers().get("X-Sendfile-Type") {
-        if let Ok(sendfile_type) = sendfile_type.to_str() {
-            let path_str = file_path.to_string_lossy();
-            let header_name = match sendfile_type {
-                "X-Accel-Redirect" => "X-Accel-Redirect", // nginx
-                _ => "X-Sendfile",                        // apache/lighttpd
-            };
-
-            return HttpResponse::Ok()
-                .insert_header((header_name, path_str.as_ref()))
-                .insert_header(("Content-Type", content_type))
-                .insert_header(("ETag", metadata.etag.as_str()))
-                .insert_header(("Last-Modified", metadata.last_modified_http()))
-                .insert_header(("Cache-Control", "private, max-age=31536000"))
-                .insert_header((
-                    "Content-Disposition",
-                    format!("attachment; filename=\"{}\"", filename),
-                ))
-                .finish();
-        }
+    if let Some(mut response) = sendfile_response(file_path, content_type, req) {
+        return response
+            .insert_header(("ETag", metadata.etag.as_str()))
+            .insert_header(("Last-Modified", metadata.last_modified_http()))
+            .insert_header(("Cache-Control", "private, max-age=31536000"))
+            .insert_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", filename),
+            ))
+            .finish();
     }
 
-    // try memory-mapped serving for small files
+    // try memory-mapped serving for small files, the body shares the mapping
     if let Ok(Some(mmap_region)) = cache.get_mmap(file_path) {
-        let data = mmap_region.mmap.to_vec(); // copy from mmap
+        let data = file_cache::region_bytes(mmap_region);
         return HttpResponse::Ok()
             .insert_header(("Content-Type", content_type))
             .insert_header(("Content-Length", data.len().to_string()))
@@ -516,6 +703,12 @@ fn serve_file_basic(
 }
 
 /// get silence paddings between two files (milliseconds)
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request")
+    )
+)]
 #[post("/silence")]
 pub async fn get_audio_silence(body: web::Json<SilenceBody>) -> impl Responder {
     let ending_file = Path::new(&body.ending_file);
@@ -578,6 +771,22 @@ fn ensure_in_root_dirs(raw_filepath: &str) -> Result<(), HttpResponse> {
     Ok(())
 }
 
+/// OpenAPI description of the stream routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    stream_track,
+    stream_info,
+))]
+pub struct ApiDoc;
+
+/// OpenAPI description of the legacy file routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    stream_track_legacy,
+    get_audio_silence,
+))]
+pub struct FileApiDoc;
+
 /// Configure stream routes
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(stream_track).service(stream_info);
@@ -0,0 +1,239 @@
+//! Offline sync API routes
+//!
+//! a client asks for an album or playlist converted to a codec and bitrate,
+//! polls the job and fetches the converted files as a zip once it finished
+
+use actix_web::http::header::ContentDisposition;
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::api::identity::{Authorized, Download};
+use crate::api::playlist::playlist_with_tracks;
+use crate::core::archive;
+use crate::core::offline_sync::{self, SyncSource, SyncState, SyncStatus};
+use crate::core::transcode::AudioFormat;
+use crate::core::SortLib;
+use crate::stores::{AlbumStore, TrackStore};
+
+fn default_format() -> String {
+    "mp3".to_string()
+}
+
+fn default_bitrate() -> u32 {
+    192
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SyncBody {
+    /// "album" or "playlist"
+    pub itemtype: String,
+    /// album hash or playlist id
+    pub itemhash: String,
+    /// mp3, flac, ogg, opus, aac or wav
+    #[serde(default = "default_format")]
+    pub format: String,
+    /// kbps, ignored by lossless formats
+    #[serde(default = "default_bitrate")]
+    pub bitrate: u32,
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(json!({"error": "Sync job not found"}))
+}
+
+/// Resolve the album or playlist a job converts, only files on disk are kept
+async fn resolve_source(body: &SyncBody, user_id: i64) -> Result<SyncSource, HttpResponse> {
+    let (title, mut tracks, playlist) = match body.itemtype.as_str() {
+        "album" => {
+            let Some(album) = AlbumStore::get().get_by_hash(&body.itemhash) else {
+                return Err(HttpResponse::NotFound().json(json!({"error": "Album not found"})));
+            };
+            let mut tracks = TrackStore::get().get_by_album(&album.albumhash);
+            SortLib::sort_tracks_album_order(&mut tracks);
+            let title = format!("{} - {}", album.albumartist(), album.title);
+            (title, tracks, false)
+        }
+        "playlist" => match playlist_with_tracks(&body.itemhash, user_id).await {
+            Ok(Some((playlist, tracks))) => (playlist.name, tracks, true),
+            Ok(None) => {
+                return Err(HttpResponse::NotFound().json(json!({"error": "Playlist not found"})))
+            }
+            Err(e) => {
+                tracing::error!("Failed to read playlist for sync: {}", e);
+                return Err(HttpResponse::InternalServerError()
+                    .json(json!({"error": "Failed to read playlist"})));
+            }
+        },
+        _ => {
+            return Err(HttpResponse::BadRequest()
+                .json(json!({"error": "itemtype must be album or playlist"})))
+        }
+    };
+
+    tracks.retain(|t| std::path::Path::new(&t.filepath).is_file());
+    if tracks.is_empty() {
+        return Err(HttpResponse::NotFound().json(json!({"error": "No files to sync"})));
+    }
+
+    let entries = if playlist {
+        archive::playlist_entries(&tracks)
+    } else {
+        archive::album_entries(&tracks)
+    };
+    Ok(SyncSource {
+        itemtype: body.itemtype.clone(),
+        itemhash: body.itemhash.clone(),
+        title,
+        tracks,
+        entries,
+    })
+}
+
+/// POST /sync
+///
+/// queues a job converting an album or playlist, poll it for progress
+#[utoipa::path(
+    request_body = SyncBody,
+    responses(
+        (status = 202, description = "Accepted", body = SyncStatus),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[post("")]
+pub async fn create_sync_job(
+    user: Authorized<Download>,
+    body: web::Json<SyncBody>,
+) -> impl Responder {
+    let body = body.into_inner();
+    let Some(format) = AudioFormat::from_str(&body.format) else {
+        return HttpResponse::BadRequest().json(json!({"error": "Unsupported format"}));
+    };
+
+    let source = match resolve_source(&body, user.id).await {
+        Ok(source) => source,
+        Err(response) => return response,
+    };
+
+    match offline_sync::start(user.id, source, format, body.bitrate) {
+        Ok(status) => HttpResponse::Accepted().json(status),
+        Err(e) => HttpResponse::BadRequest().json(json!({"error": e.to_string()})),
+    }
+}
+
+/// GET /sync
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("")]
+pub async fn list_sync_jobs(user: Authorized<Download>) -> impl Responder {
+    HttpResponse::Ok().json(json!({"jobs": offline_sync::list(user.id)}))
+}
+
+/// GET /sync/{id}
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = SyncStatus),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/{id}")]
+pub async fn get_sync_job(user: Authorized<Download>, path: web::Path<String>) -> impl Responder {
+    match offline_sync::get(&path.into_inner(), user.id) {
+        Some(status) => HttpResponse::Ok().json(status),
+        None => not_found(),
+    }
+}
+
+/// GET /sync/{id}/download
+///
+/// the converted files as a zip, once the job finished
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Job has not finished")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/{id}/download")]
+pub async fn download_sync_job(
+    user: Authorized<Download>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let Some((status, entries)) = offline_sync::entries(&path.into_inner(), user.id) else {
+        return not_found();
+    };
+    if status.state != SyncState::Finished {
+        return HttpResponse::Conflict().json(json!({
+            "error": "Sync job has not finished",
+            "state": status.state,
+        }));
+    }
+
+    HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header(ContentDisposition::attachment(archive::archive_name(
+            &status.title,
+        )))
+        .streaming(archive::zip_stream(entries))
+}
+
+/// DELETE /sync/{id}
+///
+/// cancels a job that is still running and deletes its files
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[delete("/{id}")]
+pub async fn delete_sync_job(
+    user: Authorized<Download>,
+    path: web::Path<String>,
+) -> impl Responder {
+    if offline_sync::remove(&path.into_inner(), user.id) {
+        HttpResponse::Ok().json(json!({"msg": "Sync job deleted"}))
+    } else {
+        not_found()
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    create_sync_job,
+    list_sync_jobs,
+    get_sync_job,
+    download_sync_job,
+    delete_sync_job,
+))]
+pub struct ApiDoc;
+
+/// Configure offline sync routes
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_sync_job)
+        .service(list_sync_jobs)
+        .service(get_sync_job)
+        .service(download_sync_job)
+        .service(delete_sync_job);
+}
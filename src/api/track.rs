@@ -1,11 +1,22 @@
 //! Track-specific API routes
 
-use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
-
-use crate::core::{tagger::Tagger, trackslib::TracksLib};
-use crate::stores::TrackStore;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::api::identity::{Admin, Authorized, CurrentUser, DeleteFiles, Download, EditTags};
+use crate::api::imgserver::{insert_image_hints, CardImage};
+use crate::config::UserConfig;
+use crate::core::organizer::{self, FileMove};
+use crate::core::{audiobooks, lossless, tagger::Tagger, trackslib::TracksLib, trash, FolderLib};
+use crate::db::tables::{
+    DiscoveryTable, LosslessCheck, LosslessTable, PlaylistTable, RatingTable, TrackPositionTable,
+    TrashTable,
+};
+use crate::models::Track;
+use crate::stores::{PlayStatsStore, PlaylistMembershipStore, TrackStore};
 
 /// Single track hash path
 #[derive(Debug, Deserialize)]
@@ -14,13 +25,27 @@ pub struct TrackPath {
 }
 
 /// Multiple tracks request
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct TracksRequest {
     pub trackhashes: Vec<String>,
 }
 
+/// Playback position update request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PositionUpdate {
+    /// seconds into the track
+    pub position: i64,
+}
+
+/// Rating update request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RatingUpdate {
+    /// stars from 1 to 5, zero clears the user's rating
+    pub rating: u8,
+}
+
 /// Track metadata update request
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct TrackMetadataUpdate {
     pub title: Option<String>,
     pub artist: Option<String>,
@@ -32,13 +57,39 @@ pub struct TrackMetadataUpdate {
     pub disc_number: Option<i32>,
 }
 
+/// Track move request
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct MoveRequest {
+    /// path template, the configured one when missing
+    #[serde(default)]
+    pub template: Option<String>,
+    /// only report where the file would go
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
 /// Get track by hash
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/{trackhash}")]
-pub async fn get_track(path: web::Path<String>) -> impl Responder {
+pub async fn get_track(user: CurrentUser, path: web::Path<String>) -> impl Responder {
     let trackhash = path.into_inner();
 
     match TrackStore::get().get_by_hash(&trackhash) {
-        Some(track) => HttpResponse::Ok().json(track),
+        Some(track) => {
+            let mut track = serialize_for_user(vec![track], user.id).remove(0);
+            let discovered_at = DiscoveryTable::get(user.id, "track", &trackhash)
+                .await
+                .unwrap_or_default();
+            track["discovered_at"] = serde_json::json!(discovered_at);
+            HttpResponse::Ok().json(track)
+        }
         None => HttpResponse::NotFound().json(serde_json::json!({
             "error": "Track not found"
         })),
@@ -46,14 +97,22 @@ pub async fn get_track(path: web::Path<String>) -> impl Responder {
 }
 
 /// Get multiple tracks by hashes
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[post("/batch")]
-pub async fn get_tracks_batch(body: web::Json<TracksRequest>) -> impl Responder {
+pub async fn get_tracks_batch(user: CurrentUser, body: web::Json<TracksRequest>) -> impl Responder {
     let store = TrackStore::get();
     let tracks: Vec<_> = body
         .trackhashes
         .iter()
         .filter_map(|h| store.get_by_hash(h))
         .collect();
+    let tracks = serialize_for_user(tracks, user.id);
 
     HttpResponse::Ok().json(serde_json::json!({
         "tracks": tracks,
@@ -63,6 +122,12 @@ pub async fn get_tracks_batch(body: web::Json<TracksRequest>) -> impl Responder
 }
 
 /// Get track file info
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 404, description = "Not found")
+    )
+)]
 #[get("/{trackhash}/file")]
 pub async fn get_track_file_info(path: web::Path<String>) -> impl Responder {
     let trackhash = path.into_inner();
@@ -97,9 +162,139 @@ pub async fn get_track_file_info(path: web::Path<String>) -> impl Responder {
     HttpResponse::Ok().json(file_info)
 }
 
+/// Stored verdict on whether a lossless track really is lossless
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    )
+)]
+#[get("/{trackhash}/lossless")]
+pub async fn get_lossless_check(path: web::Path<String>) -> impl Responder {
+    let trackhash = path.into_inner();
+    let Some(track) = TrackStore::get().get_by_hash(&trackhash) else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Track not found"
+        }));
+    };
+    if !lossless::is_lossless(&track) {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Track is not a lossless file"
+        }));
+    }
+
+    match LosslessTable::get_by_trackhash(&trackhash).await {
+        Ok(Some(check)) if check.filepath == track.filepath && check.last_mod == track.last_mod => {
+            HttpResponse::Ok().json(lossless_check_value(&check))
+        }
+        Ok(_) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Track has not been checked yet"
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": e.to_string()
+        })),
+    }
+}
+
+/// Run the spectral check on a lossless track now
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 404, description = "Not found")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[post("/{trackhash}/lossless")]
+pub async fn run_lossless_check(
+    _admin: Authorized<Admin>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let trackhash = path.into_inner();
+    if !TrackStore::get().exists(&trackhash) {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Track not found"
+        }));
+    }
+
+    match lossless::check_track(&trackhash).await {
+        Ok(check) => HttpResponse::Ok().json(lossless_check_value(&check)),
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": e.to_string()
+        })),
+    }
+}
+
+fn lossless_check_value(check: &LosslessCheck) -> serde_json::Value {
+    serde_json::json!({
+        "trackhash": check.trackhash,
+        "verdict": check.verdict,
+        "cutoff": check.cutoff,
+        "extent": check.extent,
+        "samplerate": check.samplerate,
+        "checked_at": check.checked_at,
+    })
+}
+
+/// Download the original file of a track
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/{trackhash}/download")]
+pub async fn download_track(
+    _user: Authorized<Download>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> impl Responder {
+    let Some(track) = TrackStore::get().get_by_hash(&path.into_inner()) else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Track not found"
+        }));
+    };
+
+    let file = match actix_files::NamedFile::open(&track.filepath) {
+        Ok(file) => file,
+        Err(_) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Track file not found"
+            }));
+        }
+    };
+
+    let filename = std::path::Path::new(&track.filepath)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| track.title.clone());
+
+    file.set_content_disposition(ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![DispositionParam::Filename(filename)],
+    })
+    .into_response(&req)
+}
+
 /// Update track metadata (writes to file)
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 #[put("/{trackhash}/metadata")]
 pub async fn update_track_metadata(
+    _user: Authorized<EditTags>,
     path: web::Path<String>,
     body: web::Json<TrackMetadataUpdate>,
     pool: web::Data<SqlitePool>,
@@ -166,33 +361,186 @@ pub async fn update_track_metadata(
     }))
 }
 
-/// Delete track from library (removes from index, not file)
-#[delete("/{trackhash}")]
-pub async fn delete_track(path: web::Path<String>, pool: web::Data<SqlitePool>) -> impl Responder {
-    let trackhash = path.into_inner();
+/// Move a track's file to where the path template puts it
+#[utoipa::path(
+    request_body = MoveRequest,
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin access required"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "File can't be moved there"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[post("/{trackhash}/move")]
+pub async fn move_track(
+    _admin: Authorized<Admin>,
+    path: web::Path<String>,
+    body: Option<web::Json<MoveRequest>>,
+) -> impl Responder {
+    let body = body.map(|b| b.into_inner()).unwrap_or_default();
+    let Some(track) = TrackStore::get().get_by_hash(&path.into_inner()) else {
+        return HttpResponse::NotFound().json(serde_json::json!({"error": "Track not found"}));
+    };
+    if !std::path::Path::new(&track.filepath).is_file() {
+        return HttpResponse::NotFound()
+            .json(serde_json::json!({"error": "Track file not found"}));
+    }
 
-    // Remove from store
-    let removed = TrackStore::get().remove(&trackhash);
+    let template = match body.template {
+        Some(template) => template,
+        None => match UserConfig::load() {
+            Ok(config) => config.file_template,
+            Err(e) => {
+                return HttpResponse::InternalServerError()
+                    .json(serde_json::json!({"error": format!("Failed to load config: {}", e)}))
+            }
+        },
+    };
+    if let Err(e) = organizer::validate_template(&template) {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error": e.to_string()}));
+    }
 
-    if removed {
-        // TODO: Remove from database
-        // Also update album/artist stores
+    let moves: Vec<FileMove> =
+        match organizer::plan(&[track], &template, &FolderLib::get_root_dirs()) {
+            Ok(moves) => moves,
+            Err(e) => {
+                return HttpResponse::Conflict().json(serde_json::json!({"error": e.to_string()}))
+            }
+        };
+    if body.dry_run {
+        return HttpResponse::Ok().json(serde_json::json!({"moves": moves, "dry_run": true}));
+    }
 
-        HttpResponse::Ok().json(serde_json::json!({
+    match organizer::apply(moves.clone()).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({"moves": moves, "dry_run": false})),
+        Err(e) => {
+            tracing::error!("Failed to move track: {:#}", e);
+            HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": format!("{:#}", e)}))
+        }
+    }
+}
+
+/// Move a track's file to the trash and remove it from the library, it can
+/// be restored until the trash is purged
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[delete("/{trackhash}")]
+pub async fn delete_track(
+    auth: Authorized<DeleteFiles>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let Some(track) = TrackStore::get().get_by_hash(&path.into_inner()) else {
+        return HttpResponse::NotFound().json(serde_json::json!({"error": "Track not found"}));
+    };
+
+    match trash::trash_track(&track, auth.user.id).await {
+        Ok(trashed) => HttpResponse::Ok().json(serde_json::json!({
             "success": true,
-            "message": "Track removed from library"
-        }))
-    } else {
-        HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Track not found"
-        }))
+            "message": "Track moved to the trash",
+            "trash_id": trashed.id,
+        })),
+        Err(e) => {
+            tracing::error!("Failed to trash track: {:#}", e);
+            HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": format!("{:#}", e)}))
+        }
+    }
+}
+
+/// Tracks waiting in the trash, most recently deleted first
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 500, description = "Server error")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[get("/trash")]
+pub async fn get_trash(_auth: Authorized<DeleteFiles>) -> impl Responder {
+    let retention_days = UserConfig::load()
+        .map(|c| c.trash_retention_days)
+        .unwrap_or_default();
+
+    match TrashTable::all().await {
+        Ok(tracks) => HttpResponse::Ok().json(serde_json::json!({
+            "tracks": tracks,
+            "retention_days": retention_days,
+        })),
+        Err(e) => HttpResponse::InternalServerError()
+            .json(serde_json::json!({"error": format!("Failed to load the trash: {}", e)})),
+    }
+}
+
+/// Restore track request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RestoreRequest {
+    /// trash entry to restore
+    pub id: i64,
+}
+
+/// Move a trashed track back to where it was deleted from
+#[utoipa::path(
+    request_body = RestoreRequest,
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Permission required"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Track can't be restored")
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+#[post("/restore")]
+pub async fn restore_track(
+    _auth: Authorized<DeleteFiles>,
+    body: web::Json<RestoreRequest>,
+) -> impl Responder {
+    match trash::restore(body.id).await {
+        Ok(Some(track)) => HttpResponse::Ok().json(serde_json::json!({
+            "trackhash": track.trackhash,
+            "filepath": track.filepath,
+        })),
+        Ok(None) => {
+            HttpResponse::NotFound().json(serde_json::json!({"error": "Track is not in the trash"}))
+        }
+        Err(e) => HttpResponse::Conflict().json(serde_json::json!({"error": format!("{:#}", e)})),
     }
 }
 
 /// Get tracks by folder path
+#[utoipa::path(
+    params(FolderQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/folder")]
-pub async fn get_tracks_by_folder(query: web::Query<FolderQuery>) -> impl Responder {
-    let tracks = TracksLib::get_by_folder(&query.path);
+pub async fn get_tracks_by_folder(
+    user: CurrentUser,
+    query: web::Query<FolderQuery>,
+) -> impl Responder {
+    let mut tracks = TracksLib::get_by_folder(&query.path);
+    if audiobooks::is_audiobook_folder(&query.path) {
+        audiobooks::sort_file_order(&mut tracks);
+    }
+    let tracks = serialize_for_user(tracks, user.id);
 
     HttpResponse::Ok().json(serde_json::json!({
         "tracks": tracks,
@@ -201,16 +549,27 @@ pub async fn get_tracks_by_folder(query: web::Query<FolderQuery>) -> impl Respon
     }))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct FolderQuery {
     pub path: String,
 }
 
 /// Get recently added tracks
+#[utoipa::path(
+    params(RecentQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/recent")]
-pub async fn get_recent_tracks(query: web::Query<RecentQuery>) -> impl Responder {
+pub async fn get_recent_tracks(
+    user: CurrentUser,
+    query: web::Query<RecentQuery>,
+) -> impl Responder {
     let limit = query.limit.unwrap_or(50);
-    let tracks = TracksLib::get_recent(limit);
+    let tracks = serialize_for_user(TracksLib::get_recent(limit), user.id);
 
     HttpResponse::Ok().json(serde_json::json!({
         "tracks": tracks,
@@ -218,24 +577,37 @@ pub async fn get_recent_tracks(query: web::Query<RecentQuery>) -> impl Responder
     }))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct RecentQuery {
     pub limit: Option<usize>,
 }
 
 /// Get random tracks
+#[utoipa::path(
+    params(RandomQuery),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[get("/random")]
-pub async fn get_random_tracks(query: web::Query<RandomQuery>) -> impl Responder {
+pub async fn get_random_tracks(
+    user: CurrentUser,
+    query: web::Query<RandomQuery>,
+) -> impl Responder {
     use rand::seq::SliceRandom;
 
     let count = query.count.unwrap_or(20);
-    let all_tracks = TrackStore::get().get_all();
+    let mut all_tracks = TrackStore::get().get_all_shared();
+    all_tracks.retain(|t| !audiobooks::is_audiobook(t));
 
     let mut rng = rand::thread_rng();
     let tracks: Vec<_> = all_tracks
         .choose_multiple(&mut rng, count.min(all_tracks.len()))
-        .cloned()
+        .map(|t| t.as_ref().clone())
         .collect();
+    let tracks = serialize_for_user(tracks, user.id);
 
     HttpResponse::Ok().json(serde_json::json!({
         "tracks": tracks,
@@ -243,12 +615,18 @@ pub async fn get_random_tracks(query: web::Query<RandomQuery>) -> impl Responder
     }))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct RandomQuery {
     pub count: Option<usize>,
 }
 
 /// Get track lyrics
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 404, description = "Not found")
+    )
+)]
 #[get("/{trackhash}/lyrics")]
 pub async fn get_track_lyrics(path: web::Path<String>) -> impl Responder {
     let trackhash = path.into_inner();
@@ -262,15 +640,15 @@ pub async fn get_track_lyrics(path: web::Path<String>) -> impl Responder {
         }
     };
 
-    // Try embedded lyrics first
+    // Try sidecar and embedded lyrics first
     let file_path = std::path::Path::new(&track.filepath);
-    if let Some(embedded) = crate::core::lyrics::LyricsLib::from_embedded(file_path) {
-        let lyrics_text = crate::core::lyrics::LyricsLib::to_lrc(&embedded);
+    if let Some(local) = crate::core::lyrics::LyricsLib::local(file_path) {
+        let lyrics_text = crate::core::lyrics::LyricsLib::to_lrc(&local);
         if !lyrics_text.is_empty() {
             return HttpResponse::Ok().json(serde_json::json!({
-                "source": "embedded",
+                "source": local.source,
                 "lyrics": lyrics_text,
-                "synced": embedded.is_synced
+                "synced": local.is_synced
             }));
         }
     }
@@ -297,15 +675,323 @@ pub async fn get_track_lyrics(path: web::Path<String>) -> impl Responder {
     }
 }
 
+/// Get the tracks the user can resume, most recently played first
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/positions")]
+pub async fn get_track_positions(user: CurrentUser) -> impl Responder {
+    let positions = match TrackPositionTable::all(user.id).await {
+        Ok(p) => p,
+        Err(e) => {
+            tracing::error!("Failed to get track positions: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to get track positions"
+            }));
+        }
+    };
+
+    let store = TrackStore::get();
+    let (tracks, positions): (Vec<_>, Vec<_>) = positions
+        .into_iter()
+        .filter_map(|p| Some((store.get_by_hash(&p.trackhash)?, p)))
+        .unzip();
+
+    let tracks: Vec<_> = serialize_for_user(tracks, user.id)
+        .into_iter()
+        .zip(positions)
+        .map(|(mut track, saved)| {
+            if let Some(obj) = track.as_object_mut() {
+                obj.insert("position".to_string(), serde_json::json!(saved.position));
+                obj.insert(
+                    "position_updated".to_string(),
+                    serde_json::json!(saved.updated),
+                );
+            }
+            track
+        })
+        .collect();
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "tracks": tracks,
+        "count": tracks.len()
+    }))
+}
+
+/// Get the saved playback position of a track
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/{trackhash}/position")]
+pub async fn get_track_position(user: CurrentUser, path: web::Path<String>) -> impl Responder {
+    let trackhash = path.into_inner();
+
+    match TrackPositionTable::get(user.id, &trackhash).await {
+        Ok(saved) => HttpResponse::Ok().json(serde_json::json!({
+            "trackhash": trackhash,
+            "position": saved.as_ref().map_or(0, |p| p.position),
+            "updated": saved.map(|p| p.updated),
+        })),
+        Err(e) => {
+            tracing::error!("Failed to get track position: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to get track position"
+            }))
+        }
+    }
+}
+
+/// Save the playback position of a track
+///
+/// a position at the start or the end of the track clears the saved one
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[post("/{trackhash}/position")]
+pub async fn set_track_position(
+    user: CurrentUser,
+    path: web::Path<String>,
+    body: web::Json<PositionUpdate>,
+) -> impl Responder {
+    let trackhash = path.into_inner();
+
+    let track = match TrackStore::get().get_by_hash(&trackhash) {
+        Some(t) => t,
+        None => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Track not found"
+            }));
+        }
+    };
+
+    let finished = track.duration > 0 && body.position >= track.duration as i64;
+    let result = if body.position <= 0 || finished {
+        TrackPositionTable::delete(user.id, &trackhash).await
+    } else {
+        let now = chrono::Utc::now().timestamp();
+        TrackPositionTable::set(user.id, &trackhash, body.position, now).await
+    };
+
+    match result {
+        Ok(()) => HttpResponse::Ok().json(serde_json::json!({
+            "trackhash": trackhash,
+            "position": if body.position <= 0 || finished { 0 } else { body.position },
+        })),
+        Err(e) => {
+            tracing::error!("Failed to save track position: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to save track position"
+            }))
+        }
+    }
+}
+
+/// Rate a track for the current user
+///
+/// clearing the rating falls back to the one in the file tags
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[post("/{trackhash}/rate")]
+pub async fn rate_track(
+    user: CurrentUser,
+    path: web::Path<String>,
+    body: web::Json<RatingUpdate>,
+) -> impl Responder {
+    let trackhash = path.into_inner();
+
+    if body.rating > 5 {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Rating must be between 0 and 5"
+        }));
+    }
+
+    let track = match TrackStore::get().get_by_hash(&trackhash) {
+        Some(t) => t,
+        None => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Track not found"
+            }));
+        }
+    };
+
+    let rating = (body.rating > 0).then_some(body.rating);
+    let result = match rating {
+        Some(stars) => {
+            let now = chrono::Utc::now().timestamp();
+            RatingTable::set(user.id, &trackhash, stars, now).await
+        }
+        None => RatingTable::delete(user.id, &trackhash).await,
+    };
+
+    match result {
+        Ok(()) => {
+            PlayStatsStore::get().set_rating(user.id, &trackhash, rating);
+            HttpResponse::Ok().json(serde_json::json!({
+                "trackhash": trackhash,
+                "rating": rating.unwrap_or_else(|| track.tag_rating()),
+                "user_rating": rating,
+            }))
+        }
+        Err(e) => {
+            tracing::error!("Failed to save track rating: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to save track rating"
+            }))
+        }
+    }
+}
+
+/// List the user's playlists containing a track
+///
+/// each entry carries the track's positions so the client can remove it
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Server error")
+    ),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[get("/{trackhash}/playlists")]
+pub async fn get_track_playlists(user: CurrentUser, path: web::Path<String>) -> impl Responder {
+    let trackhash = path.into_inner();
+
+    if TrackStore::get().get_by_hash(&trackhash).is_none() {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Track not found"
+        }));
+    }
+
+    let mut playlists = Vec::new();
+    for id in PlaylistMembershipStore::get().playlists_with(&trackhash, user.id) {
+        let playlist = match PlaylistTable::get_by_id(id).await {
+            Ok(Some(p)) => p,
+            Ok(None) => continue,
+            Err(_) => {
+                return HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": "Failed to get playlists"
+                }))
+            }
+        };
+
+        let positions: Vec<usize> = playlist
+            .trackhashes
+            .iter()
+            .enumerate()
+            .filter(|(_, hash)| **hash == trackhash)
+            .map(|(index, _)| index)
+            .collect();
+
+        playlists.push(serde_json::json!({
+            "id": playlist.id,
+            "name": playlist.name,
+            "image": playlist.image,
+            "last_updated": playlist.last_updated,
+            "count": playlist.trackhashes.len(),
+            "positions": positions,
+        }));
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "playlists": playlists,
+        "count": playlists.len()
+    }))
+}
+
+/// Serialize tracks with the user's own play stats and favorite flag
+pub fn serialize_for_user(mut tracks: Vec<Track>, user_id: i64) -> Vec<serde_json::Value> {
+    PlayStatsStore::get().personalize_tracks(user_id, &mut tracks);
+    tracks
+        .into_iter()
+        .map(|track| {
+            let is_favorite = track.fav_userids.contains(&user_id);
+            let filepath = track.filepath.clone();
+            let mut value = serde_json::to_value(track).unwrap_or_default();
+            if let Some(obj) = value.as_object_mut() {
+                obj.remove("fav_userids");
+                obj.insert("is_favorite".to_string(), serde_json::json!(is_favorite));
+                obj.insert(
+                    "is_audiobook".to_string(),
+                    serde_json::json!(audiobooks::is_audiobook_path(&filepath)),
+                );
+                insert_image_hints(obj, CardImage::Thumbnail);
+            }
+            value
+        })
+        .collect()
+}
+
+/// OpenAPI description of the track routes
+#[derive(OpenApi)]
+#[openapi(paths(
+    get_track_positions,
+    get_track,
+    get_tracks_batch,
+    get_track_file_info,
+    get_lossless_check,
+    run_lossless_check,
+    download_track,
+    update_track_metadata,
+    move_track,
+    delete_track,
+    get_trash,
+    restore_track,
+    get_tracks_by_folder,
+    get_recent_tracks,
+    get_random_tracks,
+    get_track_lyrics,
+    get_track_position,
+    set_track_position,
+    rate_track,
+    get_track_playlists,
+))]
+pub struct ApiDoc;
+
 /// Configure track routes
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(get_track)
+    cfg.service(get_track_positions)
+        .service(get_trash)
+        .service(get_track)
         .service(get_tracks_batch)
         .service(get_track_file_info)
+        .service(get_lossless_check)
+        .service(run_lossless_check)
+        .service(download_track)
         .service(update_track_metadata)
+        .service(move_track)
         .service(delete_track)
+        .service(restore_track)
         .service(get_tracks_by_folder)
         .service(get_recent_tracks)
         .service(get_random_tracks)
-        .service(get_track_lyrics);
+        .service(get_track_lyrics)
+        .service(get_track_position)
+        .service(set_track_position)
+        .service(rate_track)
+        .service(get_track_playlists);
 }
@@ -6,7 +6,10 @@ mod paths;
 mod user_config;
 
 pub use paths::Paths;
-pub use user_config::UserConfig;
+pub use user_config::{
+    AlbumMergeRules, ArtistImageProvider, ArtistImageSettings, CoverSource, MixSettings,
+    OidcSettings, SplitField, ThumbnailSettings, UserConfig, WatchdogRootOptions,
+};
 
 /// Default thumbnail sizes
 pub const XSM_THUMB_SIZE: u32 = 64;
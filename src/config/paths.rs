@@ -92,6 +92,10 @@ impl Paths {
             "images/mixes/medium",
             "images/mixes/small",
             "backups",
+            "cache/transcodes",
+            "cache/artwork",
+            "cache/sync",
+            "podcasts",
         ];
 
         for subdir in subdirs {
@@ -153,6 +157,16 @@ impl Paths {
         self.config_dir.join("settings.json")
     }
 
+    /// Get the persisted library scan state path
+    pub fn scan_state_path(&self) -> PathBuf {
+        self.config_dir.join("scan_state.json")
+    }
+
+    /// Get the last database maintenance report path
+    pub fn db_maintenance_path(&self) -> PathBuf {
+        self.config_dir.join("db_maintenance.json")
+    }
+
     /// Get the assets directory
     pub fn assets_dir(&self) -> PathBuf {
         self.config_dir.join("assets")
@@ -173,6 +187,41 @@ impl Paths {
         self.config_dir.join("backups")
     }
 
+    /// Get the cache directory
+    pub fn cache_dir(&self) -> PathBuf {
+        self.config_dir.join("cache")
+    }
+
+    /// Get the transcode cache directory
+    pub fn transcode_cache_dir(&self) -> PathBuf {
+        self.cache_dir().join("transcodes")
+    }
+
+    /// Get the directory offline sync jobs write their files into
+    pub fn sync_dir(&self) -> PathBuf {
+        self.cache_dir().join("sync")
+    }
+
+    /// Get the original artwork cache directory
+    pub fn artwork_cache_dir(&self) -> PathBuf {
+        self.cache_dir().join("artwork")
+    }
+
+    /// Get the resized image cache directory
+    pub fn resized_image_cache_dir(&self) -> PathBuf {
+        self.cache_dir().join("resized")
+    }
+
+    /// Get the directory deleted tracks are kept in until they are purged
+    pub fn trash_dir(&self) -> PathBuf {
+        self.config_dir.join("trash")
+    }
+
+    /// Get the downloaded podcast episodes directory
+    pub fn podcasts_dir(&self) -> PathBuf {
+        self.config_dir.join("podcasts")
+    }
+
     // ========== Image Paths ==========
 
     /// Get the images directory
@@ -202,12 +251,6 @@ impl Paths {
 
     // ========== Path Helpers ==========
 
-    /// Get the path for an album thumbnail
-    pub fn get_thumbnail_path(&self, albumhash: &str, size: &str) -> PathBuf {
-        self.thumbnails_dir(size)
-            .join(format!("{}.webp", albumhash))
-    }
-
     /// Get the path for an artist image
     pub fn get_artist_image_path(&self, artisthash: &str, size: &str) -> PathBuf {
         self.artist_images_dir(size)
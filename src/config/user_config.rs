@@ -6,11 +6,12 @@ use anyhow::{Context, Result};
 use once_cell::sync::OnceCell;
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use utoipa::ToSchema;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use std::sync::Arc;
 
-use super::Paths;
+use super::{Paths, LG_THUMB_SIZE, MD_THUMB_SIZE, SM_THUMB_SIZE, XSM_THUMB_SIZE};
 
 static USER_CONFIG: OnceCell<Arc<RwLock<UserConfig>>> = OnceCell::new();
 
@@ -26,6 +27,11 @@ pub struct UserConfig {
     #[serde(default = "default_true")]
     pub users_on_login: bool,
 
+    /// Requests without a token act as the first admin, for single-user
+    /// setups without logins
+    #[serde(default)]
+    pub single_user_mode: bool,
+
     /// Root directories to scan for music
     #[serde(default)]
     pub root_dirs: Vec<String>,
@@ -34,10 +40,35 @@ pub struct UserConfig {
     #[serde(default)]
     pub exclude_dirs: Vec<String>,
 
+    /// Folders whose tracks are audiobooks
+    #[serde(default)]
+    pub audiobook_dirs: Vec<String>,
+
+    /// Path template tracks are moved to under their root directory
+    #[serde(default = "default_file_template")]
+    pub file_template: String,
+
+    /// Folder new files are dropped in to be filed into the library, empty
+    /// when there is no inbox
+    #[serde(default)]
+    pub inbox_dir: String,
+
+    /// Import m3u files found in the library as playlists owned by the admin
+    #[serde(default)]
+    pub import_folder_playlists: bool,
+
+    /// Days deleted tracks stay in the trash before they are purged
+    #[serde(default = "default_trash_retention_days")]
+    pub trash_retention_days: u32,
+
     /// Artist name separators
     #[serde(default = "default_artist_separators")]
     pub artist_separators: HashSet<String>,
 
+    /// Album artist name separators, the artist separators when empty
+    #[serde(default)]
+    pub album_artist_separators: HashSet<String>,
+
     /// Artists to ignore when splitting (e.g., "AC/DC")
     #[serde(default = "default_artist_split_ignore_list")]
     pub artist_split_ignore_list: HashSet<String>,
@@ -62,6 +93,10 @@ pub struct UserConfig {
     #[serde(default)]
     pub merge_albums: bool,
 
+    /// Which albums are merged when merging is on
+    #[serde(default)]
+    pub album_merge_rules: AlbumMergeRules,
+
     /// Clean album titles
     #[serde(default = "default_true")]
     pub clean_album_title: bool,
@@ -70,6 +105,11 @@ pub struct UserConfig {
     #[serde(default)]
     pub show_albums_as_singles: bool,
 
+    /// Group each artist's singles into a virtual "Singles & EPs" album
+    /// when albums are not shown as singles
+    #[serde(default)]
+    pub group_singles: bool,
+
     /// Enable periodic scans
     #[serde(default)]
     pub enable_periodic_scans: bool,
@@ -78,10 +118,50 @@ pub struct UserConfig {
     #[serde(default = "default_scan_interval")]
     pub scan_interval: u32,
 
+    /// Hours between scheduled database maintenance runs, 0 disables them
+    #[serde(default)]
+    pub db_maintenance_interval: u32,
+
+    /// How much a user's plays and favorites lift their search results, 0 disables it
+    #[serde(default = "default_search_personal_boost")]
+    pub search_personal_boost: f64,
+
+    /// Compute chromaprint fingerprints of new and changed tracks after scans
+    #[serde(default)]
+    pub enable_fingerprinting: bool,
+
+    /// Path to the fpcalc binary used for fingerprints
+    #[serde(default = "default_fpcalc_path")]
+    pub fpcalc_path: String,
+
+    /// Check new and changed tracks for gapless album transitions after scans
+    #[serde(default)]
+    pub enable_gapless_analysis: bool,
+
+    /// Build genre, decade and record label collections every night
+    #[serde(default = "default_true")]
+    pub enable_library_hubs: bool,
+
     /// Enable file watching
     #[serde(default)]
     pub enable_watchdog: bool,
 
+    /// Watcher options keyed by root directory as listed in `root_dirs`
+    #[serde(default)]
+    pub watchdog_roots: HashMap<String, WatchdogRootOptions>,
+
+    /// Announce the library to DLNA/UPnP renderers on the local network
+    #[serde(default)]
+    pub enable_dlna: bool,
+
+    /// Name shown by DLNA clients
+    #[serde(default = "default_dlna_name")]
+    pub dlna_name: String,
+
+    /// Log plays server side when a streaming client disconnects without logging them
+    #[serde(default)]
+    pub scrobble_on_disconnect: bool,
+
     /// Show playlists in folder view
     #[serde(default)]
     pub show_playlists_in_folder_view: bool,
@@ -105,6 +185,681 @@ pub struct UserConfig {
     /// Enable guest user
     #[serde(default)]
     pub enable_guest: bool,
+
+    /// Maximum size of the on-disk transcode cache in megabytes
+    #[serde(default = "default_transcode_cache_size_mb")]
+    pub transcode_cache_size_mb: u64,
+
+    /// Album thumbnail sizes and encoding quality
+    #[serde(default)]
+    pub thumbnails: ThumbnailSettings,
+
+    /// How long clients may cache images in seconds, 0 makes them revalidate every time
+    #[serde(default = "default_image_cache_max_age")]
+    pub image_cache_max_age: u32,
+
+    /// Size of the chunks file bodies are streamed in, in KiB
+    #[serde(default = "default_stream_chunk_size")]
+    pub stream_chunk_size: u32,
+
+    /// Stream untranscoded files as slices of a memory map instead of buffered reads
+    #[serde(default = "default_true")]
+    pub stream_zero_copy: bool,
+
+    /// Hand untranscoded files to a reverse proxy that sends X-Sendfile-Type
+    #[serde(default)]
+    pub stream_sendfile: bool,
+
+    /// Size and composition of generated mixes
+    #[serde(default)]
+    pub mixes: MixSettings,
+
+    /// HTTP server tuning, read once at startup
+    #[serde(default)]
+    pub server: ServerSettings,
+
+    /// Where artist images are looked up and the credentials of the lookups
+    #[serde(default)]
+    pub artist_images: ArtistImageSettings,
+
+    /// OpenID Connect single sign-on
+    #[serde(default)]
+    pub oidc: OidcSettings,
+}
+
+/// Album thumbnail settings
+///
+/// a quality of 100 keeps lossless webp encoding, lower values use lossy webp.
+/// avif copies are only written when the server was built with the avif feature.
+/// the cover source picks whether embedded art or an image in the album folder
+/// wins when an album has both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThumbnailSettings {
+    #[serde(default = "default_xsmall_thumb_size")]
+    pub xsmall: u32,
+    #[serde(default = "default_small_thumb_size")]
+    pub small: u32,
+    #[serde(default = "default_medium_thumb_size")]
+    pub medium: u32,
+    #[serde(default = "default_large_thumb_size")]
+    pub large: u32,
+    #[serde(default = "default_thumbnail_quality")]
+    pub quality: u8,
+    /// Also write avif copies for clients that accept them
+    #[serde(default)]
+    pub avif: bool,
+    /// Threads generating thumbnails, 0 uses half the cpu cores
+    #[serde(default)]
+    pub workers: usize,
+    /// Where album art is read from first
+    #[serde(default)]
+    pub cover_source: CoverSource,
+}
+
+/// Where album art is read from first, the other source is the fallback
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CoverSource {
+    /// art embedded in the track tags
+    #[default]
+    Embedded,
+    /// cover, folder or front images next to the tracks
+    Folder,
+}
+
+impl Default for ThumbnailSettings {
+    fn default() -> Self {
+        Self {
+            xsmall: XSM_THUMB_SIZE,
+            small: SM_THUMB_SIZE,
+            medium: MD_THUMB_SIZE,
+            large: LG_THUMB_SIZE,
+            quality: default_thumbnail_quality(),
+            avif: false,
+            workers: 0,
+            cover_source: CoverSource::Embedded,
+        }
+    }
+}
+
+impl ThumbnailSettings {
+    /// Smallest thumbnail edge accepted
+    pub const MIN_SIZE: u32 = 16;
+    /// Largest thumbnail edge accepted
+    pub const MAX_SIZE: u32 = 2048;
+    /// Most thumbnail worker threads
+    pub const MAX_WORKERS: usize = 64;
+
+    /// Clamp sizes and quality into their supported ranges
+    pub fn normalized(self) -> Self {
+        let size = |v: u32| v.clamp(Self::MIN_SIZE, Self::MAX_SIZE);
+        Self {
+            xsmall: size(self.xsmall),
+            small: size(self.small),
+            medium: size(self.medium),
+            large: size(self.large),
+            quality: self.quality.clamp(1, 100),
+            avif: self.avif,
+            workers: self.workers.min(Self::MAX_WORKERS),
+            cover_source: self.cover_source,
+        }
+    }
+
+    /// Size labels paired with their max edge in pixels, largest first
+    pub fn sizes(&self) -> [(&'static str, u32); 4] {
+        [
+            ("large", self.large),
+            ("medium", self.medium),
+            ("small", self.small),
+            ("xsmall", self.xsmall),
+        ]
+    }
+
+    /// Max edge for a size label
+    pub fn size_for(&self, label: &str) -> Option<u32> {
+        self.sizes()
+            .into_iter()
+            .find(|(name, _)| *name == label)
+            .map(|(_, size)| size)
+    }
+
+    /// Labels whose files differ between two settings
+    pub fn changed_sizes(&self, other: &ThumbnailSettings) -> Vec<&'static str> {
+        if self.quality != other.quality {
+            return self.sizes().iter().map(|(name, _)| *name).collect();
+        }
+
+        self.sizes()
+            .into_iter()
+            .zip(other.sizes())
+            .filter(|((_, a), (_, b))| a != b)
+            .map(|((name, _), _)| name)
+            .collect()
+    }
+}
+
+/// Mix generation settings
+///
+/// the seed ratio is the share of a daily mix taken from the seed artist, the
+/// rest comes from related artists. the favorite weight is how many plays a
+/// favorited artist or album counts as when picking daily mix seeds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MixSettings {
+    /// Tracks in a daily mix
+    #[serde(default = "default_daily_mix_tracks")]
+    pub daily_mix_tracks: usize,
+    /// Tracks in an artist mix
+    #[serde(default = "default_artist_mix_tracks")]
+    pub artist_mix_tracks: usize,
+    #[serde(default = "default_mix_seed_ratio")]
+    pub seed_ratio: f32,
+    #[serde(default = "default_mix_favorite_weight")]
+    pub favorite_weight: f32,
+    /// Leave out tracks played within this many hours, 0 keeps them
+    #[serde(default)]
+    pub exclude_recent_hours: u32,
+    #[serde(default = "default_true")]
+    pub allow_explicit: bool,
+}
+
+impl Default for MixSettings {
+    fn default() -> Self {
+        Self {
+            daily_mix_tracks: default_daily_mix_tracks(),
+            artist_mix_tracks: default_artist_mix_tracks(),
+            seed_ratio: default_mix_seed_ratio(),
+            favorite_weight: default_mix_favorite_weight(),
+            exclude_recent_hours: 0,
+            allow_explicit: true,
+        }
+    }
+}
+
+impl MixSettings {
+    /// Fewest tracks a mix can be configured with
+    pub const MIN_TRACKS: usize = 5;
+    /// Most tracks a mix can be configured with
+    pub const MAX_TRACKS: usize = 200;
+    /// Most plays a favorite can count as
+    pub const MAX_FAVORITE_WEIGHT: f32 = 100.0;
+    /// Longest recently played window (30 days)
+    pub const MAX_EXCLUDE_HOURS: u32 = 720;
+
+    /// Clamp values into their supported ranges
+    pub fn normalized(self) -> Self {
+        let tracks = |v: usize| v.clamp(Self::MIN_TRACKS, Self::MAX_TRACKS);
+        let seed_ratio = if self.seed_ratio.is_finite() {
+            self.seed_ratio.clamp(0.0, 1.0)
+        } else {
+            default_mix_seed_ratio()
+        };
+        let favorite_weight = if self.favorite_weight.is_finite() {
+            self.favorite_weight.clamp(0.0, Self::MAX_FAVORITE_WEIGHT)
+        } else {
+            default_mix_favorite_weight()
+        };
+        Self {
+            daily_mix_tracks: tracks(self.daily_mix_tracks),
+            artist_mix_tracks: tracks(self.artist_mix_tracks),
+            seed_ratio,
+            favorite_weight,
+            exclude_recent_hours: self.exclude_recent_hours.min(Self::MAX_EXCLUDE_HOURS),
+            allow_explicit: self.allow_explicit,
+        }
+    }
+
+    /// Seed artist and related track counts for a daily mix
+    pub fn daily_mix_split(&self) -> (usize, usize) {
+        let seed = (self.daily_mix_tracks as f32 * self.seed_ratio).round() as usize;
+        let seed = seed.min(self.daily_mix_tracks);
+        (seed, self.daily_mix_tracks - seed)
+    }
+}
+
+/// A source of artist images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtistImageProvider {
+    /// artist.jpg or folder.jpg next to the artist's music
+    Local,
+    Deezer,
+    /// fanart.tv, needs the artist's musicbrainz id
+    Fanart,
+    Spotify,
+}
+
+impl ArtistImageProvider {
+    pub const ALL: [ArtistImageProvider; 4] = [
+        ArtistImageProvider::Local,
+        ArtistImageProvider::Deezer,
+        ArtistImageProvider::Fanart,
+        ArtistImageProvider::Spotify,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ArtistImageProvider::Local => "local",
+            ArtistImageProvider::Deezer => "deezer",
+            ArtistImageProvider::Fanart => "fanart",
+            ArtistImageProvider::Spotify => "spotify",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.as_str() == name)
+    }
+}
+
+/// Artist image lookup settings
+///
+/// providers are tried in order until one has an image. fanart.tv needs an api
+/// key and spotify needs client credentials, providers missing them are skipped.
+/// `image_size` is the width in pixels to download, providers send their
+/// smallest picture at least that wide or their largest one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtistImageSettings {
+    #[serde(default = "default_artist_image_providers")]
+    pub providers: Vec<ArtistImageProvider>,
+    #[serde(default)]
+    pub fanart_api_key: String,
+    #[serde(default)]
+    pub spotify_client_id: String,
+    #[serde(default)]
+    pub spotify_client_secret: String,
+    #[serde(default = "default_artist_image_size")]
+    pub image_size: u32,
+}
+
+impl Default for ArtistImageSettings {
+    fn default() -> Self {
+        Self {
+            providers: default_artist_image_providers(),
+            fanart_api_key: String::new(),
+            spotify_client_id: String::new(),
+            spotify_client_secret: String::new(),
+            image_size: default_artist_image_size(),
+        }
+    }
+}
+
+impl ArtistImageSettings {
+    /// Smallest image size accepted
+    pub const MIN_IMAGE_SIZE: u32 = 100;
+    /// Largest image size accepted
+    pub const MAX_IMAGE_SIZE: u32 = 2000;
+
+    /// Drop repeated providers and whitespace around the credentials, and keep
+    /// the image size within what providers offer
+    pub fn normalized(self) -> Self {
+        let mut providers = Vec::new();
+        for provider in self.providers {
+            if !providers.contains(&provider) {
+                providers.push(provider);
+            }
+        }
+        Self {
+            providers,
+            fanart_api_key: self.fanart_api_key.trim().to_string(),
+            spotify_client_id: self.spotify_client_id.trim().to_string(),
+            spotify_client_secret: self.spotify_client_secret.trim().to_string(),
+            image_size: self
+                .image_size
+                .clamp(Self::MIN_IMAGE_SIZE, Self::MAX_IMAGE_SIZE),
+        }
+    }
+
+    /// Whether a provider has the credentials it needs
+    pub fn is_usable(&self, provider: ArtistImageProvider) -> bool {
+        match provider {
+            ArtistImageProvider::Local | ArtistImageProvider::Deezer => true,
+            ArtistImageProvider::Fanart => !self.fanart_api_key.is_empty(),
+            ArtistImageProvider::Spotify => {
+                !self.spotify_client_id.is_empty() && !self.spotify_client_secret.is_empty()
+            }
+        }
+    }
+
+    /// Providers to try in order, leaving out the ones missing credentials
+    pub fn usable_providers(&self) -> Vec<ArtistImageProvider> {
+        self.providers
+            .iter()
+            .copied()
+            .filter(|p| self.is_usable(*p))
+            .collect()
+    }
+}
+
+/// OpenID Connect login settings
+///
+/// the issuer is the provider url its discovery document hangs off, like
+/// `https://auth.example.com` for authelia or
+/// `https://sso.example.com/realms/home` for keycloak. the redirect url is
+/// derived from the request when left empty. values of the role claim, usually
+/// `groups`, are looked up in the role map to set the user's roles on every
+/// login, users matching no entry get the default roles.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OidcSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Label of the login button
+    #[serde(default = "default_oidc_name")]
+    pub name: String,
+    #[serde(default)]
+    pub issuer: String,
+    #[serde(default)]
+    pub client_id: String,
+    #[serde(default)]
+    pub client_secret: String,
+    #[serde(default)]
+    pub redirect_url: String,
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+    #[serde(default = "default_oidc_username_claim")]
+    pub username_claim: String,
+    #[serde(default = "default_oidc_role_claim")]
+    pub role_claim: String,
+    /// Claim value to the role names it grants
+    #[serde(default)]
+    pub role_map: HashMap<String, Vec<String>>,
+    #[serde(default = "default_oidc_roles")]
+    pub default_roles: Vec<String>,
+    /// Create accounts for unknown users on their first login
+    #[serde(default = "default_true")]
+    pub auto_create: bool,
+    /// Link an existing account on first login when the provider reports the
+    /// same email as verified, accounts are otherwise only found by subject
+    #[serde(default)]
+    pub link_by_email: bool,
+}
+
+fn default_oidc_name() -> String {
+    "SSO".to_string()
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    ["openid", "profile", "email", "groups"]
+        .into_iter()
+        .map(str::to_string)
+        .collect()
+}
+
+fn default_oidc_username_claim() -> String {
+    "preferred_username".to_string()
+}
+
+fn default_oidc_role_claim() -> String {
+    "groups".to_string()
+}
+
+fn default_oidc_roles() -> Vec<String> {
+    vec!["user".to_string()]
+}
+
+impl Default for OidcSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            name: default_oidc_name(),
+            issuer: String::new(),
+            client_id: String::new(),
+            client_secret: String::new(),
+            redirect_url: String::new(),
+            scopes: default_oidc_scopes(),
+            username_claim: default_oidc_username_claim(),
+            role_claim: default_oidc_role_claim(),
+            role_map: HashMap::new(),
+            default_roles: default_oidc_roles(),
+            auto_create: true,
+            link_by_email: false,
+        }
+    }
+}
+
+impl OidcSettings {
+    /// Trim the values, drop the issuer's trailing slash and make sure the
+    /// `openid` scope is requested
+    pub fn normalized(self) -> Self {
+        let trim = |s: String| s.trim().to_string();
+        let mut scopes: Vec<String> = Vec::new();
+        for scope in std::iter::once("openid".to_string()).chain(self.scopes) {
+            let scope = scope.trim().to_string();
+            if !scope.is_empty() && !scopes.contains(&scope) {
+                scopes.push(scope);
+            }
+        }
+        let or_default = |s: String, default: fn() -> String| {
+            let s = s.trim().to_string();
+            if s.is_empty() {
+                default()
+            } else {
+                s
+            }
+        };
+
+        Self {
+            enabled: self.enabled,
+            name: or_default(self.name, default_oidc_name),
+            issuer: self.issuer.trim().trim_end_matches('/').to_string(),
+            client_id: trim(self.client_id),
+            client_secret: trim(self.client_secret),
+            redirect_url: trim(self.redirect_url),
+            scopes,
+            username_claim: or_default(self.username_claim, default_oidc_username_claim),
+            role_claim: or_default(self.role_claim, default_oidc_role_claim),
+            role_map: self.role_map,
+            default_roles: self.default_roles,
+            auto_create: self.auto_create,
+            link_by_email: self.link_by_email,
+        }
+    }
+
+    /// Whether logins can be started, enabled with an issuer and client id
+    pub fn is_usable(&self) -> bool {
+        self.enabled && !self.issuer.is_empty() && !self.client_id.is_empty()
+    }
+}
+
+/// Rules deciding which albums are merged into one
+///
+/// albums are merged when their titles match, ignoring edition suffixes like
+/// "(Deluxe Edition)" when asked to. the other rules keep matching albums
+/// apart: a different album artist, release years further apart than the
+/// tolerance or different catalog numbers. albums missing a year or catalog
+/// number join the largest group they match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumMergeRules {
+    #[serde(default = "default_true")]
+    pub ignore_edition_suffixes: bool,
+    #[serde(default = "default_true")]
+    pub require_albumartist: bool,
+    /// Years releases can be apart, `None` merges any year
+    #[serde(default)]
+    pub year_tolerance: Option<u32>,
+    #[serde(default)]
+    pub match_catalog_number: bool,
+}
+
+impl Default for AlbumMergeRules {
+    fn default() -> Self {
+        Self {
+            ignore_edition_suffixes: true,
+            require_albumartist: true,
+            year_tolerance: None,
+            match_catalog_number: false,
+        }
+    }
+}
+
+impl AlbumMergeRules {
+    /// Largest year tolerance accepted
+    pub const MAX_YEAR_TOLERANCE: u32 = 100;
+
+    /// Clamp values into their supported ranges
+    pub fn normalized(self) -> Self {
+        Self {
+            year_tolerance: self
+                .year_tolerance
+                .map(|years| years.min(Self::MAX_YEAR_TOLERANCE)),
+            ..self
+        }
+    }
+}
+
+/// HTTP server tuning
+///
+/// defaults match actix. small devices can lower the workers and connections,
+/// busy households can raise them. timeouts are in milliseconds except the
+/// keep-alive and shutdown ones which are in seconds. each value can also be
+/// set with its SWING_ environment variable, which wins over settings.json.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerSettings {
+    /// Worker threads, 0 starts one per cpu core
+    #[serde(default)]
+    pub workers: usize,
+    /// Accept HTTP/2 over plain tcp next to HTTP/1
+    #[serde(default)]
+    pub http2: bool,
+    /// Seconds an idle connection is kept open, 0 closes it after each response
+    #[serde(default = "default_keep_alive")]
+    pub keep_alive: u64,
+    /// Time a client has to send its request headers
+    #[serde(default = "default_client_request_timeout")]
+    pub client_request_timeout: u64,
+    /// Time a client has to acknowledge a closed connection
+    #[serde(default = "default_client_disconnect_timeout")]
+    pub client_disconnect_timeout: u64,
+    /// Open connections per worker
+    #[serde(default = "default_max_connections")]
+    pub max_connections: usize,
+    /// Pending connections waiting to be accepted
+    #[serde(default = "default_backlog")]
+    pub backlog: u32,
+    /// Seconds in-flight requests get to finish on shutdown
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout: u64,
+    /// Largest json request body in bytes
+    #[serde(default = "default_max_json_payload")]
+    pub max_json_payload: usize,
+    /// Largest raw request body in bytes
+    #[serde(default = "default_max_payload")]
+    pub max_payload: usize,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            workers: 0,
+            http2: false,
+            keep_alive: default_keep_alive(),
+            client_request_timeout: default_client_request_timeout(),
+            client_disconnect_timeout: default_client_disconnect_timeout(),
+            max_connections: default_max_connections(),
+            backlog: default_backlog(),
+            shutdown_timeout: default_shutdown_timeout(),
+            max_json_payload: default_max_json_payload(),
+            max_payload: default_max_payload(),
+        }
+    }
+}
+
+impl ServerSettings {
+    /// Most worker threads accepted
+    pub const MAX_WORKERS: usize = 256;
+    /// Smallest request body limit accepted (4 KiB)
+    pub const MIN_PAYLOAD: usize = 4 * 1024;
+    /// Largest request body limit accepted (1 GiB)
+    pub const MAX_PAYLOAD: usize = 1024 * 1024 * 1024;
+
+    /// Clamp values into their supported ranges
+    pub fn normalized(self) -> Self {
+        let payload = |v: usize| v.clamp(Self::MIN_PAYLOAD, Self::MAX_PAYLOAD);
+        Self {
+            workers: self.workers.min(Self::MAX_WORKERS),
+            http2: self.http2,
+            keep_alive: self.keep_alive,
+            client_request_timeout: self.client_request_timeout,
+            client_disconnect_timeout: self.client_disconnect_timeout,
+            max_connections: self.max_connections.max(1),
+            backlog: self.backlog.max(1),
+            shutdown_timeout: self.shutdown_timeout,
+            max_json_payload: payload(self.max_json_payload),
+            max_payload: payload(self.max_payload),
+        }
+    }
+
+    /// Apply the SWING_ environment overrides, unparsable values are skipped
+    pub fn with_env(self) -> Self {
+        self.with_overrides(|name| std::env::var(name).ok())
+    }
+
+    fn with_overrides(mut self, var: impl Fn(&str) -> Option<String>) -> Self {
+        fn set<T: std::str::FromStr>(
+            var: &impl Fn(&str) -> Option<String>,
+            name: &str,
+            field: &mut T,
+        ) {
+            let Some(value) = var(name).filter(|v| !v.trim().is_empty()) else {
+                return;
+            };
+            match value.trim().parse() {
+                Ok(parsed) => *field = parsed,
+                Err(_) => tracing::warn!("{}: ignoring invalid value '{}'", name, value),
+            }
+        }
+
+        if let Some(value) = var("SWING_HTTP2").filter(|v| !v.trim().is_empty()) {
+            match value.trim().to_ascii_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => self.http2 = true,
+                "0" | "false" | "no" | "off" => self.http2 = false,
+                _ => tracing::warn!("SWING_HTTP2: ignoring invalid value '{}'", value),
+            }
+        }
+        set(&var, "SWING_WORKERS", &mut self.workers);
+        set(&var, "SWING_KEEP_ALIVE", &mut self.keep_alive);
+        set(&var, "SWING_CLIENT_REQUEST_TIMEOUT", &mut self.client_request_timeout);
+        set(&var, "SWING_CLIENT_DISCONNECT_TIMEOUT", &mut self.client_disconnect_timeout);
+        set(&var, "SWING_MAX_CONNECTIONS", &mut self.max_connections);
+        set(&var, "SWING_BACKLOG", &mut self.backlog);
+        set(&var, "SWING_SHUTDOWN_TIMEOUT", &mut self.shutdown_timeout);
+        set(&var, "SWING_MAX_JSON_PAYLOAD", &mut self.max_json_payload);
+        set(&var, "SWING_MAX_PAYLOAD", &mut self.max_payload);
+        self
+    }
+}
+
+/// File watcher options for a single root directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchdogRootOptions {
+    /// Watch this root, other roots keep watching when disabled
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Watch subdirectories too
+    #[serde(default = "default_true")]
+    pub recursive: bool,
+    /// Poll for changes instead of relying on native events, for network shares
+    #[serde(default)]
+    pub poll: bool,
+    /// Seconds between polls when polling
+    #[serde(default = "default_watchdog_poll_interval")]
+    pub poll_interval: u64,
+}
+
+impl Default for WatchdogRootOptions {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            recursive: true,
+            poll: false,
+            poll_interval: default_watchdog_poll_interval(),
+        }
+    }
 }
 
 impl Default for UserConfig {
@@ -112,30 +867,68 @@ impl Default for UserConfig {
         Self {
             server_id: String::new(),
             users_on_login: true,
+            single_user_mode: false,
             root_dirs: Vec::new(),
             exclude_dirs: Vec::new(),
+            audiobook_dirs: Vec::new(),
+            file_template: default_file_template(),
+            inbox_dir: String::new(),
+            import_folder_playlists: false,
+            trash_retention_days: default_trash_retention_days(),
             artist_separators: default_artist_separators(),
+            album_artist_separators: HashSet::new(),
             artist_split_ignore_list: HashSet::new(),
             genre_separators: default_genre_separators(),
             extract_featured_artists: true,
             remove_prod_by: true,
             remove_remaster_info: true,
             merge_albums: false,
+            album_merge_rules: AlbumMergeRules::default(),
             clean_album_title: true,
             show_albums_as_singles: false,
+            group_singles: false,
             enable_periodic_scans: false,
             scan_interval: 10,
+            db_maintenance_interval: 0,
+            search_personal_boost: default_search_personal_boost(),
+            enable_fingerprinting: false,
+            fpcalc_path: default_fpcalc_path(),
+            enable_gapless_analysis: false,
+            enable_library_hubs: true,
             enable_watchdog: false,
+            watchdog_roots: HashMap::new(),
+            enable_dlna: false,
+            dlna_name: default_dlna_name(),
+            scrobble_on_disconnect: false,
             show_playlists_in_folder_view: false,
             enable_plugins: true,
             lastfm_api_key: default_lastfm_api_key(),
             lastfm_api_secret: default_lastfm_api_secret(),
             lastfm_session_keys: std::collections::HashMap::new(),
             enable_guest: false,
+            transcode_cache_size_mb: default_transcode_cache_size_mb(),
+            thumbnails: ThumbnailSettings::default(),
+            image_cache_max_age: default_image_cache_max_age(),
+            stream_chunk_size: default_stream_chunk_size(),
+            stream_zero_copy: true,
+            stream_sendfile: false,
+            mixes: MixSettings::default(),
+            server: ServerSettings::default(),
+            artist_images: ArtistImageSettings::default(),
+            oidc: OidcSettings::default(),
         }
     }
 }
 
+/// A tag whose values are split into several names
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SplitField {
+    Artist,
+    AlbumArtist,
+    Genre,
+}
+
 impl UserConfig {
     /// Load configuration from file
     pub fn load() -> Result<Self> {
@@ -210,6 +1003,23 @@ impl UserConfig {
         Ok(())
     }
 
+    /// Separators a tag is split on
+    pub fn separators_for(&self, field: SplitField) -> &HashSet<String> {
+        match field {
+            SplitField::Artist => &self.artist_separators,
+            SplitField::AlbumArtist if !self.album_artist_separators.is_empty() => {
+                &self.album_artist_separators
+            }
+            SplitField::AlbumArtist => &self.artist_separators,
+            SplitField::Genre => &self.genre_separators,
+        }
+    }
+
+    /// Watcher options for a root directory
+    pub fn watchdog_options(&self, root: &str) -> WatchdogRootOptions {
+        self.watchdog_roots.get(root).copied().unwrap_or_default()
+    }
+
     /// Check if a path is within root directories
     pub fn is_path_in_root_dirs(&self, path: &Path) -> bool {
         self.root_dirs.iter().any(|root| {
@@ -263,6 +1073,121 @@ fn default_scan_interval() -> u32 {
     10
 }
 
+fn default_search_personal_boost() -> f64 {
+    0.5
+}
+
+fn default_file_template() -> String {
+    "{albumartist}/{album}/{track} {title}".to_string()
+}
+
+fn default_fpcalc_path() -> String {
+    "fpcalc".to_string()
+}
+
+fn default_watchdog_poll_interval() -> u64 {
+    2
+}
+
+fn default_dlna_name() -> String {
+    "SwingMusic".to_string()
+}
+
+fn default_transcode_cache_size_mb() -> u64 {
+    2048
+}
+
+fn default_trash_retention_days() -> u32 {
+    30
+}
+
+fn default_image_cache_max_age() -> u32 {
+    // 30 days
+    30 * 24 * 60 * 60
+}
+
+fn default_stream_chunk_size() -> u32 {
+    256
+}
+
+fn default_keep_alive() -> u64 {
+    5
+}
+
+fn default_client_request_timeout() -> u64 {
+    5000
+}
+
+fn default_client_disconnect_timeout() -> u64 {
+    1000
+}
+
+fn default_max_connections() -> usize {
+    25_000
+}
+
+fn default_backlog() -> u32 {
+    2048
+}
+
+fn default_shutdown_timeout() -> u64 {
+    30
+}
+
+fn default_max_json_payload() -> usize {
+    // 2 MiB
+    2 * 1024 * 1024
+}
+
+fn default_max_payload() -> usize {
+    // 256 KiB
+    256 * 1024
+}
+
+fn default_xsmall_thumb_size() -> u32 {
+    XSM_THUMB_SIZE
+}
+
+fn default_small_thumb_size() -> u32 {
+    SM_THUMB_SIZE
+}
+
+fn default_medium_thumb_size() -> u32 {
+    MD_THUMB_SIZE
+}
+
+fn default_large_thumb_size() -> u32 {
+    LG_THUMB_SIZE
+}
+
+fn default_thumbnail_quality() -> u8 {
+    100
+}
+
+fn default_daily_mix_tracks() -> usize {
+    25
+}
+
+fn default_artist_mix_tracks() -> usize {
+    40
+}
+
+fn default_mix_seed_ratio() -> f32 {
+    0.6
+}
+
+fn default_mix_favorite_weight() -> f32 {
+    5.0
+}
+
+fn default_artist_image_providers() -> Vec<ArtistImageProvider> {
+    ArtistImageProvider::ALL.to_vec()
+}
+
+fn default_artist_image_size() -> u32 {
+    500
+}
+
 fn default_lastfm_api_key() -> String {
     // upstream default api key
     "0553005e93f9a4b4819d835182181806".to_string()
@@ -297,4 +1222,134 @@ mod tests {
         let deserialized: UserConfig = serde_json::from_str(&json).unwrap();
         assert_eq!(config.users_on_login, deserialized.users_on_login);
     }
+
+    #[test]
+    fn test_separators_for() {
+        let mut config = UserConfig::default();
+        assert!(config.separators_for(SplitField::Genre).contains(";"));
+        // album artists follow the artist separators until they get their own
+        assert_eq!(
+            config.separators_for(SplitField::AlbumArtist),
+            &config.artist_separators
+        );
+
+        config.album_artist_separators = HashSet::from([" & ".to_string()]);
+        assert_eq!(config.separators_for(SplitField::AlbumArtist).len(), 1);
+        assert!(config.separators_for(SplitField::Artist).contains("/"));
+
+        let field: SplitField = serde_json::from_str(r#""albumartist""#).unwrap();
+        assert_eq!(field, SplitField::AlbumArtist);
+    }
+
+    #[test]
+    fn test_thumbnail_changed_sizes() {
+        let base = ThumbnailSettings::default();
+        assert!(base.changed_sizes(&base).is_empty());
+
+        let resized = ThumbnailSettings {
+            medium: 300,
+            ..base
+        };
+        assert_eq!(resized.changed_sizes(&base), vec!["medium"]);
+
+        let requality = ThumbnailSettings {
+            quality: 80,
+            ..base
+        };
+        assert_eq!(requality.changed_sizes(&base).len(), 4);
+
+        let partial: ThumbnailSettings = serde_json::from_str(r#"{"large": 5000}"#).unwrap();
+        assert_eq!(partial.small, SM_THUMB_SIZE);
+        assert_eq!(partial.normalized().large, ThumbnailSettings::MAX_SIZE);
+    }
+
+    #[test]
+    fn test_mix_settings() {
+        let defaults = MixSettings::default();
+        assert_eq!(defaults.daily_mix_split(), (15, 10));
+
+        let partial: MixSettings = serde_json::from_str(
+            r#"{"dailyMixTracks": 1, "seedRatio": 2.5, "favoriteWeight": -1}"#,
+        )
+        .unwrap();
+        let normalized = partial.normalized();
+        assert_eq!(normalized.daily_mix_tracks, MixSettings::MIN_TRACKS);
+        assert_eq!(normalized.artist_mix_tracks, 40);
+        assert_eq!(normalized.daily_mix_split(), (5, 0));
+        assert_eq!(normalized.favorite_weight, 0.0);
+        assert!(normalized.allow_explicit);
+    }
+
+    #[test]
+    fn test_server_settings_env() {
+        let partial: ServerSettings =
+            serde_json::from_str(r#"{"workers": 2, "maxJsonPayload": 10}"#).unwrap();
+        assert_eq!(partial.keep_alive, 5);
+
+        let env = |name: &str| match name {
+            "SWING_WORKERS" => Some("1".to_string()),
+            "SWING_HTTP2" => Some("on".to_string()),
+            "SWING_KEEP_ALIVE" => Some("soon".to_string()),
+            _ => None,
+        };
+        let settings = partial.with_overrides(env).normalized();
+        assert_eq!(settings.workers, 1);
+        assert!(settings.http2);
+        assert_eq!(settings.keep_alive, 5);
+        assert_eq!(settings.max_json_payload, ServerSettings::MIN_PAYLOAD);
+    }
+
+    #[test]
+    fn test_oidc_settings() {
+        let settings: OidcSettings = serde_json::from_str(
+            r#"{"enabled": true, "issuer": " https://auth.example.com/ ", "clientId": "swing",
+                "scopes": ["email", "openid", " "], "usernameClaim": ""}"#,
+        )
+        .unwrap();
+        let settings = settings.normalized();
+        assert_eq!(settings.issuer, "https://auth.example.com");
+        assert_eq!(settings.scopes, vec!["openid", "email"]);
+        assert_eq!(settings.username_claim, "preferred_username");
+        assert_eq!(settings.default_roles, vec!["user"]);
+        assert!(settings.is_usable());
+        assert!(!OidcSettings::default().is_usable());
+    }
+
+    #[test]
+    fn test_album_merge_rules() {
+        let config: UserConfig = serde_json::from_str(r#"{"mergeAlbums": true}"#).unwrap();
+        assert_eq!(config.album_merge_rules, AlbumMergeRules::default());
+
+        let rules: AlbumMergeRules =
+            serde_json::from_str(r#"{"yearTolerance": 500, "requireAlbumartist": false}"#)
+                .unwrap();
+        let rules = rules.normalized();
+        assert_eq!(rules.year_tolerance, Some(AlbumMergeRules::MAX_YEAR_TOLERANCE));
+        assert!(rules.ignore_edition_suffixes);
+        assert!(!rules.require_albumartist);
+    }
+
+    #[test]
+    fn test_artist_image_providers() {
+        let settings: ArtistImageSettings = serde_json::from_str(
+            r#"{"providers": ["spotify", "local", "spotify", "fanart"], "spotifyClientId": " id ", "imageSize": 5000}"#,
+        )
+        .unwrap();
+        let settings = settings.normalized();
+        assert_eq!(
+            settings.providers,
+            vec![
+                ArtistImageProvider::Spotify,
+                ArtistImageProvider::Local,
+                ArtistImageProvider::Fanart,
+            ]
+        );
+        assert_eq!(settings.spotify_client_id, "id");
+        assert_eq!(settings.image_size, ArtistImageSettings::MAX_IMAGE_SIZE);
+        // spotify has no secret and fanart no key
+        assert_eq!(
+            settings.usable_providers(),
+            vec![ArtistImageProvider::Local]
+        );
+    }
 }
@@ -0,0 +1,400 @@
+//! Album merging
+//!
+//! with merging on, albums whose titles match under the merge rules are
+//! replaced by one virtual album standing for all of them, the way an
+//! artist's singles are grouped. tracks keep their own albumhash so turning
+//! merging off or changing the rules only needs the albums rebuilt.
+
+use chrono::Datelike;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::config::AlbumMergeRules;
+use crate::core::AlbumLib;
+use crate::models::{Album, ArtistRefItem, Track};
+use crate::stores::TrackStore;
+use crate::utils::hashing::create_hash;
+use crate::utils::parsers::get_base_album_title;
+
+/// An album that would be merged
+#[derive(Debug, Clone, Serialize)]
+pub struct MergeMember {
+    pub albumhash: String,
+    pub title: String,
+    pub year: Option<i32>,
+    pub trackcount: i32,
+    pub catalog_number: Option<String>,
+}
+
+/// Albums that would be merged into one
+#[derive(Debug, Clone, Serialize)]
+pub struct MergeGroup {
+    pub albumhash: String,
+    pub title: String,
+    pub albumartists: Vec<ArtistRefItem>,
+    pub members: Vec<MergeMember>,
+}
+
+/// Replace the albums matching under the rules by their merged album
+///
+/// `catalogs` maps albumhashes to catalog numbers and is only read when the
+/// rules match them
+pub fn merge_albums(
+    albums: Vec<Album>,
+    catalogs: &HashMap<String, String>,
+    rules: &AlbumMergeRules,
+) -> Vec<Album> {
+    let groups = plan(&albums, catalogs, rules);
+    if groups.is_empty() {
+        return albums;
+    }
+
+    let mut slots: Vec<Option<Album>> = albums.into_iter().map(Some).collect();
+    let mut merged = Vec::with_capacity(groups.len());
+    for group in groups {
+        let members: Vec<Album> = group.into_iter().filter_map(|i| slots[i].take()).collect();
+        merged.push(merged_album(members, rules));
+    }
+    slots.into_iter().flatten().chain(merged).collect()
+}
+
+/// Catalog number of each album, taken from the first track carrying one
+pub fn catalog_numbers(tracks: &[Track]) -> HashMap<String, String> {
+    let mut catalogs = HashMap::new();
+    for track in tracks {
+        if catalogs.contains_key(&track.albumhash) {
+            continue;
+        }
+        let catalog = track
+            .extra
+            .get("catalog_number")
+            .and_then(|v| v.as_str())
+            .map(normalize_catalog)
+            .filter(|c| !c.is_empty());
+        if let Some(catalog) = catalog {
+            catalogs.insert(track.albumhash.clone(), catalog);
+        }
+    }
+    catalogs
+}
+
+/// Albums of the library the rules would merge, largest groups first
+pub fn preview(rules: &AlbumMergeRules) -> Vec<MergeGroup> {
+    let tracks = TrackStore::get().get_all();
+    let albums = AlbumLib::collect_albums(&tracks);
+    let catalogs = catalog_numbers(&tracks);
+
+    let mut groups: Vec<MergeGroup> = plan(&albums, &catalogs, rules)
+        .into_iter()
+        .map(|group| {
+            let members: Vec<Album> = group.into_iter().map(|i| albums[i].clone()).collect();
+            let entries = members
+                .iter()
+                .map(|album| MergeMember {
+                    albumhash: album.albumhash.clone(),
+                    title: album.title.clone(),
+                    year: year_of(album),
+                    trackcount: album.trackcount,
+                    catalog_number: catalogs.get(&album.albumhash).cloned(),
+                })
+                .collect();
+            let album = merged_album(members, rules);
+            MergeGroup {
+                albumhash: album.albumhash,
+                title: album.title,
+                albumartists: album.albumartists,
+                members: entries,
+            }
+        })
+        .collect();
+    groups.sort_by(|a, b| {
+        b.members
+            .len()
+            .cmp(&a.members.len())
+            .then_with(|| a.title.to_lowercase().cmp(&b.title.to_lowercase()))
+    });
+    groups
+}
+
+/// Indexes of the albums to merge, each group has at least two albums
+fn plan(
+    albums: &[Album],
+    catalogs: &HashMap<String, String>,
+    rules: &AlbumMergeRules,
+) -> Vec<Vec<usize>> {
+    let mut buckets: HashMap<(String, String), Vec<usize>> = HashMap::new();
+    for (i, album) in albums.iter().enumerate() {
+        if !album.grouped_albumhashes().is_empty() {
+            continue;
+        }
+        let artists = if rules.require_albumartist {
+            let mut hashes: Vec<&str> = album
+                .albumartists
+                .iter()
+                .map(|a| a.artisthash.as_str())
+                .collect();
+            hashes.sort_unstable();
+            hashes.join(",")
+        } else {
+            String::new()
+        };
+        let key = (create_hash(&[&merge_title(album, rules)], true), artists);
+        buckets.entry(key).or_default().push(i);
+    }
+
+    let mut groups = Vec::new();
+    for bucket in buckets.into_values().filter(|b| b.len() > 1) {
+        let parts = if rules.match_catalog_number {
+            split(
+                bucket,
+                |i| catalogs.get(&albums[i].albumhash).cloned(),
+                |a, b| a == b,
+            )
+        } else {
+            vec![bucket]
+        };
+        for part in parts {
+            let clusters = match rules.year_tolerance {
+                Some(years) => split(
+                    part,
+                    |i| year_of(&albums[i]),
+                    |first, year| year - first <= years as i32,
+                ),
+                None => vec![part],
+            };
+            groups.extend(clusters.into_iter().filter(|c| c.len() > 1));
+        }
+    }
+
+    // hashmap order would make the merged albums come out shuffled
+    for group in &mut groups {
+        group.sort_unstable();
+    }
+    groups.sort_unstable();
+    groups
+}
+
+/// Split albums into clusters by a property, albums missing the property join
+/// the largest cluster
+///
+/// albums are visited in property order and start a new cluster when `joins`
+/// refuses them given the first property of the current cluster
+fn split<K: Ord>(
+    members: Vec<usize>,
+    key: impl Fn(usize) -> Option<K>,
+    joins: impl Fn(&K, &K) -> bool,
+) -> Vec<Vec<usize>> {
+    let mut known: Vec<(K, usize)> = Vec::new();
+    let mut unknown = Vec::new();
+    for i in members {
+        match key(i) {
+            Some(k) => known.push((k, i)),
+            None => unknown.push(i),
+        }
+    }
+    known.sort();
+
+    let mut clusters: Vec<(K, Vec<usize>)> = Vec::new();
+    for (k, i) in known {
+        match clusters.last_mut() {
+            Some((first, cluster)) if joins(first, &k) => cluster.push(i),
+            _ => clusters.push((k, vec![i])),
+        }
+    }
+    let mut clusters: Vec<Vec<usize>> = clusters.into_iter().map(|(_, c)| c).collect();
+
+    if !unknown.is_empty() {
+        // the first of the largest clusters, the earliest one on ties
+        let largest = clusters
+            .iter()
+            .enumerate()
+            .max_by(|(a, x), (b, y)| x.len().cmp(&y.len()).then_with(|| b.cmp(a)))
+            .map(|(i, _)| i);
+        match largest {
+            Some(i) => clusters[i].extend(unknown),
+            None => clusters.push(unknown),
+        }
+    }
+    clusters
+}
+
+/// Build the album standing for merged editions, the earliest one lends its
+/// hash, artists and cover
+fn merged_album(mut members: Vec<Album>, rules: &AlbumMergeRules) -> Album {
+    members.sort_by(|a, b| {
+        (a.date == 0)
+            .cmp(&(b.date == 0))
+            .then_with(|| a.date.cmp(&b.date))
+            .then_with(|| b.trackcount.cmp(&a.trackcount))
+            .then_with(|| a.albumhash.cmp(&b.albumhash))
+    });
+
+    let first = &members[0];
+    let hash = create_hash(&["merged", &first.albumhash], true);
+    let mut album = Album::new(hash, merge_title(first, rules));
+    album.albumartists = first.albumartists.clone();
+    album.album_type = first.album_type;
+    album.date = first.date;
+    album.created_date = members.iter().map(|m| m.created_date).min().unwrap_or(0);
+    album.trackcount = members.iter().map(|m| m.trackcount).sum();
+    album.duration = members.iter().map(|m| m.duration).sum();
+    album.pathhash = first.pathhash.clone();
+    album.image = first.image.clone();
+
+    let mut artisthashes = HashSet::new();
+    let mut genrehashes = HashSet::new();
+    for member in &members {
+        for hash in &member.artisthashes {
+            if artisthashes.insert(hash.clone()) {
+                album.artisthashes.push(hash.clone());
+            }
+        }
+        for genre in &member.genres {
+            if genrehashes.insert(genre.genrehash.clone()) {
+                album.genrehashes.push(genre.genrehash.clone());
+                album.genres.push(genre.clone());
+            }
+        }
+    }
+
+    let hashes: Vec<&str> = members.iter().map(|m| m.albumhash.as_str()).collect();
+    album.extra = serde_json::json!({ "merged_albums": hashes });
+    album
+}
+
+/// Title albums are matched on, without edition suffixes when the rules say so
+fn merge_title(album: &Album, rules: &AlbumMergeRules) -> String {
+    if rules.ignore_edition_suffixes {
+        let base = get_base_album_title(&album.title);
+        if !base.is_empty() {
+            return base;
+        }
+    }
+    album.title.trim().to_string()
+}
+
+/// Release year of an album, `None` when it has no date
+fn year_of(album: &Album) -> Option<i32> {
+    if album.date == 0 {
+        return None;
+    }
+    chrono::DateTime::from_timestamp(album.date, 0).map(|d| d.year())
+}
+
+/// Uppercase catalog number without spaces and dashes, "wpcr-1234" matches
+/// "WPCR 1234"
+fn normalize_catalog(catalog: &str) -> String {
+    catalog
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn album(hash: &str, title: &str, artist: &str, year: i32) -> Album {
+        let mut album = Album::new(hash.to_string(), title.to_string());
+        album.albumartists = vec![ArtistRefItem::new(artist.to_string(), artist.to_string())];
+        album.artisthashes = vec![artist.to_string()];
+        album.trackcount = 10;
+        album.date = if year == 0 {
+            0
+        } else {
+            chrono::NaiveDate::from_ymd_opt(year, 6, 1)
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .map(|d| d.and_utc().timestamp())
+                .unwrap_or(0)
+        };
+        album
+    }
+
+    fn merged(
+        albums: Vec<Album>,
+        catalogs: &HashMap<String, String>,
+        rules: AlbumMergeRules,
+    ) -> Vec<Vec<String>> {
+        let mut groups: Vec<Vec<String>> = merge_albums(albums, catalogs, &rules)
+            .iter()
+            .map(|a| {
+                let members = a.merged_albumhashes();
+                if members.is_empty() {
+                    vec![a.albumhash.clone()]
+                } else {
+                    members
+                }
+            })
+            .collect();
+        groups.sort();
+        groups
+    }
+
+    #[test]
+    fn test_merge_rules() {
+        let albums = vec![
+            album("a", "Abbey Road", "x", 1969),
+            album("b", "Abbey Road (Super Deluxe Edition)", "x", 2019),
+            album("c", "Abbey Road", "y", 1969),
+            album("d", "abbey road (Remastered)", "x", 0),
+        ];
+        let none = HashMap::new();
+        let rules = AlbumMergeRules::default();
+        assert_eq!(
+            merged(albums.clone(), &none, rules),
+            [vec!["a", "b", "d"], vec!["c"]]
+        );
+
+        // the unknown year joins the largest cluster, the earliest on ties
+        let strict = AlbumMergeRules {
+            year_tolerance: Some(5),
+            ..rules
+        };
+        assert_eq!(
+            merged(albums.clone(), &none, strict),
+            [vec!["a", "d"], vec!["b"], vec!["c"]]
+        );
+
+        let any_artist = AlbumMergeRules {
+            require_albumartist: false,
+            ignore_edition_suffixes: false,
+            ..rules
+        };
+        assert_eq!(
+            merged(albums.clone(), &none, any_artist),
+            [vec!["a", "c"], vec!["b"], vec!["d"]]
+        );
+
+        let catalogs: HashMap<String, String> = [("a", "PCS-7088"), ("b", "pcs 7088"), ("d", "X1")]
+            .iter()
+            .map(|(h, c)| (h.to_string(), normalize_catalog(c)))
+            .collect();
+        let by_catalog = AlbumMergeRules {
+            match_catalog_number: true,
+            ..rules
+        };
+        assert_eq!(
+            merged(albums, &catalogs, by_catalog),
+            [vec!["a", "b"], vec!["c"], vec!["d"]]
+        );
+    }
+
+    #[test]
+    fn test_merged_album() {
+        let albums = vec![
+            album("b", "Blue (Deluxe)", "x", 2011),
+            album("a", "Blue", "x", 1971),
+        ];
+        let merged = merge_albums(albums, &HashMap::new(), &AlbumMergeRules::default());
+        assert_eq!(merged.len(), 1);
+
+        let album = &merged[0];
+        assert_eq!(album.title, "Blue");
+        assert_eq!(album.trackcount, 20);
+        assert_eq!(year_of(album), Some(1971));
+        assert_eq!(album.merged_albumhashes(), ["a", "b"]);
+        assert!(album.grouped_albumhashes().is_empty());
+        assert_eq!(album.albumhash, create_hash(&["merged", "a"], true));
+    }
+}
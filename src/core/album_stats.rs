@@ -0,0 +1,165 @@
+//! Per-user listening stats for an album
+//!
+//! aggregated from the scrobble table over the album's tracks and cached per
+//! user and album until the next scrobble lands, like the artist stats.
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::core::artist_stats::{is_better, TopItem};
+use crate::db::tables::{ScrobbleTable, TrackPlayTotals};
+use crate::models::Track;
+
+/// cached entries kept before the cache is emptied
+const MAX_CACHED: usize = 512;
+
+/// (userid, albumhash) to the stats and the scrobble generation they were built at
+type StatsCache = HashMap<(i64, String), (u64, AlbumListeningStats)>;
+
+static CACHE: Lazy<RwLock<StatsCache>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// How a user has listened to an album
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct AlbumListeningStats {
+    /// Timestamp of the first scrobble
+    pub first_played: Option<i64>,
+    /// Timestamp of the latest scrobble
+    pub last_played: Option<i64>,
+    pub playcount: i64,
+    /// Listening time in seconds
+    pub playduration: i64,
+    pub most_played_track: Option<TopItem>,
+    /// Tracks of the album played at least once
+    pub tracks_played: usize,
+    pub trackcount: usize,
+    /// Share of the album's tracks played at least once, from 0 to 1
+    pub completion_rate: f64,
+}
+
+/// Listening stats of a user for an album with the given tracks
+pub async fn for_album(
+    user_id: i64,
+    albumhash: &str,
+    tracks: &[Track],
+) -> Result<AlbumListeningStats> {
+    let key = (user_id, albumhash.to_string());
+    let generation = ScrobbleTable::generation();
+    if let Some((built_at, stats)) = CACHE.read().get(&key) {
+        if *built_at == generation {
+            return Ok(stats.clone());
+        }
+    }
+
+    let trackhashes: Vec<String> = tracks.iter().map(|t| t.trackhash.clone()).collect();
+    let totals = ScrobbleTable::track_totals(user_id, &trackhashes).await?;
+    let stats = aggregate(tracks, &totals);
+
+    let mut cache = CACHE.write();
+    if cache.len() >= MAX_CACHED {
+        cache.clear();
+    }
+    cache.insert(key, (generation, stats.clone()));
+    Ok(stats)
+}
+
+/// Fold per-track totals into album stats
+fn aggregate(tracks: &[Track], totals: &[TrackPlayTotals]) -> AlbumListeningStats {
+    // the same recording on two discs counts as one track
+    let by_hash: HashMap<&str, &Track> = tracks.iter().map(|t| (t.trackhash.as_str(), t)).collect();
+
+    let mut stats = AlbumListeningStats {
+        trackcount: by_hash.len(),
+        ..Default::default()
+    };
+
+    for total in totals {
+        let Some(track) = by_hash.get(total.trackhash.as_str()) else {
+            continue;
+        };
+
+        stats.playcount += total.playcount;
+        stats.playduration += total.playduration;
+        stats.tracks_played += 1;
+        stats.first_played = Some(
+            stats
+                .first_played
+                .map_or(total.first_played, |t| t.min(total.first_played)),
+        );
+        stats.last_played = Some(
+            stats
+                .last_played
+                .map_or(total.last_played, |t| t.max(total.last_played)),
+        );
+
+        let item = TopItem {
+            hash: track.trackhash.clone(),
+            title: track.title.clone(),
+            playcount: total.playcount,
+            playduration: total.playduration,
+        };
+        if is_better(&item, stats.most_played_track.as_ref()) {
+            stats.most_played_track = Some(item);
+        }
+    }
+
+    if stats.trackcount > 0 {
+        stats.completion_rate = stats.tracks_played as f64 / stats.trackcount as f64;
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(hash: &str) -> Track {
+        Track {
+            trackhash: hash.to_string(),
+            title: format!("Track {}", hash),
+            ..Track::new()
+        }
+    }
+
+    fn totals(hash: &str, playcount: i64, first: i64, last: i64) -> TrackPlayTotals {
+        TrackPlayTotals {
+            trackhash: hash.to_string(),
+            playcount,
+            playduration: playcount * 200,
+            first_played: first,
+            last_played: last,
+        }
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let tracks = [track("a"), track("b"), track("c"), track("d")];
+        let stats = aggregate(
+            &tracks,
+            &[
+                totals("a", 2, 300, 900),
+                totals("c", 6, 100, 1200),
+                // a scrobble of a track no longer on the album is ignored
+                totals("gone", 40, 1, 2000),
+            ],
+        );
+
+        assert_eq!(stats.playcount, 8);
+        assert_eq!(stats.playduration, 8 * 200);
+        assert_eq!(stats.first_played, Some(100));
+        assert_eq!(stats.last_played, Some(1200));
+        assert_eq!(stats.most_played_track.unwrap().hash, "c");
+        assert_eq!((stats.tracks_played, stats.trackcount), (2, 4));
+        assert_eq!(stats.completion_rate, 0.5);
+    }
+
+    #[test]
+    fn test_aggregate_without_plays() {
+        let stats = aggregate(&[track("a")], &[]);
+        assert_eq!(stats.trackcount, 1);
+        assert_eq!(stats.completion_rate, 0.0);
+        assert!(stats.most_played_track.is_none());
+    }
+}
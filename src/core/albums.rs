@@ -1,13 +1,45 @@
 //! Album library functions
 
-use std::collections::HashMap;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use crate::models::{Album, Track};
+use crate::config::UserConfig;
+use crate::core::album_merge;
+use crate::models::{Album, AlbumType, Track};
 use crate::stores::{AlbumStore, TrackStore};
+use crate::utils::hashing::create_hash;
+
+/// Title of the virtual album an artist's singles are grouped into
+pub const SINGLES_ALBUM_TITLE: &str = "Singles & EPs";
+
+/// Fewest singles an artist needs before they are grouped
+const MIN_GROUPED_SINGLES: usize = 2;
 
 /// Album library functions
 pub struct AlbumLib;
 
+/// Tracks of one disc of an album
+#[derive(Debug, Clone, Serialize)]
+pub struct AlbumDisc {
+    pub disc: i32,
+    pub trackcount: usize,
+    pub duration: i32,
+    pub filesize: u64,
+    pub trackhashes: Vec<String>,
+}
+
+/// File level details of an album gathered from its tracks
+#[derive(Debug, Clone, Serialize)]
+pub struct AlbumDetails {
+    pub discs: Vec<AlbumDisc>,
+    /// Total size of all files in bytes
+    pub filesize: u64,
+    /// Formats of the files, most common first, e.g. "FLAC 16/44.1"
+    pub codec: String,
+    pub label: Option<String>,
+    pub copyright: Option<String>,
+}
+
 impl AlbumLib {
     /// Get all albums
     pub fn get_all() -> Vec<Album> {
@@ -41,8 +73,126 @@ impl AlbumLib {
         tracks
     }
 
+    /// Group tracks by disc in track order and sum file sizes and formats
+    ///
+    /// tracks indexed before file details were recorded are stat'ed instead
+    pub fn details(tracks: &[Track]) -> AlbumDetails {
+        let mut discs: BTreeMap<i32, AlbumDisc> = BTreeMap::new();
+        let mut formats: HashMap<(String, u32, u32), (usize, i64)> = HashMap::new();
+        let mut labels: Vec<Option<String>> = Vec::new();
+        let mut filesize = 0;
+
+        let mut ordered: Vec<&Track> = tracks.iter().collect();
+        ordered.sort_by_key(|t| (t.disc, t.track));
+
+        for track in ordered {
+            let extra = track.extra_info();
+            let size = if extra.filesize > 0 {
+                extra.filesize
+            } else {
+                std::fs::metadata(&track.filepath)
+                    .map(|m| m.len())
+                    .unwrap_or(0)
+            };
+            filesize += size;
+
+            let disc = discs.entry(track.disc).or_insert_with(|| AlbumDisc {
+                disc: track.disc,
+                trackcount: 0,
+                duration: 0,
+                filesize: 0,
+                trackhashes: Vec::new(),
+            });
+            disc.trackcount += 1;
+            disc.duration += track.duration;
+            disc.filesize += size;
+            disc.trackhashes.push(track.trackhash.clone());
+
+            let codec = if extra.codec.is_empty() {
+                std::path::Path::new(&track.filepath)
+                    .extension()
+                    .map(|e| e.to_string_lossy().to_lowercase())
+                    .unwrap_or_default()
+            } else {
+                extra.codec
+            };
+            let format = formats
+                .entry((codec, extra.bitdepth, extra.samplerate))
+                .or_default();
+            format.0 += 1;
+            format.1 += i64::from(track.bitrate);
+
+            labels.push(extra.label);
+        }
+
+        let mut formats: Vec<(String, usize)> = formats
+            .into_iter()
+            .map(|((codec, bitdepth, samplerate), (count, bitrate))| {
+                let avg_bitrate = bitrate / count as i64;
+                (
+                    format_codec(&codec, bitdepth, samplerate, avg_bitrate),
+                    count,
+                )
+            })
+            .filter(|(name, _)| !name.is_empty())
+            .collect();
+        formats.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let mut codecs: Vec<String> = Vec::new();
+        for (name, _) in formats {
+            if !codecs.contains(&name) {
+                codecs.push(name);
+            }
+        }
+
+        AlbumDetails {
+            discs: discs.into_values().collect(),
+            filesize,
+            codec: codecs.join(", "),
+            label: most_common(labels),
+            copyright: most_common(tracks.iter().map(|t| t.copyright.clone())),
+        }
+    }
+
     /// Build albums from tracks
+    ///
+    /// with merging on, albums matching under the merge rules become one
+    /// virtual album. with singles grouping on and albums not shown as
+    /// singles, each artist's one track singles are merged into a virtual album
     pub fn build_albums(tracks: &[Track]) -> Vec<Album> {
+        let mut albums = Self::collect_albums(tracks);
+
+        let (merge, rules, group) = {
+            let config = UserConfig::global();
+            let config = config.read();
+            (
+                config.merge_albums,
+                config.album_merge_rules,
+                config.group_singles && !config.show_albums_as_singles,
+            )
+        };
+        if merge {
+            let catalogs = if rules.match_catalog_number {
+                album_merge::catalog_numbers(tracks)
+            } else {
+                HashMap::new()
+            };
+            albums = album_merge::merge_albums(albums, &catalogs, &rules);
+        }
+        if group {
+            let mut first_tracks: HashMap<&str, &Track> = HashMap::new();
+            for track in tracks {
+                first_tracks
+                    .entry(track.albumhash.as_str())
+                    .or_insert(track);
+            }
+            Self::group_singles(albums, &first_tracks)
+        } else {
+            albums
+        }
+    }
+
+    /// Build one album per albumhash, before merging and singles grouping
+    pub fn collect_albums(tracks: &[Track]) -> Vec<Album> {
         let mut album_map: HashMap<String, Album> = HashMap::new();
 
         for track in tracks {
@@ -85,6 +235,72 @@ impl AlbumLib {
         album_map.into_values().collect()
     }
 
+    /// Replace the one track singles of artists with several of them by one
+    /// virtual album per artist
+    fn group_singles(all: Vec<Album>, first_tracks: &HashMap<&str, &Track>) -> Vec<Album> {
+        let mut albums = Vec::with_capacity(all.len());
+        let mut singles: HashMap<String, Vec<Album>> = HashMap::new();
+        for album in all {
+            let single = first_tracks
+                .get(album.albumhash.as_str())
+                .is_some_and(|track| album.is_loose_single(track));
+            match album.albumartists.first() {
+                Some(artist) if single => singles
+                    .entry(artist.artisthash.clone())
+                    .or_default()
+                    .push(album),
+                _ => albums.push(album),
+            }
+        }
+
+        for members in singles.into_values() {
+            if members.len() < MIN_GROUPED_SINGLES {
+                albums.extend(members);
+            } else {
+                albums.push(Self::singles_album(members));
+            }
+        }
+        albums
+    }
+
+    /// Merge one artist's singles into a virtual album, newest single first
+    fn singles_album(mut members: Vec<Album>) -> Album {
+        members.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| a.title.cmp(&b.title)));
+
+        let artist = members[0].albumartists[0].clone();
+        let hash = create_hash(&["singles", &artist.artisthash], true);
+        let mut album = Album::new(hash, SINGLES_ALBUM_TITLE.to_string());
+        album.albumartists = vec![artist];
+        album.album_type = AlbumType::Single;
+        album.date = members[0].date;
+        album.created_date = members.iter().map(|m| m.created_date).min().unwrap_or(0);
+        album.trackcount = members.iter().map(|m| m.trackcount).sum();
+        album.duration = members.iter().map(|m| m.duration).sum();
+        // the newest single lends its cover
+        album.pathhash = members[0].pathhash.clone();
+        album.image = members[0].image.clone();
+
+        let mut artisthashes = HashSet::new();
+        let mut genrehashes = HashSet::new();
+        for member in &members {
+            for hash in &member.artisthashes {
+                if artisthashes.insert(hash.clone()) {
+                    album.artisthashes.push(hash.clone());
+                }
+            }
+            for genre in &member.genres {
+                if genrehashes.insert(genre.genrehash.clone()) {
+                    album.genrehashes.push(genre.genrehash.clone());
+                    album.genres.push(genre.clone());
+                }
+            }
+        }
+
+        let hashes: Vec<&str> = members.iter().map(|m| m.albumhash.as_str()).collect();
+        album.extra = serde_json::json!({ "grouped_singles": hashes });
+        album
+    }
+
     /// Collect album genres from tracks
     pub fn collect_genres(album_hash: &str) -> Vec<String> {
         let tracks = Self::get_tracks(album_hash);
@@ -142,3 +358,164 @@ impl AlbumLib {
         albums.into_iter().skip(start).take(limit).collect()
     }
 }
+
+/// Readable format such as "FLAC 24/96" for lossless or "MP3 320kbps" for lossy
+fn format_codec(codec: &str, bitdepth: u32, samplerate: u32, bitrate: i64) -> String {
+    let name = match codec {
+        "" => return String::new(),
+        "opus" => "Opus".to_string(),
+        "vorbis" | "ogg" => "Vorbis".to_string(),
+        "wavpack" | "wv" => "WavPack".to_string(),
+        "mpeg" => "MP3".to_string(),
+        other => other.to_uppercase(),
+    };
+
+    if bitdepth > 0 && samplerate > 0 {
+        format!("{} {}/{}", name, bitdepth, f64::from(samplerate) / 1000.0)
+    } else if bitrate > 0 {
+        format!("{} {}kbps", name, bitrate)
+    } else {
+        name
+    }
+}
+
+/// Most frequent non empty value, the first one seen wins ties
+fn most_common(values: impl IntoIterator<Item = Option<String>>) -> Option<String> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for value in values.into_iter().flatten() {
+        let value = value.trim();
+        if value.is_empty() {
+            continue;
+        }
+        match counts.iter_mut().find(|(v, _)| v == value) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((value.to_string(), 1)),
+        }
+    }
+    // max_by_key keeps the last of equal maxima so walk in reverse
+    counts
+        .into_iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map(|(value, _)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ArtistRefItem, TrackExtra};
+
+    fn track(hash: &str, disc: i32, codec: &str, bitdepth: u32, samplerate: u32) -> Track {
+        let mut track = Track::new();
+        track.trackhash = hash.to_string();
+        track.disc = disc;
+        track.duration = 100;
+        track.bitrate = 900;
+        track.filepath = format!("/nonexistent/{}.flac", hash);
+        track.extra = TrackExtra {
+            codec: codec.to_string(),
+            bitdepth,
+            samplerate,
+            filesize: 1000,
+            ..TrackExtra::default()
+        }
+        .to_value();
+        track
+    }
+
+    #[test]
+    fn test_format_codec() {
+        assert_eq!(format_codec("flac", 16, 44100, 900), "FLAC 16/44.1");
+        assert_eq!(format_codec("alac", 24, 96000, 2500), "ALAC 24/96");
+        assert_eq!(format_codec("mp3", 0, 44100, 320), "MP3 320kbps");
+        assert_eq!(format_codec("opus", 0, 0, 0), "Opus");
+        assert_eq!(format_codec("", 16, 44100, 900), "");
+    }
+
+    #[test]
+    fn test_details_groups_discs_and_formats() {
+        let mut tracks = vec![
+            track("a", 1, "flac", 16, 44100),
+            track("b", 2, "flac", 16, 44100),
+            track("c", 1, "flac", 24, 96000),
+        ];
+        tracks[0].copyright = Some("2001 Label".to_string());
+        tracks[2].copyright = Some("2001 Label".to_string());
+
+        let details = AlbumLib::details(&tracks);
+        assert_eq!(details.filesize, 3000);
+        assert_eq!(details.codec, "FLAC 16/44.1, FLAC 24/96");
+        assert_eq!(details.copyright.as_deref(), Some("2001 Label"));
+        assert_eq!(details.label, None);
+
+        let discs: Vec<_> = details
+            .discs
+            .iter()
+            .map(|d| (d.disc, d.trackhashes.clone(), d.duration, d.filesize))
+            .collect();
+        assert_eq!(
+            discs,
+            [
+                (1, vec!["a".to_string(), "c".to_string()], 200, 2000),
+                (2, vec!["b".to_string()], 100, 1000),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_details_falls_back_to_file_extension() {
+        let mut old = Track::new();
+        old.filepath = "/nonexistent/old.mp3".to_string();
+        old.bitrate = 256;
+        let details = AlbumLib::details(&[old]);
+        assert_eq!(details.codec, "MP3 256kbps");
+        assert_eq!(details.filesize, 0);
+    }
+
+    #[test]
+    fn test_group_singles() {
+        let song = |artist: &str, album: &str, title: &str, date: i64| {
+            let mut t = Track::new();
+            t.trackhash = format!("{}-{}", album, title);
+            t.albumhash = format!("{}-{}", artist, album);
+            t.og_album = album.to_string();
+            t.album = album.to_string();
+            t.title = title.to_string();
+            t.albumartists = vec![ArtistRefItem::new(artist.to_string(), artist.to_string())];
+            t.compute_artisthashes();
+            t.date = date;
+            t.duration = 60;
+            t
+        };
+        let tracks = vec![
+            song("x", "One", "One", 10),
+            song("x", "Two", "Two", 20),
+            song("x", "Record", "Side A", 5),
+            song("x", "Record", "Side B", 5),
+            song("y", "Lonely", "Lonely", 30),
+        ];
+        let mut first_tracks: HashMap<&str, &Track> = HashMap::new();
+        for t in &tracks {
+            first_tracks.entry(t.albumhash.as_str()).or_insert(t);
+        }
+
+        let mut albums = AlbumLib::group_singles(AlbumLib::build_albums(&tracks), &first_tracks);
+        albums.sort_by(|a, b| a.title.cmp(&b.title));
+        let titles: Vec<_> = albums.iter().map(|a| a.title.as_str()).collect();
+        assert_eq!(titles, ["Lonely", "Record", SINGLES_ALBUM_TITLE]);
+
+        let singles = &albums[2];
+        assert_eq!(singles.album_type, AlbumType::Single);
+        assert_eq!(singles.trackcount, 2);
+        assert_eq!(singles.date, 20);
+        assert_eq!(singles.grouped_albumhashes(), ["x-Two", "x-One"]);
+        assert_eq!(singles.albumartists[0].artisthash, "x");
+    }
+
+    #[test]
+    fn test_most_common() {
+        let values = ["b", "a", " ", "a", "b"].map(|v| Some(v.to_string()));
+        assert_eq!(most_common(values).as_deref(), Some("b"));
+        assert_eq!(most_common([None, Some(String::new())]), None);
+    }
+}
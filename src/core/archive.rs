@@ -0,0 +1,276 @@
+//! Zip downloads of library files
+//!
+//! archives are written while they are sent: a blocking task writes the zip
+//! into a channel the response streams from, so nothing touches the disk and
+//! a closed connection stops the writer. audio is already compressed so the
+//! files are stored as they are, with zip64 records once the archive or a
+//! file passes 4 GiB.
+
+use bytes::Bytes;
+use chrono::{Datelike, Timelike};
+use futures::Stream;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::core::file_cache::stream_tuning;
+use crate::models::Track;
+
+/// Chunks buffered between the writer and the connection
+const CHANNEL_CAPACITY: usize = 8;
+
+/// A file and its path inside the archive
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveEntry {
+    pub path: PathBuf,
+    pub name: String,
+}
+
+/// Entries of an album archive in album order, discs get their own folder
+/// when there is more than one
+pub fn album_entries(tracks: &[Track]) -> Vec<ArchiveEntry> {
+    let discs: HashSet<i32> = tracks.iter().map(|t| t.disc).collect();
+    let mut names = HashSet::new();
+    tracks
+        .iter()
+        .map(|track| {
+            let file = file_name(track);
+            let name = if discs.len() > 1 {
+                format!("Disc {}/{}", track.disc.max(1), file)
+            } else {
+                file
+            };
+            ArchiveEntry {
+                path: PathBuf::from(&track.filepath),
+                name: unique_name(&mut names, name),
+            }
+        })
+        .collect()
+}
+
+/// Entries of a playlist archive, numbered so they keep the playlist order
+pub fn playlist_entries(tracks: &[Track]) -> Vec<ArchiveEntry> {
+    let width = tracks.len().to_string().len().max(2);
+    let mut names = HashSet::new();
+    tracks
+        .iter()
+        .enumerate()
+        .map(|(i, track)| {
+            let name = format!("{:0width$} - {}", i + 1, file_name(track), width = width);
+            ArchiveEntry {
+                path: PathBuf::from(&track.filepath),
+                name: unique_name(&mut names, name),
+            }
+        })
+        .collect()
+}
+
+/// File name for a download of `title`, without characters file systems refuse
+pub fn archive_name(title: &str) -> String {
+    let name: String = title
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let name = name.trim().trim_matches('.');
+    if name.is_empty() {
+        "download.zip".to_string()
+    } else {
+        format!("{}.zip", name)
+    }
+}
+
+/// Stream a zip archive of the entries, files that vanished are left out
+pub fn zip_stream(entries: Vec<ArchiveEntry>) -> impl Stream<Item = Result<Bytes, io::Error>> {
+    let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let chunk_size = stream_tuning().chunk_size;
+
+    tokio::task::spawn_blocking(move || {
+        let sink = ChannelWriter {
+            tx: tx.clone(),
+            buffer: Vec::with_capacity(chunk_size),
+            chunk_size,
+        };
+        if let Err(e) = write_archive(&entries, sink) {
+            // a closed connection is the only expected failure
+            if e.kind() != io::ErrorKind::BrokenPipe {
+                tracing::warn!("Zip download failed: {}", e);
+                let _ = tx.blocking_send(Err(e));
+            }
+        }
+    });
+
+    futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    })
+}
+
+/// Write a stored zip archive of the entries into `writer`
+fn write_archive<W: Write>(entries: &[ArchiveEntry], writer: W) -> io::Result<W> {
+    let mut zip = ZipWriter::new_stream(writer);
+    for entry in entries {
+        let mut file = match File::open(&entry.path) {
+            Ok(file) => file,
+            Err(e) => {
+                tracing::warn!("Skipping {} in zip download: {}", entry.path.display(), e);
+                continue;
+            }
+        };
+        let metadata = file.metadata()?;
+        let mut options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .large_file(metadata.len() >= u32::MAX as u64);
+        if let Some(modified) = metadata.modified().ok().and_then(zip_time) {
+            options = options.last_modified_time(modified);
+        }
+
+        zip.start_file(entry.name.as_str(), options)
+            .map_err(io::Error::other)?;
+        io::copy(&mut file, &mut zip)?;
+    }
+
+    let mut stream = zip.finish().map_err(io::Error::other)?.into_inner();
+    stream.flush()?;
+    Ok(stream)
+}
+
+/// Hands full chunks to the channel, fails once the receiver is gone
+struct ChannelWriter {
+    tx: mpsc::Sender<Result<Bytes, io::Error>>,
+    buffer: Vec<u8>,
+    chunk_size: usize,
+}
+
+impl ChannelWriter {
+    fn send(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buffer, Vec::with_capacity(self.chunk_size));
+        self.tx
+            .blocking_send(Ok(Bytes::from(chunk)))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= self.chunk_size {
+            self.send()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()
+    }
+}
+
+fn file_name(track: &Track) -> String {
+    Path::new(&track.filepath)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| track.title.clone())
+}
+
+/// Number repeated names, "a.flac" then "a (2).flac"
+fn unique_name(taken: &mut HashSet<String>, name: String) -> String {
+    if taken.insert(name.to_lowercase()) {
+        return name;
+    }
+    let (stem, ext) = match name.rfind('.') {
+        Some(dot) if dot > 0 => name.split_at(dot),
+        _ => (name.as_str(), ""),
+    };
+    (2..)
+        .map(|n| format!("{} ({}){}", stem, n, ext))
+        .find(|candidate| taken.insert(candidate.to_lowercase()))
+        .unwrap_or(name)
+}
+
+/// Local time in the zip format, which only covers 1980 to 2107
+fn zip_time(time: std::time::SystemTime) -> Option<zip::DateTime> {
+    let time = chrono::DateTime::<chrono::Local>::from(time);
+    zip::DateTime::from_date_and_time(
+        u16::try_from(time.year()).ok()?,
+        time.month() as u8,
+        time.day() as u8,
+        time.hour() as u8,
+        time.minute() as u8,
+        time.second() as u8,
+    )
+    .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    fn track(filepath: &str, disc: i32) -> Track {
+        let mut track = Track::new();
+        track.filepath = filepath.to_string();
+        track.disc = disc;
+        track
+    }
+
+    #[test]
+    fn test_entry_names() {
+        let tracks = vec![
+            track("/a/01 Intro.flac", 1),
+            track("/b/01 Intro.flac", 1),
+            track("/a/01 Outro.flac", 2),
+        ];
+        let names: Vec<String> = album_entries(&tracks).into_iter().map(|e| e.name).collect();
+        assert_eq!(
+            names,
+            [
+                "Disc 1/01 Intro.flac",
+                "Disc 1/01 Intro (2).flac",
+                "Disc 2/01 Outro.flac"
+            ]
+        );
+
+        let names: Vec<String> = playlist_entries(&tracks[..2])
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        assert_eq!(names, ["01 - 01 Intro.flac", "02 - 01 Intro.flac"]);
+
+        assert_eq!(archive_name("AC/DC: Live?"), "AC_DC_ Live_.zip");
+        assert_eq!(archive_name(" .. "), "download.zip");
+    }
+
+    #[test]
+    fn test_write_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("song.flac");
+        std::fs::write(&path, b"not really flac").unwrap();
+        let entries = vec![
+            ArchiveEntry {
+                path: path.clone(),
+                name: "Disc 1/song.flac".to_string(),
+            },
+            ArchiveEntry {
+                path: dir.path().join("missing.flac"),
+                name: "missing.flac".to_string(),
+            },
+        ];
+
+        let bytes = write_archive(&entries, Vec::new()).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.len(), 1);
+        let mut file = archive.by_name("Disc 1/song.flac").unwrap();
+        let mut content = Vec::new();
+        file.read_to_end(&mut content).unwrap();
+        assert_eq!(content, b"not really flac");
+    }
+}
@@ -0,0 +1,365 @@
+//! Splitting identically named artists apart
+//!
+//! artist hashes are derived from the name alone, so two different artists
+//! sharing a name end up on one page. a split rule moves the tracks of an
+//! artist that match a musicbrainz artist id, a folder or a set of albums to a
+//! new hash. rules are kept in the database and applied while tags are read,
+//! so a split survives rescans.
+
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use utoipa::ToSchema;
+
+use crate::core::populate::refresh_changed_tracks;
+use crate::db::tables::{ArtistSplit, ArtistSplitTable, TrackTable};
+use crate::models::Track;
+use crate::stores::{ArtistStore, TrackStore};
+use crate::utils::hashing::create_hash;
+
+/// rules in the order they are applied
+static RULES: Lazy<RwLock<Vec<ArtistSplit>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+/// What an admin asks to split off an artist
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct SplitRequest {
+    pub artisthash: String,
+    #[serde(default)]
+    pub mbid: Option<String>,
+    #[serde(default)]
+    pub folder: Option<String>,
+    #[serde(default)]
+    pub albumhashes: Vec<String>,
+}
+
+/// How many of an artist's tracks share a musicbrainz id, folder or album
+#[derive(Debug, Clone, Serialize)]
+pub struct SplitHint {
+    pub value: String,
+    pub trackcount: usize,
+}
+
+/// Ways the tracks of an artist could be told apart
+#[derive(Debug, Clone, Serialize)]
+pub struct SplitHints {
+    pub mbids: Vec<SplitHint>,
+    pub folders: Vec<SplitHint>,
+    pub albums: Vec<SplitHint>,
+}
+
+/// Load the stored rules so scans apply them
+pub async fn load() -> Result<()> {
+    *RULES.write() = ArtistSplitTable::all().await?;
+    Ok(())
+}
+
+/// Snapshot of the rules for a scan
+pub fn rules() -> Vec<ArtistSplit> {
+    RULES.read().clone()
+}
+
+/// Whether a rule applies to a track that has the rule's artist
+fn matches(rule: &ArtistSplit, track: &Track) -> bool {
+    let by_mbid = rule
+        .mbid
+        .as_ref()
+        .is_some_and(|mbid| track_mbids(track).any(|id| id.eq_ignore_ascii_case(mbid)));
+    let by_folder = rule
+        .folder
+        .as_ref()
+        .is_some_and(|folder| Path::new(&track.filepath).starts_with(folder));
+    let by_album = rule.albumhashes.contains(&track.albumhash);
+
+    by_mbid || by_folder || by_album
+}
+
+fn track_mbids(track: &Track) -> impl Iterator<Item = &str> {
+    track
+        .extra
+        .get("artist_mbids")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|v| v.as_str())
+}
+
+fn has_artist(track: &Track, artisthash: &str) -> bool {
+    track
+        .artists
+        .iter()
+        .chain(&track.albumartists)
+        .any(|a| a.artisthash == artisthash)
+}
+
+/// point every artist ref with hash `from` at `to`
+fn rehash(track: &mut Track, from: &str, to: &str) {
+    let refs = track
+        .artists
+        .iter_mut()
+        .chain(track.albumartists.iter_mut());
+    for artist in refs.filter(|a| a.artisthash == from) {
+        artist.artisthash = to.to_string();
+    }
+    track.artisthashes = track.artists.iter().map(|a| a.artisthash.clone()).collect();
+}
+
+/// Apply rules to a freshly read track, returns whether any matched
+pub fn apply(track: &mut Track, rules: &[ArtistSplit]) -> bool {
+    let mut changed = false;
+    for rule in rules {
+        if has_artist(track, &rule.artisthash) && matches(rule, track) {
+            rehash(track, &rule.artisthash, &rule.newhash);
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Validate a request against the library and turn it into a rule
+///
+/// the error is meant for the admin who made the request
+pub fn plan(request: &SplitRequest) -> std::result::Result<ArtistSplit, String> {
+    let artist = ArtistStore::get()
+        .get_by_hash(&request.artisthash)
+        .ok_or_else(|| "Artist not found".to_string())?;
+
+    let mbid = clean(request.mbid.as_deref()).map(|m| m.to_lowercase());
+    let folder = clean(request.folder.as_deref()).map(|f| f.trim_end_matches('/').to_string());
+    if mbid.is_none() && folder.is_none() && request.albumhashes.is_empty() {
+        return Err("Give a MusicBrainz id, a folder or albums to split by".to_string());
+    }
+
+    let mut albumhashes = request.albumhashes.clone();
+    albumhashes.sort();
+    albumhashes.dedup();
+
+    let newhash = create_hash(
+        &[
+            &request.artisthash,
+            mbid.as_deref().unwrap_or_default(),
+            folder.as_deref().unwrap_or_default(),
+            &albumhashes.join(""),
+        ],
+        false,
+    );
+    if RULES.read().iter().any(|r| r.newhash == newhash) {
+        return Err("This split already exists".to_string());
+    }
+
+    let rule = ArtistSplit {
+        id: 0,
+        artisthash: request.artisthash.clone(),
+        newhash,
+        name: artist.name,
+        mbid,
+        folder,
+        albumhashes,
+        created_at: chrono::Utc::now().timestamp(),
+    };
+
+    let tracks = TrackStore::get().get_by_artist(&rule.artisthash);
+    let matched = tracks.iter().filter(|t| matches(&rule, t)).count();
+    if matched == 0 {
+        return Err("No tracks of this artist match the split".to_string());
+    }
+    if matched == tracks.len() {
+        return Err("The split matches every track of this artist".to_string());
+    }
+    Ok(rule)
+}
+
+fn clean(value: Option<&str>) -> Option<&str> {
+    value.map(str::trim).filter(|v| !v.is_empty())
+}
+
+/// Store a planned rule and move the matching tracks, returns the rule and moved track count
+pub async fn create(rule: ArtistSplit) -> Result<(ArtistSplit, usize)> {
+    let rule = ArtistSplitTable::insert(&rule).await?;
+    load().await?;
+
+    let mut moved = TrackStore::get().get_by_artist(&rule.artisthash);
+    moved.retain_mut(|track| {
+        let matched = matches(&rule, track);
+        if matched {
+            rehash(track, &rule.artisthash, &rule.newhash);
+        }
+        matched
+    });
+
+    save_tracks(moved.clone()).await?;
+    Ok((rule, moved.len()))
+}
+
+/// Rule by id
+pub fn get(id: i64) -> Option<ArtistSplit> {
+    RULES.read().iter().find(|r| r.id == id).cloned()
+}
+
+/// A rule splitting the artist created by `rule` further
+pub fn dependent(rule: &ArtistSplit) -> Option<ArtistSplit> {
+    RULES
+        .read()
+        .iter()
+        .find(|r| r.artisthash == rule.newhash)
+        .cloned()
+}
+
+/// Delete a rule and merge its tracks back, returns the moved track count
+///
+/// rules splitting the new artist further must be deleted first
+pub async fn remove(id: i64) -> Result<usize> {
+    let rule = get(id).ok_or_else(|| anyhow!("Split not found"))?;
+    if let Some(child) = dependent(&rule) {
+        return Err(anyhow!("Split {} depends on this one", child.id));
+    }
+
+    ArtistSplitTable::delete(id).await?;
+    load().await?;
+
+    // rerun the remaining rules so the tracks land where a rescan would put them
+    let remaining = rules();
+    let mut moved = TrackStore::get().get_by_artist(&rule.newhash);
+    for track in &mut moved {
+        rehash(track, &rule.newhash, &rule.artisthash);
+        apply(track, &remaining);
+    }
+
+    save_tracks(moved.clone()).await?;
+    Ok(moved.len())
+}
+
+async fn save_tracks(tracks: Vec<Track>) -> Result<()> {
+    if tracks.is_empty() {
+        return Ok(());
+    }
+    TrackTable::update_artists(&tracks).await?;
+    refresh_changed_tracks(tracks).await
+}
+
+/// Musicbrainz ids, parent folders and albums found across an artist's tracks
+pub fn hints(artisthash: &str) -> SplitHints {
+    let tracks = TrackStore::get().get_by_artist(artisthash);
+
+    let mut mbids: HashMap<String, usize> = HashMap::new();
+    let mut folders: HashMap<String, usize> = HashMap::new();
+    let mut albums: HashMap<String, usize> = HashMap::new();
+    for track in &tracks {
+        for mbid in track_mbids(track) {
+            *mbids.entry(mbid.to_string()).or_default() += 1;
+        }
+        // album folders usually sit inside a folder per artist
+        let folder = Path::new(&track.folder)
+            .parent()
+            .unwrap_or(Path::new(&track.folder));
+        *folders
+            .entry(folder.to_string_lossy().to_string())
+            .or_default() += 1;
+        *albums.entry(track.albumhash.clone()).or_default() += 1;
+    }
+
+    SplitHints {
+        mbids: ranked(mbids),
+        folders: ranked(folders),
+        albums: ranked(albums),
+    }
+}
+
+fn ranked(counts: HashMap<String, usize>) -> Vec<SplitHint> {
+    let mut hints: Vec<SplitHint> = counts
+        .into_iter()
+        .map(|(value, trackcount)| SplitHint { value, trackcount })
+        .collect();
+    hints.sort_by(|a, b| {
+        b.trackcount
+            .cmp(&a.trackcount)
+            .then_with(|| a.value.cmp(&b.value))
+    });
+    hints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ArtistRefItem;
+
+    fn track(filepath: &str, album: &str, mbids: &[&str]) -> Track {
+        let nirvana = ArtistRefItem::new("Nirvana".to_string(), "nirvana".to_string());
+        Track {
+            filepath: filepath.to_string(),
+            albumhash: album.to_string(),
+            artists: vec![nirvana.clone()],
+            albumartists: vec![nirvana],
+            artisthashes: vec!["nirvana".to_string()],
+            extra: serde_json::json!({ "artist_mbids": mbids }),
+            ..Track::new()
+        }
+    }
+
+    fn rule(id: i64, from: &str, to: &str) -> ArtistSplit {
+        ArtistSplit {
+            id,
+            artisthash: from.to_string(),
+            newhash: to.to_string(),
+            name: "Nirvana".to_string(),
+            mbid: None,
+            folder: None,
+            albumhashes: Vec::new(),
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_apply_by_mbid_folder_and_album() {
+        let by_mbid = ArtistSplit {
+            mbid: Some("abc".to_string()),
+            ..rule(1, "nirvana", "uk")
+        };
+        let by_folder = ArtistSplit {
+            folder: Some("/music/Nirvana (UK)".to_string()),
+            ..rule(2, "nirvana", "uk2")
+        };
+        let by_album = ArtistSplit {
+            albumhashes: vec!["local".to_string()],
+            ..rule(3, "nirvana", "uk3")
+        };
+        let rules = [by_mbid, by_folder, by_album];
+
+        let mut tagged = track("/music/Nevermind/01.flac", "x", &["ABC"]);
+        assert!(apply(&mut tagged, &rules));
+        assert_eq!(tagged.artisthashes, ["uk"]);
+        assert_eq!(tagged.albumartists[0].artisthash, "uk");
+        assert_eq!(tagged.artists[0].name, "Nirvana");
+
+        let mut in_folder = track("/music/Nirvana (UK)/Local Anaesthetic/01.flac", "y", &[]);
+        assert!(apply(&mut in_folder, &rules));
+        assert_eq!(in_folder.artisthashes, ["uk2"]);
+
+        let mut on_album = track("/music/a.flac", "local", &[]);
+        assert!(apply(&mut on_album, &rules));
+        assert_eq!(on_album.artisthashes, ["uk3"]);
+
+        // a sibling folder with a longer name is not inside the split folder
+        let mut other = track("/music/Nirvana (UK) Live/01.flac", "z", &["def"]);
+        assert!(!apply(&mut other, &rules));
+        assert_eq!(other.artisthashes, ["nirvana"]);
+    }
+
+    #[test]
+    fn test_rules_chain_in_order() {
+        let first = ArtistSplit {
+            albumhashes: vec!["x".to_string()],
+            ..rule(1, "nirvana", "a")
+        };
+        let second = ArtistSplit {
+            albumhashes: vec!["x".to_string()],
+            ..rule(2, "a", "b")
+        };
+
+        let mut t = track("/music/01.flac", "x", &[]);
+        apply(&mut t, &[first, second]);
+        assert_eq!(t.artisthashes, ["b"]);
+    }
+}
@@ -0,0 +1,204 @@
+//! Per-user listening stats for an artist
+//!
+//! aggregated from the scrobble table over every track the artist appears on.
+//! results are cached per user and artist until the next scrobble lands, so
+//! reopening an artist page does not rescan the user's history.
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::db::tables::{ScrobbleTable, TrackPlayTotals};
+use crate::models::Track;
+
+/// cached entries kept before the cache is emptied
+const MAX_CACHED: usize = 512;
+
+/// (userid, artisthash) to the stats and the scrobble generation they were built at
+type StatsCache = HashMap<(i64, String), (u64, ArtistListeningStats)>;
+
+static CACHE: Lazy<RwLock<StatsCache>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// A track or album the user played the most
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TopItem {
+    pub hash: String,
+    pub title: String,
+    pub playcount: i64,
+    pub playduration: i64,
+}
+
+/// How a user has listened to an artist
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ArtistListeningStats {
+    /// Timestamp of the first scrobble
+    pub first_played: Option<i64>,
+    /// Timestamp of the latest scrobble
+    pub last_played: Option<i64>,
+    pub playcount: i64,
+    /// Listening time in seconds
+    pub playduration: i64,
+    pub top_track: Option<TopItem>,
+    pub top_album: Option<TopItem>,
+}
+
+/// Listening stats of a user for an artist with the given tracks
+pub async fn for_artist(
+    user_id: i64,
+    artisthash: &str,
+    tracks: &[Track],
+) -> Result<ArtistListeningStats> {
+    let key = (user_id, artisthash.to_string());
+    let generation = ScrobbleTable::generation();
+    if let Some((built_at, stats)) = CACHE.read().get(&key) {
+        if *built_at == generation {
+            return Ok(stats.clone());
+        }
+    }
+
+    let trackhashes: Vec<String> = tracks.iter().map(|t| t.trackhash.clone()).collect();
+    let totals = ScrobbleTable::track_totals(user_id, &trackhashes).await?;
+    let stats = aggregate(tracks, &totals);
+
+    let mut cache = CACHE.write();
+    if cache.len() >= MAX_CACHED {
+        cache.clear();
+    }
+    cache.insert(key, (generation, stats.clone()));
+    Ok(stats)
+}
+
+/// Fold per-track totals into artist stats
+fn aggregate(tracks: &[Track], totals: &[TrackPlayTotals]) -> ArtistListeningStats {
+    let by_hash: HashMap<&str, &Track> = tracks.iter().map(|t| (t.trackhash.as_str(), t)).collect();
+
+    let mut stats = ArtistListeningStats::default();
+    let mut top_track: Option<TopItem> = None;
+    let mut albums: HashMap<&str, TopItem> = HashMap::new();
+
+    for total in totals {
+        let Some(track) = by_hash.get(total.trackhash.as_str()) else {
+            continue;
+        };
+
+        stats.playcount += total.playcount;
+        stats.playduration += total.playduration;
+        stats.first_played = Some(
+            stats
+                .first_played
+                .map_or(total.first_played, |t| t.min(total.first_played)),
+        );
+        stats.last_played = Some(
+            stats
+                .last_played
+                .map_or(total.last_played, |t| t.max(total.last_played)),
+        );
+
+        let item = TopItem {
+            hash: track.trackhash.clone(),
+            title: track.title.clone(),
+            playcount: total.playcount,
+            playduration: total.playduration,
+        };
+        if is_better(&item, top_track.as_ref()) {
+            top_track = Some(item);
+        }
+
+        let album = albums
+            .entry(track.albumhash.as_str())
+            .or_insert_with(|| TopItem {
+                hash: track.albumhash.clone(),
+                title: track.album.clone(),
+                playcount: 0,
+                playduration: 0,
+            });
+        album.playcount += total.playcount;
+        album.playduration += total.playduration;
+    }
+
+    stats.top_track = top_track;
+    stats.top_album = albums.into_values().fold(None, |best, album| {
+        if is_better(&album, best.as_ref()) {
+            Some(album)
+        } else {
+            best
+        }
+    });
+    stats
+}
+
+/// more plays win, then more listening time, then the title for a stable pick
+pub(crate) fn is_better(item: &TopItem, current: Option<&TopItem>) -> bool {
+    match current {
+        None => true,
+        Some(current) => {
+            (item.playcount, item.playduration, &current.title)
+                > (current.playcount, current.playduration, &item.title)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(hash: &str, album: &str) -> Track {
+        Track {
+            trackhash: hash.to_string(),
+            title: format!("Track {}", hash),
+            albumhash: album.to_string(),
+            album: format!("Album {}", album),
+            ..Track::new()
+        }
+    }
+
+    fn totals(hash: &str, playcount: i64, first: i64, last: i64) -> TrackPlayTotals {
+        TrackPlayTotals {
+            trackhash: hash.to_string(),
+            playcount,
+            playduration: playcount * 180,
+            first_played: first,
+            last_played: last,
+        }
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let tracks = [track("a", "x"), track("b", "y"), track("c", "y")];
+        let stats = aggregate(
+            &tracks,
+            &[
+                totals("a", 5, 300, 900),
+                totals("b", 3, 100, 500),
+                totals("c", 4, 200, 1000),
+                // a scrobble of a track no longer on the artist is ignored
+                totals("gone", 50, 1, 2000),
+            ],
+        );
+
+        assert_eq!(stats.playcount, 12);
+        assert_eq!(stats.playduration, 12 * 180);
+        assert_eq!(stats.first_played, Some(100));
+        assert_eq!(stats.last_played, Some(1000));
+        assert_eq!(stats.top_track.unwrap().hash, "a");
+
+        let top_album = stats.top_album.unwrap();
+        assert_eq!(top_album.hash, "y");
+        assert_eq!(top_album.playcount, 7);
+    }
+
+    #[test]
+    fn test_aggregate_without_plays() {
+        let stats = aggregate(&[track("a", "x")], &[]);
+        assert_eq!(stats, ArtistListeningStats::default());
+    }
+
+    #[test]
+    fn test_ties_pick_the_first_title() {
+        let tracks = [track("b", "x"), track("a", "x")];
+        let stats = aggregate(&tracks, &[totals("b", 2, 0, 0), totals("a", 2, 0, 0)]);
+        assert_eq!(stats.top_track.unwrap().hash, "a");
+    }
+}
@@ -2,9 +2,43 @@
 
 use std::collections::HashMap;
 
-use crate::models::{Album, Artist, GenreRef, Track};
+use crate::models::{Album, AlbumType, Artist, GenreRef, Track};
 use crate::stores::{AlbumStore, ArtistStore, TrackStore};
 
+/// Which kinds of releases an artist track listing draws from
+#[derive(Debug, Clone, Copy)]
+pub struct TrackSources {
+    /// tracks on albums where the artist is not an album artist
+    pub appearances: bool,
+    /// tracks on compilation albums, including the artist's own
+    pub compilations: bool,
+}
+
+impl Default for TrackSources {
+    fn default() -> Self {
+        Self {
+            appearances: true,
+            compilations: true,
+        }
+    }
+}
+
+impl TrackSources {
+    /// Whether a track of the artist on an album of the given type is kept
+    pub fn includes(
+        &self,
+        track: &Track,
+        artist_hash: &str,
+        album_type: Option<AlbumType>,
+    ) -> bool {
+        let is_albumartist = track.albumartists.iter().any(|a| a.artisthash == artist_hash);
+        if !self.appearances && !is_albumartist {
+            return false;
+        }
+        self.compilations || album_type != Some(AlbumType::Compilation)
+    }
+}
+
 /// Artist library functions
 pub struct ArtistLib;
 
@@ -29,6 +63,43 @@ impl ArtistLib {
         TrackStore::get().get_by_artist(artist_hash)
     }
 
+    /// Get artist tracks limited to the given release kinds
+    pub fn get_tracks_from(artist_hash: &str, sources: TrackSources) -> Vec<Track> {
+        let tracks = Self::get_tracks(artist_hash);
+        if sources.appearances && sources.compilations {
+            return tracks;
+        }
+
+        let albums = AlbumStore::get();
+        let mut types: HashMap<String, Option<AlbumType>> = HashMap::new();
+        tracks
+            .into_iter()
+            .filter(|t| {
+                let album_type = *types
+                    .entry(t.albumhash.clone())
+                    .or_insert_with(|| albums.get_by_hash(&t.albumhash).map(|a| a.album_type));
+                sources.includes(t, artist_hash, album_type)
+            })
+            .collect()
+    }
+
+    /// Group tracks by album, keeping the order albums first show up in
+    pub fn group_by_album(tracks: Vec<Track>) -> Vec<(String, Vec<Track>)> {
+        let mut groups: Vec<(String, Vec<Track>)> = Vec::new();
+        let mut index: HashMap<String, usize> = HashMap::new();
+
+        for track in tracks {
+            match index.get(&track.albumhash) {
+                Some(&i) => groups[i].1.push(track),
+                None => {
+                    index.insert(track.albumhash.clone(), groups.len());
+                    groups.push((track.albumhash.clone(), vec![track]));
+                }
+            }
+        }
+        groups
+    }
+
     /// Get artist albums
     pub fn get_albums(artist_hash: &str) -> Vec<Album> {
         AlbumStore::get().get_by_artist(artist_hash)
@@ -118,7 +189,7 @@ impl ArtistLib {
         let track_store = TrackStore::get();
         for artist in artist_map.values_mut() {
             artist.duration = track_store
-                .get_by_artist(&artist.artisthash)
+                .get_shared_by_artist(&artist.artisthash)
                 .iter()
                 .map(|t| t.duration)
                 .sum();
@@ -173,3 +244,68 @@ impl ArtistLib {
         ArtistStore::get().search_by_name(query, limit)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ArtistRefItem;
+
+    fn track(hash: &str, album: &str, albumartist: &str) -> Track {
+        Track {
+            trackhash: hash.to_string(),
+            albumhash: album.to_string(),
+            albumartists: vec![ArtistRefItem::new(
+                albumartist.to_uppercase(),
+                albumartist.to_string(),
+            )],
+            ..Track::new()
+        }
+    }
+
+    #[test]
+    fn test_track_sources() {
+        let own = track("t1", "al1", "a");
+        let guest = track("t2", "al2", "b");
+        let comp = Some(AlbumType::Compilation);
+        let album = Some(AlbumType::Album);
+
+        let all = TrackSources::default();
+        assert!(all.includes(&guest, "a", comp));
+
+        let main = TrackSources {
+            appearances: false,
+            compilations: true,
+        };
+        assert!(main.includes(&own, "a", comp));
+        assert!(!main.includes(&guest, "a", album));
+
+        let no_comps = TrackSources {
+            appearances: true,
+            compilations: false,
+        };
+        assert!(no_comps.includes(&guest, "a", album));
+        assert!(!no_comps.includes(&own, "a", comp));
+        // tracks whose album is not loaded are kept
+        assert!(no_comps.includes(&own, "a", None));
+    }
+
+    #[test]
+    fn test_group_by_album() {
+        let tracks = vec![
+            track("t1", "al1", "a"),
+            track("t2", "al2", "a"),
+            track("t3", "al1", "a"),
+        ];
+        let groups = ArtistLib::group_by_album(tracks);
+        let shape: Vec<(&str, Vec<&str>)> = groups
+            .iter()
+            .map(|(album, tracks)| {
+                (
+                    album.as_str(),
+                    tracks.iter().map(|t| t.trackhash.as_str()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(shape, [("al1", vec!["t1", "t3"]), ("al2", vec!["t2"])]);
+    }
+}
@@ -0,0 +1,168 @@
+//! Audiobook folders
+//!
+//! tracks under a folder flagged as an audiobook play in file order, are left
+//! out of shuffled mixes and do not count towards listening stats.
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::cmp::Ordering;
+
+use crate::config::UserConfig;
+use crate::models::Track;
+use crate::utils::filesystem::normalize_path;
+
+/// flagged folders with a trailing slash, loaded from the config on first use
+static FOLDERS: Lazy<RwLock<Option<Vec<String>>>> = Lazy::new(|| RwLock::new(None));
+
+fn with_trailing_slash(path: &str) -> String {
+    let path = normalize_path(path.trim());
+    if path.ends_with('/') {
+        path
+    } else {
+        format!("{}/", path)
+    }
+}
+
+fn load_folders() -> Vec<String> {
+    if let Some(folders) = FOLDERS.read().as_ref() {
+        return folders.clone();
+    }
+
+    let folders: Vec<String> = UserConfig::load()
+        .map(|c| c.audiobook_dirs)
+        .unwrap_or_default()
+        .iter()
+        .map(|dir| with_trailing_slash(dir))
+        .collect();
+    *FOLDERS.write() = Some(folders.clone());
+    folders
+}
+
+/// Folders flagged as audiobooks
+pub fn folders() -> Vec<String> {
+    load_folders()
+}
+
+/// Whether a folder is flagged or sits inside a flagged folder
+pub fn is_audiobook_folder(path: &str) -> bool {
+    let path = with_trailing_slash(path);
+    load_folders().iter().any(|dir| path.starts_with(dir))
+}
+
+/// Whether a track belongs to an audiobook folder
+pub fn is_audiobook(track: &Track) -> bool {
+    is_audiobook_path(&track.filepath)
+}
+
+/// Whether a file path belongs to an audiobook folder
+pub fn is_audiobook_path(filepath: &str) -> bool {
+    let filepath = normalize_path(filepath);
+    load_folders().iter().any(|dir| filepath.starts_with(dir))
+}
+
+/// Flag or unflag a folder, returns the flagged folders
+pub fn set_folder(path: &str, audiobook: bool) -> Result<Vec<String>> {
+    let folder = with_trailing_slash(path);
+    let mut config = UserConfig::load()?;
+
+    config
+        .audiobook_dirs
+        .retain(|dir| with_trailing_slash(dir) != folder);
+    if audiobook {
+        config
+            .audiobook_dirs
+            .push(folder.trim_end_matches('/').to_string());
+    }
+    config.save()?;
+
+    *FOLDERS.write() = None;
+    Ok(load_folders())
+}
+
+/// Sort tracks the way their files are named on disk
+///
+/// numbers compare by value so "Chapter 2" comes before "Chapter 10"
+pub fn sort_file_order(tracks: &mut [Track]) {
+    tracks.sort_by(|a, b| natural_cmp(&a.filepath, &b.filepath));
+}
+
+/// Compare strings case insensitively with digit runs compared as numbers
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_number(&mut a);
+                let y = take_number(&mut b);
+                // compare without leading zeros, longer runs are larger numbers
+                let (xs, ys) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let ordering = xs
+                    .len()
+                    .cmp(&ys.len())
+                    .then_with(|| xs.cmp(ys))
+                    .then_with(|| x.len().cmp(&y.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn take_number(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+    let mut number = String::new();
+    while let Some(c) = chars.peek().copied().filter(char::is_ascii_digit) {
+        number.push(c);
+        chars.next();
+    }
+    number
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut names = vec![
+            "Book/Chapter 10.mp3",
+            "Book/chapter 2.mp3",
+            "Book/Chapter 1.mp3",
+            "Book/Chapter 02b.mp3",
+        ];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            vec![
+                "Book/Chapter 1.mp3",
+                "Book/chapter 2.mp3",
+                "Book/Chapter 02b.mp3",
+                "Book/Chapter 10.mp3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_folder_prefix_needs_whole_segment() {
+        *FOLDERS.write() = Some(vec!["/music/Audiobooks/".to_string()]);
+
+        assert!(is_audiobook_path("/music/Audiobooks/Dune/01.mp3"));
+        assert!(is_audiobook_folder("/music/Audiobooks"));
+        assert!(!is_audiobook_path("/music/Audiobooks Extra/01.mp3"));
+        assert!(!is_audiobook_folder("/music"));
+    }
+}
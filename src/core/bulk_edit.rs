@@ -0,0 +1,151 @@
+//! Editing the tags of every track of an album or artist at once
+//!
+//! the tags of each file are copied before it is written. when a write fails
+//! the files already written get their old tags back, so an edit reaches every
+//! track or none of them. the written files are then reindexed so the database
+//! and stores follow the new tags.
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+use utoipa::ToSchema;
+
+use crate::config::{SplitField, UserConfig};
+use crate::core::populate::reindex_track_files;
+use crate::core::tagger::{TagSnapshot, Tagger};
+use crate::models::Track;
+use crate::stores::{ArtistStore, TrackStore};
+
+/// Album level tags written to every track of an album
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct AlbumTagEdit {
+    #[serde(default)]
+    pub album: Option<String>,
+    #[serde(default)]
+    pub albumartist: Option<String>,
+    #[serde(default)]
+    pub year: Option<i32>,
+    #[serde(default)]
+    pub genre: Option<String>,
+}
+
+impl AlbumTagEdit {
+    /// Trim the text fields and drop the empty ones
+    pub fn cleaned(self) -> Self {
+        let clean = |v: Option<String>| v.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+        Self {
+            album: clean(self.album),
+            albumartist: clean(self.albumartist),
+            year: self.year,
+            genre: clean(self.genre),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.album.is_none()
+            && self.albumartist.is_none()
+            && self.year.is_none()
+            && self.genre.is_none()
+    }
+}
+
+/// Write album tags to every track of an album, returns the reindexed tracks
+pub async fn edit_album(albumhash: &str, edit: AlbumTagEdit) -> Result<Vec<Track>> {
+    let paths = filepaths(TrackStore::get().get_by_album(albumhash));
+    if paths.is_empty() {
+        return Err(anyhow!("Album has no tracks"));
+    }
+
+    let written = tokio::task::spawn_blocking(move || {
+        write_all(&paths, |path| {
+            Tagger::write_tags(
+                path,
+                None,
+                edit.album.as_deref(),
+                None,
+                edit.albumartist.as_deref(),
+                None,
+                None,
+                edit.year,
+                edit.genre.as_deref(),
+            )?;
+            Ok(true)
+        })
+    })
+    .await??;
+
+    reindex_track_files(&written).await
+}
+
+/// Rename an artist in the tags of all its tracks, returns the reindexed tracks
+pub async fn rename_artist(artisthash: &str, name: &str) -> Result<Vec<Track>> {
+    let artist = ArtistStore::get()
+        .get_by_hash(artisthash)
+        .ok_or_else(|| anyhow!("Artist not found"))?;
+    let config = UserConfig::load()?;
+    let artist_separators = config.separators_for(SplitField::Artist).clone();
+    let album_artist_separators = config.separators_for(SplitField::AlbumArtist).clone();
+    let paths = filepaths(TrackStore::get().get_by_artist(artisthash));
+
+    let from = artist.name;
+    let to = name.to_string();
+    let written = tokio::task::spawn_blocking(move || {
+        write_all(&paths, |path| {
+            Tagger::rename_artist(
+                path,
+                &from,
+                &to,
+                &artist_separators,
+                &album_artist_separators,
+            )
+        })
+    })
+    .await??;
+
+    if written.is_empty() {
+        return Err(anyhow!("The artist name is not in any artist tag"));
+    }
+    reindex_track_files(&written).await
+}
+
+fn filepaths(tracks: Vec<Track>) -> Vec<String> {
+    tracks.into_iter().map(|t| t.filepath).collect()
+}
+
+/// Apply `write` to every file, undoing all writes if one fails
+///
+/// `write` returns whether it changed the file, the changed paths are returned
+fn write_all(paths: &[String], write: impl Fn(&Path) -> Result<bool>) -> Result<Vec<String>> {
+    if let Some(missing) = paths.iter().find(|p| !Path::new(p).exists()) {
+        return Err(anyhow!("Track file not found: {}", missing));
+    }
+
+    let mut snapshots: Vec<(&String, TagSnapshot)> = Vec::new();
+    let mut changed = Vec::new();
+    for path in paths {
+        let result = Tagger::snapshot(Path::new(path)).and_then(|snapshot| {
+            // a failed save may have touched the file so it is restored too
+            snapshots.push((path, snapshot));
+            write(Path::new(path))
+        });
+
+        match result {
+            Ok(true) => changed.push(path.clone()),
+            Ok(false) => {}
+            Err(e) => {
+                rollback(&snapshots);
+                return Err(e).with_context(|| format!("Failed to write tags to {}", path));
+            }
+        }
+    }
+
+    Ok(changed)
+}
+
+fn rollback(snapshots: &[(&String, TagSnapshot)]) {
+    for (path, snapshot) in snapshots {
+        if let Err(e) = Tagger::restore(Path::new(path), snapshot) {
+            tracing::error!("Failed to restore tags of {}: {}", path, e);
+        }
+    }
+}
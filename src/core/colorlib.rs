@@ -2,7 +2,29 @@
 
 use anyhow::Result;
 use image::GenericImageView;
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::models::ColorVariants;
+
+/// Background the dark mode variant must stay readable on
+const DARK_BACKGROUND: &str = "#111111";
+
+/// Background the light mode variant must stay readable on
+const LIGHT_BACKGROUND: &str = "#ffffff";
+
+/// Minimum WCAG contrast ratio for normal text (level AA)
+const MIN_CONTRAST: f64 = 4.5;
+
+/// Number of lighten or darken steps tried before giving up
+const ADJUST_STEPS: u8 = 20;
+
+/// Bits kept per channel when grouping pixels into palette colors
+const PALETTE_BITS: u8 = 4;
+
+/// Smallest distance between two palette colors, so shades of one color
+/// don't fill the palette
+const PALETTE_MIN_DISTANCE: u32 = 48;
 
 /// Color library for extracting dominant colors from images
 pub struct ColorLib;
@@ -31,22 +53,102 @@ impl ColorLib {
 
     /// Extract dominant color from image bytes
     pub fn extract_from_bytes(data: &[u8]) -> Result<String> {
+        let colors = Self::sample_bytes(data)?;
+        let dominant = Self::find_dominant_color(&colors);
+
+        Ok(Self::rgb_to_hex(dominant))
+    }
+
+    /// Extract the dominant color and a palette of up to `count` distinct
+    /// colors from image bytes, the most common color first
+    pub fn extract_palette(data: &[u8], count: usize) -> Result<(String, Vec<String>)> {
+        let colors = Self::sample_bytes(data)?;
+        let dominant = Self::rgb_to_hex(Self::find_dominant_color(&colors));
+        let palette = Self::find_palette(&colors, count)
+            .into_iter()
+            .map(Self::rgb_to_hex)
+            .collect();
+
+        Ok((dominant, palette))
+    }
+
+    /// Pixels of a thumbnail of the image, enough to tell its colors
+    fn sample_bytes(data: &[u8]) -> Result<Vec<(u8, u8, u8)>> {
         let img = image::load_from_memory(data)?;
 
         // Resize for faster processing
         let thumbnail = img.thumbnail(100, 100);
 
-        // Sample colors
+        Ok(thumbnail
+            .pixels()
+            .map(|(_, _, pixel)| (pixel.0[0], pixel.0[1], pixel.0[2]))
+            .collect())
+    }
+
+    /// Group colors into buckets and keep the averages of the fullest ones
+    /// that are far enough apart
+    fn find_palette(colors: &[(u8, u8, u8)], count: usize) -> Vec<(u8, u8, u8)> {
+        let shift = 8 - PALETTE_BITS;
+        // pixel count and channel sums per bucket
+        let mut buckets: HashMap<(u8, u8, u8), (u64, [u64; 3])> = HashMap::new();
+        for &(r, g, b) in colors {
+            let (n, sums) = buckets
+                .entry((r >> shift, g >> shift, b >> shift))
+                .or_default();
+            *n += 1;
+            sums[0] += r as u64;
+            sums[1] += g as u64;
+            sums[2] += b as u64;
+        }
+
+        let mut buckets: Vec<_> = buckets.into_iter().collect();
+        // the bucket key breaks ties so the palette doesn't depend on map order
+        buckets.sort_by(|(ka, a), (kb, b)| b.0.cmp(&a.0).then(ka.cmp(kb)));
+
+        let mut palette: Vec<(u8, u8, u8)> = Vec::new();
+        for (_, (n, [r, g, b])) in buckets {
+            if palette.len() == count {
+                break;
+            }
+            let color = ((r / n) as u8, (g / n) as u8, (b / n) as u8);
+            if palette
+                .iter()
+                .all(|&other| Self::distance(color, other) >= PALETTE_MIN_DISTANCE)
+            {
+                palette.push(color);
+            }
+        }
+        palette
+    }
+
+    /// Euclidean distance between two colors
+    fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+        let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+        ((d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)) as f64).sqrt() as u32
+    }
+
+    /// Extract a single dominant color across several images, as for a collage
+    pub fn extract_from_collage(image_paths: &[PathBuf]) -> Result<String> {
         let mut colors: Vec<(u8, u8, u8)> = Vec::new();
 
-        for (_, _, pixel) in thumbnail.pixels() {
-            let rgba = pixel.0;
-            colors.push((rgba[0], rgba[1], rgba[2]));
+        for path in image_paths {
+            let Ok(img) = image::open(path) else {
+                continue;
+            };
+
+            // Each tile contributes the same number of samples
+            let thumbnail = img.thumbnail_exact(50, 50);
+            for (_, _, pixel) in thumbnail.pixels() {
+                let rgba = pixel.0;
+                colors.push((rgba[0], rgba[1], rgba[2]));
+            }
         }
 
-        let dominant = Self::find_dominant_color(&colors);
+        if colors.is_empty() {
+            return Err(anyhow::anyhow!("No readable images in collage"));
+        }
 
-        Ok(Self::rgb_to_hex(dominant))
+        Ok(Self::rgb_to_hex(Self::find_dominant_color(&colors)))
     }
 
     /// Find dominant color from list of colors
@@ -157,4 +259,127 @@ impl ColorLib {
             hex.to_string()
         }
     }
+
+    /// WCAG relative luminance of a color (0.0-1.0)
+    pub fn relative_luminance(hex: &str) -> Option<f64> {
+        let (r, g, b) = Self::hex_to_rgb(hex)?;
+        let channel = |c: u8| {
+            let c = c as f64 / 255.0;
+            if c <= 0.03928 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        Some(0.2126 * channel(r) + 0.7152 * channel(g) + 0.0722 * channel(b))
+    }
+
+    /// WCAG contrast ratio between two colors (1.0-21.0)
+    pub fn contrast_ratio(a: &str, b: &str) -> Option<f64> {
+        let la = Self::relative_luminance(a)?;
+        let lb = Self::relative_luminance(b)?;
+        let (lighter, darker) = if la > lb { (la, lb) } else { (lb, la) };
+        Some((lighter + 0.05) / (darker + 0.05))
+    }
+
+    /// Compute dark and light mode variants of a color
+    ///
+    /// the dark variant is lightened and the light variant darkened just
+    /// enough to reach the minimum contrast against the theme background.
+    /// invalid colors yield empty variants.
+    pub fn variants(hex: &str) -> ColorVariants {
+        let Some(rgb) = Self::hex_to_rgb(hex) else {
+            return ColorVariants::default();
+        };
+        let hex = Self::rgb_to_hex(rgb);
+
+        ColorVariants {
+            dark: Self::adjust_for_contrast(&hex, DARK_BACKGROUND, Self::lighten),
+            light: Self::adjust_for_contrast(&hex, LIGHT_BACKGROUND, Self::darken),
+        }
+    }
+
+    /// Step a color towards white or black until it contrasts with the background
+    fn adjust_for_contrast(hex: &str, background: &str, adjust: fn(&str, f32) -> String) -> String {
+        for step in 0..=ADJUST_STEPS {
+            let candidate = if step == 0 {
+                hex.to_string()
+            } else {
+                adjust(hex, step as f32 / ADJUST_STEPS as f32)
+            };
+            if Self::contrast_ratio(&candidate, background).unwrap_or(0.0) >= MIN_CONTRAST {
+                return candidate;
+            }
+        }
+        adjust(hex, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contrast_ratio_extremes() {
+        let ratio = ColorLib::contrast_ratio("#000000", "#ffffff").unwrap();
+        assert!((ratio - 21.0).abs() < 0.01);
+        let ratio = ColorLib::contrast_ratio("#777777", "#777777").unwrap();
+        assert!((ratio - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_variants_meet_minimum_contrast() {
+        for hex in [
+            "#1a1a1a", "#3b1c8c", "#777777", "#e0e0e0", "#ffee00", "#0000ff",
+        ] {
+            let variants = ColorLib::variants(hex);
+            let dark = ColorLib::contrast_ratio(&variants.dark, DARK_BACKGROUND).unwrap();
+            let light = ColorLib::contrast_ratio(&variants.light, LIGHT_BACKGROUND).unwrap();
+            assert!(
+                dark >= MIN_CONTRAST,
+                "{} dark variant {}",
+                hex,
+                variants.dark
+            );
+            assert!(
+                light >= MIN_CONTRAST,
+                "{} light variant {}",
+                hex,
+                variants.light
+            );
+        }
+    }
+
+    #[test]
+    fn test_variants_keep_readable_colors() {
+        let variants = ColorLib::variants("#FFEE00");
+        assert_eq!(variants.dark, "#ffee00");
+        assert_ne!(variants.light, "#ffee00");
+
+        let variants = ColorLib::variants("#1a1a5e");
+        assert_eq!(variants.light, "#1a1a5e");
+        assert_ne!(variants.dark, "#1a1a5e");
+    }
+
+    #[test]
+    fn test_find_palette_keeps_distinct_colors() {
+        let mut colors = vec![(200, 20, 20); 50];
+        colors.extend(vec![(205, 22, 25); 30]);
+        colors.extend(vec![(20, 20, 200); 40]);
+        colors.extend(vec![(20, 200, 20); 10]);
+
+        let palette = ColorLib::find_palette(&colors, 2);
+        assert_eq!(palette.len(), 2);
+        assert!(palette[0].0 > 190);
+        assert_eq!(palette[1], (20, 20, 200));
+
+        assert_eq!(ColorLib::find_palette(&colors, 5).len(), 3);
+        assert!(ColorLib::find_palette(&[], 5).is_empty());
+    }
+
+    #[test]
+    fn test_variants_of_invalid_color() {
+        assert_eq!(ColorLib::variants(""), ColorVariants::default());
+        assert_eq!(ColorLib::variants("#12"), ColorVariants::default());
+    }
 }
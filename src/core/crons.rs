@@ -1,9 +1,13 @@
 //! Cron jobs for periodic tasks
 
 use anyhow::Result;
+use chrono::{DateTime, Local, TimeZone, Timelike};
 use std::time::Duration;
 use tokio::time;
 
+/// Local hour the nightly jobs run at
+const NIGHTLY_HOUR: u32 = 3;
+
 /// Start all cron jobs
 pub async fn start_cron_jobs() -> Result<()> {
     // Periodic cleanup job (runs every hour)
@@ -17,6 +21,17 @@ pub async fn start_cron_jobs() -> Result<()> {
         }
     });
 
+    // Scheduled database maintenance (checked every hour)
+    tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            if let Err(e) = scheduled_maintenance().await {
+                tracing::error!("Database maintenance error: {}", e);
+            }
+        }
+    });
+
     // Periodic scan job (runs every 6 hours)
     tokio::spawn(async move {
         let mut interval = time::interval(Duration::from_secs(21600));
@@ -28,6 +43,128 @@ pub async fn start_cron_jobs() -> Result<()> {
         }
     });
 
+    // Inbox filing (checked every 10 minutes)
+    tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(600));
+        loop {
+            interval.tick().await;
+            if let Err(e) = file_inbox().await {
+                tracing::error!("Inbox filing error: {}", e);
+            }
+        }
+    });
+
+    // Trash purge (runs every hour)
+    tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            match crate::core::trash::purge_expired().await {
+                Ok(0) => {}
+                Ok(purged) => tracing::info!("Purged {} tracks from the trash", purged),
+                Err(e) => tracing::error!("Trash purge error: {}", e),
+            }
+        }
+    });
+
+    // Podcast feed refresh (runs every 3 hours)
+    tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(10800));
+        loop {
+            interval.tick().await;
+            if let Err(e) = crate::core::podcasts::refresh_all().await {
+                tracing::error!("Podcast refresh error: {}", e);
+            }
+        }
+    });
+
+    // Library hubs, built right away so a fresh library has them and then
+    // rebuilt every night
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = refresh_library_hubs().await {
+                tracing::error!("Library hub refresh error: {}", e);
+            }
+            time::sleep(until_hour(Local::now(), NIGHTLY_HOUR)).await;
+        }
+    });
+
+    // Daily mixes, made for every user once the local day starts so they stay
+    // the same all day, the history is trimmed at the same time
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = refresh_daily_mixes().await {
+                tracing::error!("Daily mix refresh error: {}", e);
+            }
+            time::sleep(until_hour(Local::now(), 0)).await;
+        }
+    });
+
+    // Decaying popularity scores for the trending rows, rebuilt every night
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = refresh_popularity().await {
+                tracing::error!("Popularity refresh error: {}", e);
+            }
+            time::sleep(until_hour(Local::now(), NIGHTLY_HOUR)).await;
+        }
+    });
+
+    Ok(())
+}
+
+/// Time left until the next time the local clock reads `hour` o'clock
+fn until_hour<Tz: TimeZone>(now: DateTime<Tz>, hour: u32) -> Duration {
+    let today = now
+        .with_hour(hour)
+        .and_then(|t| t.with_minute(0))
+        .and_then(|t| t.with_second(0))
+        .and_then(|t| t.with_nanosecond(0));
+    let next = match today {
+        Some(t) if t > now => t,
+        Some(t) => t + chrono::Duration::days(1),
+        // the hour does not exist today, e.g. skipped by a dst change
+        None => now.clone() + chrono::Duration::days(1),
+    };
+    (next - now).to_std().unwrap_or(Duration::from_secs(3600))
+}
+
+/// Rebuild the genre, decade and label collections
+async fn refresh_library_hubs() -> Result<()> {
+    let count = crate::core::recipes::Recipes::refresh_library_hubs().await?;
+    tracing::info!("Library hubs refreshed, {} hubs", count);
+    Ok(())
+}
+
+/// Store today's daily mixes for every user
+async fn refresh_daily_mixes() -> Result<()> {
+    let count = crate::core::recipes::Recipes::refresh_daily_mixes().await?;
+    tracing::info!("Daily mixes refreshed for {} users", count);
+    Ok(())
+}
+
+/// Recompute the decaying popularity of tracks, artists and albums
+async fn refresh_popularity() -> Result<()> {
+    let count = crate::core::popularity::refresh().await?;
+    tracing::info!("Popularity scores refreshed, {} scores", count);
+    Ok(())
+}
+
+/// File new files dropped in the inbox, when there is one
+async fn file_inbox() -> Result<()> {
+    let config = crate::config::UserConfig::load()?;
+    if crate::core::inbox::inbox_dir(&config).is_none() {
+        return Ok(());
+    }
+
+    let summary = crate::core::inbox::scan().await?;
+    if summary.filed + summary.queued > 0 {
+        tracing::info!(
+            "Inbox filed {} files, {} wait for review",
+            summary.filed,
+            summary.queued
+        );
+    }
     Ok(())
 }
 
@@ -38,14 +175,57 @@ async fn cleanup_task() -> Result<()> {
     let db = DbEngine::get()?;
 
     // Clean up old scrobbles (older than 1 year)
-    sqlx::query("DELETE FROM scrobble WHERE timestamp < datetime('now', '-1 year')")
+    // timestamps are unix seconds, comparing them to a date string matched every row
+    let cutoff = chrono::Utc::now().timestamp() - 365 * 24 * 3600;
+    sqlx::query("DELETE FROM scrobble WHERE timestamp < ?")
+        .bind(cutoff)
         .execute(db.pool())
         .await?;
+    crate::db::tables::ScrobbleTable::mark_changed();
+
+    // Reclaim playlist images left behind by failed updates or deleted playlists
+    let removed = crate::core::PlaylistLib::cleanup_orphan_images().await?;
+    if removed > 0 {
+        tracing::info!("Removed {} orphaned playlist images", removed);
+    }
+
+    // Expired offline sync jobs and their converted files
+    let removed = crate::core::offline_sync::prune()?;
+    if removed > 0 {
+        tracing::info!("Removed {} expired sync jobs", removed);
+    }
 
     tracing::info!("Cleanup task completed");
     Ok(())
 }
 
+/// Run database maintenance once the configured interval has passed
+async fn scheduled_maintenance() -> Result<()> {
+    use crate::config::UserConfig;
+    use crate::core::indexer::ScanProgress;
+    use crate::core::maintenance::{self, MaintenanceTasks};
+
+    let config = UserConfig::load()?;
+    let last = maintenance::last_run().map(|r| r.started_at);
+    let now = chrono::Utc::now().timestamp();
+    if !maintenance::is_due(config.db_maintenance_interval, last, now) {
+        return Ok(());
+    }
+
+    // vacuum blocks writers, try again next hour
+    if ScanProgress::state().running {
+        return Ok(());
+    }
+
+    let report = maintenance::run(MaintenanceTasks::default()).await?;
+    tracing::info!(
+        "Database maintenance completed in {} ms, reclaimed {} bytes",
+        report.duration_ms,
+        report.reclaimed
+    );
+    Ok(())
+}
+
 /// Periodic scan of music folders
 async fn periodic_scan() -> Result<()> {
     use crate::config::UserConfig;
@@ -65,3 +245,17 @@ async fn periodic_scan() -> Result<()> {
     tracing::info!("Periodic scan completed");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_until_hour() {
+        let at = |h, m| Utc.with_ymd_and_hms(2024, 5, 1, h, m, 0).unwrap();
+        assert_eq!(until_hour(at(1, 30), 3), Duration::from_secs(90 * 60));
+        assert_eq!(until_hour(at(3, 0), 3), Duration::from_secs(24 * 3600));
+        assert_eq!(until_hour(at(23, 0), 3), Duration::from_secs(4 * 3600));
+    }
+}
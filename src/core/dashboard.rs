@@ -0,0 +1,235 @@
+//! Server snapshot for the built-in admin dashboard
+//!
+//! gathers what a small admin panel needs without an external metrics stack:
+//! streams, process usage, cache sizes, background work, library counts and
+//! the last scan. cache directories are walked at most once a minute.
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use sysinfo::{PidExt, ProcessExt, System, SystemExt};
+use walkdir::WalkDir;
+
+use crate::config::Paths;
+use crate::core::indexer::{ScanPhase, ScanProgress};
+use crate::core::transcode::TranscodeCache;
+use crate::core::{fingerprint, lossless, maintenance, playback, watchdogg};
+use crate::stores::{AlbumStore, ArtistStore, TrackStore};
+
+/// how long walked cache sizes are reused
+const CACHE_SIZES_TTL: Duration = Duration::from_secs(60);
+
+/// kept between snapshots, cpu usage is measured since the previous refresh
+static SYSTEM: Lazy<Mutex<System>> = Lazy::new(|| Mutex::new(System::new()));
+
+/// cache sizes and when they were walked
+type CachedSizes = Option<(Instant, Vec<CacheSize>)>;
+
+static CACHE_SIZES: Lazy<Mutex<CachedSizes>> = Lazy::new(|| Mutex::new(None));
+
+/// Resource usage of the server process
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessUsage {
+    pub pid: u32,
+    /// share of one core since the previous snapshot, 0 on the first one
+    pub cpu_percent: f32,
+    /// resident memory in bytes
+    pub memory: u64,
+    pub virtual_memory: u64,
+    pub uptime_seconds: u64,
+}
+
+/// Streams being served
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamCounts {
+    /// stream responses still being sent
+    pub open: usize,
+    /// listening sessions followed for scrobble on disconnect
+    pub sessions: usize,
+}
+
+/// Disk used by one cache directory
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheSize {
+    pub name: String,
+    pub path: String,
+    pub size: u64,
+    pub files: u64,
+}
+
+/// Background work waiting or running
+#[derive(Debug, Clone, Serialize)]
+pub struct JobCounts {
+    pub transcodes: usize,
+    /// file changes the watchers have not applied yet
+    pub watcher_changes: usize,
+    pub scan: bool,
+    pub maintenance: bool,
+    pub fingerprints: bool,
+    pub lossless: bool,
+    /// everything above added up, each running pass counting once
+    pub depth: usize,
+}
+
+/// Items in the in-memory stores
+#[derive(Debug, Clone, Serialize)]
+pub struct LibraryCounts {
+    pub tracks: usize,
+    pub albums: usize,
+    pub artists: usize,
+}
+
+/// The running or last library scan
+#[derive(Debug, Clone, Serialize)]
+pub struct LastScan {
+    pub running: bool,
+    pub phase: ScanPhase,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    pub tracks_written: usize,
+    pub files_failed: usize,
+    pub last_error: Option<String>,
+}
+
+/// Everything the dashboard shows
+#[derive(Debug, Clone, Serialize)]
+pub struct Dashboard {
+    pub generated_at: i64,
+    pub process: Option<ProcessUsage>,
+    pub streams: StreamCounts,
+    pub caches: Vec<CacheSize>,
+    pub jobs: JobCounts,
+    pub library: LibraryCounts,
+    pub last_scan: LastScan,
+}
+
+/// Take a snapshot, walking the cache directories when the last walk is stale
+pub fn snapshot() -> Result<Dashboard> {
+    let scan = ScanProgress::state();
+    let watcher_changes = watchdogg::watcher_statuses()
+        .iter()
+        .map(|w| w.pending_changes)
+        .sum();
+
+    let mut jobs = JobCounts {
+        transcodes: TranscodeCache::active_jobs(),
+        watcher_changes,
+        scan: scan.running,
+        maintenance: maintenance::is_running(),
+        fingerprints: fingerprint::is_running(),
+        lossless: lossless::is_running(),
+        depth: 0,
+    };
+    jobs.depth = jobs.transcodes
+        + jobs.watcher_changes
+        + [
+            jobs.scan,
+            jobs.maintenance,
+            jobs.fingerprints,
+            jobs.lossless,
+        ]
+        .iter()
+        .filter(|running| **running)
+        .count();
+
+    Ok(Dashboard {
+        generated_at: chrono::Utc::now().timestamp(),
+        process: process_usage(),
+        streams: StreamCounts {
+            open: playback::open_streams(),
+            sessions: playback::active_sessions(),
+        },
+        caches: cache_sizes()?,
+        jobs,
+        library: LibraryCounts {
+            tracks: TrackStore::get().count(),
+            albums: AlbumStore::get().count(),
+            artists: ArtistStore::get().count(),
+        },
+        last_scan: LastScan {
+            running: scan.running,
+            phase: scan.phase,
+            started_at: scan.started_at,
+            finished_at: scan.finished_at,
+            tracks_written: scan.tracks_written,
+            files_failed: scan.files_failed,
+            last_error: scan.last_error,
+        },
+    })
+}
+
+fn process_usage() -> Option<ProcessUsage> {
+    let pid = sysinfo::get_current_pid().ok()?;
+    let mut system = SYSTEM.lock();
+    system.refresh_process(pid);
+    let process = system.process(pid)?;
+
+    Some(ProcessUsage {
+        pid: pid.as_u32(),
+        cpu_percent: process.cpu_usage(),
+        memory: process.memory(),
+        virtual_memory: process.virtual_memory(),
+        uptime_seconds: process.run_time(),
+    })
+}
+
+fn cache_sizes() -> Result<Vec<CacheSize>> {
+    if let Some((walked_at, sizes)) = CACHE_SIZES.lock().as_ref() {
+        if walked_at.elapsed() < CACHE_SIZES_TTL {
+            return Ok(sizes.clone());
+        }
+    }
+
+    let paths = Paths::get()?;
+    let dirs: [(&str, PathBuf); 5] = [
+        ("transcodes", paths.transcode_cache_dir()),
+        ("artwork", paths.artwork_cache_dir()),
+        ("resized", paths.resized_image_cache_dir()),
+        ("sync", paths.sync_dir()),
+        ("images", paths.images_dir()),
+    ];
+    let sizes: Vec<CacheSize> = dirs
+        .into_iter()
+        .map(|(name, dir)| {
+            let (size, files) = dir_size(&dir);
+            CacheSize {
+                name: name.to_string(),
+                path: dir.to_string_lossy().to_string(),
+                size,
+                files,
+            }
+        })
+        .collect();
+
+    *CACHE_SIZES.lock() = Some((Instant::now(), sizes.clone()));
+    Ok(sizes)
+}
+
+/// Bytes and files under a directory, nothing when it does not exist
+fn dir_size(dir: &Path) -> (u64, u64) {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .fold((0, 0), |(size, files), meta| (size + meta.len(), files + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dir_size() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("nested")).unwrap();
+        std::fs::write(dir.path().join("a.webp"), b"12345").unwrap();
+        std::fs::write(dir.path().join("nested").join("b.webp"), b"123").unwrap();
+
+        assert_eq!(dir_size(dir.path()), (8, 2));
+        assert_eq!(dir_size(&dir.path().join("missing")), (0, 0));
+    }
+}
@@ -0,0 +1,84 @@
+//! Devices plays are recorded from
+//!
+//! clients name the device they play on with the `X-Device-Id` header, the
+//! name is stored on each play so history, stats and recently played rows can
+//! be narrowed to some devices. a user can also keep devices out of their
+//! mixes, shared speakers for example, the list lives in the user's extra
+//! data.
+
+use anyhow::{bail, Result};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::db::tables::{DeviceFilter, UserTable};
+
+/// key of the excluded device list in the user's extra data
+const EXCLUDED_KEY: &str = "mix_excluded_devices";
+
+/// devices each user keeps out of mixes, loaded from the user on first use
+static MIX_EXCLUDED: Lazy<RwLock<HashMap<i64, Vec<String>>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+fn excluded_from_extra(extra: &Value) -> Vec<String> {
+    extra
+        .get(EXCLUDED_KEY)
+        .and_then(|v| v.as_array())
+        .map(|devices| {
+            devices
+                .iter()
+                .filter_map(|d| d.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Devices whose plays a user keeps out of their mixes
+pub async fn excluded_from_mixes(user_id: i64) -> Vec<String> {
+    if let Some(devices) = MIX_EXCLUDED.read().get(&user_id) {
+        return devices.clone();
+    }
+
+    let devices = match UserTable::get_by_id(user_id).await {
+        Ok(Some(user)) => excluded_from_extra(&user.extra),
+        _ => Vec::new(),
+    };
+    MIX_EXCLUDED.write().insert(user_id, devices.clone());
+    devices
+}
+
+/// The filter mixes read a user's plays through
+pub async fn mix_filter(user_id: i64) -> DeviceFilter {
+    DeviceFilter::default().excluding(excluded_from_mixes(user_id).await)
+}
+
+/// Keep a device's plays out of a user's mixes or let them back in, returns
+/// the updated list
+pub async fn set_excluded_from_mixes(
+    user_id: i64,
+    device: &str,
+    excluded: bool,
+) -> Result<Vec<String>> {
+    let Some(mut user) = UserTable::get_by_id(user_id).await? else {
+        bail!("user {} not found", user_id);
+    };
+
+    let mut devices = excluded_from_extra(&user.extra);
+    devices.retain(|d| d != device);
+    if excluded {
+        devices.push(device.to_string());
+    }
+    devices.sort();
+
+    if !user.extra.is_object() {
+        user.extra = Value::Object(Default::default());
+    }
+    if let Some(extra) = user.extra.as_object_mut() {
+        extra.insert(EXCLUDED_KEY.to_string(), serde_json::json!(devices));
+    }
+    UserTable::update(&user).await?;
+
+    MIX_EXCLUDED.write().insert(user_id, devices.clone());
+    Ok(devices)
+}
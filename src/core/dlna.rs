@@ -0,0 +1,726 @@
+//! DLNA/UPnP media server
+//!
+//! announces the server over SSDP and builds the ContentDirectory browse tree
+//! from the album and folder stores. the HTTP side (device description, service
+//! descriptions and SOAP control) lives in `api::dlna`.
+
+use anyhow::{anyhow, Result};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tracing::{debug, info, warn};
+
+use crate::config::UserConfig;
+use crate::core::transcode::AudioFormat;
+use crate::models::Track;
+use crate::stores::{AlbumStore, FolderStore, TrackStore};
+use crate::utils::network::get_local_ip;
+
+pub const DEVICE_TYPE: &str = "urn:schemas-upnp-org:device:MediaServer:1";
+pub const CONTENT_DIRECTORY: &str = "urn:schemas-upnp-org:service:ContentDirectory:1";
+pub const CONNECTION_MANAGER: &str = "urn:schemas-upnp-org:service:ConnectionManager:1";
+
+const SSDP_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const SSDP_PORT: u16 = 1900;
+
+/// Seconds clients may cache an announcement
+const MAX_AGE: u64 = 1800;
+
+/// Announcements are repeated well before they expire
+const NOTIFY_INTERVAL: Duration = Duration::from_secs(MAX_AGE / 3);
+
+const ROOT_ID: &str = "0";
+const ALBUMS_ID: &str = "albums";
+const FOLDERS_ID: &str = "folders";
+const ALBUM_PREFIX: &str = "album/";
+const FOLDER_PREFIX: &str = "folder/";
+const TRACK_PREFIX: &str = "track/";
+
+static SERVER: OnceLock<DlnaServer> = OnceLock::new();
+
+/// Bumped on library changes so clients drop cached listings
+static SYSTEM_UPDATE_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Identity of the running DLNA server
+#[derive(Debug, Clone)]
+pub struct DlnaServer {
+    /// Unique device name ("uuid:...")
+    pub udn: String,
+    pub friendly_name: String,
+    /// Base url of the web server on the announced interface
+    pub base_url: String,
+}
+
+impl DlnaServer {
+    /// The running server, `None` when DLNA is disabled
+    pub fn get() -> Option<&'static DlnaServer> {
+        SERVER.get()
+    }
+
+    /// Url of the device description
+    pub fn location(&self) -> String {
+        format!("{}/dlna/description.xml", self.base_url)
+    }
+
+    /// UPnP device description document
+    pub fn description(&self) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0" xmlns:dlna="urn:schemas-dlna-org:device-1-0">
+  <specVersion><major>1</major><minor>0</minor></specVersion>
+  <device>
+    <deviceType>{device_type}</deviceType>
+    <friendlyName>{name}</friendlyName>
+    <manufacturer>SwingMusic</manufacturer>
+    <manufacturerURL>https://github.com/swingmx/swingmusic</manufacturerURL>
+    <modelName>SwingMusic</modelName>
+    <modelNumber>{version}</modelNumber>
+    <UDN>{udn}</UDN>
+    <dlna:X_DLNADOC>DMS-1.50</dlna:X_DLNADOC>
+    <serviceList>
+      <service>
+        <serviceType>{content_directory}</serviceType>
+        <serviceId>urn:upnp-org:serviceId:ContentDirectory</serviceId>
+        <SCPDURL>/dlna/ContentDirectory.xml</SCPDURL>
+        <controlURL>/dlna/control/ContentDirectory</controlURL>
+        <eventSubURL>/dlna/event/ContentDirectory</eventSubURL>
+      </service>
+      <service>
+        <serviceType>{connection_manager}</serviceType>
+        <serviceId>urn:upnp-org:serviceId:ConnectionManager</serviceId>
+        <SCPDURL>/dlna/ConnectionManager.xml</SCPDURL>
+        <controlURL>/dlna/control/ConnectionManager</controlURL>
+        <eventSubURL>/dlna/event/ConnectionManager</eventSubURL>
+      </service>
+    </serviceList>
+  </device>
+</root>"#,
+            device_type = DEVICE_TYPE,
+            name = xml_escape(&self.friendly_name),
+            version = env!("CARGO_PKG_VERSION"),
+            udn = self.udn,
+            content_directory = CONTENT_DIRECTORY,
+            connection_manager = CONNECTION_MANAGER,
+        )
+    }
+
+    /// Notification and search targets with their unique service names
+    fn targets(&self) -> Vec<(String, String)> {
+        let mut targets = vec![
+            (
+                "upnp:rootdevice".to_string(),
+                format!("{}::upnp:rootdevice", self.udn),
+            ),
+            (self.udn.clone(), self.udn.clone()),
+        ];
+        for nt in [DEVICE_TYPE, CONTENT_DIRECTORY, CONNECTION_MANAGER] {
+            targets.push((nt.to_string(), format!("{}::{}", self.udn, nt)));
+        }
+        targets
+    }
+}
+
+/// Current ContentDirectory SystemUpdateID
+pub fn system_update_id() -> u32 {
+    SYSTEM_UPDATE_ID.load(Ordering::Relaxed)
+}
+
+/// Tell DLNA clients the library changed
+pub fn notify_library_changed() {
+    SYSTEM_UPDATE_ID.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Start announcing the server and answering SSDP searches
+///
+/// runs until the process exits, the web server on `port` serves the
+/// description and control endpoints.
+pub async fn start_dlna(port: u16) -> Result<()> {
+    let config = UserConfig::load()?;
+    let ip: Ipv4Addr = get_local_ip()
+        .and_then(|ip| ip.parse().ok())
+        .ok_or_else(|| anyhow!("no IPv4 network interface to announce DLNA on"))?;
+
+    let server = DlnaServer {
+        udn: format!("uuid:{}", device_uuid(&config.server_id)),
+        friendly_name: config.dlna_name.clone(),
+        base_url: format!("http://{}:{}", ip, port),
+    };
+    if SERVER.get().is_some() {
+        return Ok(());
+    }
+
+    let socket = UdpSocket::from_std(ssdp_socket(ip)?)?;
+    let server = SERVER.get_or_init(|| server);
+    info!(
+        "DLNA server \"{}\" announced at {}",
+        server.friendly_name,
+        server.location()
+    );
+
+    let multicast = SocketAddr::V4(SocketAddrV4::new(SSDP_ADDR, SSDP_PORT));
+    let mut notify = tokio::time::interval(NOTIFY_INTERVAL);
+    let mut buf = [0u8; 2048];
+
+    loop {
+        tokio::select! {
+            _ = notify.tick() => {
+                for (nt, usn) in server.targets() {
+                    let message = notify_message(server, &nt, &usn);
+                    if let Err(e) = socket.send_to(message.as_bytes(), multicast).await {
+                        warn!("SSDP notify failed: {}", e);
+                    }
+                }
+            }
+            received = socket.recv_from(&mut buf) => {
+                let (len, from) = match received {
+                    Ok(r) => r,
+                    Err(e) => {
+                        warn!("SSDP receive failed: {}", e);
+                        continue;
+                    }
+                };
+                let Some(st) = parse_search_target(&String::from_utf8_lossy(&buf[..len])) else {
+                    continue;
+                };
+
+                for (nt, usn) in server.targets() {
+                    if st == "ssdp:all" || st == nt {
+                        debug!("SSDP search for {} from {}", st, from);
+                        let response = search_response(server, &nt, &usn);
+                        if let Err(e) = socket.send_to(response.as_bytes(), from).await {
+                            warn!("SSDP response to {} failed: {}", from, e);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Stable device uuid, the server id is already a uuid on most installs
+fn device_uuid(server_id: &str) -> String {
+    match uuid::Uuid::parse_str(server_id) {
+        Ok(id) => id.to_string(),
+        Err(_) => {
+            let hash = crate::utils::hashing::create_hash(&[server_id, "dlna"], false);
+            let mut bytes = [0u8; 16];
+            for (i, b) in hash.bytes().cycle().take(16).enumerate() {
+                bytes[i] = b;
+            }
+            uuid::Builder::from_random_bytes(bytes)
+                .into_uuid()
+                .to_string()
+        }
+    }
+}
+
+/// Multicast socket shared with other SSDP services on the host
+fn ssdp_socket(interface: Ipv4Addr) -> Result<std::net::UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, SSDP_PORT).into())?;
+    socket.join_multicast_v4(&SSDP_ADDR, &interface)?;
+    socket.set_multicast_if_v4(&interface)?;
+    socket.set_multicast_ttl_v4(4)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+fn server_header() -> String {
+    format!(
+        "{}/1.0 UPnP/1.0 SwingMusic/{}",
+        std::env::consts::OS,
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+fn notify_message(server: &DlnaServer, nt: &str, usn: &str) -> String {
+    format!(
+        "NOTIFY * HTTP/1.1\r\n\
+         HOST: {}:{}\r\n\
+         CACHE-CONTROL: max-age={}\r\n\
+         LOCATION: {}\r\n\
+         NT: {}\r\n\
+         NTS: ssdp:alive\r\n\
+         SERVER: {}\r\n\
+         USN: {}\r\n\r\n",
+        SSDP_ADDR,
+        SSDP_PORT,
+        MAX_AGE,
+        server.location(),
+        nt,
+        server_header(),
+        usn
+    )
+}
+
+fn search_response(server: &DlnaServer, st: &str, usn: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\n\
+         CACHE-CONTROL: max-age={}\r\n\
+         DATE: {}\r\n\
+         EXT:\r\n\
+         LOCATION: {}\r\n\
+         SERVER: {}\r\n\
+         ST: {}\r\n\
+         USN: {}\r\n\r\n",
+        MAX_AGE,
+        chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT"),
+        server.location(),
+        server_header(),
+        st,
+        usn
+    )
+}
+
+/// Search target of an M-SEARCH request
+fn parse_search_target(message: &str) -> Option<String> {
+    let mut lines = message.lines();
+    if !lines.next()?.starts_with("M-SEARCH") {
+        return None;
+    }
+    lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("st")
+            .then(|| value.trim().to_string())
+    })
+}
+
+/// What a Browse request asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrowseFlag {
+    Metadata,
+    DirectChildren,
+}
+
+impl BrowseFlag {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "BrowseMetadata" => Some(Self::Metadata),
+            "BrowseDirectChildren" => Some(Self::DirectChildren),
+            _ => None,
+        }
+    }
+}
+
+/// DIDL-Lite listing returned by a Browse request
+#[derive(Debug, Clone)]
+pub struct BrowseResult {
+    pub didl: String,
+    pub number_returned: usize,
+    pub total_matches: usize,
+}
+
+/// Node of the browse tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Root,
+    Albums,
+    Folders,
+    Album(String),
+    Folder(String),
+    Track(String),
+}
+
+impl Node {
+    fn parse(id: &str) -> Option<Self> {
+        match id {
+            ROOT_ID => Some(Self::Root),
+            ALBUMS_ID => Some(Self::Albums),
+            FOLDERS_ID => Some(Self::Folders),
+            _ => {
+                if let Some(hash) = id.strip_prefix(ALBUM_PREFIX) {
+                    Some(Self::Album(hash.to_string()))
+                } else if let Some(path) = id.strip_prefix(FOLDER_PREFIX) {
+                    Some(Self::Folder(path.to_string()))
+                } else {
+                    id.strip_prefix(TRACK_PREFIX)
+                        .map(|hash| Self::Track(hash.to_string()))
+                }
+            }
+        }
+    }
+
+    fn id(&self) -> String {
+        match self {
+            Self::Root => ROOT_ID.to_string(),
+            Self::Albums => ALBUMS_ID.to_string(),
+            Self::Folders => FOLDERS_ID.to_string(),
+            Self::Album(hash) => format!("{}{}", ALBUM_PREFIX, hash),
+            Self::Folder(path) => format!("{}{}", FOLDER_PREFIX, path),
+            Self::Track(hash) => format!("{}{}", TRACK_PREFIX, hash),
+        }
+    }
+}
+
+/// Entry of a DIDL-Lite listing
+enum Entry {
+    Container {
+        id: String,
+        parent: String,
+        title: String,
+        child_count: usize,
+        class: &'static str,
+        art: Option<String>,
+    },
+    Item {
+        track: Box<Track>,
+        parent: String,
+    },
+}
+
+/// Answer a ContentDirectory Browse request
+///
+/// returns `None` when the object does not exist. a `count` of 0 returns all
+/// remaining children.
+pub fn browse(
+    object_id: &str,
+    flag: BrowseFlag,
+    start: usize,
+    count: usize,
+    base_url: &str,
+) -> Option<BrowseResult> {
+    let node = Node::parse(object_id)?;
+
+    let (entries, total_matches) = match flag {
+        BrowseFlag::Metadata => (vec![metadata(&node)?], 1),
+        BrowseFlag::DirectChildren => {
+            let children = children(&node)?;
+            let total = children.len();
+            let take = if count == 0 { usize::MAX } else { count };
+            let page: Vec<Entry> = children.into_iter().skip(start).take(take).collect();
+            (page, total)
+        }
+    };
+
+    let mut didl = String::from(
+        r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">"#,
+    );
+    for entry in &entries {
+        write_entry(&mut didl, entry, base_url);
+    }
+    didl.push_str("</DIDL-Lite>");
+
+    Some(BrowseResult {
+        didl,
+        number_returned: entries.len(),
+        total_matches,
+    })
+}
+
+fn metadata(node: &Node) -> Option<Entry> {
+    let entry = match node {
+        Node::Root => Entry::Container {
+            id: node.id(),
+            parent: "-1".to_string(),
+            title: DlnaServer::get()
+                .map(|s| s.friendly_name.clone())
+                .unwrap_or_else(|| "SwingMusic".to_string()),
+            child_count: 2,
+            class: "object.container",
+            art: None,
+        },
+        Node::Albums => albums_container(),
+        Node::Folders => folders_container(),
+        Node::Album(hash) => album_container(&AlbumStore::get().get_by_hash(hash)?),
+        Node::Folder(path) => folder_container(path)?,
+        Node::Track(hash) => {
+            let track = TrackStore::get().get_by_hash(hash)?;
+            Entry::Item {
+                parent: Node::Album(track.albumhash.clone()).id(),
+                track: Box::new(track),
+            }
+        }
+    };
+    Some(entry)
+}
+
+fn children(node: &Node) -> Option<Vec<Entry>> {
+    let entries = match node {
+        Node::Root => vec![albums_container(), folders_container()],
+        Node::Albums => {
+            let mut albums = AlbumStore::get().get_all();
+            albums.sort_by_cached_key(|a| a.title.to_lowercase());
+            albums.iter().map(album_container).collect()
+        }
+        Node::Folders => FolderStore::get()
+            .get_root_dirs()
+            .iter()
+            .filter_map(|path| folder_container(path))
+            .collect(),
+        Node::Album(hash) => {
+            if !AlbumStore::get().exists(hash) {
+                return None;
+            }
+            let mut tracks = TrackStore::get().get_by_album(hash);
+            tracks.sort_by_key(|t| (t.disc, t.track));
+            track_items(tracks, &node.id())
+        }
+        Node::Folder(path) => {
+            let store = FolderStore::get();
+            if !store.exists(path) && !store.is_root(path) {
+                return None;
+            }
+            let mut folders = store.get_children(path);
+            folders.sort_by_cached_key(|f| f.name.to_lowercase());
+            let mut entries: Vec<Entry> = folders
+                .iter()
+                .filter_map(|f| folder_container(&f.path))
+                .collect();
+
+            let mut tracks = TrackStore::get().get_by_folder(path);
+            tracks.sort_by(|a, b| a.filepath.cmp(&b.filepath));
+            entries.extend(track_items(tracks, &node.id()));
+            entries
+        }
+        Node::Track(_) => Vec::new(),
+    };
+    Some(entries)
+}
+
+fn albums_container() -> Entry {
+    Entry::Container {
+        id: ALBUMS_ID.to_string(),
+        parent: ROOT_ID.to_string(),
+        title: "Albums".to_string(),
+        child_count: AlbumStore::get().count(),
+        class: "object.container",
+        art: None,
+    }
+}
+
+fn folders_container() -> Entry {
+    Entry::Container {
+        id: FOLDERS_ID.to_string(),
+        parent: ROOT_ID.to_string(),
+        title: "Folders".to_string(),
+        child_count: FolderStore::get().get_root_dirs().len(),
+        class: "object.container",
+        art: None,
+    }
+}
+
+fn album_container(album: &crate::models::Album) -> Entry {
+    Entry::Container {
+        id: Node::Album(album.albumhash.clone()).id(),
+        parent: ALBUMS_ID.to_string(),
+        title: album.title.clone(),
+        child_count: album.trackcount.max(0) as usize,
+        class: "object.container.album.musicAlbum",
+        art: Some(format!("{}.webp", album.albumhash)),
+    }
+}
+
+fn folder_container(path: &str) -> Option<Entry> {
+    let store = FolderStore::get();
+    let is_root = store.is_root(path);
+    if !is_root && !store.exists(path) {
+        return None;
+    }
+
+    let parent = if is_root {
+        FOLDERS_ID.to_string()
+    } else {
+        Path::new(path)
+            .parent()
+            .map(|p| Node::Folder(p.to_string_lossy().to_string()).id())
+            .unwrap_or_else(|| FOLDERS_ID.to_string())
+    };
+    let title = Path::new(path)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string());
+
+    Some(Entry::Container {
+        id: Node::Folder(path.to_string()).id(),
+        parent,
+        title,
+        child_count: store.get_children(path).len() + TrackStore::get().get_by_folder(path).len(),
+        class: "object.container.storageFolder",
+        art: None,
+    })
+}
+
+fn track_items(tracks: Vec<Track>, parent: &str) -> Vec<Entry> {
+    tracks
+        .into_iter()
+        .map(|track| Entry::Item {
+            track: Box::new(track),
+            parent: parent.to_string(),
+        })
+        .collect()
+}
+
+fn write_entry(didl: &mut String, entry: &Entry, base_url: &str) {
+    match entry {
+        Entry::Container {
+            id,
+            parent,
+            title,
+            child_count,
+            class,
+            art,
+        } => {
+            didl.push_str(&format!(
+                r#"<container id="{}" parentID="{}" childCount="{}" restricted="1" searchable="0"><dc:title>{}</dc:title><upnp:class>{}</upnp:class>"#,
+                xml_escape(id),
+                xml_escape(parent),
+                child_count,
+                xml_escape(title),
+                class
+            ));
+            if let Some(image) = art {
+                didl.push_str(&format!(
+                    "<upnp:albumArtURI>{}/img/thumbnail/medium/{}</upnp:albumArtURI>",
+                    base_url,
+                    xml_escape(image)
+                ));
+            }
+            didl.push_str("</container>");
+        }
+        Entry::Item { track, parent } => {
+            let artists = track
+                .artists
+                .iter()
+                .map(|a| a.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            didl.push_str(&format!(
+                r#"<item id="{}" parentID="{}" restricted="1"><dc:title>{}</dc:title><dc:creator>{}</dc:creator><upnp:artist>{}</upnp:artist><upnp:album>{}</upnp:album>"#,
+                xml_escape(&Node::Track(track.trackhash.clone()).id()),
+                xml_escape(parent),
+                xml_escape(&track.title),
+                xml_escape(&artists),
+                xml_escape(&artists),
+                xml_escape(&track.album)
+            ));
+            if let Some(genre) = track.genres.first() {
+                didl.push_str(&format!(
+                    "<upnp:genre>{}</upnp:genre>",
+                    xml_escape(&genre.name)
+                ));
+            }
+            if track.track > 0 {
+                didl.push_str(&format!(
+                    "<upnp:originalTrackNumber>{}</upnp:originalTrackNumber>",
+                    track.track
+                ));
+            }
+            if !track.image.is_empty() {
+                didl.push_str(&format!(
+                    "<upnp:albumArtURI>{}/img/thumbnail/medium/{}</upnp:albumArtURI>",
+                    base_url,
+                    xml_escape(&track.image)
+                ));
+            }
+            didl.push_str("<upnp:class>object.item.audioItem.musicTrack</upnp:class>");
+
+            // formats browsers cannot play are transcoded by the stream endpoint
+            let ext = Path::new(&track.filepath)
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or("");
+            let (mime, size) = if AudioFormat::is_browser_compatible(ext) {
+                let size = std::fs::metadata(&track.filepath).ok().map(|m| m.len());
+                (AudioFormat::mime_type_for_extension(ext), size)
+            } else {
+                (AudioFormat::default_transcode_target().mime_type(), None)
+            };
+
+            didl.push_str(&format!(
+                r#"<res protocolInfo="http-get:*:{}:*" duration="{}""#,
+                mime,
+                format_duration(track.duration)
+            ));
+            if let Some(size) = size {
+                didl.push_str(&format!(r#" size="{}""#, size));
+            }
+            didl.push_str(&format!(
+                ">{}/stream/{}</res></item>",
+                base_url,
+                xml_escape(&track.trackhash)
+            ));
+        }
+    }
+}
+
+/// Duration as "H:MM:SS.000"
+fn format_duration(seconds: i32) -> String {
+    let seconds = seconds.max(0);
+    format!(
+        "{}:{:02}:{:02}.000",
+        seconds / 3600,
+        (seconds % 3600) / 60,
+        seconds % 60
+    )
+}
+
+/// Escape text for XML content and attributes
+pub fn xml_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Reverse of [`xml_escape`]
+pub fn xml_unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_ids_roundtrip() {
+        for node in [
+            Node::Root,
+            Node::Albums,
+            Node::Folders,
+            Node::Album("abc".to_string()),
+            Node::Folder("/music/a & b".to_string()),
+            Node::Track("def".to_string()),
+        ] {
+            assert_eq!(Node::parse(&node.id()), Some(node));
+        }
+        assert_eq!(Node::parse("unknown"), None);
+    }
+
+    #[test]
+    fn test_parse_search_target() {
+        let search = "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nst: ssdp:all\r\nMX: 2\r\n\r\n";
+        assert_eq!(parse_search_target(search).as_deref(), Some("ssdp:all"));
+
+        let notify = "NOTIFY * HTTP/1.1\r\nNT: upnp:rootdevice\r\n\r\n";
+        assert_eq!(parse_search_target(notify), None);
+    }
+
+    #[test]
+    fn test_format_duration_and_escape() {
+        assert_eq!(format_duration(3725), "1:02:05.000");
+        assert_eq!(format_duration(-3), "0:00:00.000");
+
+        let text = r#"Tom & Jerry's "<Best>""#;
+        assert_eq!(xml_unescape(&xml_escape(text)), text);
+    }
+}
@@ -19,6 +19,7 @@ pub struct AudioMetadata {
     pub duration: f64,
     pub bitrate: i32,
     pub sample_rate: i32,
+    pub bit_depth: i32,
     pub channels: i32,
     pub codec: String,
     pub format: String,
@@ -28,9 +29,22 @@ pub struct AudioMetadata {
     pub album_artist: Option<String>,
     pub track: Option<i32>,
     pub disc: Option<i32>,
+    pub track_total: Option<i32>,
+    pub disc_total: Option<i32>,
     pub date: Option<String>,
     pub genre: Option<String>,
     pub copyright: Option<String>,
+    pub label: Option<String>,
+    pub catalog_number: Option<String>,
+    /// raw musicbrainz artist and album artist id tags
+    pub artist_mbids: Vec<String>,
+    pub lyrics: Option<String>,
+    /// raw rating tag, its scale depends on the tagger that wrote it
+    pub rating: Option<String>,
+    pub bpm: Option<String>,
+    pub initial_key: Option<String>,
+    /// raw replaygain track gain, e.g. "-7.32 dB"
+    pub replaygain_track_gain: Option<String>,
 }
 
 /// ffprobe json output format structure
@@ -55,6 +69,8 @@ struct FfprobeStream {
     sample_rate: Option<String>,
     channels: Option<i32>,
     bit_rate: Option<String>,
+    bits_per_raw_sample: Option<String>,
+    bits_per_sample: Option<i32>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -86,6 +102,43 @@ struct FfprobeTags {
     genre_upper: Option<String>,
     #[serde(alias = "COPYRIGHT")]
     copyright_upper: Option<String>,
+    label: Option<String>,
+    #[serde(alias = "LABEL")]
+    label_upper: Option<String>,
+    publisher: Option<String>,
+    #[serde(alias = "PUBLISHER")]
+    publisher_upper: Option<String>,
+    catalognumber: Option<String>,
+    #[serde(alias = "CATALOGNUMBER", alias = "CATALOG_NUMBER")]
+    catalognumber_upper: Option<String>,
+    #[serde(alias = "MUSICBRAINZ_ARTISTID", alias = "MusicBrainz Artist Id")]
+    musicbrainz_artistid: Option<String>,
+    #[serde(alias = "MUSICBRAINZ_ALBUMARTISTID", alias = "MusicBrainz Album Artist Id")]
+    musicbrainz_albumartistid: Option<String>,
+    // id3 USLT frames come through with their language appended
+    #[serde(
+        alias = "LYRICS",
+        alias = "UNSYNCEDLYRICS",
+        alias = "lyrics-eng",
+        alias = "lyrics-XXX"
+    )]
+    lyrics: Option<String>,
+    rating: Option<String>,
+    #[serde(alias = "RATING")]
+    rating_upper: Option<String>,
+    bpm: Option<String>,
+    #[serde(alias = "BPM")]
+    bpm_upper: Option<String>,
+    #[serde(alias = "TBPM")]
+    tbpm: Option<String>,
+    initialkey: Option<String>,
+    #[serde(alias = "INITIALKEY")]
+    initialkey_upper: Option<String>,
+    #[serde(alias = "TKEY")]
+    tkey: Option<String>,
+    replaygain_track_gain: Option<String>,
+    #[serde(alias = "REPLAYGAIN_TRACK_GAIN")]
+    replaygain_track_gain_upper: Option<String>,
 }
 
 /// ensures ffmpeg and ffprobe are available, downloading if necessary
@@ -167,12 +220,36 @@ pub fn probe_metadata(path: &Path) -> Result<AudioMetadata> {
             metadata.genre = tags.genre.clone().or_else(|| tags.genre_upper.clone());
             metadata.copyright = tags.copyright.clone().or_else(|| tags.copyright_upper.clone());
             metadata.date = tags.date.clone().or_else(|| tags.date_upper.clone());
+            metadata.label = tags.label.clone()
+                .or_else(|| tags.label_upper.clone())
+                .or_else(|| tags.publisher.clone())
+                .or_else(|| tags.publisher_upper.clone());
+            metadata.catalog_number = tags.catalognumber.clone()
+                .or_else(|| tags.catalognumber_upper.clone());
+            metadata.artist_mbids = [&tags.musicbrainz_artistid, &tags.musicbrainz_albumartistid]
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect();
+            metadata.lyrics = tags.lyrics.clone().filter(|l| !l.trim().is_empty());
+            metadata.rating = tags.rating.clone()
+                .or_else(|| tags.rating_upper.clone());
+            metadata.bpm = tags.bpm.clone()
+                .or_else(|| tags.bpm_upper.clone())
+                .or_else(|| tags.tbpm.clone());
+            metadata.initial_key = tags.initialkey.clone()
+                .or_else(|| tags.initialkey_upper.clone())
+                .or_else(|| tags.tkey.clone());
+            metadata.replaygain_track_gain = tags.replaygain_track_gain.clone()
+                .or_else(|| tags.replaygain_track_gain_upper.clone());
             
             // parse track number (might be "1/12" format)
             let track_str = tags.track.clone().or_else(|| tags.track_upper.clone());
             if let Some(t) = track_str {
                 metadata.track = t.split('/').next()
                     .and_then(|s| s.trim().parse().ok());
+                metadata.track_total = t.split('/').nth(1)
+                    .and_then(|s| s.trim().parse().ok());
             }
             
             // parse disc number (might be "1/2" format)
@@ -180,6 +257,8 @@ pub fn probe_metadata(path: &Path) -> Result<AudioMetadata> {
             if let Some(d) = disc_str {
                 metadata.disc = d.split('/').next()
                     .and_then(|s| s.trim().parse().ok());
+                metadata.disc_total = d.split('/').nth(1)
+                    .and_then(|s| s.trim().parse().ok());
             }
         }
     }
@@ -197,6 +276,13 @@ pub fn probe_metadata(path: &Path) -> Result<AudioMetadata> {
                 if let Some(channels) = stream.channels {
                     metadata.channels = channels;
                 }
+                // lossy codecs report 0 here since they have no fixed depth
+                metadata.bit_depth = stream
+                    .bits_per_raw_sample
+                    .as_deref()
+                    .and_then(|b| b.parse().ok())
+                    .or(stream.bits_per_sample)
+                    .unwrap_or(0);
                 // stream bitrate might be more accurate than format bitrate
                 if metadata.bitrate == 0 {
                     if let Some(bitrate) = &stream.bit_rate {
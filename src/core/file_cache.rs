@@ -5,14 +5,17 @@
 //! - file metadata (etags, modification times)
 //! - filepath resolution (trackhash -> filepath mapping)
 //! - memory-mapped file regions for small files
+//! - chunked file bodies tuned by the stream settings
 
+use bytes::Bytes;
 use dashmap::DashMap;
+use futures::stream::{self, BoxStream, StreamExt};
 use lru::LruCache;
 use memmap2::Mmap;
-use once_cell::sync::OnceCell;
-use parking_lot::Mutex;
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::{Mutex, RwLock};
 use std::fs::{File, Metadata};
-use std::io;
+use std::io::{self, Read, Seek, SeekFrom};
 use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
@@ -33,6 +36,55 @@ const METADATA_CACHE_SIZE: usize = 1000;
 // global cache instance
 static FILE_CACHE: OnceCell<Arc<FileCache>> = OnceCell::new();
 
+// stream settings, loaded from the config on first use
+static STREAM_TUNING: Lazy<RwLock<Option<StreamTuning>>> = Lazy::new(|| RwLock::new(None));
+
+/// smallest stream chunk in KiB
+pub const MIN_STREAM_CHUNK_KIB: u32 = 16;
+
+/// largest stream chunk in KiB
+pub const MAX_STREAM_CHUNK_KIB: u32 = 8192;
+
+/// how file bodies are read while streaming
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamTuning {
+    /// bytes handed to the connection at a time
+    pub chunk_size: usize,
+    /// send slices of a memory map instead of copying reads into buffers
+    pub zero_copy: bool,
+    /// let a reverse proxy sending x-sendfile-type deliver the file itself
+    pub sendfile: bool,
+}
+
+impl StreamTuning {
+    pub fn from_config(config: &UserConfig) -> Self {
+        let kib = config
+            .stream_chunk_size
+            .clamp(MIN_STREAM_CHUNK_KIB, MAX_STREAM_CHUNK_KIB);
+        Self {
+            chunk_size: kib as usize * 1024,
+            zero_copy: config.stream_zero_copy,
+            sendfile: config.stream_sendfile,
+        }
+    }
+}
+
+/// current stream settings
+pub fn stream_tuning() -> StreamTuning {
+    if let Some(tuning) = *STREAM_TUNING.read() {
+        return tuning;
+    }
+
+    let tuning = StreamTuning::from_config(&UserConfig::load().unwrap_or_default());
+    *STREAM_TUNING.write() = Some(tuning);
+    tuning
+}
+
+/// apply changed stream settings without a restart
+pub fn set_stream_tuning(config: &UserConfig) {
+    *STREAM_TUNING.write() = Some(StreamTuning::from_config(config));
+}
+
 /// cached file metadata for etag generation and conditional requests
 #[derive(Clone, Debug)]
 pub struct CachedFileMetadata {
@@ -348,6 +400,81 @@ fn parse_http_date(date_str: &str) -> Result<u64, ()> {
     Err(())
 }
 
+/// hands a shared memory map to `Bytes` without copying it
+struct MappedRegion(Arc<MmapRegion>);
+
+impl AsRef<[u8]> for MappedRegion {
+    fn as_ref(&self) -> &[u8] {
+        &self.0.mmap
+    }
+}
+
+/// bytes backed by a memory-mapped region, slices of it share the mapping
+pub fn region_bytes(region: Arc<MmapRegion>) -> Bytes {
+    Bytes::from_owner(MappedRegion(region))
+}
+
+/// map a whole file, small files come from the mmap cache
+fn map_file(path: &Path) -> io::Result<Bytes> {
+    if let Some(region) = FileCache::get().map(|c| c.get_mmap(path)).transpose()?.flatten() {
+        return Ok(region_bytes(region));
+    }
+
+    let file = File::open(path)?;
+    let metadata = CachedFileMetadata::from_metadata(&file.metadata()?)?;
+    // the mapping is read only and dropped with the last chunk referencing it
+    let mmap = unsafe { Mmap::map(&file)? };
+    Ok(region_bytes(Arc::new(MmapRegion { mmap, metadata })))
+}
+
+/// stream `length` bytes of a file starting at `start`
+///
+/// zero copy serving hands out slices of a memory map, otherwise each chunk
+/// is read into its own buffer on the blocking pool
+pub fn file_stream(
+    path: &Path,
+    start: u64,
+    length: u64,
+    tuning: StreamTuning,
+) -> io::Result<BoxStream<'static, io::Result<Bytes>>> {
+    let chunk_size = tuning.chunk_size.max(1);
+
+    if tuning.zero_copy {
+        match map_file(path) {
+            Ok(bytes) => {
+                let start = (start as usize).min(bytes.len());
+                let end = start.saturating_add(length as usize).min(bytes.len());
+                let chunks = (start..end)
+                    .step_by(chunk_size)
+                    .map(move |offset| Ok(bytes.slice(offset..(offset + chunk_size).min(end))));
+                return Ok(stream::iter(chunks).boxed());
+            }
+            Err(e) => tracing::debug!("mmap of {} failed, reading instead: {}", path.display(), e),
+        }
+    }
+
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+
+    let body = stream::try_unfold((file, length), move |(mut file, remaining)| async move {
+        if remaining == 0 {
+            return Ok(None);
+        }
+
+        let size = remaining.min(chunk_size as u64) as usize;
+        let (file, buffer) = tokio::task::spawn_blocking(move || {
+            let mut buffer = vec![0u8; size];
+            file.read_exact(&mut buffer)?;
+            Ok::<_, io::Error>((file, buffer))
+        })
+        .await
+        .map_err(io::Error::other)??;
+
+        Ok(Some((Bytes::from(buffer), (file, remaining - size as u64))))
+    });
+    Ok(body.boxed())
+}
+
 /// initialize the file cache (call during startup)
 pub async fn init_file_cache() -> anyhow::Result<()> {
     FileCache::init()?;
@@ -373,3 +500,35 @@ pub async fn init_file_cache() -> anyhow::Result<()> {
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_file_stream_range() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        file.write_all(&data).unwrap();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        for zero_copy in [true, false] {
+            let tuning = StreamTuning {
+                chunk_size: 1024,
+                zero_copy,
+                sendfile: false,
+            };
+            let chunks: Vec<Bytes> = runtime.block_on(async {
+                file_stream(file.path(), 1500, 4000, tuning)
+                    .unwrap()
+                    .map(Result::unwrap)
+                    .collect()
+                    .await
+            });
+
+            assert!(chunks.iter().all(|c| c.len() <= 1024));
+            assert_eq!(chunks.concat(), &data[1500..5500]);
+        }
+    }
+}
@@ -0,0 +1,524 @@
+//! Chromaprint audio fingerprints
+//!
+//! when enabled, fingerprints of new and changed files are computed with fpcalc
+//! after library scans and kept per file. the musicbrainz plugin sends them to
+//! acoustid instead of running fpcalc again, and comparing them finds the same
+//! recording stored more than once whatever its tags or encoding.
+
+use anyhow::{anyhow, bail, Context, Result};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::UserConfig;
+use crate::core::single_flight::SingleFlight;
+use crate::db::tables::{FingerprintTable, StoredFingerprint};
+use crate::stores::TrackStore;
+
+/// Files fingerprinted between database writes
+const FINGERPRINT_BATCH: usize = 32;
+
+/// Largest duration difference in seconds between two copies of a recording
+const DURATION_WINDOW: f64 = 2.0;
+
+/// Items two fingerprints are shifted against each other when compared
+const MAX_OFFSET: usize = 8;
+
+/// Fewest overlapping items for a comparison to count
+const MIN_OVERLAP: usize = 20;
+
+/// Similarity above which two fingerprints are taken for the same recording
+pub const DEFAULT_DUPLICATE_SIMILARITY: f64 = 0.85;
+
+static PASSES: SingleFlight = SingleFlight::new();
+
+/// Output of `fpcalc -json`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Fingerprint {
+    pub duration: f64,
+    pub fingerprint: String,
+}
+
+/// A file found in a group of duplicates
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateTrack {
+    pub trackhash: String,
+    pub filepath: String,
+    pub duration: f64,
+}
+
+/// Compute the fingerprint of a file with fpcalc
+pub fn compute(fpcalc: &Path, path: &Path) -> Result<Fingerprint> {
+    let output = Command::new(fpcalc)
+        .arg("-json")
+        .arg(path)
+        .output()
+        .with_context(|| format!("failed to run {}", fpcalc.display()))?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "fpcalc failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+/// Check an fpcalc path before it is saved, the pass runs whatever it names
+///
+/// the bare name is looked up on `PATH`, anything else has to be an absolute
+/// path to an executable file called fpcalc
+pub fn validate_fpcalc(path: &str) -> Result<()> {
+    if path == "fpcalc" {
+        return Ok(());
+    }
+    let path = Path::new(path);
+    if !path.is_absolute() {
+        bail!("fpcalc path must be absolute");
+    }
+    if path.file_stem().and_then(|s| s.to_str()) != Some("fpcalc") {
+        bail!("fpcalc path must point to a file named fpcalc");
+    }
+    let metadata = std::fs::metadata(path).context("fpcalc not found")?;
+    if !metadata.is_file() || !is_executable(&metadata) {
+        bail!("fpcalc path is not an executable file");
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &std::fs::Metadata) -> bool {
+    true
+}
+
+/// Whether a fingerprint pass is running
+pub fn is_running() -> bool {
+    PASSES.is_running()
+}
+
+/// Stored fingerprint of a file, if it has a usable one
+pub async fn stored(filepath: &str) -> Option<Fingerprint> {
+    FingerprintTable::get(filepath)
+        .await
+        .ok()
+        .flatten()
+        .map(|fp| Fingerprint {
+            duration: fp.duration,
+            fingerprint: fp.fingerprint,
+        })
+}
+
+/// Run a fingerprint pass in the background
+pub fn spawn_pass() {
+    tokio::spawn(async {
+        match run_pass().await {
+            Ok(0) => {}
+            Ok(count) => tracing::info!("Fingerprinted {} files", count),
+            Err(e) => tracing::warn!("Fingerprint pass failed: {}", e),
+        }
+    });
+}
+
+/// Fingerprint new and changed files and forget removed ones
+///
+/// does nothing unless fingerprinting is enabled, returns the number of files
+/// fingerprinted
+pub async fn run_pass() -> Result<usize> {
+    if !UserConfig::load()?.enable_fingerprinting {
+        return Ok(0);
+    }
+    PASSES
+        .run(|| async { pass(&UserConfig::load()?).await })
+        .await
+}
+
+async fn pass(config: &UserConfig) -> Result<usize> {
+    validate_fpcalc(&config.fpcalc_path)?;
+    let fpcalc = PathBuf::from(&config.fpcalc_path);
+    let check = fpcalc.clone();
+    tokio::task::spawn_blocking(move || Command::new(&check).arg("-version").output())
+        .await?
+        .with_context(|| format!("fpcalc not found at {}", fpcalc.display()))?;
+
+    let tracks = TrackStore::get().get_all();
+    let known = FingerprintTable::last_mods().await?;
+
+    let live: HashSet<&str> = tracks.iter().map(|t| t.filepath.as_str()).collect();
+    let removed: Vec<String> = known
+        .keys()
+        .filter(|path| !live.contains(path.as_str()))
+        .cloned()
+        .collect();
+    FingerprintTable::delete_many(&removed).await?;
+
+    let todo: Vec<(String, String, i64)> = tracks
+        .iter()
+        .filter(|t| known.get(&t.filepath) != Some(&t.last_mod))
+        .map(|t| (t.filepath.clone(), t.trackhash.clone(), t.last_mod))
+        .collect();
+
+    let mut done = 0;
+    for chunk in todo.chunks(FINGERPRINT_BATCH) {
+        let items = chunk.to_vec();
+        let fpcalc = fpcalc.clone();
+        let rows = tokio::task::spawn_blocking(move || {
+            items
+                .into_par_iter()
+                .map(|(filepath, trackhash, last_mod)| {
+                    // a failed file is stored empty so it is only retried once it changes
+                    let fp = compute(&fpcalc, Path::new(&filepath)).unwrap_or_else(|e| {
+                        tracing::debug!("failed to fingerprint {}: {}", filepath, e);
+                        Fingerprint {
+                            duration: 0.0,
+                            fingerprint: String::new(),
+                        }
+                    });
+                    StoredFingerprint {
+                        filepath,
+                        trackhash,
+                        duration: fp.duration,
+                        fingerprint: fp.fingerprint,
+                        last_mod,
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+        .await?;
+
+        FingerprintTable::upsert_many(&rows).await?;
+        done += rows.len();
+    }
+
+    Ok(done)
+}
+
+/// Groups of files holding the same recording, largest group first
+pub async fn duplicates(min_similarity: f64) -> Result<Vec<Vec<DuplicateTrack>>> {
+    let rows = FingerprintTable::all().await?;
+    Ok(tokio::task::spawn_blocking(move || group_duplicates(rows, min_similarity)).await?)
+}
+
+fn group_duplicates(rows: Vec<StoredFingerprint>, min_similarity: f64) -> Vec<Vec<DuplicateTrack>> {
+    let mut items: Vec<(StoredFingerprint, Vec<u32>)> = rows
+        .into_iter()
+        .filter_map(|row| {
+            let raw = decode(&row.fingerprint)?;
+            Some((row, raw))
+        })
+        .collect();
+    items.sort_by(|a, b| a.0.duration.total_cmp(&b.0.duration));
+
+    // union-find over files whose durations are close enough to compare
+    let mut parent: Vec<usize> = (0..items.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+
+    for i in 0..items.len() {
+        for j in i + 1..items.len() {
+            if items[j].0.duration - items[i].0.duration > DURATION_WINDOW {
+                break;
+            }
+            if similarity(&items[i].1, &items[j].1) >= min_similarity {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[a] = b;
+            }
+        }
+    }
+
+    let mut groups: HashMap<usize, Vec<DuplicateTrack>> = HashMap::new();
+    for (i, (row, _)) in items.iter().enumerate() {
+        let group = root(&mut parent, i);
+        groups.entry(group).or_default().push(DuplicateTrack {
+            trackhash: row.trackhash.clone(),
+            filepath: row.filepath.clone(),
+            duration: row.duration,
+        });
+    }
+
+    let mut groups: Vec<Vec<DuplicateTrack>> =
+        groups.into_values().filter(|g| g.len() > 1).collect();
+    for group in &mut groups {
+        group.sort_by(|a, b| a.filepath.cmp(&b.filepath));
+    }
+    groups.sort_by(|a, b| {
+        b.len()
+            .cmp(&a.len())
+            .then_with(|| a[0].filepath.cmp(&b[0].filepath))
+    });
+    groups
+}
+
+/// Share of equal bits between two raw fingerprints at their best alignment
+pub fn similarity(a: &[u32], b: &[u32]) -> f64 {
+    let mut best = 0.0f64;
+    for offset in 0..=MAX_OFFSET {
+        for (x, y) in [(a, b), (b, a)] {
+            if offset >= x.len() {
+                continue;
+            }
+            let x = &x[offset..];
+            let overlap = x.len().min(y.len());
+            if overlap < MIN_OVERLAP {
+                continue;
+            }
+            let differing: u32 = x.iter().zip(y).map(|(p, q)| (p ^ q).count_ones()).sum();
+            best = best.max(1.0 - differing as f64 / (32 * overlap) as f64);
+        }
+    }
+    best
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// decode chromaprint's url safe base64 without padding
+fn decode_base64(input: &str) -> Option<Vec<u8>> {
+    let mut lookup = [u8::MAX; 256];
+    for (i, c) in BASE64_ALPHABET.iter().enumerate() {
+        lookup[*c as usize] = i as u8;
+    }
+
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in input.bytes() {
+        let value = lookup[c as usize];
+        if value == u8::MAX {
+            return None;
+        }
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// little endian bit reader used by the compressed format
+struct BitReader<'a> {
+    data: &'a [u8],
+    bit: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, width: usize) -> Option<u32> {
+        let mut value = 0;
+        for i in 0..width {
+            let byte = *self.data.get(self.bit / 8)?;
+            value |= (((byte >> (self.bit % 8)) & 1) as u32) << i;
+            self.bit += 1;
+        }
+        Some(value)
+    }
+}
+
+/// Decompress a fingerprint from `fpcalc` into its raw items
+pub fn decode(fingerprint: &str) -> Option<Vec<u32>> {
+    const NORMAL_BITS: usize = 3;
+    const EXCEPTION_BITS: usize = 5;
+    const MAX_NORMAL: u32 = 7;
+
+    let data = decode_base64(fingerprint)?;
+    if data.len() < 4 {
+        return None;
+    }
+    let count = (data[1] as usize) << 16 | (data[2] as usize) << 8 | data[3] as usize;
+    let body = &data[4..];
+
+    // bit positions as deltas, a zero ends an item
+    let mut normal = BitReader { data: body, bit: 0 };
+    let mut values = Vec::new();
+    let mut ended = 0;
+    while ended < count {
+        let value = normal.read(NORMAL_BITS)?;
+        if value == 0 {
+            ended += 1;
+        }
+        values.push(value);
+    }
+
+    // deltas of 7 or more continue in a second stream after the first
+    let start = (values.len() * NORMAL_BITS).div_ceil(8);
+    let mut exceptions = BitReader {
+        data: body.get(start..)?,
+        bit: 0,
+    };
+    for value in values.iter_mut().filter(|v| **v == MAX_NORMAL) {
+        *value += exceptions.read(EXCEPTION_BITS)?;
+    }
+
+    let mut raw = Vec::with_capacity(count);
+    let mut item = 0u32;
+    let mut bit = 0;
+    for value in values {
+        if value == 0 {
+            let previous = raw.last().copied().unwrap_or(0);
+            raw.push(item ^ previous);
+            item = 0;
+            bit = 0;
+            continue;
+        }
+        bit += value;
+        if bit > 32 {
+            return None;
+        }
+        item |= 1 << (bit - 1);
+    }
+    Some(raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// mirror of chromaprint's compressor
+    fn encode(raw: &[u32]) -> String {
+        let mut values = Vec::new();
+        let mut previous = 0;
+        for &item in raw {
+            let mut x = item ^ previous;
+            previous = item;
+            let (mut bit, mut last) = (1, 0);
+            while x != 0 {
+                if x & 1 == 1 {
+                    values.push(bit - last);
+                    last = bit;
+                }
+                x >>= 1;
+                bit += 1;
+            }
+            values.push(0);
+        }
+
+        let mut bits: Vec<bool> = Vec::new();
+        let push = |bits: &mut Vec<bool>, v: u32, width: usize| {
+            bits.extend((0..width).map(|i| (v >> i) & 1 == 1));
+        };
+        for v in &values {
+            push(&mut bits, (*v).min(7), 3);
+        }
+        bits.resize(bits.len().div_ceil(8) * 8, false);
+        for v in values.iter().filter(|v| **v >= 7) {
+            push(&mut bits, v - 7, 5);
+        }
+
+        let mut bytes = vec![
+            1,
+            (raw.len() >> 16) as u8,
+            (raw.len() >> 8) as u8,
+            raw.len() as u8,
+        ];
+        bytes.extend(bits.chunks(8).map(|c| {
+            c.iter()
+                .enumerate()
+                .fold(0u8, |b, (i, on)| b | (*on as u8) << i)
+        }));
+
+        let mut out = String::new();
+        for chunk in bytes.chunks(3) {
+            let n = chunk.iter().fold(0u32, |n, b| n << 8 | *b as u32) << (8 * (3 - chunk.len()));
+            for i in 0..=chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            }
+        }
+        out
+    }
+
+    fn sample(seed: u32, len: usize) -> Vec<u32> {
+        let mut x = seed;
+        (0..len)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                x
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_fpcalc() {
+        assert!(validate_fpcalc("fpcalc").is_ok());
+        assert!(validate_fpcalc("bin/fpcalc").is_err());
+        assert!(validate_fpcalc("/bin/sh").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let fpcalc = dir.path().join("fpcalc");
+        std::fs::write(&fpcalc, "#!/bin/sh\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let path = fpcalc.to_str().unwrap();
+            std::fs::set_permissions(&fpcalc, std::fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(validate_fpcalc(path).is_err());
+            std::fs::set_permissions(&fpcalc, std::fs::Permissions::from_mode(0o755)).unwrap();
+            assert!(validate_fpcalc(path).is_ok());
+        }
+        assert!(validate_fpcalc(dir.path().join("missing/fpcalc").to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_decode_round_trips_compressed_fingerprints() {
+        let raw = sample(7, 300);
+        assert_eq!(decode(&encode(&raw)), Some(raw));
+        assert_eq!(
+            decode(&encode(&[0, u32::MAX, 1 << 31])),
+            Some(vec![0, u32::MAX, 1 << 31])
+        );
+        assert_eq!(decode("!!"), None);
+        assert_eq!(decode(""), None);
+    }
+
+    #[test]
+    fn test_similarity_finds_shifted_copies() {
+        let original = sample(11, 200);
+        let mut copy = original[3..].to_vec();
+        // a lossy encode flips a few bits
+        for item in copy.iter_mut().step_by(5) {
+            *item ^= 0b101;
+        }
+
+        assert!(similarity(&original, &copy) > 0.95);
+        assert!(similarity(&original, &sample(12, 200)) < 0.6);
+    }
+
+    #[test]
+    fn test_group_duplicates_by_duration_and_similarity() {
+        let row = |path: &str, duration: f64, raw: &[u32]| StoredFingerprint {
+            filepath: path.to_string(),
+            trackhash: path.to_string(),
+            duration,
+            fingerprint: encode(raw),
+            last_mod: 0,
+        };
+        let song = sample(1, 120);
+        let rows = vec![
+            row("/a.flac", 180.0, &song),
+            row("/a.mp3", 181.0, &song),
+            row("/b.flac", 180.5, &sample(2, 120)),
+            // same audio but far longer, e.g. a live medley
+            row("/c.flac", 300.0, &song),
+        ];
+
+        let groups = group_duplicates(rows, DEFAULT_DUPLICATE_SIMILARITY);
+        assert_eq!(groups.len(), 1);
+        let paths: Vec<&str> = groups[0].iter().map(|t| t.filepath.as_str()).collect();
+        assert_eq!(paths, ["/a.flac", "/a.mp3"]);
+    }
+}
@@ -0,0 +1,193 @@
+//! Playlists from m3u files in the library
+//!
+//! m3u files a scan finds next to the music are imported as playlists named
+//! after the file and owned by the first admin. the file stays the source of
+//! truth, so every scan brings its playlist in line with it and drops the
+//! playlist once the file is gone.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use crate::core::playlistlib::PlaylistLib;
+use crate::db::tables::{PlaylistTable, UserTable};
+use crate::models::Playlist;
+use crate::stores::TrackStore;
+
+/// Key in the playlist extra holding the m3u file it follows
+pub const SOURCE_KEY: &str = "m3u_file";
+
+/// What a sync changed
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct SyncSummary {
+    pub created: usize,
+    pub updated: usize,
+    pub removed: usize,
+}
+
+/// Import new m3u files, update the playlists of changed ones and remove
+/// playlists whose file under the scanned roots is gone
+pub async fn sync(files: &[PathBuf], roots: &[PathBuf]) -> Result<SyncSummary> {
+    let mut summary = SyncSummary::default();
+    let Some(owner) = UserTable::all()
+        .await?
+        .into_iter()
+        .filter(|u| u.is_admin())
+        .map(|u| u.id)
+        .min()
+    else {
+        return Ok(summary);
+    };
+
+    let mut imported: HashMap<String, Playlist> = PlaylistTable::all(None)
+        .await?
+        .into_iter()
+        .filter_map(|p| {
+            let source = p.extra.get(SOURCE_KEY)?.as_str()?.to_string();
+            Some((source, p))
+        })
+        .collect();
+
+    let store = TrackStore::get();
+    for file in files {
+        let source = file.to_string_lossy().to_string();
+        let existing = imported.remove(&source);
+        let content = match std::fs::read(file) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(e) => {
+                tracing::warn!("Failed to read playlist {}: {}", source, e);
+                continue;
+            }
+        };
+
+        let dir = file.parent().unwrap_or(Path::new(""));
+        let trackhashes: Vec<String> = parse_m3u(&content, dir)
+            .iter()
+            .filter_map(|path| store.get_by_path(&path.to_string_lossy()))
+            .map(|t| t.trackhash)
+            .collect();
+
+        match existing {
+            Some(playlist) if playlist.trackhashes != trackhashes => {
+                PlaylistTable::update_tracks(playlist.id, &serde_json::to_string(&trackhashes)?)
+                    .await?;
+                summary.updated += 1;
+            }
+            Some(_) => {}
+            // files that point at nothing in the library are not music playlists
+            None if trackhashes.is_empty() => {}
+            None => {
+                let name = file
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_else(|| source.clone());
+                let mut playlist = Playlist::new(name, Some(owner));
+                playlist.trackhashes = trackhashes;
+                playlist.extra = serde_json::json!({ SOURCE_KEY: source });
+                PlaylistTable::insert(&playlist).await?;
+                summary.created += 1;
+            }
+        }
+    }
+
+    for (source, playlist) in imported {
+        if roots
+            .iter()
+            .any(|root| Path::new(&source).starts_with(root))
+        {
+            PlaylistLib::delete(playlist.id).await?;
+            summary.removed += 1;
+        }
+    }
+
+    Ok(summary)
+}
+
+/// File paths listed in an m3u file, relative entries are resolved against
+/// the folder of the file and urls other than `file://` are left out
+pub fn parse_m3u(content: &str, dir: &Path) -> Vec<PathBuf> {
+    content
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let entry = match line.strip_prefix("file://") {
+                Some(path) => percent_decode(path),
+                None if line.contains("://") => return None,
+                None => line.to_string(),
+            };
+            // playlists written on windows use backslashes
+            let entry = entry.replace('\\', "/");
+            Some(normalize(&dir.join(entry)))
+        })
+        .collect()
+}
+
+/// Resolve `.` and `..` without touching the disk
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = (bytes[i] == b'%')
+            .then(|| value.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_m3u() {
+        let content = "\u{feff}#EXTM3U\n\
+            #EXTINF:180,Artist - Song\n\
+            01 Song.flac\n\
+            \n\
+            ../Other Album/02 Tune.mp3\r\n\
+            sub\\03 Windows.mp3\n\
+            /music/Abs/04 Path.mp3\n\
+            file:///music/Url/05%20Encoded.mp3\n\
+            https://example.com/stream.mp3\n";
+
+        assert_eq!(
+            parse_m3u(content, Path::new("/music/Album")),
+            vec![
+                PathBuf::from("/music/Album/01 Song.flac"),
+                PathBuf::from("/music/Other Album/02 Tune.mp3"),
+                PathBuf::from("/music/Album/sub/03 Windows.mp3"),
+                PathBuf::from("/music/Abs/04 Path.mp3"),
+                PathBuf::from("/music/Url/05 Encoded.mp3"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,515 @@
+//! Gapless album detection
+//!
+//! when enabled, new and changed files are checked after library scans. a file
+//! counts as encoded for gapless playback when it is lossless or carries its
+//! encoder delay and padding in a lame header or an iTunSMPB tag. such files
+//! then get the silence at their start and end measured with ffmpeg, and two
+//! consecutive tracks of an album whose audio runs across the boundary are
+//! marked so clients know to use strict gapless transitions between them.
+
+use anyhow::{anyhow, Result};
+use lofty::{ItemKey, Probe, TaggedFileExt};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::config::UserConfig;
+use crate::core::ffmpeg;
+use crate::core::single_flight::SingleFlight;
+use crate::db::tables::{GaplessFile, GaplessTable};
+use crate::models::Track;
+use crate::stores::TrackStore;
+
+/// Files analysed between database writes
+const GAPLESS_BATCH: usize = 32;
+
+/// Seconds decoded at each end of a file
+const EDGE_WINDOW: f64 = 1.0;
+
+/// Sample rate the edges are decoded at
+const ANALYSIS_RATE: u32 = 8000;
+
+/// Loudest sample still counted as silence, about -50 dBFS
+const SILENCE_AMPLITUDE: i32 = 104;
+
+/// Most silence in seconds at a track boundary for the audio to run across it
+pub const MAX_EDGE_SILENCE: f64 = 0.1;
+
+/// Codecs that play back without padding around the audio
+const GAPLESS_CODECS: [&str; 10] = [
+    "flac", "alac", "wav", "aiff", "ape", "wavpack", "tta", "opus", "vorbis", "speex",
+];
+
+/// Extensions of gapless formats for files whose codec is unknown
+const GAPLESS_EXTENSIONS: [&str; 12] = [
+    "flac", "wav", "aif", "aiff", "ape", "wv", "tta", "ogg", "oga", "opus", "dsf", "dff",
+];
+
+/// iTunes gapless info with the encoder delay and padding in samples
+const ITUNSMPB: &str = "iTunSMPB";
+
+static PASSES: SingleFlight = SingleFlight::new();
+
+/// trackhashes of tracks whose audio runs into the next track of their album
+static GAPLESS_WITH_NEXT: Lazy<RwLock<HashSet<String>>> = Lazy::new(|| RwLock::new(HashSet::new()));
+
+/// Whether a track runs into the next track of its album without a gap
+pub fn gapless_with_next(trackhash: &str) -> bool {
+    GAPLESS_WITH_NEXT.read().contains(trackhash)
+}
+
+/// Run a gapless pass in the background
+pub fn spawn_pass() {
+    tokio::spawn(async {
+        match run_pass().await {
+            Ok(0) => {}
+            Ok(count) => tracing::info!("Checked {} files for gapless playback", count),
+            Err(e) => tracing::warn!("Gapless pass failed: {}", e),
+        }
+    });
+}
+
+/// Analyse new and changed files, forget removed ones and mark gapless pairs
+///
+/// clears the marks when the analysis is disabled, returns the number of
+/// files analysed
+pub async fn run_pass() -> Result<usize> {
+    if !UserConfig::load()?.enable_gapless_analysis {
+        GAPLESS_WITH_NEXT.write().clear();
+        return Ok(0);
+    }
+    PASSES
+        .run(|| async {
+            let done = pass().await?;
+            refresh().await?;
+            Ok(done)
+        })
+        .await
+}
+
+/// Mark gapless pairs from the stored analysis
+pub async fn refresh() -> Result<()> {
+    let files: HashMap<String, GaplessFile> = GaplessTable::all()
+        .await?
+        .into_iter()
+        .map(|file| (file.filepath.clone(), file))
+        .collect();
+    let tracks = TrackStore::get().get_all();
+
+    let pairs = tokio::task::spawn_blocking(move || gapless_pairs(&tracks, &files)).await?;
+    *GAPLESS_WITH_NEXT.write() = pairs;
+    Ok(())
+}
+
+async fn pass() -> Result<usize> {
+    let ffmpeg_path = ffmpeg::get_ffmpeg_path();
+    if !ffmpeg::is_ffmpeg_available() {
+        return Err(anyhow!("ffmpeg not found at {}", ffmpeg_path.display()));
+    }
+
+    let tracks = TrackStore::get().get_all();
+    let known = GaplessTable::last_mods().await?;
+
+    let live: HashSet<&str> = tracks.iter().map(|t| t.filepath.as_str()).collect();
+    let removed: Vec<String> = known
+        .keys()
+        .filter(|path| !live.contains(path.as_str()))
+        .cloned()
+        .collect();
+    GaplessTable::delete_many(&removed).await?;
+
+    // a track alone on its album has no neighbour to run into
+    let mut album_sizes: HashMap<&str, usize> = HashMap::new();
+    for track in &tracks {
+        *album_sizes.entry(track.albumhash.as_str()).or_default() += 1;
+    }
+
+    let todo: Vec<(String, String, String, i64)> = tracks
+        .iter()
+        .filter(|t| album_sizes.get(t.albumhash.as_str()).copied().unwrap_or(0) > 1)
+        .filter(|t| known.get(&t.filepath) != Some(&t.last_mod))
+        .map(|t| {
+            (
+                t.filepath.clone(),
+                t.trackhash.clone(),
+                t.extra_info().codec,
+                t.last_mod,
+            )
+        })
+        .collect();
+
+    let mut done = 0;
+    for chunk in todo.chunks(GAPLESS_BATCH) {
+        let items = chunk.to_vec();
+        let ffmpeg_path = ffmpeg_path.clone();
+        let rows = tokio::task::spawn_blocking(move || {
+            items
+                .into_par_iter()
+                .map(|(filepath, trackhash, codec, last_mod)| {
+                    analyse(&ffmpeg_path, filepath, trackhash, &codec, last_mod)
+                })
+                .collect::<Vec<_>>()
+        })
+        .await?;
+
+        GaplessTable::upsert_many(&rows).await?;
+        done += rows.len();
+    }
+
+    Ok(done)
+}
+
+/// Check a file for gapless encoding and measure the silence at its ends
+///
+/// files that are not encoded for gapless playback are not decoded, and a file
+/// ffmpeg cannot read is stored unmeasured so it is only retried once it changes
+fn analyse(
+    ffmpeg_path: &Path,
+    filepath: String,
+    trackhash: String,
+    codec: &str,
+    last_mod: i64,
+) -> GaplessFile {
+    let path = Path::new(&filepath);
+    let encoder_gapless = is_encoder_gapless(path, codec);
+
+    let (lead_silence, trail_silence) = if encoder_gapless {
+        let lead = decode_edge(ffmpeg_path, path, false).map(|s| edge_silence(&s).0);
+        let trail = decode_edge(ffmpeg_path, path, true).map(|s| edge_silence(&s).1);
+        match (lead, trail) {
+            (Ok(lead), Ok(trail)) => (lead, trail),
+            (Err(e), _) | (_, Err(e)) => {
+                tracing::debug!("failed to measure silence in {}: {}", filepath, e);
+                (-1.0, -1.0)
+            }
+        }
+    } else {
+        (-1.0, -1.0)
+    };
+
+    GaplessFile {
+        filepath,
+        trackhash,
+        encoder_gapless,
+        lead_silence,
+        trail_silence,
+        last_mod,
+    }
+}
+
+/// Whether a file plays back without encoder padding around its audio
+pub fn is_encoder_gapless(path: &Path, codec: &str) -> bool {
+    let codec = codec.to_lowercase();
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    if GAPLESS_CODECS.contains(&codec.as_str())
+        || codec.starts_with("pcm_")
+        || codec.starts_with("dsd_")
+        || (codec.is_empty() && GAPLESS_EXTENSIONS.contains(&extension.as_str()))
+    {
+        return true;
+    }
+
+    match (codec.as_str(), extension.as_str()) {
+        ("mp3", _) | ("", "mp3") => mp3_encoder_gapless(path),
+        ("aac", _) | ("", "m4a" | "mp4" | "aac") => has_itunsmpb_tag(path),
+        _ => false,
+    }
+}
+
+/// Whether an mp3 records its encoder delay and padding
+///
+/// looks for a lame header in the first frame and an iTunSMPB comment in the
+/// id3v2 tag in front of it
+fn mp3_encoder_gapless(path: &Path) -> bool {
+    let Ok(mut file) = File::open(path) else {
+        return false;
+    };
+
+    let mut header = [0u8; 10];
+    let mut audio_start = 0u64;
+    if file.read_exact(&mut header).is_ok() && &header[..3] == b"ID3" {
+        let size = header[6..10]
+            .iter()
+            .fold(0u64, |size, b| (size << 7) | u64::from(b & 0x7f));
+        let mut tag = Vec::new();
+        if (&mut file).take(size).read_to_end(&mut tag).is_err() {
+            return false;
+        }
+        if contains(&tag, ITUNSMPB.as_bytes()) {
+            return true;
+        }
+        let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+        audio_start = 10 + size + footer;
+    }
+
+    let mut frame = Vec::new();
+    if file.seek(SeekFrom::Start(audio_start)).is_err()
+        || file.take(8192).read_to_end(&mut frame).is_err()
+    {
+        return false;
+    }
+
+    lame_padding(&frame).is_some_and(|(delay, padding)| delay > 0 || padding > 0)
+}
+
+/// Whether an mp4 or aac file carries an iTunSMPB tag
+fn has_itunsmpb_tag(path: &Path) -> bool {
+    let Some(tagged_file) = Probe::open(path).ok().and_then(|p| p.read().ok()) else {
+        return false;
+    };
+    let key = ItemKey::Unknown(format!("----:com.apple.iTunes:{}", ITUNSMPB));
+    tagged_file
+        .tags()
+        .iter()
+        .any(|tag| tag.get_string(&key).is_some_and(|v| !v.trim().is_empty()))
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+/// Encoder delay and padding in samples from the lame header of the first
+/// mpeg audio frame in `data`
+pub fn lame_padding(data: &[u8]) -> Option<(u16, u16)> {
+    let start = data
+        .windows(2)
+        .position(|w| w[0] == 0xff && w[1] & 0xe0 == 0xe0)?;
+    let frame = &data[start..];
+    if frame.len() < 4 {
+        return None;
+    }
+
+    // mpeg 1 has longer side info than mpeg 2 and 2.5, mono shorter than stereo
+    let mpeg1 = (frame[1] >> 3) & 0x03 == 0x03;
+    let mono = (frame[3] >> 6) & 0x03 == 0x03;
+    let side_info = match (mpeg1, mono) {
+        (true, true) => 17,
+        (true, false) => 32,
+        (false, true) => 9,
+        (false, false) => 17,
+    };
+    let crc = if frame[1] & 0x01 == 0 { 2 } else { 0 };
+
+    let xing = 4 + crc + side_info;
+    let tag = frame.get(xing..xing + 8)?;
+    if &tag[..4] != b"Xing" && &tag[..4] != b"Info" {
+        return None;
+    }
+
+    let flags = u32::from_be_bytes([tag[4], tag[5], tag[6], tag[7]]);
+    let mut lame = xing + 8;
+    for (flag, len) in [(0x1, 4), (0x2, 4), (0x4, 100), (0x8, 4)] {
+        if flags & flag != 0 {
+            lame += len;
+        }
+    }
+
+    // a nine byte encoder name, then delay and padding as two 12 bit values
+    let ext = frame.get(lame..lame + 24)?;
+    if !ext[0].is_ascii_alphanumeric() {
+        return None;
+    }
+    let delay = (u16::from(ext[21]) << 4) | (u16::from(ext[22]) >> 4);
+    let padding = (u16::from(ext[22] & 0x0f) << 8) | u16::from(ext[23]);
+    Some((delay, padding))
+}
+
+/// Decode the first or last seconds of a file to mono 16 bit samples
+fn decode_edge(ffmpeg_path: &Path, path: &Path, tail: bool) -> Result<Vec<i16>> {
+    let window = EDGE_WINDOW.to_string();
+    let mut command = Command::new(ffmpeg_path);
+    command.args(["-v", "error", "-nostdin"]);
+    if tail {
+        command.args(["-sseof", &format!("-{}", window)]);
+    }
+    command.arg("-i").arg(path);
+    if !tail {
+        command.args(["-t", &window]);
+    }
+    let output = command
+        .args(["-vn", "-ac", "1", "-ar", &ANALYSIS_RATE.to_string()])
+        .args(["-f", "s16le", "-"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ffmpeg failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(output
+        .stdout
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect())
+}
+
+/// Seconds of silence at the start and at the end of decoded samples
+///
+/// samples that are silent all the way through count as silent at both ends
+pub fn edge_silence(samples: &[i16]) -> (f64, f64) {
+    let loud = |s: &i16| i32::from(*s).abs() > SILENCE_AMPLITUDE;
+    let rate = f64::from(ANALYSIS_RATE);
+
+    match (
+        samples.iter().position(loud),
+        samples.iter().rposition(loud),
+    ) {
+        (Some(first), Some(last)) => (
+            first as f64 / rate,
+            (samples.len() - 1 - last) as f64 / rate,
+        ),
+        _ => {
+            let all = samples.len() as f64 / rate;
+            (all, all)
+        }
+    }
+}
+
+/// Trackhashes of tracks whose audio runs into the next track of their album
+pub fn gapless_pairs(tracks: &[Track], files: &HashMap<String, GaplessFile>) -> HashSet<String> {
+    let mut albums: HashMap<&str, Vec<&Track>> = HashMap::new();
+    for track in tracks.iter().filter(|t| t.track > 0) {
+        albums
+            .entry(track.albumhash.as_str())
+            .or_default()
+            .push(track);
+    }
+
+    let continuous = |file: Option<&GaplessFile>, lead: bool| {
+        file.is_some_and(|f| {
+            let silence = if lead {
+                f.lead_silence
+            } else {
+                f.trail_silence
+            };
+            f.encoder_gapless && (0.0..=MAX_EDGE_SILENCE).contains(&silence)
+        })
+    };
+
+    let mut pairs = HashSet::new();
+    for album in albums.values_mut() {
+        album.sort_by_key(|t| (t.disc, t.track));
+        for pair in album.windows(2) {
+            let (current, next) = (pair[0], pair[1]);
+            let adjacent = (next.disc == current.disc && next.track == current.track + 1)
+                || (next.disc == current.disc + 1 && next.track == 1);
+
+            if adjacent
+                && continuous(files.get(&current.filepath), false)
+                && continuous(files.get(&next.filepath), true)
+            {
+                pairs.insert(current.trackhash.clone());
+            }
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lame_frame(delay: u16, padding: u16) -> Vec<u8> {
+        // mpeg 1 layer 3, no crc, stereo, with a frames field before the lame tag
+        let mut frame = vec![0u8; 200];
+        frame[..4].copy_from_slice(&[0xff, 0xfb, 0x90, 0x00]);
+        frame[36..40].copy_from_slice(b"Info");
+        frame[40..44].copy_from_slice(&1u32.to_be_bytes());
+        let lame = 48;
+        frame[lame..lame + 9].copy_from_slice(b"LAME3.100");
+        frame[lame + 21] = (delay >> 4) as u8;
+        frame[lame + 22] = (((delay & 0x0f) << 4) | (padding >> 8)) as u8;
+        frame[lame + 23] = (padding & 0xff) as u8;
+        frame
+    }
+
+    fn track(hash: &str, disc: i32, number: i32) -> Track {
+        let mut track = Track::new();
+        track.trackhash = hash.to_string();
+        track.filepath = format!("/music/{}.flac", hash);
+        track.albumhash = "album".to_string();
+        track.disc = disc;
+        track.track = number;
+        track
+    }
+
+    fn file(hash: &str, encoder_gapless: bool, lead: f64, trail: f64) -> (String, GaplessFile) {
+        let filepath = format!("/music/{}.flac", hash);
+        let file = GaplessFile {
+            filepath: filepath.clone(),
+            trackhash: hash.to_string(),
+            encoder_gapless,
+            lead_silence: lead,
+            trail_silence: trail,
+            last_mod: 0,
+        };
+        (filepath, file)
+    }
+
+    #[test]
+    fn test_lame_padding() {
+        let mut data = vec![0u8; 3];
+        data.extend(lame_frame(576, 1234));
+        assert_eq!(lame_padding(&data), Some((576, 1234)));
+
+        let mut frame = lame_frame(576, 1234);
+        frame[36..40].copy_from_slice(b"VBRI");
+        assert_eq!(lame_padding(&frame), None);
+        assert_eq!(lame_padding(&[0xff, 0xfb]), None);
+    }
+
+    #[test]
+    fn test_edge_silence() {
+        let mut samples = vec![0i16; 800];
+        samples.extend(vec![5000i16; 1600]);
+        samples.extend(vec![-20i16; 400]);
+        let (lead, trail) = edge_silence(&samples);
+        assert!((lead - 0.1).abs() < 1e-9);
+        assert!((trail - 0.05).abs() < 1e-9);
+
+        assert_eq!(edge_silence(&[0i16; 8000]), (1.0, 1.0));
+    }
+
+    #[test]
+    fn test_gapless_pairs() {
+        let tracks = vec![
+            track("a", 1, 1),
+            track("b", 1, 2),
+            track("c", 1, 3),
+            track("d", 2, 1),
+            track("e", 2, 3),
+        ];
+        let files: HashMap<String, GaplessFile> = [
+            file("a", true, 0.5, 0.0),
+            file("b", true, 0.0, 0.8),
+            file("c", true, 0.0, 0.02),
+            file("d", true, 0.01, 0.0),
+            file("e", true, 0.0, 0.0),
+        ]
+        .into_iter()
+        .collect();
+
+        let pairs = gapless_pairs(&tracks, &files);
+        // b ends in silence, d and e are not consecutive
+        let expected: HashSet<String> = ["a", "c"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(pairs, expected);
+
+        // padding left by the encoder rules out strict gapless playback
+        let mut files = files;
+        files.insert(file("b", false, 0.0, 0.0).0, file("b", false, 0.0, 0.0).1);
+        assert!(!gapless_pairs(&tracks, &files).contains("a"));
+    }
+}
@@ -0,0 +1,342 @@
+//! Genre pages
+//!
+//! genres only exist as tags on tracks, albums and artists. the artists of a
+//! genre are counted from its tracks so an artist with a single track in the
+//! genre still shows up, ranked by how many of their tracks carry it or how
+//! often the user played those. tracks are looked up through the genre index
+//! of the track store, so a genre page only reads the tracks it shows.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::core::audiobooks;
+use crate::core::sorting::SortOrder;
+use crate::models::{Album, Artist, GenreRef, Track};
+use crate::stores::{AlbumStore, ArtistStore, PlayStatsStore, TrackStore};
+
+/// How the genre list is ordered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GenreSort {
+    /// most tracks first
+    #[default]
+    Tracks,
+    Albums,
+    Artists,
+    /// most played across the library first
+    Plays,
+    /// alphabetical
+    Name,
+}
+
+impl GenreSort {
+    /// Parse a sort key, unknown keys fall back to track count
+    pub fn parse(key: &str) -> Self {
+        match key.trim().to_lowercase().as_str() {
+            "albums" | "albumcount" => GenreSort::Albums,
+            "artists" | "artistcount" => GenreSort::Artists,
+            "plays" | "playcount" => GenreSort::Plays,
+            "name" | "title" => GenreSort::Name,
+            _ => GenreSort::Tracks,
+        }
+    }
+}
+
+/// A genre with what the library has of it
+#[derive(Debug, Clone)]
+pub struct GenreSummary {
+    pub genre: GenreRef,
+    pub trackcount: usize,
+    pub albumcount: usize,
+    pub artistcount: usize,
+    /// plays of the genre's tracks by every user
+    pub playcount: i64,
+    /// Total duration in seconds
+    pub duration: i64,
+    /// albums ranked by how many of their tracks carry the genre
+    pub albumhashes: Vec<String>,
+}
+
+/// Tracks of a genre, audiobooks left out
+pub fn genre_tracks(genrehash: &str) -> Vec<Arc<Track>> {
+    TrackStore::get()
+        .get_shared_by_genre(genrehash)
+        .into_iter()
+        .filter(|t| !audiobooks::is_audiobook(t))
+        .collect()
+}
+
+/// Every genre with tracks in the library, sorted
+///
+/// `reverse` flips the natural order of the key
+pub fn all_genres(sort: GenreSort, reverse: bool) -> Vec<GenreSummary> {
+    let mut genres: Vec<GenreSummary> = TrackStore::get()
+        .get_genre_hashes()
+        .iter()
+        .filter_map(|hash| summarize(hash, &genre_tracks(hash)))
+        .collect();
+    rank_genres(&mut genres, sort, reverse);
+    genres
+}
+
+/// Counts of a genre from its tracks, `None` when there are none
+fn summarize(genrehash: &str, tracks: &[Arc<Track>]) -> Option<GenreSummary> {
+    let genre = tracks
+        .iter()
+        .flat_map(|t| &t.genres)
+        .find(|g| g.genrehash == genrehash)?
+        .clone();
+
+    let mut albums: HashMap<&str, usize> = HashMap::new();
+    let mut artists = HashSet::new();
+    for track in tracks {
+        *albums.entry(&track.albumhash).or_default() += 1;
+        artists.extend(track.artisthashes.iter().map(String::as_str));
+    }
+    let mut ranked: Vec<(&str, usize)> = albums.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+    Some(GenreSummary {
+        genre,
+        trackcount: tracks.len(),
+        albumcount: ranked.len(),
+        artistcount: artists.len(),
+        playcount: tracks.iter().map(|t| i64::from(t.playcount)).sum(),
+        duration: tracks.iter().map(|t| i64::from(t.duration)).sum(),
+        albumhashes: ranked.into_iter().map(|(h, _)| h.to_string()).collect(),
+    })
+}
+
+fn rank_genres(genres: &mut [GenreSummary], sort: GenreSort, reverse: bool) {
+    // names read from a, counts from the largest
+    let order = SortOrder::from_reverse((sort != GenreSort::Name) != reverse);
+    let name = |g: &GenreSummary| g.genre.name.to_lowercase();
+
+    genres.sort_by(|a, b| {
+        let key = match sort {
+            GenreSort::Tracks => a.trackcount.cmp(&b.trackcount),
+            GenreSort::Albums => a.albumcount.cmp(&b.albumcount),
+            GenreSort::Artists => a.artistcount.cmp(&b.artistcount),
+            GenreSort::Plays => a.playcount.cmp(&b.playcount),
+            GenreSort::Name => name(a).cmp(&name(b)),
+        };
+        order
+            .apply(key)
+            .then_with(|| name(a).cmp(&name(b)))
+            .then_with(|| a.genre.genrehash.cmp(&b.genre.genrehash))
+    });
+}
+
+/// Albums with tracks in a genre, `None` when no track has the genre
+pub fn genre_albums(genrehash: &str) -> Option<(GenreRef, Vec<Album>)> {
+    let tracks = genre_tracks(genrehash);
+    let summary = summarize(genrehash, &tracks)?;
+    Some((
+        summary.genre,
+        AlbumStore::get().get_by_hashes(&summary.albumhashes),
+    ))
+}
+
+/// How the artists of a genre are ordered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GenreArtistSort {
+    /// most tracks in the genre first
+    #[default]
+    Tracks,
+    /// most played tracks in the genre first
+    Plays,
+}
+
+impl GenreArtistSort {
+    /// Parse a sort key, unknown keys fall back to track count
+    pub fn parse(key: &str) -> Self {
+        match key.trim().to_lowercase().as_str() {
+            "plays" | "playcount" => GenreArtistSort::Plays,
+            _ => GenreArtistSort::Tracks,
+        }
+    }
+}
+
+/// An artist with their share of a genre
+#[derive(Debug, Clone)]
+pub struct GenreArtist {
+    pub artist: Artist,
+    /// tracks of the artist tagged with the genre
+    pub trackcount: usize,
+    /// the user's plays of those tracks
+    pub playcount: i64,
+}
+
+/// A genre and its ranked artists
+#[derive(Debug, Clone)]
+pub struct GenreArtists {
+    pub genre: GenreRef,
+    pub trackcount: usize,
+    pub artists: Vec<GenreArtist>,
+}
+
+impl GenreArtists {
+    /// The artist whose image and colors stand for the genre, the highest
+    /// ranked one with extracted colors
+    pub fn representative(&self) -> Option<&Artist> {
+        self.artists
+            .iter()
+            .map(|a| &a.artist)
+            .find(|a| !a.color.is_empty())
+            .or_else(|| self.artists.first().map(|a| &a.artist))
+    }
+}
+
+/// Artists of a genre ranked for a user, `None` when no track has the genre
+pub fn genre_artists(genrehash: &str, user_id: i64, sort: GenreArtistSort) -> Option<GenreArtists> {
+    let mut tracks: Vec<Track> = genre_tracks(genrehash)
+        .into_iter()
+        .map(Arc::unwrap_or_clone)
+        .collect();
+    let genre = tracks
+        .iter()
+        .flat_map(|t| &t.genres)
+        .find(|g| g.genrehash == genrehash)?
+        .clone();
+
+    PlayStatsStore::get().personalize_tracks(user_id, &mut tracks);
+    let counts = count_artists(&tracks);
+
+    let hashes: Vec<String> = counts.keys().cloned().collect();
+    let mut artists: Vec<GenreArtist> = ArtistStore::get()
+        .get_by_hashes(&hashes)
+        .into_iter()
+        .map(|artist| {
+            let (trackcount, playcount) = counts[&artist.artisthash];
+            GenreArtist {
+                artist,
+                trackcount,
+                playcount,
+            }
+        })
+        .collect();
+    rank(&mut artists, sort);
+
+    Some(GenreArtists {
+        genre,
+        trackcount: tracks.len(),
+        artists,
+    })
+}
+
+/// Tracks and plays per artisthash, every credited artist counts the track
+fn count_artists(tracks: &[Track]) -> HashMap<String, (usize, i64)> {
+    let mut counts: HashMap<String, (usize, i64)> = HashMap::new();
+    for track in tracks {
+        for hash in &track.artisthashes {
+            let entry = counts.entry(hash.clone()).or_default();
+            entry.0 += 1;
+            entry.1 += track.playcount as i64;
+        }
+    }
+    counts
+}
+
+fn rank(artists: &mut [GenreArtist], sort: GenreArtistSort) {
+    artists.sort_by(|a, b| {
+        let primary = match sort {
+            GenreArtistSort::Tracks => b
+                .trackcount
+                .cmp(&a.trackcount)
+                .then_with(|| b.playcount.cmp(&a.playcount)),
+            GenreArtistSort::Plays => b
+                .playcount
+                .cmp(&a.playcount)
+                .then_with(|| b.trackcount.cmp(&a.trackcount)),
+        };
+        primary.then_with(|| {
+            a.artist
+                .name
+                .to_lowercase()
+                .cmp(&b.artist.name.to_lowercase())
+        })
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(artists: &[&str], playcount: i32) -> Track {
+        let mut track = Track::new();
+        track.artisthashes = artists.iter().map(|a| a.to_string()).collect();
+        track.playcount = playcount;
+        track
+    }
+
+    fn entry(name: &str, counts: &HashMap<String, (usize, i64)>) -> GenreArtist {
+        let (trackcount, playcount) = counts[name];
+        GenreArtist {
+            artist: Artist::new(name.to_string(), name.to_string()),
+            trackcount,
+            playcount,
+        }
+    }
+
+    #[test]
+    fn test_rank_genre_artists() {
+        let tracks = vec![
+            track(&["a"], 1),
+            track(&["a", "b"], 0),
+            track(&["b"], 9),
+            track(&["c"], 0),
+        ];
+        let counts = count_artists(&tracks);
+        assert_eq!(counts["a"], (2, 1));
+        assert_eq!(counts["b"], (2, 9));
+
+        let mut artists: Vec<GenreArtist> =
+            ["c", "a", "b"].iter().map(|n| entry(n, &counts)).collect();
+        rank(&mut artists, GenreArtistSort::Tracks);
+        let names: Vec<&str> = artists.iter().map(|a| a.artist.name.as_str()).collect();
+        assert_eq!(names, ["b", "a", "c"]);
+
+        // more tracks win by count, more plays win by plays
+        artists[1].trackcount = 5;
+        rank(&mut artists, GenreArtistSort::Tracks);
+        assert_eq!(artists[0].artist.name, "a");
+        rank(&mut artists, GenreArtistSort::parse("Plays"));
+        assert_eq!(artists[0].artist.name, "b");
+        assert_eq!(GenreArtistSort::parse("nope"), GenreArtistSort::Tracks);
+    }
+
+    #[test]
+    fn test_summarize_and_rank_genres() {
+        let genre = |name: &str| GenreRef::new(name.to_string(), name.to_string());
+        let tagged = |album: &str, artists: &[&str], genres: &[&str]| {
+            let mut track = track(artists, 2);
+            track.albumhash = album.to_string();
+            track.genres = genres.iter().map(|g| genre(g)).collect();
+            Arc::new(track)
+        };
+        let rock = [
+            tagged("x", &["a"], &["rock"]),
+            tagged("y", &["a", "b"], &["rock", "pop"]),
+            tagged("y", &["b"], &["rock"]),
+        ];
+        let pop = [rock[1].clone()];
+
+        let summary = summarize("rock", &rock).unwrap();
+        assert_eq!(summary.genre.name, "rock");
+        assert_eq!(
+            (summary.trackcount, summary.albumcount, summary.artistcount),
+            (3, 2, 2)
+        );
+        assert_eq!(summary.playcount, 6);
+        // the album with the most tracks in the genre comes first
+        assert_eq!(summary.albumhashes, ["y", "x"]);
+        assert!(summarize("jazz", &rock).is_none());
+
+        let mut genres = vec![summarize("pop", &pop).unwrap(), summary];
+        rank_genres(&mut genres, GenreSort::Tracks, false);
+        assert_eq!(genres[0].genre.name, "rock");
+        rank_genres(&mut genres, GenreSort::parse("name"), false);
+        assert_eq!(genres[0].genre.name, "pop");
+        rank_genres(&mut genres, GenreSort::Name, true);
+        assert_eq!(genres[0].genre.name, "rock");
+    }
+}
@@ -0,0 +1,168 @@
+//! Listening history pages and their grouping
+//!
+//! pages are cut by the (timestamp, id) of their last play rather than an
+//! offset, so scrobbles landing while a user scrolls don't shift later pages.
+//! a day or session can run over into the next page, the client joins groups
+//! with the same key.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{FixedOffset, Offset, TimeZone, Utc};
+use serde::Serialize;
+
+use crate::models::TrackLog;
+
+/// quiet time after a play ends that closes a listening session
+pub const SESSION_GAP: i64 = 30 * 60;
+
+/// How history plays are grouped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryGrouping {
+    None,
+    Day,
+    Session,
+}
+
+impl HistoryGrouping {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "" | "none" => Some(Self::None),
+            "day" => Some(Self::Day),
+            "session" => Some(Self::Session),
+            _ => None,
+        }
+    }
+}
+
+/// Plays of a day or a session, newest first like the page they came from
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryGroup<T> {
+    /// `YYYY-MM-DD` for days, the start of the newest play for sessions
+    pub key: String,
+    /// start of the oldest play in the group
+    pub start: i64,
+    /// end of the newest play in the group
+    pub end: i64,
+    pub playcount: usize,
+    /// Listening time in seconds
+    pub playduration: i64,
+    pub items: Vec<T>,
+}
+
+/// Cursor pointing below the last play of a page
+pub fn encode_cursor(last: &TrackLog) -> String {
+    URL_SAFE_NO_PAD.encode(format!("{}:{}", last.timestamp, last.id))
+}
+
+/// The (timestamp, id) a cursor points below
+pub fn decode_cursor(cursor: &str) -> Option<(i64, i64)> {
+    let decoded = String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()?;
+    let (timestamp, id) = decoded.split_once(':')?;
+    Some((timestamp.parse().ok()?, id.parse().ok()?))
+}
+
+/// Group plays sorted newest first, pairing each with what is sent for it
+///
+/// days follow the client's utc offset in seconds, sessions end once the gap
+/// between a play's end and the next play's start passes [`SESSION_GAP`]
+pub fn group<T>(
+    plays: &[TrackLog],
+    items: Vec<T>,
+    grouping: HistoryGrouping,
+    utc_offset: i32,
+) -> Vec<HistoryGroup<T>> {
+    // offsets past a day are not a time zone, fall back to utc
+    let offset = FixedOffset::east_opt(utc_offset).unwrap_or_else(|| Utc.fix());
+    let mut groups: Vec<HistoryGroup<T>> = Vec::new();
+
+    for (play, item) in plays.iter().zip(items) {
+        let end = play.timestamp + i64::from(play.duration.max(0));
+        let key = match grouping {
+            HistoryGrouping::Day => offset
+                .timestamp_opt(play.timestamp, 0)
+                .single()
+                .map(|t| t.format("%Y-%m-%d").to_string())
+                .unwrap_or_default(),
+            _ => play.timestamp.to_string(),
+        };
+
+        let joins = match (groups.last(), grouping) {
+            (Some(current), HistoryGrouping::Day) => current.key == key,
+            // plays come newest first, the gap runs from this play's end to
+            // the start of the play after it
+            (Some(current), HistoryGrouping::Session) => current.start - end <= SESSION_GAP,
+            _ => false,
+        };
+
+        match groups.last_mut() {
+            Some(current) if joins => {
+                current.start = current.start.min(play.timestamp);
+                current.end = current.end.max(end);
+                current.playcount += 1;
+                current.playduration += i64::from(play.duration);
+                current.items.push(item);
+            }
+            _ => groups.push(HistoryGroup {
+                key,
+                start: play.timestamp,
+                end,
+                playcount: 1,
+                playduration: i64::from(play.duration),
+                items: vec![item],
+            }),
+        }
+    }
+
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(id: i64, timestamp: i64, duration: i32) -> TrackLog {
+        let mut log = TrackLog::new("t".to_string(), timestamp, duration, String::new(), 1);
+        log.id = id;
+        log
+    }
+
+    #[test]
+    fn test_cursor_roundtrip() {
+        let cursor = encode_cursor(&play(7, 1_700_000_000, 200));
+        assert_eq!(decode_cursor(&cursor), Some((1_700_000_000, 7)));
+        assert_eq!(decode_cursor("nope"), None);
+    }
+
+    #[test]
+    fn test_group_by_session() {
+        // newest first: two plays back to back, then one after a long break
+        let plays = [
+            play(3, 10_400, 200),
+            play(2, 10_000, 300),
+            play(1, 5_000, 200),
+        ];
+        let groups = group(&plays, vec![3, 2, 1], HistoryGrouping::Session, 0);
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].items, vec![3, 2]);
+        assert_eq!((groups[0].start, groups[0].end), (10_000, 10_600));
+        assert_eq!(groups[0].playduration, 500);
+        assert_eq!(groups[1].items, vec![1]);
+    }
+
+    #[test]
+    fn test_group_by_day_follows_offset() {
+        // 2024-01-01 23:30 and 2024-01-02 00:30 utc
+        let plays = [play(2, 1_704_155_400, 100), play(1, 1_704_151_800, 100)];
+
+        let utc = group(&plays, vec![2, 1], HistoryGrouping::Day, 0);
+        assert_eq!(utc.len(), 2);
+        assert_eq!(utc[0].key, "2024-01-02");
+
+        // an hour behind utc both plays are on the first
+        let behind = group(&plays, vec![2, 1], HistoryGrouping::Day, -3600);
+        assert_eq!(behind.len(), 1);
+        assert_eq!(behind[0].key, "2024-01-01");
+        assert_eq!(behind[0].playcount, 2);
+    }
+}
@@ -1,179 +1,786 @@
 //! Image processing functions - caching thumbnails and extracting colors
+//!
+//! album thumbnails are named by a hash of the art they were built from, so
+//! albums sharing a cover share files and changed art gets new files. the art
+//! each album was built from is recorded in the thumbnail table and the files
+//! are rebuilt when that source changes.
 
 use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
 use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use tracing::info;
-
-use crate::config::{Paths, LG_THUMB_SIZE, MD_THUMB_SIZE, SM_THUMB_SIZE, XSM_THUMB_SIZE};
+use std::sync::Arc;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tracing::{info, warn};
+use xxhash_rust::xxh3::xxh3_128;
+
+use crate::config::{
+    ArtistImageProvider, ArtistImageSettings, CoverSource, Paths, ThumbnailSettings, UserConfig,
+};
+use crate::core::colorlib::ColorLib;
 use crate::core::Tagger;
+use crate::db::tables::{ThumbnailSource, ThumbnailTable};
+use crate::models::{Artist, ColorVariants};
 use crate::stores::{AlbumStore, TrackStore};
+use crate::utils::hashing::create_hash;
 
-/// Cache album images from embedded track art (or nearby folder images) during scans
-/// Uses parallel processing for maximum speed (matching Python's multiprocessing approach)
-pub async fn cache_album_images() -> Result<usize> {
-    let paths = Paths::get()?;
+/// Encoding of a thumbnail file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThumbnailFormat {
+    WebP,
+    Avif,
+}
 
-    // Define all thumbnail sizes to generate (matching Python upstream)
-    let sizes: [(&str, u32); 4] = [
-        ("large", LG_THUMB_SIZE),   // 512px
-        ("medium", MD_THUMB_SIZE),  // 256px
-        ("small", SM_THUMB_SIZE),   // 96px
-        ("xsmall", XSM_THUMB_SIZE), // 64px
-    ];
+impl ThumbnailFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ThumbnailFormat::WebP => "webp",
+            ThumbnailFormat::Avif => "avif",
+        }
+    }
+}
 
-    // Collect unique albums (first track per albumhash)
-    let all_tracks = TrackStore::get().get_all();
-    let mut seen = std::collections::HashSet::new();
-    let albums_to_process: Vec<_> = all_tracks
+/// Encoder speed for avif thumbnails, 1 is slowest and 10 fastest
+#[cfg(feature = "avif")]
+const AVIF_SPEED: u8 = 6;
+
+/// Thumbnail sources by albumhash, loaded from the database on first use
+static THUMBNAIL_INDEX: Lazy<RwLock<Option<HashMap<String, ThumbnailSource>>>> =
+    Lazy::new(|| RwLock::new(None));
+
+/// Thumbnail worker pool with the worker count it was built for
+type SizedPool = (usize, Arc<rayon::ThreadPool>);
+
+static THUMBNAIL_POOL: Lazy<Mutex<Option<SizedPool>>> = Lazy::new(|| Mutex::new(None));
+
+async fn load_thumbnail_index() -> Result<()> {
+    if THUMBNAIL_INDEX.read().is_some() {
+        return Ok(());
+    }
+
+    let index = ThumbnailTable::all()
+        .await?
         .into_iter()
-        .filter(|track| {
-            if seen.contains(&track.albumhash) {
-                return false;
-            }
-            seen.insert(track.albumhash.clone());
-            // Skip if already processed
-            let large_dest = paths
-                .thumbnails_dir("large")
-                .join(format!("{}.webp", track.albumhash));
-            !large_dest.exists()
-        })
+        .map(|source| (source.albumhash.clone(), source))
         .collect();
+    THUMBNAIL_INDEX.write().get_or_insert(index);
+    Ok(())
+}
 
-    let total = albums_to_process.len();
-    if total > 0 {
-        info!(
-            "cache_album_images: Processing {} albums in parallel",
-            total
-        );
+/// The pool thumbnails are generated on, rebuilt when the worker count changes
+fn thumbnail_pool(workers: usize) -> Result<Arc<rayon::ThreadPool>> {
+    let workers = match workers {
+        0 => std::thread::available_parallelism()
+            .map(|n| (n.get() / 2).max(1))
+            .unwrap_or(1),
+        n => n,
+    };
+
+    let mut pool = THUMBNAIL_POOL.lock();
+    if let Some((count, pool)) = pool.as_ref() {
+        if *count == workers {
+            return Ok(pool.clone());
+        }
     }
 
-    let written = AtomicUsize::new(0);
-    let paths_ref = &paths;
-    let sizes_ref = &sizes;
+    let built = Arc::new(
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(workers)
+            .thread_name(|i| format!("thumbnails-{}", i))
+            .build()?,
+    );
+    *pool = Some((workers, built.clone()));
+    Ok(built)
+}
+
+/// Hash of the art bytes that names the thumbnail files
+pub fn content_hash(data: &[u8]) -> String {
+    format!("{:032x}", xxh3_128(data))
+}
 
-    // Process albums in parallel using rayon
-    albums_to_process.par_iter().for_each(|track| {
-        let path = std::path::Path::new(&track.filepath);
+fn thumbnail_file(
+    paths: &Paths,
+    contenthash: &str,
+    size: &str,
+    format: ThumbnailFormat,
+) -> PathBuf {
+    paths
+        .thumbnails_dir(size)
+        .join(format!("{}.{}", contenthash, format.extension()))
+}
+
+/// Whether avif copies are written, needs the avif feature
+fn avif_enabled(settings: &ThumbnailSettings) -> bool {
+    settings.avif && cfg!(feature = "avif")
+}
+
+/// Path of an album's cached thumbnail, None when it has not been built
+///
+/// only looks at the recorded thumbnails, use [`album_thumbnail`] to build
+/// missing ones.
+pub fn thumbnail_path(albumhash: &str, size: &str, format: ThumbnailFormat) -> Option<PathBuf> {
+    let paths = Paths::get().ok()?;
+    let contenthash = THUMBNAIL_INDEX
+        .read()
+        .as_ref()?
+        .get(albumhash)?
+        .contenthash
+        .clone();
+
+    let path = thumbnail_file(&paths, &contenthash, size, format);
+    path.exists().then_some(path)
+}
+
+/// Modification time in seconds and length of a file
+fn source_stat(path: &Path) -> Option<(i64, i64)> {
+    let meta = std::fs::metadata(path).ok()?;
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    Some((mtime, meta.len() as i64))
+}
+
+/// Whether the art a thumbnail was built from is unchanged
+fn source_unchanged(source: &ThumbnailSource) -> bool {
+    source_stat(Path::new(&source.source)) == Some((source.source_mtime, source.source_len))
+}
 
-        // Try to read embedded cover first, then folder image
-        let cover_bytes = Tagger::read_cover(path)
+/// Whether a better folder image showed up since a thumbnail was built
+///
+/// only looked at when folder images win or the art already came from one,
+/// embedded art is not read again to find out whether it appeared.
+fn folder_image_changed(source: &Path, cover_source: CoverSource) -> bool {
+    if cover_source == CoverSource::Embedded && !is_cover_image(source) {
+        return false;
+    }
+    find_folder_image(source).is_some_and(|image| image != source)
+}
+
+/// Whether every file of an album's thumbnails is in place and its art unchanged
+fn is_current(paths: &Paths, source: &ThumbnailSource, settings: &ThumbnailSettings) -> bool {
+    let avif = avif_enabled(settings);
+    let files_present = settings.sizes().iter().all(|(size, _)| {
+        thumbnail_file(paths, &source.contenthash, size, ThumbnailFormat::WebP).exists()
+            && (!avif
+                || thumbnail_file(paths, &source.contenthash, size, ThumbnailFormat::Avif).exists())
+    });
+    files_present
+        && source_unchanged(source)
+        && !folder_image_changed(Path::new(&source.source), settings.cover_source)
+}
+
+/// Read the art of a track from the preferred source, falling back to the other
+///
+/// returns the art with the file it was read from
+pub(crate) fn read_album_art(
+    track_path: &Path,
+    cover_source: CoverSource,
+) -> Option<(Vec<u8>, PathBuf)> {
+    let embedded = || {
+        Tagger::read_cover(track_path)
             .ok()
             .flatten()
-            .or_else(|| find_folder_image(path));
-
-        if let Some(data) = cover_bytes {
-            if let Ok(img) = image::load_from_memory(&data) {
-                let (orig_width, orig_height) = (img.width(), img.height());
-                let ratio = orig_width as f32 / orig_height as f32;
-                let albumhash = &track.albumhash;
-
-                // Generate all 4 sizes in parallel
-                sizes_ref.par_iter().for_each(|(size_name, max_size)| {
-                    let dest = paths_ref
-                        .thumbnails_dir(size_name)
-                        .join(format!("{}.webp", albumhash));
-
-                    if let Some(parent) = dest.parent() {
-                        let _ = std::fs::create_dir_all(parent);
-                    }
-
-                    let target_width = (*max_size).min(orig_width);
-                    let target_height = (target_width as f32 / ratio) as u32;
-
-                    let resized = img.resize(
-                        target_width,
-                        target_height,
-                        image::imageops::FilterType::Triangle,
-                    );
-                    let mut buf = Vec::new();
-                    if resized
-                        .write_to(
-                            &mut std::io::Cursor::new(&mut buf),
-                            image::ImageFormat::WebP,
-                        )
-                        .is_ok()
-                    {
-                        let _ = std::fs::write(&dest, buf);
-                    }
-                });
-
-                written.fetch_add(1, Ordering::Relaxed);
+            .map(|data| (data, track_path.to_path_buf()))
+    };
+    let folder = || {
+        let image = find_folder_image(track_path)?;
+        let data = std::fs::read(&image).ok()?;
+        Some((data, image))
+    };
+
+    match cover_source {
+        CoverSource::Embedded => embedded().or_else(folder),
+        CoverSource::Folder => folder().or_else(embedded),
+    }
+}
+
+/// Build every thumbnail of an album from the art of one of its tracks
+fn build_thumbnails(
+    paths: &Paths,
+    albumhash: &str,
+    track_path: &Path,
+    settings: &ThumbnailSettings,
+) -> Option<ThumbnailSource> {
+    let (data, source) = read_album_art(track_path, settings.cover_source)?;
+    let (source_mtime, source_len) = source_stat(&source)?;
+    let contenthash = content_hash(&data);
+
+    if !write_thumbnails(paths, &data, &contenthash, settings) {
+        return None;
+    }
+
+    Some(ThumbnailSource {
+        albumhash: albumhash.to_string(),
+        contenthash,
+        source: source.to_string_lossy().to_string(),
+        source_mtime,
+        source_len,
+    })
+}
+
+/// Write the missing thumbnail files of some art, returns false when the art
+/// could not be decoded
+fn write_thumbnails(
+    paths: &Paths,
+    data: &[u8],
+    contenthash: &str,
+    settings: &ThumbnailSettings,
+) -> bool {
+    let mut formats = vec![ThumbnailFormat::WebP];
+    if avif_enabled(settings) {
+        formats.push(ThumbnailFormat::Avif);
+    }
+
+    // albums sharing art share files, so they may already be written
+    let missing: Vec<(&str, u32, Vec<ThumbnailFormat>)> = settings
+        .sizes()
+        .into_iter()
+        .filter_map(|(size, max_size)| {
+            let formats: Vec<ThumbnailFormat> = formats
+                .iter()
+                .copied()
+                .filter(|f| !thumbnail_file(paths, contenthash, size, *f).exists())
+                .collect();
+            (!formats.is_empty()).then_some((size, max_size, formats))
+        })
+        .collect();
+    if missing.is_empty() {
+        return true;
+    }
+
+    let Ok(img) = image::load_from_memory(data) else {
+        return false;
+    };
+    let (orig_width, orig_height) = (img.width(), img.height());
+    if orig_width == 0 || orig_height == 0 {
+        return false;
+    }
+    let ratio = orig_width as f32 / orig_height as f32;
+
+    missing.par_iter().for_each(|(size, max_size, formats)| {
+        let target_width = (*max_size).min(orig_width);
+        let target_height = ((target_width as f32 / ratio) as u32).max(1);
+        let resized = img.resize(
+            target_width,
+            target_height,
+            image::imageops::FilterType::Triangle,
+        );
+
+        for format in formats {
+            let encoded = match format {
+                ThumbnailFormat::WebP => encode_thumbnail(&resized, settings.quality),
+                ThumbnailFormat::Avif => encode_avif(&resized, settings.quality),
+            };
+            let Some(buf) = encoded else {
+                continue;
+            };
+            let dest = thumbnail_file(paths, contenthash, size, *format);
+            if let Err(e) = write_atomic(&dest, &buf) {
+                warn!("Failed to write thumbnail {}: {}", dest.display(), e);
             }
         }
     });
 
-    let final_count = written.load(Ordering::Relaxed);
-    if final_count > 0 {
-        info!(
-            "cache_album_images complete: {} album covers cached",
-            final_count
-        );
+    true
+}
+
+/// Write a file through a temporary sibling so readers never see a partial file
+fn write_atomic(dest: &Path, data: &[u8]) -> Result<()> {
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut tmp = dest.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, dest)?;
+    Ok(())
+}
+
+/// Save built thumbnail sources to the database and the index
+///
+/// albums whose art changed lose their cached full size artwork, their colors
+/// are taken again by the next [`extract_album_colors`] pass
+async fn record_thumbnails(built: &[ThumbnailSource]) {
+    if let Err(e) = ThumbnailTable::upsert_many(built).await {
+        warn!("Failed to save thumbnail sources: {}", e);
+    }
+
+    let changed: Vec<String> = {
+        let mut index = THUMBNAIL_INDEX.write();
+        let index = index.get_or_insert_with(HashMap::new);
+        built
+            .iter()
+            .filter_map(|source| {
+                let previous = index.insert(source.albumhash.clone(), source.clone())?;
+                (previous.contenthash != source.contenthash).then(|| source.albumhash.clone())
+            })
+            .collect()
+    };
+    if changed.is_empty() {
+        return;
+    }
+
+    info!("Album art changed for {} albums", changed.len());
+    if let Ok(paths) = Paths::get() {
+        forget_original_artwork(&paths, &changed);
+    }
+}
+
+/// Content hash of the art an album's thumbnails were built from
+fn recorded_contenthash(albumhash: &str) -> Option<String> {
+    THUMBNAIL_INDEX
+        .read()
+        .as_ref()?
+        .get(albumhash)
+        .map(|source| source.contenthash.clone())
+}
+
+/// Path of an album's thumbnail, building the album's thumbnails when they are
+/// missing or their art changed
+///
+/// `pathhash` picks the track of the album whose art is used, the first track
+/// is used when no track's folder matches.
+pub async fn album_thumbnail(
+    albumhash: &str,
+    pathhash: &str,
+    size: &str,
+    format: ThumbnailFormat,
+) -> Option<PathBuf> {
+    if let Err(e) = load_thumbnail_index().await {
+        warn!("Failed to load thumbnail sources: {}", e);
+    }
+
+    let recorded = THUMBNAIL_INDEX
+        .read()
+        .as_ref()
+        .and_then(|index| index.get(albumhash).cloned());
+    if recorded.as_ref().is_some_and(source_unchanged) {
+        if let Some(path) = thumbnail_path(albumhash, size, format) {
+            return Some(path);
+        }
+    }
+
+    let tracks = TrackStore::get().get_by_album(albumhash);
+    let track = tracks
+        .iter()
+        .find(|t| !pathhash.is_empty() && create_hash(&[&t.folder], false) == pathhash)
+        .or_else(|| tracks.first())?;
+
+    let paths = Paths::get().ok()?;
+    let settings = thumbnail_settings();
+    let albumhash = albumhash.to_string();
+    let track_path = PathBuf::from(&track.filepath);
+    let built = tokio::task::spawn_blocking(move || {
+        build_thumbnails(&paths, &albumhash, &track_path, &settings)
+    })
+    .await
+    .ok()
+    .flatten()?;
+
+    record_thumbnails(std::slice::from_ref(&built)).await;
+    thumbnail_path(&built.albumhash, size, format)
+}
+
+/// Cache album images from embedded track art and folder images during scans
+///
+/// albums whose recorded art changed or whose files are missing are rebuilt on
+/// the thumbnail worker pool, then files no album uses anymore are removed.
+/// returns the number of albums whose thumbnails were built.
+pub async fn cache_album_images() -> Result<usize> {
+    let paths = Paths::get()?;
+    let settings = thumbnail_settings();
+    load_thumbnail_index().await?;
+
+    // Collect unique albums (first track per albumhash)
+    let mut seen = HashSet::new();
+    let albums: Vec<(String, String)> = TrackStore::get()
+        .get_all()
+        .into_iter()
+        .filter(|track| seen.insert(track.albumhash.clone()))
+        .map(|track| (track.albumhash, track.filepath))
+        .collect();
+    if albums.is_empty() {
+        return Ok(0);
+    }
+
+    let recorded: HashMap<String, ThumbnailSource> =
+        THUMBNAIL_INDEX.read().clone().unwrap_or_default();
+    let pool = thumbnail_pool(settings.workers)?;
+
+    let task_paths = paths.clone();
+    let results: Vec<std::result::Result<ThumbnailSource, String>> =
+        tokio::task::spawn_blocking(move || {
+            pool.install(|| {
+                albums
+                    .par_iter()
+                    .filter(|(albumhash, _)| {
+                        recorded
+                            .get(albumhash)
+                            .map(|source| !is_current(&task_paths, source, &settings))
+                            .unwrap_or(true)
+                    })
+                    .map(|(albumhash, filepath)| {
+                        build_thumbnails(&task_paths, albumhash, Path::new(filepath), &settings)
+                            .ok_or_else(|| albumhash.clone())
+                    })
+                    .collect()
+            })
+        })
+        .await?;
+
+    let mut built = Vec::new();
+    let mut without_art = Vec::new();
+    for result in results {
+        match result {
+            Ok(source) => built.push(source),
+            Err(albumhash) => without_art.push(albumhash),
+        }
+    }
+    record_thumbnails(&built).await;
+
+    forget_original_artwork(&paths, &without_art);
+
+    // forget albums that lost their art or left the library
+    let library: HashSet<String> = seen;
+    let stale: Vec<String> = {
+        let mut index = THUMBNAIL_INDEX.write();
+        let index = index.get_or_insert_with(HashMap::new);
+        let stale: Vec<String> = index
+            .keys()
+            .filter(|hash| !library.contains(*hash) || without_art.contains(*hash))
+            .cloned()
+            .collect();
+        for albumhash in &stale {
+            index.remove(albumhash);
+        }
+        stale
+    };
+    if let Err(e) = ThumbnailTable::delete_many(&stale).await {
+        warn!("Failed to forget thumbnail sources: {}", e);
+    }
+
+    let referenced: HashSet<String> = THUMBNAIL_INDEX
+        .read()
+        .as_ref()
+        .map(|index| index.values().map(|s| s.contenthash.clone()).collect())
+        .unwrap_or_default();
+    let avif = avif_enabled(&settings);
+    let removed = tokio::task::spawn_blocking(move || {
+        settings
+            .sizes()
+            .iter()
+            .map(|(size, _)| prune_thumbnails(&paths.thumbnails_dir(size), &referenced, avif))
+            .sum::<usize>()
+    })
+    .await?;
+    if removed > 0 {
+        info!("cache_album_images: Removed {} unused thumbnails", removed);
     }
 
-    Ok(final_count)
+    let count = built.len();
+    if count > 0 {
+        info!("cache_album_images complete: {} album covers cached", count);
+    }
+
+    Ok(count)
 }
 
-fn find_folder_image(track_path: &std::path::Path) -> Option<Vec<u8>> {
+/// Remove thumbnail files no album references, returns how many were removed
+///
+/// this also clears files named by albumhash from before thumbnails were named
+/// by their art, and avif files once avif is turned off.
+fn prune_thumbnails(dir: &Path, referenced: &HashSet<String>, avif: bool) -> usize {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+
+    let mut removed = 0;
+    for path in entries.flatten().map(|e| e.path()) {
+        let (Some(stem), Some(ext)) = (
+            path.file_stem().and_then(|s| s.to_str()),
+            path.extension().and_then(|e| e.to_str()),
+        ) else {
+            continue;
+        };
+
+        let keep = match ext {
+            "webp" => referenced.contains(stem),
+            "avif" => avif && referenced.contains(stem),
+            _ => true,
+        };
+        if !keep && std::fs::remove_file(&path).is_ok() {
+            removed += 1;
+        }
+    }
+    removed
+}
+
+static THUMBNAIL_SETTINGS: Lazy<RwLock<Option<ThumbnailSettings>>> =
+    Lazy::new(|| RwLock::new(None));
+
+/// Current thumbnail settings, falling back to the defaults
+///
+/// the settings are read once and kept until the thumbnails are refreshed
+pub fn thumbnail_settings() -> ThumbnailSettings {
+    if let Some(settings) = *THUMBNAIL_SETTINGS.read() {
+        return settings;
+    }
+
+    let settings = UserConfig::load()
+        .map(|c| c.thumbnails)
+        .unwrap_or_default()
+        .normalized();
+    if settings.avif && !cfg!(feature = "avif") {
+        warn!("avif thumbnails are enabled but this build has no avif support");
+    }
+    *THUMBNAIL_SETTINGS.write() = Some(settings);
+    settings
+}
+
+static IMAGE_CACHE_MAX_AGE: Lazy<RwLock<Option<u32>>> = Lazy::new(|| RwLock::new(None));
+
+/// Seconds clients may cache images for, read once like the thumbnail settings
+pub fn image_cache_max_age() -> u32 {
+    if let Some(max_age) = *IMAGE_CACHE_MAX_AGE.read() {
+        return max_age;
+    }
+
+    let max_age = UserConfig::load().unwrap_or_default().image_cache_max_age;
+    *IMAGE_CACHE_MAX_AGE.write() = Some(max_age);
+    max_age
+}
+
+/// Use a new image max-age without restarting
+pub fn set_image_cache_max_age(max_age: u32) {
+    *IMAGE_CACHE_MAX_AGE.write() = Some(max_age);
+}
+
+/// Encode a thumbnail as webp
+///
+/// quality 100 keeps the lossless encoder, anything lower uses lossy webp.
+pub fn encode_thumbnail(img: &image::DynamicImage, quality: u8) -> Option<Vec<u8>> {
+    if quality >= 100 {
+        let mut buf = Vec::new();
+        img.write_to(
+            &mut std::io::Cursor::new(&mut buf),
+            image::ImageFormat::WebP,
+        )
+        .ok()?;
+        return Some(buf);
+    }
+
+    let rgba = image::DynamicImage::ImageRgba8(img.to_rgba8());
+    let encoder = webp::Encoder::from_image(&rgba).ok()?;
+    Some(encoder.encode(quality as f32).to_vec())
+}
+
+/// Encode a thumbnail as avif with the same quality scale as webp
+#[cfg(feature = "avif")]
+fn encode_avif(img: &image::DynamicImage, quality: u8) -> Option<Vec<u8>> {
+    let mut buf = Vec::new();
+    let encoder =
+        image::codecs::avif::AvifEncoder::new_with_speed_quality(&mut buf, AVIF_SPEED, quality);
+    image::DynamicImage::ImageRgba8(img.to_rgba8())
+        .write_with_encoder(encoder)
+        .ok()?;
+    Some(buf)
+}
+
+#[cfg(not(feature = "avif"))]
+fn encode_avif(_img: &image::DynamicImage, _quality: u8) -> Option<Vec<u8>> {
+    None
+}
+
+/// Name of the file recording the settings the cached thumbnails were built with
+const THUMBNAIL_SPEC_FILE: &str = ".spec.json";
+
+/// Drop cached thumbnails built with different settings and regenerate them
+///
+/// thumbnails cached before the spec file existed are assumed to match the defaults.
+/// returns the number of albums whose thumbnails were rebuilt.
+pub async fn refresh_thumbnails() -> Result<usize> {
+    let paths = Paths::get()?;
+    let root = paths.images_dir().join("thumbnails");
+    let spec_path = root.join(THUMBNAIL_SPEC_FILE);
+
+    // the settings changed, read them again
+    *THUMBNAIL_SETTINGS.write() = None;
+    let current = thumbnail_settings();
+    let previous: ThumbnailSettings = std::fs::read_to_string(&spec_path)
+        .ok()
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .unwrap_or_default();
+
+    let stale = current.changed_sizes(&previous);
+    for size_name in &stale {
+        let dir = paths.thumbnails_dir(size_name);
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if matches!(
+                path.extension().and_then(|e| e.to_str()),
+                Some("webp" | "avif")
+            ) {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+        info!("refresh_thumbnails: Cleared stale {} thumbnails", size_name);
+    }
+
+    std::fs::create_dir_all(&root)?;
+    std::fs::write(&spec_path, serde_json::to_string_pretty(&current)?)?;
+
+    // every album may now pick different art, files of unchanged art are reused
+    if current.cover_source != previous.cover_source {
+        forget_thumbnail_sources().await?;
+        clear_original_artwork(&paths);
+    }
+
+    // turning avif on or off adds or removes files without touching the webp ones
+    if stale.is_empty()
+        && current.avif == previous.avif
+        && current.cover_source == previous.cover_source
+    {
+        return Ok(0);
+    }
+
+    cache_album_images().await
+}
+
+/// Forget the art every album's thumbnails were built from so the next pass
+/// picks it again
+async fn forget_thumbnail_sources() -> Result<()> {
+    load_thumbnail_index().await?;
+    let albumhashes: Vec<String> = THUMBNAIL_INDEX
+        .write()
+        .as_mut()
+        .map(|index| index.drain().map(|(albumhash, _)| albumhash).collect())
+        .unwrap_or_default();
+    ThumbnailTable::delete_many(&albumhashes).await
+}
+
+/// Extensions the cached full size album art is saved with
+pub(crate) const ORIGINAL_ARTWORK_EXTENSIONS: [&str; 5] = ["jpg", "png", "webp", "gif", "bmp"];
+
+/// Remove the cached full size art of some albums
+fn forget_original_artwork(paths: &Paths, albumhashes: &[String]) {
+    let dir = paths.artwork_cache_dir();
+    for albumhash in albumhashes {
+        for ext in ORIGINAL_ARTWORK_EXTENSIONS {
+            let _ = std::fs::remove_file(dir.join(format!("{}.{}", albumhash, ext)));
+        }
+    }
+}
+
+/// Remove the cached full size album art, it is extracted again on request
+fn clear_original_artwork(paths: &Paths) {
+    let Ok(entries) = std::fs::read_dir(paths.artwork_cache_dir()) else {
+        return;
+    };
+    for path in entries.flatten().map(|e| e.path()) {
+        if path.is_file() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Image names recognised as album covers, best first
+///
+/// a name matches files starting with it, so `cover (1).jpg` and `folder-front.png` count
+const COVER_NAMES: [&str; 5] = ["cover", "folder", "front", "album", "artwork"];
+
+/// Whether a path has an image extension album art is read from
+fn is_cover_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "jpg" | "jpeg" | "png" | "webp"))
+}
+
+/// Find the cover image in a track's folder
+///
+/// images named like a cover win, otherwise a folder holding a single image
+/// uses it. other images such as booklet scans are left alone.
+pub(crate) fn find_folder_image(track_path: &Path) -> Option<PathBuf> {
     let folder = track_path.parent()?;
-    let mut images: Vec<std::path::PathBuf> = std::fs::read_dir(folder)
+    let mut images: Vec<PathBuf> = std::fs::read_dir(folder)
         .ok()?
         .filter_map(|e| e.ok())
         .map(|e| e.path())
-        .filter(|p| {
-            p.is_file()
-                && p.extension()
-                    .and_then(|e| e.to_str())
-                    .map(|ext| {
-                        matches!(ext.to_lowercase().as_str(), "jpg" | "jpeg" | "png" | "webp")
-                    })
-                    .unwrap_or(false)
-        })
+        .filter(|p| p.is_file() && is_cover_image(p))
         .collect();
+    // directory order varies between filesystems
+    images.sort();
+
+    let named = images
+        .iter()
+        .filter_map(|p| {
+            let stem = p.file_stem()?.to_str()?.to_lowercase();
+            let rank = COVER_NAMES.iter().position(|name| stem.starts_with(name))?;
+            Some((rank, p))
+        })
+        .min_by_key(|(rank, _)| *rank)
+        .map(|(_, p)| p.clone());
 
-    if images.is_empty() {
-        return None;
+    match named {
+        Some(image) => Some(image),
+        None if images.len() == 1 => images.pop(),
+        None => None,
     }
+}
 
-    // Prioritize common cover names
-    let priority = ["cover", "front", "folder", "album", "artwork", "back"];
-    images.sort_by_key(|p| {
-        let name = p
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("")
-            .to_lowercase();
-        priority
-            .iter()
-            .position(|pref| name.starts_with(pref))
-            .unwrap_or(priority.len())
-    });
-
-    std::fs::read(images[0].clone()).ok()
+/// Whether an album's color has to be taken again
+///
+/// `taken_from` is the art hash a stored color was taken from and `arthash`
+/// the art the thumbnails show now, colors stored before art hashes were
+/// recorded have an empty one and are taken again once
+fn color_is_stale(taken_from: Option<&str>, arthash: Option<&str>, has_color: bool) -> bool {
+    match taken_from {
+        Some(taken_from) => arthash.is_some_and(|arthash| arthash != taken_from),
+        None => !has_color,
+    }
 }
 
 /// Extract dominant colors from album thumbnails and store in database
+///
+/// albums without a color are picked up along with albums whose art changed
+/// since their color was taken
 pub async fn extract_album_colors() -> Result<usize> {
     use crate::db::DbEngine;
 
-    let paths = Paths::get()?;
     let db = DbEngine::get()?;
+    load_thumbnail_index().await?;
 
-    // Get existing colors from database
-    let existing: std::collections::HashSet<String> = sqlx::query_as::<_, (String,)>(
-        "SELECT hash FROM libdata WHERE type = 'album' AND color IS NOT NULL AND color != ''",
+    // Get existing colors with the art they were taken from
+    let existing: HashMap<String, String> = sqlx::query_as::<_, (String, String)>(
+        "SELECT hash, arthash FROM libdata \
+         WHERE type = 'album' AND color IS NOT NULL AND color != ''",
     )
     .fetch_all(db.pool())
     .await?
     .into_iter()
-    .map(|(h,)| h)
     .collect();
 
     // Get albums that need color extraction
     let albums_needing_colors: Vec<_> = AlbumStore::get()
         .get_all()
         .into_iter()
-        .filter(|album| !existing.contains(&album.albumhash) && album.color.is_empty())
+        .filter_map(|album| {
+            let arthash = recorded_contenthash(&album.albumhash);
+            let stale = color_is_stale(
+                existing.get(&album.albumhash).map(String::as_str),
+                arthash.as_deref(),
+                !album.color.is_empty(),
+            );
+            stale.then_some((album, arthash))
+        })
         .collect();
 
     if albums_needing_colors.is_empty() {
@@ -186,42 +793,44 @@ pub async fn extract_album_colors() -> Result<usize> {
     );
 
     let processed = AtomicUsize::new(0);
-    let paths_ref = &paths;
 
     // Extract colors in parallel
-    let color_results: Vec<(String, String)> = albums_needing_colors
+    let color_results: Vec<(String, String, ColorVariants, String)> = albums_needing_colors
         .par_iter()
-        .filter_map(|album| {
+        .filter_map(|(album, arthash)| {
             // Use small thumbnail for color extraction (faster)
-            let thumb_path = paths_ref
-                .thumbnails_dir("small")
-                .join(format!("{}.webp", album.albumhash));
-
-            if !thumb_path.exists() {
-                return None;
-            }
+            let thumb_path = thumbnail_path(&album.albumhash, "small", ThumbnailFormat::WebP)?;
 
             // Extract dominant color
             let color = extract_dominant_color(&thumb_path)?;
+            let variants = ColorLib::variants(&color);
             processed.fetch_add(1, Ordering::Relaxed);
-            Some((album.albumhash.clone(), color))
+            Some((
+                album.albumhash.clone(),
+                color,
+                variants,
+                arthash.clone().unwrap_or_default(),
+            ))
         })
         .collect();
 
     // Store colors in database and update in-memory store
-    for (albumhash, color) in &color_results {
+    for (albumhash, color, variants, arthash) in &color_results {
         // Insert or update in database
         sqlx::query(
-            "INSERT INTO libdata (hash, type, color) VALUES (?, 'album', ?) 
-             ON CONFLICT(hash) DO UPDATE SET color = excluded.color",
+            "INSERT INTO libdata (hash, type, color, color_dark, color_light, arthash) VALUES (?, 'album', ?, ?, ?, ?) 
+             ON CONFLICT(hash) DO UPDATE SET color = excluded.color, color_dark = excluded.color_dark, color_light = excluded.color_light, arthash = excluded.arthash",
         )
         .bind(albumhash)
         .bind(color)
+        .bind(&variants.dark)
+        .bind(&variants.light)
+        .bind(arthash)
         .execute(db.pool())
         .await?;
 
         // Update in-memory store
-        AlbumStore::get().set_color(albumhash, color);
+        AlbumStore::get().set_color(albumhash, color, variants);
     }
 
     let count = color_results.len();
@@ -276,22 +885,90 @@ const LG_ARTIST_IMG_SIZE: u32 = 500; // Original/large
 const MD_ARTIST_IMG_SIZE: u32 = 256;
 const SM_ARTIST_IMG_SIZE: u32 = 96;
 
-/// Download artist images from Deezer API for artists without images
+/// Browser user agent, deezer turns away unknown clients
+const ARTIST_IMAGE_USER_AGENT: &str =
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36";
+
+/// Local artist image names, artist.jpg anywhere and folder.jpg in the artist's folder
+const LOCAL_ARTIST_IMAGE: &str = "artist";
+const LOCAL_ARTIST_FOLDER_IMAGE: &str = "folder";
+
+/// Access token issued to a spotify client
+struct SpotifyToken {
+    client_id: String,
+    token: String,
+    expires: Instant,
+}
+
+static SPOTIFY_TOKEN: Lazy<Mutex<Option<SpotifyToken>>> = Lazy::new(|| Mutex::new(None));
+
+/// Current artist image settings, read again for every lookup
+fn artist_image_settings() -> ArtistImageSettings {
+    UserConfig::load()
+        .map(|c| c.artist_images)
+        .unwrap_or_default()
+        .normalized()
+}
+
+fn artist_image_client() -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()?)
+}
+
+/// Path of the marker recording the providers that had no image for an artist
+fn not_found_marker(paths: &Paths, artisthash: &str) -> PathBuf {
+    paths
+        .artist_images_dir("small")
+        .join(format!("{}.notfound", artisthash))
+}
+
+/// Providers listed in a not found marker
+///
+/// markers written before there were other providers are empty and stand for deezer
+fn read_not_found_marker(path: &Path) -> Vec<ArtistImageProvider> {
+    let raw = std::fs::read_to_string(path).unwrap_or_default();
+    if raw.trim().is_empty() {
+        return vec![ArtistImageProvider::Deezer];
+    }
+    raw.split(',')
+        .filter_map(|name| ArtistImageProvider::from_name(name.trim()))
+        .collect()
+}
+
+fn write_not_found_marker(paths: &Paths, artisthash: &str, tried: &[ArtistImageProvider]) {
+    let names: Vec<&str> = tried.iter().map(|p| p.as_str()).collect();
+    let _ = std::fs::write(not_found_marker(paths, artisthash), names.join(","));
+}
+
+/// Download artist images for artists without images
+///
+/// providers are tried in the configured order. artists no provider had an
+/// image for are only looked up again with providers they were not tried with,
+/// such as one that was given credentials later.
 pub async fn download_artist_images() -> Result<usize> {
     use crate::stores::ArtistStore;
 
     let paths = Paths::get()?;
+    let settings = artist_image_settings();
+    let providers = settings.usable_providers();
 
     // Get list of existing artist images (check all size directories)
-    let mut existing: std::collections::HashSet<String> = std::collections::HashSet::new();
+    let mut existing: HashSet<String> = HashSet::new();
+    let mut tried: HashMap<String, Vec<ArtistImageProvider>> = HashMap::new();
 
     for size in &["small", "medium", "large"] {
         let artist_path = paths.artist_images_dir(size);
         let _ = std::fs::create_dir_all(&artist_path);
 
         if let Ok(entries) = std::fs::read_dir(&artist_path) {
-            for entry in entries.filter_map(|e| e.ok()) {
-                if let Some(stem) = entry.path().file_stem().and_then(|s| s.to_str()) {
+            for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+                let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+                    continue;
+                };
+                if path.extension().is_some_and(|e| e == "notfound") {
+                    tried.insert(stem.to_string(), read_not_found_marker(&path));
+                } else {
                     existing.insert(stem.to_string());
                 }
             }
@@ -303,24 +980,21 @@ pub async fn download_artist_images() -> Result<usize> {
         existing.len()
     );
 
-    // Get artists that need images
+    // Get artists that need images with the providers they were not tried with
     let all_artists = ArtistStore::get().get_all();
     let total_artists = all_artists.len();
-
-    // Debug: Show sample of existing hashes vs artist hashes
-    if !existing.is_empty() && !all_artists.is_empty() {
-        let sample_existing: Vec<_> = existing.iter().take(3).collect();
-        let sample_artists: Vec<_> = all_artists.iter().take(3).map(|a| &a.artisthash).collect();
-        tracing::debug!(
-            "Sample existing: {:?}, Sample artist hashes: {:?}",
-            sample_existing,
-            sample_artists
-        );
-    }
-
     let artists_needing_images: Vec<_> = all_artists
         .into_iter()
         .filter(|artist| !existing.contains(&artist.artisthash))
+        .filter_map(|artist| {
+            let done = tried.get(&artist.artisthash);
+            let pending: Vec<ArtistImageProvider> = providers
+                .iter()
+                .copied()
+                .filter(|p| !done.is_some_and(|done| done.contains(p)))
+                .collect();
+            (!pending.is_empty()).then_some((artist, pending))
+        })
         .collect();
 
     if artists_needing_images.is_empty() {
@@ -335,46 +1009,48 @@ pub async fn download_artist_images() -> Result<usize> {
     }
 
     info!(
-        "download_artist_images: Fetching images for {} artists from Deezer ({} already cached)",
+        "download_artist_images: Looking up images for {} artists ({} already cached)",
         artists_needing_images.len(),
         existing.len()
     );
 
     let mut downloaded = 0usize;
     let mut not_found = 0usize;
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(30))
-        .build()?;
+    let client = artist_image_client()?;
 
     // Process artists sequentially with small delays to avoid rate limiting
-    for artist in &artists_needing_images {
-        match fetch_and_save_artist_image(&client, &paths, &artist.name, &artist.artisthash).await {
-            Ok(true) => {
-                downloaded += 1;
-                // Update artist image in store
-                ArtistStore::get()
-                    .set_image(&artist.artisthash, &format!("{}.webp", artist.artisthash));
-            }
-            Ok(false) => {
-                // Artist not found on Deezer - create a marker file so we don't retry
-                let marker_path = paths
-                    .artist_images_dir("small")
-                    .join(format!("{}.notfound", artist.artisthash));
-                let _ = std::fs::write(&marker_path, "");
+    for (artist, pending) in &artists_needing_images {
+        let lookup = find_artist_image(&client, &settings, artist, pending).await;
+
+        match lookup.image {
+            Some((_, data)) => match save_artist_image(&paths, &artist.artisthash, &data) {
+                Ok(()) => {
+                    downloaded += 1;
+                    // Update artist image in store
+                    ArtistStore::get()
+                        .set_image(&artist.artisthash, &format!("{}.webp", artist.artisthash));
+                }
+                Err(e) => tracing::debug!("Failed to save image for {}: {}", artist.name, e),
+            },
+            None if !lookup.answered.is_empty() => {
+                // remember who had nothing so we don't retry them
+                let mut answered = tried.remove(&artist.artisthash).unwrap_or_default();
+                answered.extend(lookup.answered);
+                write_not_found_marker(&paths, &artist.artisthash, &answered);
                 not_found += 1;
             }
-            Err(e) => {
-                tracing::debug!("Failed to fetch image for {}: {}", artist.name, e);
-            }
+            None => {}
         }
 
         // Small delay to avoid rate limiting (100ms between requests)
-        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        if pending.iter().any(|p| *p != ArtistImageProvider::Local) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
     }
 
     if downloaded > 0 || not_found > 0 {
         info!(
-            "download_artist_images: Downloaded {} artist images, {} not found on Deezer",
+            "download_artist_images: Downloaded {} artist images, {} not found",
             downloaded, not_found
         );
     }
@@ -382,74 +1058,337 @@ pub async fn download_artist_images() -> Result<usize> {
     Ok(downloaded)
 }
 
-/// Fetch artist image URL from Deezer API and save it
-async fn fetch_and_save_artist_image(
+/// Outcome of looking up an artist image with several providers
+struct ArtistImageLookup {
+    /// the image with the provider it came from
+    image: Option<(ArtistImageProvider, Vec<u8>)>,
+    /// providers that answered without an image, failed lookups are left out
+    answered: Vec<ArtistImageProvider>,
+}
+
+/// Try providers in order until one has an image of the artist
+async fn find_artist_image(
+    client: &reqwest::Client,
+    settings: &ArtistImageSettings,
+    artist: &Artist,
+    providers: &[ArtistImageProvider],
+) -> ArtistImageLookup {
+    let mut answered = Vec::new();
+
+    for provider in providers {
+        match provider_artist_image(client, settings, *provider, artist).await {
+            Ok(Some(data)) => {
+                return ArtistImageLookup {
+                    image: Some((*provider, data)),
+                    answered,
+                }
+            }
+            Ok(None) => answered.push(*provider),
+            Err(e) => tracing::debug!(
+                "{} image lookup failed for {}: {}",
+                provider.as_str(),
+                artist.name,
+                e
+            ),
+        }
+    }
+
+    ArtistImageLookup {
+        image: None,
+        answered,
+    }
+}
+
+/// Get an artist image from one provider
+async fn provider_artist_image(
+    client: &reqwest::Client,
+    settings: &ArtistImageSettings,
+    provider: ArtistImageProvider,
+    artist: &Artist,
+) -> Result<Option<Vec<u8>>> {
+    let url = match provider {
+        ArtistImageProvider::Local => return Ok(local_artist_image(artist)),
+        ArtistImageProvider::Deezer => {
+            deezer_artist_image_url(
+                client,
+                &artist.name,
+                &artist.artisthash,
+                settings.image_size,
+            )
+            .await?
+        }
+        ArtistImageProvider::Fanart => {
+            fanart_artist_image_url(client, &settings.fanart_api_key, &artist.mbid).await?
+        }
+        ArtistImageProvider::Spotify => {
+            spotify_artist_image_url(client, settings, &artist.name, &artist.artisthash).await?
+        }
+    };
+
+    match url {
+        Some(url) => download_image(client, &url).await,
+        None => Ok(None),
+    }
+}
+
+async fn download_image(client: &reqwest::Client, url: &str) -> Result<Option<Vec<u8>>> {
+    let response = client.get(url).send().await?;
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+    Ok(Some(response.bytes().await?.to_vec()))
+}
+
+/// Find an artist image next to the artist's music
+///
+/// artist.jpg is used from any folder with the artist's albums, folder.jpg only
+/// from a folder named after the artist since it is usually album art.
+fn local_artist_image(artist: &Artist) -> Option<Vec<u8>> {
+    let mut folders: Vec<String> = TrackStore::get()
+        .get_by_artist(&artist.artisthash)
+        .into_iter()
+        .filter(|t| {
+            t.albumartists
+                .iter()
+                .any(|a| a.artisthash == artist.artisthash)
+        })
+        .map(|t| t.folder)
+        .collect();
+    folders.sort();
+    folders.dedup();
+
+    let name = artist.name.to_lowercase();
+    let named_after_artist = |dir: &Path| {
+        dir.file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.to_lowercase() == name)
+    };
+
+    for folder in &folders {
+        let folder = Path::new(folder);
+        let mut candidates = vec![(folder, &[LOCAL_ARTIST_IMAGE][..])];
+        if named_after_artist(folder) {
+            candidates.push((folder, &[LOCAL_ARTIST_FOLDER_IMAGE][..]));
+        }
+        if let Some(parent) = folder.parent().filter(|p| named_after_artist(p)) {
+            candidates.push((parent, &[LOCAL_ARTIST_IMAGE, LOCAL_ARTIST_FOLDER_IMAGE][..]));
+        }
+
+        for (dir, names) in candidates {
+            if let Some(image) = find_named_image(dir, names) {
+                if let Ok(data) = std::fs::read(image) {
+                    return Some(data);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Find an image file in a folder by name, ignoring case
+fn find_named_image(dir: &Path, names: &[&str]) -> Option<PathBuf> {
+    let files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .collect();
+
+    names.iter().find_map(|name| {
+        files
+            .iter()
+            .find(|p| {
+                let stem = p.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+                let ext = p.extension().and_then(|e| e.to_str()).unwrap_or("");
+                stem.eq_ignore_ascii_case(name)
+                    && matches!(ext.to_lowercase().as_str(), "jpg" | "jpeg" | "png" | "webp")
+            })
+            .cloned()
+    })
+}
+
+/// Pick the url of the smallest picture at least `size` wide, or of the widest
+/// one when none is large enough
+fn pick_image_url(images: &[(u64, &str)], size: u32) -> Option<String> {
+    let size = u64::from(size);
+    images
+        .iter()
+        .filter(|(width, _)| *width >= size)
+        .min_by_key(|(width, _)| *width)
+        .or_else(|| images.iter().max_by_key(|(width, _)| *width))
+        .map(|(_, url)| url.to_string())
+}
+
+/// Find the url of an artist's picture on deezer
+async fn deezer_artist_image_url(
     client: &reqwest::Client,
-    paths: &Paths,
     artist_name: &str,
     artist_hash: &str,
-) -> Result<bool> {
-    use crate::utils::hashing::create_hash;
-
+    size: u32,
+) -> Result<Option<String>> {
     // Query Deezer API - reqwest handles URL encoding automatically with query()
     let response = client
         .get("https://api.deezer.com/search/artist")
         .query(&[("q", artist_name)])
-        .header(
-            "User-Agent",
-            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36",
-        )
+        .header("User-Agent", ARTIST_IMAGE_USER_AGENT)
         .header("Accept", "application/json")
         .send()
         .await?;
 
     if !response.status().is_success() {
-        return Ok(false);
+        return Ok(None);
     }
 
     let data: serde_json::Value = response.json().await?;
-
-    // Find matching artist in results
     let results = data["data"]
         .as_array()
         .ok_or_else(|| anyhow::anyhow!("No data array"))?;
 
-    if results.is_empty() {
-        return Ok(false);
+    // First try exact hash match, then the first result (likely the best match from Deezer)
+    let matched = results
+        .iter()
+        .find(|r| create_hash(&[r["name"].as_str().unwrap_or("")], true) == artist_hash)
+        .or_else(|| results.first());
+
+    Ok(matched.and_then(|r| {
+        let pictures: Vec<(u64, &str)> = [
+            (250, "picture_medium"),
+            (500, "picture_big"),
+            (1000, "picture_xl"),
+        ]
+        .into_iter()
+        .filter_map(|(width, key)| r[key].as_str().map(|url| (width, url)))
+        .collect();
+        pick_image_url(&pictures, size)
+    }))
+}
+
+/// Find the url of the most liked artist thumb on fanart.tv
+///
+/// fanart.tv looks artists up by musicbrainz id, artists without one are skipped
+async fn fanart_artist_image_url(
+    client: &reqwest::Client,
+    api_key: &str,
+    mbid: &str,
+) -> Result<Option<String>> {
+    if mbid.is_empty() {
+        return Ok(None);
+    }
+
+    let response = client
+        .get(format!("https://webservice.fanart.tv/v3/music/{}", mbid))
+        .query(&[("api_key", api_key)])
+        .send()
+        .await?;
+
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        anyhow::bail!("fanart.tv returned {}", response.status());
     }
 
-    let mut image_url: Option<String> = None;
+    let data: serde_json::Value = response.json().await?;
+    let likes = |thumb: &serde_json::Value| {
+        thumb["likes"]
+            .as_str()
+            .and_then(|l| l.parse::<u64>().ok())
+            .unwrap_or(0)
+    };
 
-    // First try exact hash match
-    for result in results {
-        let result_name = result["name"].as_str().unwrap_or("");
-        let result_hash = create_hash(&[result_name], true);
+    Ok(data["artistthumb"]
+        .as_array()
+        .and_then(|thumbs| thumbs.iter().max_by_key(|t| likes(t)))
+        .and_then(|t| t["url"].as_str().map(|s| s.to_string())))
+}
 
-        if result_hash == artist_hash {
-            image_url = result["picture_big"].as_str().map(|s| s.to_string());
-            break;
+/// Get a spotify access token with the client credentials flow, reusing it until it expires
+async fn spotify_token(client: &reqwest::Client, settings: &ArtistImageSettings) -> Result<String> {
+    if let Some(cached) = SPOTIFY_TOKEN.lock().as_ref() {
+        if cached.client_id == settings.spotify_client_id && cached.expires > Instant::now() {
+            return Ok(cached.token.clone());
         }
     }
 
-    // Fallback: if no exact match, use the first result (likely the best match from Deezer)
-    if image_url.is_none() {
-        if let Some(first_result) = results.first() {
-            image_url = first_result["picture_big"].as_str().map(|s| s.to_string());
-        }
+    let response = client
+        .post("https://accounts.spotify.com/api/token")
+        .basic_auth(
+            &settings.spotify_client_id,
+            Some(&settings.spotify_client_secret),
+        )
+        .form(&[("grant_type", "client_credentials")])
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        anyhow::bail!("spotify token request returned {}", response.status());
     }
 
-    let Some(img_url) = image_url else {
-        return Ok(false);
-    };
+    let data: serde_json::Value = response.json().await?;
+    let token = data["access_token"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("No access token"))?
+        .to_string();
+    // renew a minute early so a token never expires mid lookup
+    let lifetime = data["expires_in"]
+        .as_u64()
+        .unwrap_or(3600)
+        .saturating_sub(60);
+
+    *SPOTIFY_TOKEN.lock() = Some(SpotifyToken {
+        client_id: settings.spotify_client_id.clone(),
+        token: token.clone(),
+        expires: Instant::now() + Duration::from_secs(lifetime),
+    });
+    Ok(token)
+}
 
-    // Download the image
-    let img_response = client.get(&img_url).send().await?;
-    if !img_response.status().is_success() {
-        return Ok(false);
+/// Find the url of an artist's picture on spotify in the configured size
+async fn spotify_artist_image_url(
+    client: &reqwest::Client,
+    settings: &ArtistImageSettings,
+    artist_name: &str,
+    artist_hash: &str,
+) -> Result<Option<String>> {
+    let token = spotify_token(client, settings).await?;
+
+    let response = client
+        .get("https://api.spotify.com/v1/search")
+        .query(&[("q", artist_name), ("type", "artist"), ("limit", "5")])
+        .bearer_auth(token)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        anyhow::bail!("spotify search returned {}", response.status());
     }
 
-    let img_bytes = img_response.bytes().await?;
-    let img = image::load_from_memory(&img_bytes)?;
+    let data: serde_json::Value = response.json().await?;
+    let results = data["artists"]["items"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    let has_images = |r: &&serde_json::Value| r["images"].as_array().is_some_and(|i| !i.is_empty());
+
+    let matched = results
+        .iter()
+        .filter(has_images)
+        .find(|r| create_hash(&[r["name"].as_str().unwrap_or("")], true) == artist_hash)
+        .or_else(|| results.iter().find(has_images));
+
+    Ok(matched
+        .and_then(|r| r["images"].as_array())
+        .and_then(|images| {
+            let pictures: Vec<(u64, &str)> = images
+                .iter()
+                .filter_map(|i| Some((i["width"].as_u64().unwrap_or(0), i["url"].as_str()?)))
+                .collect();
+            pick_image_url(&pictures, settings.image_size)
+        }))
+}
+
+/// Save an artist image in every size, replacing the current one
+fn save_artist_image(paths: &Paths, artisthash: &str, data: &[u8]) -> Result<()> {
+    let img = image::load_from_memory(data)?;
 
     // Save in 3 sizes
     let sizes = [
@@ -459,19 +1398,18 @@ async fn fetch_and_save_artist_image(
     ];
 
     let (orig_width, orig_height) = (img.width(), img.height());
+    if orig_width == 0 || orig_height == 0 {
+        anyhow::bail!("Image has no pixels");
+    }
     let ratio = orig_width as f32 / orig_height as f32;
 
     for (size_name, max_size) in &sizes {
         let dest = paths
             .artist_images_dir(size_name)
-            .join(format!("{}.webp", artist_hash));
-
-        if let Some(parent) = dest.parent() {
-            let _ = std::fs::create_dir_all(parent);
-        }
+            .join(format!("{}.webp", artisthash));
 
         let target_width = (*max_size).min(orig_width);
-        let target_height = (target_width as f32 / ratio) as u32;
+        let target_height = ((target_width as f32 / ratio) as u32).max(1);
 
         let resized = img.resize(
             target_width,
@@ -479,20 +1417,121 @@ async fn fetch_and_save_artist_image(
             image::imageops::FilterType::Triangle,
         );
         let mut buf = Vec::new();
-        if resized
-            .write_to(
-                &mut std::io::Cursor::new(&mut buf),
-                image::ImageFormat::WebP,
-            )
-            .is_ok()
-        {
-            let _ = std::fs::write(&dest, buf);
-        }
+        resized.write_to(
+            &mut std::io::Cursor::new(&mut buf),
+            image::ImageFormat::WebP,
+        )?;
+        write_atomic(&dest, &buf)?;
+    }
+
+    let _ = std::fs::remove_file(not_found_marker(paths, artisthash));
+    Ok(())
+}
+
+/// Replace an artist's image with an uploaded one
+pub async fn set_artist_image(artisthash: &str, data: &[u8]) -> Result<()> {
+    let paths = Paths::get()?;
+    save_artist_image(&paths, artisthash, data)?;
+    refresh_artist_image(artisthash).await
+}
+
+/// Replace an artist's image with the one a provider has, returns false when
+/// the provider has no image of the artist
+pub async fn fetch_artist_image_from(
+    artisthash: &str,
+    provider: ArtistImageProvider,
+) -> Result<bool> {
+    use crate::stores::ArtistStore;
+
+    let artist = ArtistStore::get()
+        .get_by_hash(artisthash)
+        .ok_or_else(|| anyhow::anyhow!("Artist not found"))?;
+    let settings = artist_image_settings();
+    if !settings.is_usable(provider) {
+        anyhow::bail!("{} is missing its credentials", provider.as_str());
     }
 
+    let client = artist_image_client()?;
+    let Some(data) = provider_artist_image(&client, &settings, provider, &artist).await? else {
+        return Ok(false);
+    };
+    set_artist_image(artisthash, &data).await?;
     Ok(true)
 }
 
+/// Drop an artist's image and look it up again with every configured provider
+///
+/// returns the provider the new image came from
+pub async fn reset_artist_image(artisthash: &str) -> Result<Option<ArtistImageProvider>> {
+    use crate::stores::ArtistStore;
+
+    let artist = ArtistStore::get()
+        .get_by_hash(artisthash)
+        .ok_or_else(|| anyhow::anyhow!("Artist not found"))?;
+    let paths = Paths::get()?;
+    for size in ["small", "medium", "large"] {
+        let _ = std::fs::remove_file(
+            paths
+                .artist_images_dir(size)
+                .join(format!("{}.webp", artisthash)),
+        );
+    }
+    let _ = std::fs::remove_file(not_found_marker(&paths, artisthash));
+
+    let settings = artist_image_settings();
+    let client = artist_image_client()?;
+    let lookup = find_artist_image(&client, &settings, &artist, &settings.usable_providers()).await;
+
+    match lookup.image {
+        Some((provider, data)) => {
+            set_artist_image(artisthash, &data).await?;
+            Ok(Some(provider))
+        }
+        None => {
+            write_not_found_marker(&paths, artisthash, &lookup.answered);
+            Ok(None)
+        }
+    }
+}
+
+/// Point the store at a new artist image and take its color
+async fn refresh_artist_image(artisthash: &str) -> Result<()> {
+    use crate::stores::ArtistStore;
+
+    let paths = Paths::get()?;
+    ArtistStore::get().set_image(artisthash, &format!("{}.webp", artisthash));
+
+    let small = paths
+        .artist_images_dir("small")
+        .join(format!("{}.webp", artisthash));
+    if let Some(color) = extract_dominant_color(&small) {
+        let variants = ColorLib::variants(&color);
+        save_artist_color(artisthash, &color, &variants).await?;
+    }
+    Ok(())
+}
+
+/// Store an artist's color in the database and the artist store
+async fn save_artist_color(artisthash: &str, color: &str, variants: &ColorVariants) -> Result<()> {
+    use crate::db::DbEngine;
+    use crate::stores::ArtistStore;
+
+    let db = DbEngine::get()?;
+    sqlx::query(
+        "INSERT INTO libdata (hash, type, color, color_dark, color_light) VALUES (?, 'artist', ?, ?, ?) 
+         ON CONFLICT(hash) DO UPDATE SET color = excluded.color, color_dark = excluded.color_dark, color_light = excluded.color_light",
+    )
+    .bind(artisthash)
+    .bind(color)
+    .bind(&variants.dark)
+    .bind(&variants.light)
+    .execute(db.pool())
+    .await?;
+
+    ArtistStore::get().set_color(artisthash, color, variants);
+    Ok(())
+}
+
 /// Extract dominant colors from artist images and store in database
 pub async fn extract_artist_colors() -> Result<usize> {
     use crate::db::DbEngine;
@@ -531,7 +1570,7 @@ pub async fn extract_artist_colors() -> Result<usize> {
     let paths_ref = &paths;
 
     // Extract colors in parallel
-    let color_results: Vec<(String, String)> = artists_needing_colors
+    let color_results: Vec<(String, String, ColorVariants)> = artists_needing_colors
         .par_iter()
         .filter_map(|artist| {
             // Use small artist image for color extraction
@@ -545,25 +1584,15 @@ pub async fn extract_artist_colors() -> Result<usize> {
 
             // Extract dominant color
             let color = extract_dominant_color(&img_path)?;
+            let variants = ColorLib::variants(&color);
             processed.fetch_add(1, Ordering::Relaxed);
-            Some((artist.artisthash.clone(), color))
+            Some((artist.artisthash.clone(), color, variants))
         })
         .collect();
 
     // Store colors in database and update in-memory store
-    for (artisthash, color) in &color_results {
-        // Insert or update in database
-        sqlx::query(
-            "INSERT INTO libdata (hash, type, color) VALUES (?, 'artist', ?) 
-             ON CONFLICT(hash) DO UPDATE SET color = excluded.color",
-        )
-        .bind(artisthash)
-        .bind(color)
-        .execute(db.pool())
-        .await?;
-
-        // Update in-memory store
-        ArtistStore::get().set_color(artisthash, color);
+    for (artisthash, color, variants) in &color_results {
+        save_artist_color(artisthash, color, variants).await?;
     }
 
     let count = color_results.len();
@@ -573,3 +1602,94 @@ pub async fn extract_artist_colors() -> Result<usize> {
 
     Ok(count)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prune_thumbnails() {
+        let dir = tempfile::tempdir().unwrap();
+        let kept = content_hash(b"cover");
+        let unused = content_hash(b"old cover");
+        for name in [
+            format!("{kept}.webp"),
+            format!("{kept}.avif"),
+            format!("{unused}.webp"),
+            "albumhash.webp".to_string(),
+            "custom.jpg".to_string(),
+        ] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+
+        let referenced = HashSet::from([kept.clone()]);
+        assert_eq!(prune_thumbnails(dir.path(), &referenced, true), 2);
+        assert!(dir.path().join(format!("{kept}.avif")).exists());
+        assert!(dir.path().join("custom.jpg").exists());
+
+        // avif copies go once avif is turned off
+        assert_eq!(prune_thumbnails(dir.path(), &referenced, false), 1);
+        assert!(dir.path().join(format!("{kept}.webp")).exists());
+        assert!(!dir.path().join(format!("{kept}.avif")).exists());
+    }
+
+    #[test]
+    fn test_color_is_stale() {
+        // no stored color
+        assert!(color_is_stale(None, Some("a"), false));
+        assert!(!color_is_stale(None, Some("a"), true));
+        // art changed since the color was taken
+        assert!(color_is_stale(Some("a"), Some("b"), true));
+        assert!(!color_is_stale(Some("a"), Some("a"), true));
+        // stored before art hashes were recorded
+        assert!(color_is_stale(Some(""), Some("a"), true));
+        // no thumbnail to take a color from
+        assert!(!color_is_stale(Some("a"), None, true));
+    }
+
+    #[test]
+    fn test_pick_image_url() {
+        let images = [(640, "large"), (160, "small"), (320, "medium")];
+        assert_eq!(pick_image_url(&images, 300).as_deref(), Some("medium"));
+        assert_eq!(pick_image_url(&images, 100).as_deref(), Some("small"));
+        // nothing is wide enough, the widest one is the closest
+        assert_eq!(pick_image_url(&images, 1000).as_deref(), Some("large"));
+        assert_eq!(pick_image_url(&[], 500), None);
+    }
+
+    #[test]
+    fn test_folder_cover_lookup() {
+        let dir = tempfile::tempdir().unwrap();
+        let track = dir.path().join("01 Intro.flac");
+        std::fs::write(&track, b"").unwrap();
+        assert_eq!(find_folder_image(&track), None);
+
+        // a lone image is taken as the cover whatever its name
+        std::fs::write(dir.path().join("scan.jpg"), b"").unwrap();
+        assert_eq!(find_folder_image(&track), Some(dir.path().join("scan.jpg")));
+
+        // with several images only cover names count
+        std::fs::write(dir.path().join("booklet.jpg"), b"").unwrap();
+        assert_eq!(find_folder_image(&track), None);
+
+        std::fs::write(dir.path().join("Front.PNG"), b"").unwrap();
+        std::fs::write(dir.path().join("folder.jpg"), b"").unwrap();
+        assert_eq!(
+            find_folder_image(&track),
+            Some(dir.path().join("folder.jpg"))
+        );
+
+        std::fs::write(dir.path().join("Cover.jpg"), b"").unwrap();
+        let cover = dir.path().join("Cover.jpg");
+        assert_eq!(find_folder_image(&track), Some(cover.clone()));
+
+        // art built from the track or a lesser image is stale once a cover shows up
+        assert!(folder_image_changed(&track, CoverSource::Folder));
+        assert!(!folder_image_changed(&track, CoverSource::Embedded));
+        assert!(folder_image_changed(
+            &dir.path().join("folder.jpg"),
+            CoverSource::Embedded
+        ));
+        assert!(!folder_image_changed(&cover, CoverSource::Folder));
+    }
+}
@@ -0,0 +1,349 @@
+//! Inbox of new files waiting to be filed into the library
+//!
+//! files dropped in the inbox folder are matched on MusicBrainz, tagged with
+//! the match, renamed by the file template and moved into the first root
+//! directory, where they are indexed like any other track. files without a
+//! confident match, or that could not be filed, wait in a review queue until
+//! a match is picked for them or they are filed with their own tags. the queue
+//! only lives in memory, the next scan of the inbox fills it again.
+
+use anyhow::{anyhow, bail, Context, Result};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
+use walkdir::WalkDir;
+
+use crate::config::UserConfig;
+use crate::core::indexer::Indexer;
+use crate::core::organizer;
+use crate::core::populate::reindex_track_files;
+use crate::core::watchdogg::Watchdog;
+use crate::models::Track;
+use crate::plugins::musicbrainz::MatchCandidate;
+use crate::plugins::MusicBrainzPlugin;
+use crate::stores::FolderStore;
+use crate::utils::hashing::create_hash;
+
+/// Score a match needs to be applied without a review
+pub const AUTO_FILE_SCORE: u8 = 90;
+
+/// Candidates kept for a file waiting for review
+const MATCH_LIMIT: usize = 5;
+
+static QUEUE: Lazy<RwLock<HashMap<String, InboxItem>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// Files left in the inbox on purpose, scans skip them until a restart
+static IGNORED: Lazy<RwLock<HashSet<String>>> = Lazy::new(|| RwLock::new(HashSet::new()));
+
+/// Scans and filing run one at a time so a file is never filed twice
+static FILING: Mutex<()> = Mutex::const_new(());
+
+/// A file waiting for review
+#[derive(Debug, Clone, Serialize)]
+pub struct InboxItem {
+    pub id: String,
+    pub filepath: String,
+    pub title: String,
+    pub artists: Vec<String>,
+    pub album: String,
+    pub duration: i32,
+    /// why the file was not filed on its own
+    pub reason: String,
+    /// MusicBrainz candidates, best match first
+    pub matches: Vec<MatchCandidate>,
+    pub found: i64,
+}
+
+/// What a scan of the inbox did
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ScanSummary {
+    pub filed: usize,
+    pub queued: usize,
+}
+
+/// The inbox folder, `None` when there is no inbox
+pub fn inbox_dir(config: &UserConfig) -> Option<PathBuf> {
+    let dir = config.inbox_dir.trim();
+    (!dir.is_empty()).then(|| PathBuf::from(dir))
+}
+
+/// Check an inbox folder before it is saved, it can't overlap a root directory
+/// or the scanner would index files before they are filed
+pub fn validate_dir(dir: &str, roots: &[String]) -> Result<()> {
+    let path = Path::new(dir.trim());
+    if !path.is_absolute() {
+        bail!("Inbox folder must be an absolute path");
+    }
+    if roots
+        .iter()
+        .any(|root| path.starts_with(root) || Path::new(root).starts_with(path))
+    {
+        bail!("Inbox folder can't be inside or contain a root directory");
+    }
+    Ok(())
+}
+
+/// Files waiting for review, oldest first
+pub fn queue() -> Vec<InboxItem> {
+    let mut items: Vec<InboxItem> = QUEUE.read().values().cloned().collect();
+    items.sort_by(|a, b| a.found.cmp(&b.found).then(a.filepath.cmp(&b.filepath)));
+    items
+}
+
+/// File what was dropped in the inbox since the last scan, files without a
+/// confident match go to the review queue
+pub async fn scan() -> Result<ScanSummary> {
+    let config = UserConfig::load()?;
+    let dir = inbox_dir(&config).ok_or_else(|| anyhow!("No inbox folder is set"))?;
+    let _guard = FILING.lock().await;
+
+    let files = tokio::task::spawn_blocking(move || audio_files(&dir)).await?;
+    let present: HashSet<String> = files
+        .iter()
+        .map(|f| f.to_string_lossy().to_string())
+        .collect();
+
+    // files taken out of the inbox by hand leave the queue
+    QUEUE
+        .write()
+        .retain(|_, item| present.contains(&item.filepath));
+    let new: Vec<PathBuf> = {
+        let queue = QUEUE.read();
+        let ignored = IGNORED.read();
+        files
+            .into_iter()
+            .filter(|f| {
+                let path = f.to_string_lossy();
+                !ignored.contains(path.as_ref()) && !queue.contains_key(&item_id(&path))
+            })
+            .collect()
+    };
+    if new.is_empty() {
+        return Ok(ScanSummary::default());
+    }
+
+    let tracks = read_tracks(&config, new).await?;
+    let plugin = MusicBrainzPlugin::load().await?;
+    let mut summary = ScanSummary::default();
+
+    for track in tracks {
+        let (matches, reason) = match &plugin {
+            Some(plugin) => match plugin.find_matches(&track, MATCH_LIMIT).await {
+                Ok(matches) => (
+                    matches,
+                    "No MusicBrainz match is confident enough".to_string(),
+                ),
+                Err(e) => (Vec::new(), format!("MusicBrainz lookup failed: {}", e)),
+            },
+            None => (Vec::new(), "MusicBrainz plugin is not active".to_string()),
+        };
+
+        let best = matches.first().filter(|m| m.score >= AUTO_FILE_SCORE);
+        let reason = match best {
+            Some(best) => match file_track(&config, Path::new(&track.filepath), Some(best)).await {
+                Ok(_) => {
+                    summary.filed += 1;
+                    continue;
+                }
+                Err(e) => format!("{:#}", e),
+            },
+            None => reason,
+        };
+
+        enqueue(&track, matches, reason);
+        summary.queued += 1;
+    }
+
+    Ok(summary)
+}
+
+/// File a queued file with a MusicBrainz recording, or with its own tags when
+/// no recording is given. `None` when the file is not in the queue
+pub async fn file(
+    id: &str,
+    recording_mbid: Option<&str>,
+    release_mbid: Option<&str>,
+) -> Result<Option<Track>> {
+    let _guard = FILING.lock().await;
+    let Some(item) = QUEUE.read().get(id).cloned() else {
+        return Ok(None);
+    };
+
+    let candidate = match recording_mbid {
+        Some(mbid) => {
+            let known = item.matches.iter().find(|m| {
+                m.recording_mbid == mbid && release_mbid.is_none_or(|r| m.release_mbid == r)
+            });
+            match known {
+                Some(candidate) => Some(candidate.clone()),
+                None => {
+                    let plugin = MusicBrainzPlugin::load()
+                        .await?
+                        .ok_or_else(|| anyhow!("MusicBrainz plugin is not active"))?;
+                    Some(plugin.get_recording(mbid, release_mbid).await?)
+                }
+            }
+        }
+        None => None,
+    };
+
+    let config = UserConfig::load()?;
+    match file_track(&config, Path::new(&item.filepath), candidate.as_ref()).await {
+        Ok(track) => {
+            QUEUE.write().remove(id);
+            Ok(Some(track))
+        }
+        Err(e) => {
+            if let Some(queued) = QUEUE.write().get_mut(id) {
+                queued.reason = format!("{:#}", e);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Take a file out of the queue, deleting it or leaving it in the inbox where
+/// scans skip it. returns whether the file was queued
+pub async fn dismiss(id: &str, delete: bool) -> Result<bool> {
+    let _guard = FILING.lock().await;
+    let Some(item) = QUEUE.write().remove(id) else {
+        return Ok(false);
+    };
+
+    if delete {
+        std::fs::remove_file(&item.filepath)
+            .with_context(|| format!("Failed to delete {}", item.filepath))?;
+    } else {
+        IGNORED.write().insert(item.filepath);
+    }
+    Ok(true)
+}
+
+/// Tag a file with the match, move it into the first root directory by the
+/// file template and index it
+async fn file_track(
+    config: &UserConfig,
+    path: &Path,
+    candidate: Option<&MatchCandidate>,
+) -> Result<Track> {
+    let root = config
+        .root_dirs
+        .first()
+        .ok_or_else(|| anyhow!("No root directory to file into"))?;
+
+    if let Some(candidate) = candidate {
+        let candidate = candidate.clone();
+        let target = path.to_path_buf();
+        tokio::task::spawn_blocking(move || candidate.write_tags(&target)).await??;
+    }
+
+    // the tags may have changed, so the destination comes from a fresh read
+    let track = read_tracks(config, vec![path.to_path_buf()])
+        .await?
+        .pop()
+        .ok_or_else(|| anyhow!("Failed to read {}", path.display()))?;
+    let to = Path::new(root).join(organizer::render(&config.file_template, &track)?);
+    if to.exists() {
+        bail!("{} already exists", to.display());
+    }
+
+    let from = path.to_path_buf();
+    let dest = to.clone();
+    tokio::task::spawn_blocking(move || organizer::move_file(&from, &dest)).await??;
+
+    let to = to.to_string_lossy().to_string();
+    let indexed = reindex_track_files(std::slice::from_ref(&to)).await?;
+    FolderStore::load_filepaths().await?;
+
+    if let Some(dir) = inbox_dir(config) {
+        let parent = path.parent().map(Path::to_path_buf);
+        tokio::task::spawn_blocking(move || prune_empty_dirs(parent.as_deref(), &dir)).await?;
+    }
+
+    indexed
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("{} was moved but could not be indexed", to))
+}
+
+fn enqueue(track: &Track, matches: Vec<MatchCandidate>, reason: String) {
+    let id = item_id(&track.filepath);
+    let item = InboxItem {
+        id: id.clone(),
+        filepath: track.filepath.clone(),
+        title: track.title.clone(),
+        artists: track.artists.iter().map(|a| a.name.clone()).collect(),
+        album: track.album.clone(),
+        duration: track.duration,
+        reason,
+        matches,
+        found: chrono::Utc::now().timestamp(),
+    };
+    QUEUE.write().insert(id, item);
+}
+
+fn item_id(filepath: &str) -> String {
+    create_hash(&[filepath], false)
+}
+
+async fn read_tracks(config: &UserConfig, paths: Vec<PathBuf>) -> Result<Vec<Track>> {
+    let indexer = Indexer::from_config(config).with_progress(false);
+    tokio::task::spawn_blocking(move || indexer.reindex_files(&paths)).await?
+}
+
+/// Audio files under the inbox folder
+fn audio_files(dir: &Path) -> Vec<PathBuf> {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .filter(Watchdog::is_audio_file)
+        .collect()
+}
+
+/// Remove folders left empty in the inbox, keeping the inbox itself
+fn prune_empty_dirs(dir: Option<&Path>, inbox: &Path) {
+    let mut current = dir;
+    while let Some(dir) = current.filter(|d| d.starts_with(inbox) && *d != inbox) {
+        if std::fs::remove_dir(dir).is_err() {
+            break;
+        }
+        current = dir.parent();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_dir() {
+        let roots = vec!["/music".to_string()];
+        assert!(validate_dir("/downloads/inbox", &roots).is_ok());
+        assert!(validate_dir("inbox", &roots).is_err());
+        assert!(validate_dir("/music/inbox", &roots).is_err());
+        assert!(validate_dir("/", &roots).is_err());
+    }
+
+    #[test]
+    fn test_prune_empty_dirs() {
+        let inbox = std::env::temp_dir().join(format!("swing-inbox-{}", std::process::id()));
+        let nested = inbox.join("artist/album");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(inbox.join("artist/keep.mp3"), b"").unwrap();
+
+        prune_empty_dirs(Some(&nested), &inbox);
+        assert!(!nested.exists());
+        assert!(inbox.join("artist").exists());
+
+        std::fs::remove_file(inbox.join("artist/keep.mp3")).unwrap();
+        prune_empty_dirs(Some(&inbox.join("artist")), &inbox);
+        assert!(inbox.exists());
+        assert!(!inbox.join("artist").exists());
+        std::fs::remove_dir(&inbox).unwrap();
+    }
+}
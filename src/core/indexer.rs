@@ -4,23 +4,39 @@
 //! - lofty for in-process metadata extraction (no subprocess spawning)
 //! - rayon for parallel file processing across all cpu cores
 //! - pre-cached config to avoid repeated disk i/o
+//! - a bounded channel streaming tracks to the caller in batches so library
+//!   scans can write to the database while tags are still being read
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use indicatif::{ProgressBar, ProgressStyle};
-use lofty::{Accessor, AudioFile, ItemKey, Probe, TaggedFileExt};
+use lofty::id3::v2::Popularimeter;
+use lofty::{
+    Accessor, AudioFile, FileType, ItemKey, ItemValue, Probe, Tag, TaggedFile, TaggedFileExt,
+};
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
 use rayon::prelude::*;
-use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+use std::panic::AssertUnwindSafe;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicI64, AtomicU64, AtomicU8, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{mpsc, oneshot};
 use walkdir::{DirEntry, WalkDir};
 
-use crate::config::UserConfig;
-use crate::core::ffmpeg;
-use crate::models::Track;
+use crate::config::{Paths, SplitField, UserConfig};
+use crate::core::lyrics::LyricsLib;
+use crate::core::{artist_split, ffmpeg};
+use crate::db::tables::{ArtistSplit, ScanHistoryTable, ScanRecord};
+use crate::models::{Track, TrackExtra};
 use crate::utils::artist_split_detector::split_artists_smart;
+use crate::utils::filesystem::describe_io_error;
 use crate::utils::hashing::{create_hash, create_track_hash};
-use crate::utils::parsers::clean_title;
+use crate::utils::parsers::{clean_title, parse_year, split_genres};
 use crate::utils::tracks::remove_remaster_info;
 
 /// supported audio extensions
@@ -36,28 +52,801 @@ const AUDIO_EXTENSIONS: &[&str] = &[
     "ape", "wv", "mpc", "tta", "dsf", "dff", "webm", "mka", "spx",
 ];
 
+/// playlist files picked up next to the music, see [`crate::core::folder_playlists`]
+const PLAYLIST_EXTENSIONS: &[&str] = &["m3u", "m3u8"];
+
+/// tracks handed to the caller per batch by [`next_batch`]
+pub const SCAN_BATCH_SIZE: usize = 500;
+
+/// extracted tracks buffered before the tag readers wait for the consumer
+const CHANNEL_CAPACITY: usize = SCAN_BATCH_SIZE * 4;
+
+/// files modified this recently are stat'ed twice to catch copies in progress
+const COPY_SETTLE_WINDOW: Duration = Duration::from_secs(60);
+
+/// minimum gap between the two stats of a recently modified file
+const COPY_RECHECK_DELAY: Duration = Duration::from_secs(2);
+
+/// deferred paths listed in the scan state, the rest are only counted
+const MAX_REPORTED_DEFERRED: usize = 100;
+
+/// failed files listed in the scan history, the rest are only counted
+const MAX_REPORTED_FAILED: usize = 100;
+
+/// duplicate paths listed per pair of conflicting roots, the rest are only counted
+const MAX_REPORTED_DUPLICATES: usize = 10;
+
+/// unreadable paths listed in the scan state, the rest are only counted
+const MAX_REPORTED_UNREADABLE: usize = 50;
+
+/// os metadata files that never hold music, compared lowercased
+///
+/// hidden files such as `.DS_Store` and appledouble `._*` files are caught by
+/// their leading dot
+const SYSTEM_FILES: &[&str] = &["thumbs.db", "ehthumbs.db", "desktop.ini"];
+
+/// progress of the running or most recent library scan
+static SCAN_PROGRESS: Lazy<RwLock<Option<Arc<ScanProgress>>>> = Lazy::new(|| RwLock::new(None));
+
+/// state of the last finished scan, kept on disk across restarts
+static LAST_SCAN: Lazy<RwLock<ScanState>> = Lazy::new(|| RwLock::new(ScanState::load()));
+
+/// what a library scan is busy with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanPhase {
+    #[default]
+    Idle,
+    /// walking the root directories for audio files
+    Walking,
+    /// reading tags and writing tracks
+    Tagging,
+    /// reloading stores, images and colors
+    Finalizing,
+}
+
+impl ScanPhase {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Walking,
+            2 => Self::Tagging,
+            3 => Self::Finalizing,
+            _ => Self::Idle,
+        }
+    }
+}
+
+/// what started a library scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanKind {
+    /// first scan of an empty library at startup
+    Initial,
+    /// every file read again
+    Full,
+    /// only new and changed files read
+    Incremental,
+}
+
+impl ScanKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ScanKind::Initial => "initial",
+            ScanKind::Full => "full",
+            ScanKind::Incremental => "incremental",
+        }
+    }
+}
+
+/// live counters of a library scan, updated from the tag reader threads
+pub struct ScanProgress {
+    kind: ScanKind,
+    started_at: i64,
+    started: Instant,
+    finished_at: AtomicI64,
+    phase: AtomicU8,
+    tagging_started: Mutex<Option<Instant>>,
+    roots: Vec<RootProgress>,
+    files_failed: AtomicUsize,
+    tracks_written: AtomicUsize,
+    skipped: Mutex<SkippedFiles>,
+    conflicts: Mutex<Vec<RootConflict>>,
+    unreadable: Mutex<UnreadablePaths>,
+    failures: Mutex<Vec<FailedFile>>,
+    changes: Mutex<ScanChanges>,
+    settings: ScanSettings,
+    error: Mutex<Option<(String, i64)>>,
+}
+
+struct RootProgress {
+    path: String,
+    files_found: AtomicUsize,
+    files_queued: AtomicUsize,
+    files_processed: AtomicUsize,
+    files_skipped: AtomicUsize,
+}
+
+/// snapshot of the running scan, or of the last one when idle
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScanState {
+    pub phase: ScanPhase,
+    pub running: bool,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    pub files_seen: usize,
+    pub files_queued: usize,
+    pub files_processed: usize,
+    pub files_failed: usize,
+    pub tracks_written: usize,
+    /// how the library changed, filled in once the tracks are written
+    #[serde(default)]
+    pub changes: ScanChanges,
+    /// files the walker left out of the scan
+    #[serde(default)]
+    pub skipped: SkippedFiles,
+    /// roots that expose the same files, only one copy of each is indexed
+    #[serde(default)]
+    pub conflicts: Vec<RootConflict>,
+    /// directories and files the walker could not read, e.g. a mount the
+    /// server user has no permission for
+    #[serde(default)]
+    pub unreadable: UnreadablePaths,
+    /// estimated seconds left while tagging
+    pub eta_seconds: Option<u64>,
+    /// most recent scan failure, kept until a later scan fails
+    pub last_error: Option<String>,
+    pub last_error_at: Option<i64>,
+    pub roots: Vec<RootScanState>,
+}
+
+/// progress of a single root directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RootScanState {
+    pub path: String,
+    pub files_seen: usize,
+    pub files_queued: usize,
+    pub files_processed: usize,
+    #[serde(default)]
+    pub files_skipped: usize,
+}
+
+/// files left out of a scan by reason
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedFiles {
+    /// hidden and os metadata files such as `.DS_Store` or `Thumbs.db`
+    pub system: usize,
+    /// zero-byte audio files
+    pub empty: usize,
+    /// audio files still being written, picked up by the next pass
+    pub copying: usize,
+    /// audio files already found under another root
+    #[serde(default)]
+    pub duplicate: usize,
+    /// the first few deferred paths
+    pub deferred: Vec<String>,
+}
+
+/// two roots that reach the same files, e.g. overlapping roots or bind mounts
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RootConflict {
+    /// root whose copies are indexed
+    pub root: String,
+    /// root whose copies are left out
+    pub duplicate_root: String,
+    pub files: usize,
+    /// the first few paths left out
+    pub paths: Vec<String>,
+}
+
+/// paths a scan could not read
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnreadablePaths {
+    pub count: usize,
+    /// permission denied paths, kept apart since they usually mean a mount
+    /// or ownership problem
+    pub permission_denied: usize,
+    /// the first few paths
+    pub paths: Vec<UnreadablePath>,
+}
+
+/// a directory or file the walker could not read
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnreadablePath {
+    pub path: String,
+    pub error: String,
+    #[serde(default)]
+    pub permission_denied: bool,
+}
+
+impl UnreadablePath {
+    fn from_walk_error(error: &walkdir::Error) -> Self {
+        let path = error
+            .path()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default();
+        match error.io_error() {
+            Some(io) => Self {
+                path,
+                error: describe_io_error(io),
+                permission_denied: io.kind() == std::io::ErrorKind::PermissionDenied,
+            },
+            None => Self {
+                path,
+                error: error.to_string(),
+                permission_denied: false,
+            },
+        }
+    }
+}
+
+/// tracks a scan added, updated and removed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanChanges {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    /// tracks in the library after the scan
+    pub total: usize,
+}
+
+/// a file whose tags could not be read
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedFile {
+    pub path: String,
+    pub error: String,
+}
+
+/// library settings a scan ran with, kept in the scan history
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScanSettings {
+    pub root_dirs: Vec<String>,
+    pub exclude_dirs: Vec<String>,
+    pub artist_separators: Vec<String>,
+    #[serde(default)]
+    pub album_artist_separators: Vec<String>,
+    pub artist_split_ignore_list: Vec<String>,
+    pub genre_separators: Vec<String>,
+    pub extract_featured_artists: bool,
+    pub remove_prod_by: bool,
+    pub remove_remaster_info: bool,
+    pub merge_albums: bool,
+    pub clean_album_title: bool,
+    pub show_albums_as_singles: bool,
+}
+
+impl ScanSettings {
+    fn from_config(config: &UserConfig) -> Self {
+        let sorted = |set: &HashSet<String>| {
+            let mut values: Vec<String> = set.iter().cloned().collect();
+            values.sort();
+            values
+        };
+        Self {
+            root_dirs: config.root_dirs.clone(),
+            exclude_dirs: config.exclude_dirs.clone(),
+            artist_separators: sorted(&config.artist_separators),
+            album_artist_separators: sorted(&config.album_artist_separators),
+            artist_split_ignore_list: sorted(&config.artist_split_ignore_list),
+            genre_separators: sorted(&config.genre_separators),
+            extract_featured_artists: config.extract_featured_artists,
+            remove_prod_by: config.remove_prod_by,
+            remove_remaster_info: config.remove_remaster_info,
+            merge_albums: config.merge_albums,
+            clean_album_title: config.clean_album_title,
+            show_albums_as_singles: config.show_albums_as_singles,
+        }
+    }
+}
+
+/// files found under one root directory
+#[derive(Debug, Default)]
+pub struct RootFiles {
+    /// audio files ready to be indexed
+    pub files: Vec<PathBuf>,
+    /// audio files still being copied, left for the next pass
+    pub deferred: Vec<PathBuf>,
+    pub skipped_system: usize,
+    pub skipped_empty: usize,
+    /// audio files already found under an earlier root
+    pub duplicates: Vec<DuplicateFile>,
+    /// directories and files the walk could not read
+    pub unreadable: Vec<UnreadablePath>,
+    /// m3u playlists found next to the music
+    pub playlists: Vec<PathBuf>,
+}
+
+/// a file left out because an earlier root already reaches it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateFile {
+    pub path: PathBuf,
+    /// the copy that is indexed instead
+    pub kept: PathBuf,
+    /// index of the root the kept copy was found under
+    pub kept_root: usize,
+}
+
+impl ScanState {
+    fn load() -> Self {
+        Paths::get()
+            .ok()
+            .and_then(|paths| std::fs::read_to_string(paths.scan_state_path()).ok())
+            .and_then(|json| serde_json::from_str::<Self>(&json).ok())
+            .map(|state| Self {
+                phase: ScanPhase::Idle,
+                running: false,
+                eta_seconds: None,
+                ..state
+            })
+            .unwrap_or_default()
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = Paths::get()?.scan_state_path();
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// marks the scan finished when dropped, including on errors
+pub struct ScanGuard(Arc<ScanProgress>);
+
+impl ScanGuard {
+    /// shared handle for the tag reader threads
+    pub fn handle(&self) -> Arc<ScanProgress> {
+        self.0.clone()
+    }
+
+    /// record why the scan failed
+    pub fn fail(&self, error: &str) {
+        *self.0.error.lock() = Some((error.to_string(), chrono::Utc::now().timestamp()));
+    }
+}
+
+impl std::ops::Deref for ScanGuard {
+    type Target = ScanProgress;
+
+    fn deref(&self) -> &ScanProgress {
+        &self.0
+    }
+}
+
+impl Drop for ScanGuard {
+    fn drop(&mut self) {
+        self.0
+            .finished_at
+            .store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+        self.0.set_phase(ScanPhase::Idle);
+
+        let state = self.0.snapshot();
+        if let Err(e) = state.save() {
+            tracing::warn!("failed to save scan state: {}", e);
+        }
+
+        let record = self.0.record(&state);
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(async move {
+                    if let Err(e) = ScanHistoryTable::insert(&record).await {
+                        tracing::warn!("failed to save scan history: {}", e);
+                    }
+                });
+            }
+            Err(_) => tracing::warn!("scan finished outside the runtime, history not saved"),
+        }
+        *LAST_SCAN.write() = state;
+    }
+}
+
+impl ScanProgress {
+    /// start tracking a scan, `None` while another scan is running
+    pub fn begin(roots: &[PathBuf], kind: ScanKind) -> Option<ScanGuard> {
+        let mut current = SCAN_PROGRESS.write();
+        if current.as_ref().is_some_and(|p| p.is_running()) {
+            return None;
+        }
+
+        let settings = UserConfig::load()
+            .map(|config| ScanSettings::from_config(&config))
+            .unwrap_or_default();
+        let progress = Arc::new(Self {
+            kind,
+            started_at: chrono::Utc::now().timestamp(),
+            started: Instant::now(),
+            finished_at: AtomicI64::new(0),
+            phase: AtomicU8::new(ScanPhase::Walking as u8),
+            tagging_started: Mutex::new(None),
+            roots: roots
+                .iter()
+                .map(|root| RootProgress {
+                    path: root.to_string_lossy().to_string(),
+                    files_found: AtomicUsize::new(0),
+                    files_queued: AtomicUsize::new(0),
+                    files_processed: AtomicUsize::new(0),
+                    files_skipped: AtomicUsize::new(0),
+                })
+                .collect(),
+            files_failed: AtomicUsize::new(0),
+            tracks_written: AtomicUsize::new(0),
+            skipped: Mutex::new(SkippedFiles::default()),
+            conflicts: Mutex::new(Vec::new()),
+            unreadable: Mutex::new(UnreadablePaths::default()),
+            failures: Mutex::new(Vec::new()),
+            changes: Mutex::new(ScanChanges::default()),
+            settings,
+            error: Mutex::new(None),
+        });
+        *current = Some(progress.clone());
+        Some(ScanGuard(progress))
+    }
+
+    /// state of the running scan, or of the last finished one
+    pub fn state() -> ScanState {
+        let running = SCAN_PROGRESS
+            .read()
+            .as_ref()
+            .filter(|p| p.is_running())
+            .map(|p| p.snapshot());
+        running.unwrap_or_else(|| LAST_SCAN.read().clone())
+    }
+
+    fn is_running(&self) -> bool {
+        self.finished_at.load(Ordering::Relaxed) == 0
+    }
+
+    /// move the scan on to another phase
+    pub fn set_phase(&self, phase: ScanPhase) {
+        if phase == ScanPhase::Tagging {
+            self.tagging_started.lock().get_or_insert_with(Instant::now);
+        }
+        self.phase.store(phase as u8, Ordering::Relaxed);
+    }
+
+    /// record the files found under a root
+    pub fn set_found(&self, root: usize, count: usize) {
+        if let Some(r) = self.roots.get(root) {
+            r.files_found.store(count, Ordering::Relaxed);
+        }
+    }
+
+    /// record the files the walker left out under a root
+    pub fn add_skipped(&self, root: usize, files: &RootFiles) {
+        if let Some(r) = self.roots.get(root) {
+            let count = files.skipped_system
+                + files.skipped_empty
+                + files.deferred.len()
+                + files.duplicates.len();
+            r.files_skipped.fetch_add(count, Ordering::Relaxed);
+        }
+        self.add_duplicates(root, &files.duplicates);
+        self.add_unreadable(root, &files.unreadable);
+
+        let mut skipped = self.skipped.lock();
+        skipped.system += files.skipped_system;
+        skipped.empty += files.skipped_empty;
+        skipped.copying += files.deferred.len();
+        skipped.duplicate += files.duplicates.len();
+        let room = MAX_REPORTED_DEFERRED.saturating_sub(skipped.deferred.len());
+        skipped.deferred.extend(
+            files
+                .deferred
+                .iter()
+                .take(room)
+                .map(|p| p.to_string_lossy().to_string()),
+        );
+    }
+
+    fn add_duplicates(&self, root: usize, duplicates: &[DuplicateFile]) {
+        let root_path = |index: usize| {
+            self.roots
+                .get(index)
+                .map(|r| r.path.clone())
+                .unwrap_or_default()
+        };
+
+        let mut conflicts = self.conflicts.lock();
+        let mut touched = Vec::new();
+        for duplicate in duplicates {
+            let kept_root = root_path(duplicate.kept_root);
+            let duplicate_root = root_path(root);
+            let index = match conflicts
+                .iter()
+                .position(|c| c.root == kept_root && c.duplicate_root == duplicate_root)
+            {
+                Some(index) => index,
+                None => {
+                    conflicts.push(RootConflict {
+                        root: kept_root,
+                        duplicate_root,
+                        ..Default::default()
+                    });
+                    conflicts.len() - 1
+                }
+            };
+            let conflict = &mut conflicts[index];
+            conflict.files += 1;
+            if conflict.paths.len() < MAX_REPORTED_DUPLICATES {
+                conflict
+                    .paths
+                    .push(duplicate.path.to_string_lossy().to_string());
+            }
+            if !touched.contains(&index) {
+                touched.push(index);
+            }
+        }
+
+        for index in touched {
+            let conflict = &conflicts[index];
+            tracing::warn!(
+                "{} files under {} are also under {}, indexing only the copies under the latter",
+                conflict.files,
+                conflict.duplicate_root,
+                conflict.root
+            );
+        }
+    }
+
+    fn add_unreadable(&self, root: usize, paths: &[UnreadablePath]) {
+        if paths.is_empty() {
+            return;
+        }
+
+        let denied = paths.iter().filter(|p| p.permission_denied).count();
+        let mut unreadable = self.unreadable.lock();
+        unreadable.count += paths.len();
+        unreadable.permission_denied += denied;
+        let room = MAX_REPORTED_UNREADABLE.saturating_sub(unreadable.paths.len());
+        unreadable.paths.extend(paths.iter().take(room).cloned());
+
+        let root = self.roots.get(root).map_or("", |r| r.path.as_str());
+        if denied > 0 {
+            tracing::warn!(
+                "{} paths under {} are not readable by the server user, first: {}",
+                denied,
+                root,
+                paths
+                    .iter()
+                    .find(|p| p.permission_denied)
+                    .map_or("", |p| p.path.as_str())
+            );
+        }
+        if paths.len() > denied {
+            tracing::warn!(
+                "{} paths under {} could not be read",
+                paths.len() - denied,
+                root
+            );
+        }
+    }
+
+    /// record the files of a root that need their tags read
+    pub fn add_queued(&self, root: usize, count: usize) {
+        if let Some(r) = self.roots.get(root) {
+            r.files_queued.fetch_add(count, Ordering::Relaxed);
+        }
+    }
+
+    /// record tracks written to the database
+    pub fn add_written(&self, count: usize) {
+        self.tracks_written.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// record how the library changed
+    pub fn set_changes(&self, changes: ScanChanges) {
+        *self.changes.lock() = changes;
+    }
+
+    fn add_processed(&self, root: usize, failed: bool) {
+        if let Some(r) = self.roots.get(root) {
+            r.files_processed.fetch_add(1, Ordering::Relaxed);
+        }
+        if failed {
+            self.files_failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn add_failure(&self, path: &Path, error: &anyhow::Error) {
+        let mut failures = self.failures.lock();
+        if failures.len() < MAX_REPORTED_FAILED {
+            // tag readers bury io errors in their own, name permission problems plainly
+            let error = match std::fs::File::open(path) {
+                Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => describe_io_error(&e),
+                _ => error.to_string(),
+            };
+            failures.push(FailedFile {
+                path: path.to_string_lossy().to_string(),
+                error,
+            });
+        }
+    }
+
+    /// history record of the finished scan
+    fn record(&self, state: &ScanState) -> ScanRecord {
+        let error = self.error.lock().as_ref().map(|(error, _)| error.clone());
+        let skipped = &state.skipped;
+        let details = serde_json::json!({
+            "failed_files": *self.failures.lock(),
+            "skipped": skipped,
+            "conflicts": state.conflicts,
+            "unreadable": state.unreadable,
+            "roots": state.roots,
+            "settings": self.settings,
+        });
+
+        ScanRecord {
+            id: 0,
+            kind: self.kind.as_str().to_string(),
+            started_at: self.started_at,
+            finished_at: state.finished_at.unwrap_or(self.started_at),
+            duration_ms: self.started.elapsed().as_millis() as i64,
+            files_seen: state.files_seen as i64,
+            files_processed: state.files_processed as i64,
+            files_failed: state.files_failed as i64,
+            files_skipped: (skipped.system + skipped.empty + skipped.copying + skipped.duplicate)
+                as i64,
+            tracks_added: state.changes.added as i64,
+            tracks_updated: state.changes.updated as i64,
+            tracks_removed: state.changes.removed as i64,
+            tracks_total: state.changes.total as i64,
+            error,
+            details,
+        }
+    }
+
+    fn snapshot(&self) -> ScanState {
+        let roots: Vec<RootScanState> = self
+            .roots
+            .iter()
+            .map(|r| RootScanState {
+                path: r.path.clone(),
+                files_seen: r.files_found.load(Ordering::Relaxed),
+                files_queued: r.files_queued.load(Ordering::Relaxed),
+                files_processed: r.files_processed.load(Ordering::Relaxed),
+                files_skipped: r.files_skipped.load(Ordering::Relaxed),
+            })
+            .collect();
+        let finished_at = self.finished_at.load(Ordering::Relaxed);
+        let phase = ScanPhase::from_u8(self.phase.load(Ordering::Relaxed));
+        let files_queued: usize = roots.iter().map(|r| r.files_queued).sum();
+        let files_processed: usize = roots.iter().map(|r| r.files_processed).sum();
+
+        let eta_seconds = match *self.tagging_started.lock() {
+            Some(started) if phase == ScanPhase::Tagging => {
+                estimate_eta(started.elapsed(), files_processed, files_queued)
+            }
+            _ => None,
+        };
+
+        let (last_error, last_error_at) = match self.error.lock().clone() {
+            Some((error, at)) => (Some(error), Some(at)),
+            None => {
+                let last = LAST_SCAN.read();
+                (last.last_error.clone(), last.last_error_at)
+            }
+        };
+
+        ScanState {
+            phase,
+            running: finished_at == 0,
+            started_at: Some(self.started_at),
+            finished_at: (finished_at != 0).then_some(finished_at),
+            files_seen: roots.iter().map(|r| r.files_seen).sum(),
+            files_queued,
+            files_processed,
+            files_failed: self.files_failed.load(Ordering::Relaxed),
+            tracks_written: self.tracks_written.load(Ordering::Relaxed),
+            changes: *self.changes.lock(),
+            skipped: self.skipped.lock().clone(),
+            conflicts: self.conflicts.lock().clone(),
+            unreadable: self.unreadable.lock().clone(),
+            eta_seconds,
+            last_error,
+            last_error_at,
+            roots,
+        }
+    }
+}
+
+/// seconds left at the rate files have been processed so far
+fn estimate_eta(elapsed: Duration, processed: usize, queued: usize) -> Option<u64> {
+    if processed == 0 {
+        return None;
+    }
+    let remaining = queued.saturating_sub(processed) as f64;
+    Some((elapsed.as_secs_f64() / processed as f64 * remaining).ceil() as u64)
+}
+
+/// tracks read by the tag readers, plus how the reading ended
+pub struct TrackStream {
+    pub tracks: mpsc::Receiver<Track>,
+    done: oneshot::Receiver<Result<()>>,
+}
+
+impl TrackStream {
+    /// run `read` on its own thread, feeding the tracks it sends into the stream
+    fn spawn<F>(read: F) -> Result<Self>
+    where
+        F: FnOnce(mpsc::Sender<Track>) -> Result<()> + Send + 'static,
+    {
+        let (tx, tracks) = mpsc::channel(CHANNEL_CAPACITY);
+        let (done_tx, done) = oneshot::channel();
+
+        std::thread::Builder::new()
+            .name("tag-extraction".to_string())
+            .spawn(move || {
+                let outcome = std::panic::catch_unwind(AssertUnwindSafe(|| read(tx)))
+                    .unwrap_or_else(|_| Err(anyhow!("tag extraction panicked")));
+                let _ = done_tx.send(outcome);
+            })?;
+
+        Ok(Self { tracks, done })
+    }
+
+    /// wait for the readers, `Ok` only when every file was read
+    ///
+    /// call once the tracks run out; nothing should be pruned on an error
+    /// since files may have been left unread.
+    pub async fn finish(self) -> Result<()> {
+        self.done
+            .await
+            .unwrap_or_else(|_| Err(anyhow!("tag extraction stopped unexpectedly")))
+    }
+}
+
+/// wait for up to `size` tracks, an empty batch means extraction is done
+pub async fn next_batch(rx: &mut mpsc::Receiver<Track>, size: usize) -> Vec<Track> {
+    let mut batch = Vec::with_capacity(size);
+    while batch.len() < size {
+        let remaining = size - batch.len();
+        if rx.recv_many(&mut batch, remaining).await == 0 {
+            break;
+        }
+    }
+    batch
+}
+
 /// pre-cached config data needed for track extraction
 /// avoids loading config from disk for every single file
 #[derive(Clone)]
 struct IndexerConfig {
     artist_separators: HashSet<String>,
+    album_artist_separators: HashSet<String>,
     artist_split_ignore_list: HashSet<String>,
     genre_separators: HashSet<String>,
+    artist_splits: Vec<ArtistSplit>,
 }
 
 impl IndexerConfig {
     fn from_user_config(config: &UserConfig) -> Self {
         Self {
-            artist_separators: config.artist_separators.clone(),
+            artist_separators: config.separators_for(SplitField::Artist).clone(),
+            album_artist_separators: config.separators_for(SplitField::AlbumArtist).clone(),
             artist_split_ignore_list: config.artist_split_ignore_list.clone(),
-            genre_separators: config.genre_separators.clone(),
+            genre_separators: config.separators_for(SplitField::Genre).clone(),
+            artist_splits: artist_split::rules(),
         }
     }
 }
 
+/// Names a single tag value is split into, the way a scan splits it
+pub fn split_tag(
+    value: &str,
+    field: SplitField,
+    separators: &HashSet<String>,
+    artist_split_ignore_list: &HashSet<String>,
+) -> Vec<String> {
+    match field {
+        SplitField::Artist | SplitField::AlbumArtist => {
+            split_artists_smart(value, separators, artist_split_ignore_list)
+        }
+        SplitField::Genre => split_genres(value, separators),
+    }
+}
+
 /// music library indexer with parallel processing
 pub struct Indexer {
     root_dirs: Vec<PathBuf>,
+    /// directories left out of scans, with everything below them
+    exclude_dirs: Vec<PathBuf>,
     artist_separators: Vec<String>,
     show_progress: bool,
 }
@@ -67,6 +856,7 @@ impl Indexer {
     pub fn new(root_dirs: Vec<String>, artist_separators: Vec<String>) -> Self {
         Self {
             root_dirs: root_dirs.into_iter().map(PathBuf::from).collect(),
+            exclude_dirs: Vec::new(),
             artist_separators,
             show_progress: true,
         }
@@ -78,6 +868,18 @@ impl Indexer {
             config.root_dirs.clone(),
             config.artist_separators.iter().cloned().collect(),
         )
+        .with_exclude_dirs(&config.exclude_dirs)
+    }
+
+    /// set the directories scans leave out
+    pub fn with_exclude_dirs(mut self, dirs: &[String]) -> Self {
+        self.exclude_dirs = dirs
+            .iter()
+            .map(|d| d.trim())
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from)
+            .collect();
+        self
     }
 
     /// set whether to show progress bar
@@ -88,50 +890,247 @@ impl Indexer {
 
     /// check if file is an audio file
     fn is_audio_file(entry: &DirEntry) -> bool {
-        if !entry.file_type().is_file() {
-            return false;
-        }
+        entry.file_type().is_file() && is_audio_path(entry.path())
+    }
 
+    /// check if file is an m3u playlist
+    fn is_playlist_file(entry: &DirEntry) -> bool {
         entry
             .path()
             .extension()
             .and_then(|ext| ext.to_str())
-            .map(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
-            .unwrap_or(false)
+            .is_some_and(|ext| PLAYLIST_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
     }
 
-    /// check if directory should be skipped
-    fn should_skip_dir(entry: &DirEntry) -> bool {
-        entry
+    /// check if directory should be skipped, hidden and excluded ones are
+    fn should_skip_dir(entry: &DirEntry, exclude_dirs: &[PathBuf]) -> bool {
+        entry.file_type().is_dir()
+            && (entry
+                .file_name()
+                .to_str()
+                .map(|s| s.starts_with('.'))
+                .unwrap_or(false)
+                || exclude_dirs.iter().any(|d| entry.path().starts_with(d)))
+    }
+
+    /// audio files below a directory that a scan would pick up, without
+    /// waiting for recently written files to settle
+    pub fn audio_files_in(&self, dir: &Path) -> Vec<PathBuf> {
+        WalkDir::new(dir)
+            .follow_links(true)
+            .into_iter()
+            .filter_entry(|e| !Self::should_skip_dir(e, &self.exclude_dirs))
+            .filter_map(|e| e.ok())
+            .filter(|e| Self::is_audio_file(e) && !is_system_file(&e.file_name().to_string_lossy()))
+            .map(|e| e.into_path())
+            .collect()
+    }
+
+    /// whether a scan would walk into a directory, it is left out when it or
+    /// a directory above it below its root is hidden or excluded
+    pub fn accepts_dir(&self, dir: &Path) -> bool {
+        if self.exclude_dirs.iter().any(|d| dir.starts_with(d)) {
+            return false;
+        }
+        let below_root = self
+            .root_dirs
+            .iter()
+            .find_map(|root| dir.strip_prefix(root).ok())
+            .unwrap_or(dir);
+        !below_root
+            .components()
+            .any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+    }
+
+    /// whether a scan would pick up a file
+    pub fn accepts_file(&self, path: &Path) -> bool {
+        let name = path
             .file_name()
-            .to_str()
-            .map(|s| s.starts_with('.'))
-            .unwrap_or(false)
+            .map(|n| n.to_string_lossy())
+            .unwrap_or_default();
+        is_audio_path(path)
+            && !is_system_file(&name)
+            && path.parent().is_none_or(|dir| self.accepts_dir(dir))
+    }
+
+    /// root directories being indexed
+    pub fn root_dirs(&self) -> &[PathBuf] {
+        &self.root_dirs
     }
 
     /// scan directories and return list of audio file paths
     pub fn scan_files(&self) -> Vec<PathBuf> {
-        let mut files = Vec::new();
+        self.scan_roots()
+            .into_iter()
+            .flat_map(|root| root.files)
+            .collect()
+    }
+
+    /// audio files grouped by root directory, in root order
+    ///
+    /// a file reachable under several roots is kept under the first of them
+    pub fn scan_roots(&self) -> Vec<RootFiles> {
+        let mut roots: Vec<RootFiles> = self
+            .root_dirs
+            .par_iter()
+            .map(|root| {
+                if !root.exists() {
+                    tracing::warn!("root directory does not exist: {}", root.display());
+                    return RootFiles::default();
+                }
+                Self::walk_root(root, &self.exclude_dirs)
+            })
+            .collect();
 
-        for root in &self.root_dirs {
-            if !root.exists() {
-                tracing::warn!("root directory does not exist: {}", root.display());
+        if roots.len() > 1 {
+            Self::drop_duplicates(&mut roots);
+        }
+        roots
+    }
+
+    /// move files an earlier root already reaches into the duplicates of
+    /// their root
+    fn drop_duplicates(roots: &mut [RootFiles]) {
+        let mut seen = HashMap::new();
+        for (index, found) in roots.iter_mut().enumerate() {
+            for path in std::mem::take(&mut found.files) {
+                let Some(key) = file_identity(&path) else {
+                    found.files.push(path);
+                    continue;
+                };
+                match seen.entry(key) {
+                    Entry::Occupied(entry) => {
+                        let (kept_root, kept): &(usize, PathBuf) = entry.get();
+                        found.duplicates.push(DuplicateFile {
+                            path,
+                            kept: kept.clone(),
+                            kept_root: *kept_root,
+                        });
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert((index, path.clone()));
+                        found.files.push(path);
+                    }
+                }
+            }
+        }
+    }
+
+    /// walk a root, leaving out system, empty and half copied files
+    ///
+    /// recently modified files are stat'ed again after the walk and deferred
+    /// when their size or mtime moved in between. directories the walk cannot
+    /// enter are listed as unreadable rather than looking empty
+    fn walk_root(root: &Path, exclude_dirs: &[PathBuf]) -> RootFiles {
+        let mut found = RootFiles::default();
+        let settle_cutoff = SystemTime::now()
+            .checked_sub(COPY_SETTLE_WINDOW)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let mut recent: Vec<(PathBuf, u64, Option<SystemTime>)> = Vec::new();
+        let mut last_recent_stat = None;
+
+        let entries = WalkDir::new(root)
+            .follow_links(true)
+            .into_iter()
+            .filter_entry(|e| !Self::should_skip_dir(e, exclude_dirs));
+
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    found.unreadable.push(UnreadablePath::from_walk_error(&e));
+                    continue;
+                }
+            };
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            if is_system_file(&entry.file_name().to_string_lossy()) {
+                found.skipped_system += 1;
+                continue;
+            }
+            if Self::is_playlist_file(&entry) {
+                found.playlists.push(entry.into_path());
+                continue;
+            }
+            if !Self::is_audio_file(&entry) {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else {
                 continue;
+            };
+
+            let modified = metadata.modified().ok();
+            if modified.is_some_and(|m| m >= settle_cutoff) {
+                recent.push((entry.into_path(), metadata.len(), modified));
+                last_recent_stat = Some(Instant::now());
+            } else if metadata.len() == 0 {
+                found.skipped_empty += 1;
+            } else {
+                found.files.push(entry.into_path());
             }
+        }
 
-            let walker = WalkDir::new(root)
-                .follow_links(true)
-                .into_iter()
-                .filter_entry(|e| !Self::should_skip_dir(e));
+        if let Some(stat_at) = last_recent_stat {
+            if let Some(wait) = COPY_RECHECK_DELAY.checked_sub(stat_at.elapsed()) {
+                std::thread::sleep(wait);
+            }
+        }
 
-            for entry in walker.filter_map(|e| e.ok()) {
-                if Self::is_audio_file(&entry) {
-                    files.push(entry.path().to_path_buf());
-                }
+        for (path, len, modified) in recent {
+            let Ok(metadata) = std::fs::metadata(&path) else {
+                continue;
+            };
+            // a fresh empty file is usually a copy that has not started writing
+            let settled = len > 0 && metadata.len() == len && metadata.modified().ok() == modified;
+            if settled {
+                found.files.push(path);
+            } else {
+                found.deferred.push(path);
             }
         }
 
-        files
+        found
+    }
+
+    /// read tags on the rayon pool and stream the tracks through a bounded channel
+    ///
+    /// files are `(root index, path)` pairs so progress is reported per root.
+    /// the readers pause while the channel is full and stop early once the
+    /// receiver is dropped.
+    pub fn extract_streaming(
+        &self,
+        files: Vec<(usize, PathBuf)>,
+        progress: Option<Arc<ScanProgress>>,
+    ) -> Result<TrackStream> {
+        let user_config = UserConfig::load()?;
+        let indexer_config = Arc::new(IndexerConfig::from_user_config(&user_config));
+        if let Some(progress) = &progress {
+            progress.set_phase(ScanPhase::Tagging);
+        }
+
+        TrackStream::spawn(move |tx| {
+            files.par_iter().try_for_each_with(tx, |tx, (root, path)| {
+                let result = extract_track(path, &indexer_config);
+
+                if let Some(progress) = &progress {
+                    progress.add_processed(*root, result.is_err());
+                }
+
+                match result {
+                    Ok(track) => tx
+                        .blocking_send(track)
+                        .map_err(|_| anyhow!("scan consumer stopped")),
+                    Err(e) => {
+                        if let Some(progress) = &progress {
+                            progress.add_failure(path, &e);
+                        }
+                        tracing::debug!("failed to read metadata from {}: {}", path.display(), e);
+                        Ok(())
+                    }
+                }
+            })
+        })
     }
 
     /// scan and extract tracks from all directories using parallel processing
@@ -167,10 +1166,7 @@ impl Indexer {
         let tracks: Vec<Track> = files
             .par_iter()
             .filter_map(|path| {
-                // try lofty first (fast, pure-rust), fall back to ffprobe
-                // for formats lofty can't handle (wma, dsf, dff, tta, etc.)
-                let result = extract_track_lofty(path, &indexer_config)
-                    .or_else(|_| extract_track_ffprobe(path, &indexer_config));
+                let result = extract_track(path, &indexer_config);
 
                 // update progress
                 let count = processed.fetch_add(1, Ordering::Relaxed) + 1;
@@ -210,16 +1206,12 @@ impl Indexer {
 
         let tracks: Vec<Track> = paths
             .par_iter()
-            .filter(|path| path.exists())
-            .filter_map(|path| {
-                match extract_track_lofty(path, &indexer_config)
-                    .or_else(|_| extract_track_ffprobe(path, &indexer_config))
-                {
-                    Ok(track) => Some(track),
-                    Err(e) => {
-                        tracing::warn!("failed to reindex {}: {}", path.display(), e);
-                        None
-                    }
+            .filter(|path| is_indexable(path))
+            .filter_map(|path| match extract_track(path, &indexer_config) {
+                Ok(track) => Some(track),
+                Err(e) => {
+                    tracing::warn!("failed to reindex {}: {}", path.display(), e);
+                    None
                 }
             })
             .collect();
@@ -228,7 +1220,57 @@ impl Indexer {
     }
 }
 
+/// what makes two paths the same file, the device and inode so bind mounts match
+#[cfg(unix)]
+fn file_identity(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(path).ok().map(|m| (m.dev(), m.ino()))
+}
+
+/// what makes two paths the same file, the path with links resolved
+#[cfg(not(unix))]
+fn file_identity(path: &Path) -> Option<PathBuf> {
+    std::fs::canonicalize(path).ok()
+}
+
+/// whether a path has one of the audio extensions scans index
+pub fn is_audio_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// hidden files and os metadata files such as `Thumbs.db`
+fn is_system_file(name: &str) -> bool {
+    name.starts_with('.') || SYSTEM_FILES.contains(&name.to_ascii_lowercase().as_str())
+}
+
+/// whether a single file changed on disk is worth reading tags from
+fn is_indexable(path: &Path) -> bool {
+    let is_system = path
+        .file_name()
+        .is_some_and(|name| is_system_file(&name.to_string_lossy()));
+    !is_system && std::fs::metadata(path).is_ok_and(|m| m.is_file() && m.len() > 0)
+}
+
 /// extract track metadata from a file using lofty (pure rust, no subprocess)
+/// read a track and apply the artist split rules to it
+fn extract_track(path: &Path, config: &IndexerConfig) -> Result<Track> {
+    // try lofty first (fast, pure-rust), fall back to ffprobe
+    // for formats lofty can't handle (wma, dsf, dff, tta, etc.)
+    let mut track = extract_track_lofty_guarded(path, config)
+        .or_else(|_| extract_track_ffprobe(path, config))?;
+    artist_split::apply(&mut track, &config.artist_splits);
+    Ok(track)
+}
+
+/// lofty read that turns a panic on a malformed file into an error
+/// so one broken file can't take the whole scan down
+fn extract_track_lofty_guarded(path: &Path, config: &IndexerConfig) -> Result<Track> {
+    std::panic::catch_unwind(AssertUnwindSafe(|| extract_track_lofty(path, config)))
+        .unwrap_or_else(|_| Err(anyhow!("tag reader panicked")))
+}
+
 fn extract_track_lofty(path: &Path, config: &IndexerConfig) -> Result<Track> {
     // read the audio file with lofty
     let tagged_file = Probe::open(path)
@@ -236,6 +1278,27 @@ fn extract_track_lofty(path: &Path, config: &IndexerConfig) -> Result<Track> {
         .read()
         .map_err(|e| anyhow::anyhow!("failed to read tags: {}", e))?;
 
+    track_from_tags(&tagged_file, path, config)
+}
+
+/// read a track from an in memory file the way a scan reads it from disk
+///
+/// `path` only names the file, nothing is read from it. the fuzz targets use
+/// this to feed arbitrary bytes through the tag reader
+pub fn extract_track_from_bytes(data: &[u8], path: &Path) -> Result<Track> {
+    let tagged_file = Probe::new(Cursor::new(data))
+        .guess_file_type()?
+        .read()
+        .map_err(|e| anyhow::anyhow!("failed to read tags: {}", e))?;
+    let config = IndexerConfig::from_user_config(&UserConfig::default());
+
+    let mut track = track_from_tags(&tagged_file, path, &config)?;
+    artist_split::apply(&mut track, &config.artist_splits);
+    Ok(track)
+}
+
+/// build a track from the tags and audio properties lofty read
+fn track_from_tags(tagged_file: &TaggedFile, path: &Path, config: &IndexerConfig) -> Result<Track> {
     let filepath = path.to_string_lossy().to_string();
     let folder = path
         .parent()
@@ -277,6 +1340,35 @@ fn extract_track_lofty(path: &Path, config: &IndexerConfig) -> Result<Track> {
             .map(|s| s.to_string())
     });
 
+    let label = tag.and_then(|t| {
+        t.get_string(&ItemKey::Label)
+            .or_else(|| t.get_string(&ItemKey::Publisher))
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    });
+
+    let catalog_number = tag.and_then(|t| {
+        t.get_string(&ItemKey::CatalogNumber)
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    });
+
+    let artist_mbids = tag
+        .map(|t| {
+            let ids = [
+                ItemKey::MusicBrainzArtistId,
+                ItemKey::MusicBrainzReleaseArtistId,
+            ]
+            .iter()
+            .flat_map(|key| t.get_strings(key))
+            .collect::<Vec<_>>();
+            split_mbids(ids)
+        })
+        .unwrap_or_default();
+
+    let embedded_lyrics = tag.and_then(LyricsLib::from_tag);
+    let has_lyrics = LyricsLib::has_local(path, embedded_lyrics.as_deref());
+
     let track_number = tag.and_then(|t| t.track()).map(|n| n as i32);
     let disc_number = tag.and_then(|t| t.disk()).map(|n| n as i32);
 
@@ -291,14 +1383,8 @@ fn extract_track_lofty(path: &Path, config: &IndexerConfig) -> Result<Track> {
         ];
 
         for key in date_keys {
-            if let Some(date_str) = t.get_string(&key) {
-                let s = date_str.trim();
-                // extract leading 4 digits as year (handles "2025", "2025-01-15", "2025-01-15T12:34:00", etc.)
-                if s.len() >= 4 && s[..4].chars().all(|c| c.is_ascii_digit()) {
-                    if let Ok(y) = s[..4].parse::<i32>() {
-                        return Some(y);
-                    }
-                }
+            if let Some(y) = t.get_string(&key).and_then(parse_year) {
+                return Some(y);
             }
         }
 
@@ -312,11 +1398,40 @@ fn extract_track_lofty(path: &Path, config: &IndexerConfig) -> Result<Track> {
     let bitrate = properties.audio_bitrate().unwrap_or(0) as i32;
 
     // get file modification time
-    let last_mod = std::fs::metadata(path)
-        .and_then(|m| m.modified())
-        .map(|t| t.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64)
+    let metadata = std::fs::metadata(path).ok();
+    let last_mod = metadata
+        .as_ref()
+        .and_then(|m| m.modified().ok())
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs() as i64)
         .unwrap_or(0);
 
+    let extra = TrackExtra {
+        track_total: tag.and_then(|t| t.track_total()).map(|n| n as i32),
+        disc_total: tag.and_then(|t| t.disk_total()).map(|n| n as i32),
+        codec: codec_name(tagged_file.file_type(), properties.bit_depth()),
+        samplerate: properties.sample_rate().unwrap_or(0),
+        bitdepth: properties.bit_depth().map(u32::from).unwrap_or(0),
+        channels: properties.channels().map(u32::from).unwrap_or(0),
+        filesize: metadata.map(|m| m.len()).unwrap_or(0),
+        label,
+        catalog_number,
+        artist_mbids,
+        rating: tag.and_then(tag_rating),
+        bpm: tag
+            .and_then(|t| {
+                t.get_string(&ItemKey::Bpm)
+                    .or_else(|| t.get_string(&ItemKey::IntegerBpm))
+            })
+            .and_then(parse_bpm),
+        key: tag
+            .and_then(|t| t.get_string(&ItemKey::InitialKey))
+            .and_then(parse_key),
+        loudness: tag
+            .and_then(|t| t.get_string(&ItemKey::ReplayGainTrackGain))
+            .and_then(parse_loudness),
+    };
+
     // clean title
     let clean = clean_title(&title);
     let cleaned_title = remove_remaster_info(&clean);
@@ -357,13 +1472,13 @@ fn extract_track_lofty(path: &Path, config: &IndexerConfig) -> Result<Track> {
         // fallback to splitting the album_artist string we resolved earlier
         album_artist_names = split_artists_smart(
             &album_artist,
-            &config.artist_separators,
+            &config.album_artist_separators,
             &config.artist_split_ignore_list,
         );
     } else if album_artist_names.len() == 1 {
         album_artist_names = split_artists_smart(
             &album_artist_names[0],
-            &config.artist_separators,
+            &config.album_artist_separators,
             &config.artist_split_ignore_list,
         );
     }
@@ -399,28 +1514,10 @@ fn extract_track_lofty(path: &Path, config: &IndexerConfig) -> Result<Track> {
 
     if genre_names.is_empty() {
         if let Some(g) = &genre {
-            genre_names = config
-                .genre_separators
-                .iter()
-                .fold(vec![g.as_str()], |acc, sep| {
-                    acc.into_iter().flat_map(|s| s.split(sep)).collect()
-                })
-                .into_iter()
-                .map(|s| s.trim().to_string())
-                .filter(|s| !s.is_empty())
-                .collect();
+            genre_names = split_genres(g, &config.genre_separators);
         }
     } else if genre_names.len() == 1 {
-        genre_names = config
-            .genre_separators
-            .iter()
-            .fold(vec![genre_names[0].as_str()], |acc, sep| {
-                acc.into_iter().flat_map(|s| s.split(sep)).collect()
-            })
-            .into_iter()
-            .map(|s| s.trim().to_string())
-            .filter(|s| !s.is_empty())
-            .collect();
+        genre_names = split_genres(&genre_names[0], &config.genre_separators);
     }
 
     let genres: Vec<crate::models::GenreRef> = genre_names
@@ -443,7 +1540,8 @@ fn extract_track_lofty(path: &Path, config: &IndexerConfig) -> Result<Track> {
     // parse date to timestamp
     let date_timestamp = if let Some(y) = year {
         chrono::NaiveDate::from_ymd_opt(y, 1, 1)
-            .map(|d| d.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp())
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .map(|d| d.and_utc().timestamp())
             .unwrap_or(0)
     } else {
         0
@@ -472,24 +1570,141 @@ fn extract_track_lofty(path: &Path, config: &IndexerConfig) -> Result<Track> {
         last_mod,
         image: String::new(),
         copyright,
-        extra: serde_json::Value::Null,
+        extra: extra.to_value(),
         lastplayed: 0,
         playcount: 0,
         playduration: 0,
+        has_lyrics,
+        rating: extra.rating.unwrap_or(0),
         weakhash,
         pos: None,
         help_text: String::new(),
         score: 0.0,
         explicit: false,
         fav_userids: HashSet::new(),
+        mbid: String::new(),
     })
 }
 
+/// lowercase codec name for a file lofty could read
+///
+/// mp4 holds either aac or alac, only alac reports a bit depth
+/// musicbrainz ids from tags that may hold several joined into one value
+fn split_mbids<'a>(values: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut ids: Vec<String> = Vec::new();
+    for id in values.into_iter().flat_map(|v| v.split(['/', ';', ','])) {
+        let id = id.trim().to_lowercase();
+        if !id.is_empty() && !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    ids
+}
+
+/// star rating from the POPM frames or RATING fields of a tag
+///
+/// id3 keeps POPM frames as raw bytes, the other formats keep text
+fn tag_rating(tag: &Tag) -> Option<u8> {
+    tag.get_items(&ItemKey::Popularimeter)
+        .find_map(|item| match item.value() {
+            ItemValue::Binary(bytes) => Popularimeter::parse(&mut &bytes[..])
+                .ok()
+                .and_then(|popm| popm_stars(popm.rating)),
+            ItemValue::Text(text) => parse_rating(text),
+            _ => None,
+        })
+}
+
+/// map a POPM byte to stars the way most players write them
+///
+/// 1, 64, 128, 196 and 255 are one to five stars, 0 is unrated
+fn popm_stars(value: u8) -> Option<u8> {
+    match value {
+        0 => None,
+        1..=31 => Some(1),
+        32..=95 => Some(2),
+        96..=159 => Some(3),
+        160..=223 => Some(4),
+        _ => Some(5),
+    }
+}
+
+/// star rating from a text rating field
+///
+/// fractions up to 1 are a share of five stars, whole numbers up to 5 are stars,
+/// up to 100 a percentage and up to 255 a POPM byte
+fn parse_rating(value: &str) -> Option<u8> {
+    let value = value.trim();
+    let number: f64 = value.parse().ok().filter(|n: &f64| n.is_finite())?;
+    if number <= 0.0 {
+        return None;
+    }
+
+    let stars = if value.contains('.') && number <= 1.0 {
+        number * 5.0
+    } else if number <= 5.0 {
+        number
+    } else if number <= 100.0 {
+        number / 20.0
+    } else if number <= 255.0 {
+        return popm_stars(number as u8);
+    } else {
+        return None;
+    };
+    Some((stars.round() as u8).clamp(1, 5))
+}
+
+/// tempo from a BPM tag, some taggers write fractions like "127.98"
+fn parse_bpm(value: &str) -> Option<u16> {
+    let bpm: f64 = value.trim().parse().ok()?;
+    // zero means untagged and anything outside this range is a bad tag
+    (20.0..=300.0).contains(&bpm).then(|| bpm.round() as u16)
+}
+
+/// musical key from a key tag, "o" is the open key notation for off key
+fn parse_key(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty() && !value.eq_ignore_ascii_case("o")).then(|| value.to_string())
+}
+
+/// integrated loudness in LUFS from a replaygain track gain like "-7.32 dB"
+///
+/// replaygain 2 targets -18 LUFS so the gain is the distance to that level
+fn parse_loudness(value: &str) -> Option<f32> {
+    let number = value
+        .trim()
+        .trim_end_matches(|c: char| c.is_ascii_alphabetic() || c.is_whitespace());
+    let gain: f32 = number.trim_start_matches('+').parse().ok()?;
+    (gain.is_finite() && gain.abs() <= 60.0).then(|| -18.0 - gain)
+}
+
+fn codec_name(file_type: FileType, bit_depth: Option<u8>) -> String {
+    let name = match file_type {
+        FileType::Aac => "aac",
+        FileType::Aiff => "aiff",
+        FileType::Ape => "ape",
+        FileType::Flac => "flac",
+        FileType::Mpeg => "mp3",
+        FileType::Mp4 if bit_depth.is_some() => "alac",
+        FileType::Mp4 => "aac",
+        FileType::Mpc => "mpc",
+        FileType::Opus => "opus",
+        FileType::Vorbis => "vorbis",
+        FileType::Speex => "speex",
+        FileType::Wav => "wav",
+        FileType::WavPack => "wavpack",
+        FileType::Custom(name) => return name.to_lowercase(),
+        _ => "",
+    };
+    name.to_string()
+}
+
 /// fallback metadata extraction using ffprobe for formats lofty can't handle.
 /// this spawns an ffprobe subprocess so it's slower than the lofty path -
 /// only used when lofty fails (wma, dsf, dff, tta, and other exotic formats).
 fn extract_track_ffprobe(path: &Path, config: &IndexerConfig) -> Result<Track> {
     let meta = ffmpeg::probe_metadata(path)?;
+    let has_lyrics = LyricsLib::has_local(path, meta.lyrics.as_deref());
 
     let filepath = path.to_string_lossy().to_string();
     let folder = path
@@ -524,23 +1739,42 @@ fn extract_track_ffprobe(path: &Path, config: &IndexerConfig) -> Result<Track> {
     let track_number = meta.track;
     let disc_number = meta.disc;
 
-    let year: Option<i32> = meta.date.and_then(|d| {
-        let s = d.trim();
-        if s.len() >= 4 && s[..4].chars().all(|c| c.is_ascii_digit()) {
-            s[..4].parse::<i32>().ok()
-        } else {
-            None
-        }
-    });
+    let year: Option<i32> = meta.date.as_deref().and_then(parse_year);
 
     let duration = meta.duration as i32;
     let bitrate = meta.bitrate;
 
-    let last_mod = std::fs::metadata(path)
-        .and_then(|m| m.modified())
-        .map(|t| t.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64)
+    let metadata = std::fs::metadata(path).ok();
+    let last_mod = metadata
+        .as_ref()
+        .and_then(|m| m.modified().ok())
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs() as i64)
         .unwrap_or(0);
 
+    let extra = TrackExtra {
+        track_total: meta.track_total,
+        disc_total: meta.disc_total,
+        codec: meta.codec.to_lowercase(),
+        samplerate: meta.sample_rate.max(0) as u32,
+        bitdepth: meta.bit_depth.max(0) as u32,
+        channels: meta.channels.max(0) as u32,
+        filesize: metadata.map(|m| m.len()).unwrap_or(0),
+        label: meta.label.filter(|s| !s.trim().is_empty()),
+        catalog_number: meta
+            .catalog_number
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty()),
+        artist_mbids: split_mbids(meta.artist_mbids.iter().map(String::as_str)),
+        rating: meta.rating.as_deref().and_then(parse_rating),
+        bpm: meta.bpm.as_deref().and_then(parse_bpm),
+        key: meta.initial_key.as_deref().and_then(parse_key),
+        loudness: meta
+            .replaygain_track_gain
+            .as_deref()
+            .and_then(parse_loudness),
+    };
+
     let clean = clean_title(&title);
     let cleaned_title = remove_remaster_info(&clean);
 
@@ -552,7 +1786,7 @@ fn extract_track_ffprobe(path: &Path, config: &IndexerConfig) -> Result<Track> {
 
     let album_artist_names = split_artists_smart(
         &album_artist,
-        &config.artist_separators,
+        &config.album_artist_separators,
         &config.artist_split_ignore_list,
     );
 
@@ -574,19 +1808,10 @@ fn extract_track_ffprobe(path: &Path, config: &IndexerConfig) -> Result<Track> {
 
     let artisthashes: Vec<String> = artists.iter().map(|a| a.artisthash.clone()).collect();
 
-    let mut genre_names: Vec<String> = Vec::new();
-    if let Some(g) = &genre {
-        genre_names = config
-            .genre_separators
-            .iter()
-            .fold(vec![g.as_str()], |acc, sep| {
-                acc.into_iter().flat_map(|s| s.split(sep)).collect()
-            })
-            .into_iter()
-            .map(|s| s.trim().to_string())
-            .filter(|s| !s.is_empty())
-            .collect();
-    }
+    let genre_names = genre
+        .as_deref()
+        .map(|g| split_genres(g, &config.genre_separators))
+        .unwrap_or_default();
 
     let genres: Vec<crate::models::GenreRef> = genre_names
         .iter()
@@ -606,7 +1831,8 @@ fn extract_track_ffprobe(path: &Path, config: &IndexerConfig) -> Result<Track> {
 
     let date_timestamp = if let Some(y) = year {
         chrono::NaiveDate::from_ymd_opt(y, 1, 1)
-            .map(|d| d.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp())
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .map(|d| d.and_utc().timestamp())
             .unwrap_or(0)
     } else {
         0
@@ -635,15 +1861,349 @@ fn extract_track_ffprobe(path: &Path, config: &IndexerConfig) -> Result<Track> {
         last_mod,
         image: String::new(),
         copyright,
-        extra: serde_json::Value::Null,
+        extra: extra.to_value(),
         lastplayed: 0,
         playcount: 0,
         playduration: 0,
+        has_lyrics,
+        rating: extra.rating.unwrap_or(0),
         weakhash,
         pos: None,
         help_text: String::new(),
         score: 0.0,
         explicit: false,
         fav_userids: HashSet::new(),
+        mbid: String::new(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_tag_per_field() {
+        let config = UserConfig {
+            album_artist_separators: HashSet::from([" & ".to_string()]),
+            ..Default::default()
+        };
+        let split = |value: &str, field: SplitField| {
+            split_tag(
+                value,
+                field,
+                config.separators_for(field),
+                &config.artist_split_ignore_list,
+            )
+        };
+
+        assert_eq!(
+            split("Simon & Garfunkel", SplitField::Artist),
+            ["Simon & Garfunkel"]
+        );
+        assert_eq!(
+            split("Simon & Garfunkel", SplitField::AlbumArtist),
+            ["Simon", "Garfunkel"]
+        );
+        assert_eq!(
+            split("Rock/Pop; R&B", SplitField::Genre),
+            ["Rock", "Pop", "R&B"]
+        );
+    }
+
+    #[test]
+    fn test_estimate_eta() {
+        assert_eq!(estimate_eta(Duration::from_secs(10), 0, 100), None);
+        assert_eq!(estimate_eta(Duration::from_secs(10), 50, 100), Some(10));
+        assert_eq!(estimate_eta(Duration::from_secs(10), 100, 100), Some(0));
+    }
+
+    #[test]
+    fn test_parse_rating() {
+        assert_eq!(popm_stars(0), None);
+        assert_eq!(popm_stars(1), Some(1));
+        assert_eq!(popm_stars(64), Some(2));
+        assert_eq!(popm_stars(128), Some(3));
+        assert_eq!(popm_stars(196), Some(4));
+        assert_eq!(popm_stars(255), Some(5));
+
+        assert_eq!(parse_rating("4"), Some(4));
+        assert_eq!(parse_rating("0.6"), Some(3));
+        assert_eq!(parse_rating("80"), Some(4));
+        assert_eq!(parse_rating("255"), Some(5));
+        assert_eq!(parse_rating("0"), None);
+        assert_eq!(parse_rating("great"), None);
+        assert_eq!(parse_rating("1000"), None);
+    }
+
+    #[test]
+    fn test_parse_audio_features() {
+        assert_eq!(parse_bpm("128"), Some(128));
+        assert_eq!(parse_bpm(" 127.6 "), Some(128));
+        assert_eq!(parse_bpm("0"), None);
+        assert_eq!(parse_bpm("fast"), None);
+
+        assert_eq!(parse_key(" Am "), Some("Am".to_string()));
+        assert_eq!(parse_key("o"), None);
+        assert_eq!(parse_key(""), None);
+
+        assert_eq!(parse_loudness("-7.5 dB"), Some(-10.5));
+        assert_eq!(parse_loudness("+2 dB"), Some(-20.0));
+        assert_eq!(parse_loudness("loud"), None);
+    }
+
+    #[test]
+    fn test_system_files() {
+        assert!(is_system_file(".DS_Store"));
+        assert!(is_system_file("._01 Intro.flac"));
+        assert!(is_system_file("Thumbs.db"));
+        assert!(is_system_file("desktop.ini"));
+        assert!(!is_system_file("01 Intro.flac"));
+    }
+
+    #[test]
+    fn test_walk_root_skips_system_and_empty_files() {
+        let dir = std::env::temp_dir().join(format!("swing-walk-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("song.mp3"), b"data").unwrap();
+        std::fs::write(dir.join("._song.mp3"), b"data").unwrap();
+        std::fs::write(dir.join(".DS_Store"), b"data").unwrap();
+        std::fs::write(dir.join("empty.mp3"), b"").unwrap();
+
+        // age the files past the settle window so none count as in progress
+        let old = SystemTime::now() - COPY_SETTLE_WINDOW * 2;
+        for name in ["song.mp3", "._song.mp3", ".DS_Store", "empty.mp3"] {
+            let file = std::fs::File::options()
+                .write(true)
+                .open(dir.join(name))
+                .unwrap();
+            file.set_modified(old).unwrap();
+        }
+
+        let found = Indexer::walk_root(&dir, &[]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(found.files, vec![dir.join("song.mp3")]);
+        assert_eq!(found.skipped_system, 2);
+        assert_eq!(found.skipped_empty, 1);
+        assert!(found.deferred.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_walk_root_reports_unreadable_dirs() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let locked = dir.path().join("locked");
+        std::fs::create_dir(&locked).unwrap();
+        std::fs::write(locked.join("song.mp3"), b"data").unwrap();
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+
+        // root reads every directory, nothing to report then
+        let readable = std::fs::read_dir(&locked).is_ok();
+        let found = Indexer::walk_root(dir.path(), &[]);
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+        if readable {
+            return;
+        }
+
+        assert!(found.files.is_empty());
+        assert_eq!(
+            found.unreadable,
+            vec![UnreadablePath {
+                path: locked.to_string_lossy().to_string(),
+                error: "permission denied".to_string(),
+                permission_denied: true,
+            }]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_roots_drops_files_under_two_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let music = dir.path().join("music");
+        std::fs::create_dir_all(music.join("album")).unwrap();
+        let song = music.join("album").join("song.mp3");
+        std::fs::write(&song, b"data").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&song)
+            .unwrap()
+            .set_modified(SystemTime::now() - COPY_SETTLE_WINDOW * 2)
+            .unwrap();
+        let mirror = dir.path().join("mirror");
+        std::os::unix::fs::symlink(&music, &mirror).unwrap();
+
+        let roots = [music.clone(), mirror.clone(), music.join("album")];
+        let indexer = Indexer::new(
+            roots
+                .iter()
+                .map(|r| r.to_string_lossy().to_string())
+                .collect(),
+            Vec::new(),
+        );
+        let found = indexer.scan_roots();
+
+        assert_eq!(found[0].files, vec![song.clone()]);
+        assert!(found[0].duplicates.is_empty());
+        for (index, path) in [
+            (1, mirror.join("album").join("song.mp3")),
+            (2, song.clone()),
+        ] {
+            assert!(found[index].files.is_empty());
+            assert_eq!(
+                found[index].duplicates,
+                vec![DuplicateFile {
+                    path,
+                    kept: song.clone(),
+                    kept_root: 0,
+                }]
+            );
+        }
+    }
+
+    /// begin() is global, so tests that start a scan take turns
+    static SCAN_TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    /// one second of silent 8 khz mono 8 bit pcm
+    fn silent_wav() -> Vec<u8> {
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36u32 + 8000).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&8000u32.to_le_bytes());
+        wav.extend_from_slice(&8000u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&8u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&8000u32.to_le_bytes());
+        wav.extend(std::iter::repeat_n(128u8, 8000));
+        wav
+    }
+
+    #[test]
+    fn test_extract_track_from_bytes() {
+        let wav = silent_wav();
+        let track = extract_track_from_bytes(&wav, Path::new("/music/Book/01 Intro.wav")).unwrap();
+        assert_eq!(track.title, "01 Intro");
+        assert_eq!(track.folder, "/music/Book");
+        assert_eq!(track.duration, 1);
+
+        assert!(extract_track_from_bytes(&wav[..20], Path::new("/music/a.wav")).is_err());
+        assert!(extract_track_from_bytes(b"not audio", Path::new("/music/a.wav")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_next_batch_and_outcome() {
+        let track = extract_track_from_bytes(&silent_wav(), Path::new("/music/a.wav")).unwrap();
+        let mut stream = TrackStream::spawn(move |tx| {
+            for _ in 0..5 {
+                tx.blocking_send(track.clone())?;
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(next_batch(&mut stream.tracks, 3).await.len(), 3);
+        assert_eq!(next_batch(&mut stream.tracks, 3).await.len(), 2);
+        assert!(next_batch(&mut stream.tracks, 3).await.is_empty());
+        assert!(stream.finish().await.is_ok());
+
+        // readers that fail or panic end the stream without a finished scan
+        let mut stream = TrackStream::spawn(|_| Err(anyhow!("disk gone"))).unwrap();
+        assert!(next_batch(&mut stream.tracks, 3).await.is_empty());
+        assert!(stream.finish().await.is_err());
+
+        let mut stream = TrackStream::spawn(|_| panic!("bad tags")).unwrap();
+        assert!(next_batch(&mut stream.tracks, 3).await.is_empty());
+        assert!(stream.finish().await.is_err());
+    }
+
+    #[test]
+    fn test_begin_refuses_a_second_scan() {
+        let _lock = SCAN_TEST_LOCK.lock();
+        let roots = [PathBuf::from("/music")];
+
+        let progress = ScanProgress::begin(&roots, ScanKind::Full).unwrap();
+        assert!(ScanProgress::begin(&roots, ScanKind::Incremental).is_none());
+        assert!(ScanProgress::begin(&roots, ScanKind::Full).is_none());
+
+        drop(progress);
+        assert!(ScanProgress::begin(&roots, ScanKind::Incremental).is_some());
+    }
+
+    #[test]
+    fn test_scan_record() {
+        let _lock = SCAN_TEST_LOCK.lock();
+        let roots = [PathBuf::from("/music"), PathBuf::from("/mnt/music")];
+        let progress = ScanProgress::begin(&roots, ScanKind::Full).unwrap();
+        assert!(ScanProgress::begin(&roots, ScanKind::Incremental).is_none());
+
+        progress.set_found(0, 3);
+        progress.add_queued(0, 3);
+        for failed in [false, false, true] {
+            progress.add_processed(0, failed);
+        }
+        progress.add_failure(Path::new("/music/bad.mp3"), &anyhow!("no tags"));
+        progress.add_skipped(
+            1,
+            &RootFiles {
+                duplicates: vec![DuplicateFile {
+                    path: PathBuf::from("/mnt/music/a.mp3"),
+                    kept: PathBuf::from("/music/a.mp3"),
+                    kept_root: 0,
+                }],
+                unreadable: vec![UnreadablePath {
+                    path: "/mnt/music/locked".to_string(),
+                    error: "permission denied".to_string(),
+                    permission_denied: true,
+                }],
+                ..Default::default()
+            },
+        );
+        progress.set_changes(ScanChanges {
+            added: 1,
+            updated: 1,
+            removed: 4,
+            total: 10,
+        });
+        progress.fail("disk full");
+
+        let record = progress.record(&progress.snapshot());
+        assert_eq!(record.kind, "full");
+        assert_eq!((record.files_seen, record.files_failed), (3, 1));
+        assert_eq!((record.tracks_added, record.tracks_removed), (1, 4));
+        assert_eq!(record.tracks_total, 10);
+        assert_eq!(record.error.as_deref(), Some("disk full"));
+        assert_eq!(record.details["failed_files"][0]["path"], "/music/bad.mp3");
+        assert_eq!(record.details["roots"][0]["files_processed"], 3);
+        assert_eq!(record.files_skipped, 1);
+        assert_eq!(
+            record.details["conflicts"][0],
+            serde_json::json!({
+                "root": "/music",
+                "duplicate_root": "/mnt/music",
+                "files": 1,
+                "paths": ["/mnt/music/a.mp3"],
+            })
+        );
+        assert_eq!(record.details["unreadable"]["count"], 1);
+        assert_eq!(record.details["unreadable"]["permission_denied"], 1);
+    }
+
+    #[test]
+    fn test_idle_state_round_trips() {
+        let state = ScanState {
+            last_error: Some("disk full".to_string()),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(json.contains("\"phase\":\"idle\""));
+        let parsed: ScanState = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.last_error.as_deref(), Some("disk full"));
+    }
+}
@@ -0,0 +1,564 @@
+//! Lossless file validation
+//!
+//! lossy encoders cut the highs off with a steep low pass, mp3 somewhere
+//! between 16 and 20.5 khz and aac around 16 to 20 khz, and decoding such a
+//! file back to flac keeps that cliff in its spectrum. thirty seconds from the
+//! middle of a lossless file are decoded with ffmpeg, their spectrum averaged
+//! into bands and searched above 11 khz for a drop of at least `CLIFF_DB` with
+//! nothing coming back past it. a cliff below the cutoffs lossy encoders use
+//! flags the file as a likely transcode, highs reaching close to nyquist pass
+//! it, and anything else is left uncertain since plenty of old recordings and
+//! masters roll off on their own.
+
+use anyhow::{anyhow, bail, Result};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::core::ffmpeg;
+use crate::core::single_flight::SingleFlight;
+use crate::db::tables::{LosslessCheck, LosslessTable};
+use crate::models::Track;
+use crate::stores::TrackStore;
+
+/// Files analysed between database writes
+const LOSSLESS_BATCH: usize = 16;
+
+/// Seconds decoded from the middle of a file
+const ANALYSIS_SECONDS: f64 = 30.0;
+
+/// Highest sample rate files are decoded at, hi-res files are resampled
+const MAX_ANALYSIS_RATE: u32 = 48_000;
+
+/// Samples per fft frame
+const FFT_SIZE: usize = 4096;
+
+/// Width of the bands the spectrum is averaged into
+const BAND_HZ: f64 = 250.0;
+
+/// Bands averaged on each side of a possible cliff, 1 khz
+const CLIFF_BANDS: usize = 4;
+
+/// Lowest frequency a lossy low pass is looked for at
+const MIN_CUTOFF_HZ: f64 = 11_000.0;
+
+/// Smallest drop in dB between the khz below and above a cutoff
+const CLIFF_DB: f64 = 25.0;
+
+/// Highest low pass lossy encoders use, lame at 320 kbps cuts at 20.5 khz
+const MAX_LOSSY_CUTOFF_HZ: f64 = 20_600.0;
+
+/// Bands quieter than this many dB below the midrange count as empty
+const EXTENT_DB: f64 = 60.0;
+
+/// Highest frequency with content for a file to pass without a cliff
+const MIN_LOSSLESS_EXTENT_HZ: f64 = 19_500.0;
+
+/// Fewest non silent frames needed for a verdict
+const MIN_FRAMES: usize = 8;
+
+/// Frames with a lower rms are skipped as silence
+const SILENT_RMS: f64 = 1e-4;
+
+/// Codecs that are supposed to be lossless
+const LOSSLESS_CODECS: [&str; 7] = ["flac", "alac", "wav", "aiff", "ape", "wavpack", "tta"];
+
+static PASSES: SingleFlight = SingleFlight::new();
+
+/// What the spectrum says about a lossless file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    /// highs reach close to nyquist without a cliff
+    Lossless,
+    /// the spectrum has a lossy encoder's low pass cliff
+    Transcode,
+    /// no cliff but not enough highs to tell
+    Uncertain,
+    /// ffmpeg could not decode the file or it was mostly silent
+    Failed,
+}
+
+impl Verdict {
+    pub const ALL: [Verdict; 4] = [
+        Verdict::Lossless,
+        Verdict::Transcode,
+        Verdict::Uncertain,
+        Verdict::Failed,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Verdict::Lossless => "lossless",
+            Verdict::Transcode => "transcode",
+            Verdict::Uncertain => "uncertain",
+            Verdict::Failed => "failed",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|v| v.as_str() == name)
+    }
+}
+
+/// Result of analysing decoded samples
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Analysis {
+    pub verdict: Verdict,
+    /// where the spectrum falls off a cliff, if it does
+    pub cutoff_hz: Option<f64>,
+    /// highest frequency with content
+    pub extent_hz: f64,
+}
+
+/// A lossless file flagged as a likely transcode
+#[derive(Debug, Clone, Serialize)]
+pub struct Suspect {
+    pub trackhash: String,
+    pub filepath: String,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub verdict: Verdict,
+    pub cutoff: i64,
+    pub extent: i64,
+    pub checked_at: i64,
+}
+
+/// Library wide summary of the verdicts
+#[derive(Debug, Clone, Serialize)]
+pub struct LosslessReport {
+    pub running: bool,
+    /// lossless files in the library
+    pub files: usize,
+    pub checked: usize,
+    pub counts: HashMap<Verdict, usize>,
+    /// files with the requested verdict, lowest cutoff first
+    pub tracks: Vec<Suspect>,
+}
+
+/// Whether a track's file is supposed to be lossless
+pub fn is_lossless(track: &Track) -> bool {
+    let codec = track.extra_info().codec;
+    if !codec.is_empty() {
+        return LOSSLESS_CODECS.contains(&codec.as_str());
+    }
+    let ext = Path::new(&track.filepath)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+    matches!(
+        ext.as_str(),
+        "flac" | "wav" | "aif" | "aiff" | "ape" | "wv" | "tta"
+    )
+}
+
+/// Whether a validation pass is running
+pub fn is_running() -> bool {
+    PASSES.is_running()
+}
+
+/// Run a validation pass in the background
+pub fn spawn_pass() {
+    tokio::spawn(async {
+        match run_pass().await {
+            Ok(0) => {}
+            Ok(count) => tracing::info!("Checked {} lossless files for transcodes", count),
+            Err(e) => tracing::warn!("Lossless validation pass failed: {}", e),
+        }
+    });
+}
+
+/// Check new and changed lossless files and forget removed ones, returns the
+/// number of files checked
+pub async fn run_pass() -> Result<usize> {
+    PASSES.run(pass).await
+}
+
+async fn pass() -> Result<usize> {
+    let ffmpeg_path = ffmpeg::get_ffmpeg_path();
+    if !ffmpeg::is_ffmpeg_available() {
+        return Err(anyhow!("ffmpeg not found at {}", ffmpeg_path.display()));
+    }
+
+    let tracks: Vec<Track> = TrackStore::get()
+        .get_all()
+        .into_iter()
+        .filter(is_lossless)
+        .collect();
+    let known = LosslessTable::last_mods().await?;
+
+    let live: HashSet<&str> = tracks.iter().map(|t| t.filepath.as_str()).collect();
+    let removed: Vec<String> = known
+        .keys()
+        .filter(|path| !live.contains(path.as_str()))
+        .cloned()
+        .collect();
+    LosslessTable::delete_many(&removed).await?;
+
+    let todo: Vec<Track> = tracks
+        .into_iter()
+        .filter(|t| known.get(&t.filepath) != Some(&t.last_mod))
+        .collect();
+
+    let mut done = 0;
+    for chunk in todo.chunks(LOSSLESS_BATCH) {
+        let items = chunk.to_vec();
+        let ffmpeg_path = ffmpeg_path.clone();
+        let rows = tokio::task::spawn_blocking(move || {
+            items
+                .par_iter()
+                .map(|track| check_file(&ffmpeg_path, track))
+                .collect::<Vec<_>>()
+        })
+        .await?;
+
+        LosslessTable::upsert_many(&rows).await?;
+        done += rows.len();
+    }
+
+    Ok(done)
+}
+
+/// Check a single track now and store the verdict
+pub async fn check_track(trackhash: &str) -> Result<LosslessCheck> {
+    let Some(track) = TrackStore::get().get_by_hash(trackhash) else {
+        bail!("track not found");
+    };
+    if !is_lossless(&track) {
+        bail!("{} is not a lossless file", track.filepath);
+    }
+    let ffmpeg_path = ffmpeg::get_ffmpeg_path();
+    if !ffmpeg::is_ffmpeg_available() {
+        bail!("ffmpeg not found at {}", ffmpeg_path.display());
+    }
+
+    let check = tokio::task::spawn_blocking(move || check_file(&ffmpeg_path, &track)).await?;
+    LosslessTable::upsert_many(std::slice::from_ref(&check)).await?;
+    Ok(check)
+}
+
+/// Verdicts across the library and the files with one verdict
+pub async fn report(verdict: Verdict) -> Result<LosslessReport> {
+    let tracks: HashMap<String, Track> = TrackStore::get()
+        .get_all()
+        .into_iter()
+        .filter(is_lossless)
+        .map(|t| (t.filepath.clone(), t))
+        .collect();
+    let checks: Vec<LosslessCheck> = LosslessTable::all()
+        .await?
+        .into_iter()
+        .filter(|c| tracks.contains_key(&c.filepath))
+        .collect();
+
+    let mut counts: HashMap<Verdict, usize> = Verdict::ALL.iter().map(|v| (*v, 0)).collect();
+    let mut listed = Vec::new();
+    for check in &checks {
+        let Some(found) = Verdict::from_name(&check.verdict) else {
+            continue;
+        };
+        *counts.entry(found).or_default() += 1;
+        if found != verdict {
+            continue;
+        }
+        let track = &tracks[&check.filepath];
+        listed.push(Suspect {
+            trackhash: track.trackhash.clone(),
+            filepath: check.filepath.clone(),
+            title: track.title.clone(),
+            artist: track.artist(),
+            album: track.album.clone(),
+            verdict: found,
+            cutoff: check.cutoff,
+            extent: check.extent,
+            checked_at: check.checked_at,
+        });
+    }
+    listed.sort_by(|a, b| {
+        a.cutoff
+            .cmp(&b.cutoff)
+            .then_with(|| a.filepath.cmp(&b.filepath))
+    });
+
+    Ok(LosslessReport {
+        running: is_running(),
+        files: tracks.len(),
+        checked: checks.len(),
+        counts,
+        tracks: listed,
+    })
+}
+
+/// Decode and analyse a file, decoding errors are stored as a failed verdict
+/// so the file is only retried once it changes
+fn check_file(ffmpeg_path: &Path, track: &Track) -> LosslessCheck {
+    let rate = match track.extra_info().samplerate {
+        0 => 44_100,
+        rate => rate.min(MAX_ANALYSIS_RATE),
+    };
+    let start = (f64::from(track.duration) - ANALYSIS_SECONDS).max(0.0) / 2.0;
+
+    let analysis = match decode(ffmpeg_path, Path::new(&track.filepath), start, rate) {
+        Ok(samples) => analyse_samples(&samples, rate),
+        Err(e) => {
+            tracing::debug!("failed to decode {}: {}", track.filepath, e);
+            None
+        }
+    };
+    let analysis = analysis.unwrap_or(Analysis {
+        verdict: Verdict::Failed,
+        cutoff_hz: None,
+        extent_hz: 0.0,
+    });
+
+    LosslessCheck {
+        filepath: track.filepath.clone(),
+        trackhash: track.trackhash.clone(),
+        verdict: analysis.verdict.as_str().to_string(),
+        cutoff: analysis.cutoff_hz.map_or(0, |f| f.round() as i64),
+        extent: analysis.extent_hz.round() as i64,
+        samplerate: i64::from(rate),
+        last_mod: track.last_mod,
+        checked_at: chrono::Utc::now().timestamp(),
+    }
+}
+
+/// Decode part of a file to mono 32 bit float samples
+fn decode(ffmpeg_path: &Path, path: &Path, start: f64, rate: u32) -> Result<Vec<f32>> {
+    let output = Command::new(ffmpeg_path)
+        .args(["-v", "error", "-nostdin"])
+        .args(["-ss", &format!("{:.3}", start)])
+        .args(["-t", &ANALYSIS_SECONDS.to_string()])
+        .arg("-i")
+        .arg(path)
+        .args(["-vn", "-ac", "1", "-ar", &rate.to_string()])
+        .args(["-f", "f32le", "-"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ffmpeg failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(output
+        .stdout
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
+}
+
+/// Judge decoded mono samples, `None` when there is too little sound
+pub fn analyse_samples(samples: &[f32], rate: u32) -> Option<Analysis> {
+    let spectrum = average_spectrum(samples)?;
+    let bin_hz = f64::from(rate) / FFT_SIZE as f64;
+    let nyquist = f64::from(rate) / 2.0;
+
+    // average the power of each band, then go to dB
+    let band_count = (nyquist / BAND_HZ) as usize;
+    let mut sums = vec![(0.0_f64, 0_usize); band_count];
+    for (bin, power) in spectrum.iter().enumerate().skip(1) {
+        let band = (bin as f64 * bin_hz / BAND_HZ) as usize;
+        if let Some(sum) = sums.get_mut(band) {
+            sum.0 += power;
+            sum.1 += 1;
+        }
+    }
+    let bands: Vec<f64> = sums
+        .iter()
+        .map(|(sum, n)| 10.0 * (sum / (*n).max(1) as f64 + 1e-30).log10())
+        .collect();
+
+    let band_of = |hz: f64| ((hz / BAND_HZ) as usize).min(band_count);
+    let midrange = &bands[band_of(1_000.0)..band_of(4_000.0)];
+    if midrange.is_empty() {
+        return None;
+    }
+    let reference = mean(midrange);
+
+    let extent_hz = bands
+        .iter()
+        .rposition(|level| *level >= reference - EXTENT_DB)
+        .map_or(0.0, |band| (band + 1) as f64 * BAND_HZ);
+
+    // the boundary with the steepest drop that nothing above recovers from
+    let mut cliff: Option<(f64, f64)> = None;
+    for boundary in band_of(MIN_CUTOFF_HZ).max(CLIFF_BANDS)..=band_count.saturating_sub(CLIFF_BANDS)
+    {
+        let below = mean(&bands[boundary - CLIFF_BANDS..boundary]);
+        let above = mean(&bands[boundary..boundary + CLIFF_BANDS]);
+        let peak_above = bands[boundary..].iter().copied().fold(f64::MIN, f64::max);
+        let drop = below - above;
+        if drop >= CLIFF_DB
+            && peak_above <= below - CLIFF_DB / 2.0
+            && cliff.is_none_or(|(_, best)| drop > best)
+        {
+            cliff = Some((boundary as f64 * BAND_HZ, drop));
+        }
+    }
+
+    let cutoff_hz = cliff.map(|(hz, _)| hz);
+    let verdict = match cutoff_hz {
+        Some(hz) if hz <= MAX_LOSSY_CUTOFF_HZ && hz < nyquist * 0.95 => Verdict::Transcode,
+        _ if extent_hz >= MIN_LOSSLESS_EXTENT_HZ.min(nyquist * 0.9) => Verdict::Lossless,
+        _ => Verdict::Uncertain,
+    };
+
+    Some(Analysis {
+        verdict,
+        cutoff_hz,
+        extent_hz,
+    })
+}
+
+/// Power spectrum averaged over the non silent frames, hann windowed
+fn average_spectrum(samples: &[f32]) -> Option<Vec<f64>> {
+    let window: Vec<f64> = (0..FFT_SIZE)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f64 / FFT_SIZE as f64).cos())
+        .collect();
+
+    let mut power = vec![0.0_f64; FFT_SIZE / 2];
+    let mut frames = 0;
+    let mut re = vec![0.0_f64; FFT_SIZE];
+    let mut im = vec![0.0_f64; FFT_SIZE];
+
+    for frame in samples.chunks_exact(FFT_SIZE) {
+        let rms =
+            (frame.iter().map(|s| f64::from(*s).powi(2)).sum::<f64>() / FFT_SIZE as f64).sqrt();
+        if rms < SILENT_RMS {
+            continue;
+        }
+
+        for (i, sample) in frame.iter().enumerate() {
+            re[i] = f64::from(*sample) * window[i];
+            im[i] = 0.0;
+        }
+        fft(&mut re, &mut im);
+        for (bin, p) in power.iter_mut().enumerate() {
+            *p += re[bin] * re[bin] + im[bin] * im[bin];
+        }
+        frames += 1;
+    }
+
+    if frames < MIN_FRAMES {
+        return None;
+    }
+    for p in &mut power {
+        *p /= frames as f64;
+    }
+    Some(power)
+}
+
+/// In place radix 2 fft, the length must be a power of two
+fn fft(re: &mut [f64], im: &mut [f64]) {
+    let n = re.len();
+
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f64;
+        let (step_re, step_im) = (angle.cos(), angle.sin());
+        for start in (0..n).step_by(len) {
+            let (mut w_re, mut w_im) = (1.0, 0.0);
+            for k in 0..len / 2 {
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+                (w_re, w_im) = (
+                    w_re * step_re - w_im * step_im,
+                    w_re * step_im + w_im * step_re,
+                );
+            }
+        }
+        len <<= 1;
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len().max(1) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 44_100;
+
+    /// tones spread evenly from 100 hz up to a limit with scrambled phases
+    fn tones(limit: f64) -> Vec<f32> {
+        let count = 150;
+        let len = RATE as usize * 2;
+        let mut samples = vec![0.0_f32; len];
+        for k in 0..count {
+            let freq = 100.0 + k as f64 * (limit - 100.0) / count as f64;
+            let phase = (k * 7919 % 628) as f64 / 100.0;
+            for (i, s) in samples.iter_mut().enumerate() {
+                let t = i as f64 / f64::from(RATE);
+                *s += (0.004 * (2.0 * PI * freq * t + phase).sin()) as f32;
+            }
+        }
+        samples
+    }
+
+    #[test]
+    fn test_fft_finds_a_tone() {
+        let mut re: Vec<f64> = (0..64)
+            .map(|i| (2.0 * PI * 5.0 * i as f64 / 64.0).cos())
+            .collect();
+        let mut im = vec![0.0; 64];
+        fft(&mut re, &mut im);
+        let peak = (0..32)
+            .max_by(|a, b| re[*a].hypot(im[*a]).total_cmp(&re[*b].hypot(im[*b])))
+            .unwrap();
+        assert_eq!(peak, 5);
+    }
+
+    fn analyse_tones(limit: f64) -> Analysis {
+        analyse_samples(&tones(limit), RATE).unwrap()
+    }
+
+    #[test]
+    fn test_verdicts() {
+        let full = analyse_tones(21_900.0);
+        assert_eq!(full.verdict, Verdict::Lossless);
+        assert!(full.extent_hz >= 21_500.0);
+
+        let mp3 = analyse_tones(16_000.0);
+        assert_eq!(mp3.verdict, Verdict::Transcode);
+        let cutoff = mp3.cutoff_hz.unwrap();
+        assert!(
+            (16_000.0..=16_500.0).contains(&cutoff),
+            "cutoff at {}",
+            cutoff
+        );
+
+        assert_eq!(analyse_tones(9_000.0).verdict, Verdict::Uncertain);
+
+        assert_eq!(analyse_samples(&vec![0.0; RATE as usize], RATE), None);
+    }
+}
@@ -1,7 +1,18 @@
 //! Lyrics fetching and parsing
 
 use anyhow::Result;
+use lofty::{ItemKey, Probe, Tag, TaggedFileExt};
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
 use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// any `[mm:ss]` or `[mm:ss.xx]` timestamp, wherever it sits in a line
+static LRC_TIMESTAMP: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\[(\d{1,3}):(\d{2})(?:\.(\d{1,3}))?\]").unwrap());
+
+/// Sidecar lyrics file extensions, in the order they are looked up
+pub const SIDECAR_EXTENSIONS: [&str; 3] = ["lrc", "rlrc", "txt"];
 
 /// Lyrics line with timestamp
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -156,23 +167,131 @@ impl LyricsLib {
         current_line
     }
 
-    /// Search for lyrics from embedded metadata
-    pub fn from_embedded(track_path: &std::path::Path) -> Option<Lyrics> {
-        use lofty::{ItemKey, Probe, TaggedFileExt};
+    /// Lyrics text stored in a tag
+    ///
+    /// covers USLT frames, the mp4 ©lyr atom and vorbis LYRICS comments
+    pub fn from_tag(tag: &Tag) -> Option<String> {
+        tag.get_string(&ItemKey::Lyrics)
+            .or_else(|| tag.get_string(&ItemKey::Unknown("USLT".to_string())))
+            .or_else(|| tag.get_string(&ItemKey::Unknown("SYLT".to_string())))
+            .filter(|text| !text.trim().is_empty())
+            .map(|text| text.to_string())
+    }
 
+    /// Lyrics text embedded in the tags of a track
+    pub fn embedded_text(track_path: &Path) -> Option<String> {
         let tagged_file = Probe::open(track_path).ok()?.read().ok()?;
 
         let tag = tagged_file
             .primary_tag()
             .or_else(|| tagged_file.first_tag())?;
 
-        // Try to find lyrics in common tag fields
-        let lyrics_text = tag
-            .get_string(&ItemKey::Lyrics)
-            .or_else(|| tag.get_string(&ItemKey::Unknown("USLT".to_string())))
-            .or_else(|| tag.get_string(&ItemKey::Unknown("SYLT".to_string())));
+        Self::from_tag(tag)
+    }
+
+    /// Search for lyrics from embedded metadata
+    pub fn from_embedded(track_path: &Path) -> Option<Lyrics> {
+        let mut lyrics = Self::parse_auto(&Self::embedded_text(track_path)?);
+        lyrics.source = Some("embedded".to_string());
+        Some(lyrics)
+    }
+
+    /// First sidecar lyrics file next to a track
+    pub fn sidecar_path(track_path: &Path) -> Option<PathBuf> {
+        SIDECAR_EXTENSIONS
+            .iter()
+            .map(|ext| track_path.with_extension(ext))
+            .find(|path| path.is_file())
+    }
+
+    /// Lyrics from a sidecar file next to a track
+    pub fn from_sidecar(track_path: &Path) -> Option<Lyrics> {
+        let content = std::fs::read_to_string(Self::sidecar_path(track_path)?).ok()?;
+        if content.trim().is_empty() {
+            return None;
+        }
+
+        let mut lyrics = Self::parse_auto(&content);
+        lyrics.source = Some("sidecar".to_string());
+        Some(lyrics)
+    }
+
+    /// Lyrics stored with a track, sidecar files win over embedded tags
+    pub fn local(track_path: &Path) -> Option<Lyrics> {
+        Self::from_sidecar(track_path).or_else(|| Self::from_embedded(track_path))
+    }
+
+    /// Lyrics text stored with a track as written, sidecar files win over
+    /// embedded tags
+    ///
+    /// a leading byte order mark is dropped so the text can be served as utf-8
+    pub fn local_text(track_path: &Path) -> Option<String> {
+        Self::sidecar_path(track_path)
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .filter(|content| !content.trim().is_empty())
+            .or_else(|| Self::embedded_text(track_path))
+            .map(|content| match content.strip_prefix('\u{feff}') {
+                Some(stripped) => stripped.to_string(),
+                None => content,
+            })
+    }
+
+    /// Whether a track has lyrics, given the text already read from its tags
+    pub fn has_local(track_path: &Path, embedded: Option<&str>) -> bool {
+        embedded.is_some_and(|text| !text.trim().is_empty())
+            || Self::sidecar_path(track_path).is_some()
+    }
+
+    /// Move every timestamp in LRC content by `ms`, clamping at zero
+    ///
+    /// metadata tags and line text are left as they are, and each timestamp
+    /// keeps the number of fraction digits it was written with
+    pub fn shift_lrc(content: &str, ms: i64) -> String {
+        LRC_TIMESTAMP
+            .replace_all(content, |caps: &Captures| {
+                let minutes: i64 = caps[1].parse().unwrap_or(0);
+                let seconds: i64 = caps[2].parse().unwrap_or(0);
+                let fraction = caps.get(3).map(|m| m.as_str()).unwrap_or("");
+                let fraction_ms = match fraction.len() {
+                    0 => 0,
+                    1 => fraction.parse::<i64>().unwrap_or(0) * 100,
+                    2 => fraction.parse::<i64>().unwrap_or(0) * 10,
+                    _ => fraction.parse::<i64>().unwrap_or(0),
+                };
+
+                let total = ((minutes * 60 + seconds) * 1000 + fraction_ms)
+                    .saturating_add(ms)
+                    .max(0);
+                let (minutes, rest) = (total / 60_000, total % 60_000);
+                let (seconds, millis) = (rest / 1000, rest % 1000);
+                match fraction.len() {
+                    0 => format!("[{:02}:{:02}]", minutes, seconds),
+                    1 => format!("[{:02}:{:02}.{}]", minutes, seconds, millis / 100),
+                    2 => format!("[{:02}:{:02}.{:02}]", minutes, seconds, millis / 10),
+                    _ => format!("[{:02}:{:02}.{:03}]", minutes, seconds, millis),
+                }
+            })
+            .into_owned()
+    }
 
-        lyrics_text.map(|text| Self::parse_lrc(text))
+    /// Write LRC content to the `.lrc` sidecar next to a track
+    ///
+    /// the file is replaced in one step so players never read a half written file
+    pub fn save_sidecar(track_path: &Path, content: &str) -> Result<PathBuf> {
+        let lrc_path = track_path.with_extension("lrc");
+        let tmp_path = track_path.with_extension("lrc.tmp");
+        std::fs::write(&tmp_path, content)?;
+        std::fs::rename(&tmp_path, &lrc_path)?;
+        Ok(lrc_path)
+    }
+
+    /// Parse lyrics as LRC when they carry timestamps, as plain text otherwise
+    pub fn parse_auto(content: &str) -> Lyrics {
+        if Self::is_lrc_format(content) {
+            Self::parse_lrc(content)
+        } else {
+            Self::parse_plain(content)
+        }
     }
 
     /// Check if text looks like LRC format
@@ -205,3 +324,140 @@ pub struct FetchedLyrics {
     pub synced: bool,
     pub source: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_shift_lrc() {
+        let lrc = "[ar:Someone]\n[00:01.50]one\n[00:59.99][01:02]two\n[00:00.200]three";
+        assert_eq!(
+            LyricsLib::shift_lrc(lrc, 250),
+            "[ar:Someone]\n[00:01.75]one\n[01:00.24][01:02]two\n[00:00.450]three"
+        );
+        assert_eq!(
+            LyricsLib::shift_lrc(lrc, -1000),
+            "[ar:Someone]\n[00:00.50]one\n[00:58.99][01:01]two\n[00:00.000]three"
+        );
+    }
+
+    #[test]
+    fn test_save_sidecar() {
+        let dir = tempfile::tempdir().unwrap();
+        let track = dir.path().join("song.mp3");
+        let path = LyricsLib::save_sidecar(&track, "[00:01.00]hi").unwrap();
+        assert_eq!(path, dir.path().join("song.lrc"));
+        assert!(LyricsLib::from_sidecar(&track).unwrap().is_synced);
+        assert!(!dir.path().join("song.lrc.tmp").exists());
+    }
+
+    #[test]
+    fn test_sidecar_lookup_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let track = dir.path().join("song.flac");
+        assert!(LyricsLib::sidecar_path(&track).is_none());
+        assert!(!LyricsLib::has_local(&track, Some("  ")));
+        assert!(LyricsLib::has_local(&track, Some("la la la")));
+
+        std::fs::write(dir.path().join("song.txt"), "first line\nsecond line").unwrap();
+        let plain = LyricsLib::from_sidecar(&track).unwrap();
+        assert!(!plain.is_synced);
+        assert_eq!(plain.lines.len(), 2);
+        assert_eq!(plain.source.as_deref(), Some("sidecar"));
+
+        // an lrc file is preferred over a plain text one
+        std::fs::write(dir.path().join("song.lrc"), "[00:01.50]hello").unwrap();
+        let synced = LyricsLib::local(&track).unwrap();
+        assert!(synced.is_synced);
+        assert_eq!(synced.lines[0].time, Some(1.5));
+        assert!(LyricsLib::has_local(&track, None));
+    }
+
+    #[test]
+    fn test_local_text_is_raw() {
+        let dir = tempfile::tempdir().unwrap();
+        let track = dir.path().join("song.flac");
+        assert!(LyricsLib::local_text(&track).is_none());
+
+        std::fs::write(dir.path().join("song.lrc"), "   \n").unwrap();
+        assert!(LyricsLib::local_text(&track).is_none());
+
+        let lrc = "[ar:Someone]\r\n[00:01.50]hello\r\n";
+        std::fs::write(dir.path().join("song.lrc"), format!("\u{feff}{}", lrc)).unwrap();
+        assert_eq!(LyricsLib::local_text(&track).as_deref(), Some(lrc));
+    }
+
+    fn lrc_line() -> impl Strategy<Value = (u32, String)> {
+        // centiseconds below 100 minutes, the most a two digit lrc minute holds
+        (0u32..600_000, "[a-z]{1,10}( [a-z]{1,10}){0,3}")
+    }
+
+    proptest! {
+        #[test]
+        fn prop_parsing_never_panics(content in "\\PC{0,200}", ms in any::<i64>()) {
+            let lyrics = LyricsLib::parse_auto(&content);
+            if lyrics.is_synced {
+                prop_assert!(lyrics
+                    .lines
+                    .windows(2)
+                    .all(|pair| pair[0].time <= pair[1].time));
+            }
+            LyricsLib::to_lrc(&lyrics);
+            LyricsLib::shift_lrc(&content, ms);
+        }
+
+        #[test]
+        fn prop_lrc_round_trips(lines in proptest::collection::vec(lrc_line(), 1..20)) {
+            let lyrics = Lyrics {
+                lines: lines
+                    .iter()
+                    .map(|(cs, text)| LyricsLine {
+                        time: Some(*cs as f64 / 100.0),
+                        text: text.clone(),
+                    })
+                    .collect(),
+                is_synced: true,
+                source: None,
+                copyright: None,
+            };
+
+            let parsed = LyricsLib::parse_lrc(&LyricsLib::to_lrc(&lyrics));
+            prop_assert!(parsed.is_synced);
+            prop_assert_eq!(parsed.lines.len(), lines.len());
+
+            let mut expected = lines.clone();
+            expected.sort_by_key(|(cs, _)| *cs);
+            for (line, (cs, _)) in parsed.lines.iter().zip(&expected) {
+                // float rounding in to_lrc may cost one centisecond
+                let time = line.time.unwrap();
+                prop_assert!((time - *cs as f64 / 100.0).abs() <= 0.011);
+            }
+        }
+
+        #[test]
+        fn prop_shift_lrc_reverses(
+            lines in proptest::collection::vec(lrc_line(), 1..20),
+            ms in 0i64..600_000,
+        ) {
+            let content = lines
+                .iter()
+                .map(|(cs, text)| {
+                    let millis = *cs as i64 * 10;
+                    format!(
+                        "[{:02}:{:02}.{:03}]{}",
+                        millis / 60_000,
+                        millis % 60_000 / 1000,
+                        millis % 1000,
+                        text
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            let shifted = LyricsLib::shift_lrc(&content, ms);
+            prop_assert_eq!(LyricsLib::shift_lrc(&shifted, -ms), content);
+        }
+    }
+}
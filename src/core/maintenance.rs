@@ -0,0 +1,253 @@
+//! Database maintenance - vacuum, analyze and index rebuilds
+//!
+//! long lived databases with a lot of scrobbles fragment over time since rows
+//! are appended and pruned constantly. maintenance rewrites both sqlite files,
+//! refreshes the query planner statistics, rebuilds sql indexes along with the
+//! in-memory search indexes, and reports file sizes before and after. it runs
+//! on demand through the admin api or on the `db_maintenance_interval` cron.
+
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use utoipa::ToSchema;
+
+use crate::config::Paths;
+use crate::db::{DbEngine, UserdataEngine};
+use crate::stores::{AlbumStore, ArtistStore, SearchStore, TrackStore};
+
+/// report of the last finished maintenance run, kept on disk across restarts
+static LAST_RUN: Lazy<RwLock<Option<MaintenanceReport>>> =
+    Lazy::new(|| RwLock::new(MaintenanceReport::load()));
+
+/// set while a maintenance run holds the databases
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Which maintenance tasks to run
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+pub struct MaintenanceTasks {
+    /// rewrite the database files to drop free pages and fragmentation
+    #[serde(default = "default_true")]
+    pub vacuum: bool,
+    /// refresh the statistics the query planner picks indexes with
+    #[serde(default = "default_true")]
+    pub analyze: bool,
+    /// rebuild sql indexes and the in-memory search indexes
+    #[serde(default = "default_true")]
+    pub reindex: bool,
+}
+
+impl Default for MaintenanceTasks {
+    fn default() -> Self {
+        Self {
+            vacuum: true,
+            analyze: true,
+            reindex: true,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// On-disk size of a database and its write-ahead log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DbFileSize {
+    pub name: String,
+    pub path: String,
+    pub size: u64,
+    pub wal_size: u64,
+    pub total: u64,
+}
+
+/// Outcome of a maintenance run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceReport {
+    pub tasks: MaintenanceTasks,
+    pub started_at: i64,
+    pub finished_at: i64,
+    pub duration_ms: u64,
+    pub before: Vec<DbFileSize>,
+    pub after: Vec<DbFileSize>,
+    /// bytes freed across all database files, negative if they grew
+    pub reclaimed: i64,
+    pub error: Option<String>,
+}
+
+impl MaintenanceReport {
+    fn load() -> Option<Self> {
+        let path = Paths::get().ok()?.db_maintenance_path();
+        let json = std::fs::read_to_string(path).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = Paths::get()?.db_maintenance_path();
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Report of the last maintenance run, if any
+pub fn last_run() -> Option<MaintenanceReport> {
+    LAST_RUN.read().clone()
+}
+
+/// Whether a maintenance run is in progress
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::Relaxed)
+}
+
+/// Current sizes of the app and userdata databases
+pub fn db_sizes() -> Result<Vec<DbFileSize>> {
+    let paths = Paths::get()?;
+    Ok(vec![
+        file_size("app", &paths.app_db_path()),
+        file_size("userdata", &paths.userdata_db_path()),
+    ])
+}
+
+fn file_size(name: &str, path: &Path) -> DbFileSize {
+    let len = |p: &Path| std::fs::metadata(p).map(|m| m.len()).unwrap_or(0);
+    let size = len(path);
+    let wal_size = len(&sidecar(path, "-wal"));
+    DbFileSize {
+        name: name.to_string(),
+        path: path.to_string_lossy().to_string(),
+        size,
+        wal_size,
+        total: size + wal_size,
+    }
+}
+
+fn sidecar(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Run the selected maintenance tasks on both databases
+///
+/// fails without touching anything if another run is in progress
+pub async fn run(tasks: MaintenanceTasks) -> Result<MaintenanceReport> {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return Err(anyhow!("Database maintenance is already running"));
+    }
+    let _running = RunningGuard;
+
+    let started = Instant::now();
+    let started_at = chrono::Utc::now().timestamp();
+    let before = db_sizes()?;
+
+    let result = run_tasks(tasks).await;
+
+    let after = db_sizes()?;
+    let total = |sizes: &[DbFileSize]| sizes.iter().map(|s| s.total as i64).sum::<i64>();
+    let report = MaintenanceReport {
+        tasks,
+        started_at,
+        finished_at: chrono::Utc::now().timestamp(),
+        duration_ms: started.elapsed().as_millis() as u64,
+        reclaimed: total(&before) - total(&after),
+        before,
+        after,
+        error: result.as_ref().err().map(|e| e.to_string()),
+    };
+
+    if let Err(e) = report.save() {
+        tracing::warn!("failed to save maintenance report: {}", e);
+    }
+    *LAST_RUN.write() = Some(report.clone());
+
+    result.map(|_| report)
+}
+
+/// clears the running flag however the run ends
+struct RunningGuard;
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+async fn run_tasks(tasks: MaintenanceTasks) -> Result<()> {
+    let app = DbEngine::get()?;
+    let userdata = UserdataEngine::get()?;
+
+    for pool in [app.pool(), userdata.pool()] {
+        maintain(pool, tasks).await?;
+    }
+
+    if tasks.reindex {
+        rebuild_search_indexes();
+    }
+    Ok(())
+}
+
+async fn maintain(pool: &SqlitePool, tasks: MaintenanceTasks) -> Result<()> {
+    // vacuum cannot run inside a transaction so use one plain connection
+    let mut conn = pool.acquire().await?;
+
+    if tasks.reindex {
+        sqlx::query("REINDEX").execute(&mut *conn).await?;
+    }
+    if tasks.vacuum {
+        sqlx::query("VACUUM").execute(&mut *conn).await?;
+    }
+    if tasks.analyze {
+        sqlx::query("ANALYZE").execute(&mut *conn).await?;
+        sqlx::query("PRAGMA optimize").execute(&mut *conn).await?;
+    }
+
+    // fold the log back into the main file so the freed space shows on disk
+    sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Rebuild the search indexes from the loaded stores
+fn rebuild_search_indexes() {
+    let search = SearchStore::get();
+    search.load_tracks(&TrackStore::get().get_all());
+    search.load_albums(&AlbumStore::get().get_all());
+    search.load_artists(&ArtistStore::get().get_all());
+}
+
+/// Whether a scheduled run is due given the interval in hours
+pub fn is_due(interval_hours: u32, last_started_at: Option<i64>, now: i64) -> bool {
+    if interval_hours == 0 {
+        return false;
+    }
+    match last_started_at {
+        Some(last) => now - last >= i64::from(interval_hours) * 3600,
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_due() {
+        assert!(!is_due(0, None, 1_000_000));
+        assert!(is_due(24, None, 1_000_000));
+        assert!(!is_due(24, Some(1_000_000 - 3600), 1_000_000));
+        assert!(is_due(24, Some(1_000_000 - 24 * 3600), 1_000_000));
+    }
+
+    #[test]
+    fn test_tasks_default_to_all() {
+        let tasks: MaintenanceTasks = serde_json::from_str(r#"{"vacuum": false}"#).unwrap();
+        assert!(!tasks.vacuum);
+        assert!(tasks.analyze);
+        assert!(tasks.reindex);
+    }
+}
@@ -1,64 +1,214 @@
 //! Map additional data into stores (favorites, colors, scrobbles)
 
+use std::collections::{HashMap, HashSet};
+
+use crate::db::tables::{MbidTable, RatingTable};
 use crate::db::DbEngine;
-use crate::stores::{AlbumStore, ArtistStore, TrackStore};
+use crate::models::{ColorVariants, Track};
+use crate::stores::{AlbumStore, ArtistStore, PlayRecord, PlayStatsStore, TrackStore};
 use anyhow::Result;
 
-/// Map favorites from database to stores
-pub async fn map_favorites() -> Result<()> {
+/// hashes looked up per query when mapping a scope
+const SCOPE_BATCH: usize = 500;
+
+/// Tracks, albums and artists an incremental update rebuilt
+#[derive(Debug, Default)]
+pub struct MapScope {
+    pub trackhashes: HashSet<String>,
+    pub albumhashes: HashSet<String>,
+    pub artisthashes: HashSet<String>,
+}
+
+impl MapScope {
+    fn contains(&self, item_type: &str, hash: &str) -> bool {
+        match item_type {
+            "track" => self.trackhashes.contains(hash),
+            "album" => self.albumhashes.contains(hash),
+            "artist" => self.artisthashes.contains(hash),
+            _ => false,
+        }
+    }
+}
+
+/// Map favorites, colors, mbids and play counts onto the items of a scope
+///
+/// the per-user play stats are only reloaded when one of the tracks was played
+pub async fn map_scope(scope: &MapScope) -> Result<()> {
     let db = DbEngine::get()?;
+    let pool = db.pool();
 
-    // Map track favorites
-    let track_favorites =
-        sqlx::query_as::<_, (String,)>("SELECT hash FROM favorite WHERE type = 'track'")
-            .fetch_all(db.pool())
-            .await?;
+    let hashes: Vec<&String> = scope
+        .trackhashes
+        .iter()
+        .chain(&scope.albumhashes)
+        .chain(&scope.artisthashes)
+        .collect();
+    for batch in hashes.chunks(SCOPE_BATCH) {
+        let placeholders = vec!["?"; batch.len()].join(",");
 
-    for (trackhash,) in track_favorites {
-        TrackStore::get().mark_favorite(&trackhash, true);
+        let sql = format!(
+            "SELECT hash, type, userid FROM favorite WHERE hash IN ({})",
+            placeholders
+        );
+        let mut query = sqlx::query_as::<_, (String, String, i64)>(&sql);
+        for hash in batch {
+            query = query.bind(*hash);
+        }
+        for (hash, fav_type, userid) in query.fetch_all(pool).await? {
+            if !scope.contains(&fav_type, &hash) {
+                continue;
+            }
+            match fav_type.as_str() {
+                "track" => TrackStore::get().mark_favorite(&hash, userid, true),
+                "album" => AlbumStore::get().mark_favorite(&hash, userid, true),
+                "artist" => ArtistStore::get().mark_favorite(&hash, userid, true),
+                _ => {}
+            }
+        }
+
+        let sql = format!(
+            "SELECT hash, type, color, color_dark, color_light FROM libdata WHERE hash IN ({})",
+            placeholders
+        );
+        let mut query = sqlx::query_as::<_, (String, String, String, String, String)>(&sql);
+        for hash in batch {
+            query = query.bind(*hash);
+        }
+        for (hash, data_type, color, dark, light) in query.fetch_all(pool).await? {
+            let variants = ColorVariants { dark, light };
+            match data_type.as_str() {
+                "album" if scope.albumhashes.contains(&hash) => {
+                    AlbumStore::get().set_color(&hash, &color, &variants)
+                }
+                "artist" if scope.artisthashes.contains(&hash) => {
+                    ArtistStore::get().set_color(&hash, &color, &variants)
+                }
+                _ => {}
+            }
+        }
+
+        let sql = format!(
+            "SELECT hash, type, mbid FROM mbid WHERE hash IN ({})",
+            placeholders
+        );
+        let mut query = sqlx::query_as::<_, (String, String, String)>(&sql);
+        for hash in batch {
+            query = query.bind(*hash);
+        }
+        for (hash, item_type, mbid) in query.fetch_all(pool).await? {
+            if !scope.contains(&item_type, &hash) {
+                continue;
+            }
+            match item_type.as_str() {
+                "track" => TrackStore::get().set_mbid(&hash, &mbid),
+                "album" => AlbumStore::get().set_mbid(&hash, &mbid),
+                "artist" => ArtistStore::get().set_mbid(&hash, &mbid),
+                _ => {}
+            }
+        }
     }
 
-    // Map album favorites
-    let album_favorites =
-        sqlx::query_as::<_, (String,)>("SELECT hash FROM favorite WHERE type = 'album'")
-            .fetch_all(db.pool())
-            .await?;
+    let trackhashes: Vec<&String> = scope.trackhashes.iter().collect();
+    let mut played = false;
+    for batch in trackhashes.chunks(SCOPE_BATCH) {
+        let sql = format!(
+            "SELECT trackhash, COUNT(*) as count FROM scrobble WHERE trackhash IN ({}) GROUP BY trackhash",
+            vec!["?"; batch.len()].join(",")
+        );
+        let mut query = sqlx::query_as::<_, (String, i32)>(&sql);
+        for hash in batch {
+            query = query.bind(*hash);
+        }
+        for (trackhash, count) in query.fetch_all(pool).await? {
+            TrackStore::get().set_play_count(&trackhash, count);
+            played = true;
+        }
+    }
 
-    for (albumhash,) in album_favorites {
-        AlbumStore::get().mark_favorite(&albumhash, true);
+    // plays count towards the albums and artists their track had when loaded
+    if played {
+        map_scrobble_data().await?;
     }
+    Ok(())
+}
 
-    // Map artist favorites
-    let artist_favorites =
-        sqlx::query_as::<_, (String,)>("SELECT hash FROM favorite WHERE type = 'artist'")
+/// Map favorites from database to stores
+pub async fn map_favorites() -> Result<()> {
+    let db = DbEngine::get()?;
+
+    let favorites =
+        sqlx::query_as::<_, (String, String, i64)>("SELECT hash, type, userid FROM favorite")
             .fetch_all(db.pool())
             .await?;
 
-    for (artisthash,) in artist_favorites {
-        ArtistStore::get().mark_favorite(&artisthash, true);
+    let track_store = TrackStore::get();
+    let album_store = AlbumStore::get();
+    let artist_store = ArtistStore::get();
+
+    for (hash, fav_type, userid) in favorites {
+        match fav_type.as_str() {
+            "track" => track_store.mark_favorite(&hash, userid, true),
+            "album" => album_store.mark_favorite(&hash, userid, true),
+            "artist" => artist_store.mark_favorite(&hash, userid, true),
+            _ => {}
+        }
     }
 
     Ok(())
 }
 
-/// Map colors from database to album store
+/// Map colors and their dark and light mode variants from database to stores
 pub async fn map_colors() -> Result<()> {
     let db = DbEngine::get()?;
 
-    let colors = sqlx::query_as::<_, (String, String)>(
-        "SELECT hash, color FROM libdata WHERE type = 'album'",
+    let colors = sqlx::query_as::<_, (String, String, String, String, String)>(
+        "SELECT hash, type, color, color_dark, color_light FROM libdata",
     )
     .fetch_all(db.pool())
     .await?;
 
-    for (albumhash, color) in colors {
-        AlbumStore::get().set_color(&albumhash, &color);
+    for (hash, data_type, color, dark, light) in colors {
+        let variants = ColorVariants { dark, light };
+        match data_type.as_str() {
+            "album" => AlbumStore::get().set_color(&hash, &color, &variants),
+            "artist" => ArtistStore::get().set_color(&hash, &color, &variants),
+            _ => {}
+        }
     }
 
     Ok(())
 }
 
+/// Map MusicBrainz IDs from database to stores
+pub async fn map_mbids() -> Result<()> {
+    for (hash, item_type, mbid) in MbidTable::all().await? {
+        match item_type.as_str() {
+            "track" => TrackStore::get().set_mbid(&hash, &mbid),
+            "album" => AlbumStore::get().set_mbid(&hash, &mbid),
+            "artist" => ArtistStore::get().set_mbid(&hash, &mbid),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Map the star ratings users gave tracks into the play stats store
+pub async fn map_ratings() -> Result<()> {
+    let ratings = RatingTable::all().await?;
+    PlayStatsStore::get().load_ratings(
+        ratings
+            .into_iter()
+            .map(|r| (r.userid, r.trackhash, r.rating.clamp(1, 5) as u8)),
+    );
+
+    Ok(())
+}
+
 /// Map scrobble data (play counts) to stores
+///
+/// track play counts in the track store are library-wide while the play stats
+/// store keeps the per-user counts for tracks, albums and artists
 pub async fn map_scrobble_data() -> Result<()> {
     let db = DbEngine::get()?;
 
@@ -73,5 +223,65 @@ pub async fn map_scrobble_data() -> Result<()> {
         TrackStore::get().set_play_count(&trackhash, count);
     }
 
+    // Map per-user play stats
+    let plays = sqlx::query_as::<_, ScrobbledPlay>(
+        "SELECT userid, trackhash, duration, timestamp FROM scrobble",
+    )
+    .fetch_all(db.pool())
+    .await?;
+
+    let tracks = played_tracks(&plays);
+    PlayStatsStore::get().load(plays.iter().filter_map(|play| play_record(play, &tracks)));
+
+    Ok(())
+}
+
+/// Rebuild the play stats of one user after plays were removed from their history
+pub async fn map_user_scrobble_data(user_id: i64) -> Result<()> {
+    let db = DbEngine::get()?;
+
+    let plays = sqlx::query_as::<_, ScrobbledPlay>(
+        "SELECT userid, trackhash, duration, timestamp FROM scrobble WHERE userid = ?",
+    )
+    .bind(user_id)
+    .fetch_all(db.pool())
+    .await?;
+
+    let tracks = played_tracks(&plays);
+    PlayStatsStore::get().load_user(
+        user_id,
+        plays.iter().filter_map(|play| play_record(play, &tracks)),
+    );
+
     Ok(())
 }
+
+/// (userid, trackhash, duration, timestamp) of a scrobble
+type ScrobbledPlay = (i64, String, i32, i64);
+
+/// The library's copy of each played track, looked up once per track
+fn played_tracks(plays: &[ScrobbledPlay]) -> HashMap<String, Option<Track>> {
+    let track_store = TrackStore::get();
+    let mut tracks: HashMap<String, Option<Track>> = HashMap::new();
+    for (_, trackhash, _, _) in plays {
+        tracks
+            .entry(trackhash.clone())
+            .or_insert_with(|| track_store.get_by_hash(trackhash));
+    }
+    tracks
+}
+
+fn play_record<'a>(
+    (userid, trackhash, duration, timestamp): &'a ScrobbledPlay,
+    tracks: &'a HashMap<String, Option<Track>>,
+) -> Option<PlayRecord<'a>> {
+    let track = tracks.get(trackhash)?.as_ref()?;
+    Some(PlayRecord {
+        userid: *userid,
+        trackhash,
+        albumhash: &track.albumhash,
+        artisthashes: &track.artisthashes,
+        duration: *duration,
+        timestamp: *timestamp,
+    })
+}
@@ -1,30 +1,62 @@
 //! Core library functions for SwingMusic
 
+pub mod album_merge;
+pub mod album_stats;
 pub mod albums;
+pub mod archive;
+pub mod artist_split;
+pub mod artist_stats;
 pub mod artistlib;
+pub mod audiobooks;
+pub mod bulk_edit;
 pub mod colorlib;
 pub mod crons;
+pub mod dashboard;
+pub mod devices;
+pub mod dlna;
 pub mod ffmpeg;
 pub mod file_cache;
+pub mod fingerprint;
 pub mod folder;
+pub mod folder_playlists;
+pub mod gapless;
+pub mod genres;
+pub mod history;
 pub mod homepage;
 pub mod images;
+pub mod inbox;
 pub mod indexer;
+pub mod lossless;
 pub mod lyrics;
+pub mod maintenance;
 pub mod mapstuff;
+pub mod offline_sync;
+pub mod oidc;
+pub mod organizer;
+pub mod playback;
 pub mod playlistlib;
+pub mod podcasts;
+pub mod popularity;
 pub mod populate;
+pub mod radio;
 pub mod recipes;
+pub mod scrobble_export;
 pub mod search;
+pub mod search_index;
 pub mod silence;
+pub mod similarity;
+pub mod single_flight;
 pub mod sorting;
 pub mod tagger;
+pub mod track_filter;
 pub mod trackslib;
 pub mod transcode;
+pub mod trash;
 pub mod watchdogg;
+pub mod wrapped;
 
 pub use albums::AlbumLib;
-pub use artistlib::ArtistLib;
+pub use artistlib::{ArtistLib, TrackSources};
 pub use folder::FolderLib;
 pub use playlistlib::PlaylistLib;
 pub use search::SearchLib;
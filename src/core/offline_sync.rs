@@ -0,0 +1,462 @@
+//! Offline sync jobs
+//!
+//! a job converts every track of an album or playlist to one codec and bitrate
+//! so the result fits on a device with little space, then hands it out as a
+//! zip. tracks are converted one after another into a folder owned by the job
+//! instead of the transcode cache, which could evict them before the zip is
+//! fetched. jobs only live in memory, their folder is removed once they are
+//! deleted or expire.
+
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use utoipa::ToSchema;
+
+use crate::config::Paths;
+use crate::core::archive::ArchiveEntry;
+use crate::core::ffmpeg;
+use crate::core::transcode::{AudioFormat, TranscodeCache, Transcoder};
+use crate::models::Track;
+
+/// Seconds a finished job and its files are kept
+pub const JOB_TTL: i64 = 24 * 3600;
+
+/// Jobs a user may have at once, finished ones included
+pub const MAX_JOBS_PER_USER: usize = 5;
+
+/// Jobs converting at the same time, the rest wait in the queue
+const MAX_RUNNING: usize = 2;
+
+static JOBS: Lazy<RwLock<HashMap<String, SyncJob>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+static RUNNING: Semaphore = Semaphore::const_new(MAX_RUNNING);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncState {
+    Queued,
+    Running,
+    Finished,
+    Failed,
+}
+
+/// What a job converts, as resolved by the caller
+pub struct SyncSource {
+    pub itemtype: String,
+    pub itemhash: String,
+    pub title: String,
+    pub tracks: Vec<Track>,
+    /// archive entries of the original files, one per track
+    pub entries: Vec<ArchiveEntry>,
+}
+
+/// Public view of a job
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SyncStatus {
+    pub id: String,
+    pub itemtype: String,
+    pub itemhash: String,
+    pub title: String,
+    pub format: String,
+    pub bitrate: u32,
+    pub state: SyncState,
+    pub total: usize,
+    pub done: usize,
+    /// archive names of the tracks that could not be converted
+    pub failed: Vec<String>,
+    pub error: Option<String>,
+    /// bytes of the converted files once the job finished
+    pub size: u64,
+    pub created: i64,
+    pub finished: Option<i64>,
+}
+
+struct SyncJob {
+    user_id: i64,
+    status: SyncStatus,
+    format: AudioFormat,
+    dir: PathBuf,
+    files: Vec<SyncFile>,
+    cancel: Arc<AtomicBool>,
+}
+
+struct SyncFile {
+    source: PathBuf,
+    /// bitrate of the source in kbps, 0 when unknown
+    bitrate: u32,
+    entry: ArchiveEntry,
+}
+
+/// Queue a job converting `source` to `format` at `bitrate` kbps
+pub fn start(
+    user_id: i64,
+    source: SyncSource,
+    format: AudioFormat,
+    bitrate: u32,
+) -> Result<SyncStatus> {
+    if source.tracks.is_empty() {
+        return Err(anyhow!("No tracks to sync"));
+    }
+
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    let dir = Paths::get()?.sync_dir().join(&id);
+    let bitrate = bitrate.clamp(TranscodeCache::MIN_BITRATE, TranscodeCache::MAX_BITRATE);
+
+    let names = converted_names(&source.entries, format.extension());
+    let files = source
+        .tracks
+        .iter()
+        .zip(names)
+        .enumerate()
+        .map(|(i, (track, name))| SyncFile {
+            source: PathBuf::from(&track.filepath),
+            bitrate: track.bitrate.max(0) as u32,
+            entry: ArchiveEntry {
+                path: dir.join(format!("{:05}.{}", i, format.extension())),
+                name,
+            },
+        })
+        .collect::<Vec<_>>();
+
+    let status = SyncStatus {
+        id: id.clone(),
+        itemtype: source.itemtype,
+        itemhash: source.itemhash,
+        title: source.title,
+        format: format.extension().to_string(),
+        bitrate,
+        state: SyncState::Queued,
+        total: files.len(),
+        done: 0,
+        failed: Vec::new(),
+        error: None,
+        size: 0,
+        created: chrono::Utc::now().timestamp(),
+        finished: None,
+    };
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    {
+        let mut jobs = JOBS.write();
+        if jobs.values().filter(|j| j.user_id == user_id).count() >= MAX_JOBS_PER_USER {
+            return Err(anyhow!(
+                "Only {} sync jobs are kept per user, delete one first",
+                MAX_JOBS_PER_USER
+            ));
+        }
+        jobs.insert(
+            id.clone(),
+            SyncJob {
+                user_id,
+                status: status.clone(),
+                format,
+                dir,
+                files,
+                cancel: cancel.clone(),
+            },
+        );
+    }
+
+    tokio::spawn(run(id, cancel));
+    Ok(status)
+}
+
+/// Status of a job owned by `user_id`
+pub fn get(id: &str, user_id: i64) -> Option<SyncStatus> {
+    JOBS.read()
+        .get(id)
+        .filter(|job| job.user_id == user_id)
+        .map(|job| job.status.clone())
+}
+
+/// Jobs of `user_id`, newest first
+pub fn list(user_id: i64) -> Vec<SyncStatus> {
+    let mut jobs: Vec<SyncStatus> = JOBS
+        .read()
+        .values()
+        .filter(|job| job.user_id == user_id)
+        .map(|job| job.status.clone())
+        .collect();
+    jobs.sort_by_key(|job| std::cmp::Reverse(job.created));
+    jobs
+}
+
+/// Archive entries of the converted files of a job owned by `user_id`
+pub fn entries(id: &str, user_id: i64) -> Option<(SyncStatus, Vec<ArchiveEntry>)> {
+    let jobs = JOBS.read();
+    let job = jobs.get(id).filter(|job| job.user_id == user_id)?;
+    let entries = job
+        .files
+        .iter()
+        .filter(|file| !job.status.failed.contains(&file.entry.name))
+        .map(|file| file.entry.clone())
+        .collect();
+    Some((job.status.clone(), entries))
+}
+
+/// Cancel a job owned by `user_id` and delete its files
+pub fn remove(id: &str, user_id: i64) -> bool {
+    let job = {
+        let mut jobs = JOBS.write();
+        match jobs.get(id) {
+            Some(job) if job.user_id == user_id => jobs.remove(id),
+            _ => None,
+        }
+    };
+    let Some(job) = job else {
+        return false;
+    };
+
+    // a running job stops before its next track and cleans up after itself
+    job.cancel.store(true, Ordering::Relaxed);
+    let _ = std::fs::remove_dir_all(&job.dir);
+    true
+}
+
+/// Drop expired jobs and folders no job owns, left over from a previous run
+pub fn prune() -> Result<usize> {
+    let now = chrono::Utc::now().timestamp();
+    let expired: Vec<(String, i64)> = JOBS
+        .read()
+        .iter()
+        .filter(|(_, job)| job.status.finished.is_some_and(|t| now - t > JOB_TTL))
+        .map(|(id, job)| (id.clone(), job.user_id))
+        .collect();
+    for (id, user_id) in &expired {
+        remove(id, *user_id);
+    }
+
+    let dir = Paths::get()?.sync_dir();
+    let Ok(read) = std::fs::read_dir(&dir) else {
+        return Ok(expired.len());
+    };
+    let mut removed = expired.len();
+    for entry in read.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !JOBS.read().contains_key(&name) && std::fs::remove_dir_all(entry.path()).is_ok() {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Archive names with the extension of the converted files, kept unique
+fn converted_names(entries: &[ArchiveEntry], extension: &str) -> Vec<String> {
+    let mut taken = std::collections::HashSet::new();
+    entries
+        .iter()
+        .map(|entry| {
+            let stem = match entry.name.rfind('.') {
+                Some(dot) if dot > entry.name.rfind('/').map_or(0, |s| s + 1) => &entry.name[..dot],
+                _ => entry.name.as_str(),
+            };
+            let name = format!("{}.{}", stem, extension);
+            if taken.insert(name.to_lowercase()) {
+                return name;
+            }
+            (2..)
+                .map(|n| format!("{} ({}).{}", stem, n, extension))
+                .find(|candidate| taken.insert(candidate.to_lowercase()))
+                .unwrap_or(name)
+        })
+        .collect()
+}
+
+/// Whether the source can be copied as it is instead of converted
+fn can_copy(source: &Path, source_bitrate: u32, format: AudioFormat, bitrate: u32) -> bool {
+    let same_format = source
+        .extension()
+        .and_then(|e| e.to_str())
+        .and_then(AudioFormat::from_str)
+        == Some(format);
+    let lossless = matches!(format, AudioFormat::Flac | AudioFormat::Wav);
+    same_format && (lossless || (source_bitrate > 0 && source_bitrate <= bitrate))
+}
+
+fn update(id: &str, f: impl FnOnce(&mut SyncStatus)) {
+    if let Some(job) = JOBS.write().get_mut(id) {
+        f(&mut job.status);
+    }
+}
+
+async fn run(id: String, cancel: Arc<AtomicBool>) {
+    let Ok(_permit) = RUNNING.acquire().await else {
+        return;
+    };
+
+    let Some((dir, format, bitrate, files)) = JOBS.read().get(&id).map(|job| {
+        let files: Vec<(PathBuf, u32, ArchiveEntry)> = job
+            .files
+            .iter()
+            .map(|f| (f.source.clone(), f.bitrate, f.entry.clone()))
+            .collect();
+        (job.dir.clone(), job.format, job.status.bitrate, files)
+    }) else {
+        return;
+    };
+    update(&id, |s| s.state = SyncState::Running);
+
+    let fail = |e: anyhow::Error| {
+        tracing::error!("Sync job {} failed: {}", id, e);
+        let _ = std::fs::remove_dir_all(&dir);
+        update(&id, |s| {
+            s.state = SyncState::Failed;
+            s.error = Some(e.to_string());
+            s.finished = Some(chrono::Utc::now().timestamp());
+        });
+    };
+    if let Err(e) = tokio::fs::create_dir_all(&dir).await {
+        return fail(e.into());
+    }
+
+    let mut size = 0;
+    let mut last_error = None;
+    // only checked once a track needs converting, copies work without ffmpeg
+    let mut ffmpeg_ready = false;
+    for (source, source_bitrate, entry) in files {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+
+        let result = if can_copy(&source, source_bitrate, format, bitrate) {
+            tokio::fs::copy(&source, &entry.path)
+                .await
+                .map(|_| ())
+                .map_err(anyhow::Error::from)
+        } else {
+            if !ffmpeg_ready {
+                if let Err(e) = ensure_ffmpeg().await {
+                    return fail(e);
+                }
+                ffmpeg_ready = true;
+            }
+            convert(&source, &entry.path, format, bitrate).await
+        };
+
+        match result.and_then(|_| Ok(std::fs::metadata(&entry.path)?.len())) {
+            Ok(bytes) => {
+                size += bytes;
+                update(&id, |s| s.done += 1);
+            }
+            Err(e) => {
+                tracing::warn!("Sync job {} skipped {}: {}", id, source.display(), e);
+                let _ = std::fs::remove_file(&entry.path);
+                last_error = Some(e.to_string());
+                update(&id, |s| {
+                    s.done += 1;
+                    s.failed.push(entry.name.clone());
+                });
+            }
+        }
+    }
+
+    if cancel.load(Ordering::Relaxed) {
+        let _ = std::fs::remove_dir_all(&dir);
+        return;
+    }
+
+    update(&id, |s| {
+        s.size = size;
+        s.finished = Some(chrono::Utc::now().timestamp());
+        if s.failed.len() == s.total {
+            s.state = SyncState::Failed;
+            s.error = last_error;
+        } else {
+            s.state = SyncState::Finished;
+        }
+    });
+}
+
+/// Make sure ffmpeg is there, downloading it when it is not
+async fn ensure_ffmpeg() -> Result<()> {
+    if !Transcoder::is_ffmpeg_available() {
+        tokio::task::spawn_blocking(Transcoder::ensure_ffmpeg).await??;
+    }
+    Ok(())
+}
+
+/// Convert one file, keeping its tags
+async fn convert(input: &Path, output: &Path, format: AudioFormat, bitrate: u32) -> Result<()> {
+    let mut cmd = tokio::process::Command::new(ffmpeg::get_ffmpeg_path());
+    cmd.args(["-v", "error", "-y", "-i"])
+        .arg(input)
+        // cover art streams can't be muxed into most audio containers
+        .args(["-map", "0:a:0", "-vn", "-map_metadata", "0"])
+        .args(["-f", format.ffmpeg_format(), "-c:a", format.ffmpeg_codec()]);
+
+    // lossless targets ignore the bitrate
+    if !matches!(format, AudioFormat::Flac | AudioFormat::Wav) {
+        cmd.args(["-b:a", &format!("{}k", bitrate)]);
+    }
+
+    let out = cmd
+        .arg(output)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .output()
+        .await?;
+
+    if !out.status.success() {
+        return Err(anyhow!(
+            "ffmpeg exited with {}: {}",
+            out.status,
+            String::from_utf8_lossy(&out.stderr)
+                .lines()
+                .last()
+                .unwrap_or("")
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str) -> ArchiveEntry {
+        ArchiveEntry {
+            path: PathBuf::from(name),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_converted_names() {
+        let entries = [
+            entry("Disc 1/01 Intro.flac"),
+            entry("Disc 1/01 Intro.mp3"),
+            entry("Disc.2/no extension"),
+        ];
+        assert_eq!(
+            converted_names(&entries, "opus"),
+            [
+                "Disc 1/01 Intro.opus",
+                "Disc 1/01 Intro (2).opus",
+                "Disc.2/no extension.opus"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_can_copy() {
+        let mp3 = Path::new("/music/a.mp3");
+        assert!(can_copy(mp3, 128, AudioFormat::Mp3, 192));
+        assert!(!can_copy(mp3, 320, AudioFormat::Mp3, 192));
+        assert!(!can_copy(mp3, 0, AudioFormat::Mp3, 192));
+        assert!(!can_copy(mp3, 128, AudioFormat::Opus, 192));
+        assert!(can_copy(
+            Path::new("/music/a.FLAC"),
+            900,
+            AudioFormat::Flac,
+            32
+        ));
+    }
+}
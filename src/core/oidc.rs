@@ -0,0 +1,652 @@
+//! OpenID Connect login
+//!
+//! the authorization code flow with pkce. starting a login stores the state,
+//! nonce and code verifier for ten minutes and sends the browser to the
+//! provider. the callback trades the code for an id token, checks it against
+//! the provider's signing keys and maps the claims to a local user, creating
+//! one when auto creation is on. linked users keep the provider's subject in
+//! their extra data so renaming them on either side keeps the link.
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::config::OidcSettings;
+use crate::db::tables::UserTable;
+use crate::models::{User, UserRole};
+use crate::utils::auth::{generate_random_string, hash_password};
+
+const USER_AGENT: &str = concat!("SwingMusic/", env!("CARGO_PKG_VERSION"));
+
+/// how long a started login can be finished
+const PENDING_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// started logins kept before the oldest are dropped
+const MAX_PENDING: usize = 1000;
+
+/// how long discovery documents and signing keys are reused
+const DISCOVERY_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// key of the provider link in a user's extra data
+const LINK_KEY: &str = "oidc";
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(Duration::from_secs(15))
+        .build()
+        .unwrap_or_default()
+});
+
+static PENDING: Lazy<RwLock<HashMap<String, PendingLogin>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// issuer to its discovery document, signing keys and when they were fetched
+static PROVIDERS: Lazy<RwLock<HashMap<String, (Instant, Provider)>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// The parts of a discovery document the flow needs
+#[derive(Debug, Clone, Deserialize)]
+struct Metadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+    #[serde(default)]
+    userinfo_endpoint: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Provider {
+    metadata: Metadata,
+    keys: JwkSet,
+}
+
+#[derive(Debug, Clone)]
+struct PendingLogin {
+    nonce: String,
+    verifier: String,
+    redirect_uri: String,
+    return_to: String,
+    started: Instant,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+    #[serde(default)]
+    access_token: Option<String>,
+}
+
+/// Who the provider says logged in
+#[derive(Debug, Clone, PartialEq)]
+pub struct OidcIdentity {
+    pub subject: String,
+    pub username: String,
+    pub email: String,
+    /// whether the provider checked the email belongs to the user
+    pub email_verified: bool,
+    pub name: String,
+    /// values of the role claim
+    pub groups: Vec<String>,
+}
+
+impl OidcIdentity {
+    /// Read the identity from id token or userinfo claims, the username falls
+    /// back to the standard claims and then the subject
+    pub fn from_claims(settings: &OidcSettings, claims: &Map<String, Value>) -> Result<Self> {
+        let text = |key: &str| {
+            claims
+                .get(key)
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+        };
+
+        let subject = text("sub").ok_or_else(|| anyhow!("the id token has no subject"))?;
+        let username = [
+            settings.username_claim.as_str(),
+            "preferred_username",
+            "email",
+        ]
+        .into_iter()
+        .find_map(text)
+        .unwrap_or_else(|| subject.clone());
+
+        let groups = match claims.get(&settings.role_claim) {
+            Some(Value::Array(values)) => values
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+            Some(Value::String(value)) => value
+                .split([',', ' '])
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Ok(Self {
+            subject,
+            username,
+            email: text("email").unwrap_or_default(),
+            email_verified: matches!(claims.get("email_verified"), Some(Value::Bool(true)))
+                || text("email_verified").is_some_and(|v| v == "true"),
+            name: text("name")
+                .or_else(|| text("given_name"))
+                .unwrap_or_default(),
+            groups,
+        })
+    }
+}
+
+/// Roles for a user from the values of their role claim, `None` when no role
+/// map is set up so roles are managed in swing music instead
+pub fn mapped_roles(settings: &OidcSettings, groups: &[String]) -> Option<Vec<UserRole>> {
+    if settings.role_map.is_empty() {
+        return None;
+    }
+
+    let mut roles: Vec<UserRole> = Vec::new();
+    for group in groups {
+        let names = settings.role_map.get(group).into_iter().flatten();
+        for role in names.filter_map(|name| UserRole::from_str(name)) {
+            if !roles.contains(&role) {
+                roles.push(role);
+            }
+        }
+    }
+    if roles.is_empty() {
+        roles = default_roles(settings);
+    }
+    Some(roles)
+}
+
+fn default_roles(settings: &OidcSettings) -> Vec<UserRole> {
+    settings
+        .default_roles
+        .iter()
+        .filter_map(|name| UserRole::from_str(name))
+        .collect()
+}
+
+/// Whether a path is safe to send the browser back to after the login, only
+/// paths on this server are
+pub fn is_local_path(path: &str) -> bool {
+    path.starts_with('/') && !path.starts_with("//") && !path.contains('\\')
+}
+
+/// Start a login, returns the provider url to send the browser to
+pub async fn start_login(
+    settings: &OidcSettings,
+    redirect_uri: &str,
+    return_to: &str,
+) -> Result<String> {
+    let provider = provider(settings, false).await?;
+
+    let state = generate_random_string(32);
+    let nonce = generate_random_string(32);
+    let verifier = generate_random_string(64);
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+
+    let return_to = if is_local_path(return_to) {
+        return_to
+    } else {
+        "/"
+    };
+    {
+        let mut pending = PENDING.write();
+        pending.retain(|_, login| login.started.elapsed() < PENDING_TTL);
+        if pending.len() >= MAX_PENDING {
+            if let Some(oldest) = pending
+                .iter()
+                .min_by_key(|(_, login)| login.started)
+                .map(|(state, _)| state.clone())
+            {
+                pending.remove(&oldest);
+            }
+        }
+        pending.insert(
+            state.clone(),
+            PendingLogin {
+                nonce: nonce.clone(),
+                verifier,
+                redirect_uri: redirect_uri.to_string(),
+                return_to: return_to.to_string(),
+                started: Instant::now(),
+            },
+        );
+    }
+
+    let scope = settings.scopes.join(" ");
+    let mut url = reqwest::Url::parse(&provider.metadata.authorization_endpoint)
+        .context("the provider's authorization endpoint is not a url")?;
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &settings.client_id)
+        .append_pair("redirect_uri", redirect_uri)
+        .append_pair("scope", &scope)
+        .append_pair("state", &state)
+        .append_pair("nonce", &nonce)
+        .append_pair("code_challenge", &challenge)
+        .append_pair("code_challenge_method", "S256");
+    Ok(url.to_string())
+}
+
+/// Finish a login from the provider's callback, returns the local user and
+/// the path to send the browser back to
+pub async fn finish_login(
+    settings: &OidcSettings,
+    state: &str,
+    code: &str,
+) -> Result<(User, String)> {
+    let pending = PENDING
+        .write()
+        .remove(state)
+        .filter(|login| login.started.elapsed() < PENDING_TTL)
+        .ok_or_else(|| anyhow!("the login expired or was already used, start it again"))?;
+
+    let provider = provider(settings, false).await?;
+    let tokens = exchange_code(settings, &provider.metadata, &pending, code).await?;
+    let id_token = tokens
+        .id_token
+        .ok_or_else(|| anyhow!("the provider sent no id token, is the openid scope allowed"))?;
+
+    let mut claims = verify_id_token(settings, &id_token).await?;
+    if claims.get("nonce").and_then(Value::as_str) != Some(pending.nonce.as_str()) {
+        bail!("the id token does not belong to this login");
+    }
+
+    // group claims are often only in the userinfo response
+    let missing = !claims.contains_key(&settings.role_claim)
+        || !claims.contains_key(&settings.username_claim);
+    if let (true, Some(endpoint), Some(access_token)) = (
+        missing,
+        provider.metadata.userinfo_endpoint.as_deref(),
+        tokens.access_token.as_deref(),
+    ) {
+        match userinfo(endpoint, access_token).await {
+            Ok(info) if info.get("sub") == claims.get("sub") => {
+                for (key, value) in info {
+                    claims.entry(key).or_insert(value);
+                }
+            }
+            Ok(_) => tracing::warn!("ignoring userinfo for a different subject"),
+            Err(e) => tracing::warn!("could not fetch oidc userinfo: {}", e),
+        }
+    }
+
+    let identity = OidcIdentity::from_claims(settings, &claims)?;
+    let user = provision(settings, &identity).await?;
+    Ok((user, pending.return_to))
+}
+
+async fn provider(settings: &OidcSettings, refresh: bool) -> Result<Provider> {
+    if !refresh {
+        if let Some((fetched, provider)) = PROVIDERS.read().get(&settings.issuer) {
+            if fetched.elapsed() < DISCOVERY_TTL {
+                return Ok(provider.clone());
+            }
+        }
+    }
+
+    let url = format!("{}/.well-known/openid-configuration", settings.issuer);
+    let metadata: Metadata = CLIENT
+        .get(&url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("could not reach the provider at {}", url))?
+        .json()
+        .await
+        .context("the provider's discovery document is invalid")?;
+    if metadata.issuer.trim_end_matches('/') != settings.issuer {
+        bail!(
+            "the provider calls itself {}, not {}",
+            metadata.issuer,
+            settings.issuer
+        );
+    }
+
+    let keys: JwkSet = CLIENT
+        .get(&metadata.jwks_uri)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .context("could not fetch the provider's signing keys")?
+        .json()
+        .await
+        .context("the provider's signing keys are invalid")?;
+
+    let provider = Provider { metadata, keys };
+    PROVIDERS
+        .write()
+        .insert(settings.issuer.clone(), (Instant::now(), provider.clone()));
+    Ok(provider)
+}
+
+async fn exchange_code(
+    settings: &OidcSettings,
+    metadata: &Metadata,
+    pending: &PendingLogin,
+    code: &str,
+) -> Result<TokenResponse> {
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", pending.redirect_uri.as_str()),
+        ("code_verifier", pending.verifier.as_str()),
+        ("client_id", settings.client_id.as_str()),
+    ];
+    let mut request = CLIENT.post(&metadata.token_endpoint);
+    if settings.client_secret.is_empty() {
+        request = request.form(&form);
+    } else {
+        // client_secret_basic is the default every provider supports, the
+        // secret is also posted for the ones configured for client_secret_post
+        form.push(("client_secret", settings.client_secret.as_str()));
+        request = request
+            .basic_auth(&settings.client_id, Some(&settings.client_secret))
+            .form(&form);
+    }
+
+    let response = request
+        .send()
+        .await
+        .context("could not reach the provider's token endpoint")?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body: Value = response.json().await.unwrap_or(Value::Null);
+        let reason = body
+            .get("error_description")
+            .or_else(|| body.get("error"))
+            .and_then(Value::as_str)
+            .unwrap_or("no reason given");
+        bail!("the provider refused the code ({}): {}", status, reason);
+    }
+    response
+        .json()
+        .await
+        .context("the provider's token response is invalid")
+}
+
+async fn verify_id_token(settings: &OidcSettings, token: &str) -> Result<Map<String, Value>> {
+    let header = decode_header(token).context("the id token is malformed")?;
+
+    let key = match header.alg {
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+            if settings.client_secret.is_empty() {
+                bail!("the id token is signed with the client secret but none is set");
+            }
+            DecodingKey::from_secret(settings.client_secret.as_bytes())
+        }
+        _ => {
+            let mut provider = provider(settings, false).await?;
+            let find = |keys: &JwkSet| match &header.kid {
+                Some(kid) => keys.find(kid).cloned(),
+                None => keys.keys.first().cloned(),
+            };
+            // keys rotate, an unknown key id refreshes them once
+            let jwk = match find(&provider.keys) {
+                Some(jwk) => jwk,
+                None => {
+                    provider = self::provider(settings, true).await?;
+                    find(&provider.keys)
+                        .ok_or_else(|| anyhow!("the id token is signed with an unknown key"))?
+                }
+            };
+            DecodingKey::from_jwk(&jwk).context("the provider's signing key is unsupported")?
+        }
+    };
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_audience(&[&settings.client_id]);
+    validation.set_issuer(&[settings.issuer.as_str(), &format!("{}/", settings.issuer)]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+    let data = decode::<Map<String, Value>>(token, &key, &validation)
+        .context("the id token did not verify")?;
+    Ok(data.claims)
+}
+
+async fn userinfo(endpoint: &str, access_token: &str) -> Result<Map<String, Value>> {
+    Ok(CLIENT
+        .get(endpoint)
+        .bearer_auth(access_token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+/// The subject a user is linked to, if they are linked to this issuer
+fn linked_subject<'a>(user: &'a User, issuer: &str) -> Option<&'a str> {
+    let link = user.extra.get(LINK_KEY)?;
+    if link.get("issuer").and_then(Value::as_str) != Some(issuer) {
+        return None;
+    }
+    link.get("sub").and_then(Value::as_str)
+}
+
+/// The account an identity signs in as, `None` when a new one has to be made
+///
+/// accounts are found by the issuer and subject they were linked with. the
+/// username and email claims can be picked by whoever registers at the
+/// provider, so an unlinked account is only taken over by a verified email
+/// and only when linking by email is turned on.
+fn local_account(
+    settings: &OidcSettings,
+    identity: &OidcIdentity,
+    users: Vec<User>,
+) -> Result<Option<User>> {
+    if let Some(user) = users
+        .iter()
+        .find(|u| linked_subject(u, &settings.issuer) == Some(identity.subject.as_str()))
+    {
+        return Ok(Some(user.clone()));
+    }
+
+    if settings.link_by_email && identity.email_verified && !identity.email.is_empty() {
+        match users
+            .iter()
+            .find(|u| u.email.eq_ignore_ascii_case(&identity.email))
+        {
+            Some(user) if user.roles.contains(&UserRole::Guest) => {
+                bail!(
+                    "{} is the guest account and can not sign in with sso",
+                    user.username
+                )
+            }
+            Some(user) if linked_subject(user, &settings.issuer).is_some() => {
+                bail!("{} is linked to another sso account", user.username)
+            }
+            Some(user) => return Ok(Some(user.clone())),
+            None => {}
+        }
+    }
+
+    if users.iter().any(|u| u.username == identity.username) {
+        bail!(
+            "{} already exists and is not linked to this sso account",
+            identity.username
+        );
+    }
+    if !settings.auto_create {
+        bail!(
+            "there is no account for {}, ask an admin to add one",
+            identity.username
+        );
+    }
+    Ok(None)
+}
+
+/// Find or create the user for the identity, then bring their roles and
+/// profile up to date
+async fn provision(settings: &OidcSettings, identity: &OidcIdentity) -> Result<User> {
+    let users = UserTable::all().await?;
+    let mut user = match local_account(settings, identity, users)? {
+        Some(user) => user,
+        None => create_user(settings, identity).await?,
+    };
+
+    if let Some(roles) = mapped_roles(settings, &identity.groups) {
+        user.roles = roles;
+    }
+    if user.email.is_empty() {
+        user.email = identity.email.clone();
+    }
+    if user.firstname.is_empty() {
+        user.firstname = identity.name.clone();
+    }
+    if !user.extra.is_object() {
+        user.extra = Value::Object(Map::new());
+    }
+    if let Some(extra) = user.extra.as_object_mut() {
+        extra.insert(
+            LINK_KEY.to_string(),
+            json!({"issuer": settings.issuer, "sub": identity.subject}),
+        );
+    }
+    UserTable::update(&user).await?;
+    Ok(user)
+}
+
+async fn create_user(settings: &OidcSettings, identity: &OidcIdentity) -> Result<User> {
+    // the password is never shown, sso users sign in through the provider only
+    let password = hash_password(&generate_random_string(48))?;
+    let mut user = User::new(identity.username.clone(), password);
+    user.roles =
+        mapped_roles(settings, &identity.groups).unwrap_or_else(|| default_roles(settings));
+    user.email = identity.email.clone();
+    user.firstname = identity.name.clone();
+
+    user.id = UserTable::insert(&user).await?;
+    tracing::info!("created user {} on their first sso login", user.username);
+    Ok(user)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> OidcSettings {
+        OidcSettings {
+            issuer: "https://sso.example.com".to_string(),
+            username_claim: "nickname".to_string(),
+            ..OidcSettings::default()
+        }
+    }
+
+    fn identity(username: &str, email: &str, email_verified: bool) -> OidcIdentity {
+        OidcIdentity {
+            subject: "sub-1".to_string(),
+            username: username.to_string(),
+            email: email.to_string(),
+            email_verified,
+            name: String::new(),
+            groups: Vec::new(),
+        }
+    }
+
+    fn local_user(id: i64, username: &str, email: &str) -> User {
+        let mut user = User::admin(username.to_string(), String::new());
+        user.id = id;
+        user.email = email.to_string();
+        user
+    }
+
+    #[test]
+    fn test_identity_from_claims() {
+        let claims = json!({
+            "sub": "abc",
+            "preferred_username": "ada",
+            "email": "ada@example.com",
+            "groups": ["music-admins", "family"],
+        });
+        let identity = OidcIdentity::from_claims(&settings(), claims.as_object().unwrap()).unwrap();
+        assert_eq!(identity.username, "ada");
+        assert_eq!(identity.groups, vec!["music-admins", "family"]);
+
+        let claims = json!({"sub": "abc", "groups": "a,b"});
+        let identity = OidcIdentity::from_claims(&settings(), claims.as_object().unwrap()).unwrap();
+        assert_eq!(identity.username, "abc");
+        assert_eq!(identity.groups, vec!["a", "b"]);
+
+        assert!(OidcIdentity::from_claims(&settings(), &Map::new()).is_err());
+    }
+
+    #[test]
+    fn test_mapped_roles() {
+        let mut settings = settings();
+        assert_eq!(mapped_roles(&settings, &["family".to_string()]), None);
+
+        settings.role_map = HashMap::from([
+            ("music-admins".to_string(), vec!["admin".to_string()]),
+            (
+                "family".to_string(),
+                vec!["user".to_string(), "can_download".to_string()],
+            ),
+        ]);
+        let groups = vec!["family".to_string(), "music-admins".to_string()];
+        assert_eq!(
+            mapped_roles(&settings, &groups),
+            Some(vec![UserRole::User, UserRole::CanDownload, UserRole::Admin])
+        );
+        assert_eq!(
+            mapped_roles(&settings, &["strangers".to_string()]),
+            Some(vec![UserRole::User])
+        );
+    }
+
+    #[test]
+    fn test_local_account_needs_link_or_verified_email() {
+        let mut settings = settings();
+        let users = || vec![local_user(1, "admin", "admin@example.com")];
+
+        // a provider user picking the admin's name or email does not get the account
+        assert!(local_account(&settings, &identity("admin", "", false), users()).is_err());
+        let spoofed = identity("someone", "admin@example.com", true);
+        assert!(local_account(&settings, &spoofed, users())
+            .unwrap()
+            .is_none());
+
+        settings.link_by_email = true;
+        let unverified = identity("someone", "admin@example.com", false);
+        assert!(local_account(&settings, &unverified, users())
+            .unwrap()
+            .is_none());
+        let verified = identity("someone", "Admin@example.com", true);
+        assert_eq!(
+            local_account(&settings, &verified, users())
+                .unwrap()
+                .map(|u| u.id),
+            Some(1)
+        );
+
+        // a linked account is found by its subject alone
+        let mut linked = local_user(2, "ada", "");
+        linked.extra = json!({LINK_KEY: {"issuer": settings.issuer, "sub": "sub-1"}});
+        let found = local_account(&settings, &identity("admin", "", false), vec![linked]);
+        assert_eq!(found.unwrap().map(|u| u.id), Some(2));
+    }
+
+    #[test]
+    fn test_is_local_path() {
+        assert!(is_local_path("/album/abc"));
+        assert!(!is_local_path("//evil.example.com"));
+        assert!(!is_local_path("https://evil.example.com"));
+        assert!(!is_local_path("/\\evil.example.com"));
+    }
+}
@@ -0,0 +1,517 @@
+//! Moving and renaming library files on disk
+//!
+//! tracks are filed under their root directory by a path template like
+//! `{albumartist}/{album}/{track} {title}`, the file keeps its extension.
+//! files are renamed first and the database follows in one transaction. when
+//! a rename or the transaction fails the files already moved are put back, so
+//! the disk and the library never disagree. the stores are updated once both
+//! went through.
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Datelike;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+
+use crate::core::populate::refresh_changed_tracks;
+use crate::core::FolderLib;
+use crate::db::tables::TrackTable;
+use crate::models::Track;
+use crate::stores::{FolderStore, TrackStore};
+
+/// Placeholders a template can use
+pub const PLACEHOLDERS: [&str; 8] = [
+    "albumartist",
+    "artist",
+    "album",
+    "title",
+    "track",
+    "disc",
+    "year",
+    "genre",
+];
+
+/// Longest file or folder name written, in bytes
+const MAX_NAME_LEN: usize = 200;
+
+/// Moves run one at a time so two requests can't race for a destination
+static MOVING: Mutex<()> = Mutex::const_new(());
+
+/// A file and where it goes
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct FileMove {
+    pub trackhash: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// Check a template before it is saved or used
+pub fn validate_template(template: &str) -> Result<()> {
+    let template = template.trim();
+    if template.is_empty() {
+        bail!("Template is empty");
+    }
+    if template.starts_with(['/', '\\']) {
+        bail!("Template must be relative to the root directory");
+    }
+    if template
+        .split(['/', '\\'])
+        .any(|segment| matches!(segment.trim(), "." | ".."))
+    {
+        bail!("Template can't leave its root directory");
+    }
+
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let Some(close) = rest[open..].find('}') else {
+            bail!("Unclosed placeholder in template");
+        };
+        let name = &rest[open + 1..open + close];
+        if !PLACEHOLDERS.contains(&name) {
+            bail!("Unknown placeholder {{{}}}", name);
+        }
+        rest = &rest[open + close + 1..];
+    }
+    if rest.contains('}') {
+        bail!("Unopened placeholder in template");
+    }
+    Ok(())
+}
+
+/// Path of a track relative to its root directory
+pub fn render(template: &str, track: &Track) -> Result<PathBuf> {
+    validate_template(template)?;
+
+    let mut path = PathBuf::new();
+    for segment in template.trim().split(['/', '\\']) {
+        let mut name = String::new();
+        let mut rest = segment;
+        while let Some(open) = rest.find('{') {
+            let close = open + rest[open..].find('}').unwrap_or(0);
+            name.push_str(&rest[..open]);
+            name.push_str(&clean_name(&placeholder(&rest[open + 1..close], track)));
+            rest = &rest[close + 1..];
+        }
+        name.push_str(rest);
+
+        let name = trim_name(&name);
+        if !name.is_empty() {
+            path.push(name);
+        }
+    }
+
+    let Some(stem) = path.file_name().map(|n| n.to_string_lossy().into_owned()) else {
+        bail!("Template gives the track an empty name");
+    };
+    if let Some(ext) = Path::new(&track.filepath).extension() {
+        path.set_file_name(format!("{}.{}", stem, ext.to_string_lossy()));
+    }
+    Ok(path)
+}
+
+/// Root directory a path is under, the deepest one when roots are nested
+///
+/// paths with `..` in them are under none, `starts_with` compares components
+/// so they could climb out of the root
+pub fn root_of<'a>(path: &Path, roots: &'a [String]) -> Option<&'a str> {
+    if path.components().any(|c| c == Component::ParentDir) {
+        return None;
+    }
+    roots
+        .iter()
+        .filter(|root| path.starts_with(root.as_str()))
+        .max_by_key(|root| root.len())
+        .map(String::as_str)
+}
+
+/// Moves filing `tracks` by `template`, tracks already in place are left out
+///
+/// fails when two tracks would end up at the same path or a destination is
+/// taken by another file
+pub fn plan(tracks: &[Track], template: &str, roots: &[String]) -> Result<Vec<FileMove>> {
+    let mut moves = Vec::new();
+    let mut taken = HashSet::new();
+    for track in tracks {
+        let from = Path::new(&track.filepath);
+        let root = root_of(from, roots)
+            .ok_or_else(|| anyhow!("{} is not in a root directory", track.filepath))?;
+        let to = Path::new(root).join(render(template, track)?);
+        let to_str = to.to_string_lossy().to_string();
+
+        if !taken.insert(to_str.clone()) {
+            bail!("More than one track would be moved to {}", to_str);
+        }
+        if to == from {
+            continue;
+        }
+        if to.exists() {
+            bail!("{} already exists", to_str);
+        }
+        moves.push(FileMove {
+            trackhash: track.trackhash.clone(),
+            from: track.filepath.clone(),
+            to: to_str,
+        });
+    }
+    Ok(moves)
+}
+
+/// Tracks of the library under a folder, subfolders included
+pub fn tracks_under(folder: &Path) -> Vec<Track> {
+    TrackStore::get()
+        .get_all_shared()
+        .iter()
+        .filter(|t| Path::new(&t.filepath).starts_with(folder))
+        .map(|t| t.as_ref().clone())
+        .collect()
+}
+
+/// Move the files and update the database and stores, returns the moved tracks
+pub async fn apply(moves: Vec<FileMove>) -> Result<Vec<Track>> {
+    if moves.is_empty() {
+        return Ok(Vec::new());
+    }
+    let _guard = MOVING.lock().await;
+
+    let done = moves.clone();
+    tokio::task::spawn_blocking(move || move_files(&done)).await??;
+
+    let pairs: Vec<(String, String)> = moves
+        .iter()
+        .map(|m| (m.from.clone(), m.to.clone()))
+        .collect();
+    if let Err(e) = TrackTable::move_filepaths(&pairs).await {
+        let done = moves.clone();
+        tokio::task::spawn_blocking(move || undo(&done)).await?;
+        return Err(e.context("Failed to update the database, files were moved back"));
+    }
+
+    let tracks = update_stores(&pairs).await?;
+
+    let sources: Vec<PathBuf> = moves
+        .iter()
+        .filter_map(|m| Path::new(&m.from).parent().map(Path::to_path_buf))
+        .collect();
+    tokio::task::spawn_blocking(move || prune_empty_dirs(&sources)).await?;
+    Ok(tracks)
+}
+
+/// Rename a folder inside a root directory, returns the new path and the
+/// tracks that moved with it
+pub async fn rename_folder(path: &str, name: &str) -> Result<(String, Vec<Track>)> {
+    let name = name.trim();
+    if name.is_empty() || trim_name(&clean_name(name)) != name {
+        bail!("Folder name is empty or has characters that can't be used");
+    }
+
+    let from = Path::new(path);
+    let roots = FolderLib::get_root_dirs();
+    match root_of(from, &roots) {
+        Some(root) if Path::new(root) != from => {}
+        Some(_) => bail!("Root directories can't be renamed"),
+        None => bail!("Folder is not in a root directory"),
+    }
+    let to = from
+        .parent()
+        .ok_or_else(|| anyhow!("Folder has no parent"))?
+        .join(name);
+
+    // checked under the lock so two renames can't both find the name free
+    let _guard = MOVING.lock().await;
+    if !from.is_dir() {
+        bail!("Folder does not exist");
+    }
+    if to.exists() {
+        bail!("{} already exists", to.display());
+    }
+    let (source, target) = (from.to_path_buf(), to.clone());
+    tokio::task::spawn_blocking(move || std::fs::rename(source, target))
+        .await?
+        .with_context(|| format!("Failed to rename {}", path))?;
+
+    let pairs: Vec<(String, String)> = tracks_under(from)
+        .into_iter()
+        .filter_map(|t| {
+            let rest = Path::new(&t.filepath).strip_prefix(from).ok()?;
+            Some((
+                t.filepath.clone(),
+                to.join(rest).to_string_lossy().to_string(),
+            ))
+        })
+        .collect();
+    if let Err(e) = TrackTable::move_filepaths(&pairs).await {
+        let (source, target) = (to.clone(), from.to_path_buf());
+        if let Err(undo) =
+            tokio::task::spawn_blocking(move || std::fs::rename(source, target)).await?
+        {
+            tracing::error!("Failed to rename {} back: {}", to.display(), undo);
+        }
+        return Err(e.context("Failed to update the database, the folder was renamed back"));
+    }
+
+    let tracks = update_stores(&pairs).await?;
+    Ok((to.to_string_lossy().to_string(), tracks))
+}
+
+/// Rename a file, copying it when the destination is on another filesystem
+pub fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    std::fs::copy(from, to).with_context(|| format!("Failed to move {}", from.display()))?;
+    if let Err(e) = std::fs::remove_file(from) {
+        // a copy left behind would be indexed twice
+        let _ = std::fs::remove_file(to);
+        return Err(e).with_context(|| format!("Failed to move {}", from.display()));
+    }
+    Ok(())
+}
+
+/// Rename every file, putting the moved ones back when one fails
+fn move_files(moves: &[FileMove]) -> Result<()> {
+    for (i, m) in moves.iter().enumerate() {
+        let to = Path::new(&m.to);
+        let result = if to.exists() {
+            Err(anyhow!("{} already exists", m.to))
+        } else {
+            to.parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| std::fs::rename(&m.from, to))
+                .with_context(|| format!("Failed to move {}", m.from))
+        };
+
+        if let Err(e) = result {
+            undo(&moves[..i]);
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Move files back to where they came from
+fn undo(moves: &[FileMove]) {
+    for m in moves.iter().rev() {
+        if let Err(e) = std::fs::rename(&m.to, &m.from) {
+            tracing::error!("Failed to move {} back: {}", m.to, e);
+        }
+    }
+    let created: Vec<PathBuf> = moves
+        .iter()
+        .filter_map(|m| Path::new(&m.to).parent().map(Path::to_path_buf))
+        .collect();
+    prune_empty_dirs(&created);
+}
+
+/// Swap the moved tracks into the stores and rebuild the folder tree
+async fn update_stores(moves: &[(String, String)]) -> Result<Vec<Track>> {
+    let store = TrackStore::get();
+    let tracks: Vec<Track> = moves
+        .iter()
+        .filter_map(|(from, to)| {
+            let mut track = store.get_by_path(from)?;
+            track.filepath = to.clone();
+            track.folder = Path::new(to)
+                .parent()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default();
+            // the artwork path hashes the folder
+            track.image = String::new();
+            Some(track)
+        })
+        .collect();
+
+    let old: Vec<String> = moves.iter().map(|(from, _)| from.clone()).collect();
+    store.remove_by_paths(&old);
+    refresh_changed_tracks(tracks.clone()).await?;
+    FolderStore::load_filepaths().await?;
+    Ok(tracks)
+}
+
+/// Remove folders left empty, up to their root directory
+fn prune_empty_dirs(dirs: &[PathBuf]) {
+    let roots = FolderLib::get_root_dirs();
+    for dir in dirs {
+        let mut current = dir.as_path();
+        while root_of(current, &roots).is_some_and(|root| Path::new(root) != current) {
+            let empty = std::fs::read_dir(current).is_ok_and(|mut d| d.next().is_none());
+            if !empty || std::fs::remove_dir(current).is_err() {
+                break;
+            }
+            match current.parent() {
+                Some(parent) => current = parent,
+                None => break,
+            }
+        }
+    }
+}
+
+/// Value of a placeholder for a track
+fn placeholder(name: &str, track: &Track) -> String {
+    let or = |value: String, fallback: &str| {
+        if value.trim().is_empty() {
+            fallback.to_string()
+        } else {
+            value
+        }
+    };
+
+    match name {
+        "albumartist" => or(or(track.albumartist(), &track.artist()), "Unknown Artist"),
+        "artist" => or(track.artist(), "Unknown Artist"),
+        "album" => or(track.album.clone(), "Unknown Album"),
+        "title" => or(
+            track.title.clone(),
+            &Path::new(&track.filepath)
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        ),
+        "track" if track.track > 0 => format!("{:02}", track.track),
+        "disc" if track.disc > 0 => track.disc.to_string(),
+        "year" if track.date != 0 => chrono::DateTime::from_timestamp(track.date, 0)
+            .map(|d| d.year().to_string())
+            .unwrap_or_default(),
+        "genre" => track
+            .genres
+            .first()
+            .map(|g| g.name.clone())
+            .unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+/// Replace characters file systems refuse in a name
+fn clean_name(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Trim spaces and dots off a name and keep it under the length limit
+fn trim_name(name: &str) -> String {
+    let name = name.trim().trim_matches('.').trim();
+    let mut end = name.len().min(MAX_NAME_LEN);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    name[..end].trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ArtistRefItem;
+
+    fn track(filepath: &str) -> Track {
+        let mut track = Track::new();
+        track.filepath = filepath.to_string();
+        track.trackhash = filepath.to_string();
+        track.title = "What? Now".to_string();
+        track.album = "Live: 1999".to_string();
+        track.artists = vec![ArtistRefItem::new("AC/DC".to_string(), "a".to_string())];
+        track.track = 3;
+        track
+    }
+
+    #[test]
+    fn test_validate_template() {
+        assert!(validate_template("{albumartist}/{album}/{track} {title}").is_ok());
+        assert!(validate_template("").is_err());
+        assert!(validate_template("/{title}").is_err());
+        assert!(validate_template("../{title}").is_err());
+        assert!(validate_template("{title").is_err());
+        assert!(validate_template("{composer}/{title}").is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        // a placeholder without a value leaves no empty folder behind
+        let mut single_disc = track("/m/x.flac");
+        single_disc.disc = 0;
+        let path = render("{albumartist}/{album}/{disc}/{track} {title}", &single_disc).unwrap();
+        assert_eq!(path, PathBuf::from("AC_DC/Live_ 1999/03 What_ Now.flac"));
+
+        let mut untitled = track("/m/song.mp3");
+        untitled.title = String::new();
+        untitled.track = 0;
+        let path = render("{album}/{track} {title}", &untitled).unwrap();
+        assert_eq!(path, PathBuf::from("Live_ 1999/song.mp3"));
+    }
+
+    #[test]
+    fn test_plan() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let roots = vec![root.clone()];
+        let in_place = track(&format!("{}/AC_DC/03 What_ Now.flac", root));
+        let moved = track(&format!("{}/inbox/a.mp3", root));
+
+        let moves = plan(
+            &[in_place.clone(), moved],
+            "{artist}/{track} {title}",
+            &roots,
+        )
+        .unwrap();
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].to, format!("{}/AC_DC/03 What_ Now.mp3", root));
+
+        let twin = track(&format!("{}/inbox/b.flac", root));
+        assert!(plan(&[in_place, twin], "{artist}/{track} {title}", &roots).is_err());
+
+        assert!(plan(&[track("/elsewhere/a.mp3")], "{title}", &roots).is_err());
+    }
+
+    #[test]
+    fn test_root_of_refuses_parent_dirs() {
+        let roots = vec!["/music".to_string(), "/music/live".to_string()];
+        assert_eq!(
+            root_of(Path::new("/music/live/a"), &roots),
+            Some("/music/live")
+        );
+        assert_eq!(root_of(Path::new("/music/a"), &roots), Some("/music"));
+        assert_eq!(root_of(Path::new("/music/../etc/foo"), &roots), None);
+        assert_eq!(root_of(Path::new("/music/live/../../etc"), &roots), None);
+        assert_eq!(root_of(Path::new("/musicals/a"), &roots), None);
+    }
+
+    #[test]
+    fn test_move_files_rolls_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.mp3");
+        let b = dir.path().join("b.mp3");
+        std::fs::write(&a, b"a").unwrap();
+        std::fs::write(&b, b"b").unwrap();
+        let taken = dir.path().join("taken.mp3");
+        std::fs::write(&taken, b"taken").unwrap();
+
+        let to_a = dir.path().join("new/a.mp3");
+        let moves = [
+            FileMove {
+                trackhash: "a".to_string(),
+                from: a.to_string_lossy().to_string(),
+                to: to_a.to_string_lossy().to_string(),
+            },
+            FileMove {
+                trackhash: "b".to_string(),
+                from: b.to_string_lossy().to_string(),
+                to: taken.to_string_lossy().to_string(),
+            },
+        ];
+
+        assert!(move_files(&moves).is_err());
+        assert!(a.exists());
+        assert!(b.exists());
+        assert!(!to_a.exists());
+    }
+}
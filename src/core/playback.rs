@@ -0,0 +1,459 @@
+//! Play logging shared by the logger route and streaming sessions
+//!
+//! some clients (plain `<audio>` tags, dlna renderers) stream tracks without
+//! ever calling the log endpoint. when `scrobble_on_disconnect` is enabled each
+//! stream request opens or joins a session per client and track. a session ends
+//! once the client moves on to another track or stops requesting data, and the
+//! play is logged if the listened time passes the scrobble threshold and the
+//! client did not log it itself.
+
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::web::Bytes;
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use crate::config::UserConfig;
+use crate::core::homepage::HomepageStore;
+use crate::db::tables::{DiscoveryTable, ScrobbleTable};
+use crate::models::Track;
+use crate::plugins::LastFmPlugin;
+use crate::stores::{AlbumStore, ArtistStore, PlayRecord, PlayStatsStore, TrackStore};
+use crate::utils::extras::get_extra_info;
+
+/// source recorded for plays logged from a stream session
+pub const STREAM_SOURCE: &str = "stream";
+
+/// idle time after the last connection closes before a client counts as gone
+const KEEPALIVE: Duration = Duration::from_secs(20);
+
+/// time an ended session waits for the client to log the play itself
+const LOG_GRACE: Duration = Duration::from_secs(30);
+
+/// how often ended and idle sessions are checked
+const SWEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+static SESSIONS: Lazy<Mutex<HashMap<u64, StreamSession>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// stream responses still being sent
+static OPEN_STREAMS: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of stream responses still being sent
+pub fn open_streams() -> usize {
+    OPEN_STREAMS.load(Ordering::Relaxed)
+}
+
+/// Number of listening sessions that have not ended yet
+pub fn active_sessions() -> usize {
+    SESSIONS
+        .lock()
+        .values()
+        .filter(|s| s.ended.is_none())
+        .count()
+}
+
+/// Record a play for a user on a device, updating stats, the homepage and
+/// last.fm
+pub async fn record_play(
+    user_id: i64,
+    track: &Track,
+    timestamp: i64,
+    duration: i32,
+    source: &str,
+    device: &str,
+) -> Result<()> {
+    let extra = get_extra_info(&track.trackhash, "track");
+    ScrobbleTable::add_with_extra(
+        &track.trackhash,
+        timestamp,
+        duration,
+        source,
+        user_id,
+        device,
+        &extra,
+    )
+    .await?;
+    DiscoveryTable::record(
+        user_id,
+        &track.trackhash,
+        &track.albumhash,
+        &track.artisthashes,
+        timestamp,
+    )
+    .await?;
+
+    // a session for this track no longer needs to log it
+    forget_sessions(user_id, &track.trackhash);
+
+    HomepageStore::get().update_recently_played(user_id).await;
+
+    TrackStore::get().increment_play_stats(&track.trackhash, duration, timestamp);
+    AlbumStore::get().increment_play_stats(&track.albumhash, duration, timestamp);
+    for artisthash in &track.artisthashes {
+        ArtistStore::get().increment_play_stats(artisthash, duration, timestamp);
+    }
+    PlayStatsStore::get().record(PlayRecord {
+        userid: user_id,
+        trackhash: &track.trackhash,
+        albumhash: &track.albumhash,
+        artisthashes: &track.artisthashes,
+        duration,
+        timestamp,
+    });
+
+    if LastFmPlugin::should_scrobble(track.duration, duration) {
+        if let Some(session_key) = lastfm_session_for_user(user_id) {
+            let plugin = LastFmPlugin::new();
+            let scrobble_track = track.clone();
+
+            tokio::spawn(async move {
+                if let Err(err) = plugin
+                    .scrobble(&scrobble_track, timestamp, &session_key)
+                    .await
+                {
+                    tracing::warn!("lastfm scrobble error: {}", err);
+                }
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn lastfm_session_for_user(user_id: i64) -> Option<String> {
+    let config = UserConfig::load().ok()?;
+    config.get_lastfm_session_key(&user_id.to_string()).cloned()
+}
+
+struct StreamSession {
+    client: String,
+    /// device the plays are recorded for
+    device: String,
+    user_id: i64,
+    trackhash: String,
+    track_duration: i32,
+    started: Instant,
+    started_at: i64,
+    last_activity: Instant,
+    open_connections: usize,
+    /// a response reaching the end of the file was fully sent
+    reached_end: bool,
+    /// when the session ended, logging waits out [`LOG_GRACE`] from here
+    ended: Option<(Instant, i32)>,
+}
+
+impl StreamSession {
+    /// seconds listened, as far as the server can tell
+    fn played(&self, now: Instant) -> i32 {
+        // once the whole file is buffered only the wall clock is left to go by
+        let until = if self.open_connections > 0 || self.reached_end {
+            now
+        } else {
+            self.last_activity
+        };
+        let elapsed = until.saturating_duration_since(self.started).as_secs();
+        elapsed.min(self.track_duration.max(0) as u64) as i32
+    }
+
+    fn end(&mut self, now: Instant) {
+        if self.ended.is_none() {
+            self.ended = Some((now, self.played(now)));
+        }
+    }
+
+    fn is_idle(&self, now: Instant) -> bool {
+        if self.open_connections > 0 {
+            return false;
+        }
+        if self.reached_end {
+            let limit = Duration::from_secs(self.track_duration.max(0) as u64) + KEEPALIVE;
+            return now.saturating_duration_since(self.started) >= limit;
+        }
+        now.saturating_duration_since(self.last_activity) >= KEEPALIVE
+    }
+}
+
+/// Tracks one open stream response, closing the connection on drop
+pub struct StreamGuard {
+    session_id: u64,
+    covers_end: bool,
+    completed: bool,
+}
+
+impl StreamGuard {
+    /// Join or open the session for a client streaming a track
+    ///
+    /// `covers_end` tells whether the response runs to the end of the file so
+    /// a fully sent body means the client buffered the whole track
+    pub fn open(
+        client: String,
+        device: String,
+        user_id: i64,
+        track: &Track,
+        covers_end: bool,
+    ) -> Self {
+        let now = Instant::now();
+        let mut sessions = SESSIONS.lock();
+
+        let active = sessions
+            .iter_mut()
+            .find(|(_, s)| s.ended.is_none() && s.client == client);
+
+        let session_id = match active {
+            Some((id, session))
+                if session.user_id == user_id
+                    && session.trackhash == track.trackhash
+                    && !session.is_idle(now) =>
+            {
+                session.open_connections += 1;
+                session.last_activity = now;
+                *id
+            }
+            previous => {
+                // the client moved on, the previous track waits to be logged
+                if let Some((_, session)) = previous {
+                    session.end(now);
+                }
+
+                let id = NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed);
+                sessions.insert(
+                    id,
+                    StreamSession {
+                        client,
+                        device,
+                        user_id,
+                        trackhash: track.trackhash.clone(),
+                        track_duration: track.duration,
+                        started: now,
+                        started_at: chrono::Utc::now().timestamp(),
+                        last_activity: now,
+                        open_connections: 1,
+                        reached_end: false,
+                        ended: None,
+                    },
+                );
+                id
+            }
+        };
+
+        Self {
+            session_id,
+            covers_end,
+            completed: false,
+        }
+    }
+
+    /// Wrap a response body so the session sees when it finishes or drops
+    pub fn wrap(self, body: BoxBody) -> TrackedBody {
+        TrackedBody { body, guard: self }
+    }
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        let mut sessions = SESSIONS.lock();
+        if let Some(session) = sessions.get_mut(&self.session_id) {
+            session.open_connections = session.open_connections.saturating_sub(1);
+            session.last_activity = Instant::now();
+            if self.covers_end && self.completed {
+                session.reached_end = true;
+            }
+        }
+    }
+}
+
+/// Response body reporting completion and disconnects to its session
+pub struct TrackedBody {
+    body: BoxBody,
+    guard: StreamGuard,
+}
+
+impl MessageBody for TrackedBody {
+    type Error = <BoxBody as MessageBody>::Error;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let poll = Pin::new(&mut self.body).poll_next(cx);
+        if let Poll::Ready(None) = poll {
+            self.guard.completed = true;
+        }
+        poll
+    }
+}
+
+/// Response body counted in [`open_streams`] until it finishes or drops
+pub struct CountedBody {
+    body: BoxBody,
+}
+
+impl CountedBody {
+    pub fn new(body: BoxBody) -> Self {
+        OPEN_STREAMS.fetch_add(1, Ordering::Relaxed);
+        Self { body }
+    }
+}
+
+impl Drop for CountedBody {
+    fn drop(&mut self) {
+        OPEN_STREAMS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl MessageBody for CountedBody {
+    type Error = <BoxBody as MessageBody>::Error;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        Pin::new(&mut self.body).poll_next(cx)
+    }
+}
+
+/// Drop sessions for a track the client logged itself
+fn forget_sessions(user_id: i64, trackhash: &str) {
+    SESSIONS
+        .lock()
+        .retain(|_, s| !(s.user_id == user_id && s.trackhash == trackhash));
+}
+
+/// Periodically end idle sessions and log the plays clients never logged
+pub async fn run_session_sweeper() {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        for due in take_due_sessions(Instant::now()) {
+            if let Err(e) = log_session(&due).await {
+                tracing::warn!("failed to log stream session for {}: {}", due.trackhash, e);
+            }
+        }
+    }
+}
+
+/// an ended session whose play should be logged
+struct DueSession {
+    user_id: i64,
+    trackhash: String,
+    device: String,
+    started_at: i64,
+    played: i32,
+}
+
+/// remove sessions past their grace period, returning the ones worth logging
+fn take_due_sessions(now: Instant) -> Vec<DueSession> {
+    let mut sessions = SESSIONS.lock();
+    for session in sessions.values_mut() {
+        if session.is_idle(now) {
+            session.end(now);
+        }
+    }
+
+    let due: Vec<u64> = sessions
+        .iter()
+        .filter(|(_, s)| {
+            s.ended
+                .is_some_and(|(at, _)| now.saturating_duration_since(at) >= LOG_GRACE)
+        })
+        .map(|(id, _)| *id)
+        .collect();
+
+    due.into_iter()
+        .filter_map(|id| sessions.remove(&id))
+        .filter_map(|s| {
+            let (_, played) = s.ended?;
+            LastFmPlugin::should_scrobble(s.track_duration, played).then_some(DueSession {
+                user_id: s.user_id,
+                trackhash: s.trackhash,
+                device: s.device,
+                started_at: s.started_at,
+                played,
+            })
+        })
+        .collect()
+}
+
+async fn log_session(due: &DueSession) -> Result<()> {
+    let Some(track) = TrackStore::get().get_by_hash(&due.trackhash) else {
+        return Ok(());
+    };
+
+    // the client may have logged the play after the grace period started
+    let now = chrono::Utc::now().timestamp();
+    let logged = ScrobbleTable::get_in_range(due.user_id, due.started_at, now)
+        .await?
+        .iter()
+        .any(|log| log.trackhash == due.trackhash);
+    if logged {
+        return Ok(());
+    }
+
+    record_play(
+        due.user_id,
+        &track,
+        now,
+        due.played,
+        STREAM_SOURCE,
+        &due.device,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(track_duration: i32) -> StreamSession {
+        let now = Instant::now();
+        StreamSession {
+            client: "client".to_string(),
+            device: String::new(),
+            user_id: 1,
+            trackhash: "abc".to_string(),
+            track_duration,
+            started: now,
+            started_at: 0,
+            last_activity: now,
+            open_connections: 0,
+            reached_end: false,
+            ended: None,
+        }
+    }
+
+    #[test]
+    fn test_played_uses_last_activity_after_disconnect() {
+        let mut s = session(300);
+        s.last_activity = s.started + Duration::from_secs(90);
+        assert_eq!(s.played(s.started + Duration::from_secs(200)), 90);
+
+        s.open_connections = 1;
+        assert_eq!(s.played(s.started + Duration::from_secs(200)), 200);
+        assert_eq!(s.played(s.started + Duration::from_secs(900)), 300);
+    }
+
+    #[test]
+    fn test_buffered_session_waits_for_track_length() {
+        let mut s = session(120);
+        s.reached_end = true;
+        assert!(!s.is_idle(s.started + Duration::from_secs(60)));
+        assert!(s.is_idle(s.started + Duration::from_secs(120) + KEEPALIVE));
+    }
+}
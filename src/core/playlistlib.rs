@@ -1,14 +1,106 @@
 //! Playlist library functions
 
 use anyhow::Result;
+use std::collections::HashSet;
+use std::fs;
+use std::time::{Duration, SystemTime};
 
-use crate::db::tables::PlaylistTable;
-use crate::models::{Playlist, Track};
+use crate::config::Paths;
+use crate::core::colorlib::ColorLib;
+use crate::core::dlna::xml_escape;
+use crate::db::tables::{PlaylistImageTable, PlaylistTable};
+use crate::models::{ColorVariants, Playlist, Track};
 use crate::stores::TrackStore;
 
+/// how long an untracked or superseded image file may live before it is collected
+const ORPHAN_IMAGE_GRACE_SECS: u64 = 3600;
+
 /// Playlist library functions
 pub struct PlaylistLib;
 
+/// Playlist file formats other players can import
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaylistFormat {
+    M3u,
+    Xspf,
+}
+
+impl PlaylistFormat {
+    /// Parse a format name as given in the export query
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "m3u" | "m3u8" => Some(Self::M3u),
+            "xspf" => Some(Self::Xspf),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::M3u => "m3u8",
+            Self::Xspf => "xspf",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::M3u => "audio/x-mpegurl; charset=utf-8",
+            Self::Xspf => "application/xspf+xml; charset=utf-8",
+        }
+    }
+}
+
+/// How the tracks of another playlist are combined into a playlist
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaylistCombine {
+    /// append the tracks not in the playlist yet
+    Merge,
+    /// drop the tracks the other playlist has
+    Subtract,
+    /// keep only the tracks the other playlist has
+    Intersect,
+}
+
+impl PlaylistCombine {
+    /// Parse an operation name as given in the combine body
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "merge" | "union" => Some(Self::Merge),
+            "subtract" | "difference" => Some(Self::Subtract),
+            "intersect" | "intersection" => Some(Self::Intersect),
+            _ => None,
+        }
+    }
+
+    /// Combine `source` into `target`, the order of `target` is kept and merged
+    /// tracks are appended in the order of `source`
+    pub fn apply(&self, target: &[String], source: &[String]) -> Vec<String> {
+        let in_source: HashSet<&String> = source.iter().collect();
+        match self {
+            Self::Merge => {
+                let mut present: HashSet<&String> = target.iter().collect();
+                let mut combined = target.to_vec();
+                for hash in source {
+                    if present.insert(hash) {
+                        combined.push(hash.clone());
+                    }
+                }
+                combined
+            }
+            Self::Subtract => target
+                .iter()
+                .filter(|h| !in_source.contains(h))
+                .cloned()
+                .collect(),
+            Self::Intersect => target
+                .iter()
+                .filter(|h| in_source.contains(h))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
 impl PlaylistLib {
     /// Get all playlists
     pub async fn get_all() -> Result<Vec<Playlist>> {
@@ -20,9 +112,9 @@ impl PlaylistLib {
         PlaylistTable::get_by_id(id).await
     }
 
-    /// Create new playlist
-    pub async fn create(name: &str, description: Option<&str>) -> Result<i64> {
-        let mut playlist = Playlist::new(name.to_string(), None);
+    /// Create new playlist owned by a user
+    pub async fn create(name: &str, description: Option<&str>, userid: i64) -> Result<i64> {
+        let mut playlist = Playlist::new(name.to_string(), Some(userid));
         if let Some(desc) = description {
             playlist.extra = serde_json::json!({ "description": desc });
         }
@@ -46,7 +138,10 @@ impl PlaylistLib {
 
     /// Delete playlist
     pub async fn delete(id: i64) -> Result<()> {
-        PlaylistTable::delete(id, 0).await.map(|_| ())
+        if PlaylistTable::delete(id, 0).await? {
+            Self::release_images(id, None).await?;
+        }
+        Ok(())
     }
 
     /// Get playlist tracks
@@ -164,6 +259,21 @@ impl PlaylistLib {
         }
     }
 
+    /// Render a playlist file listing `tracks` in order
+    ///
+    /// `location` returns the path or url written for each track
+    pub fn export(
+        playlist: &Playlist,
+        tracks: &[Track],
+        format: PlaylistFormat,
+        location: impl Fn(&Track) -> String,
+    ) -> String {
+        match format {
+            PlaylistFormat::M3u => render_m3u(playlist, tracks, location),
+            PlaylistFormat::Xspf => render_xspf(playlist, tracks, location),
+        }
+    }
+
     /// Duplicate playlist
     pub async fn duplicate(playlist_id: i64, new_name: Option<&str>) -> Result<i64> {
         let playlist = PlaylistTable::get_by_id(playlist_id).await?;
@@ -175,9 +285,312 @@ impl PlaylistLib {
                 let mut new_playlist = p.clone();
                 new_playlist.id = 0;
                 new_playlist.name = name.to_string();
-                PlaylistTable::insert(&new_playlist).await
+                let id = PlaylistTable::insert(&new_playlist).await?;
+                if let Some(image) = &new_playlist.image {
+                    let variants = ColorVariants {
+                        dark: new_playlist.color_dark.clone(),
+                        light: new_playlist.color_light.clone(),
+                    };
+                    PlaylistImageTable::insert(id, image, &new_playlist.color, &variants).await?;
+                }
+                Ok(id)
             }
             None => Err(anyhow::anyhow!("Playlist not found")),
         }
     }
+
+    /// Record a freshly written image file for a playlist
+    ///
+    /// the files are removed again if they cannot be tracked so nothing leaks.
+    /// returns the dominant color of the image.
+    pub async fn track_image(playlist_id: i64, filename: &str) -> Result<String> {
+        let color = Self::image_color(filename).await;
+        let variants = ColorLib::variants(&color);
+        if let Err(e) = PlaylistImageTable::insert(playlist_id, filename, &color, &variants).await {
+            delete_image_files(filename);
+            return Err(e);
+        }
+        Ok(color)
+    }
+
+    /// Dominant color of a playlist image, read from its thumbnail when available
+    pub async fn image_color(filename: &str) -> String {
+        let Ok(paths) = Paths::get() else {
+            return String::new();
+        };
+        let dir = paths.playlist_images_dir();
+        let thumb = dir.join(format!("thumb_{}", filename));
+        let source = if thumb.exists() {
+            thumb
+        } else {
+            dir.join(filename)
+        };
+
+        tokio::task::spawn_blocking(move || ColorLib::extract_dominant(&source).ok())
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+    }
+
+    /// Drop an image file from a playlist and delete it once nothing else uses it
+    pub async fn discard_image(playlist_id: i64, filename: &str) -> Result<()> {
+        PlaylistImageTable::delete(playlist_id, filename).await?;
+        if !PlaylistImageTable::is_referenced(filename).await? {
+            delete_image_files(filename);
+        }
+        Ok(())
+    }
+
+    /// Release every image tracked for a playlist except `keep`
+    pub async fn release_images(playlist_id: i64, keep: Option<&str>) -> Result<()> {
+        for row in PlaylistImageTable::get_by_playlist(playlist_id).await? {
+            if Some(row.filename.as_str()) != keep {
+                Self::discard_image(playlist_id, &row.filename).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reconcile the playlist image table with the images directory
+    ///
+    /// returns the number of files removed from disk.
+    pub async fn cleanup_orphan_images() -> Result<usize> {
+        let playlists = PlaylistTable::all(None).await?;
+        let ids: HashSet<i64> = playlists.iter().map(|p| p.id).collect();
+        let referenced: HashSet<(i64, String)> = playlists
+            .iter()
+            .filter_map(|p| p.image.clone().map(|img| (p.id, img)))
+            .filter(|(_, img)| !img.is_empty())
+            .collect();
+
+        let now = chrono::Utc::now().timestamp();
+        let grace = ORPHAN_IMAGE_GRACE_SECS as i64;
+
+        // drop rows of deleted playlists and superseded images past the grace period
+        let mut tracked: HashSet<String> = HashSet::new();
+        let mut seen: HashSet<(i64, String)> = HashSet::new();
+        for row in PlaylistImageTable::all().await? {
+            let key = (row.playlistid, row.filename.clone());
+            let stale = !ids.contains(&row.playlistid)
+                || (!referenced.contains(&key) && now - row.created_at > grace);
+
+            if stale {
+                PlaylistImageTable::delete(row.playlistid, &row.filename).await?;
+            } else {
+                // backfill colors for images tracked before colors were extracted
+                if row.color.is_empty() && referenced.contains(&key) {
+                    let color = Self::image_color(&row.filename).await;
+                    if !color.is_empty() {
+                        let variants = ColorLib::variants(&color);
+                        PlaylistImageTable::set_color(
+                            row.playlistid,
+                            &row.filename,
+                            &color,
+                            &variants,
+                        )
+                        .await?;
+                    }
+                }
+                tracked.insert(row.filename);
+                seen.insert(key);
+            }
+        }
+
+        // adopt images restored or written before tracking existed
+        for (id, image) in referenced.difference(&seen) {
+            let color = Self::image_color(image).await;
+            let variants = ColorLib::variants(&color);
+            PlaylistImageTable::insert(*id, image, &color, &variants).await?;
+            tracked.insert(image.clone());
+        }
+
+        let dir = Paths::get()?.playlist_images_dir();
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(0),
+        };
+
+        let cutoff = SystemTime::now() - Duration::from_secs(ORPHAN_IMAGE_GRACE_SECS);
+        let mut removed = 0;
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !path.is_file() {
+                continue;
+            }
+
+            let name = entry.file_name().to_string_lossy().to_string();
+            let base = name.strip_prefix("thumb_").unwrap_or(&name);
+            if tracked.contains(base) {
+                continue;
+            }
+
+            // legacy images are named after the playlist id alone
+            let stem = base.split('.').next().unwrap_or_default();
+            if stem
+                .parse::<i64>()
+                .map(|id| ids.contains(&id))
+                .unwrap_or(false)
+            {
+                continue;
+            }
+
+            let recent = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .map(|modified| modified > cutoff)
+                .unwrap_or(true);
+            if recent {
+                continue;
+            }
+
+            if fs::remove_file(&path).is_ok() {
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+fn render_m3u(
+    playlist: &Playlist,
+    tracks: &[Track],
+    location: impl Fn(&Track) -> String,
+) -> String {
+    let mut out = String::from("#EXTM3U\n");
+    out.push_str(&format!("#PLAYLIST:{}\n", single_line(&playlist.name)));
+
+    for track in tracks {
+        out.push_str(&format!(
+            "#EXTINF:{},{} - {}\n{}\n",
+            track.duration,
+            single_line(&track.artist()),
+            single_line(&track.title),
+            location(track)
+        ));
+    }
+    out
+}
+
+fn render_xspf(
+    playlist: &Playlist,
+    tracks: &[Track],
+    location: impl Fn(&Track) -> String,
+) -> String {
+    let mut out = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n",
+    );
+    out.push_str(&format!(
+        "  <title>{}</title>\n",
+        xml_escape(&playlist.name)
+    ));
+    out.push_str("  <trackList>\n");
+
+    for track in tracks {
+        out.push_str("    <track>\n");
+        out.push_str(&format!(
+            "      <location>{}</location>\n",
+            xml_escape(&location(track))
+        ));
+        out.push_str(&format!(
+            "      <title>{}</title>\n",
+            xml_escape(&track.title)
+        ));
+        out.push_str(&format!(
+            "      <creator>{}</creator>\n",
+            xml_escape(&track.artist())
+        ));
+        out.push_str(&format!(
+            "      <album>{}</album>\n",
+            xml_escape(&track.album)
+        ));
+        if track.track > 0 {
+            out.push_str(&format!("      <trackNum>{}</trackNum>\n", track.track));
+        }
+        // xspf durations are in milliseconds
+        out.push_str(&format!(
+            "      <duration>{}</duration>\n",
+            i64::from(track.duration.max(0)) * 1000
+        ));
+        out.push_str("    </track>\n");
+    }
+
+    out.push_str("  </trackList>\n</playlist>\n");
+    out
+}
+
+/// m3u entries are line based so tags must not break lines
+fn single_line(value: &str) -> String {
+    value.replace(['\r', '\n'], " ")
+}
+
+/// Delete an image file and its thumbnail from the playlist images directory
+pub fn delete_image_files(filename: &str) {
+    if let Ok(paths) = Paths::get() {
+        let dir = paths.playlist_images_dir();
+        let _ = fs::remove_file(dir.join(filename));
+        let _ = fs::remove_file(dir.join(format!("thumb_{}", filename)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(title: &str, filepath: &str) -> Track {
+        let mut track = Track::new();
+        track.title = title.to_string();
+        track.album = "Album".to_string();
+        track.filepath = filepath.to_string();
+        track.duration = 215;
+        track.track = 3;
+        track
+    }
+
+    #[test]
+    fn test_combine_playlists() {
+        let hashes = |s: &str| -> Vec<String> { s.chars().map(String::from).collect() };
+        let target = hashes("abcab");
+        let source = hashes("dbed");
+
+        let apply = |op: &str| PlaylistCombine::parse(op).unwrap().apply(&target, &source);
+        assert_eq!(apply("merge"), hashes("abcabde"));
+        assert_eq!(apply("Subtract"), hashes("aca"));
+        assert_eq!(apply("intersect"), hashes("bb"));
+        assert_eq!(PlaylistCombine::parse("xor"), None);
+    }
+
+    #[test]
+    fn test_export_m3u_keeps_entries_on_one_line() {
+        let playlist = Playlist::new("Road\ntrip".to_string(), None);
+        let tracks = vec![track("First\nSong", "/music/a.flac")];
+
+        let out = PlaylistLib::export(&playlist, &tracks, PlaylistFormat::M3u, |t| {
+            t.filepath.clone()
+        });
+
+        assert_eq!(
+            out,
+            "#EXTM3U\n#PLAYLIST:Road trip\n#EXTINF:215, - First Song\n/music/a.flac\n"
+        );
+    }
+
+    #[test]
+    fn test_export_xspf_escapes_and_uses_milliseconds() {
+        let playlist = Playlist::new("Rock & Roll".to_string(), None);
+        let tracks = vec![track("<Intro>", "/music/a.flac")];
+
+        let out = PlaylistLib::export(&playlist, &tracks, PlaylistFormat::Xspf, |_| {
+            "http://host/stream/abc?x=1&y=2".to_string()
+        });
+
+        assert!(out.contains("<title>Rock &amp; Roll</title>"));
+        assert!(out.contains("<location>http://host/stream/abc?x=1&amp;y=2</location>"));
+        assert!(out.contains("<title>&lt;Intro&gt;</title>"));
+        assert!(out.contains("<duration>215000</duration>"));
+        assert!(out.contains("<trackNum>3</trackNum>"));
+    }
 }
@@ -0,0 +1,515 @@
+//! Podcast subscriptions
+//!
+//! feeds are fetched and parsed here, their episodes are stored per podcast and
+//! refreshed by a cron job. episodes are streamed through the server, from the
+//! downloaded file when there is one and from the feed's enclosure otherwise.
+//! feed and episode urls come from users and feeds, so they are only fetched
+//! from public addresses.
+
+use anyhow::{bail, Context, Result};
+use bytes::Bytes;
+use futures::Stream;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+use crate::config::Paths;
+use crate::core::radio;
+use crate::db::tables::PodcastTable;
+use crate::models::{Podcast, PodcastEpisode};
+
+/// how long fetching a feed may take
+const FEED_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// largest feed accepted, long running shows easily pass a few megabytes
+const MAX_FEED_BYTES: usize = 20 * 1024 * 1024;
+
+/// largest episode downloaded, hours of high bitrate audio stay well below it
+const MAX_EPISODE_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+
+/// episodes being downloaded
+static DOWNLOADING: Lazy<Mutex<HashSet<i64>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Podcast details and episodes read from a feed
+#[derive(Debug, Default)]
+pub struct Feed {
+    pub title: String,
+    pub author: String,
+    pub description: String,
+    pub image: String,
+    pub link: String,
+    pub episodes: Vec<PodcastEpisode>,
+}
+
+/// Subscribe a user to a feed, returns the podcast and its episode count
+pub async fn subscribe(userid: i64, feed_url: &str) -> Result<(Podcast, usize)> {
+    let feed_url = radio::validate_url(feed_url)?.to_string();
+    if PodcastTable::get_by_feed(&feed_url, userid)
+        .await?
+        .is_some()
+    {
+        bail!("Already subscribed to this podcast");
+    }
+
+    let feed = fetch_feed(&feed_url).await?;
+    let now = chrono::Utc::now().timestamp();
+    let mut podcast = Podcast {
+        id: 0,
+        userid,
+        feed_url,
+        title: String::new(),
+        author: String::new(),
+        description: String::new(),
+        image: String::new(),
+        link: String::new(),
+        last_checked: now,
+        created_at: now,
+    };
+    apply_feed(&mut podcast, &feed);
+
+    podcast.id = PodcastTable::insert(&podcast).await?;
+    let count = PodcastTable::upsert_episodes(podcast.id, &feed.episodes).await?;
+    Ok((podcast, count))
+}
+
+/// Fetch a podcast's feed again, returns the number of new episodes
+pub async fn refresh(podcast: &mut Podcast) -> Result<usize> {
+    let feed = fetch_feed(&podcast.feed_url).await?;
+    apply_feed(podcast, &feed);
+    podcast.last_checked = chrono::Utc::now().timestamp();
+
+    PodcastTable::update(podcast).await?;
+    PodcastTable::upsert_episodes(podcast.id, &feed.episodes).await
+}
+
+/// Refresh every subscribed podcast
+pub async fn refresh_all() -> Result<()> {
+    let mut new_episodes = 0;
+    for mut podcast in PodcastTable::all(None).await? {
+        match refresh(&mut podcast).await {
+            Ok(count) => new_episodes += count,
+            Err(e) => tracing::warn!("Failed to refresh podcast {}: {}", podcast.feed_url, e),
+        }
+    }
+
+    if new_episodes > 0 {
+        tracing::info!("Found {} new podcast episodes", new_episodes);
+    }
+    Ok(())
+}
+
+/// Remove a podcast with its episodes, progress and downloaded files
+pub async fn unsubscribe(podcast: &Podcast) -> Result<()> {
+    let files = PodcastTable::episode_files(podcast.id).await?;
+    PodcastTable::delete(podcast.id).await?;
+
+    for file in files {
+        let _ = tokio::fs::remove_file(&file).await;
+    }
+    if let Ok(paths) = Paths::get() {
+        let _ = tokio::fs::remove_dir(paths.podcasts_dir().join(podcast.id.to_string())).await;
+    }
+    Ok(())
+}
+
+/// Whether an episode is being downloaded
+pub fn is_downloading(episodeid: i64) -> bool {
+    DOWNLOADING.lock().contains(&episodeid)
+}
+
+/// Download an episode in the background, false when it is already downloading
+pub fn spawn_download(episode: PodcastEpisode) -> bool {
+    if !DOWNLOADING.lock().insert(episode.id) {
+        return false;
+    }
+
+    tokio::spawn(async move {
+        if let Err(e) = download(&episode).await {
+            tracing::error!("Failed to download episode {}: {}", episode.audio_url, e);
+        }
+        DOWNLOADING.lock().remove(&episode.id);
+    });
+    true
+}
+
+async fn download(episode: &PodcastEpisode) -> Result<PathBuf> {
+    let paths = Paths::get()?;
+    let dir = paths.podcasts_dir().join(episode.podcastid.to_string());
+    tokio::fs::create_dir_all(&dir).await?;
+
+    let ext = file_extension(&episode.audio_url, &episode.mime_type);
+    let target = dir.join(format!("{}.{}", episode.id, ext));
+    let partial = dir.join(format!("{}.{}.part", episode.id, ext));
+
+    let result = async {
+        let url = radio::validate_url(&episode.audio_url)?;
+        let (_, mut response) = radio::fetch_public(url, |request| request).await?;
+        if response.content_length().unwrap_or(0) > MAX_EPISODE_BYTES {
+            bail!("Episode is too large");
+        }
+        let mut file = tokio::fs::File::create(&partial).await?;
+        let mut written = 0;
+        while let Some(chunk) = response.chunk().await? {
+            written += chunk.len() as u64;
+            if written > MAX_EPISODE_BYTES {
+                bail!("Episode is too large");
+            }
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        tokio::fs::rename(&partial, &target).await?;
+        anyhow::Ok(())
+    }
+    .await;
+
+    if let Err(e) = result {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(e);
+    }
+
+    PodcastTable::set_episode_file(episode.id, &target.to_string_lossy()).await?;
+    Ok(target)
+}
+
+/// Delete the downloaded file of an episode
+pub async fn remove_download(episode: &PodcastEpisode) -> Result<()> {
+    if episode.filepath.is_empty() {
+        return Ok(());
+    }
+
+    match tokio::fs::remove_file(&episode.filepath).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    PodcastTable::set_episode_file(episode.id, "").await
+}
+
+/// Downloaded file of an episode, None when it has to be streamed from the feed
+pub fn local_file(episode: &PodcastEpisode) -> Option<&Path> {
+    let path = Path::new(&episode.filepath);
+    (!episode.filepath.is_empty() && path.exists()).then_some(path)
+}
+
+/// Request an episode's audio from its host, passing on a range header
+pub async fn open_remote(
+    episode: &PodcastEpisode,
+    range: Option<&str>,
+) -> Result<reqwest::Response> {
+    let url = radio::validate_url(&episode.audio_url)?;
+    let (_, response) = radio::fetch_public(url, |request| match range {
+        Some(range) => request.header("Range", range),
+        None => request,
+    })
+    .await?;
+    Ok(response)
+}
+
+/// Body of an upstream response as a stream
+pub fn response_stream(
+    response: reqwest::Response,
+) -> impl Stream<Item = Result<Bytes, reqwest::Error>> {
+    futures::stream::unfold(Some(response), |response| async move {
+        let mut response = response?;
+        match response.chunk().await {
+            Ok(Some(chunk)) => Some((Ok(chunk), Some(response))),
+            Ok(None) => None,
+            Err(e) => Some((Err(e), None)),
+        }
+    })
+}
+
+async fn fetch_feed(url: &str) -> Result<Feed> {
+    let url = radio::validate_url(url)?;
+    let (_, mut response) =
+        radio::fetch_public(url, |request| request.timeout(FEED_TIMEOUT)).await?;
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() > MAX_FEED_BYTES {
+            bail!("Feed is too large");
+        }
+    }
+
+    parse_feed(&String::from_utf8_lossy(&body)).context("Failed to parse feed")
+}
+
+fn apply_feed(podcast: &mut Podcast, feed: &Feed) {
+    podcast.title = if feed.title.is_empty() {
+        podcast.feed_url.clone()
+    } else {
+        feed.title.clone()
+    };
+    podcast.author = feed.author.clone();
+    podcast.description = feed.description.clone();
+    podcast.image = feed.image.clone();
+    podcast.link = feed.link.clone();
+}
+
+fn file_extension(url: &str, mime_type: &str) -> String {
+    let from_url = reqwest::Url::parse(url).ok().and_then(|u| {
+        Path::new(u.path())
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_lowercase)
+    });
+    let from_mime = || match mime_type {
+        "audio/mpeg" | "audio/mp3" => Some("mp3".to_string()),
+        "audio/mp4" | "audio/x-m4a" | "audio/aac" => Some("m4a".to_string()),
+        "audio/ogg" | "audio/opus" => Some("ogg".to_string()),
+        _ => mime_guess::get_mime_extensions_str(mime_type)
+            .and_then(|exts| exts.first())
+            .map(|e| e.to_string()),
+    };
+
+    from_url
+        .filter(|e| e.len() <= 5 && e.chars().all(|c| c.is_ascii_alphanumeric()))
+        .or_else(from_mime)
+        .unwrap_or_else(|| "mp3".to_string())
+}
+
+/// Parse an RSS podcast feed
+pub fn parse_feed(xml: &str) -> Result<Feed> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut feed = Feed::default();
+    let mut path: Vec<String> = Vec::new();
+    let mut text = String::new();
+    let mut item: Option<PodcastEpisode> = None;
+    let mut seen_channel = false;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                let name = element_name(&e);
+                if name == "channel" {
+                    seen_channel = true;
+                }
+                if name == "item" {
+                    item = Some(empty_episode());
+                }
+                read_attributes(&e, &name, &mut feed, item.as_mut());
+                path.push(name);
+                text.clear();
+            }
+            Event::Empty(e) => {
+                let name = element_name(&e);
+                read_attributes(&e, &name, &mut feed, item.as_mut());
+            }
+            Event::Text(e) => text.push_str(&e.unescape()?),
+            Event::CData(e) => text.push_str(&String::from_utf8_lossy(&e.into_inner())),
+            Event::End(_) => {
+                let Some(name) = path.pop() else {
+                    continue;
+                };
+                let value = std::mem::take(&mut text).trim().to_string();
+                let parent = path.last().map(String::as_str).unwrap_or("");
+
+                if name == "item" {
+                    if let Some(mut episode) = item.take() {
+                        if episode.guid.is_empty() {
+                            episode.guid = episode.audio_url.clone();
+                        }
+                        if !episode.audio_url.is_empty() {
+                            feed.episodes.push(episode);
+                        }
+                    }
+                } else if let Some(episode) = item.as_mut() {
+                    set_episode_field(episode, &name, value);
+                } else if parent == "channel" {
+                    set_channel_field(&mut feed, &name, value);
+                } else if parent == "image" && name == "url" && feed.image.is_empty() {
+                    feed.image = value;
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if !seen_channel {
+        bail!("Not an RSS feed");
+    }
+    Ok(feed)
+}
+
+fn element_name(e: &BytesStart) -> String {
+    String::from_utf8_lossy(e.name().as_ref()).to_lowercase()
+}
+
+fn attribute(e: &BytesStart, key: &str) -> Option<String> {
+    e.attributes()
+        .flatten()
+        .find(|a| a.key.as_ref().eq_ignore_ascii_case(key.as_bytes()))
+        .and_then(|a| a.unescape_value().ok())
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+fn read_attributes(
+    e: &BytesStart,
+    name: &str,
+    feed: &mut Feed,
+    episode: Option<&mut PodcastEpisode>,
+) {
+    match (name, episode) {
+        ("enclosure", Some(episode)) => {
+            if let Some(url) = attribute(e, "url") {
+                episode.audio_url = url;
+                episode.mime_type = attribute(e, "type").unwrap_or_default();
+            }
+        }
+        ("itunes:image", Some(episode)) => {
+            episode.image = attribute(e, "href").unwrap_or_default();
+        }
+        ("itunes:image", None) => {
+            // the itunes cover is preferred over the rss <image>
+            if let Some(href) = attribute(e, "href") {
+                feed.image = href;
+            }
+        }
+        _ => {}
+    }
+}
+
+fn set_channel_field(feed: &mut Feed, name: &str, value: String) {
+    match name {
+        "title" => feed.title = value,
+        "link" => feed.link = value,
+        "description" => feed.description = value,
+        "itunes:summary" if feed.description.is_empty() => feed.description = value,
+        "itunes:author" => feed.author = value,
+        "author" | "managingeditor" if feed.author.is_empty() => feed.author = value,
+        _ => {}
+    }
+}
+
+fn set_episode_field(episode: &mut PodcastEpisode, name: &str, value: String) {
+    match name {
+        "title" => episode.title = value,
+        "guid" => episode.guid = value,
+        "description" => episode.description = value,
+        "itunes:summary" | "content:encoded" if episode.description.is_empty() => {
+            episode.description = value
+        }
+        "itunes:duration" => episode.duration = parse_duration(&value),
+        "pubdate" => episode.published = parse_date(&value),
+        _ => {}
+    }
+}
+
+fn empty_episode() -> PodcastEpisode {
+    PodcastEpisode {
+        id: 0,
+        podcastid: 0,
+        guid: String::new(),
+        title: String::new(),
+        description: String::new(),
+        audio_url: String::new(),
+        mime_type: String::new(),
+        duration: 0,
+        published: 0,
+        image: String::new(),
+        filepath: String::new(),
+    }
+}
+
+/// Parse an itunes duration given as seconds, MM:SS or HH:MM:SS
+fn parse_duration(value: &str) -> i64 {
+    value
+        .split(':')
+        .map(|part| part.trim().parse::<f64>().ok())
+        .try_fold(0.0, |total, part| part.map(|p| total * 60.0 + p))
+        .map(|secs| secs as i64)
+        .unwrap_or(0)
+}
+
+fn parse_date(value: &str) -> i64 {
+    chrono::DateTime::parse_from_rfc2822(value)
+        .or_else(|_| chrono::DateTime::parse_from_rfc3339(value))
+        .map(|dt| dt.timestamp())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+  <channel>
+    <title>Rust &amp; Friends</title>
+    <link>https://example.com</link>
+    <description><![CDATA[A show about <b>Rust</b>]]></description>
+    <itunes:author>Ferris</itunes:author>
+    <itunes:image href="https://example.com/cover.jpg"/>
+    <image><url>https://example.com/small.jpg</url><title>Rust</title></image>
+    <item>
+      <title>Episode 2</title>
+      <guid isPermaLink="false">ep-2</guid>
+      <pubDate>Tue, 02 Jan 2024 10:00:00 GMT</pubDate>
+      <itunes:duration>1:02:03</itunes:duration>
+      <enclosure url="https://example.com/ep2.mp3" length="1" type="audio/mpeg"/>
+    </item>
+    <item>
+      <title>Episode 1</title>
+      <itunes:duration>754</itunes:duration>
+      <enclosure url="https://example.com/ep1.m4a" type="audio/x-m4a"/>
+    </item>
+    <item>
+      <title>Trailer without audio</title>
+    </item>
+  </channel>
+</rss>"#;
+
+    #[test]
+    fn test_parse_feed() {
+        let feed = parse_feed(FEED).unwrap();
+        assert_eq!(feed.title, "Rust & Friends");
+        assert_eq!(feed.description, "A show about <b>Rust</b>");
+        assert_eq!(feed.author, "Ferris");
+        assert_eq!(feed.image, "https://example.com/cover.jpg");
+
+        assert_eq!(feed.episodes.len(), 2);
+        let latest = &feed.episodes[0];
+        assert_eq!(latest.guid, "ep-2");
+        assert_eq!(latest.duration, 3723);
+        assert_eq!(latest.published, 1704189600);
+        assert_eq!(latest.mime_type, "audio/mpeg");
+
+        // episodes without a guid are keyed by their audio url
+        assert_eq!(feed.episodes[1].guid, "https://example.com/ep1.m4a");
+        assert_eq!(feed.episodes[1].duration, 754);
+    }
+
+    #[test]
+    fn test_parse_feed_rejects_other_documents() {
+        assert!(parse_feed("<html><body>not a feed</body></html>").is_err());
+    }
+
+    #[test]
+    fn test_file_extension() {
+        assert_eq!(file_extension("https://a.com/ep.M4A?x=1", ""), "m4a");
+        assert_eq!(file_extension("https://a.com/episode", "audio/mpeg"), "mp3");
+    }
+
+    #[tokio::test]
+    async fn test_private_urls_are_not_fetched() {
+        assert!(fetch_feed("http://127.0.0.1:1970/feed.xml").await.is_err());
+        assert!(subscribe(1, "http://localhost/feed.xml").await.is_err());
+
+        let feed = FEED.replace(
+            "https://example.com/ep2.mp3",
+            "http://169.254.169.254/latest/meta-data/",
+        );
+        let episode = &parse_feed(&feed).unwrap().episodes[0];
+        assert!(open_remote(episode, None).await.is_err());
+    }
+}
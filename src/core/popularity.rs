@@ -0,0 +1,200 @@
+//! Decaying popularity scores
+//!
+//! every play adds a weight that halves every `HALF_LIFE_DAYS`, so a track
+//! played a lot last year ranks below one played a few times this week. the
+//! scores of tracks, their artists and their albums are rebuilt nightly from
+//! the play history.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::core::audiobooks;
+use crate::db::tables::{PopularityScore, PopularityTable, ScrobblePoint, ScrobbleTable};
+use crate::stores::TrackStore;
+
+/// Days after which a play counts half as much
+pub const HALF_LIFE_DAYS: f64 = 14.0;
+
+/// Scores below this are left out, a single play drops under it after about 93 days
+const MIN_SCORE: f64 = 0.01;
+
+/// What a popularity score is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PopularityKind {
+    Track,
+    Artist,
+    Album,
+}
+
+impl PopularityKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Track => "track",
+            Self::Artist => "artist",
+            Self::Album => "album",
+        }
+    }
+
+    /// Parse a kind, plural forms are accepted
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().trim_end_matches('s') {
+            "track" => Some(Self::Track),
+            "artist" => Some(Self::Artist),
+            "album" => Some(Self::Album),
+            _ => None,
+        }
+    }
+}
+
+/// Album and artists a play is credited to
+pub struct PlayCredits {
+    pub albumhash: String,
+    pub artisthashes: Vec<String>,
+}
+
+/// Weight of a play `age` seconds ago
+pub fn decay_weight(age: i64) -> f64 {
+    let days = age.max(0) as f64 / 86400.0;
+    0.5f64.powf(days / HALF_LIFE_DAYS)
+}
+
+/// Score every track, artist and album in a play history
+///
+/// plays of tracks `credits` knows nothing about are skipped
+pub fn compute_scores<F>(plays: &[ScrobblePoint], now: i64, credits: F) -> Vec<PopularityScore>
+where
+    F: Fn(&str) -> Option<PlayCredits>,
+{
+    let mut known: HashMap<&str, Option<PlayCredits>> = HashMap::new();
+    let mut totals: HashMap<(i64, PopularityKind, String), f64> = HashMap::new();
+
+    for play in plays {
+        let Some(credit) = known
+            .entry(play.trackhash.as_str())
+            .or_insert_with(|| credits(&play.trackhash))
+        else {
+            continue;
+        };
+
+        let weight = decay_weight(now - play.timestamp);
+        let mut add = |kind, hash: &str| {
+            *totals
+                .entry((play.userid, kind, hash.to_string()))
+                .or_default() += weight;
+        };
+
+        add(PopularityKind::Track, &play.trackhash);
+        add(PopularityKind::Album, &credit.albumhash);
+        for artisthash in &credit.artisthashes {
+            add(PopularityKind::Artist, artisthash);
+        }
+    }
+
+    totals
+        .into_iter()
+        .filter(|(_, score)| *score >= MIN_SCORE)
+        .map(|((userid, kind, hash), score)| PopularityScore {
+            userid,
+            kind: kind.as_str().to_string(),
+            hash,
+            score,
+            updated: now,
+        })
+        .collect()
+}
+
+/// Rebuild the scores of every user from the play history, returns how many were stored
+pub async fn refresh() -> Result<usize> {
+    let plays = ScrobbleTable::history().await?;
+    let now = chrono::Utc::now().timestamp();
+
+    let store = TrackStore::get();
+    let scores = compute_scores(&plays, now, |trackhash| {
+        let track = store.get_by_hash(trackhash)?;
+        // audiobooks stay out of listening stats
+        if audiobooks::is_audiobook(&track) {
+            return None;
+        }
+        Some(PlayCredits {
+            albumhash: track.albumhash,
+            artisthashes: track.artisthashes,
+        })
+    });
+
+    PopularityTable::replace_all(&scores).await?;
+    Ok(scores.len())
+}
+
+/// The items of a kind a user has been playing the most lately
+pub async fn trending(
+    userid: i64,
+    kind: PopularityKind,
+    limit: usize,
+) -> Result<Vec<PopularityScore>> {
+    PopularityTable::top(userid, kind.as_str(), limit).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(userid: i64, trackhash: &str, timestamp: i64) -> ScrobblePoint {
+        ScrobblePoint {
+            userid,
+            trackhash: trackhash.to_string(),
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn test_decay_weight() {
+        let day = 86400;
+        assert!((decay_weight(0) - 1.0).abs() < 1e-9);
+        assert!((decay_weight(14 * day) - 0.5).abs() < 1e-9);
+        assert!((decay_weight(28 * day) - 0.25).abs() < 1e-9);
+        // plays in the future count as now
+        assert!((decay_weight(-day) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_recent_plays_outrank_old_ones() {
+        let now = 1_000 * 86400;
+        let plays = vec![
+            // played a lot two months ago
+            play(1, "old", now - 60 * 86400),
+            play(1, "old", now - 60 * 86400),
+            play(1, "old", now - 61 * 86400),
+            play(1, "old", now - 62 * 86400),
+            // played twice this week
+            play(1, "new", now - 86400),
+            play(1, "new", now - 2 * 86400),
+            play(2, "new", now),
+            play(1, "gone", now),
+        ];
+
+        let scores = compute_scores(&plays, now, |hash| match hash {
+            "gone" => None,
+            _ => Some(PlayCredits {
+                albumhash: format!("album-{}", hash),
+                artisthashes: vec!["artist".to_string()],
+            }),
+        });
+
+        let score = |userid: i64, kind: PopularityKind, hash: &str| {
+            scores
+                .iter()
+                .find(|s| s.userid == userid && s.kind == kind.as_str() && s.hash == hash)
+                .map(|s| s.score)
+        };
+
+        let old = score(1, PopularityKind::Track, "old").unwrap();
+        let new = score(1, PopularityKind::Track, "new").unwrap();
+        assert!(new > old);
+        assert_eq!(score(1, PopularityKind::Album, "album-new"), Some(new));
+        assert!((score(1, PopularityKind::Artist, "artist").unwrap() - (old + new)).abs() < 1e-9);
+        assert_eq!(score(2, PopularityKind::Track, "new"), Some(1.0));
+        assert_eq!(score(1, PopularityKind::Track, "gone"), None);
+    }
+}
@@ -1,12 +1,20 @@
 //! Populate stores from database/index data
 
 use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use tokio::sync::Mutex;
 
 use crate::config::UserConfig;
+use crate::core::indexer::Indexer;
+use crate::core::mapstuff::{
+    map_colors, map_favorites, map_mbids, map_scope, map_scrobble_data, MapScope,
+};
 use crate::core::{AlbumLib, ArtistLib};
 use crate::db::tables::TrackTable;
 use crate::models::Track;
 use crate::stores::{AlbumStore, ArtistStore, FolderStore, TrackStore};
+use crate::utils::filesystem::normalize_path;
 
 /// Populate all in-memory stores from database
 pub async fn populate_stores() -> Result<()> {
@@ -58,6 +66,46 @@ pub fn refresh_with_tracks(new_tracks: Vec<Track>) {
     ArtistStore::get().load(artists);
 }
 
+/// Re-read the tags of files already in the library and update the database and stores
+pub async fn reindex_track_files(paths: &[String]) -> Result<Vec<Track>> {
+    let config = UserConfig::load()?;
+    let indexer = Indexer::from_config(&config).with_progress(false);
+    let files: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+    let mut tracks = indexer.reindex_files(&files)?;
+
+    // Preserve play stats
+    let existing = TrackTable::get_by_filepaths(paths).await?;
+    for track in &mut tracks {
+        if let Some(old) = existing.iter().find(|t| t.filepath == track.filepath) {
+            track.lastplayed = old.lastplayed;
+            track.playcount = old.playcount;
+            track.playduration = old.playduration;
+        }
+    }
+
+    TrackTable::remove_by_filepaths(paths).await?;
+    TrackTable::insert_many(&tracks).await?;
+
+    TrackStore::get().remove_by_paths(paths);
+    refresh_changed_tracks(tracks.clone()).await?;
+
+    Ok(tracks)
+}
+
+/// Swap changed tracks into the stores and rebuild albums and artists around them
+pub async fn refresh_changed_tracks(tracks: Vec<Track>) -> Result<()> {
+    let paths: Vec<String> = tracks.iter().map(|t| t.filepath.clone()).collect();
+    TrackStore::get().remove_by_paths(&paths);
+    refresh_with_tracks(tracks);
+
+    // rebuilt albums and artists lose their mapped data
+    map_favorites().await?;
+    map_colors().await?;
+    map_mbids().await?;
+    map_scrobble_data().await?;
+    Ok(())
+}
+
 /// Remove tracks from stores
 pub fn remove_tracks(paths: &[String]) {
     TrackStore::get().remove_by_paths(paths);
@@ -72,6 +120,151 @@ pub fn remove_tracks(paths: &[String]) {
     ArtistStore::get().load(artists);
 }
 
+/// File changes run one at a time so two bursts can't interleave
+static APPLYING: Mutex<()> = Mutex::const_new(());
+
+/// What applying file changes did to the library
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FileChanges {
+    pub indexed: usize,
+    pub removed: usize,
+}
+
+/// Index changed files and drop removed ones, rebuilding only the albums and
+/// artists they belong to instead of the whole library
+pub async fn apply_file_changes(changed: &[String], removed: &[String]) -> Result<FileChanges> {
+    let _guard = APPLYING.lock().await;
+    let store = TrackStore::get();
+
+    // paths the library doesn't know, like files moved to the trash, are left alone
+    let removed: Vec<String> = removed
+        .iter()
+        .filter(|p| store.path_exists(p))
+        .cloned()
+        .collect();
+
+    let config = UserConfig::load()?;
+    let indexer = Indexer::from_config(&config).with_progress(false);
+    let files: Vec<PathBuf> = changed.iter().map(PathBuf::from).collect();
+    let mut tracks = tokio::task::spawn_blocking(move || indexer.reindex_files(&files)).await??;
+    if tracks.is_empty() && removed.is_empty() {
+        return Ok(FileChanges::default());
+    }
+
+    // Preserve play stats
+    let indexed: Vec<String> = tracks.iter().map(|t| t.filepath.clone()).collect();
+    let existing = TrackTable::get_by_filepaths(&indexed).await?;
+    for track in &mut tracks {
+        if let Some(old) = existing.iter().find(|t| t.filepath == track.filepath) {
+            track.lastplayed = old.lastplayed;
+            track.playcount = old.playcount;
+            track.playduration = old.playduration;
+        }
+    }
+
+    let replaced: Vec<String> = indexed.into_iter().chain(removed.iter().cloned()).collect();
+    TrackTable::remove_by_filepaths(&replaced).await?;
+    TrackTable::insert_many(&tracks).await?;
+
+    // albums and artists of both the old and the new tags need a rebuild
+    let mut scope = MapScope::default();
+    let mut folders = HashSet::new();
+    let old_tracks: Vec<Track> = replaced
+        .iter()
+        .filter_map(|p| store.get_by_path(p))
+        .collect();
+    for track in old_tracks.iter().chain(&tracks) {
+        scope.trackhashes.insert(track.trackhash.clone());
+        scope.albumhashes.insert(track.albumhash.clone());
+        for artist in track.artists.iter().chain(&track.albumartists) {
+            scope.artisthashes.insert(artist.artisthash.clone());
+        }
+        folders.insert(normalize_path(&track.folder));
+    }
+
+    store.remove_by_paths(&replaced);
+    for track in &tracks {
+        store.add(track.clone());
+    }
+    if rebuild_albums(&scope.albumhashes) {
+        scope.albumhashes.extend(AlbumStore::get().get_all_hashes());
+    }
+    rebuild_artists(&scope.artisthashes);
+
+    // rebuilt tracks, albums and artists lose their mapped data
+    map_scope(&scope).await?;
+    FolderStore::get().refresh_folders(&folders);
+
+    Ok(FileChanges {
+        indexed: tracks.len(),
+        removed: removed.len(),
+    })
+}
+
+/// Rebuild albums from their tracks in the store, all of them when merging or
+/// singles grouping can tie an album to others, which is what the result says
+fn rebuild_albums(hashes: &HashSet<String>) -> bool {
+    let track_store = TrackStore::get();
+    let album_store = AlbumStore::get();
+
+    let regroup = {
+        let config = UserConfig::global();
+        let config = config.read();
+        config.merge_albums || (config.group_singles && !config.show_albums_as_singles)
+    };
+    if regroup {
+        album_store.load(AlbumLib::build_albums(&track_store.get_all()));
+        return true;
+    }
+
+    let tracks: Vec<Track> = hashes
+        .iter()
+        .flat_map(|h| track_store.get_by_album(h))
+        .collect();
+    let mut built: HashMap<String, _> = AlbumLib::build_albums(&tracks)
+        .into_iter()
+        .map(|a| (a.albumhash.clone(), a))
+        .collect();
+
+    for hash in hashes {
+        match built.remove(hash) {
+            Some(album) => album_store.update(album),
+            None => album_store.remove(hash),
+        }
+    }
+    false
+}
+
+/// Rebuild artists from their tracks in the store, dropping those left
+/// without tracks
+fn rebuild_artists(hashes: &HashSet<String>) {
+    let track_store = TrackStore::get();
+    let artist_store = ArtistStore::get();
+
+    let mut seen = HashSet::new();
+    let tracks: Vec<Track> = hashes
+        .iter()
+        .flat_map(|h| track_store.get_by_artist(h))
+        .filter(|t| seen.insert(t.trackhash.clone()))
+        .collect();
+    let mut built: HashMap<String, _> = ArtistLib::build_artists(&tracks)
+        .into_iter()
+        .map(|a| (a.artisthash.clone(), a))
+        .collect();
+
+    for hash in hashes {
+        match built.remove(hash) {
+            Some(mut artist) => {
+                if artist.image.is_empty() {
+                    artist.set_image();
+                }
+                artist_store.update(artist);
+            }
+            None => artist_store.remove(hash),
+        }
+    }
+}
+
 /// Clear all stores
 pub fn clear_stores() {
     TrackStore::get().clear();
@@ -87,7 +280,7 @@ pub fn get_stats() -> LibraryStats {
         album_count: AlbumStore::get().count(),
         artist_count: ArtistStore::get().count(),
         total_duration: TrackStore::get()
-            .get_all()
+            .get_all_shared()
             .iter()
             .map(|t| t.duration as i64)
             .sum(),
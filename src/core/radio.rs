@@ -0,0 +1,526 @@
+//! Internet radio streaming
+//!
+//! stations are proxied through the server so clients play them from the same
+//! origin as the library. the upstream is asked for icy metadata, which is cut
+//! out of the audio and kept as the station's now playing info. station urls
+//! and every redirect they take must lead to a public address, so the proxy
+//! can not be pointed at the server's own network. podcast feeds and episodes
+//! are fetched through the same guards.
+
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
+use futures::Stream;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+const USER_AGENT: &str = concat!("SwingMusic/", env!("CARGO_PKG_VERSION"));
+
+/// playlist files followed before giving up on a station url
+const MAX_PLAYLIST_HOPS: usize = 2;
+
+/// largest playlist file read when resolving a station url
+const MAX_PLAYLIST_BYTES: usize = 64 * 1024;
+
+/// redirects followed for one request
+const MAX_REDIRECTS: usize = 5;
+
+/// Whether an address is reachable on the public internet
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                // carrier grade nat
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local())
+            }
+        },
+    }
+}
+
+static NOW_PLAYING: Lazy<RwLock<HashMap<i64, NowPlaying>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// Fields of an icy metadata block
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct IcyMetadata {
+    pub title: Option<String>,
+    pub url: Option<String>,
+}
+
+/// What a station was last seen playing
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct NowPlaying {
+    pub title: Option<String>,
+    pub url: Option<String>,
+    /// station name, genre and bitrate from the icy headers
+    pub name: Option<String>,
+    pub genre: Option<String>,
+    pub bitrate: Option<u32>,
+    pub updated: i64,
+}
+
+/// Now playing info of a station, None until it has been streamed
+pub fn now_playing(station_id: i64) -> Option<NowPlaying> {
+    NOW_PLAYING.read().get(&station_id).cloned()
+}
+
+pub fn forget(station_id: i64) {
+    NOW_PLAYING.write().remove(&station_id);
+}
+
+/// Check that a station url can be fetched by the proxy
+///
+/// host names are checked again when they are resolved
+pub fn validate_url(url: &str) -> Result<reqwest::Url> {
+    let parsed = reqwest::Url::parse(url.trim()).map_err(|_| anyhow!("Invalid stream url"))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        bail!("Stream url must use http or https");
+    }
+
+    let host = parsed.host_str().unwrap_or_default();
+    let public = match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(ip) => is_public(ip),
+        Err(_) => {
+            let host = host.trim_end_matches('.').to_ascii_lowercase();
+            !host.is_empty() && host != "localhost" && !host.ends_with(".localhost")
+        }
+    };
+    if !public {
+        bail!("Stream url must point to a public address");
+    }
+    Ok(parsed)
+}
+
+/// An open upstream station stream
+pub struct RadioStream {
+    response: reqwest::Response,
+    pub content_type: String,
+    /// audio bytes between metadata blocks, None when the station sends none
+    metaint: Option<usize>,
+    info: NowPlaying,
+}
+
+/// Connect to a station, following .pls and .m3u playlists to the stream
+pub async fn open(url: &str) -> Result<RadioStream> {
+    let mut url = validate_url(url)?;
+
+    for _ in 0..=MAX_PLAYLIST_HOPS {
+        let (fetched, response) =
+            fetch_public(url, |request| request.header("Icy-MetaData", "1")).await?;
+        url = fetched;
+
+        let content_type = header(&response, "content-type")
+            .map(|v| v.split(';').next().unwrap_or("").trim().to_lowercase())
+            .unwrap_or_default();
+
+        if !is_playlist(url.path(), &content_type) {
+            let info = NowPlaying {
+                name: header(&response, "icy-name"),
+                genre: header(&response, "icy-genre"),
+                bitrate: header(&response, "icy-br").and_then(|v| v.parse().ok()),
+                updated: chrono::Utc::now().timestamp(),
+                ..Default::default()
+            };
+            let metaint = header(&response, "icy-metaint")
+                .and_then(|v| v.parse().ok())
+                .filter(|&n: &usize| n > 0);
+            let content_type = if content_type.starts_with("audio/") {
+                content_type
+            } else {
+                "audio/mpeg".to_string()
+            };
+
+            return Ok(RadioStream {
+                response,
+                content_type,
+                metaint,
+                info,
+            });
+        }
+
+        let body = read_limited(response, MAX_PLAYLIST_BYTES).await?;
+        let body = String::from_utf8_lossy(&body);
+        if body.contains("#EXT-X-") {
+            bail!("HLS streams are not supported");
+        }
+        let entry = playlist_entry(&body).ok_or_else(|| anyhow!("Playlist has no stream"))?;
+        url = validate_url(&entry)?;
+    }
+
+    bail!("Too many nested playlists")
+}
+
+/// Request a url, following redirects to public addresses only
+///
+/// `prepare` sets headers or a timeout on each request of the chain, returns
+/// the url the response came from with the response
+pub async fn fetch_public(
+    url: reqwest::Url,
+    prepare: impl Fn(reqwest::RequestBuilder) -> reqwest::RequestBuilder,
+) -> Result<(reqwest::Url, reqwest::Response)> {
+    let mut url = validate_url(url.as_str())?;
+    for _ in 0..=MAX_REDIRECTS {
+        let response = prepare(connect(&url).await?).send().await?;
+        if !response.status().is_redirection() {
+            return Ok((url, response.error_for_status()?));
+        }
+
+        let location =
+            header(&response, "location").ok_or_else(|| anyhow!("Redirect has no location"))?;
+        url = redirect_target(&url, &location)?;
+    }
+
+    bail!("Too many redirects")
+}
+
+/// Where a redirect leads, refused unless it is a public address
+fn redirect_target(url: &reqwest::Url, location: &str) -> Result<reqwest::Url> {
+    let next = url
+        .join(location)
+        .map_err(|_| anyhow!("Invalid redirect location"))?;
+    validate_url(next.as_str())
+}
+
+/// Build the request pinned to the public addresses the host resolved to, so
+/// a second lookup can not swap in a private one
+async fn connect(url: &reqwest::Url) -> Result<reqwest::RequestBuilder> {
+    let host = url.host_str().unwrap_or_default();
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs = public_addrs(host, port).await?;
+
+    // no overall timeout, the stream stays open for as long as the client listens
+    let client = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(Duration::from_secs(10))
+        .redirect(reqwest::redirect::Policy::none())
+        .resolve_to_addrs(host, &addrs)
+        .build()?;
+    Ok(client.get(url.clone()))
+}
+
+/// Addresses of a host that are public, an error when it has none
+async fn public_addrs(host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await?
+        .filter(|addr| is_public(addr.ip()))
+        .collect();
+    if addrs.is_empty() {
+        bail!("Stream url must point to a public address");
+    }
+    Ok(addrs)
+}
+
+/// Audio of a station with the metadata removed
+///
+/// every metadata block updates the station's now playing info
+pub fn audio_stream(
+    station_id: i64,
+    stream: RadioStream,
+) -> impl Stream<Item = Result<Bytes, reqwest::Error>> {
+    NOW_PLAYING.write().insert(station_id, stream.info);
+
+    let demuxer = stream.metaint.map(IcyDemuxer::new);
+    futures::stream::unfold(
+        (stream.response, demuxer, false),
+        move |(mut response, mut demuxer, done)| async move {
+            if done {
+                return None;
+            }
+
+            loop {
+                let chunk = match response.chunk().await {
+                    Ok(Some(chunk)) => chunk,
+                    Ok(None) => return None,
+                    Err(e) => return Some((Err(e), (response, demuxer, true))),
+                };
+
+                let Some(demux) = demuxer.as_mut() else {
+                    return Some((Ok(chunk), (response, demuxer, false)));
+                };
+
+                let mut audio = Vec::with_capacity(chunk.len());
+                if let Some(meta) = demux.push(&chunk, &mut audio) {
+                    update_now_playing(station_id, meta);
+                }
+                if !audio.is_empty() {
+                    return Some((Ok(Bytes::from(audio)), (response, demuxer, false)));
+                }
+            }
+        },
+    )
+}
+
+fn update_now_playing(station_id: i64, meta: IcyMetadata) {
+    let mut now_playing = NOW_PLAYING.write();
+    let entry = now_playing.entry(station_id).or_default();
+    entry.title = meta.title;
+    entry.url = meta.url;
+    entry.updated = chrono::Utc::now().timestamp();
+}
+
+fn header(response: &reqwest::Response, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+fn is_playlist(path: &str, content_type: &str) -> bool {
+    let path = path.to_lowercase();
+    path.ends_with(".pls")
+        || path.ends_with(".m3u")
+        || path.ends_with(".m3u8")
+        || matches!(
+            content_type,
+            "audio/x-scpls"
+                | "audio/scpls"
+                | "audio/x-mpegurl"
+                | "audio/mpegurl"
+                | "application/vnd.apple.mpegurl"
+                | "application/x-mpegurl"
+        )
+}
+
+async fn read_limited(mut response: reqwest::Response, limit: usize) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() > limit {
+            bail!("Playlist is too large");
+        }
+    }
+    Ok(body)
+}
+
+/// First stream url of a .pls or .m3u playlist
+fn playlist_entry(body: &str) -> Option<String> {
+    body.lines().map(str::trim).find_map(|line| {
+        // pls entries are "File1=http://..."
+        let value = match line.split_once('=') {
+            Some((key, value)) if key.to_lowercase().starts_with("file") => value.trim(),
+            _ if line.starts_with('#') || line.starts_with('[') => return None,
+            _ => line,
+        };
+        let lower = value.to_lowercase();
+        (lower.starts_with("http://") || lower.starts_with("https://")).then(|| value.to_string())
+    })
+}
+
+/// Parse a metadata block such as `StreamTitle='Artist - Title';StreamUrl='';`
+pub fn parse_metadata(block: &[u8]) -> IcyMetadata {
+    let end = block.iter().position(|&b| b == 0).unwrap_or(block.len());
+    let text = match std::str::from_utf8(&block[..end]) {
+        Ok(text) => text.to_string(),
+        // older servers send latin-1
+        Err(_) => block[..end].iter().map(|&b| b as char).collect(),
+    };
+
+    let field = |key: &str| {
+        let start = text.find(&format!("{}='", key))? + key.len() + 2;
+        let rest = &text[start..];
+        // values may contain quotes, so a field only ends at "';"
+        let value = match rest.find("';") {
+            Some(stop) => &rest[..stop],
+            None => rest.strip_suffix('\'').unwrap_or(rest),
+        };
+        let value = value.trim();
+        (!value.is_empty()).then(|| value.to_string())
+    };
+
+    IcyMetadata {
+        title: field("StreamTitle"),
+        url: field("StreamUrl"),
+    }
+}
+
+/// Splits an icy stream into audio and metadata blocks
+///
+/// after every `metaint` audio bytes comes a length byte, the metadata block is
+/// sixteen times that many bytes long.
+pub struct IcyDemuxer {
+    metaint: usize,
+    state: DemuxState,
+    metadata: Vec<u8>,
+}
+
+enum DemuxState {
+    Audio(usize),
+    Length,
+    Metadata(usize),
+}
+
+impl IcyDemuxer {
+    pub fn new(metaint: usize) -> Self {
+        Self {
+            metaint,
+            state: DemuxState::Audio(metaint),
+            metadata: Vec::new(),
+        }
+    }
+
+    /// Append the audio of `input` to `audio`, returns the last metadata block it completed
+    pub fn push(&mut self, mut input: &[u8], audio: &mut Vec<u8>) -> Option<IcyMetadata> {
+        let mut latest = None;
+
+        while !input.is_empty() {
+            match self.state {
+                DemuxState::Audio(remaining) => {
+                    let n = remaining.min(input.len());
+                    audio.extend_from_slice(&input[..n]);
+                    input = &input[n..];
+                    self.state = if n == remaining {
+                        DemuxState::Length
+                    } else {
+                        DemuxState::Audio(remaining - n)
+                    };
+                }
+                DemuxState::Length => {
+                    let len = input[0] as usize * 16;
+                    input = &input[1..];
+                    self.state = if len == 0 {
+                        DemuxState::Audio(self.metaint)
+                    } else {
+                        DemuxState::Metadata(len)
+                    };
+                }
+                DemuxState::Metadata(remaining) => {
+                    let n = remaining.min(input.len());
+                    self.metadata.extend_from_slice(&input[..n]);
+                    input = &input[n..];
+                    if n == remaining {
+                        latest = Some(parse_metadata(&self.metadata));
+                        self.metadata.clear();
+                        self.state = DemuxState::Audio(self.metaint);
+                    } else {
+                        self.state = DemuxState::Metadata(remaining - n);
+                    }
+                }
+            }
+        }
+
+        latest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_url_rejects_private_hosts() {
+        assert!(validate_url("https://stream.example.com/live.mp3").is_ok());
+        assert!(validate_url("http://93.184.216.34:8000/").is_ok());
+
+        for url in [
+            "ftp://stream.example.com/live.mp3",
+            "http://127.0.0.1:1970/api",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://192.168.1.10/",
+            "http://10.0.0.1/",
+            "http://100.64.0.1/",
+            "http://0.0.0.0/",
+            "http://[::1]/",
+            "http://[fd00::1]/",
+            "http://[fe80::1]/",
+            "http://[::ffff:127.0.0.1]/",
+            "http://localhost:1970/",
+            "http://radio.localhost/",
+        ] {
+            assert!(validate_url(url).is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_redirects_to_private_addresses_are_refused() {
+        let url = validate_url("https://stream.example.com/live").unwrap();
+        assert_eq!(
+            redirect_target(&url, "/other.mp3").unwrap().as_str(),
+            "https://stream.example.com/other.mp3"
+        );
+        assert!(redirect_target(&url, "http://169.254.169.254/latest/meta-data/").is_err());
+        assert!(redirect_target(&url, "http://127.0.0.1:1970/api").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_public_addrs_drops_private_addresses() {
+        assert!(public_addrs("localhost", 80).await.is_err());
+        assert!(public_addrs("[::1]", 80).await.is_err());
+        assert_eq!(
+            public_addrs("93.184.216.34", 80).await.unwrap(),
+            vec![SocketAddr::from(([93, 184, 216, 34], 80))]
+        );
+    }
+
+    #[test]
+    fn test_parse_metadata() {
+        let mut block = b"StreamTitle='Guns N' Roses - Don't Cry';StreamUrl='';".to_vec();
+        block.resize(64, 0);
+
+        let meta = parse_metadata(&block);
+        assert_eq!(meta.title.as_deref(), Some("Guns N' Roses - Don't Cry"));
+        assert_eq!(meta.url, None);
+    }
+
+    #[test]
+    fn test_demuxer_strips_metadata_across_chunks() {
+        let mut meta = b"StreamTitle='Song';".to_vec();
+        meta.resize(32, 0);
+
+        let mut stream = b"abcd".to_vec();
+        stream.push(2);
+        stream.extend_from_slice(&meta);
+        stream.extend_from_slice(b"efgh");
+        stream.push(0);
+        stream.extend_from_slice(b"ij");
+
+        let mut demuxer = IcyDemuxer::new(4);
+        let mut audio = Vec::new();
+        let mut titles = Vec::new();
+        for chunk in stream.chunks(3) {
+            if let Some(meta) = demuxer.push(chunk, &mut audio) {
+                titles.push(meta.title);
+            }
+        }
+
+        assert_eq!(audio, b"abcdefghij");
+        assert_eq!(titles, vec![Some("Song".to_string())]);
+    }
+
+    #[test]
+    fn test_playlist_entry() {
+        let pls = "[playlist]\nNumberOfEntries=1\nFile1=http://radio.example/live\nTitle1=Live\n";
+        assert_eq!(
+            playlist_entry(pls).as_deref(),
+            Some("http://radio.example/live")
+        );
+
+        let m3u = "#EXTM3U\n#EXTINF:-1,Live\nhttps://radio.example/stream.mp3\n";
+        assert_eq!(
+            playlist_entry(m3u).as_deref(),
+            Some("https://radio.example/stream.mp3")
+        );
+    }
+}
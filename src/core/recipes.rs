@@ -1,12 +1,27 @@
 //! Recipe system for generating mixes
 
+use chrono::{Datelike, Local, NaiveDate};
+use once_cell::sync::Lazy;
 use rand::seq::SliceRandom;
-use std::collections::{HashMap, HashSet};
-
-use crate::db::tables::ScrobbleTable;
-use crate::models::Track;
-use crate::stores::{ArtistStore, TrackStore};
+use rand::Rng;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+
+use crate::config::{MixSettings, UserConfig};
+use crate::core::{audiobooks, devices};
+use crate::core::colorlib::ColorLib;
+use crate::core::images::{thumbnail_path, ThumbnailFormat};
+use crate::db::tables::{
+    CollectionRow, CollectionTable, FavoriteTable, MixTable, PlaylistTable, ScrobbleTable,
+    SimilarArtistTable, UserTable,
+};
+use crate::models::{
+    Album, CollectionItem, ColorVariants, FavoriteType, GenreRef, MixSourceType, Track,
+};
+use crate::stores::{AlbumStore, ArtistStore, PlayStatsStore, TrackStore};
 use crate::utils::dates::get_timestamp_days_ago;
+use crate::utils::hashing::create_hash;
 
 /// Mix/Recipe result
 #[derive(Debug, Clone)]
@@ -28,6 +43,70 @@ pub struct ArtistStats {
     pub duration: i64,
 }
 
+/// Seed a daily mix is built around
+enum DailyMixSeed {
+    Artist(String),
+    Genre(GenreRef),
+}
+
+impl DailyMixSeed {
+    fn hash(&self) -> &str {
+        match self {
+            DailyMixSeed::Artist(hash) => hash,
+            DailyMixSeed::Genre(genre) => &genre.genrehash,
+        }
+    }
+}
+
+/// Mix settings and the tracks they rule out for a user
+struct MixOptions {
+    settings: MixSettings,
+    recently_played: HashSet<String>,
+}
+
+impl MixOptions {
+    async fn load(user_id: i64) -> Self {
+        let settings = UserConfig::load().unwrap_or_default().mixes.normalized();
+
+        let mut recently_played = HashSet::new();
+        if settings.exclude_recent_hours > 0 {
+            let end = chrono::Utc::now().timestamp();
+            let start = end - settings.exclude_recent_hours as i64 * 3600;
+            let scrobbles = ScrobbleTable::get_in_range(user_id, start, end)
+                .await
+                .unwrap_or_default();
+            recently_played.extend(scrobbles.into_iter().map(|s| s.trackhash));
+        }
+
+        Self {
+            settings,
+            recently_played,
+        }
+    }
+
+    /// Whether a track may be added to a mix
+    fn allows(&self, track: &Track) -> bool {
+        (self.settings.allow_explicit || !track.explicit)
+            && !self.recently_played.contains(&track.trackhash)
+            && !audiobooks::is_audiobook(track)
+    }
+}
+
+/// Daily mixes made for a user each day
+pub const DAILY_MIX_COUNT: usize = 6;
+
+/// Days daily mixes stay in the history, saved ones are kept for good
+pub const DAILY_MIX_HISTORY_DAYS: i64 = 30;
+
+/// Mix ID prefix shared by all daily mixes
+pub const DAILY_MIX_PREFIX: &str = "d";
+
+/// Mix ID prefix shared by all artist radio mixes
+pub const ARTIST_MIX_PREFIX: &str = "a";
+
+/// serializes daily mix generation so concurrent homepage loads store one set
+static DAILY_MIX_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
 /// Recipe generators
 pub struct Recipes;
 
@@ -54,9 +133,13 @@ impl Recipes {
 
     /// Get recently added tracks
     pub fn recently_added(limit: usize) -> Vec<Track> {
-        let mut tracks = TrackStore::get().get_all();
+        let mut tracks = TrackStore::get().get_all_shared();
         tracks.sort_by(|a, b| b.last_mod.cmp(&a.last_mod));
-        tracks.into_iter().take(limit).collect()
+        tracks
+            .iter()
+            .take(limit)
+            .map(|t| t.as_ref().clone())
+            .collect()
     }
 
     /// Get top streamed tracks
@@ -84,11 +167,16 @@ impl Recipes {
     }
 
     /// "Because you listened to" mix
+    ///
+    /// tracks by similar artists (from the Last.fm similar artists table) are
+    /// picked with a probability weighted by artist similarity, remaining slots
+    /// are filled with tracks sharing a genre with the artist.
     pub async fn because_you_listened_to(artist_hash: &str, limit: usize) -> Option<Mix> {
         let artist = ArtistStore::get().get_by_hash(artist_hash)?;
 
         // Get tracks from this artist
-        let artist_tracks = TrackStore::get().get_by_artist(artist_hash);
+        let mut artist_tracks = TrackStore::get().get_by_artist(artist_hash);
+        artist_tracks.retain(|t| !audiobooks::is_audiobook(t));
 
         if artist_tracks.is_empty() {
             return None;
@@ -102,17 +190,46 @@ impl Recipes {
             }
         }
 
-        let all_tracks = TrackStore::get().get_all();
-        let mut similar_tracks: Vec<Track> = all_tracks
-            .into_iter()
-            .filter(|t| {
-                !t.artisthashes.contains(&artist_hash.to_string())
-                    && t.genrehashes.iter().any(|g| genre_hashes.contains(g))
-            })
-            .collect();
+        // similarity weight per artist, a missing weight still counts as similar
+        let similar_weights: HashMap<String, f64> =
+            SimilarArtistTable::get_similar_full(artist_hash)
+                .await
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|s| s.artisthash != artist_hash)
+                .map(|s| {
+                    let weight = if s.weight > 0.0 { s.weight } else { 1.0 };
+                    (s.artisthash, weight)
+                })
+                .collect();
+
+        let mut weighted: Vec<(Track, f64)> = Vec::new();
+        let mut genre_tracks: Vec<Track> = Vec::new();
+        for track in TrackStore::get().get_all_shared() {
+            if audiobooks::is_audiobook(&track)
+                || track.artisthashes.iter().any(|h| h == artist_hash)
+            {
+                continue;
+            }
+
+            let similarity = track
+                .artisthashes
+                .iter()
+                .filter_map(|h| similar_weights.get(h))
+                .fold(0.0_f64, |max, w| max.max(*w));
+
+            if similarity > 0.0 {
+                weighted.push((track.as_ref().clone(), similarity));
+            } else if track.genrehashes.iter().any(|g| genre_hashes.contains(g)) {
+                genre_tracks.push(track.as_ref().clone());
+            }
+        }
 
-        similar_tracks.shuffle(&mut rand::thread_rng());
-        similar_tracks.truncate(limit);
+        let mut similar_tracks = Self::weighted_sample(weighted, limit);
+        if similar_tracks.len() < limit {
+            genre_tracks.shuffle(&mut rand::thread_rng());
+            similar_tracks.extend(genre_tracks.into_iter().take(limit - similar_tracks.len()));
+        }
 
         Some(Mix {
             id: format!("because-{}", artist_hash),
@@ -123,11 +240,31 @@ impl Recipes {
         })
     }
 
+    /// Sample up to `limit` tracks without replacement, favouring higher weights
+    ///
+    /// each track gets the key `u^(1/weight)` for a random `u` in (0, 1] and the
+    /// tracks with the largest keys are kept.
+    fn weighted_sample(candidates: Vec<(Track, f64)>, limit: usize) -> Vec<Track> {
+        let mut rng = rand::thread_rng();
+        let mut keyed: Vec<(f64, Track)> = candidates
+            .into_iter()
+            .filter(|(_, weight)| *weight > 0.0 && weight.is_finite())
+            .map(|(track, weight)| {
+                let u: f64 = 1.0 - rng.gen::<f64>();
+                (u.powf(1.0 / weight), track)
+            })
+            .collect();
+
+        keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+        keyed.into_iter().take(limit).map(|(_, t)| t).collect()
+    }
+
     /// Artist mix - deep dive into an artist
     pub fn artist_mix(artist_hash: &str, limit: usize) -> Option<Mix> {
         let artist = ArtistStore::get().get_by_hash(artist_hash)?;
 
         let mut tracks = TrackStore::get().get_by_artist(artist_hash);
+        tracks.retain(|t| !audiobooks::is_audiobook(t));
         tracks.shuffle(&mut rand::thread_rng());
         tracks.truncate(limit);
 
@@ -149,12 +286,13 @@ impl Recipes {
         let genre_lower = genre.to_lowercase();
         let genre_hash = crate::utils::hashing::create_hash(&[genre], true);
 
-        let all_tracks = TrackStore::get().get_all();
-        let mut tracks: Vec<Track> = all_tracks
+        let mut tracks: Vec<Arc<Track>> = TrackStore::get()
+            .get_all_shared()
             .into_iter()
             .filter(|t| {
-                t.genrehashes.contains(&genre_hash)
-                    || t.genre().to_lowercase().contains(&genre_lower)
+                (t.genrehashes.contains(&genre_hash)
+                    || t.genre().to_lowercase().contains(&genre_lower))
+                    && !audiobooks::is_audiobook(t)
             })
             .collect();
 
@@ -164,6 +302,7 @@ impl Recipes {
 
         tracks.shuffle(&mut rand::thread_rng());
         tracks.truncate(limit);
+        let tracks: Vec<Track> = tracks.iter().map(|t| t.as_ref().clone()).collect();
 
         Some(Mix {
             id: format!("genre-{}", genre_hash),
@@ -179,11 +318,11 @@ impl Recipes {
         let start_year = decade;
         let end_year = decade + 9;
 
-        let all_tracks = TrackStore::get().get_all();
-        let mut tracks: Vec<Track> = all_tracks
+        let mut tracks: Vec<Arc<Track>> = TrackStore::get()
+            .get_all_shared()
             .into_iter()
             .filter(|t| {
-                if t.date == 0 {
+                if t.date == 0 || audiobooks::is_audiobook(t) {
                     return false;
                 }
                 let year = chrono::DateTime::from_timestamp(t.date, 0)
@@ -199,6 +338,7 @@ impl Recipes {
 
         tracks.shuffle(&mut rand::thread_rng());
         tracks.truncate(limit);
+        let tracks: Vec<Track> = tracks.iter().map(|t| t.as_ref().clone()).collect();
 
         Some(Mix {
             id: format!("decade-{}", decade),
@@ -211,9 +351,11 @@ impl Recipes {
 
     /// Random mix
     pub fn random_mix(limit: usize) -> Mix {
-        let mut tracks = TrackStore::get().get_all();
+        let mut tracks = TrackStore::get().get_all_shared();
+        tracks.retain(|t| !audiobooks::is_audiobook(t));
         tracks.shuffle(&mut rand::thread_rng());
         tracks.truncate(limit);
+        let tracks: Vec<Track> = tracks.iter().map(|t| t.as_ref().clone()).collect();
 
         Mix {
             id: "random".to_string(),
@@ -272,7 +414,8 @@ impl Recipes {
         let start = get_timestamp_days_ago(days);
         let end = chrono::Utc::now().timestamp();
 
-        let scrobbles = ScrobbleTable::get_in_range(user_id, start, end)
+        let devices = devices::mix_filter(user_id).await;
+        let scrobbles = ScrobbleTable::get_in_range_on(user_id, start, end, &devices)
             .await
             .unwrap_or_default();
 
@@ -281,7 +424,10 @@ impl Recipes {
         let track_store = TrackStore::get();
 
         for scrobble in scrobbles {
-            if let Some(track) = track_store.get_by_hash(&scrobble.trackhash) {
+            let track = track_store
+                .get_by_hash(&scrobble.trackhash)
+                .filter(|t| !audiobooks::is_audiobook(t));
+            if let Some(track) = track {
                 for artisthash in &track.artisthashes {
                     let entry = artist_stats.entry(artisthash.clone()).or_insert((0, 0));
                     entry.0 += 1;
@@ -323,60 +469,202 @@ impl Recipes {
     /// Generate artist mixes for homepage based on listening history
     pub async fn generate_artist_mixes(limit: usize, user_id: i64) -> Vec<crate::models::Mix> {
         let mut mixes = Vec::new();
+        let options = MixOptions::load(user_id).await;
 
         // get top artists from recent listening
         let top = Self::top_artists_in_period(30, limit * 2, user_id).await;
 
         for stats in top.into_iter().take(limit) {
-            if let Some(artist) = ArtistStore::get().get_by_hash(&stats.artisthash) {
-                let mut tracks = TrackStore::get().get_by_artist(&stats.artisthash);
-                if tracks.is_empty() {
-                    continue;
-                }
+            if let Some(mix) = Self::artist_radio(&stats.artisthash, &options) {
+                mixes.push(mix);
+            }
+        }
 
-                tracks.shuffle(&mut rand::thread_rng());
-                tracks.truncate(40);
+        mixes
+    }
 
-                // build mix
-                let mix = crate::models::Mix::new(
-                    format!("a{}", stats.artisthash),
-                    format!("{} Radio", artist.name),
-                    Self::build_mix_description(&tracks, &stats.artisthash),
-                    tracks.iter().map(|t| t.trackhash.clone()).collect(),
-                    stats.artisthash.clone(),
-                    0,
-                );
+    /// Radio mix of an artist's tracks
+    fn artist_radio(artisthash: &str, options: &MixOptions) -> Option<crate::models::Mix> {
+        let artist = ArtistStore::get().get_by_hash(artisthash)?;
+        let mut tracks = TrackStore::get().get_by_artist(artisthash);
+        tracks.retain(|t| options.allows(t));
+        if tracks.is_empty() {
+            return None;
+        }
 
-                mixes.push(mix);
+        tracks.shuffle(&mut rand::thread_rng());
+        tracks.truncate(options.settings.artist_mix_tracks);
+
+        let mut mix = crate::models::Mix::new(
+            format!("{}{}", ARTIST_MIX_PREFIX, artisthash),
+            format!("{} Radio", artist.name),
+            Self::build_mix_description(&tracks, artisthash),
+            tracks.iter().map(|t| t.trackhash.clone()).collect(),
+            artisthash.to_string(),
+            0,
+        );
+
+        let (color, variants) = Self::mix_colors(&mix);
+        mix.set_color(color, variants);
+        Some(mix)
+    }
+
+    /// A stored mix of a user, artist radio IDs from the homepage are stored
+    /// on first use so their tracks stay put between requests
+    pub async fn resolve_mix(
+        mixid: &str,
+        user_id: i64,
+    ) -> anyhow::Result<Option<crate::models::Mix>> {
+        if let Some(mix) = MixTable::get_by_mixid(mixid, user_id).await? {
+            return Ok(Some(mix));
+        }
+
+        let Some(artisthash) = mixid.strip_prefix(ARTIST_MIX_PREFIX) else {
+            return Ok(None);
+        };
+        if let Some(mix) = MixTable::get_artist_mix(artisthash, user_id).await? {
+            return Ok(Some(mix));
+        }
+
+        let options = MixOptions::load(user_id).await;
+        let Some(mut mix) = Self::artist_radio(artisthash, &options) else {
+            return Ok(None);
+        };
+        // mix IDs are unique across users
+        mix.mixid = format!("{}{}-{}", ARTIST_MIX_PREFIX, artisthash, user_id);
+        mix.userid = user_id;
+        mix.id = MixTable::insert(&mix).await?;
+        Ok(Some(mix))
+    }
+
+    /// Give a stored mix fresh tracks from the artist or genre it was made
+    /// from, `None` for mixes that have no source to draw from again
+    pub async fn regenerate_mix(
+        mix: &crate::models::Mix,
+        user_id: i64,
+    ) -> anyhow::Result<Option<crate::models::Mix>> {
+        let options = MixOptions::load(user_id).await;
+
+        let fresh = if mix.mixid.starts_with(ARTIST_MIX_PREFIX) {
+            Self::artist_radio(&mix.sourcehash, &options)
+        } else if mix.mixid.starts_with(DAILY_MIX_PREFIX) {
+            let all_tracks: Vec<Track> = TrackStore::get()
+                .get_all()
+                .into_iter()
+                .filter(|t| options.allows(t))
+                .collect();
+            // daily mixes are seeded by an artist or a genre
+            if ArtistStore::get().get_by_hash(&mix.sourcehash).is_some() {
+                Self::artist_daily_mix(&mix.sourcehash, 1, &all_tracks, &options)
+            } else {
+                all_tracks
+                    .iter()
+                    .flat_map(|t| t.genres.iter())
+                    .find(|g| g.genrehash == mix.sourcehash)
+                    .cloned()
+                    .and_then(|genre| Self::genre_daily_mix(&genre, 1, &all_tracks, &options))
             }
+        } else {
+            None
+        };
+        let Some(fresh) = fresh else {
+            return Ok(None);
+        };
+
+        // the mix keeps its ID, title, saved state and extra fields
+        let mut regenerated = mix.clone();
+        regenerated.description = fresh.description;
+        regenerated.trackhashes = fresh.trackhashes;
+        regenerated.images = fresh.images;
+        regenerated.timestamp = chrono::Utc::now().timestamp();
+        if let Some(extra) = regenerated.extra.as_object_mut() {
+            extra.remove("color");
         }
+        let (color, variants) = Self::mix_colors(&regenerated);
+        regenerated.set_color(color, variants);
 
-        mixes
+        MixTable::update(&regenerated).await?;
+        Ok(Some(regenerated))
+    }
+
+    /// Collage images of a mix, its stored images or the first albums in it
+    pub fn mix_images(mix: &crate::models::Mix) -> Vec<String> {
+        if !mix.images.is_empty() {
+            return mix.images.iter().take(4).cloned().collect();
+        }
+
+        let mut albums: Vec<String> = Vec::new();
+        for track in TrackStore::get().get_by_hashes(&mix.trackhashes) {
+            if !albums.contains(&track.albumhash) {
+                albums.push(track.albumhash.clone());
+                if albums.len() == 4 {
+                    break;
+                }
+            }
+        }
+        albums.iter().map(|h| format!("{}.webp", h)).collect()
+    }
+
+    /// Dominant color of a mix collage with its dark and light mode variants
+    ///
+    /// variants cached alongside the color are reused, otherwise they are
+    /// derived from the collage color.
+    pub fn mix_colors(mix: &crate::models::Mix) -> (String, ColorVariants) {
+        let color = Self::mix_color(mix);
+        let variants = match mix.color_variants() {
+            Some(variants) if mix.color() == Some(color.as_str()) => variants,
+            _ => ColorLib::variants(&color),
+        };
+        (color, variants)
+    }
+
+    /// Dominant color of a mix collage
+    ///
+    /// uses the cached color when present, otherwise samples the small album
+    /// thumbnails of the collage images (or the first albums in the mix).
+    pub fn mix_color(mix: &crate::models::Mix) -> String {
+        if let Some(color) = mix.color() {
+            return color.to_string();
+        }
+
+        let tiles: Vec<std::path::PathBuf> = Self::mix_images(mix)
+            .iter()
+            .map(|img| img.split('?').next().unwrap_or(img))
+            .map(|img| img.trim_end_matches(".webp"))
+            .filter_map(|albumhash| thumbnail_path(albumhash, "small", ThumbnailFormat::WebP))
+            .collect();
+
+        ColorLib::extract_from_collage(&tiles).unwrap_or_default()
     }
 
     /// Generate daily mixes (spotify-style) based on listening history
     /// starts working with just 1 day of activity
+    ///
+    /// favorited artists and albums add to the play counts of their artists,
+    /// so users who favorite more than they listen still get seeded mixes.
+    ///
+    /// users without usable history get cold-start mixes instead, see
+    /// [`Recipes::cold_start_seeds`].
     pub async fn generate_daily_mixes(max_mixes: usize, user_id: i64) -> Vec<crate::models::Mix> {
-        let mut mixes = Vec::new();
-
         // get scrobbles from the last 30 days (works with minimal data)
         let start = get_timestamp_days_ago(30);
         let end = chrono::Utc::now().timestamp();
 
-        let scrobbles = ScrobbleTable::get_in_range(user_id, start, end)
+        // plays on devices kept out of mixes do not seed them
+        let devices = devices::mix_filter(user_id).await;
+        let scrobbles = ScrobbleTable::get_in_range_on(user_id, start, end, &devices)
             .await
             .unwrap_or_default();
 
-        if scrobbles.is_empty() {
-            return mixes;
-        }
-
         // build artist play counts
         let mut artist_play_counts: HashMap<String, i32> = HashMap::new();
         let track_store = TrackStore::get();
 
         for scrobble in &scrobbles {
-            if let Some(track) = track_store.get_by_hash(&scrobble.trackhash) {
+            let track = track_store
+                .get_by_hash(&scrobble.trackhash)
+                .filter(|t| !audiobooks::is_audiobook(t));
+            if let Some(track) = track {
                 // count primary artist (first in list)
                 if let Some(primary_artist) = track.artisthashes.first() {
                     *artist_play_counts.entry(primary_artist.clone()).or_insert(0) += 1;
@@ -384,108 +672,404 @@ impl Recipes {
             }
         }
 
-        // sort artists by play count
-        let mut sorted_artists: Vec<_> = artist_play_counts.into_iter().collect();
-        sorted_artists.sort_by(|a, b| b.1.cmp(&a.1));
+        let options = MixOptions::load(user_id).await;
+        let favorite_artists = if options.settings.favorite_weight > 0.0 {
+            Self::favorited_artists(user_id).await
+        } else {
+            Vec::new()
+        };
 
         // take top artists as seeds for daily mixes
-        let seed_artists: Vec<String> = sorted_artists
+        let seed_artists: VecDeque<DailyMixSeed> = Self::rank_seed_artists(
+            &artist_play_counts,
+            &favorite_artists,
+            options.settings.favorite_weight,
+        )
+        .into_iter()
+        .take(max_mixes * 2)
+        .map(DailyMixSeed::Artist)
+        .collect();
+
+        let all_tracks: Vec<Track> = track_store
+            .get_all()
             .into_iter()
-            .take(max_mixes * 2)
-            .map(|(hash, _)| hash)
+            .filter(|t| options.allows(t))
             .collect();
+        let mixes =
+            Self::daily_mixes_from_seeds(vec![seed_artists], max_mixes, &all_tracks, &options);
+        if !mixes.is_empty() {
+            return mixes;
+        }
 
-        let mut used_artists: HashSet<String> = HashSet::new();
-        let mut mix_number = 1;
+        let seeds = Self::cold_start_seeds(max_mixes, user_id, &all_tracks).await;
+        Self::daily_mixes_from_seeds(seeds, max_mixes, &all_tracks, &options)
+    }
 
-        for seed_artisthash in seed_artists {
-            if mixes.len() >= max_mixes {
-                break;
+    /// Today's daily mixes of a user, generated and stored on first use
+    ///
+    /// the stored mixes are a snapshot, they keep their tracks for the whole
+    /// day instead of being reshuffled on every request
+    pub async fn daily_mixes(user_id: i64) -> anyhow::Result<Vec<crate::models::Mix>> {
+        Self::ensure_daily_mixes(user_id, Local::now().date_naive()).await
+    }
+
+    /// Mix ID prefix of the daily mixes a user got on a day
+    pub fn daily_mix_prefix(user_id: i64, day: NaiveDate) -> String {
+        format!("{}{}-{}-", DAILY_MIX_PREFIX, user_id, day.format("%Y%m%d"))
+    }
+
+    async fn ensure_daily_mixes(
+        user_id: i64,
+        day: NaiveDate,
+    ) -> anyhow::Result<Vec<crate::models::Mix>> {
+        let _guard = DAILY_MIX_LOCK.lock().await;
+
+        let prefix = Self::daily_mix_prefix(user_id, day);
+        let stored = MixTable::get_by_prefix(user_id, &prefix).await?;
+        if !stored.is_empty() {
+            return Ok(stored);
+        }
+
+        let mut mixes = Self::generate_daily_mixes(DAILY_MIX_COUNT, user_id).await;
+        for (index, mix) in mixes.iter_mut().enumerate() {
+            mix.mixid = format!("{}{}", prefix, index + 1);
+            mix.userid = user_id;
+            if let Some(extra) = mix.extra.as_object_mut() {
+                extra.insert("day".to_string(), day.to_string().into());
             }
+            mix.id = MixTable::insert(mix).await?;
+        }
+        Ok(mixes)
+    }
+
+    /// Make today's daily mixes for every user that has none yet and drop
+    /// unsaved mixes older than the history, returns the number of users that
+    /// got new mixes
+    pub async fn refresh_daily_mixes() -> anyhow::Result<usize> {
+        let today = Local::now().date_naive();
+        let mut refreshed = 0;
 
-            // skip if we already used this artist as a seed
-            if used_artists.contains(&seed_artisthash) {
+        for user in UserTable::all().await? {
+            let prefix = Self::daily_mix_prefix(user.id, today);
+            if !MixTable::get_by_prefix(user.id, &prefix).await?.is_empty() {
                 continue;
             }
-            used_artists.insert(seed_artisthash.clone());
+            if !Self::ensure_daily_mixes(user.id, today).await?.is_empty() {
+                refreshed += 1;
+            }
+        }
 
-            let artist = match ArtistStore::get().get_by_hash(&seed_artisthash) {
-                Some(a) => a,
-                None => continue,
-            };
+        let cutoff = get_timestamp_days_ago(DAILY_MIX_HISTORY_DAYS);
+        let removed = MixTable::delete_unsaved_before(DAILY_MIX_PREFIX, cutoff).await?;
+        if removed > 0 {
+            tracing::info!("Removed {} expired daily mixes", removed);
+        }
 
-            // get all tracks by seed artist
-            let mut seed_tracks = track_store.get_by_artist(&seed_artisthash);
-            if seed_tracks.is_empty() {
-                continue;
+        Ok(refreshed)
+    }
+
+    /// Artists the user favorited directly or through one of their albums
+    async fn favorited_artists(user_id: i64) -> Vec<String> {
+        let album_store = AlbumStore::get();
+        let favorites = FavoriteTable::all(Some(user_id)).await.unwrap_or_default();
+
+        favorites
+            .iter()
+            .filter_map(|fav| match fav.favorite_type {
+                FavoriteType::Artist => Some(fav.hash.clone()),
+                FavoriteType::Album => album_store
+                    .get_by_hash(&fav.hash)
+                    .and_then(|a| a.albumartists.first().map(|r| r.artisthash.clone())),
+                FavoriteType::Track => None,
+            })
+            .collect()
+    }
+
+    /// Order seed artists by play count plus `weight` for every favorite
+    fn rank_seed_artists(
+        play_counts: &HashMap<String, i32>,
+        favorite_artists: &[String],
+        weight: f32,
+    ) -> Vec<String> {
+        let mut scores: HashMap<&str, f32> = play_counts
+            .iter()
+            .map(|(hash, count)| (hash.as_str(), *count as f32))
+            .collect();
+        if weight > 0.0 {
+            for hash in favorite_artists {
+                *scores.entry(hash.as_str()).or_default() += weight;
             }
+        }
 
-            // collect genres from seed artist tracks
-            let mut genre_hashes: HashSet<String> = HashSet::new();
-            for track in &seed_tracks {
-                for hash in &track.genrehashes {
-                    genre_hashes.insert(hash.clone());
-                }
+        let mut ranked: Vec<(&str, f32)> = scores.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        ranked
+            .into_iter()
+            .map(|(hash, _)| hash.to_string())
+            .collect()
+    }
+
+    /// Seeds for users with no listening history
+    ///
+    /// returns one queue per source: the user's favorites, the most represented
+    /// genres in the library and the artists of recently added albums.
+    async fn cold_start_seeds(
+        max_mixes: usize,
+        user_id: i64,
+        all_tracks: &[Track],
+    ) -> Vec<VecDeque<DailyMixSeed>> {
+        let track_store = TrackStore::get();
+        let album_store = AlbumStore::get();
+        let album_artist = |albumhash: &str| {
+            album_store
+                .get_by_hash(albumhash)
+                .and_then(|a| a.albumartists.first().map(|r| r.artisthash.clone()))
+        };
+
+        // artists behind the user's favorites, most recent first
+        let favorites = FavoriteTable::all(Some(user_id)).await.unwrap_or_default();
+        let favorite_artists: Vec<String> = favorites
+            .iter()
+            .filter_map(|fav| match fav.favorite_type {
+                FavoriteType::Artist => Some(fav.hash.clone()),
+                FavoriteType::Album => album_artist(&fav.hash),
+                FavoriteType::Track => track_store
+                    .get_by_hash(&fav.hash)
+                    .and_then(|t| t.artisthashes.first().cloned()),
+            })
+            .collect();
+
+        // genres with the most tracks in the library
+        let mut genre_counts: HashMap<String, (GenreRef, usize)> = HashMap::new();
+        for track in all_tracks {
+            for genre in &track.genres {
+                genre_counts
+                    .entry(genre.genrehash.clone())
+                    .or_insert_with(|| (genre.clone(), 0))
+                    .1 += 1;
             }
+        }
+        let mut top_genres: Vec<(GenreRef, usize)> = genre_counts.into_values().collect();
+        top_genres.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.name.cmp(&b.0.name)));
+
+        // album artists of the most recently added albums
+        let mut recent_tracks: Vec<&Track> = all_tracks.iter().collect();
+        recent_tracks.sort_by_key(|t| std::cmp::Reverse(t.last_mod));
+        let mut seen_albums: HashSet<&str> = HashSet::new();
+        let recent_artists: Vec<String> = recent_tracks
+            .into_iter()
+            .filter(|t| seen_albums.insert(t.albumhash.as_str()))
+            .filter_map(|t| album_artist(&t.albumhash))
+            .collect();
 
-            // find related tracks (same genres, different artist)
-            let all_tracks = track_store.get_all();
-            let mut related_tracks: Vec<_> = all_tracks
+        let dedup = |artists: Vec<String>| -> VecDeque<DailyMixSeed> {
+            let mut seen = HashSet::new();
+            artists
                 .into_iter()
-                .filter(|t| {
-                    !t.artisthashes.contains(&seed_artisthash)
-                        && t.genrehashes.iter().any(|g| genre_hashes.contains(g))
-                })
-                .collect();
+                .filter(|hash| seen.insert(hash.clone()))
+                .take(max_mixes * 2)
+                .map(DailyMixSeed::Artist)
+                .collect()
+        };
+
+        vec![
+            dedup(favorite_artists),
+            top_genres
+                .into_iter()
+                .take(max_mixes * 2)
+                .map(|(genre, _)| DailyMixSeed::Genre(genre))
+                .collect(),
+            dedup(recent_artists),
+        ]
+    }
 
-            // shuffle both pools
-            seed_tracks.shuffle(&mut rand::thread_rng());
-            related_tracks.shuffle(&mut rand::thread_rng());
+    /// Build daily mixes taking seeds from each queue in turn
+    ///
+    /// a queue moves on to its next seed when one cannot fill a mix, and an
+    /// artist already used by an earlier mix is never reused as a seed.
+    fn daily_mixes_from_seeds(
+        mut queues: Vec<VecDeque<DailyMixSeed>>,
+        max_mixes: usize,
+        all_tracks: &[Track],
+        options: &MixOptions,
+    ) -> Vec<crate::models::Mix> {
+        let mut mixes = Vec::new();
+        let mut used_seeds: HashSet<String> = HashSet::new();
 
-            // compose mix: ~60% seed artist, ~40% related
-            let seed_count = 15.min(seed_tracks.len());
-            let related_count = 10.min(related_tracks.len());
+        loop {
+            let mut progressed = false;
 
-            let mut mix_tracks: Vec<crate::models::Track> = Vec::new();
-            mix_tracks.extend(seed_tracks.into_iter().take(seed_count));
-            mix_tracks.extend(related_tracks.into_iter().take(related_count));
+            for queue in queues.iter_mut() {
+                if mixes.len() >= max_mixes {
+                    return mixes;
+                }
 
-            // shuffle the final mix
-            mix_tracks.shuffle(&mut rand::thread_rng());
+                while let Some(seed) = queue.pop_front() {
+                    if !used_seeds.insert(seed.hash().to_string()) {
+                        continue;
+                    }
 
-            // ensure we have at least a few tracks
-            if mix_tracks.len() < 5 {
-                continue;
+                    let mix_number = mixes.len() + 1;
+                    let mix = match &seed {
+                        DailyMixSeed::Artist(hash) => {
+                            Self::artist_daily_mix(hash, mix_number, all_tracks, options)
+                        }
+                        DailyMixSeed::Genre(genre) => {
+                            Self::genre_daily_mix(genre, mix_number, all_tracks, options)
+                        }
+                    };
+
+                    if let Some(mix) = mix {
+                        mixes.push(mix);
+                        progressed = true;
+                        break;
+                    }
+                }
             }
 
-            // limit to 25 tracks
-            mix_tracks.truncate(25);
+            if !progressed {
+                return mixes;
+            }
+        }
+    }
 
-            // collect images from first few tracks
-            let images: Vec<String> = mix_tracks
-                .iter()
-                .take(4)
-                .map(|t| t.image.clone())
-                .collect();
+    /// Daily mix built around a seed artist and related tracks of the same genres
+    fn artist_daily_mix(
+        seed_artisthash: &str,
+        mix_number: usize,
+        all_tracks: &[Track],
+        options: &MixOptions,
+    ) -> Option<crate::models::Mix> {
+        let artist = ArtistStore::get().get_by_hash(seed_artisthash)?;
+
+        // get all tracks by seed artist
+        let mut seed_tracks = TrackStore::get().get_by_artist(seed_artisthash);
+        seed_tracks.retain(|t| options.allows(t));
+        if seed_tracks.is_empty() {
+            return None;
+        }
 
-            let mix = crate::models::Mix::new(
-                format!("d{}", mix_number),
-                format!("Daily Mix {}", mix_number),
-                Self::build_daily_mix_description(&mix_tracks, &seed_artisthash, &artist.name),
-                mix_tracks.iter().map(|t| t.trackhash.clone()).collect(),
-                seed_artisthash.clone(),
-                0,
-            );
+        // collect genres from seed artist tracks
+        let mut genre_hashes: HashSet<String> = HashSet::new();
+        for track in &seed_tracks {
+            for hash in &track.genrehashes {
+                genre_hashes.insert(hash.clone());
+            }
+        }
 
-            // add images to the mix
-            let mut mix_with_images = mix;
-            mix_with_images.images = images;
+        // find related tracks (same genres, different artist)
+        let mut related_tracks: Vec<Track> = all_tracks
+            .iter()
+            .filter(|t| {
+                !t.artisthashes.iter().any(|h| h == seed_artisthash)
+                    && t.genrehashes.iter().any(|g| genre_hashes.contains(g))
+            })
+            .cloned()
+            .collect();
 
-            mixes.push(mix_with_images);
-            mix_number += 1;
+        // shuffle both pools
+        seed_tracks.shuffle(&mut rand::thread_rng());
+        related_tracks.shuffle(&mut rand::thread_rng());
+
+        // compose mix from the configured seed/related split, related tracks
+        // make up for a seed artist with too few tracks
+        let (seed_target, related_target) = options.settings.daily_mix_split();
+        let seed_count = seed_target.min(seed_tracks.len());
+        let related_count = (related_target + seed_target - seed_count).min(related_tracks.len());
+
+        let mut mix_tracks: Vec<Track> = Vec::new();
+        mix_tracks.extend(seed_tracks.into_iter().take(seed_count));
+        mix_tracks.extend(related_tracks.into_iter().take(related_count));
+
+        let description =
+            Self::build_daily_mix_description(&mix_tracks, seed_artisthash, &artist.name);
+        Self::finish_daily_mix(mix_tracks, mix_number, description, seed_artisthash, options)
+    }
+
+    /// Daily mix of tracks from a single genre
+    fn genre_daily_mix(
+        genre: &GenreRef,
+        mix_number: usize,
+        all_tracks: &[Track],
+        options: &MixOptions,
+    ) -> Option<crate::models::Mix> {
+        let mut mix_tracks: Vec<Track> = all_tracks
+            .iter()
+            .filter(|t| t.genrehashes.contains(&genre.genrehash))
+            .cloned()
+            .collect();
+        mix_tracks.shuffle(&mut rand::thread_rng());
+        mix_tracks.truncate(options.settings.daily_mix_tracks);
+
+        let description = Self::build_genre_mix_description(&mix_tracks, &genre.name);
+        Self::finish_daily_mix(mix_tracks, mix_number, description, &genre.genrehash, options)
+    }
+
+    /// Shuffle, trim and package daily mix tracks, rejecting mixes that are too short
+    fn finish_daily_mix(
+        mut mix_tracks: Vec<Track>,
+        mix_number: usize,
+        description: String,
+        sourcehash: &str,
+        options: &MixOptions,
+    ) -> Option<crate::models::Mix> {
+        // shuffle the final mix
+        mix_tracks.shuffle(&mut rand::thread_rng());
+
+        // ensure we have at least a few tracks
+        if mix_tracks.len() < MixSettings::MIN_TRACKS {
+            return None;
         }
 
-        mixes
+        mix_tracks.truncate(options.settings.daily_mix_tracks);
+
+        // collect images from first few tracks
+        let images: Vec<String> = mix_tracks
+            .iter()
+            .take(4)
+            .map(|t| t.image.clone())
+            .collect();
+
+        let mut mix = crate::models::Mix::new(
+            format!("d{}", mix_number),
+            format!("Daily Mix {}", mix_number),
+            description,
+            mix_tracks.iter().map(|t| t.trackhash.clone()).collect(),
+            sourcehash.to_string(),
+            0,
+        );
+
+        // add images to the mix
+        mix.images = images;
+        let (color, variants) = Self::mix_colors(&mix);
+        mix.set_color(color, variants);
+
+        Some(mix)
+    }
+
+    /// Build description for a genre daily mix showing featured artists
+    fn build_genre_mix_description(tracks: &[Track], genre_name: &str) -> String {
+        let mut featured: Vec<String> = Vec::new();
+        let mut seen = HashSet::new();
+
+        for track in tracks {
+            if featured.len() >= 3 {
+                break;
+            }
+            if let Some(first_artist) = track.artisthashes.first() {
+                if seen.insert(first_artist.clone()) {
+                    if let Some(artist) = ArtistStore::get().get_by_hash(first_artist) {
+                        featured.push(artist.name.clone());
+                    }
+                }
+            }
+        }
+
+        if featured.is_empty() {
+            format!("{} from your library", genre_name)
+        } else {
+            format!("{} with {} and more", genre_name, featured.join(", "))
+        }
     }
 
     /// Build description for daily mix showing featured artists
@@ -672,3 +1256,866 @@ pub struct RecentlyPlayedItem {
     pub timestamp: i64,
     pub help_text: Option<String>,
 }
+
+/// Fewest distinct tracks of an album played before it counts as started
+const MIN_STARTED_ALBUM_TRACKS: usize = 2;
+
+/// An album a user started but has not played every track of
+#[derive(Debug, Clone)]
+pub struct AlbumCompletion {
+    pub album: Album,
+    /// distinct tracks of the album played
+    pub played: usize,
+    pub total: usize,
+    pub lastplayed: i64,
+}
+
+impl AlbumCompletion {
+    /// Share of the album played as a whole percentage
+    pub fn percent(&self) -> usize {
+        self.played * 100 / self.total.max(1)
+    }
+}
+
+impl Recipes {
+    /// Albums a user started but never finished, most recently played first
+    pub fn unfinished_albums(limit: usize, user_id: i64) -> Vec<AlbumCompletion> {
+        let track_store = TrackStore::get();
+        let album_store = AlbumStore::get();
+
+        let mut albums: Vec<AlbumCompletion> = PlayStatsStore::get()
+            .played_album_tracks(user_id)
+            .into_iter()
+            .filter_map(|(albumhash, played, lastplayed)| {
+                let tracks: HashSet<String> = track_store
+                    .get_by_album(&albumhash)
+                    .into_iter()
+                    .map(|t| t.trackhash)
+                    .collect();
+                let (played, total) = album_progress(&tracks, &played)?;
+                let album = album_store.get_by_hash(&albumhash)?;
+                Some(AlbumCompletion {
+                    album,
+                    played,
+                    total,
+                    lastplayed,
+                })
+            })
+            .collect();
+
+        albums.sort_by_key(|a| std::cmp::Reverse(a.lastplayed));
+        albums.truncate(limit);
+        albums
+    }
+}
+
+/// Played and total track counts of an album started but not finished,
+/// plays of tracks since retagged off the album are not counted
+fn album_progress(tracks: &HashSet<String>, played: &HashSet<String>) -> Option<(usize, usize)> {
+    let played = tracks.intersection(played).count();
+    (played >= MIN_STARTED_ALBUM_TRACKS && played < tracks.len()).then_some((played, tracks.len()))
+}
+
+/// Fewest albums a genre, decade or label needs to get a hub
+const MIN_HUB_ALBUMS: usize = 4;
+/// Most hubs built for each kind, the largest are kept
+const MAX_HUBS_PER_KIND: usize = 12;
+/// Most albums listed in one hub
+const MAX_HUB_ALBUMS: usize = 40;
+
+/// What a library hub groups its albums by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HubKind {
+    Genre,
+    Decade,
+    Label,
+}
+
+/// A collection of albums built from the library tags
+#[derive(Debug, Clone)]
+pub struct LibraryHub {
+    /// stable id across rebuilds, e.g. `genre:<hash>`, `decade:1990`
+    pub key: String,
+    pub kind: HubKind,
+    pub name: String,
+    pub description: String,
+    /// most played first
+    pub albumhashes: Vec<String>,
+}
+
+/// Albums grouped under one hub key while the hubs are built
+#[derive(Default)]
+struct HubGroup<'a> {
+    names: HashMap<String, usize>,
+    albums: Vec<&'a Album>,
+}
+
+impl<'a> HubGroup<'a> {
+    fn add(&mut self, name: &str, album: &'a Album) {
+        *self.names.entry(name.to_string()).or_default() += 1;
+        self.albums.push(album);
+    }
+
+    /// the spelling most albums use
+    fn name(&self) -> String {
+        self.names
+            .iter()
+            .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+            .map(|(name, _)| name.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl Recipes {
+    /// Build genre, decade and record label hubs from albums
+    ///
+    /// `labels` maps albumhashes to the label most of their tracks carry
+    pub fn library_hubs(albums: &[Album], labels: &HashMap<String, String>) -> Vec<LibraryHub> {
+        let mut genres: HashMap<String, HubGroup> = HashMap::new();
+        let mut decades: HashMap<String, HubGroup> = HashMap::new();
+        let mut by_label: HashMap<String, HubGroup> = HashMap::new();
+
+        for album in albums {
+            for genre in &album.genres {
+                genres
+                    .entry(genre.genrehash.clone())
+                    .or_default()
+                    .add(&genre.name, album);
+            }
+
+            let year = chrono::DateTime::from_timestamp(album.date, 0)
+                .map(|dt| dt.year())
+                .unwrap_or(0);
+            if album.date != 0 && year >= 1900 {
+                let decade = year - year % 10;
+                decades
+                    .entry(decade.to_string())
+                    .or_default()
+                    .add(&format!("{}s", decade), album);
+            }
+
+            if let Some(label) = labels.get(&album.albumhash) {
+                by_label
+                    .entry(create_hash(&[&label.to_lowercase()], true))
+                    .or_default()
+                    .add(label, album);
+            }
+        }
+
+        let mut hubs = Self::hubs_of_kind(HubKind::Genre, genres);
+        hubs.extend(Self::hubs_of_kind(HubKind::Decade, decades));
+        hubs.extend(Self::hubs_of_kind(HubKind::Label, by_label));
+        hubs
+    }
+
+    fn hubs_of_kind(kind: HubKind, groups: HashMap<String, HubGroup>) -> Vec<LibraryHub> {
+        let mut groups: Vec<(String, HubGroup)> = groups
+            .into_iter()
+            .filter(|(_, group)| group.albums.len() >= MIN_HUB_ALBUMS)
+            .collect();
+        groups.sort_by(|a, b| {
+            b.1.albums
+                .len()
+                .cmp(&a.1.albums.len())
+                .then_with(|| a.0.cmp(&b.0))
+        });
+        groups.truncate(MAX_HUBS_PER_KIND);
+
+        groups
+            .into_iter()
+            .map(|(id, mut group)| {
+                let name = group.name();
+                group.albums.sort_by(|a, b| {
+                    b.playcount
+                        .cmp(&a.playcount)
+                        .then_with(|| b.date.cmp(&a.date))
+                        .then_with(|| a.title.to_lowercase().cmp(&b.title.to_lowercase()))
+                });
+                let (key, description) = match kind {
+                    HubKind::Genre => (
+                        format!("genre:{}", id),
+                        format!("{} albums in your library", name),
+                    ),
+                    HubKind::Decade => (
+                        format!("decade:{}", id),
+                        format!("Albums released in the {}", name),
+                    ),
+                    HubKind::Label => (format!("label:{}", id), format!("Releases on {}", name)),
+                };
+                LibraryHub {
+                    key,
+                    kind,
+                    name,
+                    description,
+                    albumhashes: group
+                        .albums
+                        .iter()
+                        .take(MAX_HUB_ALBUMS)
+                        .map(|a| a.albumhash.clone())
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+
+    /// Label most tracks of each album carry, audiobook albums are left out
+    fn album_labels(tracks: &[Track]) -> (HashMap<String, String>, HashSet<String>) {
+        let mut counts: HashMap<&str, HashMap<String, usize>> = HashMap::new();
+        let mut audiobook_albums = HashSet::new();
+
+        for track in tracks {
+            if audiobooks::is_audiobook(track) {
+                audiobook_albums.insert(track.albumhash.clone());
+                continue;
+            }
+            if let Some(label) = track.extra.get("label").and_then(|l| l.as_str()) {
+                let label = label.trim();
+                if !label.is_empty() {
+                    *counts
+                        .entry(track.albumhash.as_str())
+                        .or_default()
+                        .entry(label.to_string())
+                        .or_default() += 1;
+                }
+            }
+        }
+
+        let labels = counts
+            .into_iter()
+            .filter_map(|(albumhash, labels)| {
+                let label = labels
+                    .into_iter()
+                    .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))?
+                    .0;
+                Some((albumhash.to_string(), label))
+            })
+            .collect();
+        (labels, audiobook_albums)
+    }
+
+    /// Rebuild the generated hubs in the collections table
+    ///
+    /// hubs are matched to their rows by key so ids stay stable, hubs that
+    /// fell below the album threshold are removed. collections a user has
+    /// edited no longer carry a key and are left alone. returns the number of
+    /// hubs kept.
+    pub async fn refresh_library_hubs() -> anyhow::Result<usize> {
+        let enabled = UserConfig::load()
+            .map(|c| c.enable_library_hubs)
+            .unwrap_or(true);
+
+        let hubs = if enabled {
+            let (labels, audiobook_albums) = Self::album_labels(&TrackStore::get().get_all());
+            let mut albums = AlbumStore::get().get_all();
+            albums.retain(|a| !audiobook_albums.contains(&a.albumhash));
+            Self::library_hubs(&albums, &labels)
+        } else {
+            Vec::new()
+        };
+
+        let mut existing: HashMap<String, CollectionRow> = HashMap::new();
+        for row in CollectionTable::get_all().await? {
+            let key = generated_key(row.extra_data.as_deref());
+            let Some(key) = key else {
+                continue;
+            };
+            // restored backups can bring a second copy of a hub
+            match existing.entry(key) {
+                Entry::Occupied(_) => CollectionTable::delete(row.id).await?,
+                Entry::Vacant(slot) => {
+                    slot.insert(row);
+                }
+            }
+        }
+
+        for hub in &hubs {
+            // collections list their newest items first, so store the
+            // albums least played first
+            let items: Vec<CollectionItem> = hub
+                .albumhashes
+                .iter()
+                .rev()
+                .map(|hash| CollectionItem::album(hash))
+                .collect();
+            let settings = serde_json::to_string(&items)?;
+            let extra = serde_json::to_string(&serde_json::json!({
+                "description": hub.description,
+                "generated": hub.key,
+                "kind": hub.kind,
+            }))?;
+
+            match existing.remove(&hub.key) {
+                Some(row) => {
+                    let unchanged = row.name == hub.name
+                        && row.settings == settings
+                        && row.extra_data.as_deref() == Some(extra.as_str());
+                    if !unchanged {
+                        CollectionTable::update(
+                            row.id,
+                            Some(&hub.name),
+                            Some(&settings),
+                            Some(&extra),
+                        )
+                        .await?;
+                    }
+                }
+                None => {
+                    CollectionTable::insert(&hub.name, &settings, Some(&extra)).await?;
+                }
+            }
+        }
+
+        for row in existing.into_values() {
+            CollectionTable::delete(row.id).await?;
+        }
+
+        Ok(hubs.len())
+    }
+}
+
+/// Hub key of a generated collection, None for collections users made
+pub fn generated_key(extra_data: Option<&str>) -> Option<String> {
+    let extra: serde_json::Value = serde_json::from_str(extra_data?).ok()?;
+    extra
+        .get("generated")
+        .and_then(|k| k.as_str())
+        .map(String::from)
+}
+
+/// Fewest tracks that fit a mood before its mix is offered
+const MIN_MOOD_TRACKS: usize = 8;
+
+/// Moods a mix can be built around from tempo, key and loudness tags
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mood {
+    HighEnergy,
+    Chill,
+    Focus,
+}
+
+impl Mood {
+    pub const ALL: [Mood; 3] = [Mood::HighEnergy, Mood::Chill, Mood::Focus];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Mood::HighEnergy => "high-energy",
+            Mood::Chill => "chill",
+            Mood::Focus => "focus",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim().to_lowercase().replace('_', "-");
+        Self::ALL.into_iter().find(|m| m.as_str() == value)
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Mood::HighEnergy => "High Energy",
+            Mood::Chill => "Chill",
+            Mood::Focus => "Focus",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Mood::HighEnergy => "Fast, loud tracks to keep you moving",
+            Mood::Chill => "Slow, quiet tracks to wind down to",
+            Mood::Focus => "Steady mid tempo tracks to work to",
+        }
+    }
+
+    /// How well a track fits the mood, None when it does not fit
+    ///
+    /// tempo is required, loudness and key only move the score when tagged
+    pub fn score(&self, features: &AudioFeatures) -> Option<f32> {
+        let bpm = features.bpm?;
+        let loudness = features.loudness;
+        match self {
+            Mood::HighEnergy => {
+                if bpm < 115.0 || loudness.is_some_and(|l| l < -14.0) {
+                    return None;
+                }
+                let mode = if features.minor == Some(false) {
+                    0.25
+                } else {
+                    0.0
+                };
+                let level = loudness.map_or(0.5, |l| ramp(l, -14.0, -6.0));
+                Some(ramp(bpm, 115.0, 150.0) + level + mode)
+            }
+            Mood::Chill => {
+                if bpm > 100.0 || loudness.is_some_and(|l| l > -9.0) {
+                    return None;
+                }
+                let mode = if features.minor == Some(true) {
+                    0.25
+                } else {
+                    0.0
+                };
+                let level = loudness.map_or(0.5, |l| ramp(l, -9.0, -16.0));
+                Some(ramp(bpm, 100.0, 65.0) + level + mode)
+            }
+            Mood::Focus => {
+                if !(80.0..=125.0).contains(&bpm)
+                    || loudness.is_some_and(|l| !(-16.0..=-8.0).contains(&l))
+                {
+                    return None;
+                }
+                // a steady middle of the road level matters more than the key here
+                let tempo = 1.0 - (bpm - 100.0).abs() / 25.0;
+                let level = loudness.map_or(0.5, |l| 1.0 - (l + 12.0).abs() / 4.0);
+                Some(tempo.max(0.0) + level.max(0.0))
+            }
+        }
+    }
+}
+
+/// Tempo, mode and loudness of a track read from its tags
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AudioFeatures {
+    pub bpm: Option<f32>,
+    /// whether the key is minor, None when the key is untagged or unreadable
+    pub minor: Option<bool>,
+    /// integrated loudness in LUFS
+    pub loudness: Option<f32>,
+}
+
+impl AudioFeatures {
+    pub fn of(track: &Track) -> Self {
+        let extra = track.extra_info();
+        Self {
+            bpm: extra.bpm.map(f32::from),
+            minor: extra.key.as_deref().and_then(key_is_minor),
+            loudness: extra.loudness,
+        }
+    }
+}
+
+/// where a value sits between two points, clamped to 0..1, `to` may be below `from`
+fn ramp(value: f32, from: f32, to: f32) -> f32 {
+    ((value - from) / (to - from)).clamp(0.0, 1.0)
+}
+
+/// Whether a key tag is minor
+///
+/// reads note names like "Am", "F# minor" or "Eb", the Camelot wheel ("8A")
+/// and open key notation ("1m")
+pub fn key_is_minor(key: &str) -> Option<bool> {
+    let key = key.trim().to_lowercase();
+
+    // camelot and open key put the mode letter after the wheel number
+    let digits = key.chars().take_while(char::is_ascii_digit).count();
+    if digits > 0 {
+        let number: u8 = key[..digits].parse().ok()?;
+        if !(1..=12).contains(&number) {
+            return None;
+        }
+        return match &key[digits..] {
+            "a" | "m" => Some(true),
+            "b" | "d" => Some(false),
+            _ => None,
+        };
+    }
+
+    let mut chars = key.chars();
+    if !matches!(chars.next(), Some('a'..='g')) {
+        return None;
+    }
+    match chars
+        .as_str()
+        .trim_start_matches(['#', 'b', '♯', '♭'])
+        .trim()
+    {
+        "" | "maj" | "major" => Some(false),
+        "m" | "min" | "minor" => Some(true),
+        _ => None,
+    }
+}
+
+impl Recipes {
+    /// Mix of tracks that fit a mood, None when too few tracks are tagged for it
+    ///
+    /// the best fitting tracks are shuffled so the mix changes between visits
+    pub async fn mood_mix(mood: Mood, limit: usize, user_id: i64) -> Option<Mix> {
+        let options = MixOptions::load(user_id).await;
+
+        let mut scored: Vec<(f32, Track)> = TrackStore::get()
+            .get_all()
+            .into_iter()
+            .filter(|t| options.allows(t))
+            .filter_map(|t| mood.score(&AudioFeatures::of(&t)).map(|s| (s, t)))
+            .collect();
+        if scored.len() < MIN_MOOD_TRACKS {
+            return None;
+        }
+
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.truncate(limit.max(1) * 2);
+        let mut tracks: Vec<Track> = scored.into_iter().map(|(_, t)| t).collect();
+        tracks.shuffle(&mut rand::thread_rng());
+        tracks.truncate(limit.max(1));
+
+        Some(Mix {
+            id: format!("mood-{}", mood.as_str()),
+            name: mood.title().to_string(),
+            description: mood.description().to_string(),
+            tracks,
+            image: None,
+        })
+    }
+
+    /// Mood mixes the library has enough tagged tracks for
+    pub async fn mood_mixes(limit: usize, user_id: i64) -> Vec<(Mood, Mix)> {
+        let mut mixes = Vec::new();
+        for mood in Mood::ALL {
+            if let Some(mix) = Self::mood_mix(mood, limit, user_id).await {
+                mixes.push((mood, mix));
+            }
+        }
+        mixes
+    }
+
+    /// Tracks in a mood mix, the same as a daily mix
+    pub fn mood_mix_size() -> usize {
+        UserConfig::load()
+            .unwrap_or_default()
+            .mixes
+            .normalized()
+            .daily_mix_tracks
+    }
+}
+
+/// Recently played tracks a queue continuation is seeded from
+pub const QUEUE_SEED_TRACKS: usize = 25;
+
+/// Tracks by one artist in a single continuation batch
+const QUEUE_ARTIST_LIMIT: usize = 2;
+
+/// Seed weight lost with each older play
+const QUEUE_RECENCY_DECAY: f64 = 0.85;
+
+/// Share of the seed weight the queue source adds, split over its tracks
+const QUEUE_SOURCE_WEIGHT: f64 = 2.0;
+
+/// Seed artists whose similar artists are looked up
+const QUEUE_SIMILAR_SEEDS: usize = 5;
+
+/// How much a shared genre adds to a track's score
+const QUEUE_GENRE_WEIGHT: f64 = 0.3;
+
+impl Recipes {
+    /// Next tracks for a queue that ran out, radio style
+    ///
+    /// `recent` holds the last played trackhashes, oldest first, and `source`
+    /// the token the queue was started from (`al:`, `ar:`, `pl:`, `fo:`, `tr:`
+    /// or `favorite`). artists of the recent tracks, weighted towards the
+    /// latest plays, and of the source seed the batch. tracks by the seeds and
+    /// their similar artists are sampled by weight with at most two per
+    /// artist, tracks sharing a genre and then random ones fill what is left so
+    /// playback never runs dry.
+    pub async fn continue_queue(
+        recent: &[String],
+        source: &str,
+        exclude: &HashSet<String>,
+        limit: usize,
+        user_id: i64,
+    ) -> Vec<Track> {
+        let options = MixOptions::load(user_id).await;
+        let track_store = TrackStore::get();
+
+        let mut played: HashSet<String> = exclude.clone();
+        played.extend(recent.iter().cloned());
+
+        let mut artist_weights: HashMap<String, f64> = HashMap::new();
+        let mut genre_weights: HashMap<String, f64> = HashMap::new();
+        let mut add_seed = |track: &Track, weight: f64| {
+            for hash in &track.artisthashes {
+                *artist_weights.entry(hash.clone()).or_insert(0.0) += weight;
+            }
+            for hash in &track.genrehashes {
+                *genre_weights.entry(hash.clone()).or_insert(0.0) += weight;
+            }
+        };
+
+        let mut weight = 1.0;
+        for hash in recent.iter().rev().take(QUEUE_SEED_TRACKS) {
+            if let Some(track) = track_store.get_by_hash(hash) {
+                add_seed(&track, weight);
+            }
+            weight *= QUEUE_RECENCY_DECAY;
+        }
+
+        let source_tracks = Self::queue_source_tracks(source, user_id).await;
+        if !source_tracks.is_empty() {
+            let share = QUEUE_SOURCE_WEIGHT / source_tracks.len() as f64;
+            for track in &source_tracks {
+                add_seed(track, share);
+            }
+        }
+
+        normalize_weights(&mut artist_weights);
+        normalize_weights(&mut genre_weights);
+
+        // similar artists inherit the weight of the seed that led to them
+        let mut seeds: Vec<(String, f64)> =
+            artist_weights.iter().map(|(h, w)| (h.clone(), *w)).collect();
+        seeds.sort_by(|a, b| b.1.total_cmp(&a.1));
+        for (seed, seed_weight) in seeds.into_iter().take(QUEUE_SIMILAR_SEEDS) {
+            let similar = SimilarArtistTable::get_similar_full(&seed)
+                .await
+                .unwrap_or_default();
+            let max = similar.iter().map(|s| s.weight).fold(0.0_f64, f64::max);
+            for entry in similar {
+                let similarity = if max > 0.0 && entry.weight > 0.0 {
+                    entry.weight / max
+                } else {
+                    1.0
+                };
+                let weight = artist_weights.entry(entry.artisthash).or_insert(0.0);
+                *weight = weight.max(seed_weight * similarity);
+            }
+        }
+
+        let candidates: Vec<Track> = track_store
+            .get_all()
+            .into_iter()
+            .filter(|t| !played.contains(&t.trackhash) && options.allows(t))
+            .collect();
+
+        let weighted: Vec<(Track, f64)> = candidates
+            .iter()
+            .filter_map(|track| {
+                let artist = max_weight(&track.artisthashes, &artist_weights);
+                let genre = max_weight(&track.genrehashes, &genre_weights);
+                let score = artist + QUEUE_GENRE_WEIGHT * genre;
+                (score > 0.0).then(|| (track.clone(), score))
+            })
+            .collect();
+
+        let mut picked: Vec<Track> = Vec::with_capacity(limit);
+        let mut per_artist: HashMap<String, usize> = HashMap::new();
+        let pool = weighted.len();
+        for track in Self::weighted_sample(weighted, pool) {
+            if picked.len() >= limit {
+                break;
+            }
+            let artist = track.artisthashes.first().cloned().unwrap_or_default();
+            let count = per_artist.entry(artist).or_insert(0);
+            if *count >= QUEUE_ARTIST_LIMIT {
+                continue;
+            }
+            *count += 1;
+            picked.push(track);
+        }
+
+        if picked.len() < limit {
+            let taken: HashSet<String> = picked.iter().map(|t| t.trackhash.clone()).collect();
+            let mut rest: Vec<Track> = candidates
+                .into_iter()
+                .filter(|t| !taken.contains(&t.trackhash))
+                .collect();
+            rest.shuffle(&mut rand::thread_rng());
+            picked.extend(rest.into_iter().take(limit - picked.len()));
+        }
+
+        picked
+    }
+
+    /// Tracks the source token of a queue points at, empty when it points at
+    /// nothing the user can see
+    async fn queue_source_tracks(source: &str, user_id: i64) -> Vec<Track> {
+        let track_store = TrackStore::get();
+        let (prefix, id) = source.split_once(':').unwrap_or((source, ""));
+
+        let mut tracks = match MixSourceType::from_prefix(prefix) {
+            Some(MixSourceType::Artist) => track_store.get_by_artist(id),
+            Some(MixSourceType::Album) => track_store.get_by_album(id),
+            Some(MixSourceType::Folder) => track_store.get_by_folder(id),
+            Some(MixSourceType::Track) => track_store.get_by_hash(id).into_iter().collect(),
+            Some(MixSourceType::Playlist) => match id.parse::<i64>() {
+                Ok(pid) => match PlaylistTable::get_by_id(pid).await {
+                    Ok(Some(p)) if p.can_view(user_id) => track_store.get_by_hashes(&p.trackhashes),
+                    _ => Vec::new(),
+                },
+                Err(_) => Vec::new(),
+            },
+            Some(MixSourceType::Favorite) => track_store
+                .get_all()
+                .into_iter()
+                .filter(|t| t.is_favorite(user_id))
+                .collect(),
+            None => Vec::new(),
+        };
+
+        // a sample is enough to know what the source sounds like
+        tracks.shuffle(&mut rand::thread_rng());
+        tracks.truncate(QUEUE_SEED_TRACKS * 4);
+        tracks
+    }
+}
+
+/// Scale weights so the largest is one
+fn normalize_weights(weights: &mut HashMap<String, f64>) {
+    let max = weights.values().copied().fold(0.0_f64, f64::max);
+    if max > 0.0 {
+        for weight in weights.values_mut() {
+            *weight /= max;
+        }
+    }
+}
+
+/// Largest weight of any of the hashes
+fn max_weight(hashes: &[String], weights: &HashMap<String, f64>) -> f64 {
+    hashes
+        .iter()
+        .filter_map(|h| weights.get(h))
+        .fold(0.0_f64, |max, w| max.max(*w))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rank_seed_artists_with_favorites() {
+        let plays: HashMap<String, i32> =
+            [("a".to_string(), 4), ("b".to_string(), 2)].into_iter().collect();
+        let favorites = vec!["b".to_string(), "c".to_string(), "b".to_string()];
+
+        assert_eq!(Recipes::rank_seed_artists(&plays, &favorites, 0.0), vec!["a", "b"]);
+        assert_eq!(
+            Recipes::rank_seed_artists(&plays, &favorites, 1.5),
+            vec!["b", "a", "c"]
+        );
+    }
+
+    fn album(hash: &str, year: i32, genre: &str, playcount: i32) -> Album {
+        let mut album = Album::new(hash.to_string(), format!("Album {}", hash));
+        album.date = chrono::NaiveDate::from_ymd_opt(year, 6, 1)
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .map(|d| d.and_utc().timestamp())
+            .unwrap_or(0);
+        album.genres = vec![GenreRef::new(genre.to_string(), genre.to_lowercase())];
+        album.playcount = playcount;
+        album
+    }
+
+    #[test]
+    fn test_album_progress() {
+        let set = |hashes: &[&str]| -> HashSet<String> {
+            hashes.iter().map(|h| h.to_string()).collect()
+        };
+        let tracks = set(&["a", "b", "c", "d"]);
+
+        assert_eq!(album_progress(&tracks, &set(&["a", "c", "x"])), Some((2, 4)));
+        // one track is not enough to count as started
+        assert_eq!(album_progress(&tracks, &set(&["a", "x"])), None);
+        assert_eq!(album_progress(&tracks, &set(&["a", "b", "c", "d"])), None);
+    }
+
+    #[test]
+    fn test_queue_weights() {
+        let mut weights: HashMap<String, f64> =
+            [("a".to_string(), 4.0), ("b".to_string(), 1.0)].into_iter().collect();
+        normalize_weights(&mut weights);
+        assert_eq!(weights["a"], 1.0);
+        assert_eq!(weights["b"], 0.25);
+
+        let hashes = vec!["b".to_string(), "c".to_string()];
+        assert_eq!(max_weight(&hashes, &weights), 0.25);
+        assert_eq!(max_weight(&["c".to_string()], &weights), 0.0);
+    }
+
+    #[test]
+    fn test_key_is_minor() {
+        assert_eq!(key_is_minor("Am"), Some(true));
+        assert_eq!(key_is_minor("F# minor"), Some(true));
+        assert_eq!(key_is_minor("Bbm"), Some(true));
+        assert_eq!(key_is_minor("Eb"), Some(false));
+        assert_eq!(key_is_minor("C major"), Some(false));
+        assert_eq!(key_is_minor("8A"), Some(true));
+        assert_eq!(key_is_minor("12B"), Some(false));
+        assert_eq!(key_is_minor("1m"), Some(true));
+        assert_eq!(key_is_minor("6d"), Some(false));
+        assert_eq!(key_is_minor("13A"), None);
+        assert_eq!(key_is_minor("Hm"), None);
+    }
+
+    #[test]
+    fn test_mood_scores() {
+        let fast = AudioFeatures {
+            bpm: Some(140.0),
+            minor: Some(false),
+            loudness: Some(-7.0),
+        };
+        let slow = AudioFeatures {
+            bpm: Some(72.0),
+            minor: Some(true),
+            loudness: Some(-15.0),
+        };
+        let steady = AudioFeatures {
+            bpm: Some(100.0),
+            minor: None,
+            loudness: Some(-12.0),
+        };
+
+        assert!(Mood::HighEnergy.score(&fast).is_some());
+        assert!(Mood::HighEnergy.score(&slow).is_none());
+        assert!(Mood::Chill.score(&slow).is_some());
+        assert!(Mood::Chill.score(&fast).is_none());
+        assert!(Mood::Focus.score(&steady).is_some());
+        assert!(Mood::Focus.score(&fast).is_none());
+
+        // untagged tempo fits nothing
+        assert!(Mood::ALL
+            .iter()
+            .all(|m| m.score(&AudioFeatures::default()).is_none()));
+
+        // a faster track is a better high energy fit than a slower one
+        let faster = AudioFeatures {
+            bpm: Some(150.0),
+            ..fast
+        };
+        assert!(Mood::HighEnergy.score(&faster) > Mood::HighEnergy.score(&fast));
+
+        assert_eq!(Mood::parse("High_Energy"), Some(Mood::HighEnergy));
+        assert_eq!(Mood::parse("sad"), None);
+    }
+
+    #[test]
+    fn test_daily_mix_prefix() {
+        let day = NaiveDate::from_ymd_opt(2026, 3, 9).unwrap();
+        let prefix = Recipes::daily_mix_prefix(1, day);
+        assert_eq!(prefix, "d1-20260309-");
+        // another user's or another day's mixes never share the prefix
+        assert!(!Recipes::daily_mix_prefix(12, day).starts_with(&prefix));
+        assert!(!Recipes::daily_mix_prefix(1, day.succ_opt().unwrap()).starts_with(&prefix));
+    }
+
+    #[test]
+    fn test_library_hubs() {
+        let albums: Vec<Album> = (0..6)
+            .map(|i| album(&format!("a{}", i), 1990 + i, "Rock", i))
+            .chain((0..3).map(|i| album(&format!("b{}", i), 2005, "Jazz", 0)))
+            .collect();
+        let labels: HashMap<String, String> = (0..4)
+            .map(|i| (format!("a{}", i), "Sub Pop".to_string()))
+            .collect();
+
+        let hubs = Recipes::library_hubs(&albums, &labels);
+        let keys: Vec<&str> = hubs.iter().map(|h| h.key.as_str()).collect();
+
+        // jazz and the 2000s fall short of the album threshold
+        assert_eq!(keys.len(), 3);
+        assert_eq!(keys[0], "genre:rock");
+        assert_eq!(keys[1], "decade:1990");
+        assert!(keys[2].starts_with("label:"));
+
+        assert_eq!(hubs[0].albumhashes[..2], ["a5", "a4"]);
+        assert_eq!(hubs[1].name, "1990s");
+        assert_eq!(hubs[2].name, "Sub Pop");
+        assert_eq!(hubs[2].albumhashes.len(), 4);
+    }
+}
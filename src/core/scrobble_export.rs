@@ -0,0 +1,288 @@
+//! Listening history exports for public scrobble services
+//!
+//! plays are written either as the last.fm csv that third party exporters
+//! produce and scrobble importers read, or as the json array of listens
+//! listenbrainz imports. tracks that left the library since they were played
+//! are exported from the title and artists stored with the play.
+
+use chrono::{TimeZone, Utc};
+use serde_json::{json, Value};
+
+use crate::models::{Track, TrackLog};
+
+/// Header row of the last.fm csv
+const LASTFM_HEADER: &str = "uts,utc_time,artist,artist_mbid,album,album_mbid,track,track_mbid";
+
+/// Submission client named on listenbrainz listens
+const CLIENT_NAME: &str = "Swing Music";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    LastfmCsv,
+    ListenbrainzJson,
+}
+
+impl ExportFormat {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "lastfm-csv" => Some(Self::LastfmCsv),
+            "listenbrainz-json" => Some(Self::ListenbrainzJson),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::LastfmCsv => "csv",
+            Self::ListenbrainzJson => "json",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::LastfmCsv => "text/csv; charset=utf-8",
+            Self::ListenbrainzJson => "application/json",
+        }
+    }
+}
+
+/// One play with the metadata scrobble services match on
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportedPlay {
+    pub timestamp: i64,
+    /// seconds the track was listened to
+    pub duration: i32,
+    pub title: String,
+    pub artists: Vec<String>,
+    pub artist_mbids: Vec<String>,
+    pub album: String,
+    pub albumartist: String,
+    pub track_mbid: String,
+    /// 0 when unknown
+    pub tracknumber: i32,
+    /// length of the track in seconds, 0 when unknown
+    pub length: i32,
+}
+
+impl ExportedPlay {
+    /// Build a play from its log, `None` when neither the library nor the log
+    /// still know the title and artist
+    pub fn new(log: &TrackLog, track: Option<&Track>) -> Option<Self> {
+        let play = match track {
+            Some(track) => Self {
+                timestamp: log.timestamp,
+                duration: log.duration,
+                title: track.title.clone(),
+                artists: track.artists.iter().map(|a| a.name.clone()).collect(),
+                artist_mbids: track.extra_info().artist_mbids,
+                album: track.album.clone(),
+                albumartist: track.albumartist(),
+                track_mbid: track.mbid.clone(),
+                tracknumber: track.track,
+                length: track.duration,
+            },
+            None => Self {
+                timestamp: log.timestamp,
+                duration: log.duration,
+                title: log
+                    .extra
+                    .get("title")
+                    .and_then(|t| t.as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+                artists: log
+                    .extra
+                    .get("artists")
+                    .and_then(|a| a.as_array())
+                    .map(|artists| {
+                        artists
+                            .iter()
+                            .filter_map(|a| a.as_str().map(String::from))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                artist_mbids: Vec::new(),
+                album: String::new(),
+                albumartist: String::new(),
+                track_mbid: String::new(),
+                tracknumber: 0,
+                length: 0,
+            },
+        };
+
+        if play.title.is_empty() || play.artists.is_empty() {
+            return None;
+        }
+        Some(play)
+    }
+
+    fn artist(&self) -> String {
+        self.artists.join(", ")
+    }
+}
+
+/// Write the plays in the given format
+pub fn export(plays: &[ExportedPlay], format: ExportFormat) -> String {
+    match format {
+        ExportFormat::LastfmCsv => lastfm_csv(plays),
+        ExportFormat::ListenbrainzJson => listenbrainz_json(plays),
+    }
+}
+
+fn lastfm_csv(plays: &[ExportedPlay]) -> String {
+    let mut out = String::from(LASTFM_HEADER);
+    out.push('\n');
+    for play in plays {
+        let time = Utc
+            .timestamp_opt(play.timestamp, 0)
+            .single()
+            .map(|t| t.format("%d %b %Y, %H:%M").to_string())
+            .unwrap_or_default();
+        // last.fm keeps a single artist mbid per scrobble
+        let artist_mbid = play.artist_mbids.first().map_or("", String::as_str);
+
+        let fields = [
+            play.timestamp.to_string(),
+            time,
+            play.artist(),
+            artist_mbid.to_string(),
+            play.album.clone(),
+            String::new(),
+            play.title.clone(),
+            play.track_mbid.clone(),
+        ];
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+fn listenbrainz_json(plays: &[ExportedPlay]) -> String {
+    let listens: Vec<Value> = plays
+        .iter()
+        .map(|play| {
+            let mut info = json!({
+                "submission_client": CLIENT_NAME,
+                "media_player": CLIENT_NAME,
+                "artist_names": play.artists,
+            });
+            if play.length > 0 {
+                info["duration_ms"] = json!(i64::from(play.length) * 1000);
+            }
+            if play.tracknumber > 0 {
+                info["tracknumber"] = json!(play.tracknumber);
+            }
+            if !play.track_mbid.is_empty() {
+                info["recording_mbid"] = json!(play.track_mbid);
+            }
+            if !play.artist_mbids.is_empty() {
+                info["artist_mbids"] = json!(play.artist_mbids);
+            }
+            if !play.albumartist.is_empty() {
+                info["release_artist_name"] = json!(play.albumartist);
+            }
+
+            let mut metadata = json!({
+                "artist_name": play.artist(),
+                "track_name": play.title,
+                "additional_info": info,
+            });
+            if !play.album.is_empty() {
+                metadata["release_name"] = json!(play.album);
+            }
+
+            json!({
+                "listened_at": play.timestamp,
+                "track_metadata": metadata,
+            })
+        })
+        .collect();
+
+    serde_json::to_string_pretty(&listens).unwrap_or_else(|_| "[]".to_string())
+}
+
+/// Quote a csv field when it holds a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ArtistRefItem;
+
+    fn log(extra: Value) -> TrackLog {
+        TrackLog {
+            id: 1,
+            trackhash: "abc".to_string(),
+            timestamp: 1_700_000_000,
+            duration: 180,
+            source: String::new(),
+            userid: 1,
+            device: String::new(),
+            extra,
+            source_type: None,
+            source_id: None,
+        }
+    }
+
+    fn track() -> Track {
+        let mut track = Track::new();
+        track.title = "Hello, \"World\"".to_string();
+        track.album = "Album".to_string();
+        track.artists = vec![ArtistRefItem::new("Artist".to_string(), "h".to_string())];
+        track.albumartists = vec![ArtistRefItem::new("Artist".to_string(), "h".to_string())];
+        track.mbid = "rec-mbid".to_string();
+        track.track = 3;
+        track.duration = 200;
+        track
+    }
+
+    #[test]
+    fn test_play_from_removed_track() {
+        let play = ExportedPlay::new(
+            &log(json!({"title": "Gone", "artists": ["A", "B"]})),
+            None,
+        )
+        .unwrap();
+        assert_eq!(play.title, "Gone");
+        assert_eq!(play.artist(), "A, B");
+
+        assert!(ExportedPlay::new(&log(json!({})), None).is_none());
+    }
+
+    #[test]
+    fn test_lastfm_csv() {
+        let play = ExportedPlay::new(&log(json!({})), Some(&track())).unwrap();
+        let csv = export(&[play], ExportFormat::LastfmCsv);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], LASTFM_HEADER);
+        assert_eq!(
+            lines[1],
+            "1700000000,\"14 Nov 2023, 22:13\",Artist,,Album,,\"Hello, \"\"World\"\"\",rec-mbid"
+        );
+    }
+
+    #[test]
+    fn test_listenbrainz_json() {
+        let play = ExportedPlay::new(&log(json!({})), Some(&track())).unwrap();
+        let listens: Value =
+            serde_json::from_str(&export(&[play], ExportFormat::ListenbrainzJson)).unwrap();
+        let listen = &listens[0];
+        assert_eq!(listen["listened_at"], 1_700_000_000);
+        assert_eq!(listen["track_metadata"]["release_name"], "Album");
+        assert_eq!(
+            listen["track_metadata"]["additional_info"]["recording_mbid"],
+            "rec-mbid"
+        );
+        assert_eq!(
+            listen["track_metadata"]["additional_info"]["duration_ms"],
+            200_000
+        );
+    }
+}
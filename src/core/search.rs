@@ -1,10 +1,60 @@
 //! Search functionality for tracks, albums, artists
+//!
+//! queries are answered from the inverted indexes in `SearchStore`, see
+//! `core::search_index` for tokenization and ranking. the `*_for_user`
+//! variants then lift what the user plays and favorites, so their most played
+//! "Blue" album ranks above an obscure one with the same title.
 
-use std::cmp::Ordering;
 use std::collections::HashMap;
 
 use crate::models::{Album, Artist, Track};
-use crate::stores::{AlbumStore, ArtistStore, TrackStore};
+use crate::stores::{AlbumStore, ArtistStore, PlayStatsStore, SearchStore, TrackStore};
+
+/// Upper bound of the `search_personal_boost` setting
+pub const MAX_SEARCH_PERSONAL_BOOST: f64 = 5.0;
+
+/// at least this many text matches are ranked again with the user's history,
+/// so a loved item just outside a small limit can still make it in
+const PERSONAL_CANDIDATES: usize = 50;
+
+/// share of the boost a favorite is worth, on top of its plays
+const FAVORITE_AFFINITY: f64 = 0.5;
+
+/// Library items with per-user listening history
+pub trait PersonalItem {
+    fn playcount(&self) -> i32;
+    fn is_favorite(&self, user_id: i64) -> bool;
+}
+
+impl PersonalItem for Track {
+    fn playcount(&self) -> i32 {
+        self.playcount
+    }
+
+    fn is_favorite(&self, user_id: i64) -> bool {
+        Track::is_favorite(self, user_id)
+    }
+}
+
+impl PersonalItem for Album {
+    fn playcount(&self) -> i32 {
+        self.playcount
+    }
+
+    fn is_favorite(&self, user_id: i64) -> bool {
+        Album::is_favorite(self, user_id)
+    }
+}
+
+impl PersonalItem for Artist {
+    fn playcount(&self) -> i32 {
+        self.playcount
+    }
+
+    fn is_favorite(&self, user_id: i64) -> bool {
+        Artist::is_favorite(self, user_id)
+    }
+}
 
 /// Search result item
 #[derive(Debug, Clone)]
@@ -19,173 +69,171 @@ pub struct SearchLib;
 impl SearchLib {
     /// Search tracks by query
     pub fn search_tracks(query: &str, limit: usize) -> Vec<SearchResult<Track>> {
+        let hits = SearchStore::get().search_tracks(query, limit);
         let store = TrackStore::get();
-        let tracks = store.get_all();
 
-        Self::fuzzy_search(&tracks, query, |t| &t.title, limit)
+        hits.into_iter()
+            .filter_map(|hit| {
+                store.get_by_hash(&hit.key).map(|item| SearchResult {
+                    item,
+                    score: hit.score,
+                })
+            })
+            .collect()
     }
 
     /// Search albums by query
     pub fn search_albums(query: &str, limit: usize) -> Vec<SearchResult<Album>> {
+        let hits = SearchStore::get().search_albums(query, limit);
         let store = AlbumStore::get();
-        let albums = store.get_all();
 
-        Self::fuzzy_search(&albums, query, |a| &a.title, limit)
+        hits.into_iter()
+            .filter_map(|hit| {
+                store.get_by_hash(&hit.key).map(|item| SearchResult {
+                    item,
+                    score: hit.score,
+                })
+            })
+            .collect()
     }
 
     /// Search artists by query
     pub fn search_artists(query: &str, limit: usize) -> Vec<SearchResult<Artist>> {
+        let hits = SearchStore::get().search_artists(query, limit);
         let store = ArtistStore::get();
-        let artists = store.get_all();
 
-        Self::fuzzy_search(&artists, query, |a| &a.name, limit)
+        hits.into_iter()
+            .filter_map(|hit| {
+                store.get_by_hash(&hit.key).map(|item| SearchResult {
+                    item,
+                    score: hit.score,
+                })
+            })
+            .collect()
     }
 
-    /// Combined search across all types
-    pub fn search_all(
+    /// Search tracks, lifting the ones the user listens to
+    pub fn search_tracks_for_user(
         query: &str,
-        tracks_limit: usize,
-        albums_limit: usize,
-        artists_limit: usize,
-    ) -> (
-        Vec<SearchResult<Track>>,
-        Vec<SearchResult<Album>>,
-        Vec<SearchResult<Artist>>,
-    ) {
-        let tracks = Self::search_tracks(query, tracks_limit);
-        let albums = Self::search_albums(query, albums_limit);
-        let artists = Self::search_artists(query, artists_limit);
+        limit: usize,
+        user_id: i64,
+        boost: f64,
+    ) -> Vec<SearchResult<Track>> {
+        let results = Self::search_tracks(query, limit.max(PERSONAL_CANDIDATES));
+        Self::rank_for_user(results, limit, user_id, boost, |items| {
+            PlayStatsStore::get().personalize_tracks(user_id, items)
+        })
+    }
 
-        (tracks, albums, artists)
+    /// Search albums, lifting the ones the user listens to
+    pub fn search_albums_for_user(
+        query: &str,
+        limit: usize,
+        user_id: i64,
+        boost: f64,
+    ) -> Vec<SearchResult<Album>> {
+        let results = Self::search_albums(query, limit.max(PERSONAL_CANDIDATES));
+        Self::rank_for_user(results, limit, user_id, boost, |items| {
+            PlayStatsStore::get().personalize_albums(user_id, items)
+        })
     }
 
-    /// Fuzzy search implementation
-    fn fuzzy_search<T: Clone>(
-        items: &[T],
+    /// Search artists, lifting the ones the user listens to
+    pub fn search_artists_for_user(
         query: &str,
-        get_name: impl Fn(&T) -> &str,
         limit: usize,
+        user_id: i64,
+        boost: f64,
+    ) -> Vec<SearchResult<Artist>> {
+        let results = Self::search_artists(query, limit.max(PERSONAL_CANDIDATES));
+        Self::rank_for_user(results, limit, user_id, boost, |items| {
+            PlayStatsStore::get().personalize_artists(user_id, items)
+        })
+    }
+
+    /// overlay the user's play stats, boost and cut down to the limit
+    fn rank_for_user<T: PersonalItem>(
+        results: Vec<SearchResult<T>>,
+        limit: usize,
+        user_id: i64,
+        boost: f64,
+        personalize: impl FnOnce(&mut [T]),
     ) -> Vec<SearchResult<T>> {
-        let query_lower = query.to_lowercase();
-        let query_words: Vec<&str> = query_lower.split_whitespace().collect();
+        let (mut items, scores): (Vec<T>, Vec<f64>) =
+            results.into_iter().map(|r| (r.item, r.score)).unzip();
+        personalize(&mut items);
 
         let mut results: Vec<SearchResult<T>> = items
-            .iter()
-            .filter_map(|item| {
-                let name = get_name(item).to_lowercase();
-                let score = Self::calculate_score(&name, &query_lower, &query_words);
-
-                if score > 0.0 {
-                    Some(SearchResult {
-                        item: item.clone(),
-                        score,
-                    })
-                } else {
-                    None
-                }
-            })
+            .into_iter()
+            .zip(scores)
+            .map(|(item, score)| SearchResult { item, score })
             .collect();
-
-        // Sort by score descending
-        results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
-
+        Self::boost_personal(&mut results, user_id, boost);
         results.truncate(limit);
         results
     }
 
-    /// Calculate search score
-    fn calculate_score(text: &str, query: &str, query_words: &[&str]) -> f64 {
-        let mut score = 0.0;
-
-        // Exact match gets highest score
-        if text == query {
-            return 1000.0;
-        }
-
-        // Starts with query
-        if text.starts_with(query) {
-            score += 100.0;
-        }
-
-        // Contains query
-        if text.contains(query) {
-            score += 50.0;
-        }
-
-        // Word matches
-        let text_words: Vec<&str> = text.split_whitespace().collect();
-        for query_word in query_words {
-            for text_word in &text_words {
-                if *text_word == *query_word {
-                    score += 30.0;
-                } else if text_word.starts_with(query_word) {
-                    score += 20.0;
-                } else if text_word.contains(query_word) {
-                    score += 10.0;
-                }
-            }
+    /// Scale scores by the user's affinity for each item and sort again
+    ///
+    /// affinity is the item's plays on a log scale relative to the most played
+    /// result, plus a share for favorites, capped at one. a score grows by at
+    /// most `boost` times itself, so text relevance still leads
+    pub fn boost_personal<T: PersonalItem>(
+        results: &mut [SearchResult<T>],
+        user_id: i64,
+        boost: f64,
+    ) {
+        if boost <= 0.0 || results.is_empty() {
+            return;
         }
 
-        // Levenshtein distance for fuzzy matching
-        if score == 0.0 {
-            let distance = Self::levenshtein(text, query);
-            let max_len = text.len().max(query.len()) as f64;
-            if max_len > 0.0 {
-                let similarity = 1.0 - (distance as f64 / max_len);
-                if similarity > 0.5 {
-                    score = similarity * 20.0;
-                }
-            }
+        let max_plays = results
+            .iter()
+            .map(|r| r.item.playcount().max(0))
+            .max()
+            .unwrap_or(0);
+        let scale = (1.0 + max_plays as f64).ln();
+
+        for result in results.iter_mut() {
+            let plays = if scale > 0.0 {
+                (1.0 + result.item.playcount().max(0) as f64).ln() / scale
+            } else {
+                0.0
+            };
+            let favorite = if result.item.is_favorite(user_id) {
+                FAVORITE_AFFINITY
+            } else {
+                0.0
+            };
+            result.score *= 1.0 + boost * (plays + favorite).min(1.0);
         }
 
-        score
+        results.sort_by(|a, b| b.score.total_cmp(&a.score));
     }
 
-    /// Calculate Levenshtein distance
-    fn levenshtein(s1: &str, s2: &str) -> usize {
-        let m = s1.len();
-        let n = s2.len();
-
-        if m == 0 {
-            return n;
-        }
-        if n == 0 {
-            return m;
-        }
-
-        let mut dp = vec![vec![0; n + 1]; m + 1];
-
-        for i in 0..=m {
-            dp[i][0] = i;
-        }
-        for j in 0..=n {
-            dp[0][j] = j;
-        }
-
-        let s1_chars: Vec<char> = s1.chars().collect();
-        let s2_chars: Vec<char> = s2.chars().collect();
-
-        for i in 1..=m {
-            for j in 1..=n {
-                let cost = if s1_chars[i - 1] == s2_chars[j - 1] {
-                    0
-                } else {
-                    1
-                };
-                dp[i][j] = (dp[i - 1][j] + 1)
-                    .min(dp[i][j - 1] + 1)
-                    .min(dp[i - 1][j - 1] + cost);
-            }
-        }
+    /// Combined search across all types
+    pub fn search_all(
+        query: &str,
+        tracks_limit: usize,
+        albums_limit: usize,
+        artists_limit: usize,
+    ) -> (
+        Vec<SearchResult<Track>>,
+        Vec<SearchResult<Album>>,
+        Vec<SearchResult<Artist>>,
+    ) {
+        let tracks = Self::search_tracks(query, tracks_limit);
+        let albums = Self::search_albums(query, albums_limit);
+        let artists = Self::search_artists(query, artists_limit);
 
-        dp[m][n]
+        (tracks, albums, artists)
     }
 
     /// Top results by play count
     pub fn top_tracks(limit: usize, play_counts: &HashMap<String, i32>) -> Vec<Track> {
         let store = TrackStore::get();
         let mut tracks_with_plays: Vec<_> = store
-            .get_all()
+            .get_all_shared()
             .into_iter()
             .map(|t| {
                 let plays = play_counts.get(&t.trackhash).copied().unwrap_or(0);
@@ -199,7 +247,54 @@ impl SearchLib {
         tracks_with_plays
             .into_iter()
             .take(limit)
-            .map(|(t, _)| t)
+            .map(|(t, _)| t.as_ref().clone())
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn album(hash: &str, playcount: i32) -> SearchResult<Album> {
+        let mut item = Album::new(hash.to_string(), hash.to_string());
+        item.playcount = playcount;
+        SearchResult { item, score: 1.0 }
+    }
+
+    fn order(results: &[SearchResult<Album>]) -> Vec<&str> {
+        results.iter().map(|r| r.item.albumhash.as_str()).collect()
+    }
+
+    #[test]
+    fn test_boost_lifts_played_items() {
+        let mut results = vec![album("obscure", 0), album("mine", 40)];
+        SearchLib::boost_personal(&mut results, 1, 0.5);
+        assert_eq!(order(&results), ["mine", "obscure"]);
+        assert_eq!(results[0].score, 1.5);
+        assert_eq!(results[1].score, 1.0);
+    }
+
+    #[test]
+    fn test_boost_keeps_relevance_first() {
+        // a much better text match still wins over a played one
+        let mut results = vec![album("exact", 0), album("played", 100)];
+        results[0].score = 2.0;
+        SearchLib::boost_personal(&mut results, 1, 0.5);
+        assert_eq!(order(&results), ["exact", "played"]);
+    }
+
+    #[test]
+    fn test_boost_favorites_and_disabled() {
+        let mut favorite = album("favorite", 0);
+        favorite.item.fav_userids.insert(7);
+        let mut results = vec![album("plain", 0), favorite];
+
+        SearchLib::boost_personal(&mut results, 7, 0.0);
+        assert_eq!(order(&results), ["plain", "favorite"]);
+
+        SearchLib::boost_personal(&mut results, 7, 1.0);
+        assert_eq!(order(&results), ["favorite", "plain"]);
+        assert_eq!(results[0].score, 1.5);
+    }
+}
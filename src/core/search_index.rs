@@ -0,0 +1,447 @@
+//! Inverted index used by the search subsystem
+//!
+//! documents are tokenized into normalized terms (lowercased, deunicoded, split on
+//! non-alphanumeric boundaries) and stored in a sorted term dictionary so that exact,
+//! prefix and fuzzy lookups only touch the matching postings instead of every item.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+
+/// maximum number of dictionary terms a single prefix token may expand into
+const MAX_PREFIX_EXPANSIONS: usize = 512;
+
+/// query tokens shorter than this never use fuzzy matching
+const MIN_FUZZY_TOKEN_LEN: usize = 4;
+
+/// score given when the whole query equals the primary field
+const EXACT_MATCH_BONUS: f64 = 1000.0;
+
+/// score given when the primary field starts with the whole query
+const PREFIX_MATCH_BONUS: f64 = 100.0;
+
+/// score given when the primary field contains the whole query as a phrase
+const PHRASE_MATCH_BONUS: f64 = 50.0;
+
+/// weight of a single matched query token
+const TOKEN_MATCH_WEIGHT: f64 = 100.0;
+
+/// A single ranked search hit
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    /// Key of the matched document (entity hash)
+    pub key: String,
+    /// Relevance score, higher is better
+    pub score: f64,
+}
+
+/// Posting entry for a term
+#[derive(Debug, Clone, Copy)]
+struct Posting {
+    doc: u32,
+    /// highest field weight this term appears in for the document
+    weight: f32,
+}
+
+/// Indexed document
+#[derive(Debug, Clone)]
+struct IndexedDoc {
+    key: String,
+    /// normalized primary field used for phrase bonuses and tie breaking
+    primary: String,
+    terms: Vec<String>,
+}
+
+/// How a query token matched a dictionary term
+#[derive(Debug, Clone, Copy)]
+enum TermMatch {
+    Exact,
+    Prefix { ratio: f64 },
+    Fuzzy { distance: usize },
+}
+
+impl TermMatch {
+    fn quality(self) -> f64 {
+        match self {
+            TermMatch::Exact => 1.0,
+            // a longer typed prefix is a stronger signal than a couple of letters
+            TermMatch::Prefix { ratio } => 0.6 + 0.3 * ratio,
+            TermMatch::Fuzzy { distance } => 0.5 - 0.15 * distance as f64,
+        }
+    }
+}
+
+/// Inverted index over documents made of weighted text fields
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    terms: BTreeMap<String, Vec<Posting>>,
+    docs: Vec<Option<IndexedDoc>>,
+    free_slots: Vec<u32>,
+    ids: HashMap<String, u32>,
+}
+
+impl SearchIndex {
+    /// Create an empty index
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of indexed documents
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Whether the index holds no documents
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Remove every document
+    pub fn clear(&mut self) {
+        self.terms.clear();
+        self.docs.clear();
+        self.free_slots.clear();
+        self.ids.clear();
+    }
+
+    /// Insert or replace a document
+    ///
+    /// `fields` is a list of `(text, weight)` pairs; the first field is treated as the
+    /// primary name of the document.
+    pub fn insert(&mut self, key: &str, fields: &[(&str, f32)]) {
+        self.remove(key);
+
+        let primary = fields
+            .first()
+            .map(|(text, _)| normalize(text))
+            .unwrap_or_default();
+
+        let mut weights: HashMap<String, f32> = HashMap::new();
+        for (text, weight) in fields {
+            for token in tokenize(text) {
+                let entry = weights.entry(token).or_insert(0.0);
+                if *weight > *entry {
+                    *entry = *weight;
+                }
+            }
+        }
+
+        let doc = match self.free_slots.pop() {
+            Some(slot) => slot,
+            None => {
+                self.docs.push(None);
+                (self.docs.len() - 1) as u32
+            }
+        };
+
+        for (term, weight) in &weights {
+            let postings = self.terms.entry(term.clone()).or_default();
+            let posting = Posting {
+                doc,
+                weight: *weight,
+            };
+            match postings.binary_search_by_key(&doc, |p| p.doc) {
+                Ok(pos) => postings[pos] = posting,
+                Err(pos) => postings.insert(pos, posting),
+            }
+        }
+
+        self.docs[doc as usize] = Some(IndexedDoc {
+            key: key.to_string(),
+            primary,
+            terms: weights.into_keys().collect(),
+        });
+        self.ids.insert(key.to_string(), doc);
+    }
+
+    /// Remove a document, returning whether it was indexed
+    pub fn remove(&mut self, key: &str) -> bool {
+        let Some(doc) = self.ids.remove(key) else {
+            return false;
+        };
+
+        if let Some(indexed) = self.docs[doc as usize].take() {
+            for term in &indexed.terms {
+                let now_empty = match self.terms.get_mut(term) {
+                    Some(postings) => {
+                        if let Ok(pos) = postings.binary_search_by_key(&doc, |p| p.doc) {
+                            postings.remove(pos);
+                        }
+                        postings.is_empty()
+                    }
+                    None => false,
+                };
+
+                if now_empty {
+                    self.terms.remove(term);
+                }
+            }
+        }
+
+        self.free_slots.push(doc);
+        true
+    }
+
+    /// Search the index and return up to `limit` hits ordered by relevance
+    ///
+    /// every query token has to match (exactly, by prefix or fuzzily) for a document
+    /// to be returned.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        let tokens = tokenize(query);
+        if tokens.is_empty() || limit == 0 {
+            return Vec::new();
+        }
+
+        let mut per_token: Vec<HashMap<u32, f64>> = Vec::with_capacity(tokens.len());
+        for token in &tokens {
+            let scores = self.match_token(token);
+            if scores.is_empty() {
+                return Vec::new();
+            }
+            per_token.push(scores);
+        }
+
+        // intersect starting from the most selective token
+        per_token.sort_by_key(|scores| scores.len());
+        let (first, rest) = per_token.split_first().expect("at least one token");
+
+        let normalized_query = tokens.join(" ");
+        let mut hits: Vec<(SearchHit, usize)> = first
+            .iter()
+            .filter_map(|(doc, score)| {
+                let mut total = *score;
+                for other in rest {
+                    total += other.get(doc)?;
+                }
+
+                let indexed = self.docs[*doc as usize].as_ref()?;
+                let mut score = total * TOKEN_MATCH_WEIGHT / tokens.len() as f64;
+
+                if indexed.primary == normalized_query {
+                    score += EXACT_MATCH_BONUS;
+                } else if indexed.primary.starts_with(&normalized_query) {
+                    score += PREFIX_MATCH_BONUS;
+                } else if indexed.primary.contains(&normalized_query) {
+                    score += PHRASE_MATCH_BONUS;
+                }
+
+                Some((
+                    SearchHit {
+                        key: indexed.key.clone(),
+                        score,
+                    },
+                    indexed.primary.len(),
+                ))
+            })
+            .collect();
+
+        // shorter names win ties since more of them is covered by the query
+        hits.sort_by(|(a, a_len), (b, b_len)| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a_len.cmp(b_len))
+                .then_with(|| a.key.cmp(&b.key))
+        });
+
+        hits.into_iter().take(limit).map(|(hit, _)| hit).collect()
+    }
+
+    /// Collect per-document scores for a single query token
+    fn match_token(&self, token: &str) -> HashMap<u32, f64> {
+        let mut scores: HashMap<u32, f64> = HashMap::new();
+        let mut has_exact = false;
+
+        let mut expansions: Vec<(&String, &Vec<Posting>)> = self
+            .terms
+            .range::<str, _>((std::ops::Bound::Included(token), std::ops::Bound::Unbounded))
+            .take_while(|(term, _)| term.starts_with(token))
+            .collect();
+
+        if expansions.len() > MAX_PREFIX_EXPANSIONS {
+            // prefer the closest completions when a short prefix is very common
+            expansions.sort_by_key(|(term, _)| term.len());
+            expansions.truncate(MAX_PREFIX_EXPANSIONS);
+        }
+
+        for (term, postings) in expansions {
+            let kind = if term == token {
+                has_exact = true;
+                TermMatch::Exact
+            } else {
+                TermMatch::Prefix {
+                    ratio: token.len() as f64 / term.len() as f64,
+                }
+            };
+            accumulate(&mut scores, postings, kind);
+        }
+
+        // typo tolerance only kicks in when the token is not a known word
+        if !has_exact && token.chars().count() >= MIN_FUZZY_TOKEN_LEN {
+            let max_distance = if token.chars().count() >= 8 { 2 } else { 1 };
+            let token_len = token.chars().count();
+
+            for (term, postings) in &self.terms {
+                let term_len = term.chars().count();
+                if term_len.abs_diff(token_len) > max_distance || term.starts_with(token) {
+                    continue;
+                }
+
+                if let Some(distance) = bounded_levenshtein(token, term, max_distance) {
+                    accumulate(&mut scores, postings, TermMatch::Fuzzy { distance });
+                }
+            }
+        }
+
+        scores
+    }
+}
+
+/// Merge a term's postings into the running per-document scores
+fn accumulate(scores: &mut HashMap<u32, f64>, postings: &[Posting], kind: TermMatch) {
+    let quality = kind.quality();
+    for posting in postings {
+        let score = quality * posting.weight as f64;
+        let entry = scores.entry(posting.doc).or_insert(0.0);
+        if score > *entry {
+            *entry = score;
+        }
+    }
+}
+
+/// Split text into normalized search terms
+pub fn tokenize(text: &str) -> Vec<String> {
+    let decoded = deunicode::deunicode(text).to_lowercase();
+
+    decoded
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Normalize text into a single space separated string of terms
+pub fn normalize(text: &str) -> String {
+    tokenize(text).join(" ")
+}
+
+/// Levenshtein distance that gives up once `max` is exceeded
+fn bounded_levenshtein(a: &str, b: &str, max: usize) -> Option<usize> {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a_char) in a.iter().enumerate() {
+        current[0] = i + 1;
+        let mut row_min = current[0];
+
+        for (j, b_char) in b.iter().enumerate() {
+            let cost = usize::from(a_char != b_char);
+            current[j + 1] = (previous[j + 1] + 1)
+                .min(current[j] + 1)
+                .min(previous[j] + cost);
+            row_min = row_min.min(current[j + 1]);
+        }
+
+        if row_min > max {
+            return None;
+        }
+
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    let distance = previous[b.len()];
+    (distance <= max).then_some(distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index_with(docs: &[(&str, &str)]) -> SearchIndex {
+        let mut index = SearchIndex::new();
+        for (key, name) in docs {
+            index.insert(key, &[(name, 1.0)]);
+        }
+        index
+    }
+
+    fn keys(hits: &[SearchHit]) -> Vec<&str> {
+        hits.iter().map(|h| h.key.as_str()).collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(tokenize("AC/DC - Back in Black"), vec!["ac", "dc", "back", "in", "black"]);
+        assert_eq!(tokenize("Beyoncé"), vec!["beyonce"]);
+        assert!(tokenize("  -- ").is_empty());
+    }
+
+    #[test]
+    fn test_exact_match_ranks_first() {
+        let index = index_with(&[("a", "Blue Train"), ("b", "Blue"), ("c", "Kind of Blue")]);
+        let hits = index.search("blue", 10);
+        assert_eq!(keys(&hits), vec!["b", "a", "c"]);
+    }
+
+    #[test]
+    fn test_prefix_match() {
+        let index = index_with(&[("a", "Paranoid Android"), ("b", "Karma Police")]);
+        assert_eq!(keys(&index.search("para", 10)), vec!["a"]);
+        assert_eq!(keys(&index.search("karma pol", 10)), vec!["b"]);
+    }
+
+    #[test]
+    fn test_all_tokens_required() {
+        let index = index_with(&[("a", "Paranoid Android"), ("b", "Android Lust")]);
+        assert_eq!(keys(&index.search("android paranoid", 10)), vec!["a"]);
+        assert!(index.search("android missing", 10).is_empty());
+    }
+
+    #[test]
+    fn test_fuzzy_match() {
+        let index = index_with(&[("a", "Radiohead"), ("b", "Portishead")]);
+        assert_eq!(keys(&index.search("radiohaed", 10)), vec!["a"]);
+        // short tokens are never fuzzed
+        assert!(index.search("rdo", 10).is_empty());
+    }
+
+    #[test]
+    fn test_secondary_fields_rank_lower() {
+        let mut index = SearchIndex::new();
+        index.insert("a", &[("Something", 1.0), ("Bowie", 0.5)]);
+        index.insert("b", &[("Bowie Live", 1.0), ("Other", 0.5)]);
+        assert_eq!(keys(&index.search("bowie", 10)), vec!["b", "a"]);
+    }
+
+    #[test]
+    fn test_incremental_updates() {
+        let mut index = index_with(&[("a", "Alpha"), ("b", "Beta")]);
+        assert_eq!(index.len(), 2);
+
+        index.insert("a", &[("Gamma", 1.0)]);
+        assert!(index.search("alpha", 10).is_empty());
+        assert_eq!(keys(&index.search("gamma", 10)), vec!["a"]);
+
+        assert!(index.remove("b"));
+        assert!(!index.remove("b"));
+        assert!(index.search("beta", 10).is_empty());
+
+        // freed slots are reused without leaking stale postings
+        index.insert("c", &[("Delta", 1.0)]);
+        assert_eq!(keys(&index.search("delta", 10)), vec!["c"]);
+        assert_eq!(index.len(), 2);
+    }
+
+    #[test]
+    fn test_bounded_levenshtein() {
+        assert_eq!(bounded_levenshtein("kitten", "sitten", 1), Some(1));
+        assert_eq!(bounded_levenshtein("kitten", "sitting", 2), None);
+        assert_eq!(bounded_levenshtein("kitten", "sitting", 3), Some(3));
+    }
+}
@@ -0,0 +1,300 @@
+//! Artist similarity computed from the local library
+//!
+//! used when no last.fm similar artists are stored for an artist. three signals
+//! are blended: artists played close together in a listening session, artists
+//! that appear on the same albums, and overlap between the genres they are
+//! tagged with. results are written to the similar artists table marked as
+//! local, so every consumer of that table picks them up, and recomputed once
+//! they are older than `LOCAL_TTL`.
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use crate::db::tables::{ScrobblePoint, ScrobbleTable, SimilarArtistData, SimilarArtistTable};
+use crate::models::Track;
+use crate::stores::TrackStore;
+
+/// plays this close to each other count as the same listening session
+const SESSION_WINDOW_SECS: i64 = 30 * 60;
+
+/// how long locally computed results are served before recomputing
+const LOCAL_TTL: Duration = Duration::from_secs(24 * 3600);
+
+/// similar artists kept per artist
+const MAX_SIMILAR: usize = 30;
+
+/// candidates scoring below this are dropped
+const MIN_SCORE: f64 = 0.05;
+
+const SESSION_WEIGHT: f64 = 0.5;
+const ALBUM_WEIGHT: f64 = 0.3;
+const GENRE_WEIGHT: f64 = 0.2;
+
+/// artisthash to when its local similar artists were last computed
+static COMPUTED_AT: Lazy<RwLock<HashMap<String, Instant>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// Similar artists for an artist, computing them locally when last.fm has none
+///
+/// ordered by weight, most similar first
+pub async fn similar_artists(artisthash: &str) -> Result<Vec<SimilarArtistData>> {
+    let mut stored = SimilarArtistTable::get_similar_full(artisthash).await?;
+    let is_local = stored.iter().all(|s| s.local);
+
+    if !stored.is_empty() && !is_local {
+        stored.sort_by(|a, b| b.weight.total_cmp(&a.weight));
+        return Ok(stored);
+    }
+
+    let fresh = COMPUTED_AT
+        .read()
+        .get(artisthash)
+        .is_some_and(|at| at.elapsed() < LOCAL_TTL);
+    if fresh {
+        return Ok(stored);
+    }
+
+    let similar = compute(artisthash).await?;
+    SimilarArtistTable::insert(artisthash, &similar).await?;
+    COMPUTED_AT
+        .write()
+        .insert(artisthash.to_string(), Instant::now());
+    Ok(similar)
+}
+
+/// Compute similar artists from the library and every user's scrobbles
+pub async fn compute(artisthash: &str) -> Result<Vec<SimilarArtistData>> {
+    let history = ScrobbleTable::history().await?;
+    let tracks = TrackStore::get().get_all();
+    let artisthash = artisthash.to_string();
+
+    // scoring walks the whole library so keep it off the async workers
+    let similar =
+        tokio::task::spawn_blocking(move || score(&artisthash, &tracks, &history)).await?;
+    Ok(similar)
+}
+
+/// Blend the session, album and genre signals into weighted similar artists
+fn score(target: &str, tracks: &[Track], history: &[ScrobblePoint]) -> Vec<SimilarArtistData> {
+    let mut names: HashMap<&str, &str> = HashMap::new();
+    let mut track_artists: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut album_artists: HashMap<&str, HashSet<&str>> = HashMap::new();
+    let mut genres: HashMap<&str, HashSet<&str>> = HashMap::new();
+
+    for track in tracks {
+        let artists: Vec<&str> = track
+            .artists
+            .iter()
+            .chain(&track.albumartists)
+            .map(|a| {
+                names.entry(&a.artisthash).or_insert(&a.name);
+                a.artisthash.as_str()
+            })
+            .collect();
+
+        for &artist in &artists {
+            genres
+                .entry(artist)
+                .or_default()
+                .extend(track.genrehashes.iter().map(String::as_str));
+        }
+        album_artists
+            .entry(&track.albumhash)
+            .or_default()
+            .extend(artists.iter().copied());
+        track_artists.insert(&track.trackhash, artists);
+    }
+
+    let sessions = session_counts(target, &track_artists, history);
+    let albums = shared_album_counts(target, &album_artists);
+
+    let empty = HashSet::new();
+    let target_genres = genres.get(target).unwrap_or(&empty);
+    let max_sessions = sessions.values().copied().max().unwrap_or(0).max(1) as f64;
+    let max_albums = albums.values().copied().max().unwrap_or(0).max(1) as f64;
+
+    let mut scored: Vec<SimilarArtistData> = names
+        .iter()
+        .filter(|(hash, _)| **hash != target)
+        .filter_map(|(&hash, &name)| {
+            let session = sessions.get(hash).copied().unwrap_or(0);
+            let album = albums.get(hash).copied().unwrap_or(0);
+            let genre = jaccard(target_genres, genres.get(hash).unwrap_or(&empty));
+
+            let weight = SESSION_WEIGHT * session as f64 / max_sessions
+                + ALBUM_WEIGHT * album as f64 / max_albums
+                + GENRE_WEIGHT * genre;
+            (weight >= MIN_SCORE).then(|| SimilarArtistData {
+                artisthash: hash.to_string(),
+                name: name.to_string(),
+                weight: (weight * 1000.0).round() / 1000.0,
+                scrobbles: session as i64,
+                listeners: 0,
+                local: true,
+            })
+        })
+        .collect();
+
+    scored.sort_by(|a, b| {
+        b.weight
+            .total_cmp(&a.weight)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    scored.truncate(MAX_SIMILAR);
+    scored
+}
+
+/// How often each artist was played in the same session as a play of `target`
+///
+/// `history` must be ordered by user then time
+fn session_counts<'a>(
+    target: &str,
+    track_artists: &HashMap<&str, Vec<&'a str>>,
+    history: &[ScrobblePoint],
+) -> HashMap<&'a str, usize> {
+    let artists_of = |play: &ScrobblePoint| {
+        track_artists
+            .get(play.trackhash.as_str())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    };
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for (i, play) in history.iter().enumerate() {
+        if !artists_of(play).contains(&target) {
+            continue;
+        }
+
+        let in_session = |other: &&ScrobblePoint| {
+            other.userid == play.userid
+                && (other.timestamp - play.timestamp).abs() <= SESSION_WINDOW_SECS
+        };
+        let before = history[..i].iter().rev().take_while(in_session);
+        let after = history[i + 1..].iter().take_while(in_session);
+
+        // each artist counts once per play of the target
+        let neighbours: HashSet<&str> = before
+            .chain(after)
+            .flat_map(|other| artists_of(other).iter().copied())
+            .filter(|artist| *artist != target)
+            .collect();
+        for artist in neighbours {
+            *counts.entry(artist).or_default() += 1;
+        }
+    }
+    counts
+}
+
+/// Number of albums each artist shares with `target`
+fn shared_album_counts<'a>(
+    target: &str,
+    album_artists: &HashMap<&str, HashSet<&'a str>>,
+) -> HashMap<&'a str, usize> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for artists in album_artists.values() {
+        if !artists.contains(target) {
+            continue;
+        }
+        for artist in artists.iter().filter(|a| **a != target) {
+            *counts.entry(artist).or_default() += 1;
+        }
+    }
+    counts
+}
+
+fn jaccard(a: &HashSet<&str>, b: &HashSet<&str>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ArtistRefItem;
+
+    fn track(hash: &str, album: &str, artists: &[&str], genres: &[&str]) -> Track {
+        Track {
+            trackhash: hash.to_string(),
+            albumhash: album.to_string(),
+            artists: artists
+                .iter()
+                .map(|a| ArtistRefItem::new(a.to_uppercase(), a.to_string()))
+                .collect(),
+            genrehashes: genres.iter().map(|g| g.to_string()).collect(),
+            ..Track::new()
+        }
+    }
+
+    fn play(userid: i64, trackhash: &str, timestamp: i64) -> ScrobblePoint {
+        ScrobblePoint {
+            userid,
+            trackhash: trackhash.to_string(),
+            timestamp,
+        }
+    }
+
+    fn library() -> Vec<Track> {
+        vec![
+            track("t1", "al1", &["a"], &["rock"]),
+            track("t2", "al1", &["a", "b"], &["rock"]),
+            track("t3", "al2", &["c"], &["rock", "indie"]),
+            track("t4", "al3", &["d"], &["jazz"]),
+            track("t5", "al4", &["e"], &["rock"]),
+        ]
+    }
+
+    #[test]
+    fn test_session_counts() {
+        let tracks = library();
+        let mut track_artists: HashMap<&str, Vec<&str>> = HashMap::new();
+        for t in &tracks {
+            track_artists.insert(
+                &t.trackhash,
+                t.artists.iter().map(|a| a.artisthash.as_str()).collect(),
+            );
+        }
+
+        let history = [
+            play(1, "t3", 0),
+            play(1, "t1", 600),
+            play(1, "t4", 1200),
+            // a new session, too far from the previous play of a
+            play(1, "t5", 10_000),
+            // another user's play at the same time is not a neighbour
+            play(2, "t4", 600),
+            play(2, "t1", 5000),
+            play(2, "t3", 5100),
+        ];
+        let counts = session_counts("a", &track_artists, &history);
+        assert_eq!(counts.get("c"), Some(&2));
+        assert_eq!(counts.get("d"), Some(&1));
+        assert_eq!(counts.get("e"), None);
+    }
+
+    #[test]
+    fn test_score_blends_signals() {
+        let tracks = library();
+        let history = [play(1, "t1", 0), play(1, "t3", 60)];
+        let similar = score("a", &tracks, &history);
+        let hashes: Vec<&str> = similar.iter().map(|s| s.artisthash.as_str()).collect();
+
+        // c was played alongside a and shares a genre, b shares an album
+        assert_eq!(hashes, ["c", "b", "e"]);
+        assert!(similar.iter().all(|s| s.local));
+        assert_eq!(similar[0].name, "C");
+        assert_eq!(similar[0].scrobbles, 1);
+        // jazz only artist d has nothing in common with a
+        assert!(!hashes.contains(&"d"));
+    }
+
+    #[test]
+    fn test_score_unknown_artist() {
+        assert!(score("missing", &library(), &[]).is_empty());
+    }
+}
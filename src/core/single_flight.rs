@@ -0,0 +1,106 @@
+//! Background passes that run one at a time
+//!
+//! a pass asked for while one runs is not started next to it. the running pass
+//! goes again once it is done instead, so files changed in the meantime are
+//! still picked up.
+
+use anyhow::Result;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Guard that lets a single pass run at a time
+pub struct SingleFlight {
+    running: AtomicBool,
+    /// set when a pass is asked for while one runs, so the running pass goes again
+    pending: AtomicBool,
+}
+
+impl SingleFlight {
+    pub const fn new() -> Self {
+        Self {
+            running: AtomicBool::new(false),
+            pending: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether a pass is running
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Acquire)
+    }
+
+    /// Run `pass` until no other pass was asked for while it ran
+    ///
+    /// returns the summed counts of the runs, or 0 right away when a pass is
+    /// already running
+    pub async fn run<F, Fut>(&self, mut pass: F) -> Result<usize>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<usize>>,
+    {
+        if self.running.swap(true, Ordering::AcqRel) {
+            self.pending.store(true, Ordering::Release);
+            return Ok(0);
+        }
+        let _guard = RunningGuard(&self.running);
+
+        let mut done = 0;
+        loop {
+            self.pending.store(false, Ordering::Release);
+            done += pass().await?;
+            if !self.pending.load(Ordering::Acquire) {
+                return Ok(done);
+            }
+        }
+    }
+}
+
+impl Default for SingleFlight {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct RunningGuard<'a>(&'a AtomicBool);
+
+impl Drop for RunningGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use std::sync::atomic::AtomicUsize;
+
+    #[tokio::test]
+    async fn test_pass_asked_for_while_running_runs_again() {
+        let flight = SingleFlight::new();
+        let runs = AtomicUsize::new(0);
+
+        let done = flight
+            .run(|| async {
+                // a second request while the first run is going
+                if runs.fetch_add(1, Ordering::SeqCst) == 0 {
+                    assert!(flight.is_running());
+                    assert_eq!(flight.run(|| async { Ok(100) }).await.unwrap(), 0);
+                }
+                Ok(2)
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        assert_eq!(done, 4);
+        assert!(!flight.is_running());
+    }
+
+    #[tokio::test]
+    async fn test_failed_pass_lets_the_next_one_run() {
+        let flight = SingleFlight::new();
+        assert!(flight.run(|| async { Err(anyhow!("boom")) }).await.is_err());
+        assert!(!flight.is_running());
+        assert_eq!(flight.run(|| async { Ok(3) }).await.unwrap(), 3);
+    }
+}
@@ -1,85 +1,578 @@
-//! Sorting utilities for tracks, albums, artists
+//! Sorting utilities for tracks, folders, albums, artists
+//!
+//! every endpoint that takes a sort key parses it into one of the enums here so
+//! the same key orders items the same way everywhere. unknown keys fall back to
+//! a sensible default instead of failing the request, matching upstream.
+//!
+//! a sort parameter may also list several keys, e.g.
+//! `albumartist,year,album,disc,track`, each optionally suffixed with `:asc` or
+//! `:desc`. later keys only break ties left by earlier ones and a final
+//! tiebreak on a unique field keeps pages stable between requests.
 
+use serde::Deserialize;
+use std::borrow::Borrow;
 use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::time::UNIX_EPOCH;
 
-use crate::models::{Album, Artist, Track};
+use crate::models::{Album, Artist, Folder, Track};
 
-/// Sort order
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum SortOrder {
-    Ascending,
-    Descending,
+pub use crate::models::SortOrder;
+
+impl SortOrder {
+    /// Order from the `reverse` flag most endpoints take
+    pub fn from_reverse(reverse: bool) -> Self {
+        if reverse {
+            SortOrder::Descending
+        } else {
+            SortOrder::Ascending
+        }
+    }
+
+    /// Parse "asc" or "desc", anything else is ascending
+    pub fn parse(order: &str) -> Self {
+        match order.trim().to_lowercase().as_str() {
+            "desc" | "descending" => SortOrder::Descending,
+            _ => SortOrder::Ascending,
+        }
+    }
+
+    /// Apply the order to an ascending comparison
+    pub fn apply(&self, ordering: Ordering) -> Ordering {
+        match self {
+            SortOrder::Ascending => ordering,
+            SortOrder::Descending => ordering.reverse(),
+        }
+    }
 }
 
-/// Sort field for tracks
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum TrackSortBy {
+/// Sort key for track lists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(from = "String")]
+pub enum TrackSort {
+    /// keep the order the tracks came in
+    #[default]
+    Default,
     Title,
     Album,
-    Artist,
-    Duration,
-    DateAdded,
-    TrackNumber,
-    DiscNumber,
-    Year,
+    AlbumArtists,
+    Artists,
     Bitrate,
-    PlayCount,
+    /// release date
+    Date,
+    /// album, then disc and track number
+    Disc,
+    TrackNumber,
+    Duration,
+    /// file modification time
+    LastMod,
     LastPlayed,
+    PlayCount,
+    PlayDuration,
+    /// star rating of the requesting user, falling back to the tags
+    Rating,
 }
 
-/// Sort field for albums
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum AlbumSortBy {
+impl TrackSort {
+    /// Parse a sort key, unknown keys sort by title
+    pub fn parse(key: &str) -> Self {
+        Self::try_parse(key).unwrap_or(TrackSort::Title)
+    }
+
+    /// Parse a sort key, `None` for unknown keys
+    pub fn try_parse(key: &str) -> Option<Self> {
+        let sort = match key.trim().to_lowercase().as_str() {
+            "default" | "" => TrackSort::Default,
+            "title" => TrackSort::Title,
+            "album" => TrackSort::Album,
+            "albumartists" | "albumartist" => TrackSort::AlbumArtists,
+            "artists" | "artist" => TrackSort::Artists,
+            "bitrate" => TrackSort::Bitrate,
+            "date" | "year" => TrackSort::Date,
+            "disc" => TrackSort::Disc,
+            "track" | "tracknumber" => TrackSort::TrackNumber,
+            "duration" => TrackSort::Duration,
+            "last_mod" | "lastmod" | "date_added" | "created" => TrackSort::LastMod,
+            "lastplayed" => TrackSort::LastPlayed,
+            "playcount" => TrackSort::PlayCount,
+            "playduration" => TrackSort::PlayDuration,
+            "rating" | "stars" => TrackSort::Rating,
+            _ => return None,
+        };
+        Some(sort)
+    }
+
+    /// Canonical key as sent by the client
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrackSort::Default => "default",
+            TrackSort::Title => "title",
+            TrackSort::Album => "album",
+            TrackSort::AlbumArtists => "albumartists",
+            TrackSort::Artists => "artists",
+            TrackSort::Bitrate => "bitrate",
+            TrackSort::Date => "date",
+            TrackSort::Disc => "disc",
+            TrackSort::TrackNumber => "track",
+            TrackSort::Duration => "duration",
+            TrackSort::LastMod => "last_mod",
+            TrackSort::LastPlayed => "lastplayed",
+            TrackSort::PlayCount => "playcount",
+            TrackSort::PlayDuration => "playduration",
+            TrackSort::Rating => "rating",
+        }
+    }
+
+    /// Compare two tracks ascending on this key, ties are broken by title
+    pub fn compare(&self, a: &Track, b: &Track) -> Ordering {
+        self.compare_key(a, b)
+            .then_with(|| lower(&a.title).cmp(&lower(&b.title)))
+    }
+
+    /// Compare two tracks ascending on this key alone
+    pub fn compare_key(&self, a: &Track, b: &Track) -> Ordering {
+        match self {
+            TrackSort::Default => Ordering::Equal,
+            TrackSort::Title => lower(&a.title).cmp(&lower(&b.title)),
+            TrackSort::Album => lower(&a.album).cmp(&lower(&b.album)),
+            TrackSort::AlbumArtists => {
+                first_name(&a.albumartists).cmp(&first_name(&b.albumartists))
+            }
+            TrackSort::Artists => first_name(&a.artists).cmp(&first_name(&b.artists)),
+            TrackSort::Bitrate => a.bitrate.cmp(&b.bitrate),
+            TrackSort::Date => a.date.cmp(&b.date),
+            TrackSort::Disc => lower(&a.album)
+                .cmp(&lower(&b.album))
+                .then_with(|| (a.disc, a.track).cmp(&(b.disc, b.track))),
+            TrackSort::TrackNumber => a.track.cmp(&b.track),
+            TrackSort::Duration => a.duration.cmp(&b.duration),
+            TrackSort::LastMod => a.last_mod.cmp(&b.last_mod),
+            TrackSort::LastPlayed => a.lastplayed.cmp(&b.lastplayed),
+            TrackSort::PlayCount => a.playcount.cmp(&b.playcount),
+            TrackSort::PlayDuration => a.playduration.cmp(&b.playduration),
+            TrackSort::Rating => a.rating.cmp(&b.rating),
+        }
+    }
+}
+
+impl From<String> for TrackSort {
+    fn from(key: String) -> Self {
+        Self::parse(&key)
+    }
+}
+
+/// Sort key for folder listings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(from = "String")]
+pub enum FolderSort {
+    /// keep the order the folders were read in
+    #[default]
+    Default,
+    Name,
+    TrackCount,
+    /// directory modification time
+    LastMod,
+}
+
+impl FolderSort {
+    /// Parse a sort key, unknown keys sort by name
+    pub fn parse(key: &str) -> Self {
+        Self::try_parse(key).unwrap_or(FolderSort::Name)
+    }
+
+    /// Parse a sort key, `None` for unknown keys
+    pub fn try_parse(key: &str) -> Option<Self> {
+        let sort = match key.trim().to_lowercase().as_str() {
+            "default" | "" => FolderSort::Default,
+            "name" => FolderSort::Name,
+            "trackcount" => FolderSort::TrackCount,
+            "lastmod" | "last_mod" => FolderSort::LastMod,
+            _ => return None,
+        };
+        Some(sort)
+    }
+
+    /// Canonical key as sent by the client
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FolderSort::Default => "default",
+            FolderSort::Name => "name",
+            FolderSort::TrackCount => "trackcount",
+            FolderSort::LastMod => "lastmod",
+        }
+    }
+}
+
+impl From<String> for FolderSort {
+    fn from(key: String) -> Self {
+        Self::parse(&key)
+    }
+}
+
+/// Fields a folder listing entry is sorted on
+pub trait FolderSortFields {
+    fn sort_name(&self) -> &str;
+    fn sort_path(&self) -> &str;
+    fn sort_trackcount(&self) -> i32;
+}
+
+impl FolderSortFields for Folder {
+    fn sort_name(&self) -> &str {
+        &self.name
+    }
+
+    fn sort_path(&self) -> &str {
+        &self.path
+    }
+
+    fn sort_trackcount(&self) -> i32 {
+        self.trackcount
+    }
+}
+
+/// Sort key for album lists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(from = "String")]
+pub enum AlbumSort {
     Title,
-    Artist,
-    Year,
+    AlbumArtists,
+    /// release date
+    Date,
     TrackCount,
     Duration,
-    DateAdded,
+    /// when the album was added to the library
+    #[default]
+    CreatedDate,
     PlayCount,
+    PlayDuration,
+    LastPlayed,
 }
 
-/// Sort field for artists
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum ArtistSortBy {
+impl AlbumSort {
+    /// Parse a sort key, unknown keys sort by date added
+    pub fn parse(key: &str) -> Self {
+        Self::try_parse(key).unwrap_or(AlbumSort::CreatedDate)
+    }
+
+    /// Parse a sort key, `None` for unknown keys
+    pub fn try_parse(key: &str) -> Option<Self> {
+        let sort = match key.trim().to_lowercase().as_str() {
+            "title" | "album" => AlbumSort::Title,
+            "albumartists" | "albumartist" | "artists" | "artist" => AlbumSort::AlbumArtists,
+            "date" | "year" => AlbumSort::Date,
+            "trackcount" | "tracks" => AlbumSort::TrackCount,
+            "duration" => AlbumSort::Duration,
+            "created_date" | "date_added" | "created" => AlbumSort::CreatedDate,
+            "playcount" => AlbumSort::PlayCount,
+            "playduration" => AlbumSort::PlayDuration,
+            "lastplayed" => AlbumSort::LastPlayed,
+            _ => return None,
+        };
+        Some(sort)
+    }
+
+    /// Canonical key as sent by the client
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlbumSort::Title => "title",
+            AlbumSort::AlbumArtists => "albumartists",
+            AlbumSort::Date => "date",
+            AlbumSort::TrackCount => "trackcount",
+            AlbumSort::Duration => "duration",
+            AlbumSort::CreatedDate => "created_date",
+            AlbumSort::PlayCount => "playcount",
+            AlbumSort::PlayDuration => "playduration",
+            AlbumSort::LastPlayed => "lastplayed",
+        }
+    }
+
+    /// Compare two albums ascending on this key
+    pub fn compare(&self, a: &Album, b: &Album) -> Ordering {
+        match self {
+            AlbumSort::Title => lower(&a.title).cmp(&lower(&b.title)),
+            AlbumSort::AlbumArtists => {
+                first_name(&a.albumartists).cmp(&first_name(&b.albumartists))
+            }
+            AlbumSort::Date => a.date.cmp(&b.date),
+            AlbumSort::TrackCount => a.trackcount.cmp(&b.trackcount),
+            AlbumSort::Duration => a.duration.cmp(&b.duration),
+            AlbumSort::CreatedDate => a.created_date.cmp(&b.created_date),
+            AlbumSort::PlayCount => a.playcount.cmp(&b.playcount),
+            AlbumSort::PlayDuration => a.playduration.cmp(&b.playduration),
+            AlbumSort::LastPlayed => a.lastplayed.cmp(&b.lastplayed),
+        }
+    }
+}
+
+impl From<String> for AlbumSort {
+    fn from(key: String) -> Self {
+        Self::parse(&key)
+    }
+}
+
+/// Sort key for artist lists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(from = "String")]
+pub enum ArtistSort {
     Name,
     TrackCount,
     AlbumCount,
+    Duration,
+    /// when the artist was added to the library
+    #[default]
+    CreatedDate,
     PlayCount,
+    PlayDuration,
+    LastPlayed,
+}
+
+impl ArtistSort {
+    /// Parse a sort key, unknown keys sort by date added
+    pub fn parse(key: &str) -> Self {
+        Self::try_parse(key).unwrap_or(ArtistSort::CreatedDate)
+    }
+
+    /// Parse a sort key, `None` for unknown keys
+    pub fn try_parse(key: &str) -> Option<Self> {
+        let sort = match key.trim().to_lowercase().as_str() {
+            "name" | "artist" => ArtistSort::Name,
+            "trackcount" | "tracks" => ArtistSort::TrackCount,
+            "albumcount" | "albums" => ArtistSort::AlbumCount,
+            "duration" => ArtistSort::Duration,
+            "created_date" | "date_added" | "created" => ArtistSort::CreatedDate,
+            "playcount" => ArtistSort::PlayCount,
+            "playduration" => ArtistSort::PlayDuration,
+            "lastplayed" => ArtistSort::LastPlayed,
+            _ => return None,
+        };
+        Some(sort)
+    }
+
+    /// Canonical key as sent by the client
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArtistSort::Name => "name",
+            ArtistSort::TrackCount => "trackcount",
+            ArtistSort::AlbumCount => "albumcount",
+            ArtistSort::Duration => "duration",
+            ArtistSort::CreatedDate => "created_date",
+            ArtistSort::PlayCount => "playcount",
+            ArtistSort::PlayDuration => "playduration",
+            ArtistSort::LastPlayed => "lastplayed",
+        }
+    }
+
+    /// Compare two artists ascending on this key
+    pub fn compare(&self, a: &Artist, b: &Artist) -> Ordering {
+        match self {
+            ArtistSort::Name => lower(&a.name).cmp(&lower(&b.name)),
+            ArtistSort::TrackCount => a.trackcount.cmp(&b.trackcount),
+            ArtistSort::AlbumCount => a.albumcount.cmp(&b.albumcount),
+            ArtistSort::Duration => a.duration.cmp(&b.duration),
+            ArtistSort::CreatedDate => a.created_date.cmp(&b.created_date),
+            ArtistSort::PlayCount => a.playcount.cmp(&b.playcount),
+            ArtistSort::PlayDuration => a.playduration.cmp(&b.playduration),
+            ArtistSort::LastPlayed => a.lastplayed.cmp(&b.lastplayed),
+        }
+    }
+}
+
+impl From<String> for ArtistSort {
+    fn from(key: String) -> Self {
+        Self::parse(&key)
+    }
+}
+
+/// A sort key enum that can appear in a compound sort
+pub trait SortField: Copy + Default + PartialEq {
+    /// Parse a sort key, falling back like the endpoint always has
+    fn parse(key: &str) -> Self;
+
+    /// Parse a sort key, `None` for unknown keys
+    fn try_parse(key: &str) -> Option<Self>;
+}
+
+macro_rules! impl_sort_field {
+    ($($sort:ty),*) => {
+        $(impl SortField for $sort {
+            fn parse(key: &str) -> Self {
+                <$sort>::parse(key)
+            }
+
+            fn try_parse(key: &str) -> Option<Self> {
+                <$sort>::try_parse(key)
+            }
+        })*
+    };
+}
+
+impl_sort_field!(TrackSort, FolderSort, AlbumSort, ArtistSort);
+
+/// One key of a compound sort
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey<K> {
+    pub by: K,
+    /// order of this key alone, `None` follows the order of the request
+    pub order: Option<SortOrder>,
+}
+
+impl<K> SortKey<K> {
+    fn order_or(&self, order: SortOrder) -> SortOrder {
+        self.order.unwrap_or(order)
+    }
+}
+
+/// Several sort keys applied in turn, e.g. `albumartist,year:desc,album`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "String", bound(deserialize = "K: SortField"))]
+pub struct CompoundSort<K> {
+    keys: Vec<SortKey<K>>,
+}
+
+impl<K: SortField> CompoundSort<K> {
+    /// Parse a comma separated list of keys
+    ///
+    /// a single key parses exactly like the key enum does, unknown keys in a
+    /// list are skipped and a list with no known keys falls back the same way
+    pub fn parse(spec: &str) -> Self {
+        let keys: Vec<SortKey<K>> = spec
+            .split(',')
+            .filter(|part| !part.trim().is_empty())
+            .filter_map(|part| {
+                let (key, order) = match part.split_once(':') {
+                    Some((key, order)) => (key, Some(SortOrder::parse(order))),
+                    None => (part, None),
+                };
+                K::try_parse(key).map(|by| SortKey { by, order })
+            })
+            .collect();
+
+        if keys.is_empty() || !spec.contains(',') {
+            let (key, order) = split_sort(spec);
+            let order = spec.contains(':').then_some(order);
+            return Self {
+                keys: vec![SortKey {
+                    by: K::parse(key),
+                    order,
+                }],
+            };
+        }
+        Self { keys }
+    }
+
+    /// Sort on a single key in the order of the request
+    pub fn single(by: K) -> Self {
+        Self {
+            keys: vec![SortKey { by, order: None }],
+        }
+    }
+
+    /// The keys in the order they apply, the default key when none were given
+    pub fn keys(&self) -> Vec<SortKey<K>> {
+        if self.keys.is_empty() {
+            vec![SortKey {
+                by: K::default(),
+                order: None,
+            }]
+        } else {
+            self.keys.clone()
+        }
+    }
+
+    /// The key that decides the order first
+    pub fn primary(&self) -> K {
+        self.keys.first().map(|k| k.by).unwrap_or_default()
+    }
+
+    /// Order of the primary key, final tiebreaks follow it
+    fn primary_order(&self, order: SortOrder) -> SortOrder {
+        self.keys.first().map_or(order, |k| k.order_or(order))
+    }
+}
+
+impl<K> Default for CompoundSort<K> {
+    fn default() -> Self {
+        Self { keys: Vec::new() }
+    }
+}
+
+impl<K: SortField> From<K> for CompoundSort<K> {
+    fn from(by: K) -> Self {
+        Self::single(by)
+    }
+}
+
+impl<K: SortField> From<String> for CompoundSort<K> {
+    fn from(spec: String) -> Self {
+        Self::parse(&spec)
+    }
+}
+
+/// Compare on each key in turn until one of them differs
+fn compare_keys<K: Copy, T>(
+    keys: &[SortKey<K>],
+    order: SortOrder,
+    a: &T,
+    b: &T,
+    compare: impl Fn(K, &T, &T) -> Ordering,
+) -> Ordering {
+    keys.iter()
+        .map(|key| key.order_or(order).apply(compare(key.by, a, b)))
+        .find(|cmp| *cmp != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+fn lower(s: &str) -> String {
+    s.to_lowercase()
+}
+
+fn first_name(artists: &[crate::models::ArtistRefItem]) -> Option<String> {
+    artists.first().map(|ar| ar.name.to_lowercase())
+}
+
+fn modified_secs(path: &str) -> u64 {
+    std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
 }
 
 /// Sorting library
 pub struct SortLib;
 
 impl SortLib {
-    /// Sort tracks by field
-    pub fn sort_tracks(tracks: &mut [Track], by: TrackSortBy, order: SortOrder) {
-        tracks.sort_by(|a, b| {
-            let cmp = match by {
-                TrackSortBy::Title => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
-                TrackSortBy::Album => a.album.to_lowercase().cmp(&b.album.to_lowercase()),
-                TrackSortBy::Artist => a.artist().to_lowercase().cmp(&b.artist().to_lowercase()),
-                TrackSortBy::Duration => a.duration.cmp(&b.duration),
-                TrackSortBy::DateAdded => a.date.cmp(&b.date),
-                TrackSortBy::TrackNumber => a.track.cmp(&b.track),
-                TrackSortBy::DiscNumber => {
-                    let dc = a.disc.cmp(&b.disc);
-                    if dc == Ordering::Equal {
-                        a.track.cmp(&b.track)
-                    } else {
-                        dc
-                    }
-                }
-                TrackSortBy::Year => a.date.cmp(&b.date),
-                TrackSortBy::Bitrate => a.bitrate.cmp(&b.bitrate),
-                TrackSortBy::PlayCount => Ordering::Equal, // Requires external data
-                TrackSortBy::LastPlayed => Ordering::Equal, // Requires external data
-            };
+    /// Sort tracks by key, the default key only applies the order
+    pub fn sort_tracks(tracks: &mut [Track], by: TrackSort, order: SortOrder) {
+        Self::sort_tracks_by(tracks, &CompoundSort::single(by), order);
+    }
 
-            match order {
-                SortOrder::Ascending => cmp,
-                SortOrder::Descending => cmp.reverse(),
+    /// Sort tracks on several keys, remaining ties are broken by title and path
+    ///
+    /// takes shared tracks as well, so large lists can be sorted without copies
+    pub fn sort_tracks_by<T: Borrow<Track>>(
+        tracks: &mut [T],
+        sort: &CompoundSort<TrackSort>,
+        order: SortOrder,
+    ) {
+        let keys = sort.keys();
+        let tiebreak = sort.primary_order(order);
+        if keys.iter().all(|k| k.by == TrackSort::Default) {
+            if tiebreak == SortOrder::Descending {
+                tracks.reverse();
             }
+            return;
+        }
+        tracks.sort_by(|a, b| {
+            let (a, b) = (a.borrow(), b.borrow());
+            compare_keys(&keys, order, a, b, |by, a, b| by.compare_key(a, b)).then_with(|| {
+                tiebreak.apply(
+                    lower(&a.title)
+                        .cmp(&lower(&b.title))
+                        .then_with(|| a.filepath.cmp(&b.filepath)),
+                )
+            })
         });
     }
 
@@ -95,115 +588,504 @@ impl SortLib {
         });
     }
 
-    /// Sort albums by field
-    pub fn sort_albums(albums: &mut [Album], by: AlbumSortBy, order: SortOrder) {
-        albums.sort_by(|a, b| {
-            let cmp = match by {
-                AlbumSortBy::Title => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
-                AlbumSortBy::Artist => a
-                    .albumartist()
-                    .to_lowercase()
-                    .cmp(&b.albumartist().to_lowercase()),
-                AlbumSortBy::Year => a.date.cmp(&b.date),
-                AlbumSortBy::TrackCount => a.count().cmp(&b.count()),
-                AlbumSortBy::Duration => a.duration.cmp(&b.duration),
-                AlbumSortBy::DateAdded => a.date.cmp(&b.date),
-                // Models store playcount without underscore
-                AlbumSortBy::PlayCount => a.playcount.cmp(&b.playcount),
-            };
+    /// Sort folder entries by key, the default key only applies the order
+    pub fn sort_folders<F: FolderSortFields>(folders: &mut [F], by: FolderSort, order: SortOrder) {
+        Self::sort_folders_by(folders, &CompoundSort::single(by), order);
+    }
 
-            match order {
-                SortOrder::Ascending => cmp,
-                SortOrder::Descending => cmp.reverse(),
+    /// Sort folder entries on several keys, full ties keep their order
+    pub fn sort_folders_by<F: FolderSortFields>(
+        folders: &mut [F],
+        sort: &CompoundSort<FolderSort>,
+        order: SortOrder,
+    ) {
+        let keys = sort.keys();
+        if keys.iter().all(|k| k.by == FolderSort::Default) {
+            if sort.primary_order(order) == SortOrder::Descending {
+                folders.reverse();
             }
+            return;
+        }
+
+        // stat each folder once rather than on every comparison
+        let mtimes: HashMap<String, u64> = if keys.iter().any(|k| k.by == FolderSort::LastMod) {
+            folders
+                .iter()
+                .map(|f| (f.sort_path().to_string(), modified_secs(f.sort_path())))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+        let mtime = |f: &F| mtimes.get(f.sort_path()).copied().unwrap_or(0);
+
+        folders.sort_by(|a, b| {
+            compare_keys(&keys, order, a, b, |by, a, b| match by {
+                FolderSort::Default => Ordering::Equal,
+                FolderSort::Name => lower(a.sort_name()).cmp(&lower(b.sort_name())),
+                FolderSort::TrackCount => a.sort_trackcount().cmp(&b.sort_trackcount()),
+                FolderSort::LastMod => mtime(a).cmp(&mtime(b)),
+            })
         });
     }
 
-    /// Sort artists by field
-    pub fn sort_artists(artists: &mut [Artist], by: ArtistSortBy, order: SortOrder) {
-        artists.sort_by(|a, b| {
-            let cmp = match by {
-                ArtistSortBy::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
-                ArtistSortBy::TrackCount => a.trackcount.cmp(&b.trackcount),
-                ArtistSortBy::AlbumCount => a.albumcount.cmp(&b.albumcount),
-                // Models store playcount without underscore
-                ArtistSortBy::PlayCount => a.playcount.cmp(&b.playcount),
-            };
+    /// Sort albums by key
+    pub fn sort_albums(albums: &mut [Album], by: AlbumSort, order: SortOrder) {
+        Self::sort_albums_by(albums, &CompoundSort::single(by), order);
+    }
 
-            match order {
-                SortOrder::Ascending => cmp,
-                SortOrder::Descending => cmp.reverse(),
-            }
+    /// Sort albums on several keys, remaining ties are broken by album hash
+    pub fn sort_albums_by<T: Borrow<Album>>(
+        albums: &mut [T],
+        sort: &CompoundSort<AlbumSort>,
+        order: SortOrder,
+    ) {
+        let keys = sort.keys();
+        let tiebreak = sort.primary_order(order);
+        albums.sort_by(|a, b| {
+            let (a, b) = (a.borrow(), b.borrow());
+            compare_keys(&keys, order, a, b, |by, a, b| by.compare(a, b))
+                .then_with(|| tiebreak.apply(a.albumhash.cmp(&b.albumhash)))
         });
     }
 
-    /// Parse sort parameter string (e.g., "title:asc", "year:desc")
-    pub fn parse_track_sort(sort: &str) -> (TrackSortBy, SortOrder) {
-        let parts: Vec<&str> = sort.split(':').collect();
-
-        let by = match parts.first().map(|s| *s) {
-            Some("title") => TrackSortBy::Title,
-            Some("album") => TrackSortBy::Album,
-            Some("artist") => TrackSortBy::Artist,
-            Some("duration") => TrackSortBy::Duration,
-            Some("date_added") | Some("created") => TrackSortBy::DateAdded,
-            Some("track") => TrackSortBy::TrackNumber,
-            Some("disc") => TrackSortBy::DiscNumber,
-            Some("year") | Some("date") => TrackSortBy::Year,
-            Some("bitrate") => TrackSortBy::Bitrate,
-            Some("playcount") => TrackSortBy::PlayCount,
-            Some("lastplayed") => TrackSortBy::LastPlayed,
-            _ => TrackSortBy::Title,
-        };
+    /// Sort artists by key
+    pub fn sort_artists(artists: &mut [Artist], by: ArtistSort, order: SortOrder) {
+        Self::sort_artists_by(artists, &CompoundSort::single(by), order);
+    }
 
-        let order = match parts.get(1).map(|s| *s) {
-            Some("desc") => SortOrder::Descending,
-            _ => SortOrder::Ascending,
-        };
+    /// Sort artists on several keys, remaining ties are broken by artist hash
+    pub fn sort_artists_by<T: Borrow<Artist>>(
+        artists: &mut [T],
+        sort: &CompoundSort<ArtistSort>,
+        order: SortOrder,
+    ) {
+        let keys = sort.keys();
+        let tiebreak = sort.primary_order(order);
+        artists.sort_by(|a, b| {
+            let (a, b) = (a.borrow(), b.borrow());
+            compare_keys(&keys, order, a, b, |by, a, b| by.compare(a, b))
+                .then_with(|| tiebreak.apply(a.artisthash.cmp(&b.artisthash)))
+        });
+    }
 
-        (by, order)
+    /// Parse sort parameter string (e.g., "title:asc", "year:desc")
+    pub fn parse_track_sort(sort: &str) -> (TrackSort, SortOrder) {
+        let (key, order) = split_sort(sort);
+        (TrackSort::parse(key), order)
     }
 
     /// Parse album sort parameter
-    pub fn parse_album_sort(sort: &str) -> (AlbumSortBy, SortOrder) {
-        let parts: Vec<&str> = sort.split(':').collect();
-
-        let by = match parts.first().map(|s| *s) {
-            Some("title") => AlbumSortBy::Title,
-            Some("artist") => AlbumSortBy::Artist,
-            Some("year") | Some("date") => AlbumSortBy::Year,
-            Some("trackcount") | Some("tracks") => AlbumSortBy::TrackCount,
-            Some("duration") => AlbumSortBy::Duration,
-            Some("date_added") | Some("created") => AlbumSortBy::DateAdded,
-            Some("playcount") => AlbumSortBy::PlayCount,
-            _ => AlbumSortBy::Title,
+    pub fn parse_album_sort(sort: &str) -> (AlbumSort, SortOrder) {
+        let (key, order) = split_sort(sort);
+        (AlbumSort::parse(key), order)
+    }
+
+    /// Parse artist sort parameter
+    pub fn parse_artist_sort(sort: &str) -> (ArtistSort, SortOrder) {
+        let (key, order) = split_sort(sort);
+        (ArtistSort::parse(key), order)
+    }
+}
+
+fn split_sort(sort: &str) -> (&str, SortOrder) {
+    match sort.split_once(':') {
+        Some((key, order)) => (key, SortOrder::parse(order)),
+        None => (sort, SortOrder::Ascending),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ArtistRefItem;
+
+    fn track(title: &str) -> Track {
+        Track {
+            title: title.to_string(),
+            ..Track::new()
+        }
+    }
+
+    fn titles(tracks: &[Track]) -> Vec<&str> {
+        tracks.iter().map(|t| t.title.as_str()).collect()
+    }
+
+    fn artist_ref(name: &str) -> Vec<ArtistRefItem> {
+        vec![ArtistRefItem::new(name.to_string(), name.to_string())]
+    }
+
+    /// tracks whose every sortable field ascends in the order c, a, b
+    fn tracks() -> Vec<Track> {
+        let mut out = Vec::new();
+        for (rank, title) in [(2, "a"), (3, "b"), (1, "c")] {
+            let mut t = track(title);
+            let name = ["", "x", "y", "z"][rank];
+            t.album = name.to_uppercase();
+            t.albumartists = artist_ref(name);
+            t.artists = artist_ref(name);
+            t.bitrate = rank as i32 * 320;
+            t.date = rank as i64 * 1000;
+            t.disc = 1;
+            t.track = rank as i32;
+            t.duration = rank as i32 * 60;
+            t.last_mod = rank as i64 * 10;
+            t.lastplayed = rank as i64 * 100;
+            t.playcount = rank as i32;
+            t.playduration = rank as i32 * 200;
+            t.rating = rank as u8;
+            out.push(t);
+        }
+        out
+    }
+
+    const ALL_TRACK_SORTS: [TrackSort; 15] = [
+        TrackSort::Default,
+        TrackSort::Title,
+        TrackSort::Album,
+        TrackSort::AlbumArtists,
+        TrackSort::Artists,
+        TrackSort::Bitrate,
+        TrackSort::Date,
+        TrackSort::Disc,
+        TrackSort::TrackNumber,
+        TrackSort::Duration,
+        TrackSort::LastMod,
+        TrackSort::LastPlayed,
+        TrackSort::PlayCount,
+        TrackSort::PlayDuration,
+        TrackSort::Rating,
+    ];
+
+    #[test]
+    fn test_track_sort_orderings() {
+        for by in ALL_TRACK_SORTS {
+            let expected: &[&str] = match by {
+                TrackSort::Default => &["a", "b", "c"],
+                TrackSort::Title => &["a", "b", "c"],
+                _ => &["c", "a", "b"],
+            };
+            let mut asc = tracks();
+            SortLib::sort_tracks(&mut asc, by, SortOrder::Ascending);
+            assert_eq!(titles(&asc), expected, "{:?} ascending", by);
+
+            let mut desc = tracks();
+            SortLib::sort_tracks(&mut desc, by, SortOrder::Descending);
+            let reversed: Vec<&str> = expected.iter().rev().copied().collect();
+            assert_eq!(titles(&desc), reversed, "{:?} descending", by);
+        }
+    }
+
+    #[test]
+    fn test_track_sort_keys_round_trip() {
+        for by in ALL_TRACK_SORTS {
+            assert_eq!(TrackSort::parse(by.as_str()), by);
+        }
+        assert_eq!(TrackSort::parse("lastmod"), TrackSort::LastMod);
+        assert_eq!(TrackSort::parse("artist"), TrackSort::Artists);
+        assert_eq!(TrackSort::parse("nonsense"), TrackSort::Title);
+        assert_eq!(TrackSort::parse(""), TrackSort::Default);
+    }
+
+    #[test]
+    fn test_track_sort_ties_break_by_title() {
+        let mut list = vec![track("b"), track("C"), track("a")];
+        for t in &mut list {
+            t.album = "same".to_string();
+            t.disc = 1;
+            t.track = 1;
+        }
+        SortLib::sort_tracks(&mut list, TrackSort::Album, SortOrder::Ascending);
+        assert_eq!(titles(&list), ["a", "b", "C"]);
+    }
+
+    #[test]
+    fn test_track_sort_disc_groups_albums() {
+        let mut list = Vec::new();
+        for (title, album, disc, num) in [
+            ("b2", "B", 1, 2),
+            ("a21", "a", 2, 1),
+            ("a12", "a", 1, 2),
+            ("b1", "B", 1, 1),
+            ("a11", "a", 1, 1),
+        ] {
+            let mut t = track(title);
+            t.album = album.to_string();
+            t.disc = disc;
+            t.track = num;
+            list.push(t);
+        }
+        SortLib::sort_tracks(&mut list, TrackSort::Disc, SortOrder::Ascending);
+        assert_eq!(titles(&list), ["a11", "a12", "a21", "b1", "b2"]);
+    }
+
+    #[test]
+    fn test_track_sort_deserializes_from_query() {
+        #[derive(Deserialize)]
+        struct Query {
+            #[serde(default)]
+            sortby: TrackSort,
+        }
+        let parse = |q: &str| {
+            actix_web::web::Query::<Query>::from_query(q)
+                .unwrap()
+                .into_inner()
+                .sortby
         };
+        assert_eq!(parse("sortby=playcount"), TrackSort::PlayCount);
+        assert_eq!(parse("sortby=unknown"), TrackSort::Title);
+        assert_eq!(parse(""), TrackSort::Default);
+    }
 
-        let order = match parts.get(1).map(|s| *s) {
-            Some("desc") => SortOrder::Descending,
-            _ => SortOrder::Ascending,
+    fn folders() -> Vec<Folder> {
+        vec![
+            Folder::with_trackcount("beta".to_string(), "/nonexistent/b".to_string(), 1),
+            Folder::with_trackcount("Alpha".to_string(), "/nonexistent/a".to_string(), 3),
+            Folder::with_trackcount("gamma".to_string(), "/nonexistent/c".to_string(), 2),
+        ]
+    }
+
+    fn names(folders: &[Folder]) -> Vec<&str> {
+        folders.iter().map(|f| f.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_folder_sort_orderings() {
+        let cases = [
+            (FolderSort::Default, ["beta", "Alpha", "gamma"]),
+            (FolderSort::Name, ["Alpha", "beta", "gamma"]),
+            (FolderSort::TrackCount, ["beta", "gamma", "Alpha"]),
+            // missing folders share a zero mtime and keep their order
+            (FolderSort::LastMod, ["beta", "Alpha", "gamma"]),
+        ];
+        for (by, expected) in cases {
+            let mut asc = folders();
+            SortLib::sort_folders(&mut asc, by, SortOrder::Ascending);
+            assert_eq!(names(&asc), expected, "{:?} ascending", by);
+            assert_eq!(FolderSort::parse(by.as_str()), by);
+        }
+
+        let mut desc = folders();
+        SortLib::sort_folders(&mut desc, FolderSort::TrackCount, SortOrder::Descending);
+        assert_eq!(names(&desc), ["Alpha", "gamma", "beta"]);
+
+        let mut desc = folders();
+        SortLib::sort_folders(&mut desc, FolderSort::Default, SortOrder::Descending);
+        assert_eq!(names(&desc), ["gamma", "Alpha", "beta"]);
+
+        assert_eq!(FolderSort::parse("whatever"), FolderSort::Name);
+    }
+
+    #[test]
+    fn test_folder_sort_by_mtime() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut list = Vec::new();
+        for (name, age) in [("new", 0), ("old", 7200), ("mid", 3600)] {
+            let path = dir.path().join(name);
+            std::fs::create_dir(&path).unwrap();
+            let mtime = std::time::SystemTime::now() - std::time::Duration::from_secs(age);
+            std::fs::File::open(&path)
+                .unwrap()
+                .set_modified(mtime)
+                .unwrap();
+            list.push(Folder::new(
+                name.to_string(),
+                path.to_string_lossy().to_string(),
+            ));
+        }
+        SortLib::sort_folders(&mut list, FolderSort::LastMod, SortOrder::Ascending);
+        assert_eq!(names(&list), ["old", "mid", "new"]);
+        SortLib::sort_folders(&mut list, FolderSort::LastMod, SortOrder::Descending);
+        assert_eq!(names(&list), ["new", "mid", "old"]);
+    }
+
+    #[test]
+    fn test_album_sort_orderings() {
+        let sorts = [
+            AlbumSort::Title,
+            AlbumSort::AlbumArtists,
+            AlbumSort::Date,
+            AlbumSort::TrackCount,
+            AlbumSort::Duration,
+            AlbumSort::CreatedDate,
+            AlbumSort::PlayCount,
+            AlbumSort::PlayDuration,
+            AlbumSort::LastPlayed,
+        ];
+        let albums = || {
+            [(2, "b"), (3, "c"), (1, "a")]
+                .into_iter()
+                .map(|(rank, name)| Album {
+                    title: name.to_uppercase(),
+                    albumartists: artist_ref(name),
+                    date: rank * 10,
+                    trackcount: rank as i32,
+                    duration: rank as i32 * 60,
+                    created_date: rank * 100,
+                    playcount: rank as i32,
+                    playduration: rank as i32 * 30,
+                    lastplayed: rank * 1000,
+                    ..Album::default()
+                })
+                .collect::<Vec<_>>()
         };
+        for by in sorts {
+            let mut list = albums();
+            SortLib::sort_albums(&mut list, by, SortOrder::Ascending);
+            let got: Vec<_> = list.iter().map(|a| a.title.as_str()).collect();
+            assert_eq!(got, ["A", "B", "C"], "{:?} ascending", by);
+
+            SortLib::sort_albums(&mut list, by, SortOrder::Descending);
+            let got: Vec<_> = list.iter().map(|a| a.title.as_str()).collect();
+            assert_eq!(got, ["C", "B", "A"], "{:?} descending", by);
 
-        (by, order)
+            assert_eq!(AlbumSort::parse(by.as_str()), by);
+        }
+        assert_eq!(
+            SortLib::parse_album_sort("year:desc"),
+            (AlbumSort::Date, SortOrder::Descending)
+        );
+        assert_eq!(AlbumSort::parse("bogus"), AlbumSort::CreatedDate);
     }
 
-    /// Parse artist sort parameter
-    pub fn parse_artist_sort(sort: &str) -> (ArtistSortBy, SortOrder) {
-        let parts: Vec<&str> = sort.split(':').collect();
-
-        let by = match parts.first().map(|s| *s) {
-            Some("name") => ArtistSortBy::Name,
-            Some("trackcount") | Some("tracks") => ArtistSortBy::TrackCount,
-            Some("albumcount") | Some("albums") => ArtistSortBy::AlbumCount,
-            Some("playcount") => ArtistSortBy::PlayCount,
-            _ => ArtistSortBy::Name,
+    #[test]
+    fn test_artist_sort_orderings() {
+        let sorts = [
+            ArtistSort::Name,
+            ArtistSort::TrackCount,
+            ArtistSort::AlbumCount,
+            ArtistSort::Duration,
+            ArtistSort::CreatedDate,
+            ArtistSort::PlayCount,
+            ArtistSort::PlayDuration,
+            ArtistSort::LastPlayed,
+        ];
+        let artists = || {
+            [(3, "c"), (1, "a"), (2, "b")]
+                .into_iter()
+                .map(|(rank, name)| Artist {
+                    name: name.to_string(),
+                    trackcount: rank as i32,
+                    albumcount: rank as i32,
+                    duration: rank as i32 * 60,
+                    created_date: rank * 100,
+                    playcount: rank as i32,
+                    playduration: rank as i32 * 30,
+                    lastplayed: rank * 1000,
+                    ..Artist::default()
+                })
+                .collect::<Vec<_>>()
         };
+        for by in sorts {
+            let mut list = artists();
+            SortLib::sort_artists(&mut list, by, SortOrder::Ascending);
+            let got: Vec<_> = list.iter().map(|a| a.name.as_str()).collect();
+            assert_eq!(got, ["a", "b", "c"], "{:?} ascending", by);
 
-        let order = match parts.get(1).map(|s| *s) {
-            Some("desc") => SortOrder::Descending,
-            _ => SortOrder::Ascending,
+            SortLib::sort_artists(&mut list, by, SortOrder::Descending);
+            let got: Vec<_> = list.iter().map(|a| a.name.as_str()).collect();
+            assert_eq!(got, ["c", "b", "a"], "{:?} descending", by);
+
+            assert_eq!(ArtistSort::parse(by.as_str()), by);
+        }
+        assert_eq!(
+            SortLib::parse_artist_sort("albums"),
+            (ArtistSort::AlbumCount, SortOrder::Ascending)
+        );
+    }
+
+    #[test]
+    fn test_compound_sort_parse() {
+        let sort =
+            CompoundSort::<TrackSort>::parse("albumartist, year:desc,bogus,album,disc,track");
+        let keys: Vec<_> = sort.keys().iter().map(|k| (k.by, k.order)).collect();
+        assert_eq!(
+            keys,
+            [
+                (TrackSort::AlbumArtists, None),
+                (TrackSort::Date, Some(SortOrder::Descending)),
+                (TrackSort::Album, None),
+                (TrackSort::Disc, None),
+                (TrackSort::TrackNumber, None),
+            ]
+        );
+
+        // single and unusable keys fall back like the key enums do
+        assert_eq!(
+            CompoundSort::<TrackSort>::parse("bogus").primary(),
+            TrackSort::Title
+        );
+        assert_eq!(
+            CompoundSort::<AlbumSort>::parse("bogus,nope").primary(),
+            AlbumSort::CreatedDate
+        );
+        assert_eq!(
+            CompoundSort::<AlbumSort>::parse("").primary(),
+            AlbumSort::CreatedDate
+        );
+        assert_eq!(
+            CompoundSort::<FolderSort>::default().keys()[0].by,
+            FolderSort::Default
+        );
+    }
+
+    #[test]
+    fn test_compound_sort_tracks() {
+        let mut list = Vec::new();
+        for (title, artist, year, album, disc, num) in [
+            ("b1", "Zed", 2001, "B", 1, 1),
+            ("a2", "abba", 1999, "A", 1, 2),
+            ("c1", "abba", 2005, "C", 1, 1),
+            ("a3", "abba", 1999, "A", 2, 1),
+            ("a1", "abba", 1999, "A", 1, 1),
+        ] {
+            let mut t = track(title);
+            t.albumartists = artist_ref(artist);
+            t.date = year;
+            t.album = album.to_string();
+            t.disc = disc;
+            t.track = num;
+            list.push(t);
+        }
+
+        let sort = CompoundSort::parse("albumartist,year,album,disc,track");
+        SortLib::sort_tracks_by(&mut list, &sort, SortOrder::Ascending);
+        assert_eq!(titles(&list), ["a1", "a2", "a3", "c1", "b1"]);
+
+        let sort = CompoundSort::parse("albumartist,year:desc,disc,track");
+        SortLib::sort_tracks_by(&mut list, &sort, SortOrder::Ascending);
+        assert_eq!(titles(&list), ["c1", "a1", "a2", "a3", "b1"]);
+
+        SortLib::sort_tracks_by(&mut list, &sort, SortOrder::Descending);
+        assert_eq!(titles(&list), ["b1", "c1", "a3", "a2", "a1"]);
+    }
+
+    #[test]
+    fn test_compound_sort_albums_stable() {
+        let albums = |order: &[&str]| {
+            order
+                .iter()
+                .map(|hash| Album {
+                    albumhash: hash.to_string(),
+                    title: if *hash == "z" { "b" } else { "a" }.to_string(),
+                    ..Album::default()
+                })
+                .collect::<Vec<_>>()
         };
+        let sort = CompoundSort::parse("title,date");
+        for input in [["z", "y", "x"], ["x", "z", "y"]] {
+            let mut list = albums(&input);
+            SortLib::sort_albums_by(&mut list, &sort, SortOrder::Ascending);
+            let got: Vec<_> = list.iter().map(|a| a.albumhash.as_str()).collect();
+            assert_eq!(got, ["x", "y", "z"]);
 
-        (by, order)
+            // shared albums sort the same way
+            let mut shared: Vec<_> = albums(&input)
+                .into_iter()
+                .map(std::sync::Arc::new)
+                .collect();
+            SortLib::sort_albums_by(&mut shared, &sort, SortOrder::Ascending);
+            let got: Vec<_> = shared.iter().map(|a| a.albumhash.as_str()).collect();
+            assert_eq!(got, ["x", "y", "z"]);
+        }
     }
 }
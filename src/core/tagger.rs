@@ -1,12 +1,19 @@
 //! Tag writer - write metadata back to audio files
 
 use anyhow::Result;
-use lofty::{Accessor, ItemKey, Probe, Tag, TagExt, TagType, TaggedFileExt};
+use lofty::{Accessor, ItemKey, ItemValue, Probe, Tag, TagExt, TagItem, TagType, TaggedFileExt};
+use std::collections::HashSet;
 use std::path::Path;
 
 /// Tag writer for updating audio file metadata
 pub struct Tagger;
 
+/// The primary tag of a file as it was before an edit
+pub struct TagSnapshot {
+    tag_type: TagType,
+    tag: Option<Tag>,
+}
+
 impl Tagger {
     /// Write tags to a file
     pub fn write_tags(
@@ -71,6 +78,117 @@ impl Tagger {
         Ok(())
     }
 
+    /// Write MusicBrainz identifiers to a file, empty ids are left untouched
+    pub fn write_musicbrainz_ids(
+        path: &Path,
+        recording_id: &str,
+        release_id: &str,
+        releasegroup_id: &str,
+        artist_ids: &[String],
+        albumartist_ids: &[String],
+    ) -> Result<()> {
+        let mut tagged_file = Probe::open(path)?.read()?;
+
+        let tag = match tagged_file.primary_tag_mut() {
+            Some(t) => t,
+            None => {
+                let tag_type = Self::get_tag_type(&tagged_file);
+                tagged_file.insert_tag(Tag::new(tag_type));
+                tagged_file.primary_tag_mut().unwrap()
+            }
+        };
+
+        for (key, value) in [
+            (ItemKey::MusicBrainzRecordingId, recording_id),
+            (ItemKey::MusicBrainzReleaseId, release_id),
+            (ItemKey::MusicBrainzReleaseGroupId, releasegroup_id),
+        ] {
+            if !value.is_empty() {
+                tag.insert_text(key, value.to_string());
+            }
+        }
+
+        for (key, values) in [
+            (ItemKey::MusicBrainzArtistId, artist_ids),
+            (ItemKey::MusicBrainzReleaseArtistId, albumartist_ids),
+        ] {
+            if values.is_empty() {
+                continue;
+            }
+            tag.remove_key(&key);
+            for value in values {
+                tag.push(TagItem::new(key.clone(), ItemValue::Text(value.clone())));
+            }
+        }
+
+        tag.save_to_path(path)?;
+
+        Ok(())
+    }
+
+    /// Copy the primary tag so a later edit can be undone
+    pub fn snapshot(path: &Path) -> Result<TagSnapshot> {
+        let tagged_file = Probe::open(path)?.read()?;
+        let tag_type = tagged_file.primary_tag_type();
+        Ok(TagSnapshot {
+            tag_type,
+            tag: tagged_file.tag(tag_type).cloned(),
+        })
+    }
+
+    /// Put back the primary tag saved by [`Tagger::snapshot`]
+    pub fn restore(path: &Path, snapshot: &TagSnapshot) -> Result<()> {
+        match &snapshot.tag {
+            Some(tag) => tag.save_to_path(path)?,
+            None => snapshot.tag_type.remove_from_path(path)?,
+        }
+        Ok(())
+    }
+
+    /// Rename an artist in the artist and album artist tags
+    ///
+    /// the name is only replaced where it is a whole artist between the
+    /// separators of its tag, so renaming `Nirvana` leaves `Nirvana UK` alone.
+    /// returns whether the file changed
+    pub fn rename_artist(
+        path: &Path,
+        from: &str,
+        to: &str,
+        artist_separators: &HashSet<String>,
+        album_artist_separators: &HashSet<String>,
+    ) -> Result<bool> {
+        let mut tagged_file = Probe::open(path)?.read()?;
+        let Some(tag) = tagged_file.primary_tag_mut() else {
+            return Ok(false);
+        };
+
+        let mut changed = false;
+        for (key, separators) in [
+            (ItemKey::TrackArtist, artist_separators),
+            (ItemKey::AlbumArtist, album_artist_separators),
+        ] {
+            let values: Vec<String> = tag.get_strings(&key).map(str::to_string).collect();
+            let renamed: Vec<String> = values
+                .iter()
+                .map(|v| replace_name(v, from, to, separators).unwrap_or_else(|| v.clone()))
+                .collect();
+            if renamed == values {
+                continue;
+            }
+
+            tag.remove_key(&key);
+            for value in renamed {
+                tag.push(TagItem::new(key.clone(), ItemValue::Text(value)));
+            }
+            changed = true;
+        }
+
+        if changed {
+            tag.save_to_path(path)?;
+        }
+        Ok(changed)
+    }
+
     /// Get best tag type for file format
     fn get_tag_type(file: &lofty::TaggedFile) -> TagType {
         match file.file_type() {
@@ -219,3 +337,140 @@ impl Tagger {
         Ok(tags)
     }
 }
+
+/// words that join artists in addition to the configured separators
+const ARTIST_JOINERS: [&str; 9] = [
+    "&",
+    ",",
+    "+",
+    "feat.",
+    "ft.",
+    "featuring",
+    "vs.",
+    "with",
+    "x",
+];
+
+/// whether `text` starts with a separator or joiner, `text` is lowercase and trimmed
+fn joins_at_start(text: &str, separators: &HashSet<String>) -> bool {
+    let word_end = |s: &str, joiner: &str| {
+        !joiner.ends_with(char::is_alphanumeric)
+            || !s[joiner.len()..].starts_with(char::is_alphanumeric)
+    };
+    separators
+        .iter()
+        .map(|s| s.trim())
+        .any(|s| !s.is_empty() && text.starts_with(s))
+        || ARTIST_JOINERS
+            .iter()
+            .any(|j| text.starts_with(j) && word_end(text, j))
+}
+
+/// whether `text` ends with a separator or joiner, `text` is lowercase and trimmed
+fn joins_at_end(text: &str, separators: &HashSet<String>) -> bool {
+    let word_start = |s: &str, joiner: &str| {
+        !joiner.starts_with(char::is_alphanumeric)
+            || !s[..s.len() - joiner.len()].ends_with(char::is_alphanumeric)
+    };
+    separators
+        .iter()
+        .map(|s| s.trim())
+        .any(|s| !s.is_empty() && text.ends_with(s))
+        || ARTIST_JOINERS
+            .iter()
+            .any(|j| text.ends_with(j) && word_start(text, j))
+}
+
+/// Replace case-insensitive occurrences of `from` that are a whole artist in a tag value
+fn replace_name(value: &str, from: &str, to: &str, separators: &HashSet<String>) -> Option<String> {
+    if from.is_empty() {
+        return None;
+    }
+    let pattern = regex::RegexBuilder::new(&regex::escape(from))
+        .case_insensitive(true)
+        .build()
+        .ok()?;
+
+    let mut result = String::with_capacity(value.len());
+    let mut last = 0;
+    for m in pattern.find_iter(value) {
+        // a match running into letters is part of a longer word
+        let glued = (m.as_str().starts_with(char::is_alphanumeric)
+            && value[..m.start()].ends_with(char::is_alphanumeric))
+            || (m.as_str().ends_with(char::is_alphanumeric)
+                && value[m.end()..].starts_with(char::is_alphanumeric));
+        let before = value[..m.start()].trim_end().to_lowercase();
+        let after = value[m.end()..].trim_start().to_lowercase();
+        let whole = !glued
+            && (before.is_empty() || joins_at_end(&before, separators))
+            && (after.is_empty() || joins_at_start(&after, separators));
+        if !whole {
+            continue;
+        }
+        result.push_str(&value[last..m.start()]);
+        result.push_str(to);
+        last = m.end();
+    }
+
+    if last == 0 {
+        return None;
+    }
+    result.push_str(&value[last..]);
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_replace_name_matches_whole_artists_only() {
+        let separators: HashSet<String> = [";", "/", ", "].map(String::from).into();
+        let rename = |value: &str, from: &str, to: &str| replace_name(value, from, to, &separators);
+
+        assert_eq!(
+            rename("Nirvana, nirvana feat. Kurt", "Nirvana", "Nirvana (UK)").as_deref(),
+            Some("Nirvana (UK), Nirvana (UK) feat. Kurt")
+        );
+        assert_eq!(rename("A/A", "A", "B").as_deref(), Some("B/B"));
+        assert_eq!(
+            rename("Kurt & Nirvana", "Nirvana", "X").as_deref(),
+            Some("Kurt & X")
+        );
+        assert_eq!(rename("Nirvana UK", "Nirvana", "X"), None);
+        assert_eq!(rename("Nirvanas", "Nirvana", "X"), None);
+        assert_eq!(rename("Nirvana Xtreme", "Nirvana", "X"), None);
+        assert_eq!(rename("Björk", "Bj", "X"), None);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_replace_name_never_panics(
+            value in "\\PC{0,40}",
+            from in "\\PC{0,8}",
+            to in "\\PC{0,8}",
+        ) {
+            let separators: HashSet<String> = [";", "/", ", "].map(String::from).into();
+            replace_name(&value, &from, &to, &separators);
+        }
+
+        #[test]
+        fn prop_replace_name_swaps_whole_artists(
+            names in proptest::collection::vec("[A-Za-z]{1,8}", 1..6),
+            pick in any::<prop::sample::Index>(),
+            sep in prop_oneof![Just(";"), Just("/"), Just(", ")],
+        ) {
+            let separators: HashSet<String> = [";", "/", ", "].map(String::from).into();
+            let from = pick.get(&names).clone();
+            let expected = names
+                .iter()
+                .map(|name| if name.eq_ignore_ascii_case(&from) { "Renamed" } else { name })
+                .collect::<Vec<_>>()
+                .join(sep);
+
+            let renamed = replace_name(&names.join(sep), &from, "Renamed", &separators);
+            prop_assert_eq!(renamed, Some(expected));
+        }
+    }
+}
@@ -0,0 +1,333 @@
+//! Track filter expressions
+//!
+//! an expression is a list of space separated terms that must all match.
+//! bare words have to appear in the title, artists or album, `field:value`
+//! terms narrow the match further:
+//!
+//! - `title:`, `artist:`, `album:`, `albumartist:`, `genre:` and `folder:`
+//!   match a part of that field
+//! - `year:`, `rating:`, `plays:`, `duration:` (seconds) and `bitrate:` take a
+//!   number, a comparison like `>=4` or a range like `1990-1999`
+//! - `is:favorite`, `is:explicit` and `has:lyrics` check flags
+//!
+//! values with spaces are quoted, `artist:"the band"`, and a leading `-`
+//! excludes the matching tracks instead.
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Datelike};
+
+use crate::core::search_index::normalize;
+use crate::models::Track;
+
+/// Text field a `field:value` term matches against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextField {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+    Folder,
+}
+
+/// Numeric field a `field:value` term compares
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NumberField {
+    Year,
+    Rating,
+    Plays,
+    Duration,
+    Bitrate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Eq(f64),
+    Gt(f64),
+    Ge(f64),
+    Lt(f64),
+    Le(f64),
+    Range(f64, f64),
+}
+
+impl Comparison {
+    fn parse(value: &str) -> Option<Self> {
+        let number = |s: &str| s.trim().parse::<f64>().ok().filter(|n| n.is_finite());
+
+        if let Some(rest) = value.strip_prefix(">=") {
+            return number(rest).map(Comparison::Ge);
+        }
+        if let Some(rest) = value.strip_prefix("<=") {
+            return number(rest).map(Comparison::Le);
+        }
+        if let Some(rest) = value.strip_prefix('>') {
+            return number(rest).map(Comparison::Gt);
+        }
+        if let Some(rest) = value.strip_prefix('<') {
+            return number(rest).map(Comparison::Lt);
+        }
+        if let Some(rest) = value.strip_prefix('=') {
+            return number(rest).map(Comparison::Eq);
+        }
+        if let Some((low, high)) = value.split_once('-') {
+            let (low, high) = (number(low)?, number(high)?);
+            return Some(Comparison::Range(low.min(high), low.max(high)));
+        }
+        number(value).map(Comparison::Eq)
+    }
+
+    fn matches(&self, n: f64) -> bool {
+        match *self {
+            Comparison::Eq(v) => n == v,
+            Comparison::Gt(v) => n > v,
+            Comparison::Ge(v) => n >= v,
+            Comparison::Lt(v) => n < v,
+            Comparison::Le(v) => n <= v,
+            Comparison::Range(low, high) => n >= low && n <= high,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    /// normalized words that must appear in the title, artists or album
+    Text(String),
+    Field(TextField, String),
+    Number(NumberField, Comparison),
+    Favorite,
+    Explicit,
+    Lyrics,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Term {
+    negated: bool,
+    condition: Condition,
+}
+
+/// A parsed filter expression
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackFilter {
+    terms: Vec<Term>,
+}
+
+impl TrackFilter {
+    /// Parse an expression, unknown fields and malformed numbers are errors
+    pub fn parse(expression: &str) -> Result<Self> {
+        let mut terms = Vec::new();
+
+        for word in split_terms(expression) {
+            let (negated, word) = match word.strip_prefix('-') {
+                Some(rest) if !rest.is_empty() => (true, rest.to_string()),
+                _ => (false, word),
+            };
+
+            let condition = match word.split_once(':') {
+                Some((field, value)) if !field.is_empty() => parse_condition(field, value)?,
+                _ => {
+                    let text = normalize(&word);
+                    if text.is_empty() {
+                        continue;
+                    }
+                    Condition::Text(text)
+                }
+            };
+            terms.push(Term { negated, condition });
+        }
+
+        Ok(Self { terms })
+    }
+
+    /// Whether the expression has no terms and so matches every track
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Whether a track matches every term, ratings, play counts and favorites
+    /// are read for the given user so tracks should be personalized first
+    pub fn matches(&self, track: &Track, user_id: i64) -> bool {
+        let mut text: Option<String> = None;
+
+        self.terms.iter().all(|term| {
+            let hit = match &term.condition {
+                Condition::Text(words) => text
+                    .get_or_insert_with(|| searchable_text(track))
+                    .contains(words.as_str()),
+                Condition::Field(field, value) => field_text(track, *field).contains(value),
+                Condition::Number(field, comparison) => {
+                    number(track, *field).is_some_and(|n| comparison.matches(n))
+                }
+                Condition::Favorite => track.is_favorite(user_id),
+                Condition::Explicit => track.explicit,
+                Condition::Lyrics => track.has_lyrics,
+            };
+            hit != term.negated
+        })
+    }
+
+    /// The tracks matching the expression, in the order given
+    pub fn apply(&self, tracks: Vec<Track>, user_id: i64) -> Vec<Track> {
+        tracks
+            .into_iter()
+            .filter(|track| self.matches(track, user_id))
+            .collect()
+    }
+}
+
+/// Split on whitespace outside double quotes, quotes are dropped
+fn split_terms(expression: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for c in expression.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    terms.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        terms.push(current);
+    }
+    terms
+}
+
+fn parse_condition(field: &str, value: &str) -> Result<Condition> {
+    let field = field.to_lowercase();
+
+    let text_field = match field.as_str() {
+        "title" => Some(TextField::Title),
+        "artist" | "artists" => Some(TextField::Artist),
+        "album" => Some(TextField::Album),
+        "albumartist" | "albumartists" => Some(TextField::AlbumArtist),
+        "genre" => Some(TextField::Genre),
+        "folder" | "path" => Some(TextField::Folder),
+        _ => None,
+    };
+    if let Some(text_field) = text_field {
+        let value = if text_field == TextField::Folder {
+            value.to_lowercase()
+        } else {
+            normalize(value)
+        };
+        if value.is_empty() {
+            bail!("{} needs a value", field);
+        }
+        return Ok(Condition::Field(text_field, value));
+    }
+
+    let number_field = match field.as_str() {
+        "year" => Some(NumberField::Year),
+        "rating" | "stars" => Some(NumberField::Rating),
+        "plays" | "playcount" => Some(NumberField::Plays),
+        "duration" => Some(NumberField::Duration),
+        "bitrate" => Some(NumberField::Bitrate),
+        _ => None,
+    };
+    if let Some(number_field) = number_field {
+        let Some(comparison) = Comparison::parse(value) else {
+            bail!("{}:{} is not a number, comparison or range", field, value);
+        };
+        return Ok(Condition::Number(number_field, comparison));
+    }
+
+    match (field.as_str(), value.to_lowercase().as_str()) {
+        ("is", "favorite" | "favourite" | "fav" | "liked") => Ok(Condition::Favorite),
+        ("is", "explicit") => Ok(Condition::Explicit),
+        ("has", "lyrics") => Ok(Condition::Lyrics),
+        ("is" | "has", other) => bail!("unknown flag {}:{}", field, other),
+        _ => bail!("unknown field {}", field),
+    }
+}
+
+fn searchable_text(track: &Track) -> String {
+    normalize(&format!(
+        "{} {} {} {}",
+        track.title,
+        track.artist(),
+        track.album,
+        track.albumartist()
+    ))
+}
+
+fn field_text(track: &Track, field: TextField) -> String {
+    match field {
+        TextField::Title => normalize(&track.title),
+        TextField::Artist => normalize(&track.artist()),
+        TextField::Album => normalize(&track.album),
+        TextField::AlbumArtist => normalize(&track.albumartist()),
+        TextField::Genre => normalize(&track.genre()),
+        TextField::Folder => track.folder.to_lowercase(),
+    }
+}
+
+fn number(track: &Track, field: NumberField) -> Option<f64> {
+    let n = match field {
+        NumberField::Year if track.date == 0 => return None,
+        NumberField::Year => DateTime::from_timestamp(track.date, 0)?.year() as f64,
+        NumberField::Rating => track.rating as f64,
+        NumberField::Plays => track.playcount as f64,
+        NumberField::Duration => track.duration as f64,
+        NumberField::Bitrate => track.bitrate as f64,
+    };
+    Some(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ArtistRefItem, GenreRef};
+
+    fn track(title: &str, artist: &str, album: &str, year: i32) -> Track {
+        let mut track = Track::new();
+        track.title = title.to_string();
+        track.album = album.to_string();
+        track.artists = vec![ArtistRefItem::new(artist.to_string(), String::new())];
+        track.albumartists = track.artists.clone();
+        track.genres = vec![GenreRef::new("Post Rock".to_string(), String::new())];
+        track.folder = format!("/music/{}/{}/", artist, album);
+        track.date = chrono::NaiveDate::from_ymd_opt(year, 6, 1)
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .map(|d| d.and_utc().timestamp())
+            .unwrap_or(0);
+        track.duration = 240;
+        track
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(TrackFilter::parse("colour:blue").is_err());
+        assert!(TrackFilter::parse("year:soon").is_err());
+        assert!(TrackFilter::parse("is:loud").is_err());
+        assert!(TrackFilter::parse("artist:").is_err());
+        assert!(TrackFilter::parse("  ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_matches() {
+        let mut song = track(
+            "Your Hand in Mine",
+            "Explosions in the Sky",
+            "The Earth",
+            2003,
+        );
+        song.rating = 5;
+        song.fav_userids.insert(1);
+
+        let matches = |expression: &str| TrackFilter::parse(expression).unwrap().matches(&song, 1);
+
+        assert!(matches("hand explosions"));
+        assert!(matches("artist:\"explosions in\" year:2000-2005"));
+        assert!(matches("genre:post rating:>=4 is:favorite duration:<300"));
+        assert!(matches("folder:/music/explosions -has:lyrics"));
+        assert!(!matches("hand -artist:explosions"));
+        assert!(!matches("year:>2003"));
+        assert!(!matches("title:earth"));
+        assert!(!TrackFilter::parse("is:favorite").unwrap().matches(&song, 2));
+    }
+}
@@ -2,6 +2,7 @@
 
 use std::collections::HashMap;
 
+use crate::core::SearchLib;
 use crate::models::Track;
 use crate::stores::TrackStore;
 
@@ -36,26 +37,31 @@ impl TracksLib {
 
     /// Get paginated tracks
     pub fn get_paginated(page: usize, limit: usize) -> Vec<Track> {
-        let tracks = TrackStore::get().get_all();
+        let tracks = TrackStore::get().get_all_shared();
         let start = page * limit;
 
         if start >= tracks.len() {
             return Vec::new();
         }
 
-        tracks.into_iter().skip(start).take(limit).collect()
+        tracks
+            .iter()
+            .skip(start)
+            .take(limit)
+            .map(|t| t.as_ref().clone())
+            .collect()
     }
 
     /// Get random tracks
     pub fn get_random(count: usize) -> Vec<Track> {
         use rand::seq::SliceRandom;
 
-        let tracks = TrackStore::get().get_all();
+        let tracks = TrackStore::get().get_all_shared();
         let mut rng = rand::thread_rng();
 
         tracks
             .choose_multiple(&mut rng, count.min(tracks.len()))
-            .cloned()
+            .map(|t| t.as_ref().clone())
             .collect()
     }
 
@@ -63,16 +69,17 @@ impl TracksLib {
     pub fn get_by_genre(genre: &str) -> Vec<Track> {
         let genre_lower = genre.to_lowercase();
         TrackStore::get()
-            .get_all()
-            .into_iter()
+            .get_all_shared()
+            .iter()
             .filter(|t| t.genre().to_lowercase().contains(&genre_lower))
+            .map(|t| t.as_ref().clone())
             .collect()
     }
 
     /// Get all unique genres
     pub fn get_all_genres() -> Vec<String> {
         let mut genres: Vec<String> = TrackStore::get()
-            .get_all()
+            .get_all_shared()
             .iter()
             .filter(|t| !t.genre().is_empty())
             .map(|t| t.genre().clone())
@@ -86,9 +93,10 @@ impl TracksLib {
     /// Get tracks by year
     pub fn get_by_year(year: i32) -> Vec<Track> {
         TrackStore::get()
-            .get_all()
-            .into_iter()
+            .get_all_shared()
+            .iter()
             .filter(|t| t.date == year as i64)
+            .map(|t| t.as_ref().clone())
             .collect()
     }
 
@@ -98,16 +106,17 @@ impl TracksLib {
         let cutoff = now - (days * 24 * 60 * 60);
 
         TrackStore::get()
-            .get_all()
-            .into_iter()
+            .get_all_shared()
+            .iter()
             .filter(|t| t.date >= cutoff)
+            .map(|t| t.as_ref().clone())
             .collect()
     }
 
     /// Get total duration of all tracks
     pub fn total_duration() -> i64 {
         TrackStore::get()
-            .get_all()
+            .get_all_shared()
             .iter()
             .map(|t| t.duration as i64)
             .sum()
@@ -143,35 +152,32 @@ impl TracksLib {
         groups
     }
 
-    /// Search tracks
+    /// Search tracks by title, artist or album
     pub fn search(query: &str, limit: usize) -> Vec<Track> {
-        let query_lower = query.to_lowercase();
-
-        TrackStore::get()
-            .get_all()
+        SearchLib::search_tracks(query, limit)
             .into_iter()
-            .filter(|t| {
-                t.title.to_lowercase().contains(&query_lower)
-                    || t.album.to_lowercase().contains(&query_lower)
-                    || t.artist().to_lowercase().contains(&query_lower)
-            })
-            .take(limit)
+            .map(|result| result.item)
             .collect()
     }
 
     /// Get tracks in a folder
     pub fn get_by_folder(folder_path: &str) -> Vec<Track> {
         TrackStore::get()
-            .get_all()
-            .into_iter()
+            .get_all_shared()
+            .iter()
             .filter(|t| t.folder == folder_path)
+            .map(|t| t.as_ref().clone())
             .collect()
     }
 
     /// Get recent tracks (most recently added, by last_mod)
     pub fn get_recent(limit: usize) -> Vec<Track> {
-        let mut tracks = TrackStore::get().get_all();
+        let mut tracks = TrackStore::get().get_all_shared();
         tracks.sort_by(|a, b| b.last_mod.cmp(&a.last_mod));
-        tracks.into_iter().take(limit).collect()
+        tracks
+            .iter()
+            .take(limit)
+            .map(|t| t.as_ref().clone())
+            .collect()
     }
 }
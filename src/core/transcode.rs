@@ -1,9 +1,15 @@
 //! Audio transcoding utilities using ffmpeg-sidecar
 
 use anyhow::Result;
-use std::path::Path;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::Arc;
+use tokio::sync::watch;
 
+use crate::config::{Paths, UserConfig};
 use crate::core::ffmpeg;
 
 /// Audio format/codec
@@ -37,7 +43,8 @@ impl AudioFormat {
             AudioFormat::Flac => "audio/flac",
             AudioFormat::Ogg => "audio/ogg",
             AudioFormat::Opus => "audio/opus",
-            AudioFormat::Aac => "audio/mp4",
+            // streamed as raw adts frames rather than an mp4 container
+            AudioFormat::Aac => "audio/aac",
             AudioFormat::Wav => "audio/wav",
         }
     }
@@ -135,6 +142,17 @@ pub enum Quality {
 }
 
 impl Quality {
+    /// parse from the `quality` query parameter
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "low" => Some(Quality::Low),
+            "medium" => Some(Quality::Medium),
+            "high" => Some(Quality::High),
+            "best" => Some(Quality::Best),
+            _ => None,
+        }
+    }
+
     /// get bitrate in kbps
     pub fn bitrate(&self) -> u32 {
         match self {
@@ -269,3 +287,407 @@ impl Transcoder {
         )
     }
 }
+
+/// state of a running or finished transcode job
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobState {
+    /// ffmpeg is being resolved and the cache file created
+    Starting,
+    Running,
+    Finished,
+    Failed(String),
+}
+
+/// A transcode that is being written into the cache
+///
+/// ffmpeg writes straight into the final cache file so readers can tail it while the
+/// job runs. writing to a seekable file (instead of a pipe) also lets the mp3 muxer
+/// back-fill the xing/lame header with encoder delay and padding, which is what
+/// players need for gapless playback.
+#[derive(Debug)]
+pub struct TranscodeJob {
+    pub path: PathBuf,
+    pub format: AudioFormat,
+    state: watch::Receiver<JobState>,
+}
+
+impl TranscodeJob {
+    /// current job state
+    pub fn state(&self) -> JobState {
+        self.state.borrow().clone()
+    }
+
+    /// whether ffmpeg is still writing the file or about to
+    pub fn is_running(&self) -> bool {
+        matches!(self.state(), JobState::Starting | JobState::Running)
+    }
+
+    /// wait until the cache file exists and ffmpeg writes into it
+    pub async fn started(&self) -> Result<()> {
+        let mut state = self.state.clone();
+        let result = state
+            .wait_for(|s| *s != JobState::Starting)
+            .await
+            .map(|s| s.clone());
+
+        match result {
+            Ok(JobState::Failed(e)) => Err(anyhow::anyhow!(e)),
+            Ok(_) => Ok(()),
+            Err(_) => Err(anyhow::anyhow!("transcode job was dropped")),
+        }
+    }
+
+    /// wait for the job to finish and return the path of the complete file
+    pub async fn wait(&self) -> Result<PathBuf> {
+        let mut state = self.state.clone();
+        let result = state
+            .wait_for(|s| !matches!(s, JobState::Starting | JobState::Running))
+            .await
+            .map(|s| s.clone());
+
+        match result {
+            Ok(JobState::Finished) => Ok(self.path.clone()),
+            Ok(JobState::Failed(e)) => Err(anyhow::anyhow!(e)),
+            _ => Err(anyhow::anyhow!("transcode job was dropped")),
+        }
+    }
+}
+
+/// Lookup result for a cached transcode
+pub enum CachedTranscode {
+    /// complete file that can be served with byte ranges
+    Ready(PathBuf),
+    /// transcode still in progress
+    InProgress(Arc<TranscodeJob>),
+}
+
+/// What a request for a cache entry gets, decided under the jobs lock
+enum Claim {
+    Ready(PathBuf),
+    Running(Arc<TranscodeJob>),
+    /// a new job the caller has to start, its state goes through the sender
+    Start(Arc<TranscodeJob>, watch::Sender<JobState>),
+}
+
+/// jobs currently writing into the cache, keyed by cache key
+static ACTIVE_JOBS: Lazy<Mutex<HashMap<String, Arc<TranscodeJob>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// On-disk cache of transcoded tracks keyed by trackhash, codec and bitrate
+///
+/// the key also holds the source file's modified time and size, so a file that
+/// is replaced or retagged under the same hash is transcoded again. a finished entry is marked by a `.done` file next to the audio so interrupted
+/// transcodes from a previous run are never served as complete.
+pub struct TranscodeCache;
+
+impl TranscodeCache {
+    /// lowest bitrate a client may request
+    pub const MIN_BITRATE: u32 = 32;
+    /// highest bitrate a client may request
+    pub const MAX_BITRATE: u32 = 320;
+
+    /// build the cache key for a transcode of a source with the given modified
+    /// time (in milliseconds) and size
+    pub fn cache_key(
+        trackhash: &str,
+        modified: u128,
+        size: u64,
+        format: AudioFormat,
+        bitrate: u32,
+    ) -> String {
+        format!(
+            "{}-{}-{}-{}-{}",
+            trackhash,
+            modified,
+            size,
+            format.extension(),
+            bitrate
+        )
+    }
+
+    fn entry_paths(key: &str, format: AudioFormat) -> Result<(PathBuf, PathBuf)> {
+        let dir = Paths::get()?.transcode_cache_dir();
+        Ok((
+            dir.join(format!("{}.{}", key, format.extension())),
+            dir.join(format!("{}.done", key)),
+        ))
+    }
+
+    /// return the cached transcode or start a new ffmpeg job for it
+    ///
+    /// concurrent requests for the same key share a single job, so seeking around in
+    /// a track that is still transcoding never restarts ffmpeg.
+    pub async fn get_or_start(
+        trackhash: &str,
+        input: &Path,
+        format: AudioFormat,
+        bitrate: u32,
+    ) -> Result<CachedTranscode> {
+        let bitrate = bitrate.clamp(Self::MIN_BITRATE, Self::MAX_BITRATE);
+        let source = tokio::fs::metadata(input).await?;
+        let modified = source
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        let key = Self::cache_key(trackhash, modified, source.len(), format, bitrate);
+        let (path, marker) = Self::entry_paths(&key, format)?;
+
+        let (job, state_tx) = match Self::claim(&key, &path, &marker, format) {
+            Claim::Ready(path) => return Ok(CachedTranscode::Ready(path)),
+            Claim::Running(job) => return Ok(CachedTranscode::InProgress(job)),
+            Claim::Start(job, state_tx) => (job, state_tx),
+        };
+
+        // ffmpeg may have to be downloaded first, other requests don't wait on the lock
+        let ffmpeg = tokio::task::spawn_blocking(|| {
+            if Transcoder::is_ffmpeg_available() {
+                Ok(())
+            } else {
+                Transcoder::ensure_ffmpeg()
+            }
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|ready| ready);
+        let spawned = ffmpeg.and_then(|_| Self::spawn(input, &path, &marker, format, bitrate));
+        let child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                let _ = std::fs::remove_file(&path);
+                ACTIVE_JOBS.lock().remove(&key);
+                let _ = state_tx.send(JobState::Failed(e.to_string()));
+                return Err(e);
+            }
+        };
+        let _ = state_tx.send(JobState::Running);
+
+        tokio::spawn(async move {
+            let state = match child.wait_with_output().await {
+                Ok(out) if out.status.success() => match std::fs::File::create(&marker) {
+                    Ok(_) => JobState::Finished,
+                    Err(e) => JobState::Failed(format!("failed to mark transcode done: {}", e)),
+                },
+                Ok(out) => JobState::Failed(format!(
+                    "ffmpeg exited with {}: {}",
+                    out.status,
+                    String::from_utf8_lossy(&out.stderr)
+                        .lines()
+                        .last()
+                        .unwrap_or("")
+                )),
+                Err(e) => JobState::Failed(format!("failed to run ffmpeg: {}", e)),
+            };
+
+            if let JobState::Failed(e) = &state {
+                tracing::error!("transcode {} failed: {}", key, e);
+                let _ = std::fs::remove_file(&path);
+            }
+
+            ACTIVE_JOBS.lock().remove(&key);
+            let _ = state_tx.send(state);
+
+            if let Err(e) = Self::prune() {
+                tracing::warn!("failed to prune transcode cache: {}", e);
+            }
+        });
+
+        Ok(CachedTranscode::InProgress(job))
+    }
+
+    /// join the running job for a key, take the finished entry or register a
+    /// new job, the lock is only held for the lookup
+    fn claim(key: &str, path: &Path, marker: &Path, format: AudioFormat) -> Claim {
+        let mut jobs = ACTIVE_JOBS.lock();
+        if let Some(job) = jobs.get(key) {
+            return Claim::Running(job.clone());
+        }
+        if path.exists() && marker.exists() {
+            return Claim::Ready(path.to_path_buf());
+        }
+
+        let (state_tx, state_rx) = watch::channel(JobState::Starting);
+        let job = Arc::new(TranscodeJob {
+            path: path.to_path_buf(),
+            format,
+            state: state_rx,
+        });
+        jobs.insert(key.to_string(), job.clone());
+        Claim::Start(job, state_tx)
+    }
+
+    /// create the cache file and start ffmpeg writing into it
+    fn spawn(
+        input: &Path,
+        path: &Path,
+        marker: &Path,
+        format: AudioFormat,
+        bitrate: u32,
+    ) -> Result<tokio::process::Child> {
+        // create the file up front so readers can open it before ffmpeg starts writing
+        let _ = std::fs::remove_file(marker);
+        std::fs::File::create(path)?;
+
+        let mut cmd = tokio::process::Command::new(ffmpeg::get_ffmpeg_path());
+        cmd.args(["-v", "error", "-y", "-i"])
+            .arg(input)
+            // cover art streams can't be muxed into most audio containers
+            .args(["-map", "0:a:0", "-vn"])
+            .args(["-f", format.ffmpeg_format(), "-c:a", format.ffmpeg_codec()]);
+
+        // lossless targets ignore the bitrate
+        if !matches!(format, AudioFormat::Flac | AudioFormat::Wav) {
+            cmd.args(["-b:a", &format!("{}k", bitrate)]);
+        }
+
+        cmd.arg(path)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::piped());
+
+        Ok(cmd.spawn()?)
+    }
+
+    /// number of transcodes currently running
+    pub fn active_jobs() -> usize {
+        ACTIVE_JOBS.lock().len()
+    }
+
+    /// evict the least recently used entries until the cache fits the configured size
+    pub fn prune() -> Result<()> {
+        let max_bytes = UserConfig::load()?.transcode_cache_size_mb * 1024 * 1024;
+        let dir = Paths::get()?.transcode_cache_dir();
+
+        let active: Vec<PathBuf> = ACTIVE_JOBS
+            .lock()
+            .values()
+            .map(|job| job.path.clone())
+            .collect();
+
+        let mut entries: Vec<(PathBuf, u64, std::time::SystemTime)> = Vec::new();
+        for entry in std::fs::read_dir(&dir)?.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) == Some("done") || active.contains(&path) {
+                continue;
+            }
+            if let Ok(meta) = entry.metadata() {
+                // access time is often disabled, modified time is the next best thing
+                let used = meta.accessed().or_else(|_| meta.modified());
+                entries.push((path, meta.len(), used.unwrap_or(std::time::UNIX_EPOCH)));
+            }
+        }
+
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        if total <= max_bytes {
+            return Ok(());
+        }
+
+        entries.sort_by_key(|(_, _, used)| *used);
+        for (path, size, _) in entries {
+            if total <= max_bytes {
+                break;
+            }
+            let _ = std::fs::remove_file(path.with_extension("done"));
+            if std::fs::remove_file(&path).is_ok() {
+                total = total.saturating_sub(size);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(dir: &Path, key: &str) -> (PathBuf, PathBuf) {
+        (
+            dir.join(format!("{}.mp3", key)),
+            dir.join(format!("{}.done", key)),
+        )
+    }
+
+    #[test]
+    fn test_claim_shares_running_job() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = TranscodeCache::cache_key("dedup", 1, 10, AudioFormat::Mp3, 128);
+        let (path, marker) = entry(dir.path(), &key);
+
+        let Claim::Start(job, state_tx) =
+            TranscodeCache::claim(&key, &path, &marker, AudioFormat::Mp3)
+        else {
+            panic!("the first request starts the job");
+        };
+        assert!(job.is_running());
+
+        // a second request joins the job instead of starting ffmpeg again
+        match TranscodeCache::claim(&key, &path, &marker, AudioFormat::Mp3) {
+            Claim::Running(other) => assert!(Arc::ptr_eq(&job, &other)),
+            _ => panic!("the second request joins the running job"),
+        }
+
+        ACTIVE_JOBS.lock().remove(&key);
+        let _ = state_tx.send(JobState::Failed("stopped".to_string()));
+        assert!(!job.is_running());
+    }
+
+    #[test]
+    fn test_claim_reuses_finished_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = TranscodeCache::cache_key("cached", 1, 10, AudioFormat::Mp3, 128);
+        let (path, marker) = entry(dir.path(), &key);
+
+        // a file without its done marker is an interrupted transcode
+        std::fs::write(&path, b"partial").unwrap();
+        match TranscodeCache::claim(&key, &path, &marker, AudioFormat::Mp3) {
+            Claim::Start(..) => {
+                ACTIVE_JOBS.lock().remove(&key);
+            }
+            _ => panic!("an unmarked file is transcoded again"),
+        }
+
+        std::fs::write(&marker, b"").unwrap();
+        match TranscodeCache::claim(&key, &path, &marker, AudioFormat::Mp3) {
+            Claim::Ready(ready) => assert_eq!(ready, path),
+            _ => panic!("a finished entry is served from the cache"),
+        }
+        assert!(!ACTIVE_JOBS.lock().contains_key(&key));
+    }
+
+    #[test]
+    fn test_changed_source_gets_a_new_key() {
+        let key = TranscodeCache::cache_key("hash", 1_000, 10, AudioFormat::Mp3, 128);
+        assert_ne!(
+            key,
+            TranscodeCache::cache_key("hash", 2_000, 10, AudioFormat::Mp3, 128)
+        );
+        assert_ne!(
+            key,
+            TranscodeCache::cache_key("hash", 1_000, 11, AudioFormat::Mp3, 128)
+        );
+        assert_eq!(
+            key,
+            TranscodeCache::cache_key("hash", 1_000, 10, AudioFormat::Mp3, 128)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_started_waits_for_spawn() {
+        let (state_tx, state_rx) = watch::channel(JobState::Starting);
+        let job = TranscodeJob {
+            path: PathBuf::from("/tmp/never.mp3"),
+            format: AudioFormat::Mp3,
+            state: state_rx,
+        };
+
+        state_tx.send(JobState::Running).unwrap();
+        assert!(job.started().await.is_ok());
+
+        state_tx
+            .send(JobState::Failed("no ffmpeg".to_string()))
+            .unwrap();
+        assert!(job.started().await.is_err());
+        assert!(job.wait().await.is_err());
+    }
+}
@@ -0,0 +1,177 @@
+//! Trash for deleted tracks
+//!
+//! deleting a track moves its file into the trash folder and hides its row,
+//! so plays and tags come back with it when it is restored. the purge cron
+//! removes files that stayed in the trash longer than the retention period.
+
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
+
+use crate::config::{Paths, UserConfig};
+use crate::core::organizer;
+use crate::core::populate::{reindex_track_files, remove_tracks};
+use crate::db::tables::{TrackTable, TrashTable, TrashedTrack};
+use crate::models::Track;
+use crate::stores::FolderStore;
+
+/// Trash moves run one at a time so a restore can't race a purge
+static MOVING: Mutex<()> = Mutex::const_new(());
+
+/// Move a track's file into the trash and take it out of the library
+pub async fn trash_track(track: &Track, userid: i64) -> Result<TrashedTrack> {
+    let _guard = MOVING.lock().await;
+
+    let from = PathBuf::from(&track.filepath);
+    if !from.is_file() {
+        bail!("Track file not found");
+    }
+
+    let deleted_at = chrono::Utc::now().timestamp();
+    let to = trash_path(&Paths::get()?.trash_dir(), track, deleted_at);
+    if to.exists() {
+        bail!("{} already exists", to.display());
+    }
+    let (src, dest) = (from.clone(), to.clone());
+    tokio::task::spawn_blocking(move || organizer::move_file(&src, &dest)).await??;
+
+    let mut trashed = TrashedTrack {
+        id: 0,
+        trackhash: track.trackhash.clone(),
+        title: track.title.clone(),
+        artist: track
+            .artists
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+        album: track.album.clone(),
+        filepath: track.filepath.clone(),
+        trashpath: to.to_string_lossy().to_string(),
+        userid,
+        deleted_at,
+    };
+    trashed.id = match TrashTable::insert(&trashed).await {
+        Ok(id) => id,
+        Err(e) => {
+            if let Err(undo) = organizer::move_file(&to, &from) {
+                tracing::error!("Failed to move {} back: {}", to.display(), undo);
+            }
+            return Err(e.context("Failed to record the trashed file, it was moved back"));
+        }
+    };
+
+    let paths = std::slice::from_ref(&track.filepath);
+    TrackTable::soft_remove_by_filepaths(paths).await?;
+    remove_tracks(paths);
+    FolderStore::load_filepaths().await?;
+
+    Ok(trashed)
+}
+
+/// Move a trashed file back to where it was deleted from and index it again,
+/// `None` when there is no such trashed file
+pub async fn restore(id: i64) -> Result<Option<Track>> {
+    let _guard = MOVING.lock().await;
+
+    let Some(trashed) = TrashTable::get(id).await? else {
+        return Ok(None);
+    };
+    let from = PathBuf::from(&trashed.trashpath);
+    let to = PathBuf::from(&trashed.filepath);
+    if to.exists() {
+        bail!("{} already exists", trashed.filepath);
+    }
+    if !from.is_file() {
+        bail!("The trashed file is missing");
+    }
+
+    let (src, dest) = (from.clone(), to.clone());
+    tokio::task::spawn_blocking(move || organizer::move_file(&src, &dest)).await??;
+    remove_entry_dir(&from);
+    TrashTable::delete(id).await?;
+
+    // the hidden row keeps the play stats, a scan may have dropped it since
+    let paths = std::slice::from_ref(&trashed.filepath);
+    TrackTable::restore_by_filepaths(paths).await?;
+    let indexed = reindex_track_files(paths).await?;
+    FolderStore::load_filepaths().await?;
+
+    indexed
+        .into_iter()
+        .next()
+        .map(Some)
+        .ok_or_else(|| anyhow!("{} was restored but could not be indexed", trashed.filepath))
+}
+
+/// Delete files that stayed in the trash longer than the retention period,
+/// returns how many were purged
+pub async fn purge_expired() -> Result<usize> {
+    let days = UserConfig::load()?.trash_retention_days;
+    let cutoff = chrono::Utc::now().timestamp() - i64::from(days) * 24 * 60 * 60;
+
+    let _guard = MOVING.lock().await;
+    let expired = TrashTable::deleted_before(cutoff).await?;
+    let mut purged = Vec::new();
+    for trashed in expired {
+        let path = PathBuf::from(&trashed.trashpath);
+        match std::fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                tracing::warn!("Failed to purge {}: {}", trashed.trashpath, e);
+                continue;
+            }
+        }
+        remove_entry_dir(&path);
+        TrashTable::delete(trashed.id).await?;
+        purged.push(trashed.filepath);
+    }
+
+    // only hidden rows go, a new file may have been indexed at the same path
+    let hidden: HashSet<String> = TrackTable::removed_filepaths().await?.into_iter().collect();
+    let rows: Vec<String> = purged
+        .iter()
+        .filter(|p| hidden.contains(*p))
+        .cloned()
+        .collect();
+    TrackTable::remove_by_filepaths(&rows).await?;
+
+    Ok(purged.len())
+}
+
+/// Where a deleted file is kept, each deletion gets its own folder so the
+/// file keeps its name
+fn trash_path(dir: &Path, track: &Track, deleted_at: i64) -> PathBuf {
+    let name = Path::new(&track.filepath)
+        .file_name()
+        .map(|n| n.to_os_string())
+        .unwrap_or_else(|| track.trackhash.clone().into());
+    dir.join(format!("{}-{}", deleted_at, track.trackhash))
+        .join(name)
+}
+
+/// Remove the folder of a deletion once its file is gone
+fn remove_entry_dir(path: &Path) {
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::remove_dir(dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trash_path() {
+        let mut track = Track::new();
+        track.trackhash = "abc".to_string();
+        track.filepath = "/music/artist/01 song.flac".to_string();
+
+        assert_eq!(
+            trash_path(Path::new("/trash"), &track, 1700000000),
+            PathBuf::from("/trash/1700000000-abc/01 song.flac")
+        );
+    }
+}
@@ -1,11 +1,56 @@
 //! File system watcher for detecting music library changes
-
-use std::path::PathBuf;
+//!
+//! every root directory gets its own supervised watcher task. a watcher that
+//! errors or panics is restarted with backoff without touching the others, and
+//! its health and most recent events are kept for the status endpoint.
+//!
+//! events are collected until the root goes quiet, then only the files they
+//! touched are indexed or removed, so copying an album in is one update.
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{channel, Receiver, Sender};
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use futures::FutureExt;
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Config, Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use serde::Serialize;
+
+use crate::config::{UserConfig, WatchdogRootOptions};
+use crate::core::indexer::{is_audio_path, Indexer, ScanProgress};
+use crate::core::populate::apply_file_changes;
+use crate::stores::TrackStore;
+use crate::utils::filesystem::normalize_path;
+
+/// events kept per root for the status endpoint
+const RECENT_EVENTS: usize = 20;
+
+/// first restart delay, doubled on every consecutive failure
+const RESTART_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// longest wait between restarts
+const RESTART_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// a watcher running this long resets the restart backoff
+const STABLE_AFTER: Duration = Duration::from_secs(300);
+
+/// how often a watcher drains events
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// how often a watcher checks its root is still there
+const ROOT_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// how long a root has to be quiet before its changes are applied
+const DEBOUNCE_QUIET: Duration = Duration::from_secs(2);
 
-use anyhow::Result;
-use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+/// longest a busy root holds changes back
+const DEBOUNCE_MAX: Duration = Duration::from_secs(30);
 
 /// File system event types
 #[derive(Debug, Clone)]
@@ -18,30 +63,48 @@ pub enum FsEvent {
 
 /// File system watchdog
 pub struct Watchdog {
-    watcher: RecommendedWatcher,
+    watcher: Box<dyn Watcher + Send>,
     receiver: Receiver<FsEvent>,
+    errors: Receiver<notify::Error>,
+    recursive: RecursiveMode,
     watched_paths: Vec<PathBuf>,
 }
 
 impl Watchdog {
     /// Create new watchdog
     pub fn new() -> Result<Self> {
+        Self::with_options(&WatchdogRootOptions::default())
+    }
+
+    /// Create a watchdog using the native or polling backend of a root's options
+    pub fn with_options(options: &WatchdogRootOptions) -> Result<Self> {
         let (tx, rx) = channel();
+        let (err_tx, err_rx) = channel();
 
-        let event_handler = move |res: Result<Event, notify::Error>| {
-            if let Ok(event) = res {
-                Self::handle_event(&tx, event);
+        let event_handler = move |res: Result<Event, notify::Error>| match res {
+            Ok(event) => Self::handle_event(&tx, event),
+            Err(e) => {
+                let _ = err_tx.send(e);
             }
         };
 
-        let watcher = RecommendedWatcher::new(
-            event_handler,
-            Config::default().with_poll_interval(Duration::from_secs(2)),
-        )?;
+        let config =
+            Config::default().with_poll_interval(Duration::from_secs(options.poll_interval.max(1)));
+        let watcher: Box<dyn Watcher + Send> = if options.poll {
+            Box::new(PollWatcher::new(event_handler, config)?)
+        } else {
+            Box::new(RecommendedWatcher::new(event_handler, config)?)
+        };
 
         Ok(Self {
             watcher,
             receiver: rx,
+            errors: err_rx,
+            recursive: if options.recursive {
+                RecursiveMode::Recursive
+            } else {
+                RecursiveMode::NonRecursive
+            },
             watched_paths: Vec::new(),
         })
     }
@@ -49,6 +112,22 @@ impl Watchdog {
     /// Handle raw notify event
     fn handle_event(tx: &Sender<FsEvent>, event: Event) {
         match event.kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+                let _ = tx.send(FsEvent::Renamed(
+                    event.paths[0].clone(),
+                    event.paths[1].clone(),
+                ));
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                for path in event.paths {
+                    let _ = tx.send(FsEvent::Deleted(path));
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                for path in event.paths {
+                    let _ = tx.send(FsEvent::Created(path));
+                }
+            }
             EventKind::Create(_) => {
                 for path in event.paths {
                     let _ = tx.send(FsEvent::Created(path));
@@ -79,7 +158,7 @@ impl Watchdog {
 
     /// Watch a directory
     pub fn watch(&mut self, path: &PathBuf) -> Result<()> {
-        self.watcher.watch(path, RecursiveMode::Recursive)?;
+        self.watcher.watch(path, self.recursive)?;
         self.watched_paths.push(path.clone());
         Ok(())
     }
@@ -119,6 +198,11 @@ impl Watchdog {
         events
     }
 
+    /// Take the next error reported by the watcher backend, if any
+    pub fn next_error(&self) -> Option<notify::Error> {
+        self.errors.try_recv().ok()
+    }
+
     /// Wait for next event (blocking)
     pub fn wait_for_event(&self) -> Result<FsEvent> {
         Ok(self.receiver.recv()?)
@@ -136,15 +220,7 @@ impl Watchdog {
 
     /// Check if path is audio file
     pub fn is_audio_file(path: &PathBuf) -> bool {
-        const AUDIO_EXTENSIONS: &[&str] = &[
-            "mp3", "flac", "ogg", "wav", "m4a", "aac", "wma", "opus", "aiff", "alac",
-            "ape", "wv", "mpc", "tta", "dsf", "dff", "webm", "mka", "spx",
-        ];
-
-        path.extension()
-            .and_then(|ext| ext.to_str())
-            .map(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
-            .unwrap_or(false)
+        is_audio_path(path)
     }
 
     /// Filter events to only audio file events
@@ -167,53 +243,560 @@ impl Default for Watchdog {
     }
 }
 
-/// Start the watchdog service
-pub async fn start_watchdog() -> Result<()> {
-    use crate::config::UserConfig;
-    use crate::core::file_cache::FileCache;
+/// Health of a root watcher
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatcherState {
+    Starting,
+    Running,
+    Restarting,
+}
+
+/// Audio file event handled by a watcher
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchEvent {
+    pub kind: &'static str,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    pub timestamp: i64,
+}
+
+impl From<&FsEvent> for WatchEvent {
+    fn from(event: &FsEvent) -> Self {
+        let (kind, path, to) = match event {
+            FsEvent::Created(path) => ("created", path, None),
+            FsEvent::Modified(path) => ("modified", path, None),
+            FsEvent::Deleted(path) => ("deleted", path, None),
+            FsEvent::Renamed(from, to) => ("renamed", from, Some(to)),
+        };
+
+        Self {
+            kind,
+            path: path.to_string_lossy().to_string(),
+            to: to.map(|p| p.to_string_lossy().to_string()),
+            timestamp: chrono::Utc::now().timestamp(),
+        }
+    }
+}
+
+/// Health and recent activity of the watcher for one root directory
+#[derive(Debug, Clone, Serialize)]
+pub struct WatcherStatus {
+    /// root as listed in the config
+    pub root: String,
+    /// resolved directory being watched
+    pub path: String,
+    pub state: WatcherState,
+    pub options: WatchdogRootOptions,
+    pub started_at: Option<i64>,
+    pub restarts: u32,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<i64>,
+    pub events_processed: u64,
+    /// files waiting for the root to go quiet
+    pub pending_changes: usize,
+    /// newest first
+    pub recent_events: VecDeque<WatchEvent>,
+}
 
-    let config = UserConfig::load()?;
+struct RootWatcher {
+    options: WatchdogRootOptions,
+    status: Arc<Mutex<WatcherStatus>>,
+    task: tokio::task::JoinHandle<()>,
+}
 
-    if !config.enable_watchdog {
-        return Ok(());
+/// running watchers keyed by root as listed in the config
+static WATCHERS: Lazy<RwLock<HashMap<String, RootWatcher>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// Start, stop and restart root watchers so they match the config
+///
+/// roots whose options changed get a fresh watcher, everything else keeps
+/// running untouched
+pub fn sync_watchers(config: &UserConfig) {
+    let mut desired: HashMap<String, (PathBuf, WatchdogRootOptions)> = HashMap::new();
+    if config.enable_watchdog {
+        for root in &config.root_dirs {
+            let options = config.watchdog_options(root);
+            if !options.enabled {
+                continue;
+            }
+            match resolve_root(root) {
+                Some(path) => {
+                    desired.insert(root.clone(), (path, options));
+                }
+                None => tracing::warn!("cannot resolve root directory {} for watching", root),
+            }
+        }
     }
 
-    let mut watchdog = Watchdog::new()?;
+    let mut watchers = WATCHERS.write();
+    watchers.retain(|root, watcher| {
+        let keep = desired
+            .get(root)
+            .is_some_and(|(_, options)| *options == watcher.options);
+        if !keep {
+            watcher.task.abort();
+            tracing::info!("stopped watching {}", root);
+        }
+        keep
+    });
+
+    for (root, (path, options)) in desired {
+        if let Entry::Vacant(entry) = watchers.entry(root) {
+            tracing::info!("watching {} for changes", path.display());
+            let watcher = spawn_root_watcher(entry.key().clone(), path, options);
+            entry.insert(watcher);
+        }
+    }
+}
 
-    for root_dir in &config.root_dirs {
-        watchdog.watch(&PathBuf::from(root_dir))?;
+/// Status of every running root watcher
+pub fn watcher_statuses() -> Vec<WatcherStatus> {
+    let mut statuses: Vec<WatcherStatus> = WATCHERS
+        .read()
+        .values()
+        .map(|w| w.status.lock().clone())
+        .collect();
+    statuses.sort_by(|a, b| a.root.cmp(&b.root));
+    statuses
+}
+
+fn resolve_root(root: &str) -> Option<PathBuf> {
+    if root == "$home" {
+        return directories::UserDirs::new().map(|u| u.home_dir().to_path_buf());
     }
 
-    // Process events in a loop
+    let normalized = normalize_path(root);
+    (!normalized.is_empty()).then(|| PathBuf::from(normalized))
+}
+
+fn spawn_root_watcher(root: String, path: PathBuf, options: WatchdogRootOptions) -> RootWatcher {
+    let status = Arc::new(Mutex::new(WatcherStatus {
+        root,
+        path: path.to_string_lossy().to_string(),
+        state: WatcherState::Starting,
+        options,
+        started_at: None,
+        restarts: 0,
+        last_error: None,
+        last_error_at: None,
+        events_processed: 0,
+        pending_changes: 0,
+        recent_events: VecDeque::with_capacity(RECENT_EVENTS),
+    }));
+
+    let task = tokio::spawn(supervise_root(path, options, status.clone()));
+    RootWatcher {
+        options,
+        status,
+        task,
+    }
+}
+
+/// Keep a root watcher alive, restarting it with backoff when it fails or panics
+async fn supervise_root(
+    path: PathBuf,
+    options: WatchdogRootOptions,
+    status: Arc<Mutex<WatcherStatus>>,
+) {
+    let mut failures = 0u32;
+
     loop {
+        let started = std::time::Instant::now();
+        let result = std::panic::AssertUnwindSafe(watch_root(&path, &options, &status))
+            .catch_unwind()
+            .await;
+
+        let error = match result {
+            Ok(Ok(never)) => match never {},
+            Ok(Err(e)) => e.to_string(),
+            Err(panic) => panic_message(panic.as_ref()),
+        };
+
+        if started.elapsed() >= STABLE_AFTER {
+            failures = 0;
+        }
+        failures += 1;
+        let delay = restart_delay(failures);
+
+        tracing::warn!(
+            "watcher for {} failed, restarting in {}s: {}",
+            path.display(),
+            delay.as_secs(),
+            error
+        );
+
+        {
+            let mut status = status.lock();
+            status.state = WatcherState::Restarting;
+            status.restarts += 1;
+            status.last_error = Some(error);
+            status.last_error_at = Some(chrono::Utc::now().timestamp());
+        }
+
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Watch a single root until the backend reports an error or the root goes away
+async fn watch_root(
+    path: &Path,
+    options: &WatchdogRootOptions,
+    status: &Mutex<WatcherStatus>,
+) -> Result<std::convert::Infallible> {
+    if !path.is_dir() {
+        return Err(anyhow!("root directory does not exist: {}", path.display()));
+    }
+
+    let mut watchdog = Watchdog::with_options(options)?;
+    watchdog.watch(&path.to_path_buf())?;
+
+    {
+        let mut status = status.lock();
+        status.state = WatcherState::Running;
+        status.started_at = Some(chrono::Utc::now().timestamp());
+    }
+
+    let mut last_root_check = Instant::now();
+    let mut pending = PendingChanges::default();
+    loop {
+        if let Some(e) = watchdog.next_error() {
+            return Err(e.into());
+        }
+
+        // native backends go quiet when a mount disappears
+        if last_root_check.elapsed() >= ROOT_CHECK_INTERVAL {
+            if !path.is_dir() {
+                return Err(anyhow!(
+                    "root directory is no longer available: {}",
+                    path.display()
+                ));
+            }
+            last_root_check = Instant::now();
+        }
+
         let events = watchdog.get_events();
-        if !events.is_empty() {
-            let audio_events = Watchdog::filter_audio_events(events);
-
-            if !audio_events.is_empty() {
-                // invalidate file cache for changed paths
-                if let Some(cache) = FileCache::get() {
-                    for event in &audio_events {
-                        match event {
-                            FsEvent::Modified(path) | FsEvent::Deleted(path) => {
-                                cache.invalidate_path(path);
-                            }
-                            FsEvent::Renamed(from, to) => {
-                                cache.invalidate_path(from);
-                                cache.invalidate_path(to);
-                            }
-                            FsEvent::Created(_) => {
-                                // new files don't need cache invalidation
-                            }
-                        }
-                    }
+        let now = Instant::now();
+        for event in &events {
+            pending.record(event, now);
+        }
+
+        let audio_events = Watchdog::filter_audio_events(events);
+        if !audio_events.is_empty() {
+            invalidate_cache(&audio_events);
+
+            let mut status = status.lock();
+            status.events_processed += audio_events.len() as u64;
+            for event in &audio_events {
+                if status.recent_events.len() == RECENT_EVENTS {
+                    status.recent_events.pop_back();
                 }
+                status.recent_events.push_front(WatchEvent::from(event));
+            }
+        }
+
+        // a running scan indexes the same files, the changes wait for it
+        if pending.is_due(now) && !ScanProgress::state().running {
+            let (changed, removed) = pending.take();
+            apply_changes(changed, removed).await;
+        }
+        status.lock().pending_changes = pending.len();
+
+        tokio::time::sleep(EVENT_POLL_INTERVAL).await;
+    }
+}
+
+/// Changes seen since they were last applied, coalesced so a file written in
+/// many steps is indexed once and a file deleted again is not indexed at all
+#[derive(Debug, Default)]
+struct PendingChanges {
+    changed: HashSet<PathBuf>,
+    removed: HashSet<PathBuf>,
+    first_at: Option<Instant>,
+    last_at: Option<Instant>,
+}
+
+impl PendingChanges {
+    /// Add an event, created folders and removed paths of any kind are kept
+    /// since they may hold audio files
+    fn record(&mut self, event: &FsEvent, now: Instant) {
+        match event {
+            FsEvent::Created(path) => self.change(path),
+            FsEvent::Modified(path) if Watchdog::is_audio_file(path) => self.change(path),
+            FsEvent::Modified(_) => return,
+            FsEvent::Deleted(path) => self.remove(path),
+            FsEvent::Renamed(from, to) => {
+                self.remove(from);
+                self.change(to);
+            }
+        }
+        self.first_at.get_or_insert(now);
+        self.last_at = Some(now);
+    }
+
+    fn change(&mut self, path: &Path) {
+        self.removed.remove(path);
+        self.changed.insert(path.to_path_buf());
+    }
+
+    fn remove(&mut self, path: &Path) {
+        self.changed.remove(path);
+        self.removed.insert(path.to_path_buf());
+    }
+
+    fn len(&self) -> usize {
+        self.changed.len() + self.removed.len()
+    }
+
+    /// Whether the root has been quiet long enough, or busy for too long
+    fn is_due(&self, now: Instant) -> bool {
+        match (self.first_at, self.last_at) {
+            (Some(first), Some(last)) => {
+                now.duration_since(last) >= DEBOUNCE_QUIET
+                    || now.duration_since(first) >= DEBOUNCE_MAX
+            }
+            _ => false,
+        }
+    }
+
+    /// Take the changed and removed paths, leaving nothing pending
+    fn take(&mut self) -> (Vec<PathBuf>, Vec<PathBuf>) {
+        let pending = std::mem::take(self);
+        (
+            pending.changed.into_iter().collect(),
+            pending.removed.into_iter().collect(),
+        )
+    }
+}
+
+/// Index the files a burst of events touched and drop the removed ones
+async fn apply_changes(changed: Vec<PathBuf>, removed: Vec<PathBuf>) {
+    let indexer = match UserConfig::load() {
+        Ok(config) => Indexer::from_config(&config),
+        Err(e) => {
+            tracing::warn!("failed to load config for file changes: {}", e);
+            return;
+        }
+    };
+    let resolved =
+        tokio::task::spawn_blocking(move || resolve_changes(&indexer, changed, removed)).await;
+    let (files, removed) = match resolved {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            tracing::warn!("failed to resolve file changes: {}", e);
+            return;
+        }
+    };
+    if files.is_empty() && removed.is_empty() {
+        return;
+    }
+
+    match apply_file_changes(&files, &removed).await {
+        Ok(summary) => tracing::info!(
+            "indexed {} and removed {} changed audio files",
+            summary.indexed,
+            summary.removed
+        ),
+        Err(e) => tracing::warn!("failed to apply file changes: {}", e),
+    }
+}
+
+/// Turn pending paths into audio files to index and library paths to remove,
+/// created folders are walked and removed folders take their tracks with them
+///
+/// new files go through the same filters as a scan, excluded and hidden
+/// directories included
+fn resolve_changes(
+    indexer: &Indexer,
+    changed: Vec<PathBuf>,
+    removed: Vec<PathBuf>,
+) -> (Vec<String>, Vec<String>) {
+    let mut files = Vec::new();
+    let mut gone = Vec::new();
+
+    for path in changed {
+        if path.is_dir() {
+            if indexer.accepts_dir(&path) {
+                files.extend(indexer.audio_files_in(&path));
+            }
+        } else if Watchdog::is_audio_file(&path) {
+            // a file renamed away only shows up as modified on some backends
+            if !path.is_file() {
+                gone.push(path);
+            } else if indexer.accepts_file(&path) {
+                files.push(path);
+            }
+        }
+    }
+
+    let mut removed_dirs = Vec::new();
+    for path in removed {
+        if Watchdog::is_audio_file(&path) {
+            gone.push(path);
+        } else {
+            removed_dirs.push(path);
+        }
+    }
+    let mut removed: Vec<String> = gone
+        .iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+    if !removed_dirs.is_empty() {
+        removed.extend(
+            TrackStore::get()
+                .get_all_paths()
+                .into_iter()
+                .filter(|p| removed_dirs.iter().any(|dir| Path::new(p).starts_with(dir))),
+        );
+    }
+
+    let files = files
+        .iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+    (files, removed)
+}
 
-                // TODO: Handle events (reindex changed files)
-                tracing::info!("Detected {} audio file changes", audio_events.len());
+fn invalidate_cache(audio_events: &[FsEvent]) {
+    use crate::core::file_cache::FileCache;
+
+    // invalidate file cache for changed paths
+    if let Some(cache) = FileCache::get() {
+        for event in audio_events {
+            match event {
+                FsEvent::Modified(path) | FsEvent::Deleted(path) => {
+                    cache.invalidate_path(path);
+                }
+                FsEvent::Renamed(from, to) => {
+                    cache.invalidate_path(from);
+                    cache.invalidate_path(to);
+                }
+                FsEvent::Created(_) => {
+                    // new files don't need cache invalidation
+                }
             }
         }
+    }
+}
+
+fn restart_delay(failures: u32) -> Duration {
+    let exponent = failures.saturating_sub(1).min(6);
+    RESTART_BASE_DELAY
+        .saturating_mul(1 << exponent)
+        .min(RESTART_MAX_DELAY)
+}
+
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        format!("watcher panicked: {}", message)
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        format!("watcher panicked: {}", message)
+    } else {
+        "watcher panicked".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_changes_uses_scan_filters() {
+        let root = tempfile::tempdir().unwrap();
+        let album = root.path().join("album");
+        let excluded = root.path().join("excluded");
+        for dir in [&album, &album.join(".hidden"), &excluded] {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        for file in [
+            album.join("01.flac"),
+            album.join("cover.jpg"),
+            album.join("._01.flac"),
+            album.join(".hidden").join("02.flac"),
+            excluded.join("03.flac"),
+        ] {
+            std::fs::write(file, b"data").unwrap();
+        }
+
+        let indexer = Indexer::new(vec![root.path().to_string_lossy().to_string()], Vec::new())
+            .with_exclude_dirs(&[excluded.to_string_lossy().to_string()]);
+        let changed = vec![
+            album.clone(),
+            excluded.clone(),
+            excluded.join("03.flac"),
+            album.join(".hidden").join("02.flac"),
+        ];
+        let (files, removed) = resolve_changes(&indexer, changed, Vec::new());
+
+        assert_eq!(
+            files,
+            vec![album.join("01.flac").to_string_lossy().to_string()]
+        );
+        assert!(removed.is_empty());
+    }
+
+    #[test]
+    fn test_restart_delay_backs_off_to_limit() {
+        assert_eq!(restart_delay(1), Duration::from_secs(1));
+        assert_eq!(restart_delay(2), Duration::from_secs(2));
+        assert_eq!(restart_delay(4), Duration::from_secs(8));
+        assert_eq!(restart_delay(50), RESTART_MAX_DELAY);
+    }
+
+    #[test]
+    fn test_watch_event_from_rename() {
+        let event = WatchEvent::from(&FsEvent::Renamed(
+            PathBuf::from("/music/a.mp3"),
+            PathBuf::from("/music/b.mp3"),
+        ));
+        assert_eq!(event.kind, "renamed");
+        assert_eq!(event.path, "/music/a.mp3");
+        assert_eq!(event.to.as_deref(), Some("/music/b.mp3"));
+    }
+
+    #[test]
+    fn test_pending_changes_coalesce() {
+        let now = Instant::now();
+        let mut pending = PendingChanges::default();
+        let song = PathBuf::from("/music/a.mp3");
+
+        pending.record(&FsEvent::Created(song.clone()), now);
+        pending.record(&FsEvent::Modified(song.clone()), now);
+        pending.record(&FsEvent::Modified(song.clone()), now);
+        pending.record(&FsEvent::Modified(PathBuf::from("/music/album")), now);
+        assert_eq!(pending.len(), 1);
+
+        pending.record(
+            &FsEvent::Renamed(song.clone(), PathBuf::from("/music/b.mp3")),
+            now,
+        );
+        pending.record(&FsEvent::Deleted(PathBuf::from("/music/c.mp3")), now);
+        let (changed, mut removed) = pending.take();
+        removed.sort();
+        assert_eq!(changed, vec![PathBuf::from("/music/b.mp3")]);
+        assert_eq!(removed, vec![song, PathBuf::from("/music/c.mp3")]);
+        assert_eq!(pending.len(), 0);
+    }
+
+    #[test]
+    fn test_pending_changes_due() {
+        let start = Instant::now();
+        let mut pending = PendingChanges::default();
+        assert!(!pending.is_due(start + DEBOUNCE_MAX));
+
+        // a steady trickle of events holds the changes back until the limit
+        for second in 0..DEBOUNCE_MAX.as_secs() {
+            let now = start + Duration::from_secs(second);
+            pending.record(&FsEvent::Modified(PathBuf::from("/music/a.mp3")), now);
+            assert!(!pending.is_due(now + Duration::from_millis(500)));
+        }
+        assert!(pending.is_due(start + DEBOUNCE_MAX));
 
-        tokio::time::sleep(Duration::from_millis(100)).await;
+        pending.take();
+        pending.record(&FsEvent::Deleted(PathBuf::from("/music/a.mp3")), start);
+        assert!(!pending.is_due(start + Duration::from_secs(1)));
+        assert!(pending.is_due(start + DEBOUNCE_QUIET));
     }
 }
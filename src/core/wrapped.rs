@@ -0,0 +1,501 @@
+//! Year in review of a user's listening
+//!
+//! aggregates a calendar year (utc) of scrobbles into top tracks, albums and
+//! artists, listening time per month, genres and what the user discovered.
+//! reports are cached per user and year until the next scrobble lands. the
+//! share image is a card in the color of the top albums with their covers and
+//! a bar for each month.
+
+use anyhow::Result;
+use chrono::{Datelike, TimeZone, Utc};
+use image::{imageops, ImageFormat, Rgba, RgbaImage};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+
+use crate::core::audiobooks;
+use crate::core::colorlib::ColorLib;
+use crate::core::images::{thumbnail_path, ThumbnailFormat};
+use crate::db::tables::{DiscoveryTable, ScrobbleTable};
+use crate::models::{Track, TrackLog};
+use crate::stores::TrackStore;
+
+/// items in each top list
+const TOP_COUNT: usize = 5;
+
+/// genres listed before the rest are folded into "other"
+const GENRE_COUNT: usize = 8;
+
+/// cached reports kept before the cache is emptied
+const MAX_CACHED: usize = 64;
+
+/// width and height of the share image
+const IMAGE_SIZE: u32 = 1080;
+
+/// (userid, year) to the report and the scrobble generation it was built at
+type ReportCache = HashMap<(i64, i32), (u64, WrappedReport)>;
+
+static CACHE: Lazy<RwLock<ReportCache>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// A track, album or artist the user played the most
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WrappedItem {
+    pub hash: String,
+    pub title: String,
+    /// artists of tracks and albums, empty for artists
+    pub subtitle: String,
+    /// image file name, `<albumhash>.webp` or `<artisthash>.webp`
+    pub image: String,
+    pub playcount: i64,
+    /// Listening time in seconds
+    pub playduration: i64,
+}
+
+/// Listening in one month
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MonthListening {
+    /// 1 for january
+    pub month: u32,
+    pub playcount: i64,
+    pub playduration: i64,
+    pub hours: f64,
+}
+
+/// Share of the listening time that went to a genre
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GenreShare {
+    pub genre: String,
+    pub playduration: i64,
+    /// from 0 to 1
+    pub share: f64,
+}
+
+/// Items first played during the year
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Discoveries {
+    pub tracks: usize,
+    pub albums: usize,
+    pub artists: usize,
+    /// the most played of the artists first played during the year
+    pub top_new_artist: Option<WrappedItem>,
+}
+
+/// A year of listening
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct WrappedReport {
+    pub year: i32,
+    pub playcount: i64,
+    /// Listening time in seconds
+    pub playduration: i64,
+    pub hours: f64,
+    pub tracks: usize,
+    pub albums: usize,
+    pub artists: usize,
+    pub top_tracks: Vec<WrappedItem>,
+    pub top_albums: Vec<WrappedItem>,
+    pub top_artists: Vec<WrappedItem>,
+    pub months: Vec<MonthListening>,
+    pub genres: Vec<GenreShare>,
+    pub discoveries: Discoveries,
+}
+
+/// First and last second of a year in utc
+pub fn year_range(year: i32) -> Option<(i64, i64)> {
+    let start = Utc.with_ymd_and_hms(year, 1, 1, 0, 0, 0).single()?;
+    let end = Utc.with_ymd_and_hms(year + 1, 1, 1, 0, 0, 0).single()?;
+    Some((start.timestamp(), end.timestamp() - 1))
+}
+
+/// The year in review of a user
+pub async fn for_year(user_id: i64, year: i32) -> Result<WrappedReport> {
+    let Some((start, end)) = year_range(year) else {
+        anyhow::bail!("Invalid year {}", year);
+    };
+
+    let key = (user_id, year);
+    let generation = ScrobbleTable::generation();
+    if let Some((built_at, report)) = CACHE.read().get(&key) {
+        if *built_at == generation {
+            return Ok(report.clone());
+        }
+    }
+
+    let plays = ScrobbleTable::get_in_range(user_id, start, end).await?;
+    let discovered = DiscoveryTable::discovered_between(user_id, start, end).await?;
+
+    let track_store = TrackStore::get();
+    let hashes: Vec<String> = plays
+        .iter()
+        .map(|p| p.trackhash.clone())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    let tracks: HashMap<String, Track> = track_store
+        .get_by_hashes(&hashes)
+        .into_iter()
+        .filter(|t| !audiobooks::is_audiobook(t))
+        .map(|t| (t.trackhash.clone(), t))
+        .collect();
+
+    let report = aggregate(year, &plays, &tracks, &discovered);
+
+    let mut cache = CACHE.write();
+    if cache.len() >= MAX_CACHED {
+        cache.clear();
+    }
+    cache.insert(key, (generation, report.clone()));
+    Ok(report)
+}
+
+/// Running totals of one item
+#[derive(Default)]
+struct Tally {
+    playcount: i64,
+    playduration: i64,
+}
+
+impl Tally {
+    fn add(&mut self, duration: i64) {
+        self.playcount += 1;
+        self.playduration += duration;
+    }
+}
+
+/// Fold a year of plays into a report, plays of tracks not in `tracks` are
+/// left out
+fn aggregate(
+    year: i32,
+    plays: &[TrackLog],
+    tracks: &HashMap<String, Track>,
+    discovered: &[(String, String)],
+) -> WrappedReport {
+    let mut report = WrappedReport {
+        year,
+        months: (1..=12)
+            .map(|month| MonthListening {
+                month,
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    };
+
+    let mut by_track: HashMap<&str, Tally> = HashMap::new();
+    let mut by_album: HashMap<&str, (Tally, &Track)> = HashMap::new();
+    let mut by_artist: HashMap<&str, (Tally, &str)> = HashMap::new();
+    let mut by_genre: HashMap<&str, i64> = HashMap::new();
+
+    for play in plays {
+        let Some(track) = tracks.get(&play.trackhash) else {
+            continue;
+        };
+        let duration = i64::from(play.duration.max(0));
+
+        report.playcount += 1;
+        report.playduration += duration;
+        if let Some(month) = Utc
+            .timestamp_opt(play.timestamp, 0)
+            .single()
+            .and_then(|t| report.months.get_mut(t.month0() as usize))
+        {
+            month.playcount += 1;
+            month.playduration += duration;
+        }
+
+        by_track.entry(&track.trackhash).or_default().add(duration);
+        by_album
+            .entry(&track.albumhash)
+            .or_insert_with(|| (Tally::default(), track))
+            .0
+            .add(duration);
+        for artist in &track.artists {
+            by_artist
+                .entry(&artist.artisthash)
+                .or_insert_with(|| (Tally::default(), &artist.name))
+                .0
+                .add(duration);
+        }
+        for genre in &track.genres {
+            *by_genre.entry(&genre.name).or_default() += duration;
+        }
+    }
+
+    report.hours = hours(report.playduration);
+    for month in &mut report.months {
+        month.hours = hours(month.playduration);
+    }
+    report.tracks = by_track.len();
+    report.albums = by_album.len();
+    report.artists = by_artist.len();
+
+    report.top_tracks = top(by_track.iter().map(|(hash, tally)| {
+        let track = &tracks[*hash];
+        item(tally, hash, &track.title, track.artist(), &track.albumhash)
+    }));
+    report.top_albums = top(by_album.iter().map(|(hash, (tally, track))| {
+        let artists = track
+            .albumartists
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        item(tally, hash, &track.album, artists, hash)
+    }));
+    let artists: Vec<WrappedItem> = by_artist
+        .iter()
+        .map(|(hash, (tally, name))| item(tally, hash, name, String::new(), hash))
+        .collect();
+
+    let new_artists: HashSet<&str> = discovered
+        .iter()
+        .filter(|(kind, _)| kind == "artist")
+        .map(|(_, hash)| hash.as_str())
+        .collect();
+    let count = |kind: &str| discovered.iter().filter(|(k, _)| k == kind).count();
+    report.discoveries = Discoveries {
+        tracks: count("track"),
+        albums: count("album"),
+        artists: new_artists.len(),
+        top_new_artist: top(artists
+            .iter()
+            .filter(|a| new_artists.contains(a.hash.as_str()))
+            .cloned())
+        .into_iter()
+        .next(),
+    };
+    report.top_artists = top(artists.into_iter());
+
+    report.genres = genre_shares(by_genre);
+    report
+}
+
+fn item(tally: &Tally, hash: &str, title: &str, subtitle: String, image: &str) -> WrappedItem {
+    WrappedItem {
+        hash: hash.to_string(),
+        title: title.to_string(),
+        subtitle,
+        image: format!("{}.webp", image),
+        playcount: tally.playcount,
+        playduration: tally.playduration,
+    }
+}
+
+/// most plays first, then most listening time and the title for a stable order
+fn top(items: impl Iterator<Item = WrappedItem>) -> Vec<WrappedItem> {
+    let mut items: Vec<WrappedItem> = items.collect();
+    items.sort_by(|a, b| {
+        b.playcount
+            .cmp(&a.playcount)
+            .then(b.playduration.cmp(&a.playduration))
+            .then_with(|| a.title.cmp(&b.title))
+    });
+    items.truncate(TOP_COUNT);
+    items
+}
+
+/// Largest genres by listening time, the rest folded into "other"
+///
+/// a track with several genres counts fully for each, so shares are taken of
+/// the summed genre time rather than of the year's listening time
+fn genre_shares(by_genre: HashMap<&str, i64>) -> Vec<GenreShare> {
+    let sum: i64 = by_genre.values().sum();
+    if sum == 0 {
+        return Vec::new();
+    }
+
+    let mut genres: Vec<(&str, i64)> = by_genre.into_iter().collect();
+    genres.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+    let share = |duration: i64| duration as f64 / sum as f64;
+    let mut shares: Vec<GenreShare> = genres
+        .iter()
+        .take(GENRE_COUNT)
+        .map(|(genre, duration)| GenreShare {
+            genre: genre.to_string(),
+            playduration: *duration,
+            share: share(*duration),
+        })
+        .collect();
+
+    let other: i64 = genres.iter().skip(GENRE_COUNT).map(|(_, d)| d).sum();
+    if other > 0 {
+        shares.push(GenreShare {
+            genre: "other".to_string(),
+            playduration: other,
+            share: share(other),
+        });
+    }
+    shares
+}
+
+fn hours(seconds: i64) -> f64 {
+    (seconds as f64 / 360.0).round() / 10.0
+}
+
+/// PNG share card of a report
+///
+/// the top album covers fill the upper part of the card, the months are bars
+/// scaled to the busiest month below them
+pub fn render_image(report: &WrappedReport) -> Result<Vec<u8>> {
+    let covers: Vec<_> = report
+        .top_albums
+        .iter()
+        .take(4)
+        .filter_map(|album| {
+            let albumhash = album.image.trim_end_matches(".webp");
+            thumbnail_path(albumhash, "large", ThumbnailFormat::WebP)
+        })
+        .collect();
+
+    let background = ColorLib::extract_from_collage(&covers)
+        .ok()
+        .and_then(|hex| ColorLib::hex_to_rgb(&ColorLib::darken(&hex, 0.3)))
+        .unwrap_or((24, 24, 24));
+    let bar_color = if ColorLib::is_dark(&ColorLib::rgb_to_hex(background)) {
+        Rgba([255, 255, 255, 230])
+    } else {
+        Rgba([0, 0, 0, 230])
+    };
+
+    let mut card = RgbaImage::from_pixel(
+        IMAGE_SIZE,
+        IMAGE_SIZE,
+        Rgba([background.0, background.1, background.2, 255]),
+    );
+
+    // covers in a 2x2 grid, or a single large one
+    let margin = 60;
+    let grid = IMAGE_SIZE - margin * 2;
+    let area = grid * 2 / 3;
+    let (columns, tile) = if covers.len() >= 4 {
+        (2, area / 2)
+    } else {
+        (1, area)
+    };
+    let left = (IMAGE_SIZE - tile * columns) / 2;
+    for (i, path) in covers.iter().take((columns * columns) as usize).enumerate() {
+        let Ok(cover) = image::open(path) else {
+            continue;
+        };
+        let cover = cover.resize_to_fill(tile, tile, imageops::FilterType::Triangle);
+        let (x, y) = (i as u32 % columns, i as u32 / columns);
+        imageops::overlay(
+            &mut card,
+            &cover.to_rgba8(),
+            i64::from(left + x * tile),
+            i64::from(margin + y * tile),
+        );
+    }
+
+    // a bar for each month along the bottom
+    let chart_top = margin + area + margin;
+    let chart_height = IMAGE_SIZE - margin - chart_top;
+    let slot = grid / 12;
+    let busiest = report
+        .months
+        .iter()
+        .map(|m| m.playduration)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    for (i, month) in report.months.iter().enumerate() {
+        let height = ((month.playduration as f64 / busiest as f64) * chart_height as f64) as u32;
+        let height = height.max(4);
+        let x0 = margin + i as u32 * slot + slot / 6;
+        let width = slot * 2 / 3;
+        for x in x0..x0 + width {
+            for y in (IMAGE_SIZE - margin - height)..(IMAGE_SIZE - margin) {
+                card.put_pixel(x, y, bar_color);
+            }
+        }
+    }
+
+    let mut buf = Vec::new();
+    card.write_to(&mut Cursor::new(&mut buf), ImageFormat::Png)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ArtistRefItem, GenreRef};
+
+    fn track(hash: &str, album: &str, artist: &str, genre: &str) -> Track {
+        Track {
+            trackhash: hash.to_string(),
+            title: format!("Track {}", hash),
+            albumhash: album.to_string(),
+            album: format!("Album {}", album),
+            artists: vec![ArtistRefItem::new(artist.to_string(), artist.to_string())],
+            genres: vec![GenreRef::new(genre.to_string(), genre.to_string())],
+            ..Track::new()
+        }
+    }
+
+    fn play(hash: &str, timestamp: i64, duration: i32) -> TrackLog {
+        TrackLog::new(hash.to_string(), timestamp, duration, String::new(), 1)
+    }
+
+    #[test]
+    fn test_year_range() {
+        assert_eq!(year_range(2024), Some((1_704_067_200, 1_735_689_599)));
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let tracks: HashMap<String, Track> = [
+            track("a", "x", "p", "rock"),
+            track("b", "x", "p", "rock"),
+            track("c", "y", "q", "jazz"),
+        ]
+        .into_iter()
+        .map(|t| (t.trackhash.clone(), t))
+        .collect();
+
+        let jan = 1_704_067_200 + 3600;
+        let mar = 1_709_251_200 + 3600;
+        let plays = [
+            play("a", jan, 200),
+            play("a", jan + 300, 200),
+            play("b", mar, 100),
+            play("c", mar + 300, 1000),
+            // a track no longer in the library
+            play("gone", mar, 500),
+        ];
+        let discovered = vec![
+            ("artist".to_string(), "q".to_string()),
+            ("track".to_string(), "c".to_string()),
+        ];
+
+        let report = aggregate(2024, &plays, &tracks, &discovered);
+
+        assert_eq!(report.playcount, 4);
+        assert_eq!(report.playduration, 1500);
+        assert_eq!((report.tracks, report.albums, report.artists), (3, 2, 2));
+        assert_eq!(report.top_tracks[0].hash, "a");
+        assert_eq!(report.top_albums[0].hash, "x");
+        assert_eq!(report.top_albums[0].playcount, 3);
+        assert_eq!(report.top_artists[0].hash, "p");
+
+        assert_eq!(report.months.len(), 12);
+        assert_eq!(report.months[0].playcount, 2);
+        assert_eq!(report.months[2].playduration, 1100);
+
+        assert_eq!(report.genres[0].genre, "jazz");
+        assert_eq!(report.genres[0].playduration, 1000);
+
+        assert_eq!(report.discoveries.artists, 1);
+        assert_eq!(report.discoveries.tracks, 1);
+        assert_eq!(report.discoveries.top_new_artist.unwrap().hash, "q");
+    }
+
+    #[test]
+    fn test_render_image_without_covers() {
+        let report = aggregate(2024, &[], &HashMap::new(), &[]);
+        let png = render_image(&report).unwrap();
+        let img = image::load_from_memory(&png).unwrap();
+        assert_eq!((img.width(), img.height()), (IMAGE_SIZE, IMAGE_SIZE));
+    }
+}
@@ -97,7 +97,9 @@ async fn create_tables() -> Result<()> {
             lastplayed INTEGER NOT NULL DEFAULT 0,
             playcount INTEGER NOT NULL DEFAULT 0,
             playduration INTEGER NOT NULL DEFAULT 0,
-            extra TEXT DEFAULT '{}'
+            extra TEXT DEFAULT '{}',
+            removed_at INTEGER,
+            has_lyrics INTEGER NOT NULL DEFAULT 0
         );
         CREATE INDEX IF NOT EXISTS idx_track_albumhash ON track(albumhash);
         CREATE INDEX IF NOT EXISTS idx_track_filepath ON track(filepath);
@@ -130,11 +132,12 @@ async fn create_tables() -> Result<()> {
         r#"
         CREATE TABLE IF NOT EXISTS favorite (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
-            hash TEXT NOT NULL UNIQUE,
+            hash TEXT NOT NULL,
             type TEXT NOT NULL,
             timestamp INTEGER NOT NULL,
             userid INTEGER NOT NULL DEFAULT 1,
             extra TEXT DEFAULT '{}',
+            UNIQUE (hash, type, userid),
             FOREIGN KEY (userid) REFERENCES user(id) ON DELETE CASCADE
         );
         CREATE INDEX IF NOT EXISTS idx_favorite_type ON favorite(type);
@@ -156,9 +159,45 @@ async fn create_tables() -> Result<()> {
             image TEXT,
             trackhashes TEXT NOT NULL DEFAULT '[]',
             settings TEXT NOT NULL DEFAULT '{}',
-            extra TEXT DEFAULT '{}'
+            extra TEXT DEFAULT '{}',
+            shared INTEGER NOT NULL DEFAULT 0
         );
         CREATE INDEX IF NOT EXISTS idx_playlist_name ON playlist(name);
+        CREATE INDEX IF NOT EXISTS idx_playlist_userid ON playlist(userid);
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Users allowed to edit a playlist they do not own
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS playlist_collaborator (
+            playlistid INTEGER NOT NULL,
+            userid INTEGER NOT NULL,
+            PRIMARY KEY (playlistid, userid),
+            FOREIGN KEY (playlistid) REFERENCES playlist(id) ON DELETE CASCADE,
+            FOREIGN KEY (userid) REFERENCES user(id) ON DELETE CASCADE
+        );
+        CREATE INDEX IF NOT EXISTS idx_playlist_collaborator_userid ON playlist_collaborator(userid);
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Playlist image table
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS playlist_image (
+            playlistid INTEGER NOT NULL,
+            filename TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            color TEXT NOT NULL DEFAULT '',
+            color_dark TEXT NOT NULL DEFAULT '',
+            color_light TEXT NOT NULL DEFAULT '',
+            PRIMARY KEY (playlistid, filename)
+        );
+        CREATE INDEX IF NOT EXISTS idx_playlist_image_filename ON playlist_image(filename);
         "#,
     )
     .execute(pool)
@@ -174,11 +213,13 @@ async fn create_tables() -> Result<()> {
             duration INTEGER NOT NULL,
             source TEXT NOT NULL,
             userid INTEGER NOT NULL,
+            device TEXT NOT NULL DEFAULT '',
             extra TEXT DEFAULT '{}',
             FOREIGN KEY (userid) REFERENCES user(id) ON DELETE CASCADE
         );
         CREATE INDEX IF NOT EXISTS idx_scrobble_trackhash ON scrobble(trackhash);
         CREATE INDEX IF NOT EXISTS idx_scrobble_userid ON scrobble(userid);
+        CREATE INDEX IF NOT EXISTS idx_scrobble_user_time ON scrobble(userid, timestamp);
         "#,
     )
     .execute(pool)
@@ -215,7 +256,10 @@ async fn create_tables() -> Result<()> {
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             hash TEXT NOT NULL UNIQUE,
             type TEXT NOT NULL,
-            color TEXT NOT NULL
+            color TEXT NOT NULL,
+            color_dark TEXT NOT NULL DEFAULT '',
+            color_light TEXT NOT NULL DEFAULT '',
+            arthash TEXT NOT NULL DEFAULT ''
         );
         CREATE INDEX IF NOT EXISTS idx_libdata_hash ON libdata(hash);
         CREATE INDEX IF NOT EXISTS idx_libdata_type ON libdata(type);
@@ -224,6 +268,293 @@ async fn create_tables() -> Result<()> {
     .execute(pool)
     .await?;
 
+    // MusicBrainz IDs of tracks, albums and artists
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS mbid (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            hash TEXT NOT NULL,
+            type TEXT NOT NULL,
+            mbid TEXT NOT NULL,
+            UNIQUE (hash, type)
+        );
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Rules splitting identically named artists apart
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS artistsplit (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            artisthash TEXT NOT NULL,
+            newhash TEXT NOT NULL,
+            name TEXT NOT NULL,
+            mbid TEXT,
+            folder TEXT,
+            albumhashes TEXT NOT NULL DEFAULT '[]',
+            created_at INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_artistsplit_artisthash ON artistsplit(artisthash);
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Chromaprint fingerprints per library file
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS fingerprint (
+            filepath TEXT PRIMARY KEY,
+            trackhash TEXT NOT NULL,
+            duration REAL NOT NULL DEFAULT 0,
+            fingerprint TEXT NOT NULL DEFAULT '',
+            last_mod INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE INDEX IF NOT EXISTS idx_fingerprint_trackhash ON fingerprint(trackhash);
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Encoder padding and edge silence of library files for gapless albums
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS gapless (
+            filepath TEXT PRIMARY KEY,
+            trackhash TEXT NOT NULL,
+            encoder_gapless INTEGER NOT NULL DEFAULT 0,
+            lead_silence REAL NOT NULL DEFAULT -1,
+            trail_silence REAL NOT NULL DEFAULT -1,
+            last_mod INTEGER NOT NULL DEFAULT 0
+        );
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Spectral verdicts on lossless files, flagging likely lossy transcodes
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS lossless_check (
+            filepath TEXT PRIMARY KEY,
+            trackhash TEXT NOT NULL,
+            verdict TEXT NOT NULL,
+            cutoff INTEGER NOT NULL DEFAULT 0,
+            extent INTEGER NOT NULL DEFAULT 0,
+            samplerate INTEGER NOT NULL DEFAULT 0,
+            last_mod INTEGER NOT NULL DEFAULT 0,
+            checked_at INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE INDEX IF NOT EXISTS idx_lossless_check_trackhash ON lossless_check(trackhash);
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Podcast subscriptions, their episodes and per user playback progress
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS podcast (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            userid INTEGER NOT NULL,
+            feed_url TEXT NOT NULL,
+            title TEXT NOT NULL,
+            author TEXT NOT NULL DEFAULT '',
+            description TEXT NOT NULL DEFAULT '',
+            image TEXT NOT NULL DEFAULT '',
+            link TEXT NOT NULL DEFAULT '',
+            last_checked INTEGER NOT NULL DEFAULT 0,
+            created_at INTEGER NOT NULL DEFAULT 0,
+            UNIQUE(userid, feed_url)
+        );
+        CREATE TABLE IF NOT EXISTS podcast_episode (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            podcastid INTEGER NOT NULL,
+            guid TEXT NOT NULL,
+            title TEXT NOT NULL,
+            description TEXT NOT NULL DEFAULT '',
+            audio_url TEXT NOT NULL,
+            mime_type TEXT NOT NULL DEFAULT '',
+            duration INTEGER NOT NULL DEFAULT 0,
+            published INTEGER NOT NULL DEFAULT 0,
+            image TEXT NOT NULL DEFAULT '',
+            filepath TEXT NOT NULL DEFAULT '',
+            UNIQUE(podcastid, guid)
+        );
+        CREATE INDEX IF NOT EXISTS idx_podcast_episode_podcastid
+            ON podcast_episode(podcastid, published);
+        CREATE TABLE IF NOT EXISTS podcast_progress (
+            userid INTEGER NOT NULL,
+            episodeid INTEGER NOT NULL,
+            position INTEGER NOT NULL DEFAULT 0,
+            completed INTEGER NOT NULL DEFAULT 0,
+            updated INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (userid, episodeid)
+        );
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Source art of the cached album thumbnails, files are named by the art's content hash
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS thumbnail (
+            albumhash TEXT PRIMARY KEY,
+            contenthash TEXT NOT NULL,
+            source TEXT NOT NULL,
+            source_mtime INTEGER NOT NULL DEFAULT 0,
+            source_len INTEGER NOT NULL DEFAULT 0
+        );
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Summaries of finished library scans, newest kept
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS scan_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            kind TEXT NOT NULL,
+            started_at INTEGER NOT NULL,
+            finished_at INTEGER NOT NULL,
+            duration_ms INTEGER NOT NULL DEFAULT 0,
+            files_seen INTEGER NOT NULL DEFAULT 0,
+            files_processed INTEGER NOT NULL DEFAULT 0,
+            files_failed INTEGER NOT NULL DEFAULT 0,
+            files_skipped INTEGER NOT NULL DEFAULT 0,
+            tracks_added INTEGER NOT NULL DEFAULT 0,
+            tracks_updated INTEGER NOT NULL DEFAULT 0,
+            tracks_removed INTEGER NOT NULL DEFAULT 0,
+            tracks_total INTEGER NOT NULL DEFAULT 0,
+            error TEXT,
+            details TEXT NOT NULL DEFAULT '{}'
+        );
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Saved playback positions per user, used to resume audiobooks
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS track_position (
+            userid INTEGER NOT NULL,
+            trackhash TEXT NOT NULL,
+            position INTEGER NOT NULL DEFAULT 0,
+            updated INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (userid, trackhash)
+        );
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Star ratings users gave tracks
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS rating (
+            userid INTEGER NOT NULL,
+            trackhash TEXT NOT NULL,
+            rating INTEGER NOT NULL,
+            updated INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (userid, trackhash)
+        );
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Decaying popularity of tracks, artists and albums per user, rebuilt nightly
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS popularity (
+            userid INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            hash TEXT NOT NULL,
+            score REAL NOT NULL,
+            updated INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (userid, kind, hash)
+        );
+        CREATE INDEX IF NOT EXISTS idx_popularity_score ON popularity(userid, kind, score);
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // When each user first played a track, album or artist, kept past scrobble pruning
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS discovery (
+            userid INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            hash TEXT NOT NULL,
+            discovered_at INTEGER NOT NULL,
+            PRIMARY KEY (userid, kind, hash)
+        );
+        CREATE INDEX IF NOT EXISTS idx_discovery_time ON discovery(userid, kind, discovered_at);
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Client settings per user, values are JSON so clients can store any shape
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS preference (
+            userid INTEGER NOT NULL,
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            updated INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (userid, key)
+        );
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Deleted tracks waiting in the trash, the file path is where they are restored to
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS trash (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            trackhash TEXT NOT NULL,
+            title TEXT NOT NULL DEFAULT '',
+            artist TEXT NOT NULL DEFAULT '',
+            album TEXT NOT NULL DEFAULT '',
+            filepath TEXT NOT NULL,
+            trashpath TEXT NOT NULL,
+            userid INTEGER NOT NULL,
+            deleted_at INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_trash_deleted_at ON trash(deleted_at);
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Internet radio stations per user
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS radio (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            userid INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            url TEXT NOT NULL,
+            homepage TEXT NOT NULL DEFAULT '',
+            image TEXT NOT NULL DEFAULT '',
+            genre TEXT NOT NULL DEFAULT '',
+            created_at INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE INDEX IF NOT EXISTS idx_radio_userid ON radio(userid);
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
     // Similar artists table (per-related-artist rows)
     sqlx::query(
         r#"
@@ -316,6 +647,12 @@ async fn create_tables() -> Result<()> {
             version INTEGER NOT NULL DEFAULT 0
         );
         INSERT OR IGNORE INTO dbmigration (id, version) VALUES (1, 0);
+        CREATE TABLE IF NOT EXISTS dbmigration_log (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            checksum TEXT NOT NULL,
+            applied_at INTEGER NOT NULL
+        );
         "#,
     )
     .execute(pool)
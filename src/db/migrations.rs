@@ -1,81 +1,535 @@
 //! Database migrations
+//!
+//! each applied migration is logged with a checksum of its version, name and
+//! statements, so a binary whose migrations differ from the ones a database went through
+//! refuses to start instead of running on a schema it does not know. the app
+//! database is copied into the backups folder before pending migrations run
+//! and copied back if one of them fails.
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, Connection, SqlitePool};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use tracing::info;
 
+use super::tables::DiscoveryTable;
 use super::DbEngine;
+use crate::config::Paths;
+use crate::core::colorlib::ColorLib;
 
-/// Current migration version
-const CURRENT_VERSION: i32 = 2;
+/// A schema change, applied once in version order
+struct Migration {
+    version: i32,
+    name: &'static str,
+    /// statements run in order, what the checksum covers besides version and name
+    steps: &'static [Step],
+}
+
+/// A statement of a migration, skipped when its guard query returns false
+struct Step {
+    guard: Option<&'static str>,
+    sql: &'static str,
+}
+
+impl Step {
+    const fn sql(sql: &'static str) -> Self {
+        Self { guard: None, sql }
+    }
+
+    const fn when(guard: &'static str, sql: &'static str) -> Self {
+        Self {
+            guard: Some(guard),
+            sql,
+        }
+    }
+}
+
+impl Migration {
+    const fn new(version: i32, name: &'static str, steps: &'static [Step]) -> Self {
+        Self {
+            version,
+            name,
+            steps,
+        }
+    }
+
+    fn checksum(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(format!("{}:{}", self.version, self.name));
+        for step in self.steps {
+            hasher.update([0]);
+            hasher.update(step.guard.unwrap_or_default());
+            hasher.update([0]);
+            hasher.update(step.sql);
+        }
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// Every migration, a new one goes at the end with the next version
+const MIGRATIONS: &[Migration] = &[
+    // tables are created in setup_sqlite
+    Migration::new(1, "initial schema", &[]),
+    Migration::new(
+        2,
+        "mix timestamp",
+        &[Step::when(
+            "SELECT COUNT(*) = 0 FROM pragma_table_info('mix') WHERE name = 'timestamp'",
+            "ALTER TABLE mix ADD COLUMN timestamp INTEGER NOT NULL DEFAULT (strftime('%s','now'))",
+        )],
+    ),
+    Migration::new(
+        3,
+        "track playlist images",
+        &[Step::sql(
+            "INSERT OR IGNORE INTO playlist_image (playlistid, filename, created_at) \
+             SELECT id, image, strftime('%s','now') FROM playlist WHERE image IS NOT NULL AND image != ''",
+        )],
+    ),
+    Migration::new(
+        4,
+        "playlist image color",
+        &[Step::when(
+            "SELECT COUNT(*) = 0 FROM pragma_table_info('playlist_image') WHERE name = 'color'",
+            "ALTER TABLE playlist_image ADD COLUMN color TEXT NOT NULL DEFAULT ''",
+        )],
+    ),
+    Migration::new(
+        5,
+        "per-user favorites",
+        &[
+            // rows written before requests carried a user id belong to the default user
+            Step::sql(
+                "UPDATE favorite SET userid = 1 WHERE userid = 0 AND EXISTS (SELECT 1 FROM user WHERE id = 1); \
+                 UPDATE scrobble SET userid = 1 WHERE userid = 0 AND EXISTS (SELECT 1 FROM user WHERE id = 1); \
+                 UPDATE playlist SET userid = 1 WHERE userid = 0 AND EXISTS (SELECT 1 FROM user WHERE id = 1); \
+                 UPDATE mix SET userid = 1 WHERE userid = 0 AND EXISTS (SELECT 1 FROM user WHERE id = 1)",
+            ),
+            // favorites used to be unique per hash which blocked a second user
+            // from favoriting the same item
+            Step::when(
+                "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'favorite' \
+                 AND sql LIKE '%hash TEXT NOT NULL UNIQUE%'",
+                r#"
+                CREATE TABLE favorite_new (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    hash TEXT NOT NULL,
+                    type TEXT NOT NULL,
+                    timestamp INTEGER NOT NULL,
+                    userid INTEGER NOT NULL DEFAULT 1,
+                    extra TEXT DEFAULT '{}',
+                    UNIQUE (hash, type, userid),
+                    FOREIGN KEY (userid) REFERENCES user(id) ON DELETE CASCADE
+                );
+                INSERT OR IGNORE INTO favorite_new (id, hash, type, timestamp, userid, extra)
+                    SELECT id, hash, type, timestamp, userid, extra FROM favorite;
+                DROP TABLE favorite;
+                ALTER TABLE favorite_new RENAME TO favorite;
+                CREATE INDEX IF NOT EXISTS idx_favorite_type ON favorite(type);
+                CREATE INDEX IF NOT EXISTS idx_favorite_timestamp ON favorite(timestamp);
+                CREATE INDEX IF NOT EXISTS idx_favorite_userid ON favorite(userid);
+                "#,
+            ),
+        ],
+    ),
+    // the variants of stored colors are derived in code once the columns exist
+    Migration::new(
+        6,
+        "color variants",
+        &[
+            Step::when(
+                "SELECT COUNT(*) = 0 FROM pragma_table_info('libdata') WHERE name = 'color_dark'",
+                "ALTER TABLE libdata ADD COLUMN color_dark TEXT NOT NULL DEFAULT ''",
+            ),
+            Step::when(
+                "SELECT COUNT(*) = 0 FROM pragma_table_info('libdata') WHERE name = 'color_light'",
+                "ALTER TABLE libdata ADD COLUMN color_light TEXT NOT NULL DEFAULT ''",
+            ),
+            Step::when(
+                "SELECT COUNT(*) = 0 FROM pragma_table_info('playlist_image') WHERE name = 'color_dark'",
+                "ALTER TABLE playlist_image ADD COLUMN color_dark TEXT NOT NULL DEFAULT ''",
+            ),
+            Step::when(
+                "SELECT COUNT(*) = 0 FROM pragma_table_info('playlist_image') WHERE name = 'color_light'",
+                "ALTER TABLE playlist_image ADD COLUMN color_light TEXT NOT NULL DEFAULT ''",
+            ),
+        ],
+    ),
+    // tracks of a removed root directory are hidden instead of deleted
+    Migration::new(
+        7,
+        "hide tracks of removed roots",
+        &[Step::when(
+            "SELECT COUNT(*) = 0 FROM pragma_table_info('track') WHERE name = 'removed_at'",
+            "ALTER TABLE track ADD COLUMN removed_at INTEGER",
+        )],
+    ),
+    // lyrics are detected while reading tags, the next scan reads every file
+    // again so the flag gets filled
+    Migration::new(
+        8,
+        "track lyrics flag",
+        &[Step::when(
+            "SELECT COUNT(*) = 0 FROM pragma_table_info('track') WHERE name = 'has_lyrics'",
+            "ALTER TABLE track ADD COLUMN has_lyrics INTEGER NOT NULL DEFAULT 0; \
+             UPDATE track SET last_mod = 0",
+        )],
+    ),
+    // playlists are private to their owner unless shared
+    Migration::new(
+        9,
+        "shared playlists",
+        &[Step::when(
+            "SELECT COUNT(*) = 0 FROM pragma_table_info('playlist') WHERE name = 'shared'",
+            "ALTER TABLE playlist ADD COLUMN shared INTEGER NOT NULL DEFAULT 0",
+        )],
+    ),
+    // album colors remember the art they were taken from, existing colors are
+    // taken again once since their art is unknown
+    Migration::new(
+        10,
+        "album color art hash",
+        &[Step::when(
+            "SELECT COUNT(*) = 0 FROM pragma_table_info('libdata') WHERE name = 'arthash'",
+            "ALTER TABLE libdata ADD COLUMN arthash TEXT NOT NULL DEFAULT ''",
+        )],
+    ),
+    // plays remember the device they came from, older plays have none
+    Migration::new(
+        11,
+        "scrobble device",
+        &[
+            Step::when(
+                "SELECT COUNT(*) = 0 FROM pragma_table_info('scrobble') WHERE name = 'device'",
+                "ALTER TABLE scrobble ADD COLUMN device TEXT NOT NULL DEFAULT ''",
+            ),
+            Step::sql(
+                "CREATE INDEX IF NOT EXISTS idx_scrobble_userid_device ON scrobble(userid, device)",
+            ),
+        ],
+    ),
+    // first plays are kept apart from scrobbles, seeded in code from the
+    // plays recorded so far
+    Migration::new(12, "discovery backfill", &[]),
+];
+
+/// Pre-migration backups kept, older ones are removed
+const BACKUPS_KEPT: usize = 3;
+
+/// Version the last migration brings a database to
+fn latest_version() -> i32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
 
 /// Run database migrations
 pub async fn run_migrations() -> Result<()> {
     let engine = DbEngine::get()?;
     let pool = engine.pool();
 
-    // Get current version
-    let row: (i32,) = sqlx::query_as("SELECT version FROM dbmigration WHERE id = 1")
-        .fetch_one(pool)
-        .await?;
-    let current_version = row.0;
+    let current_version = get_migration_version().await?;
+    let applied = applied_checksums(pool).await?;
+    check_applied(current_version, &applied)?;
 
-    if current_version >= CURRENT_VERSION {
+    // databases migrated before checksums were logged trust what they went through
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|m| m.version <= current_version && !applied.contains_key(&m.version))
+    {
+        log_migration(pool, migration).await?;
+    }
+
+    let pending = pending_migrations(current_version);
+    if pending.is_empty() {
         info!("Database is up to date (version {})", current_version);
         return Ok(());
     }
 
     info!(
         "Running migrations from version {} to {}",
-        current_version, CURRENT_VERSION
+        current_version,
+        latest_version()
     );
 
-    // Run migrations in order
-    for version in (current_version + 1)..=CURRENT_VERSION {
-        run_migration(version).await?;
+    let backup = backup_database(pool, current_version).await?;
+    info!("Backed up the database to {:?}", backup);
 
-        // Update version
-        sqlx::query("UPDATE dbmigration SET version = ? WHERE id = 1")
-            .bind(version)
-            .execute(pool)
-            .await?;
+    // Run migrations in order
+    for migration in pending {
+        if let Err(e) = apply_migration(pool, migration).await {
+            tracing::error!("Migration {} failed: {}", migration.version, e);
+            pool.close().await;
+            restore_database(&backup)?;
+            anyhow::bail!(
+                "Migration {} ({}) failed, the database was restored from {:?}: {}",
+                migration.version,
+                migration.name,
+                backup,
+                e
+            );
+        }
 
-        info!("Applied migration {}", version);
+        info!("Applied migration {}", migration.version);
     }
 
+    prune_backups(&backup_dir()?);
     Ok(())
 }
 
-async fn run_migration(version: i32) -> Result<()> {
-    let engine = DbEngine::get()?;
-    let pool = engine.pool();
+/// What running the migrations would do, without touching the database
+#[derive(Debug, Clone)]
+pub struct MigrationReport {
+    pub database: PathBuf,
+    pub current_version: i32,
+    pub latest_version: i32,
+    /// (version, name) of the migrations that would run
+    pub pending: Vec<(i32, &'static str)>,
+    /// why the migrations would refuse to run
+    pub problem: Option<String>,
+}
 
-    match version {
-        1 => {
-            // Initial migration - tables already created in setup_sqlite
-            // This is a placeholder for future migrations
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Database: {}", self.database.display())?;
+        writeln!(
+            f,
+            "Schema version {} of {}",
+            self.current_version, self.latest_version
+        )?;
+        if let Some(problem) = &self.problem {
+            return writeln!(f, "Migrations would not run: {}", problem);
+        }
+        if self.pending.is_empty() {
+            return writeln!(f, "No pending migrations");
         }
-        2 => {
-            // add timestamp column to mix table if missing
-            let has_column: i64 = sqlx::query_scalar(
-                "SELECT COUNT(*) FROM pragma_table_info('mix') WHERE name = 'timestamp'",
-            )
-            .fetch_one(pool)
+        writeln!(f, "Pending migrations:")?;
+        for (version, name) in &self.pending {
+            writeln!(f, "  {:>3}  {}", version, name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Report the pending migrations of the app database, opened read only
+pub async fn migration_dry_run() -> Result<MigrationReport> {
+    let database = Paths::get()?.app_db_path();
+    let mut report = MigrationReport {
+        database: database.clone(),
+        current_version: 0,
+        latest_version: latest_version(),
+        pending: Vec::new(),
+        problem: None,
+    };
+
+    if database.exists() {
+        let mut conn = SqliteConnectOptions::from_str(&format!("sqlite:{}", database.display()))?
+            .read_only(true)
+            .connect()
+            .await
+            .context("Failed to open the database")?;
+
+        // tables missing from an old database are created on the next start
+        report.current_version = sqlx::query_scalar("SELECT version FROM dbmigration WHERE id = 1")
+            .fetch_optional(&mut conn)
             .await
-            .unwrap_or(1);
-
-            if has_column == 0 {
-                sqlx::query(
-                    "ALTER TABLE mix ADD COLUMN timestamp INTEGER NOT NULL DEFAULT (strftime('%s','now'))",
-                )
-                .execute(pool)
-                .await?;
+            .ok()
+            .flatten()
+            .unwrap_or(0);
+        let rows: Vec<(i32, String)> =
+            sqlx::query_as("SELECT version, checksum FROM dbmigration_log")
+                .fetch_all(&mut conn)
+                .await
+                .unwrap_or_default();
+        conn.close().await?;
+
+        let applied: BTreeMap<i32, String> = rows.into_iter().collect();
+        report.problem = check_applied(report.current_version, &applied)
+            .err()
+            .map(|e| e.to_string());
+    }
+
+    report.pending = pending_migrations(report.current_version)
+        .into_iter()
+        .map(|m| (m.version, m.name))
+        .collect();
+    Ok(report)
+}
+
+fn pending_migrations(current_version: i32) -> Vec<&'static Migration> {
+    MIGRATIONS
+        .iter()
+        .filter(|m| m.version > current_version)
+        .collect()
+}
+
+/// Refuse databases from a newer release and migrations that changed after
+/// they were applied
+fn check_applied(current_version: i32, applied: &BTreeMap<i32, String>) -> Result<()> {
+    if current_version > latest_version() {
+        anyhow::bail!(
+            "the database is at version {} but this release only knows version {}, \
+             restore a backup or run a newer release",
+            current_version,
+            latest_version()
+        );
+    }
+
+    for migration in MIGRATIONS {
+        if let Some(checksum) = applied.get(&migration.version) {
+            if *checksum != migration.checksum() {
+                anyhow::bail!(
+                    "migration {} ({}) differs from the one applied to the database",
+                    migration.version,
+                    migration.name
+                );
             }
         }
-        _ => {
-            tracing::warn!("Unknown migration version: {}", version);
+    }
+    Ok(())
+}
+
+async fn applied_checksums(pool: &SqlitePool) -> Result<BTreeMap<i32, String>> {
+    let rows: Vec<(i32, String)> = sqlx::query_as("SELECT version, checksum FROM dbmigration_log")
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().collect())
+}
+
+async fn log_migration(pool: &SqlitePool, migration: &Migration) -> Result<()> {
+    sqlx::query(
+        "INSERT OR REPLACE INTO dbmigration_log (version, name, checksum, applied_at) \
+         VALUES (?, ?, ?, ?)",
+    )
+    .bind(migration.version)
+    .bind(migration.name)
+    .bind(migration.checksum())
+    .bind(chrono::Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+async fn apply_migration(pool: &SqlitePool, migration: &Migration) -> Result<()> {
+    run_migration(migration).await?;
+
+    // Update version
+    sqlx::query("UPDATE dbmigration SET version = ? WHERE id = 1")
+        .bind(migration.version)
+        .execute(pool)
+        .await?;
+    log_migration(pool, migration).await
+}
+
+fn backup_dir() -> Result<PathBuf> {
+    Ok(Paths::get()?.backups_dir().join("migrations"))
+}
+
+/// Copy the app database into the backups folder
+///
+/// `VACUUM INTO` writes a consistent copy including what is still in the wal
+async fn backup_database(pool: &SqlitePool, version: i32) -> Result<PathBuf> {
+    let dir = backup_dir()?;
+    std::fs::create_dir_all(&dir)?;
+    let backup = dir.join(format!(
+        "swingmusic.v{}.{}.db",
+        version,
+        chrono::Utc::now().timestamp()
+    ));
+
+    sqlx::query("VACUUM INTO ?")
+        .bind(backup.to_string_lossy().to_string())
+        .execute(pool)
+        .await
+        .context("Failed to back up the database before migrating")?;
+    Ok(backup)
+}
+
+/// Put a backup back in place of the app database, the pool must be closed
+fn restore_database(backup: &Path) -> Result<()> {
+    let database = Paths::get()?.app_db_path();
+    std::fs::copy(backup, &database).context("Failed to restore the database backup")?;
+
+    // the wal of the failed run must not be replayed over the backup
+    for suffix in ["-wal", "-shm"] {
+        let mut sidecar = database.as_os_str().to_owned();
+        sidecar.push(suffix);
+        let _ = std::fs::remove_file(PathBuf::from(sidecar));
+    }
+    Ok(())
+}
+
+/// Keep the newest pre-migration backups
+fn prune_backups(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut backups: Vec<(std::time::SystemTime, PathBuf)> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "db"))
+        .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+        .collect();
+
+    backups.sort_by_key(|b| std::cmp::Reverse(b.0));
+    for (_, path) in backups.into_iter().skip(BACKUPS_KEPT) {
+        if let Err(e) = std::fs::remove_file(&path) {
+            tracing::warn!("Failed to remove old migration backup {:?}: {}", path, e);
         }
     }
+}
+
+async fn run_migration(migration: &Migration) -> Result<()> {
+    let engine = DbEngine::get()?;
+    let pool = engine.pool();
 
+    let mut tx = pool.begin().await?;
+    for step in migration.steps {
+        if let Some(guard) = step.guard {
+            let run: bool = sqlx::query_scalar(guard).fetch_one(&mut *tx).await?;
+            if !run {
+                continue;
+            }
+        }
+        sqlx::query(step.sql).execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+
+    // data fixups that need more than sql
+    match migration.version {
+        6 => derive_color_variants(pool).await?,
+        12 => DiscoveryTable::backfill().await?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Fill the dark and light mode variants of stored colors
+async fn derive_color_variants(pool: &SqlitePool) -> Result<()> {
+    for table in ["libdata", "playlist_image"] {
+        let colors: Vec<(String,)> = sqlx::query_as(&format!(
+            "SELECT DISTINCT color FROM {} WHERE color != '' AND (color_dark = '' OR color_light = '')",
+            table
+        ))
+        .fetch_all(pool)
+        .await?;
+
+        let mut tx = pool.begin().await?;
+        for (color,) in colors {
+            let variants = ColorLib::variants(&color);
+            sqlx::query(&format!(
+                "UPDATE {} SET color_dark = ?, color_light = ? WHERE color = ?",
+                table
+            ))
+            .bind(&variants.dark)
+            .bind(&variants.light)
+            .bind(&color)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+    }
     Ok(())
 }
 
@@ -90,3 +544,51 @@ pub async fn get_migration_version() -> Result<i32> {
 
     Ok(row.0)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions_follow_each_other() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, i as i32 + 1);
+        }
+    }
+
+    #[test]
+    fn test_check_applied() {
+        let applied: BTreeMap<i32, String> = MIGRATIONS[..2]
+            .iter()
+            .map(|m| (m.version, m.checksum()))
+            .collect();
+        assert!(check_applied(2, &applied).is_ok());
+        assert_eq!(pending_migrations(2).len(), MIGRATIONS.len() - 2);
+
+        // a database from a newer release
+        assert!(check_applied(latest_version() + 1, &applied).is_err());
+
+        // a migration that changed after it ran
+        let mut changed = applied.clone();
+        changed.insert(2, "0".repeat(64));
+        assert!(check_applied(2, &changed).is_err());
+    }
+
+    #[test]
+    fn test_checksum_covers_statements() {
+        const EDITED: &[Step] = &[Step::when(
+            "SELECT COUNT(*) = 0 FROM pragma_table_info('mix') WHERE name = 'timestamp'",
+            "ALTER TABLE mix ADD COLUMN timestamp INTEGER NOT NULL DEFAULT 0",
+        )];
+        let original = &MIGRATIONS[1];
+        let edited = Migration::new(original.version, original.name, EDITED);
+        assert_ne!(edited.checksum(), original.checksum());
+
+        // a database that went through the edited body is refused
+        let applied = BTreeMap::from([
+            (MIGRATIONS[0].version, MIGRATIONS[0].checksum()),
+            (edited.version, edited.checksum()),
+        ]);
+        assert!(check_applied(2, &applied).is_err());
+    }
+}
@@ -8,6 +8,6 @@ pub mod tables;
 mod userdata;
 
 pub use engine::{setup_sqlite, DbEngine};
-pub use migrations::run_migrations;
+pub use migrations::{migration_dry_run, run_migrations, MigrationReport};
 pub use tables::*;
 pub use userdata::{setup_userdata, UserdataEngine};
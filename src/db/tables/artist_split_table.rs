@@ -0,0 +1,111 @@
+//! Artist split rule table operations
+
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::FromRow;
+
+use crate::db::DbEngine;
+
+/// A rule moving part of an artist's tracks to a new artist hash
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ArtistSplit {
+    pub id: i64,
+    /// hash shared by the identically named artists
+    pub artisthash: String,
+    /// hash given to the tracks the rule matches
+    pub newhash: String,
+    /// name shown for the split artist
+    pub name: String,
+    /// musicbrainz artist id the tracks must be tagged with
+    pub mbid: Option<String>,
+    /// folder the tracks must live under
+    pub folder: Option<String>,
+    /// albums whose tracks are moved
+    pub albumhashes: Vec<String>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, FromRow)]
+struct ArtistSplitRow {
+    id: i64,
+    artisthash: String,
+    newhash: String,
+    name: String,
+    mbid: Option<String>,
+    folder: Option<String>,
+    albumhashes: String,
+    created_at: i64,
+}
+
+impl ArtistSplitRow {
+    fn into_split(self) -> ArtistSplit {
+        ArtistSplit {
+            id: self.id,
+            artisthash: self.artisthash,
+            newhash: self.newhash,
+            name: self.name,
+            mbid: self.mbid,
+            folder: self.folder,
+            albumhashes: serde_json::from_str(&self.albumhashes).unwrap_or_default(),
+            created_at: self.created_at,
+        }
+    }
+}
+
+/// Artist split rule table operations
+pub struct ArtistSplitTable;
+
+impl ArtistSplitTable {
+    /// Get all rules, oldest first
+    pub async fn all() -> Result<Vec<ArtistSplit>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows: Vec<ArtistSplitRow> = sqlx::query_as("SELECT * FROM artistsplit ORDER BY id")
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows.into_iter().map(|r| r.into_split()).collect())
+    }
+
+    /// Insert a rule and return it with its id
+    pub async fn insert(split: &ArtistSplit) -> Result<ArtistSplit> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO artistsplit (
+                artisthash, newhash, name, mbid, folder, albumhashes, created_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&split.artisthash)
+        .bind(&split.newhash)
+        .bind(&split.name)
+        .bind(&split.mbid)
+        .bind(&split.folder)
+        .bind(serde_json::to_string(&split.albumhashes)?)
+        .bind(split.created_at)
+        .execute(pool)
+        .await?;
+
+        Ok(ArtistSplit {
+            id: result.last_insert_rowid(),
+            ..split.clone()
+        })
+    }
+
+    /// Delete a rule by id
+    pub async fn delete(id: i64) -> Result<bool> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let result = sqlx::query("DELETE FROM artistsplit WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
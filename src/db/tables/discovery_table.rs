@@ -0,0 +1,117 @@
+//! When each user first played a track, album or artist
+//!
+//! plays older than a year are pruned from the scrobble table, so the first
+//! play of an item is kept here for good. rows only ever move to an earlier
+//! time, restoring older plays from a backup moves them back.
+
+use anyhow::Result;
+
+use crate::db::DbEngine;
+
+/// Discovery table operations
+pub struct DiscoveryTable;
+
+impl DiscoveryTable {
+    /// When a user first played an item of a kind, `None` when they never did
+    pub async fn get(userid: i64, kind: &str, hash: &str) -> Result<Option<i64>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let discovered: Option<i64> = sqlx::query_scalar(
+            "SELECT discovered_at FROM discovery WHERE userid = ? AND kind = ? AND hash = ?",
+        )
+        .bind(userid)
+        .bind(kind)
+        .bind(hash)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(discovered)
+    }
+
+    /// (kind, hash) of the items a user first played within a time range
+    pub async fn discovered_between(
+        userid: i64,
+        start_time: i64,
+        end_time: i64,
+    ) -> Result<Vec<(String, String)>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows: Vec<(String, String)> = sqlx::query_as(
+            "SELECT kind, hash FROM discovery \
+             WHERE userid = ? AND discovered_at >= ? AND discovered_at <= ?",
+        )
+        .bind(userid)
+        .bind(start_time)
+        .bind(end_time)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Record a play of a track, its album and artists
+    pub async fn record(
+        userid: i64,
+        trackhash: &str,
+        albumhash: &str,
+        artisthashes: &[String],
+        timestamp: i64,
+    ) -> Result<()> {
+        let engine = DbEngine::get()?;
+        let mut tx = engine.pool().begin().await?;
+
+        let items = [("track", trackhash), ("album", albumhash)]
+            .into_iter()
+            .chain(artisthashes.iter().map(|h| ("artist", h.as_str())));
+        for (kind, hash) in items {
+            sqlx::query(
+                r#"
+                INSERT INTO discovery (userid, kind, hash, discovered_at)
+                VALUES (?, ?, ?, ?)
+                ON CONFLICT(userid, kind, hash) DO UPDATE SET
+                    discovered_at = MIN(discovered_at, excluded.discovered_at)
+                "#,
+            )
+            .bind(userid)
+            .bind(kind)
+            .bind(hash)
+            .bind(timestamp)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Take first plays from the scrobbles still kept, the library resolves
+    /// the albums and artists of the played tracks
+    pub async fn backfill() -> Result<()> {
+        let engine = DbEngine::get()?;
+        let mut tx = engine.pool().begin().await?;
+
+        for select in [
+            "SELECT userid, 'track', trackhash, MIN(timestamp) FROM scrobble \
+             GROUP BY userid, trackhash",
+            "SELECT s.userid, 'album', t.albumhash, MIN(s.timestamp) FROM scrobble s \
+             JOIN track t ON t.trackhash = s.trackhash GROUP BY s.userid, t.albumhash",
+            "SELECT s.userid, 'artist', json_extract(a.value, '$.artisthash'), MIN(s.timestamp) \
+             FROM scrobble s JOIN track t ON t.trackhash = s.trackhash, json_each(t.artists) a \
+             GROUP BY s.userid, json_extract(a.value, '$.artisthash')",
+        ] {
+            sqlx::query(&format!(
+                "INSERT INTO discovery (userid, kind, hash, discovered_at) {} \
+                 ON CONFLICT(userid, kind, hash) DO UPDATE SET \
+                 discovered_at = MIN(discovered_at, excluded.discovered_at)",
+                select
+            ))
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
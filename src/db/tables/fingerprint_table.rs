@@ -0,0 +1,130 @@
+//! Audio fingerprint table operations
+
+use anyhow::Result;
+use sqlx::FromRow;
+use std::collections::HashMap;
+
+use crate::db::DbEngine;
+
+/// Chromaprint fingerprint of a library file
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct StoredFingerprint {
+    pub filepath: String,
+    pub trackhash: String,
+    /// duration in seconds as measured by fpcalc
+    pub duration: f64,
+    /// compressed fingerprint, empty when fpcalc could not read the file
+    pub fingerprint: String,
+    /// modification time of the file when it was fingerprinted
+    pub last_mod: i64,
+}
+
+/// Audio fingerprint table operations
+pub struct FingerprintTable;
+
+impl FingerprintTable {
+    /// Get all usable fingerprints
+    pub async fn all() -> Result<Vec<StoredFingerprint>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows = sqlx::query_as("SELECT * FROM fingerprint WHERE fingerprint != ''")
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows)
+    }
+
+    /// Get the usable fingerprint of a file
+    pub async fn get(filepath: &str) -> Result<Option<StoredFingerprint>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let row =
+            sqlx::query_as("SELECT * FROM fingerprint WHERE filepath = ? AND fingerprint != ''")
+                .bind(filepath)
+                .fetch_optional(pool)
+                .await?;
+
+        Ok(row)
+    }
+
+    /// Modification time of every fingerprinted file, including failed ones
+    pub async fn last_mods() -> Result<HashMap<String, i64>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows: Vec<(String, i64)> = sqlx::query_as("SELECT filepath, last_mod FROM fingerprint")
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows.into_iter().collect())
+    }
+
+    /// Count usable fingerprints
+    pub async fn count() -> Result<i64> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let row: (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM fingerprint WHERE fingerprint != ''")
+                .fetch_one(pool)
+                .await?;
+
+        Ok(row.0)
+    }
+
+    /// Insert or replace fingerprints in a single transaction
+    pub async fn upsert_many(fingerprints: &[StoredFingerprint]) -> Result<()> {
+        if fingerprints.is_empty() {
+            return Ok(());
+        }
+
+        let engine = DbEngine::get()?;
+        let mut tx = engine.pool().begin().await?;
+
+        for fp in fingerprints {
+            sqlx::query(
+                r#"
+                INSERT INTO fingerprint (filepath, trackhash, duration, fingerprint, last_mod)
+                VALUES (?, ?, ?, ?, ?)
+                ON CONFLICT(filepath) DO UPDATE SET
+                    trackhash = excluded.trackhash,
+                    duration = excluded.duration,
+                    fingerprint = excluded.fingerprint,
+                    last_mod = excluded.last_mod
+                "#,
+            )
+            .bind(&fp.filepath)
+            .bind(&fp.trackhash)
+            .bind(fp.duration)
+            .bind(&fp.fingerprint)
+            .bind(fp.last_mod)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Delete the fingerprints of files no longer in the library
+    pub async fn delete_many(filepaths: &[String]) -> Result<()> {
+        if filepaths.is_empty() {
+            return Ok(());
+        }
+
+        let engine = DbEngine::get()?;
+        let mut tx = engine.pool().begin().await?;
+
+        for filepath in filepaths {
+            sqlx::query("DELETE FROM fingerprint WHERE filepath = ?")
+                .bind(filepath)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+//! Gapless analysis table operations
+
+use anyhow::Result;
+use sqlx::FromRow;
+use std::collections::HashMap;
+
+use crate::db::DbEngine;
+
+/// What the gapless analysis found out about a library file
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct GaplessFile {
+    pub filepath: String,
+    pub trackhash: String,
+    /// whether the file plays back without encoder padding around the audio
+    pub encoder_gapless: bool,
+    /// seconds of silence at the start, negative when it was not measured
+    pub lead_silence: f64,
+    /// seconds of silence at the end, negative when it was not measured
+    pub trail_silence: f64,
+    /// modification time of the file when it was analysed
+    pub last_mod: i64,
+}
+
+/// Gapless analysis table operations
+pub struct GaplessTable;
+
+impl GaplessTable {
+    /// Get every analysed file
+    pub async fn all() -> Result<Vec<GaplessFile>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows = sqlx::query_as("SELECT * FROM gapless")
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows)
+    }
+
+    /// Modification time of every analysed file
+    pub async fn last_mods() -> Result<HashMap<String, i64>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows: Vec<(String, i64)> = sqlx::query_as("SELECT filepath, last_mod FROM gapless")
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows.into_iter().collect())
+    }
+
+    /// Insert or replace analysis results in a single transaction
+    pub async fn upsert_many(files: &[GaplessFile]) -> Result<()> {
+        if files.is_empty() {
+            return Ok(());
+        }
+
+        let engine = DbEngine::get()?;
+        let mut tx = engine.pool().begin().await?;
+
+        for file in files {
+            sqlx::query(
+                r#"
+                INSERT INTO gapless
+                    (filepath, trackhash, encoder_gapless, lead_silence, trail_silence, last_mod)
+                VALUES (?, ?, ?, ?, ?, ?)
+                ON CONFLICT(filepath) DO UPDATE SET
+                    trackhash = excluded.trackhash,
+                    encoder_gapless = excluded.encoder_gapless,
+                    lead_silence = excluded.lead_silence,
+                    trail_silence = excluded.trail_silence,
+                    last_mod = excluded.last_mod
+                "#,
+            )
+            .bind(&file.filepath)
+            .bind(&file.trackhash)
+            .bind(file.encoder_gapless)
+            .bind(file.lead_silence)
+            .bind(file.trail_silence)
+            .bind(file.last_mod)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Delete the results of files no longer in the library
+    pub async fn delete_many(filepaths: &[String]) -> Result<()> {
+        if filepaths.is_empty() {
+            return Ok(());
+        }
+
+        let engine = DbEngine::get()?;
+        let mut tx = engine.pool().begin().await?;
+
+        for filepath in filepaths {
+            sqlx::query("DELETE FROM gapless WHERE filepath = ?")
+                .bind(filepath)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
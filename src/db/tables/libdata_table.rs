@@ -4,6 +4,7 @@ use anyhow::Result;
 use sqlx::FromRow;
 
 use crate::db::DbEngine;
+use crate::models::ColorVariants;
 
 /// Database row for libdata table
 #[derive(Debug, FromRow)]
@@ -13,6 +14,8 @@ pub struct LibDataRow {
     #[sqlx(rename = "type")]
     pub data_type: String,
     pub color: String,
+    pub color_dark: String,
+    pub color_light: String,
 }
 
 /// LibData table operations
@@ -20,20 +23,30 @@ pub struct LibDataTable;
 
 impl LibDataTable {
     /// Update or insert lib data entry
-    pub async fn upsert(hash: &str, data_type: &str, color: &str) -> Result<()> {
+    pub async fn upsert(
+        hash: &str,
+        data_type: &str,
+        color: &str,
+        variants: &ColorVariants,
+    ) -> Result<()> {
         let engine = DbEngine::get()?;
         let pool = engine.pool();
 
         sqlx::query(
             r#"
-            INSERT INTO libdata (hash, type, color)
-            VALUES (?, ?, ?)
-            ON CONFLICT(hash) DO UPDATE SET color = excluded.color
+            INSERT INTO libdata (hash, type, color, color_dark, color_light)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT(hash) DO UPDATE SET
+                color = excluded.color,
+                color_dark = excluded.color_dark,
+                color_light = excluded.color_light
             "#,
         )
         .bind(hash)
         .bind(data_type)
         .bind(color)
+        .bind(&variants.dark)
+        .bind(&variants.light)
         .execute(pool)
         .await?;
 
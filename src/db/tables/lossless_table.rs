@@ -0,0 +1,132 @@
+//! Lossless validation table operations
+
+use anyhow::Result;
+use sqlx::FromRow;
+use std::collections::HashMap;
+
+use crate::db::DbEngine;
+
+/// The verdict on a lossless file
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct LosslessCheck {
+    pub filepath: String,
+    pub trackhash: String,
+    /// `lossless`, `transcode` or `uncertain`
+    pub verdict: String,
+    /// frequency in Hz the spectrum is cut off at, 0 when there is no cutoff
+    pub cutoff: i64,
+    /// highest frequency in Hz with audible content
+    pub extent: i64,
+    /// sample rate the file was analysed at
+    pub samplerate: i64,
+    /// modification time of the file when it was analysed
+    pub last_mod: i64,
+    /// when the file was analysed
+    pub checked_at: i64,
+}
+
+/// Lossless validation table operations
+pub struct LosslessTable;
+
+impl LosslessTable {
+    /// Get every verdict
+    pub async fn all() -> Result<Vec<LosslessCheck>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows = sqlx::query_as("SELECT * FROM lossless_check")
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows)
+    }
+
+    /// Latest verdict on a track's file
+    pub async fn get_by_trackhash(trackhash: &str) -> Result<Option<LosslessCheck>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let row = sqlx::query_as(
+            "SELECT * FROM lossless_check WHERE trackhash = ? ORDER BY checked_at DESC LIMIT 1",
+        )
+        .bind(trackhash)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Modification time of every analysed file
+    pub async fn last_mods() -> Result<HashMap<String, i64>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows: Vec<(String, i64)> =
+            sqlx::query_as("SELECT filepath, last_mod FROM lossless_check")
+                .fetch_all(pool)
+                .await?;
+
+        Ok(rows.into_iter().collect())
+    }
+
+    /// Insert or replace verdicts in a single transaction
+    pub async fn upsert_many(checks: &[LosslessCheck]) -> Result<()> {
+        if checks.is_empty() {
+            return Ok(());
+        }
+
+        let engine = DbEngine::get()?;
+        let mut tx = engine.pool().begin().await?;
+
+        for check in checks {
+            sqlx::query(
+                r#"
+                INSERT INTO lossless_check
+                    (filepath, trackhash, verdict, cutoff, extent, samplerate, last_mod, checked_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(filepath) DO UPDATE SET
+                    trackhash = excluded.trackhash,
+                    verdict = excluded.verdict,
+                    cutoff = excluded.cutoff,
+                    extent = excluded.extent,
+                    samplerate = excluded.samplerate,
+                    last_mod = excluded.last_mod,
+                    checked_at = excluded.checked_at
+                "#,
+            )
+            .bind(&check.filepath)
+            .bind(&check.trackhash)
+            .bind(&check.verdict)
+            .bind(check.cutoff)
+            .bind(check.extent)
+            .bind(check.samplerate)
+            .bind(check.last_mod)
+            .bind(check.checked_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Delete the verdicts of files no longer in the library
+    pub async fn delete_many(filepaths: &[String]) -> Result<()> {
+        if filepaths.is_empty() {
+            return Ok(());
+        }
+
+        let engine = DbEngine::get()?;
+        let mut tx = engine.pool().begin().await?;
+
+        for filepath in filepaths {
+            sqlx::query("DELETE FROM lossless_check WHERE filepath = ?")
+                .bind(filepath)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,46 @@
+//! MusicBrainz ID table operations
+//!
+//! ids are keyed by item hash and type ("track", "album" or "artist") so they
+//! survive rescans that rebuild the library stores.
+
+use anyhow::Result;
+
+use crate::db::DbEngine;
+
+/// MusicBrainz ID table operations
+pub struct MbidTable;
+
+impl MbidTable {
+    /// Insert or update the id of an item
+    pub async fn upsert(hash: &str, item_type: &str, mbid: &str) -> Result<()> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        sqlx::query(
+            r#"
+            INSERT INTO mbid (hash, type, mbid)
+            VALUES (?, ?, ?)
+            ON CONFLICT(hash, type) DO UPDATE SET mbid = excluded.mbid
+            "#,
+        )
+        .bind(hash)
+        .bind(item_type)
+        .bind(mbid)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get all ids as (hash, type, mbid)
+    pub async fn all() -> Result<Vec<(String, String, String)>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows = sqlx::query_as("SELECT hash, type, mbid FROM mbid")
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows)
+    }
+}
@@ -93,6 +93,72 @@ impl MixTable {
         Ok(row.map(|r| r.into_mix()))
     }
 
+    /// Get the stored artist radio of a user
+    pub async fn get_artist_mix(artisthash: &str, userid: i64) -> Result<Option<Mix>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let row: Option<MixRow> = sqlx::query_as(
+            "SELECT * FROM mix WHERE sourcehash = ? AND userid = ? AND substr(mixid, 1, 1) = 'a'",
+        )
+        .bind(artisthash)
+        .bind(userid)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.map(|r| r.into_mix()))
+    }
+
+    /// Get the mixes of a user whose mix ID starts with a prefix, oldest first
+    pub async fn get_by_prefix(userid: i64, prefix: &str) -> Result<Vec<Mix>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows: Vec<MixRow> = sqlx::query_as(
+            "SELECT * FROM mix WHERE userid = ? AND substr(mixid, 1, length(?)) = ? ORDER BY id",
+        )
+        .bind(userid)
+        .bind(prefix)
+        .bind(prefix)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|r| r.into_mix()).collect())
+    }
+
+    /// Delete unsaved mixes whose mix ID starts with a prefix and that were
+    /// created before a timestamp, returns the number removed
+    pub async fn delete_unsaved_before(prefix: &str, before: i64) -> Result<u64> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let result = sqlx::query(
+            "DELETE FROM mix WHERE saved = 0 AND timestamp < ? \
+             AND substr(mixid, 1, length(?)) = ?",
+        )
+        .bind(before)
+        .bind(prefix)
+        .bind(prefix)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Delete a mix of a user, returns whether it existed
+    pub async fn delete(mixid: &str, userid: i64) -> Result<bool> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let result = sqlx::query("DELETE FROM mix WHERE mixid = ? AND userid = ?")
+            .bind(mixid)
+            .bind(userid)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
     /// Insert mix (upsert)
     pub async fn insert(mix: &Mix) -> Result<i64> {
         let engine = DbEngine::get()?;
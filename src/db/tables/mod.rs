@@ -1,24 +1,59 @@
 //! Database table operations
 
+mod artist_split_table;
 mod collection_table;
+mod discovery_table;
 mod favorite_table;
+mod fingerprint_table;
+mod gapless_table;
 mod libdata_table;
+mod lossless_table;
+mod mbid_table;
 mod mix_table;
 mod page_table;
+mod playlist_image_table;
 mod playlist_table;
 mod plugin_table;
+mod podcast_table;
+mod preference_table;
+mod popularity_table;
+mod radio_table;
+mod rating_table;
+mod scan_history_table;
 mod scrobble_table;
 mod similar_artist_table;
+mod thumbnail_table;
+mod track_position_table;
 mod track_table;
+mod trash_table;
 mod user_table;
 
-pub use collection_table::CollectionTable;
+pub use artist_split_table::{ArtistSplit, ArtistSplitTable};
+pub use collection_table::{CollectionRow, CollectionTable};
+pub use discovery_table::DiscoveryTable;
 pub use favorite_table::FavoriteTable;
+pub use fingerprint_table::{FingerprintTable, StoredFingerprint};
+pub use gapless_table::{GaplessFile, GaplessTable};
+pub use lossless_table::{LosslessCheck, LosslessTable};
+pub use mbid_table::MbidTable;
+pub use page_table::{PageRow, PageTable};
+pub use playlist_image_table::PlaylistImageTable;
 pub use playlist_table::PlaylistTable;
 pub use plugin_table::PluginTable;
-pub use scrobble_table::ScrobbleTable;
+pub use podcast_table::PodcastTable;
+pub use preference_table::PreferenceTable;
+pub use popularity_table::{PopularityScore, PopularityTable};
+pub use radio_table::RadioTable;
+pub use rating_table::{RatingTable, TrackRating};
+pub use scan_history_table::{ScanHistoryTable, ScanRecord, MAX_SCAN_HISTORY};
+pub use scrobble_table::{
+    DeviceFilter, DevicePlays, ScrobblePoint, ScrobbleTable, TrackPlayTotals,
+};
+pub use thumbnail_table::{ThumbnailSource, ThumbnailTable};
+pub use track_position_table::TrackPositionTable;
 pub use track_table::TrackTable;
+pub use trash_table::{TrashTable, TrashedTrack};
 pub use user_table::UserTable;
 
 pub use mix_table::MixTable;
-pub use similar_artist_table::SimilarArtistTable;
+pub use similar_artist_table::{SimilarArtistData, SimilarArtistTable};
@@ -109,6 +109,60 @@ impl PageTable {
         Ok(rows)
     }
 
+    /// Get pages of a type whose page_id starts with a prefix, in order
+    pub async fn get_by_type(page_type: &str, page_id_prefix: &str) -> Result<Vec<PageRow>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows = sqlx::query_as::<_, PageRow>(
+            "SELECT id, page_type, page_name, page_id, order_index, settings, active FROM pages \
+             WHERE page_type = ? AND substr(page_id, 1, length(?)) = ? ORDER BY order_index ASC",
+        )
+        .bind(page_type)
+        .bind(page_id_prefix)
+        .bind(page_id_prefix)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Insert a page or replace the one with the same page_id
+    pub async fn upsert(
+        page_type: &str,
+        page_name: &str,
+        page_id: &str,
+        order_index: i32,
+        settings: &str,
+        active: bool,
+    ) -> Result<()> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        sqlx::query(
+            r#"
+            INSERT INTO pages (page_type, page_name, page_id, order_index, settings, active)
+            VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT(page_id) DO UPDATE SET
+                page_type = excluded.page_type,
+                page_name = excluded.page_name,
+                order_index = excluded.order_index,
+                settings = excluded.settings,
+                active = excluded.active
+            "#,
+        )
+        .bind(page_type)
+        .bind(page_name)
+        .bind(page_id)
+        .bind(order_index)
+        .bind(settings)
+        .bind(active)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
     /// Update page order
     pub async fn update_order(id: i64, order_index: i32) -> Result<()> {
         let engine = DbEngine::get()?;
@@ -0,0 +1,133 @@
+//! Playlist image table operations
+//!
+//! every image file written into the playlist images directory is recorded here
+//! so files left behind by failed updates or deleted playlists can be reclaimed.
+
+use anyhow::Result;
+use sqlx::FromRow;
+
+use crate::db::DbEngine;
+use crate::models::ColorVariants;
+
+/// A tracked playlist image file
+#[derive(Debug, Clone, FromRow)]
+pub struct PlaylistImageRow {
+    pub playlistid: i64,
+    pub filename: String,
+    pub created_at: i64,
+    pub color: String,
+    pub color_dark: String,
+    pub color_light: String,
+}
+
+/// Playlist image table operations
+pub struct PlaylistImageTable;
+
+impl PlaylistImageTable {
+    /// Record an image file for a playlist
+    pub async fn insert(
+        playlistid: i64,
+        filename: &str,
+        color: &str,
+        variants: &ColorVariants,
+    ) -> Result<()> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        sqlx::query(
+            "INSERT OR IGNORE INTO playlist_image (playlistid, filename, created_at, color, color_dark, color_light) VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(playlistid)
+        .bind(filename)
+        .bind(chrono::Utc::now().timestamp())
+        .bind(color)
+        .bind(&variants.dark)
+        .bind(&variants.light)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get all tracked image files
+    pub async fn all() -> Result<Vec<PlaylistImageRow>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows =
+            sqlx::query_as("SELECT playlistid, filename, created_at, color, color_dark, color_light FROM playlist_image")
+                .fetch_all(pool)
+                .await?;
+
+        Ok(rows)
+    }
+
+    /// Get the image files tracked for a playlist
+    pub async fn get_by_playlist(playlistid: i64) -> Result<Vec<PlaylistImageRow>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows = sqlx::query_as(
+            "SELECT playlistid, filename, created_at, color, color_dark, color_light FROM playlist_image WHERE playlistid = ?",
+        )
+        .bind(playlistid)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Check whether a file is still tracked by any playlist or used as a playlist image
+    pub async fn is_referenced(filename: &str) -> Result<bool> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let row: (i64,) = sqlx::query_as(
+            "SELECT (SELECT COUNT(*) FROM playlist_image WHERE filename = ?) + (SELECT COUNT(*) FROM playlist WHERE image = ?)",
+        )
+        .bind(filename)
+        .bind(filename)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(row.0 > 0)
+    }
+
+    /// Set the dominant color and its variants of a tracked image
+    pub async fn set_color(
+        playlistid: i64,
+        filename: &str,
+        color: &str,
+        variants: &ColorVariants,
+    ) -> Result<()> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        sqlx::query(
+            "UPDATE playlist_image SET color = ?, color_dark = ?, color_light = ? WHERE playlistid = ? AND filename = ?",
+        )
+        .bind(color)
+        .bind(&variants.dark)
+        .bind(&variants.light)
+        .bind(playlistid)
+            .bind(filename)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Stop tracking an image file for a playlist
+    pub async fn delete(playlistid: i64, filename: &str) -> Result<()> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        sqlx::query("DELETE FROM playlist_image WHERE playlistid = ? AND filename = ?")
+            .bind(playlistid)
+            .bind(filename)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+}
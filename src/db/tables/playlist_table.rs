@@ -2,9 +2,11 @@
 
 use anyhow::Result;
 use sqlx::FromRow;
+use std::collections::HashSet;
 
 use crate::db::DbEngine;
 use crate::models::{Playlist, PlaylistSettings};
+use crate::stores::PlaylistMembershipStore;
 
 /// Database row for playlist table
 #[derive(Debug, FromRow)]
@@ -17,6 +19,11 @@ struct PlaylistRow {
     trackhashes: String,
     settings: String,
     extra: String,
+    shared: bool,
+    collaborators: Option<String>,
+    color: Option<String>,
+    color_dark: Option<String>,
+    color_light: Option<String>,
 }
 
 impl PlaylistRow {
@@ -26,7 +33,7 @@ impl PlaylistRow {
         let extra: serde_json::Value =
             serde_json::from_str(&self.extra).unwrap_or(serde_json::Value::Null);
 
-        Playlist::from_db_row(
+        let mut playlist = Playlist::from_db_row(
             self.id,
             self.name,
             self.image,
@@ -35,7 +42,31 @@ impl PlaylistRow {
             settings,
             Some(self.userid),
             extra,
-        )
+        );
+        playlist.shared = self.shared;
+        playlist.collaborators = self
+            .collaborators
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|id| id.parse().ok())
+            .collect();
+        playlist.color = self.color.unwrap_or_default();
+        playlist.color_dark = self.color_dark.unwrap_or_default();
+        playlist.color_light = self.color_light.unwrap_or_default();
+        playlist
+    }
+}
+
+/// playlist columns plus its collaborators and the colors of the current custom image
+const SELECT_PLAYLIST: &str = "SELECT p.*, \
+     (SELECT GROUP_CONCAT(pc.userid) FROM playlist_collaborator pc WHERE pc.playlistid = p.id) \
+     AS collaborators, pi.color AS color, pi.color_dark AS color_dark, \
+     pi.color_light AS color_light FROM playlist p \
+     LEFT JOIN playlist_image pi ON pi.playlistid = p.id AND pi.filename = p.image";
+
+impl PlaylistRow {
+    fn select(filter: &str) -> String {
+        format!("{} {}", SELECT_PLAYLIST, filter)
     }
 }
 
@@ -43,31 +74,46 @@ impl PlaylistRow {
 pub struct PlaylistTable;
 
 impl PlaylistTable {
-    /// Get all playlists
+    /// Get the playlists owned by a user, or every playlist when `userid` is None
     pub async fn all(userid: Option<i64>) -> Result<Vec<Playlist>> {
         let engine = DbEngine::get()?;
         let pool = engine.pool();
 
         let rows: Vec<PlaylistRow> = if let Some(uid) = userid {
-            sqlx::query_as("SELECT * FROM playlist WHERE userid = ?")
+            sqlx::query_as(&PlaylistRow::select("WHERE p.userid = ?"))
                 .bind(uid)
                 .fetch_all(pool)
                 .await?
         } else {
-            sqlx::query_as("SELECT * FROM playlist")
-                .fetch_all(pool)
-                .await?
+            sqlx::query_as(SELECT_PLAYLIST).fetch_all(pool).await?
         };
 
         Ok(rows.into_iter().map(|r| r.into_playlist()).collect())
     }
 
+    /// Get the playlists a user owns, collaborates on or that are shared
+    pub async fn visible(userid: i64) -> Result<Vec<Playlist>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows: Vec<PlaylistRow> = sqlx::query_as(&PlaylistRow::select(
+            "WHERE p.userid = ? OR p.shared = 1 OR EXISTS \
+             (SELECT 1 FROM playlist_collaborator c WHERE c.playlistid = p.id AND c.userid = ?)",
+        ))
+        .bind(userid)
+        .bind(userid)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|r| r.into_playlist()).collect())
+    }
+
     /// Get playlist by ID
     pub async fn get_by_id(id: i64) -> Result<Option<Playlist>> {
         let engine = DbEngine::get()?;
         let pool = engine.pool();
 
-        let row: Option<PlaylistRow> = sqlx::query_as("SELECT * FROM playlist WHERE id = ?")
+        let row: Option<PlaylistRow> = sqlx::query_as(&PlaylistRow::select("WHERE p.id = ?"))
             .bind(id)
             .fetch_optional(pool)
             .await?;
@@ -85,7 +131,7 @@ impl PlaylistTable {
         let extra = serde_json::to_string(&playlist.extra)?;
 
         let result = sqlx::query(
-            "INSERT INTO playlist (userid, name, last_updated, image, trackhashes, settings, extra) VALUES (?, ?, ?, ?, ?, ?, ?)"
+            "INSERT INTO playlist (userid, name, last_updated, image, trackhashes, settings, extra, shared) VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
         )
         .bind(playlist.userid.unwrap_or(1))
         .bind(&playlist.name)
@@ -94,10 +140,13 @@ impl PlaylistTable {
         .bind(&trackhashes)
         .bind(&settings)
         .bind(&extra)
+        .bind(playlist.shared)
         .execute(pool)
         .await?;
 
-        Ok(result.last_insert_rowid())
+        let id = result.last_insert_rowid();
+        PlaylistMembershipStore::get().set(id, playlist.userid.unwrap_or(1), &playlist.trackhashes);
+        Ok(id)
     }
 
     /// Check if playlist name exists
@@ -131,8 +180,9 @@ impl PlaylistTable {
             .and_then(|(t,)| serde_json::from_str(&t).ok())
             .unwrap_or_default();
 
+        let mut present: HashSet<String> = current.iter().cloned().collect();
         for hash in trackhashes {
-            if !current.contains(hash) {
+            if present.insert(hash.clone()) {
                 current.push(hash.clone());
             }
         }
@@ -147,6 +197,7 @@ impl PlaylistTable {
             .execute(pool)
             .await?;
 
+        PlaylistMembershipStore::get().set_tracks(id, None, &current);
         Ok(())
     }
 
@@ -190,6 +241,7 @@ impl PlaylistTable {
             .execute(pool)
             .await?;
 
+        PlaylistMembershipStore::get().set_tracks(id, None, &new_trackhashes);
         Ok(())
     }
 
@@ -216,6 +268,11 @@ impl PlaylistTable {
         .execute(pool)
         .await?;
 
+        PlaylistMembershipStore::get().set_tracks(
+            playlist.id,
+            playlist.userid,
+            &playlist.trackhashes,
+        );
         Ok(())
     }
 
@@ -235,6 +292,38 @@ impl PlaylistTable {
         Ok(())
     }
 
+    /// Set who besides the owner can see and edit a playlist
+    ///
+    /// the owner is never stored as a collaborator
+    pub async fn set_sharing(id: i64, shared: bool, collaborators: &[i64]) -> Result<()> {
+        let engine = DbEngine::get()?;
+        let mut tx = engine.pool().begin().await?;
+
+        sqlx::query("UPDATE playlist SET shared = ? WHERE id = ?")
+            .bind(shared)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM playlist_collaborator WHERE playlistid = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        for userid in collaborators {
+            sqlx::query(
+                "INSERT OR IGNORE INTO playlist_collaborator (playlistid, userid) \
+                 SELECT id, ? FROM playlist WHERE id = ? AND userid != ?",
+            )
+            .bind(userid)
+            .bind(id)
+            .bind(userid)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
     /// Remove playlist image
     pub async fn remove_image(id: i64) -> Result<()> {
         let engine = DbEngine::get()?;
@@ -266,7 +355,11 @@ impl PlaylistTable {
                 .await?
         };
 
-        Ok(result.rows_affected() > 0)
+        let deleted = result.rows_affected() > 0;
+        if deleted {
+            PlaylistMembershipStore::get().remove(id);
+        }
+        Ok(deleted)
     }
 
     /// Update playlist trackhashes
@@ -283,6 +376,8 @@ impl PlaylistTable {
             .execute(pool)
             .await?;
 
+        let trackhashes: Vec<String> = serde_json::from_str(trackhashes_json).unwrap_or_default();
+        PlaylistMembershipStore::get().set_tracks(id, None, &trackhashes);
         Ok(())
     }
 }
@@ -39,6 +39,20 @@ impl PluginTable {
         Ok(())
     }
 
+    /// Insert an inactive plugin if it does not exist yet
+    pub async fn insert_default(name: &str, settings: &str) -> Result<()> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        sqlx::query("INSERT OR IGNORE INTO plugin (name, settings, active) VALUES (?, ?, 0)")
+            .bind(name)
+            .bind(settings)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
     /// Get plugin by name
     pub async fn get_by_name(name: &str) -> Result<Option<PluginRow>> {
         let engine = DbEngine::get()?;
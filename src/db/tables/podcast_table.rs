@@ -0,0 +1,409 @@
+//! Podcast, episode and episode progress table operations
+
+use anyhow::Result;
+use sqlx::FromRow;
+use std::collections::{HashMap, HashSet};
+
+use crate::db::DbEngine;
+use crate::models::{EpisodeProgress, Podcast, PodcastEpisode};
+
+/// Database row for podcast table
+#[derive(Debug, FromRow)]
+struct PodcastRow {
+    id: i64,
+    userid: i64,
+    feed_url: String,
+    title: String,
+    author: String,
+    description: String,
+    image: String,
+    link: String,
+    last_checked: i64,
+    created_at: i64,
+}
+
+impl PodcastRow {
+    fn into_podcast(self) -> Podcast {
+        Podcast {
+            id: self.id,
+            userid: self.userid,
+            feed_url: self.feed_url,
+            title: self.title,
+            author: self.author,
+            description: self.description,
+            image: self.image,
+            link: self.link,
+            last_checked: self.last_checked,
+            created_at: self.created_at,
+        }
+    }
+}
+
+/// Database row for podcast_episode table
+#[derive(Debug, FromRow)]
+struct EpisodeRow {
+    id: i64,
+    podcastid: i64,
+    guid: String,
+    title: String,
+    description: String,
+    audio_url: String,
+    mime_type: String,
+    duration: i64,
+    published: i64,
+    image: String,
+    filepath: String,
+}
+
+impl EpisodeRow {
+    fn into_episode(self) -> PodcastEpisode {
+        PodcastEpisode {
+            id: self.id,
+            podcastid: self.podcastid,
+            guid: self.guid,
+            title: self.title,
+            description: self.description,
+            audio_url: self.audio_url,
+            mime_type: self.mime_type,
+            duration: self.duration,
+            published: self.published,
+            image: self.image,
+            filepath: self.filepath,
+        }
+    }
+}
+
+/// Podcast table operations
+pub struct PodcastTable;
+
+impl PodcastTable {
+    /// Get the podcasts of a user, or of every user when `userid` is None
+    pub async fn all(userid: Option<i64>) -> Result<Vec<Podcast>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows: Vec<PodcastRow> = if let Some(uid) = userid {
+            sqlx::query_as("SELECT * FROM podcast WHERE userid = ? ORDER BY title COLLATE NOCASE")
+                .bind(uid)
+                .fetch_all(pool)
+                .await?
+        } else {
+            sqlx::query_as("SELECT * FROM podcast")
+                .fetch_all(pool)
+                .await?
+        };
+
+        Ok(rows.into_iter().map(PodcastRow::into_podcast).collect())
+    }
+
+    /// Get a podcast owned by a user
+    pub async fn get(id: i64, userid: i64) -> Result<Option<Podcast>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let row: Option<PodcastRow> =
+            sqlx::query_as("SELECT * FROM podcast WHERE id = ? AND userid = ?")
+                .bind(id)
+                .bind(userid)
+                .fetch_optional(pool)
+                .await?;
+
+        Ok(row.map(PodcastRow::into_podcast))
+    }
+
+    /// Get the podcast a user subscribed to at a feed url
+    pub async fn get_by_feed(feed_url: &str, userid: i64) -> Result<Option<Podcast>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let row: Option<PodcastRow> =
+            sqlx::query_as("SELECT * FROM podcast WHERE feed_url = ? AND userid = ?")
+                .bind(feed_url)
+                .bind(userid)
+                .fetch_optional(pool)
+                .await?;
+
+        Ok(row.map(PodcastRow::into_podcast))
+    }
+
+    /// Insert a podcast, returns its id
+    pub async fn insert(podcast: &Podcast) -> Result<i64> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO podcast
+                (userid, feed_url, title, author, description, image, link, last_checked, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(podcast.userid)
+        .bind(&podcast.feed_url)
+        .bind(&podcast.title)
+        .bind(&podcast.author)
+        .bind(&podcast.description)
+        .bind(&podcast.image)
+        .bind(&podcast.link)
+        .bind(podcast.last_checked)
+        .bind(podcast.created_at)
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Update the feed details of a podcast
+    pub async fn update(podcast: &Podcast) -> Result<()> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        sqlx::query(
+            r#"
+            UPDATE podcast
+            SET title = ?, author = ?, description = ?, image = ?, link = ?, last_checked = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(&podcast.title)
+        .bind(&podcast.author)
+        .bind(&podcast.description)
+        .bind(&podcast.image)
+        .bind(&podcast.link)
+        .bind(podcast.last_checked)
+        .bind(podcast.id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Delete a podcast with its episodes and their progress
+    pub async fn delete(id: i64) -> Result<()> {
+        let engine = DbEngine::get()?;
+        let mut tx = engine.pool().begin().await?;
+
+        sqlx::query(
+            "DELETE FROM podcast_progress WHERE episodeid IN \
+             (SELECT id FROM podcast_episode WHERE podcastid = ?)",
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM podcast_episode WHERE podcastid = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM podcast WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Get episodes of a podcast, newest first
+    ///
+    /// a negative limit returns every episode after `start`
+    pub async fn episodes(podcastid: i64, start: i64, limit: i64) -> Result<Vec<PodcastEpisode>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows: Vec<EpisodeRow> = sqlx::query_as(
+            "SELECT * FROM podcast_episode WHERE podcastid = ? \
+             ORDER BY published DESC, id DESC LIMIT ? OFFSET ?",
+        )
+        .bind(podcastid)
+        .bind(limit)
+        .bind(start.max(0))
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(EpisodeRow::into_episode).collect())
+    }
+
+    /// Count the episodes of a podcast
+    pub async fn episode_count(podcastid: i64) -> Result<i64> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let row: (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM podcast_episode WHERE podcastid = ?")
+                .bind(podcastid)
+                .fetch_one(pool)
+                .await?;
+
+        Ok(row.0)
+    }
+
+    /// Get an episode with the id of the user owning its podcast
+    pub async fn get_episode(id: i64) -> Result<Option<(PodcastEpisode, i64)>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let row: Option<EpisodeRow> = sqlx::query_as("SELECT * FROM podcast_episode WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+        let Some(episode) = row.map(EpisodeRow::into_episode) else {
+            return Ok(None);
+        };
+
+        let owner: Option<(i64,)> = sqlx::query_as("SELECT userid FROM podcast WHERE id = ?")
+            .bind(episode.podcastid)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(owner.map(|(userid,)| (episode, userid)))
+    }
+
+    /// Insert new episodes and update the known ones, returns how many were new
+    ///
+    /// downloaded files of known episodes are kept
+    pub async fn upsert_episodes(podcastid: i64, episodes: &[PodcastEpisode]) -> Result<usize> {
+        if episodes.is_empty() {
+            return Ok(0);
+        }
+
+        let engine = DbEngine::get()?;
+        let mut tx = engine.pool().begin().await?;
+
+        let known: Vec<(String,)> =
+            sqlx::query_as("SELECT guid FROM podcast_episode WHERE podcastid = ?")
+                .bind(podcastid)
+                .fetch_all(&mut *tx)
+                .await?;
+        let known: HashSet<String> = known.into_iter().map(|(guid,)| guid).collect();
+
+        for episode in episodes {
+            sqlx::query(
+                r#"
+                INSERT INTO podcast_episode
+                    (podcastid, guid, title, description, audio_url, mime_type, duration, published, image)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(podcastid, guid) DO UPDATE SET
+                    title = excluded.title,
+                    description = excluded.description,
+                    audio_url = excluded.audio_url,
+                    mime_type = excluded.mime_type,
+                    duration = excluded.duration,
+                    published = excluded.published,
+                    image = excluded.image
+                "#,
+            )
+            .bind(podcastid)
+            .bind(&episode.guid)
+            .bind(&episode.title)
+            .bind(&episode.description)
+            .bind(&episode.audio_url)
+            .bind(&episode.mime_type)
+            .bind(episode.duration)
+            .bind(episode.published)
+            .bind(&episode.image)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        let fresh: HashSet<&str> = episodes
+            .iter()
+            .map(|e| e.guid.as_str())
+            .filter(|guid| !known.contains(*guid))
+            .collect();
+        Ok(fresh.len())
+    }
+
+    /// Record the downloaded file of an episode, empty when it was removed
+    pub async fn set_episode_file(id: i64, filepath: &str) -> Result<()> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        sqlx::query("UPDATE podcast_episode SET filepath = ? WHERE id = ?")
+            .bind(filepath)
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Downloaded files of the episodes of a podcast
+    pub async fn episode_files(podcastid: i64) -> Result<Vec<String>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows: Vec<(String,)> = sqlx::query_as(
+            "SELECT filepath FROM podcast_episode WHERE podcastid = ? AND filepath != ''",
+        )
+        .bind(podcastid)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|(path,)| path).collect())
+    }
+
+    /// Progress of a user through the episodes of a podcast
+    pub async fn progress(userid: i64, podcastid: i64) -> Result<HashMap<i64, EpisodeProgress>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows: Vec<(i64, i64, bool, i64)> = sqlx::query_as(
+            r#"
+            SELECT pp.episodeid, pp.position, pp.completed, pp.updated
+            FROM podcast_progress pp
+            JOIN podcast_episode pe ON pe.id = pp.episodeid
+            WHERE pp.userid = ? AND pe.podcastid = ?
+            "#,
+        )
+        .bind(userid)
+        .bind(podcastid)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(episodeid, position, completed, updated)| {
+                (
+                    episodeid,
+                    EpisodeProgress {
+                        position,
+                        completed,
+                        updated,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    /// Save the progress of a user through an episode
+    pub async fn set_progress(
+        userid: i64,
+        episodeid: i64,
+        progress: &EpisodeProgress,
+    ) -> Result<()> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        sqlx::query(
+            r#"
+            INSERT INTO podcast_progress (userid, episodeid, position, completed, updated)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT(userid, episodeid) DO UPDATE SET
+                position = excluded.position,
+                completed = excluded.completed,
+                updated = excluded.updated
+            "#,
+        )
+        .bind(userid)
+        .bind(episodeid)
+        .bind(progress.position)
+        .bind(progress.completed)
+        .bind(progress.updated)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+//! Decaying popularity scores of tracks, artists and albums
+
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::FromRow;
+
+use crate::db::DbEngine;
+
+/// How popular an item is with a user right now
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct PopularityScore {
+    pub userid: i64,
+    /// track, artist or album
+    pub kind: String,
+    pub hash: String,
+    pub score: f64,
+    pub updated: i64,
+}
+
+/// Popularity table operations
+pub struct PopularityTable;
+
+impl PopularityTable {
+    /// Get the highest scoring items of a kind for a user
+    pub async fn top(userid: i64, kind: &str, limit: usize) -> Result<Vec<PopularityScore>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows: Vec<PopularityScore> = sqlx::query_as(
+            "SELECT userid, kind, hash, score, updated FROM popularity \
+             WHERE userid = ? AND kind = ? ORDER BY score DESC LIMIT ?",
+        )
+        .bind(userid)
+        .bind(kind)
+        .bind(limit as i64)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Replace every score with a freshly computed set
+    pub async fn replace_all(scores: &[PopularityScore]) -> Result<()> {
+        let engine = DbEngine::get()?;
+        let mut tx = engine.pool().begin().await?;
+
+        sqlx::query("DELETE FROM popularity")
+            .execute(&mut *tx)
+            .await?;
+        for score in scores {
+            sqlx::query(
+                "INSERT INTO popularity (userid, kind, hash, score, updated) \
+                 VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(score.userid)
+            .bind(&score.kind)
+            .bind(&score.hash)
+            .bind(score.score)
+            .bind(score.updated)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
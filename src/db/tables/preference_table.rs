@@ -0,0 +1,86 @@
+//! Per-user client preferences
+
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::db::DbEngine;
+
+/// User preference table operations
+pub struct PreferenceTable;
+
+impl PreferenceTable {
+    /// Get every preference of a user by key
+    pub async fn all(userid: i64) -> Result<BTreeMap<String, Value>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows: Vec<(String, String)> =
+            sqlx::query_as("SELECT key, value FROM preference WHERE userid = ?")
+                .bind(userid)
+                .fetch_all(pool)
+                .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, serde_json::from_str(&value).ok()?)))
+            .collect())
+    }
+
+    /// Save and clear preferences of a user in a single transaction, a `None`
+    /// value clears the key
+    pub async fn update(
+        userid: i64,
+        changes: &BTreeMap<String, Option<Value>>,
+        updated: i64,
+    ) -> Result<()> {
+        let engine = DbEngine::get()?;
+        let mut tx = engine.pool().begin().await?;
+
+        for (key, value) in changes {
+            match value {
+                Some(value) => {
+                    sqlx::query(
+                        r#"
+                        INSERT INTO preference (userid, key, value, updated)
+                        VALUES (?, ?, ?, ?)
+                        ON CONFLICT(userid, key) DO UPDATE SET
+                            value = excluded.value,
+                            updated = excluded.updated
+                        "#,
+                    )
+                    .bind(userid)
+                    .bind(key)
+                    .bind(value.to_string())
+                    .bind(updated)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                None => {
+                    sqlx::query("DELETE FROM preference WHERE userid = ? AND key = ?")
+                        .bind(userid)
+                        .bind(key)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+            }
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Clear a preference of a user, returns whether it was set
+    pub async fn delete(userid: i64, key: &str) -> Result<bool> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let result = sqlx::query("DELETE FROM preference WHERE userid = ? AND key = ?")
+            .bind(userid)
+            .bind(key)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,131 @@
+//! Radio station table operations
+
+use anyhow::Result;
+use sqlx::FromRow;
+
+use crate::db::DbEngine;
+use crate::models::RadioStation;
+
+/// Database row for radio table
+#[derive(Debug, FromRow)]
+struct RadioRow {
+    id: i64,
+    userid: i64,
+    name: String,
+    url: String,
+    homepage: String,
+    image: String,
+    genre: String,
+    created_at: i64,
+}
+
+impl RadioRow {
+    fn into_station(self) -> RadioStation {
+        RadioStation {
+            id: self.id,
+            userid: self.userid,
+            name: self.name,
+            url: self.url,
+            homepage: self.homepage,
+            image: self.image,
+            genre: self.genre,
+            created_at: self.created_at,
+        }
+    }
+}
+
+/// Radio station table operations
+pub struct RadioTable;
+
+impl RadioTable {
+    /// Get the stations of a user, sorted by name
+    pub async fn all(userid: i64) -> Result<Vec<RadioStation>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows: Vec<RadioRow> =
+            sqlx::query_as("SELECT * FROM radio WHERE userid = ? ORDER BY name COLLATE NOCASE")
+                .bind(userid)
+                .fetch_all(pool)
+                .await?;
+
+        Ok(rows.into_iter().map(RadioRow::into_station).collect())
+    }
+
+    /// Get a station owned by a user
+    pub async fn get(id: i64, userid: i64) -> Result<Option<RadioStation>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let row: Option<RadioRow> =
+            sqlx::query_as("SELECT * FROM radio WHERE id = ? AND userid = ?")
+                .bind(id)
+                .bind(userid)
+                .fetch_optional(pool)
+                .await?;
+
+        Ok(row.map(RadioRow::into_station))
+    }
+
+    /// Insert a station, returns its id
+    pub async fn insert(station: &RadioStation) -> Result<i64> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO radio (userid, name, url, homepage, image, genre, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(station.userid)
+        .bind(&station.name)
+        .bind(&station.url)
+        .bind(&station.homepage)
+        .bind(&station.image)
+        .bind(&station.genre)
+        .bind(station.created_at)
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Update a station owned by `station.userid`
+    pub async fn update(station: &RadioStation) -> Result<bool> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let result = sqlx::query(
+            r#"
+            UPDATE radio SET name = ?, url = ?, homepage = ?, image = ?, genre = ?
+            WHERE id = ? AND userid = ?
+            "#,
+        )
+        .bind(&station.name)
+        .bind(&station.url)
+        .bind(&station.homepage)
+        .bind(&station.image)
+        .bind(&station.genre)
+        .bind(station.id)
+        .bind(station.userid)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete a station owned by a user
+    pub async fn delete(id: i64, userid: i64) -> Result<bool> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let result = sqlx::query("DELETE FROM radio WHERE id = ? AND userid = ?")
+            .bind(id)
+            .bind(userid)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
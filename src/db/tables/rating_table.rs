@@ -0,0 +1,73 @@
+//! Per-user track rating table operations
+
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::FromRow;
+
+use crate::db::DbEngine;
+
+/// A user's star rating of a track
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct TrackRating {
+    pub userid: i64,
+    pub trackhash: String,
+    /// stars from 1 to 5
+    pub rating: i64,
+    pub updated: i64,
+}
+
+/// Track rating table operations
+pub struct RatingTable;
+
+impl RatingTable {
+    /// Get every rating of every user
+    pub async fn all() -> Result<Vec<TrackRating>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows: Vec<TrackRating> =
+            sqlx::query_as("SELECT userid, trackhash, rating, updated FROM rating")
+                .fetch_all(pool)
+                .await?;
+
+        Ok(rows)
+    }
+
+    /// Save the rating a user gave a track
+    pub async fn set(userid: i64, trackhash: &str, rating: u8, updated: i64) -> Result<()> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        sqlx::query(
+            r#"
+            INSERT INTO rating (userid, trackhash, rating, updated)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT(userid, trackhash) DO UPDATE SET
+                rating = excluded.rating,
+                updated = excluded.updated
+            "#,
+        )
+        .bind(userid)
+        .bind(trackhash)
+        .bind(rating as i64)
+        .bind(updated)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Forget the rating a user gave a track
+    pub async fn delete(userid: i64, trackhash: &str) -> Result<()> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        sqlx::query("DELETE FROM rating WHERE userid = ? AND trackhash = ?")
+            .bind(userid)
+            .bind(trackhash)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+}
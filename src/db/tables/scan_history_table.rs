@@ -0,0 +1,153 @@
+//! Library scan history table operations
+
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::FromRow;
+
+use crate::db::DbEngine;
+
+/// Finished scans kept, older ones are dropped as new ones are recorded
+pub const MAX_SCAN_HISTORY: i64 = 500;
+
+/// Summary of a finished library scan
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanRecord {
+    pub id: i64,
+    /// initial, full or incremental
+    pub kind: String,
+    pub started_at: i64,
+    pub finished_at: i64,
+    pub duration_ms: i64,
+    pub files_seen: i64,
+    pub files_processed: i64,
+    pub files_failed: i64,
+    pub files_skipped: i64,
+    pub tracks_added: i64,
+    pub tracks_updated: i64,
+    pub tracks_removed: i64,
+    /// tracks in the library once the scan finished
+    pub tracks_total: i64,
+    /// why the scan failed, none when it completed
+    pub error: Option<String>,
+    /// failed files, skipped files, roots and the settings the scan ran with
+    pub details: serde_json::Value,
+}
+
+/// Database row for scan_history table
+#[derive(Debug, FromRow)]
+struct ScanRecordRow {
+    id: i64,
+    kind: String,
+    started_at: i64,
+    finished_at: i64,
+    duration_ms: i64,
+    files_seen: i64,
+    files_processed: i64,
+    files_failed: i64,
+    files_skipped: i64,
+    tracks_added: i64,
+    tracks_updated: i64,
+    tracks_removed: i64,
+    tracks_total: i64,
+    error: Option<String>,
+    details: String,
+}
+
+impl ScanRecordRow {
+    fn into_record(self) -> ScanRecord {
+        ScanRecord {
+            id: self.id,
+            kind: self.kind,
+            started_at: self.started_at,
+            finished_at: self.finished_at,
+            duration_ms: self.duration_ms,
+            files_seen: self.files_seen,
+            files_processed: self.files_processed,
+            files_failed: self.files_failed,
+            files_skipped: self.files_skipped,
+            tracks_added: self.tracks_added,
+            tracks_updated: self.tracks_updated,
+            tracks_removed: self.tracks_removed,
+            tracks_total: self.tracks_total,
+            error: self.error,
+            details: serde_json::from_str(&self.details).unwrap_or_default(),
+        }
+    }
+}
+
+/// Scan history table operations
+pub struct ScanHistoryTable;
+
+impl ScanHistoryTable {
+    /// Record a finished scan and drop the oldest beyond [`MAX_SCAN_HISTORY`]
+    ///
+    /// the id of the record is ignored, returns the id it was stored under
+    pub async fn insert(record: &ScanRecord) -> Result<i64> {
+        let engine = DbEngine::get()?;
+        let mut tx = engine.pool().begin().await?;
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO scan_history
+                (kind, started_at, finished_at, duration_ms, files_seen, files_processed,
+                 files_failed, files_skipped, tracks_added, tracks_updated, tracks_removed,
+                 tracks_total, error, details)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&record.kind)
+        .bind(record.started_at)
+        .bind(record.finished_at)
+        .bind(record.duration_ms)
+        .bind(record.files_seen)
+        .bind(record.files_processed)
+        .bind(record.files_failed)
+        .bind(record.files_skipped)
+        .bind(record.tracks_added)
+        .bind(record.tracks_updated)
+        .bind(record.tracks_removed)
+        .bind(record.tracks_total)
+        .bind(&record.error)
+        .bind(record.details.to_string())
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            "DELETE FROM scan_history WHERE id NOT IN \
+             (SELECT id FROM scan_history ORDER BY id DESC LIMIT ?)",
+        )
+        .bind(MAX_SCAN_HISTORY)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Get recorded scans, newest first
+    pub async fn recent(start: i64, limit: i64) -> Result<Vec<ScanRecord>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows: Vec<ScanRecordRow> =
+            sqlx::query_as("SELECT * FROM scan_history ORDER BY id DESC LIMIT ? OFFSET ?")
+                .bind(limit)
+                .bind(start.max(0))
+                .fetch_all(pool)
+                .await?;
+
+        Ok(rows.into_iter().map(ScanRecordRow::into_record).collect())
+    }
+
+    /// Count the recorded scans
+    pub async fn count() -> Result<i64> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let row: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM scan_history")
+            .fetch_one(pool)
+            .await?;
+
+        Ok(row.0)
+    }
+}
@@ -3,6 +3,7 @@
 use anyhow::Result;
 use serde_json::Value;
 use sqlx::FromRow;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::db::DbEngine;
 use crate::models::TrackLog;
@@ -17,6 +18,7 @@ struct ScrobbleRow {
     source: String,
     userid: i64,
     extra: String,
+    device: String,
 }
 
 impl ScrobbleRow {
@@ -29,11 +31,102 @@ impl ScrobbleRow {
             self.userid,
         );
         log.id = self.id;
+        log.device = self.device;
         log.extra = serde_json::from_str(&self.extra).unwrap_or_default();
         log
     }
 }
 
+/// bumped whenever scrobbles are added or removed so cached aggregates can
+/// tell they are stale
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Play totals of one track
+#[derive(Debug, Clone, FromRow)]
+pub struct TrackPlayTotals {
+    pub trackhash: String,
+    pub playcount: i64,
+    pub playduration: i64,
+    pub first_played: i64,
+    pub last_played: i64,
+}
+
+/// Plays of a user on one device
+#[derive(Debug, Clone, FromRow)]
+pub struct DevicePlays {
+    pub device: String,
+    pub plays: i64,
+    pub last_played: i64,
+}
+
+/// Devices a query keeps plays from
+///
+/// an empty `only` list keeps every device that is not in `except`, plays
+/// without a device are matched by the empty string
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceFilter {
+    pub only: Vec<String>,
+    pub except: Vec<String>,
+}
+
+impl DeviceFilter {
+    /// Parse a comma separated device list, a leading `-` excludes a device
+    /// and a lone `-` stands for plays without a device
+    pub fn parse(value: Option<&str>) -> Self {
+        let mut filter = Self::default();
+        for part in value.unwrap_or_default().split(',') {
+            let part = part.trim();
+            if part.is_empty() {
+                continue;
+            }
+            match part.strip_prefix('-') {
+                Some(device) => filter.except.push(device.trim().to_string()),
+                None => filter.only.push(part.to_string()),
+            }
+        }
+        filter
+    }
+
+    /// The filter with more devices excluded
+    pub fn excluding<I: IntoIterator<Item = String>>(mut self, devices: I) -> Self {
+        self.except.extend(devices);
+        self
+    }
+
+    /// Whether the filter keeps every play
+    pub fn is_empty(&self) -> bool {
+        self.only.is_empty() && self.except.is_empty()
+    }
+
+    /// Whether a play from a device is kept
+    pub fn allows(&self, device: &str) -> bool {
+        (self.only.is_empty() || self.only.iter().any(|d| d == device))
+            && !self.except.iter().any(|d| d == device)
+    }
+
+    /// the `only` and `except` lists as json arrays for `json_each`
+    fn bind_values(&self) -> Result<(String, String)> {
+        Ok((
+            serde_json::to_string(&self.only)?,
+            serde_json::to_string(&self.except)?,
+        ))
+    }
+}
+
+/// where clause keeping the plays a `DeviceFilter` allows, binds the `only`
+/// list twice and then the `except` list
+const DEVICE_CLAUSE: &str = "(json_array_length(?) = 0 \
+     OR device IN (SELECT value FROM json_each(?))) \
+     AND device NOT IN (SELECT value FROM json_each(?))";
+
+/// A single play without its payload, for scanning a whole history
+#[derive(Debug, Clone, FromRow)]
+pub struct ScrobblePoint {
+    pub userid: i64,
+    pub trackhash: String,
+    pub timestamp: i64,
+}
+
 /// Scrobble table operations
 pub struct ScrobbleTable;
 
@@ -57,18 +150,20 @@ impl ScrobbleTable {
             duration,
             source,
             userid,
+            "",
             &serde_json::json!({}),
         )
         .await
     }
 
-    /// Add scrobble entry with extra payload
+    /// Add scrobble entry with the device it was played on and extra payload
     pub async fn add_with_extra(
         trackhash: &str,
         timestamp: i64,
         duration: i32,
         source: &str,
         userid: i64,
+        device: &str,
         extra: &Value,
     ) -> Result<i64> {
         let engine = DbEngine::get()?;
@@ -77,29 +172,99 @@ impl ScrobbleTable {
         let extra_json = serde_json::to_string(extra).unwrap_or_else(|_| "{}".to_string());
 
         let result = sqlx::query(
-            "INSERT INTO scrobble (trackhash, timestamp, duration, source, userid, extra) VALUES (?, ?, ?, ?, ?, ?)"
+            "INSERT INTO scrobble (trackhash, timestamp, duration, source, userid, device, extra) VALUES (?, ?, ?, ?, ?, ?, ?)"
         )
         .bind(trackhash)
         .bind(timestamp)
         .bind(duration)
         .bind(source)
         .bind(userid)
+        .bind(device)
         .bind(extra_json)
         .execute(pool)
         .await?;
+        Self::mark_changed();
 
         Ok(result.last_insert_rowid())
     }
 
+    /// Current change counter, differs from an earlier value once scrobbles changed
+    pub fn generation() -> u64 {
+        GENERATION.load(Ordering::Acquire)
+    }
+
+    /// Record that scrobbles were added or removed outside of `add_with_extra`
+    pub fn mark_changed() {
+        GENERATION.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Every play of every user ordered by user and time
+    pub async fn history() -> Result<Vec<ScrobblePoint>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows: Vec<ScrobblePoint> = sqlx::query_as(
+            "SELECT userid, trackhash, timestamp FROM scrobble ORDER BY userid, timestamp",
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Per-track play totals of a user for the given tracks
+    pub async fn track_totals(
+        userid: i64,
+        trackhashes: &[String],
+    ) -> Result<Vec<TrackPlayTotals>> {
+        if trackhashes.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        // a json array sidesteps the bound parameter limit for large artists
+        let hashes = serde_json::to_string(trackhashes)?;
+        let rows: Vec<TrackPlayTotals> = sqlx::query_as(
+            "SELECT trackhash, COUNT(*) AS playcount, COALESCE(SUM(duration), 0) AS playduration, \
+             MIN(timestamp) AS first_played, MAX(timestamp) AS last_played \
+             FROM scrobble WHERE userid = ? AND trackhash IN (SELECT value FROM json_each(?)) \
+             GROUP BY trackhash",
+        )
+        .bind(userid)
+        .bind(hashes)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
     /// Get paginated scrobbles
     pub async fn get_paginated(userid: i64, start: i64, limit: i64) -> Result<Vec<TrackLog>> {
+        Self::get_paginated_on(userid, &DeviceFilter::default(), start, limit).await
+    }
+
+    /// Get paginated scrobbles from the devices a filter keeps
+    pub async fn get_paginated_on(
+        userid: i64,
+        devices: &DeviceFilter,
+        start: i64,
+        limit: i64,
+    ) -> Result<Vec<TrackLog>> {
         let engine = DbEngine::get()?;
         let pool = engine.pool();
 
-        let rows: Vec<ScrobbleRow> = sqlx::query_as(
-            "SELECT * FROM scrobble WHERE userid = ? ORDER BY timestamp DESC LIMIT ? OFFSET ?",
-        )
+        let (only, except) = devices.bind_values()?;
+        let rows: Vec<ScrobbleRow> = sqlx::query_as(&format!(
+            "SELECT * FROM scrobble WHERE userid = ? AND {} \
+             ORDER BY timestamp DESC LIMIT ? OFFSET ?",
+            DEVICE_CLAUSE
+        ))
         .bind(userid)
+        .bind(&only)
+        .bind(&only)
+        .bind(except)
         .bind(limit)
         .bind(start)
         .fetch_all(pool)
@@ -135,14 +300,30 @@ impl ScrobbleTable {
         userid: i64,
         start_time: i64,
         end_time: i64,
+    ) -> Result<Vec<TrackLog>> {
+        Self::get_in_range_on(userid, start_time, end_time, &DeviceFilter::default()).await
+    }
+
+    /// Get scrobbles in time range from the devices a filter keeps
+    pub async fn get_in_range_on(
+        userid: i64,
+        start_time: i64,
+        end_time: i64,
+        devices: &DeviceFilter,
     ) -> Result<Vec<TrackLog>> {
         let engine = DbEngine::get()?;
         let pool = engine.pool();
 
-        let rows: Vec<ScrobbleRow> = sqlx::query_as(
-            "SELECT * FROM scrobble WHERE userid = ? AND timestamp >= ? AND timestamp <= ? ORDER BY timestamp DESC"
-        )
+        let (only, except) = devices.bind_values()?;
+        let rows: Vec<ScrobbleRow> = sqlx::query_as(&format!(
+            "SELECT * FROM scrobble WHERE userid = ? AND {} AND timestamp >= ? AND timestamp <= ? \
+             ORDER BY timestamp DESC",
+            DEVICE_CLAUSE
+        ))
         .bind(userid)
+        .bind(&only)
+        .bind(&only)
+        .bind(except)
         .bind(start_time)
         .bind(end_time)
         .fetch_all(pool)
@@ -170,6 +351,92 @@ impl ScrobbleTable {
         Ok(rows.into_iter().map(|r| r.into_track_log()).collect())
     }
 
+    /// A page of a user's history, newest first, starting below `before`
+    ///
+    /// `before` is the (timestamp, id) of the last play of the previous page so
+    /// pages stay put while new plays come in
+    pub async fn history_page(
+        userid: i64,
+        devices: &DeviceFilter,
+        start_time: i64,
+        end_time: i64,
+        before: Option<(i64, i64)>,
+        limit: i64,
+    ) -> Result<Vec<TrackLog>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let (only, except) = devices.bind_values()?;
+        let (before_time, before_id) = before.unwrap_or((i64::MAX, i64::MAX));
+        let rows: Vec<ScrobbleRow> = sqlx::query_as(&format!(
+            "SELECT * FROM scrobble WHERE userid = ? AND {} AND timestamp >= ? AND timestamp <= ? \
+             AND (timestamp < ? OR (timestamp = ? AND id < ?)) \
+             ORDER BY timestamp DESC, id DESC LIMIT ?",
+            DEVICE_CLAUSE
+        ))
+        .bind(userid)
+        .bind(&only)
+        .bind(&only)
+        .bind(except)
+        .bind(start_time)
+        .bind(end_time)
+        .bind(before_time)
+        .bind(before_time)
+        .bind(before_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|r| r.into_track_log()).collect())
+    }
+
+    /// Delete one play of a user, returning it when it existed
+    pub async fn delete(userid: i64, id: i64) -> Result<Option<TrackLog>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let row: Option<ScrobbleRow> =
+            sqlx::query_as("DELETE FROM scrobble WHERE id = ? AND userid = ? RETURNING *")
+                .bind(id)
+                .bind(userid)
+                .fetch_optional(pool)
+                .await?;
+        if row.is_some() {
+            Self::mark_changed();
+        }
+
+        Ok(row.map(|r| r.into_track_log()))
+    }
+
+    /// Plays of a track across every user
+    pub async fn track_playcount(trackhash: &str) -> Result<i64> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let row: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM scrobble WHERE trackhash = ?")
+            .bind(trackhash)
+            .fetch_one(pool)
+            .await?;
+
+        Ok(row.0)
+    }
+
+    /// Devices a user has played on, most recently used first
+    pub async fn devices(userid: i64) -> Result<Vec<DevicePlays>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows: Vec<DevicePlays> = sqlx::query_as(
+            "SELECT device, COUNT(*) AS plays, MAX(timestamp) AS last_played \
+             FROM scrobble WHERE userid = ? GROUP BY device ORDER BY last_played DESC",
+        )
+        .bind(userid)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
     /// Get all scrobbles for default user (compat wrapper)
     pub async fn get_all() -> Result<Vec<TrackLog>> {
         Self::all(0).await
@@ -233,3 +500,23 @@ impl ScrobbleTable {
         Ok(row.0.unwrap_or(0))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_filter_parse() {
+        let filter = DeviceFilter::parse(Some(" phone, -kitchen,,laptop ,-"));
+        assert_eq!(filter.only, vec!["phone", "laptop"]);
+        assert_eq!(filter.except, vec!["kitchen", ""]);
+        assert!(filter.allows("phone"));
+        assert!(!filter.allows("kitchen"));
+        assert!(!filter.allows("tv"));
+        assert!(DeviceFilter::parse(None).is_empty());
+
+        let mixes = DeviceFilter::default().excluding(vec!["kitchen".to_string()]);
+        assert!(mixes.allows(""));
+        assert!(!mixes.allows("kitchen"));
+    }
+}
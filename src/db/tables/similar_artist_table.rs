@@ -19,6 +19,9 @@ pub struct SimilarArtistData {
     pub scrobbles: i64,
     #[serde(default)]
     pub listeners: i64,
+    /// computed from the local library rather than fetched from last.fm
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub local: bool,
 }
 
 /// similar artist table operations
@@ -0,0 +1,90 @@
+//! Album thumbnail source table operations
+
+use anyhow::Result;
+use sqlx::FromRow;
+
+use crate::db::DbEngine;
+
+/// Art an album's thumbnails were built from
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+pub struct ThumbnailSource {
+    pub albumhash: String,
+    /// hash of the art bytes, names the thumbnail files
+    pub contenthash: String,
+    /// audio file with embedded art or the image file the art was read from
+    pub source: String,
+    /// modification time of the source in seconds
+    pub source_mtime: i64,
+    pub source_len: i64,
+}
+
+/// Thumbnail source table operations
+pub struct ThumbnailTable;
+
+impl ThumbnailTable {
+    /// Get every recorded thumbnail source
+    pub async fn all() -> Result<Vec<ThumbnailSource>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows = sqlx::query_as("SELECT * FROM thumbnail")
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows)
+    }
+
+    /// Insert or replace thumbnail sources in a single transaction
+    pub async fn upsert_many(sources: &[ThumbnailSource]) -> Result<()> {
+        if sources.is_empty() {
+            return Ok(());
+        }
+
+        let engine = DbEngine::get()?;
+        let mut tx = engine.pool().begin().await?;
+
+        for source in sources {
+            sqlx::query(
+                r#"
+                INSERT INTO thumbnail (albumhash, contenthash, source, source_mtime, source_len)
+                VALUES (?, ?, ?, ?, ?)
+                ON CONFLICT(albumhash) DO UPDATE SET
+                    contenthash = excluded.contenthash,
+                    source = excluded.source,
+                    source_mtime = excluded.source_mtime,
+                    source_len = excluded.source_len
+                "#,
+            )
+            .bind(&source.albumhash)
+            .bind(&source.contenthash)
+            .bind(&source.source)
+            .bind(source.source_mtime)
+            .bind(source.source_len)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Forget the sources of albums no longer in the library
+    pub async fn delete_many(albumhashes: &[String]) -> Result<()> {
+        if albumhashes.is_empty() {
+            return Ok(());
+        }
+
+        let engine = DbEngine::get()?;
+        let mut tx = engine.pool().begin().await?;
+
+        for albumhash in albumhashes {
+            sqlx::query("DELETE FROM thumbnail WHERE albumhash = ?")
+                .bind(albumhash)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+//! Saved track playback positions
+
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::FromRow;
+
+use crate::db::DbEngine;
+
+/// Where a user stopped playing a track
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct TrackPosition {
+    pub trackhash: String,
+    /// seconds into the track
+    pub position: i64,
+    pub updated: i64,
+}
+
+/// Track position table operations
+pub struct TrackPositionTable;
+
+impl TrackPositionTable {
+    /// Get the saved position of a track
+    pub async fn get(userid: i64, trackhash: &str) -> Result<Option<TrackPosition>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let row: Option<TrackPosition> = sqlx::query_as(
+            "SELECT trackhash, position, updated FROM track_position \
+             WHERE userid = ? AND trackhash = ?",
+        )
+        .bind(userid)
+        .bind(trackhash)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Get every saved position of a user, most recently updated first
+    pub async fn all(userid: i64) -> Result<Vec<TrackPosition>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows: Vec<TrackPosition> = sqlx::query_as(
+            "SELECT trackhash, position, updated FROM track_position \
+             WHERE userid = ? ORDER BY updated DESC",
+        )
+        .bind(userid)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Save the position of a track
+    pub async fn set(userid: i64, trackhash: &str, position: i64, updated: i64) -> Result<()> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        sqlx::query(
+            r#"
+            INSERT INTO track_position (userid, trackhash, position, updated)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT(userid, trackhash) DO UPDATE SET
+                position = excluded.position,
+                updated = excluded.updated
+            "#,
+        )
+        .bind(userid)
+        .bind(trackhash)
+        .bind(position)
+        .bind(updated)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Forget the position of a track
+    pub async fn delete(userid: i64, trackhash: &str) -> Result<()> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        sqlx::query("DELETE FROM track_position WHERE userid = ? AND trackhash = ?")
+            .bind(userid)
+            .bind(trackhash)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -6,6 +6,9 @@ use sqlx::FromRow;
 use crate::db::DbEngine;
 use crate::models::{ArtistRefItem, GenreRef, Track};
 
+/// keeps `IN (...)` lists below sqlite's bound parameter limit
+const FILEPATH_CHUNK_SIZE: usize = 500;
+
 /// Database row for track table
 #[derive(Debug, FromRow)]
 struct TrackRow {
@@ -30,6 +33,7 @@ struct TrackRow {
     playcount: i32,
     playduration: i32,
     extra: String,
+    has_lyrics: bool,
 }
 
 impl TrackRow {
@@ -52,7 +56,7 @@ impl TrackRow {
         let og_album = self.album.clone();
         let og_title = self.title.clone();
 
-        Track {
+        let mut track = Track {
             id: self.id,
             album: self.album,
             albumartists,
@@ -75,6 +79,8 @@ impl TrackRow {
             lastplayed: self.lastplayed,
             playcount: self.playcount,
             playduration: self.playduration,
+            has_lyrics: self.has_lyrics,
+            rating: 0,
             og_album,
             og_title,
             artisthashes,
@@ -86,7 +92,10 @@ impl TrackRow {
             score: 0.0,
             explicit: false,
             fav_userids: Default::default(),
-        }
+            mbid: String::new(),
+        };
+        track.rating = track.tag_rating();
+        track
     }
 }
 
@@ -94,12 +103,12 @@ impl TrackRow {
 pub struct TrackTable;
 
 impl TrackTable {
-    /// Get all tracks
+    /// Get all tracks, leaving out soft removed ones
     pub async fn all() -> Result<Vec<Track>> {
         let engine = DbEngine::get()?;
         let pool = engine.pool();
 
-        let rows: Vec<TrackRow> = sqlx::query_as("SELECT * FROM track")
+        let rows: Vec<TrackRow> = sqlx::query_as("SELECT * FROM track WHERE removed_at IS NULL")
             .fetch_all(pool)
             .await?;
 
@@ -109,8 +118,13 @@ impl TrackTable {
     /// Insert a single track
     pub async fn insert_one(track: &Track) -> Result<i64> {
         let engine = DbEngine::get()?;
-        let pool = engine.pool();
+        Self::insert_with(engine.pool(), track).await
+    }
 
+    async fn insert_with<'e, E>(executor: E, track: &Track) -> Result<i64>
+    where
+        E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+    {
         let albumartists = serde_json::to_string(&track.albumartists)?;
         let artists = serde_json::to_string(&track.artists)?;
         let genres = serde_json::to_string(&track.genres)?;
@@ -121,8 +135,9 @@ impl TrackTable {
             INSERT INTO track (
                 album, albumartists, albumhash, artists, bitrate, copyright,
                 date, disc, duration, filepath, folder, genres, last_mod,
-                title, track, trackhash, lastplayed, playcount, playduration, extra
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                title, track, trackhash, lastplayed, playcount, playduration, extra,
+                has_lyrics
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#,
         )
         .bind(&track.album)
@@ -145,17 +160,35 @@ impl TrackTable {
         .bind(track.playcount)
         .bind(track.playduration)
         .bind(&extra)
-        .execute(pool)
+        .bind(track.has_lyrics)
+        .execute(executor)
         .await?;
 
         Ok(result.last_insert_rowid())
     }
 
-    /// Insert multiple tracks
+    /// Insert multiple tracks in a single transaction
     pub async fn insert_many(tracks: &[Track]) -> Result<()> {
+        Self::replace_many(&[], tracks).await
+    }
+
+    /// Remove tracks by file path and insert replacements in a single transaction
+    pub async fn replace_many(remove_filepaths: &[String], tracks: &[Track]) -> Result<()> {
+        if remove_filepaths.is_empty() && tracks.is_empty() {
+            return Ok(());
+        }
+
+        let engine = DbEngine::get()?;
+        let mut tx = engine.pool().begin().await?;
+
+        for chunk in remove_filepaths.chunks(FILEPATH_CHUNK_SIZE) {
+            Self::delete_filepaths(&mut *tx, chunk).await?;
+        }
         for track in tracks {
-            Self::insert_one(track).await?;
+            Self::insert_with(&mut *tx, track).await?;
         }
+
+        tx.commit().await?;
         Ok(())
     }
 
@@ -169,7 +202,10 @@ impl TrackTable {
         let pool = engine.pool();
 
         let placeholders: String = filepaths.iter().map(|_| "?").collect::<Vec<_>>().join(",");
-        let query = format!("SELECT * FROM track WHERE filepath IN ({})", placeholders);
+        let query = format!(
+            "SELECT * FROM track WHERE removed_at IS NULL AND filepath IN ({})",
+            placeholders
+        );
 
         let mut query_builder = sqlx::query_as::<_, TrackRow>(&query);
         for path in filepaths {
@@ -186,10 +222,11 @@ impl TrackTable {
         let pool = engine.pool();
 
         let pattern = format!("{}%", path);
-        let rows: Vec<TrackRow> = sqlx::query_as("SELECT * FROM track WHERE filepath LIKE ?")
-            .bind(&pattern)
-            .fetch_all(pool)
-            .await?;
+        let rows: Vec<TrackRow> =
+            sqlx::query_as("SELECT * FROM track WHERE removed_at IS NULL AND filepath LIKE ?")
+                .bind(&pattern)
+                .fetch_all(pool)
+                .await?;
 
         Ok(rows.into_iter().map(|r| r.into_track()).collect())
     }
@@ -201,8 +238,21 @@ impl TrackTable {
         }
 
         let engine = DbEngine::get()?;
-        let pool = engine.pool();
+        let mut tx = engine.pool().begin().await?;
 
+        let mut removed = 0;
+        for chunk in filepaths.chunks(FILEPATH_CHUNK_SIZE) {
+            removed += Self::delete_filepaths(&mut *tx, chunk).await?;
+        }
+
+        tx.commit().await?;
+        Ok(removed)
+    }
+
+    async fn delete_filepaths<'e, E>(executor: E, filepaths: &[String]) -> Result<u64>
+    where
+        E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+    {
         let placeholders: String = filepaths.iter().map(|_| "?").collect::<Vec<_>>().join(",");
         let query = format!("DELETE FROM track WHERE filepath IN ({})", placeholders);
 
@@ -211,10 +261,102 @@ impl TrackTable {
             query_builder = query_builder.bind(path);
         }
 
-        let result = query_builder.execute(pool).await?;
+        let result = query_builder.execute(executor).await?;
         Ok(result.rows_affected())
     }
 
+    /// Point tracks at the files they were moved to in a single transaction
+    ///
+    /// each move is `(from, to)`, the analysis results kept per file path
+    /// follow the file so they are not computed again
+    pub async fn move_filepaths(moves: &[(String, String)]) -> Result<u64> {
+        if moves.is_empty() {
+            return Ok(0);
+        }
+
+        let engine = DbEngine::get()?;
+        let mut tx = engine.pool().begin().await?;
+
+        let mut moved = 0;
+        for (from, to) in moves {
+            let folder = std::path::Path::new(to)
+                .parent()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default();
+            moved += sqlx::query("UPDATE track SET filepath = ?, folder = ? WHERE filepath = ?")
+                .bind(to)
+                .bind(&folder)
+                .bind(from)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+
+            for table in ["fingerprint", "gapless", "lossless_check"] {
+                sqlx::query(&format!(
+                    "UPDATE OR REPLACE {} SET filepath = ? WHERE filepath = ?",
+                    table
+                ))
+                .bind(to)
+                .bind(from)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(moved)
+    }
+
+    /// Hide tracks by file path, keeping their rows and play stats
+    pub async fn soft_remove_by_filepaths(filepaths: &[String]) -> Result<u64> {
+        Self::set_removed(filepaths, Some(chrono::Utc::now().timestamp())).await
+    }
+
+    /// Bring back soft removed tracks by file path
+    pub async fn restore_by_filepaths(filepaths: &[String]) -> Result<u64> {
+        Self::set_removed(filepaths, None).await
+    }
+
+    /// File paths of soft removed tracks
+    pub async fn removed_filepaths() -> Result<Vec<String>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let paths: Vec<String> =
+            sqlx::query_scalar("SELECT filepath FROM track WHERE removed_at IS NOT NULL")
+                .fetch_all(pool)
+                .await?;
+
+        Ok(paths)
+    }
+
+    async fn set_removed(filepaths: &[String], removed_at: Option<i64>) -> Result<u64> {
+        if filepaths.is_empty() {
+            return Ok(0);
+        }
+
+        let engine = DbEngine::get()?;
+        let mut tx = engine.pool().begin().await?;
+
+        let mut changed = 0;
+        for chunk in filepaths.chunks(FILEPATH_CHUNK_SIZE) {
+            let placeholders: String = chunk.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+            let query = format!(
+                "UPDATE track SET removed_at = ? WHERE filepath IN ({})",
+                placeholders
+            );
+
+            let mut query_builder = sqlx::query(&query).bind(removed_at);
+            for path in chunk {
+                query_builder = query_builder.bind(path);
+            }
+            changed += query_builder.execute(&mut *tx).await?.rows_affected();
+        }
+
+        tx.commit().await?;
+        Ok(changed)
+    }
+
     /// Update play statistics for a track
     pub async fn update_play_stats(
         trackhash: &str,
@@ -238,12 +380,48 @@ impl TrackTable {
         Ok(())
     }
 
+    /// Set whether a track has local lyrics
+    pub async fn set_has_lyrics(trackhash: &str, has_lyrics: bool) -> Result<()> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        sqlx::query("UPDATE track SET has_lyrics = ? WHERE trackhash = ?")
+            .bind(has_lyrics)
+            .bind(trackhash)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Rewrite the artists and album artists of tracks, matched by file path
+    pub async fn update_artists(tracks: &[Track]) -> Result<()> {
+        if tracks.is_empty() {
+            return Ok(());
+        }
+
+        let engine = DbEngine::get()?;
+        let mut tx = engine.pool().begin().await?;
+
+        for track in tracks {
+            sqlx::query("UPDATE track SET artists = ?, albumartists = ? WHERE filepath = ?")
+                .bind(serde_json::to_string(&track.artists)?)
+                .bind(serde_json::to_string(&track.albumartists)?)
+                .bind(&track.filepath)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
     /// Get track count
     pub async fn count() -> Result<i64> {
         let engine = DbEngine::get()?;
         let pool = engine.pool();
 
-        let row: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM track")
+        let row: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM track WHERE removed_at IS NULL")
             .fetch_one(pool)
             .await?;
 
@@ -0,0 +1,106 @@
+//! Trash table operations
+
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::FromRow;
+
+use crate::db::DbEngine;
+
+/// A deleted track waiting in the trash
+#[derive(Debug, Clone, PartialEq, Serialize, FromRow)]
+pub struct TrashedTrack {
+    pub id: i64,
+    pub trackhash: String,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    /// where the file is restored to
+    pub filepath: String,
+    /// where the file is kept until it is purged
+    #[serde(skip)]
+    pub trashpath: String,
+    /// user who deleted the track
+    pub userid: i64,
+    pub deleted_at: i64,
+}
+
+/// Trash table operations
+pub struct TrashTable;
+
+impl TrashTable {
+    /// Get every trashed track, most recently deleted first
+    pub async fn all() -> Result<Vec<TrashedTrack>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows = sqlx::query_as("SELECT * FROM trash ORDER BY deleted_at DESC, id DESC")
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows)
+    }
+
+    /// Get a trashed track by id
+    pub async fn get(id: i64) -> Result<Option<TrashedTrack>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let row = sqlx::query_as("SELECT * FROM trash WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(row)
+    }
+
+    /// Trashed tracks deleted before a timestamp
+    pub async fn deleted_before(timestamp: i64) -> Result<Vec<TrashedTrack>> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let rows = sqlx::query_as("SELECT * FROM trash WHERE deleted_at < ?")
+            .bind(timestamp)
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows)
+    }
+
+    /// Insert a trashed track, returns its id
+    pub async fn insert(track: &TrashedTrack) -> Result<i64> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO trash (trackhash, title, artist, album, filepath, trashpath, userid, deleted_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&track.trackhash)
+        .bind(&track.title)
+        .bind(&track.artist)
+        .bind(&track.album)
+        .bind(&track.filepath)
+        .bind(&track.trashpath)
+        .bind(track.userid)
+        .bind(track.deleted_at)
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Delete a trashed track, returns whether it existed
+    pub async fn delete(id: i64) -> Result<bool> {
+        let engine = DbEngine::get()?;
+        let pool = engine.pool();
+
+        let result = sqlx::query("DELETE FROM trash WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
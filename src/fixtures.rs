@@ -0,0 +1,255 @@
+//! Synthetic library fixtures for benchmarks
+//!
+//! builds a deterministic library of any size and loads it into the stores the
+//! same way startup does, so store queries, search and serialization can be
+//! measured without scanning real files.
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::HashSet;
+
+use crate::core::{AlbumLib, ArtistLib};
+use crate::models::{ArtistRefItem, GenreRef, Track};
+use crate::stores::{AlbumStore, ArtistStore, FolderStore, TrackStore};
+use crate::utils::hashing::{create_hash, create_track_hash};
+
+/// Words titles and names are built from, also useful as search queries
+pub const WORDS: &[&str] = &[
+    "midnight",
+    "velvet",
+    "harbor",
+    "echo",
+    "golden",
+    "static",
+    "river",
+    "neon",
+    "paper",
+    "hollow",
+    "summer",
+    "winter",
+    "signal",
+    "glass",
+    "wild",
+    "silver",
+    "ocean",
+    "desert",
+    "electric",
+    "quiet",
+    "broken",
+    "crystal",
+    "shadow",
+    "morning",
+    "fever",
+    "garden",
+    "thunder",
+    "lantern",
+    "satellite",
+    "autumn",
+    "marble",
+    "violet",
+    "canyon",
+    "northern",
+    "tide",
+    "ember",
+    "phantom",
+    "orbit",
+    "willow",
+    "cascade",
+];
+
+const GENRES: &[&str] = &[
+    "Rock",
+    "Pop",
+    "Jazz",
+    "Electronic",
+    "Hip Hop",
+    "Folk",
+    "Classical",
+    "Soul",
+    "Metal",
+    "Ambient",
+    "Indie",
+    "Blues",
+];
+
+/// Root folder of the generated file paths
+pub const ROOT_DIR: &str = "/music";
+
+/// Shape of a generated library
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LibrarySize {
+    pub artists: usize,
+    pub albums: usize,
+    pub tracks: usize,
+}
+
+impl LibrarySize {
+    /// A library shaped like a typical collection, ten tracks per album and
+    /// four albums per artist
+    pub fn tracks(tracks: usize) -> Self {
+        let albums = (tracks / 10).max(1);
+        Self {
+            artists: (albums / 4).max(1),
+            albums,
+            tracks,
+        }
+    }
+}
+
+struct AlbumSpec {
+    title: String,
+    artist: ArtistRefItem,
+    folder: String,
+    year: i32,
+    genre: GenreRef,
+}
+
+fn words(rng: &mut StdRng, count: usize) -> String {
+    (0..count)
+        .map(|_| {
+            let word = WORDS.choose(rng).copied().unwrap_or("echo");
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn artist_ref(name: String) -> ArtistRefItem {
+    let artisthash = create_hash(&[&name], true);
+    ArtistRefItem::new(name, artisthash)
+}
+
+fn year_timestamp(year: i32) -> i64 {
+    chrono::NaiveDate::from_ymd_opt(year, 1, 1)
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|d| d.and_utc().timestamp())
+        .unwrap_or(0)
+}
+
+/// Generate tracks for a library, the same seed always gives the same library
+pub fn generate_tracks(size: LibrarySize, seed: u64) -> Vec<Track> {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    // the index keeps names unique, real libraries rarely repeat an artist
+    let artists: Vec<ArtistRefItem> = (0..size.artists.max(1))
+        .map(|i| artist_ref(format!("{} {}", words(&mut rng, 2), i + 1)))
+        .collect();
+
+    let albums: Vec<AlbumSpec> = (0..size.albums.max(1))
+        .map(|i| {
+            let artist = artists[i % artists.len()].clone();
+            let count = rng.gen_range(1..=3);
+            let title = format!("{} {}", words(&mut rng, count), i + 1);
+            let genre = GENRES.choose(&mut rng).copied().unwrap_or("Rock");
+            AlbumSpec {
+                folder: format!("{}/{}/{}", ROOT_DIR, artist.name, title),
+                title,
+                artist,
+                year: rng.gen_range(1965..=2025),
+                genre: GenreRef::new(genre.to_string(), create_hash(&[genre], true)),
+            }
+        })
+        .collect();
+
+    let mut hashes = HashSet::with_capacity(size.tracks);
+    let mut tracks = Vec::with_capacity(size.tracks);
+
+    for i in 0..size.tracks {
+        let album = &albums[i % albums.len()];
+        let number = (i / albums.len()) as i32 + 1;
+
+        let mut artists_on_track = vec![album.artist.clone()];
+        if rng.gen_bool(0.15) {
+            let featured = artists
+                .choose(&mut rng)
+                .cloned()
+                .unwrap_or_else(|| album.artist.clone());
+            if featured.artisthash != album.artist.artisthash {
+                artists_on_track.push(featured);
+            }
+        }
+        let artist_names: Vec<&str> = artists_on_track.iter().map(|a| a.name.as_str()).collect();
+
+        // reroll the rare title that repeats within an album
+        let (title, trackhash) = loop {
+            let count = rng.gen_range(1..=4);
+            let title = words(&mut rng, count);
+            let hash = create_track_hash(&artist_names.join(", "), &album.title, &title);
+            if hashes.insert(hash.clone()) {
+                break (title, hash);
+            }
+        };
+
+        let mut track = Track::new();
+        track.id = i as i64 + 1;
+        track.filepath = format!("{}/{:02} {}.flac", album.folder, number, title);
+        track.folder = album.folder.clone();
+        track.album = album.title.clone();
+        track.og_album = album.title.clone();
+        track.og_title = title.clone();
+        track.weakhash = create_hash(&[&album.title, &title], true);
+        track.title = title;
+        track.trackhash = trackhash;
+        track.albumhash = create_hash(&[&album.title, &album.artist.name], true);
+        track.artists = artists_on_track;
+        track.albumartists = vec![album.artist.clone()];
+        track.compute_artisthashes();
+        track.genres = vec![album.genre.clone()];
+        track.compute_genrehashes();
+        track.track = number;
+        track.duration = rng.gen_range(90..=480);
+        track.bitrate = *[320, 1011, 1411].choose(&mut rng).unwrap_or(&320);
+        track.date = year_timestamp(album.year);
+        track.last_mod = track.date;
+        track.playcount = rng.gen_range(0..50);
+        tracks.push(track);
+    }
+
+    tracks
+}
+
+/// Load tracks into the stores the way startup does, albums and artists are
+/// derived from the tracks
+pub fn load_library(tracks: Vec<Track>) {
+    let folders: Vec<String> = tracks.iter().map(|t| t.folder.clone()).collect();
+
+    TrackStore::get().load(tracks);
+    let tracks = TrackStore::get().get_all();
+    AlbumStore::get().load(AlbumLib::build_albums(&tracks));
+    ArtistStore::get().load(ArtistLib::build_artists(&tracks));
+
+    let roots = vec![ROOT_DIR.to_string()];
+    FolderStore::get().set_root_dirs(roots.clone());
+    FolderStore::get().load_from_paths(folders, &roots);
+}
+
+/// Generate a library and load it into the stores
+pub fn load_synthetic_library(size: LibrarySize, seed: u64) {
+    load_library(generate_tracks(size, seed));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_tracks() {
+        let size = LibrarySize::tracks(200);
+        let tracks = generate_tracks(size, 7);
+        assert_eq!(tracks.len(), 200);
+
+        let albums: HashSet<&str> = tracks.iter().map(|t| t.albumhash.as_str()).collect();
+        assert_eq!(albums.len(), size.albums);
+
+        let again = generate_tracks(size, 7);
+        assert!(tracks
+            .iter()
+            .zip(&again)
+            .all(|(a, b)| a.trackhash == b.trackhash && a.filepath == b.filepath));
+    }
+}
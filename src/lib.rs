@@ -0,0 +1,22 @@
+//! SwingMusic - A beautiful, self-hosted music player for your local audio files
+//!
+//! The server binary lives in `main.rs`, the modules are exposed here so
+//! benchmarks can drive the stores, search and serializers directly.
+
+#![allow(dead_code)]
+#![allow(unused_variables)]
+// exported methods named from_str predate the lib target and mirror upstream
+#![allow(clippy::should_implement_trait)]
+
+pub mod api;
+pub mod config;
+pub mod core;
+pub mod db;
+#[cfg(feature = "bench")]
+pub mod fixtures;
+pub mod models;
+pub mod plugins;
+pub mod serializers;
+pub mod service;
+pub mod stores;
+pub mod utils;
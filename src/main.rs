@@ -5,21 +5,15 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
 
-mod api;
-mod config;
-mod core;
-mod db;
-mod models;
-mod plugins;
-mod serializers;
-mod stores;
-mod utils;
-
 use anyhow::Result;
 use clap::Parser;
+use std::ffi::OsString;
 use std::path::PathBuf;
 use tracing::info;
 
+use swingmusic::service::StopSignal;
+use swingmusic::{api, config, core, db, plugins, service, stores, utils};
+
 /// SwingMusic - Self-hosted music player
 #[derive(Parser, Debug)]
 #[command(name = "swingmusic")]
@@ -54,12 +48,75 @@ struct Args {
     /// Reset password for a user
     #[arg(long)]
     password_reset: bool,
+
+    /// Report pending database migrations without applying them
+    #[arg(long)]
+    migrate_dry_run: bool,
+
+    /// Install and start a Windows service running with the other flags given
+    #[arg(long)]
+    install_service: bool,
+
+    /// Stop and remove the Windows service
+    #[arg(long)]
+    uninstall_service: bool,
+
+    /// Run under the Windows service manager, set by --install-service
+    #[arg(long, hide = true)]
+    run_as_service: bool,
+}
+
+impl Args {
+    /// Server flags the installed service is launched with
+    ///
+    /// paths are made absolute since services start in the system directory
+    fn service_arguments(&self) -> Result<Vec<OsString>> {
+        let mut arguments: Vec<OsString> = vec![
+            "--host".into(),
+            self.host.clone().into(),
+            "--port".into(),
+            self.port.to_string().into(),
+        ];
+        if self.debug {
+            arguments.push("--debug".into());
+        }
+        if let Some(config) = &self.config {
+            arguments.push("--config".into());
+            arguments.push(std::path::absolute(config)?.into());
+        }
+        if let Some(client) = &self.client {
+            arguments.push("--client".into());
+            arguments.push(std::path::absolute(client)?.into());
+        }
+        Ok(arguments)
+    }
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     let args = Args::parse();
 
+    if args.install_service {
+        return service::install(args.service_arguments()?);
+    }
+    if args.uninstall_service {
+        return service::uninstall();
+    }
+    if args.run_as_service {
+        return service::run_as_service(move |stop| run(args, Some(stop)));
+    }
+
+    run(args, None)
+}
+
+/// Build the async runtime and run the server until it shuts down
+fn run(args: Args, stop: Option<StopSignal>) -> Result<()> {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(serve(args, stop))
+}
+
+async fn serve(args: Args, stop: Option<StopSignal>) -> Result<()> {
     // initialize logging with filters to suppress noisy dependency warnings
     let log_level = if args.debug { "debug" } else { "info" };
 
@@ -89,11 +146,21 @@ async fn main() -> Result<()> {
         return utils::tools::password_reset().await;
     }
 
+    if args.migrate_dry_run {
+        print!("{}", db::migration_dry_run().await?);
+        return Ok(());
+    }
+
     // Setup and run
-    start_swingmusic(args.host, args.port, args.setup_config).await
+    start_swingmusic(args.host, args.port, args.setup_config, stop).await
 }
 
-async fn start_swingmusic(host: String, port: u16, setup_config: Option<PathBuf>) -> Result<()> {
+async fn start_swingmusic(
+    host: String,
+    port: u16,
+    setup_config: Option<PathBuf>,
+    stop: Option<StopSignal>,
+) -> Result<()> {
     // Run setup
     info!("Running setup...");
     run_setup(setup_config).await?;
@@ -133,6 +200,11 @@ async fn start_swingmusic(host: String, port: u16, setup_config: Option<PathBuf>
         }
     }
 
+    // scans apply the artist split rules so load them first
+    if let Err(e) = core::artist_split::load().await {
+        tracing::warn!("Failed to load artist splits: {}", e);
+    }
+
     tokio::spawn(async {
         if let Err(e) = maybe_run_initial_scan().await {
             tracing::error!("Initial scan error: {}", e);
@@ -142,22 +214,21 @@ async fn start_swingmusic(host: String, port: u16, setup_config: Option<PathBuf>
     // Build the application
     info!("Building application...");
 
-    // Load data into memory
-    info!("Loading data into memory...");
-    load_into_memory().await?;
-
-    // Start background tasks
-    info!("Starting background tasks...");
-    start_background_tasks().await?;
-
-    // Start the server
-    let addr = format!("{}:{}", host, port);
-    info!("Server listening on http://{}", addr);
+    // Sockets bound by the service manager replace --host and --port
+    let listeners = service::inherited_listeners()?;
+    let port = listeners
+        .first()
+        .and_then(|l| l.local_addr().ok())
+        .map_or(port, |addr| addr.port());
 
     use actix_cors::Cors;
-    use actix_web::{middleware, App, HttpServer};
+    use actix_web::{middleware, web, App, HttpServer};
+    use std::time::Duration;
+
+    let tuning = config::UserConfig::load()?.server.with_env().normalized();
+    info!("Server tuning: {:?}", tuning);
 
-    HttpServer::new(|| {
+    let mut server = HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
             .allow_any_method()
@@ -165,18 +236,116 @@ async fn start_swingmusic(host: String, port: u16, setup_config: Option<PathBuf>
             .max_age(3600);
 
         App::new()
+            .app_data(web::JsonConfig::default().limit(tuning.max_json_payload))
+            .app_data(web::PayloadConfig::new(tuning.max_payload))
             .wrap(cors)
             .wrap(middleware::Logger::default())
             .wrap(middleware::Compress::default())
             .configure(api::configure)
     })
-    .bind(addr)?
-    .run()
-    .await?;
+    .keep_alive(Duration::from_secs(tuning.keep_alive))
+    .client_request_timeout(Duration::from_millis(tuning.client_request_timeout))
+    .client_disconnect_timeout(Duration::from_millis(tuning.client_disconnect_timeout))
+    .max_connections(tuning.max_connections)
+    .backlog(tuning.backlog)
+    .shutdown_timeout(tuning.shutdown_timeout)
+    .disable_signals();
+    if tuning.workers > 0 {
+        server = server.workers(tuning.workers);
+    }
+
+    // Start the server
+    if listeners.is_empty() {
+        let addr = format!("{}:{}", host, port);
+        info!("Server listening on http://{}", addr);
+        server = if tuning.http2 {
+            server.bind_auto_h2c(addr)?
+        } else {
+            server.bind(addr)?
+        };
+    } else {
+        for listener in listeners {
+            info!(
+                "Server listening on http://{} (socket activation)",
+                listener.local_addr()?
+            );
+            server = if tuning.http2 {
+                server.listen_auto_h2c(listener)?
+            } else {
+                server.listen(listener)?
+            };
+        }
+    }
+
+    // Load the stores once the sockets are bound so clients can follow the
+    // warm-up through /about instead of reading an empty library
+    tokio::spawn(async move {
+        info!("Loading data into memory...");
+        if let Err(e) = load_into_memory().await {
+            tracing::error!("Failed to load data into memory: {}", e);
+            return;
+        }
+
+        info!("Starting background tasks...");
+        if let Err(e) = start_background_tasks(port).await {
+            tracing::error!("Failed to start background tasks: {}", e);
+        }
+    });
+
+    let server = server.run();
+    let handle = server.handle();
+    tokio::spawn(async move {
+        shutdown_signal(stop).await;
+        info!("Shutting down...");
+        service::notify_stopping();
+        handle.stop(true).await;
+    });
+
+    service::notify_ready("Serving requests");
+    server.await?;
 
     Ok(())
 }
 
+/// Wait for ctrl-c, SIGTERM or a stop request from the service manager
+async fn shutdown_signal(stop: Option<StopSignal>) {
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    // a dropped sender is not a stop request
+    let service_stop = async {
+        let stopped = match stop {
+            Some(stop) => stop.await.is_ok(),
+            None => false,
+        };
+        if !stopped {
+            std::future::pending::<()>().await;
+        }
+    };
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+        _ = service_stop => {}
+    }
+}
+
 async fn run_setup(setup_config: Option<PathBuf>) -> Result<()> {
     use crate::config::UserConfig;
     use crate::db::{run_migrations, setup_sqlite, setup_userdata, UserTable};
@@ -224,7 +393,9 @@ async fn run_setup(setup_config: Option<PathBuf>) -> Result<()> {
 /// Run a one-time library scan on first startup so media is available immediately
 async fn maybe_run_initial_scan() -> Result<()> {
     use crate::config::UserConfig;
-    use crate::core::indexer::Indexer;
+    use crate::core::indexer::{
+        next_batch, Indexer, ScanChanges, ScanKind, ScanProgress, SCAN_BATCH_SIZE,
+    };
     use crate::db::tables::TrackTable;
 
     // Skip when tracks already exist (subsequent starts)
@@ -242,18 +413,56 @@ async fn maybe_run_initial_scan() -> Result<()> {
 
     info!("Running initial library scan...");
     let indexer = Indexer::from_config(&config).with_progress(false);
-    let tracks = indexer.index()?;
+    let Some(progress) = ScanProgress::begin(indexer.root_dirs(), ScanKind::Initial) else {
+        info!("A library scan is already running; skipping initial scan");
+        return Ok(());
+    };
+
+    let mut files = Vec::new();
+    for (root, found) in indexer.scan_roots().into_iter().enumerate() {
+        progress.set_found(root, found.files.len());
+        progress.add_queued(root, found.files.len());
+        progress.add_skipped(root, &found);
+        files.extend(found.files.into_iter().map(|path| (root, path)));
+    }
 
-    if tracks.is_empty() {
+    let mut stream = indexer.extract_streaming(files, Some(progress.handle()))?;
+    let mut indexed = 0usize;
+    loop {
+        let batch = next_batch(&mut stream.tracks, SCAN_BATCH_SIZE).await;
+        if batch.is_empty() {
+            break;
+        }
+
+        if let Err(e) = TrackTable::insert_many(&batch).await {
+            progress.fail(&e.to_string());
+            return Err(e);
+        }
+        indexed += batch.len();
+        progress.add_written(batch.len());
+    }
+    if let Err(e) = stream.finish().await {
+        progress.fail(&e.to_string());
+        return Err(e);
+    }
+    progress.set_changes(ScanChanges {
+        added: indexed,
+        total: indexed,
+        ..Default::default()
+    });
+    drop(progress);
+
+    if indexed == 0 {
         info!("Initial scan found no audio files in configured roots");
         return Ok(());
     }
 
-    TrackTable::insert_many(&tracks).await?;
-    info!("Initial scan indexed {} tracks", tracks.len());
+    info!("Initial scan indexed {} tracks", indexed);
 
     // Reload stores to make tracks available immediately
     load_into_memory().await?;
+    crate::core::fingerprint::spawn_pass();
+    crate::core::gapless::spawn_pass();
 
     Ok(())
 }
@@ -261,30 +470,67 @@ async fn maybe_run_initial_scan() -> Result<()> {
 async fn load_into_memory() -> Result<()> {
     use crate::core::images::{
         cache_album_images, download_artist_images, extract_album_colors, extract_artist_colors,
+        refresh_thumbnails,
+    };
+    use crate::core::mapstuff::{
+        map_colors, map_favorites, map_mbids, map_ratings, map_scrobble_data,
     };
-    use crate::core::mapstuff::{map_colors, map_favorites, map_scrobble_data};
+    use crate::stores::readiness::{mark_loaded, StoreKind};
     use crate::stores::{AlbumStore, ArtistStore, FolderStore, TrackStore};
 
-    // Load tracks
+    // Load tracks, library routes open once they are in
     info!("Loading tracks...");
     TrackStore::load_all_tracks().await?;
+    mark_loaded(StoreKind::Tracks);
 
     // Load albums
     info!("Loading albums...");
     AlbumStore::load_albums().await?;
+    mark_loaded(StoreKind::Albums);
 
     // Load artists
     info!("Loading artists...");
     ArtistStore::load_artists().await?;
+    mark_loaded(StoreKind::Artists);
 
     // Load folder paths
     info!("Loading folder paths...");
     FolderStore::load_filepaths().await?;
+    mark_loaded(StoreKind::Folders);
+
+    // Index playlist memberships
+    info!("Loading playlist memberships...");
+    let playlists = crate::db::tables::PlaylistTable::all(None).await?;
+    crate::stores::PlaylistMembershipStore::get().load(&playlists);
+    mark_loaded(StoreKind::Playlists);
+
+    // Map user data before the slow image work so favorites and play counts
+    // show up early
+    info!("Mapping favorites...");
+    map_favorites().await?;
+
+    info!("Mapping colors...");
+    map_colors().await?;
+
+    info!("Mapping MusicBrainz IDs...");
+    map_mbids().await?;
+
+    info!("Mapping scrobble data...");
+    map_scrobble_data().await?;
+
+    info!("Mapping track ratings...");
+    map_ratings().await?;
+    mark_loaded(StoreKind::Metadata);
 
     // Initialize file serving cache (for fast file lookups and http caching)
     info!("Initializing file serving cache...");
     crate::core::file_cache::init_file_cache().await?;
 
+    // Drop thumbnails built with outdated size or quality settings
+    if let Err(e) = refresh_thumbnails().await {
+        tracing::warn!("Failed to refresh thumbnails: {}", e);
+    }
+
     // Cache album images (extract from tracks)
     info!("Caching album images...");
     if let Ok(cached) = cache_album_images().await {
@@ -305,20 +551,10 @@ async fn load_into_memory() -> Result<()> {
     info!("Extracting artist colors...");
     let _ = extract_artist_colors().await;
 
-    // Map additional data
-    info!("Mapping favorites...");
-    map_favorites().await?;
-
-    info!("Mapping colors...");
-    map_colors().await?;
-
-    info!("Mapping scrobble data...");
-    map_scrobble_data().await?;
-
     Ok(())
 }
 
-async fn start_background_tasks() -> Result<()> {
+async fn start_background_tasks(port: u16) -> Result<()> {
     use crate::plugins::register_plugins;
 
     // Register plugins
@@ -331,12 +567,22 @@ async fn start_background_tasks() -> Result<()> {
         }
     });
 
-    // Start file watcher if enabled
+    // Fingerprint files added or changed while the server was down
+    crate::core::fingerprint::spawn_pass();
+    crate::core::gapless::spawn_pass();
+
+    // Log plays of clients that stream without calling the log endpoint
+    tokio::spawn(crate::core::playback::run_session_sweeper());
+
+    // Start a file watcher per root if enabled
     let config = crate::config::UserConfig::load()?;
-    if config.enable_watchdog {
-        tokio::spawn(async {
-            if let Err(e) = crate::core::watchdogg::start_watchdog().await {
-                tracing::error!("Watchdog error: {}", e);
+    crate::core::watchdogg::sync_watchers(&config);
+
+    // Start DLNA media server if enabled
+    if config.enable_dlna {
+        tokio::spawn(async move {
+            if let Err(e) = crate::core::dlna::start_dlna(port).await {
+                tracing::error!("DLNA server error: {}", e);
             }
         });
     }
@@ -60,6 +60,12 @@ pub struct Album {
     /// Dominant color from artwork
     #[serde(default)]
     pub color: String,
+    /// Dominant color adjusted for dark backgrounds
+    #[serde(default)]
+    pub color_dark: String,
+    /// Dominant color adjusted for light backgrounds
+    #[serde(default)]
+    pub color_light: String,
     /// Creation date (Unix timestamp)
     #[serde(default)]
     pub created_date: i64,
@@ -119,6 +125,9 @@ pub struct Album {
     /// Help text (for display)
     #[serde(skip_serializing_if = "String::is_empty", default)]
     pub help_text: String,
+    /// MusicBrainz release ID
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub mbid: String,
 }
 
 impl Album {
@@ -131,6 +140,8 @@ impl Album {
             artisthashes: Vec::new(),
             base_title: String::new(),
             color: String::new(),
+            color_dark: String::new(),
+            color_light: String::new(),
             created_date: 0,
             date: 0,
             duration: 0,
@@ -151,6 +162,7 @@ impl Album {
             fav_userids: HashSet::new(),
             weakhash: String::new(),
             help_text: String::new(),
+            mbid: String::new(),
         }
     }
 
@@ -211,11 +223,49 @@ impl Album {
         self.album_type = self.determine_type(tracks);
     }
 
+    /// Hashes of the albums grouped into this virtual singles album
+    pub fn grouped_albumhashes(&self) -> Vec<String> {
+        self.extra_hashes("grouped_singles")
+    }
+
+    /// Hashes of the editions merged into this album
+    pub fn merged_albumhashes(&self) -> Vec<String> {
+        self.extra_hashes("merged_albums")
+    }
+
+    /// Hashes of the albums a virtual album stands for, grouped singles or
+    /// merged editions
+    pub fn member_albumhashes(&self) -> Vec<String> {
+        let mut hashes = self.grouped_albumhashes();
+        hashes.extend(self.merged_albumhashes());
+        hashes
+    }
+
+    fn extra_hashes(&self, key: &str) -> Vec<String> {
+        self.extra
+            .get(key)
+            .and_then(|v| v.as_array())
+            .map(|hashes| {
+                hashes
+                    .iter()
+                    .filter_map(|h| h.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Whether this is a one track album that would be typed as a single
+    pub fn is_loose_single(&self, track: &Track) -> bool {
+        self.trackcount == 1
+            && !self.is_compilation()
+            && self.determine_type(std::slice::from_ref(track)) == AlbumType::Single
+    }
+
     /// Determine the album type
     fn determine_type(&self, tracks: &[Track]) -> AlbumType {
         let show_as_singles = UserConfig::global().read().show_albums_as_singles;
 
-        if self.is_single(tracks, show_as_singles) {
+        if !self.grouped_albumhashes().is_empty() || self.is_single(tracks, show_as_singles) {
             return AlbumType::Single;
         }
         if self.is_soundtrack() {
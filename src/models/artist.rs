@@ -51,6 +51,12 @@ pub struct Artist {
     /// Dominant color from image
     #[serde(default)]
     pub color: String,
+    /// Dominant color adjusted for dark backgrounds
+    #[serde(default)]
+    pub color_dark: String,
+    /// Dominant color adjusted for light backgrounds
+    #[serde(default)]
+    pub color_light: String,
     /// Image path
     #[serde(skip_serializing_if = "String::is_empty", default)]
     pub image: String,
@@ -63,6 +69,9 @@ pub struct Artist {
     /// Help text (for display)
     #[serde(skip_serializing_if = "String::is_empty", default)]
     pub help_text: String,
+    /// MusicBrainz artist ID
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub mbid: String,
 }
 
 impl Artist {
@@ -84,10 +93,13 @@ impl Artist {
             playduration: 0,
             extra: serde_json::Value::Null,
             color: String::new(),
+            color_dark: String::new(),
+            color_light: String::new(),
             image: String::new(),
             score: 0.0,
             fav_userids: HashSet::new(),
             help_text: String::new(),
+            mbid: String::new(),
         }
     }
 
@@ -0,0 +1,25 @@
+//! Collection models
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// An album or artist pinned to a collection
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct CollectionItem {
+    #[serde(rename = "type")]
+    pub item_type: String,
+    pub hash: String,
+    #[serde(default)]
+    pub help_text: Option<String>,
+}
+
+impl CollectionItem {
+    /// An album entry
+    pub fn album(albumhash: &str) -> Self {
+        Self {
+            item_type: "album".to_string(),
+            hash: albumhash.to_string(),
+            help_text: None,
+        }
+    }
+}
@@ -161,63 +161,6 @@ impl SortOrder {
     }
 }
 
-/// Track sort keys
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum TrackSortKey {
-    #[default]
-    Default,
-    Title,
-    Album,
-    Artists,
-    Duration,
-    Bitrate,
-    Date,
-    Playcount,
-    Lastplayed,
-    Filepath,
-}
-
-/// Album sort keys
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum AlbumSortKey {
-    #[default]
-    Title,
-    Artists,
-    Date,
-    Duration,
-    Trackcount,
-    Playcount,
-    Lastplayed,
-    CreatedDate,
-}
-
-/// Artist sort keys
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum ArtistSortKey {
-    #[default]
-    Name,
-    Trackcount,
-    Albumcount,
-    Duration,
-    Playcount,
-    Lastplayed,
-    CreatedDate,
-}
-
-/// Folder sort keys
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum FolderSortKey {
-    #[default]
-    Default,
-    Name,
-    Lastmod,
-    Trackcount,
-}
-
 /// Time period for statistics
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
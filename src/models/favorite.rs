@@ -1,9 +1,10 @@
 //! Favorite model
 
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 /// Favorite type
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum FavoriteType {
     Track,
@@ -2,6 +2,8 @@
 
 use serde::{Deserialize, Serialize};
 
+use super::ColorVariants;
+
 /// A mix (personalized playlist)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Mix {
@@ -103,6 +105,47 @@ impl Mix {
             extra,
         }
     }
+
+    /// Dominant collage color stored in extra
+    pub fn color(&self) -> Option<&str> {
+        self.extra
+            .get("color")
+            .and_then(|v| v.as_str())
+            .filter(|c| !c.is_empty())
+    }
+
+    /// Dark and light mode variants of the collage color stored in extra
+    pub fn color_variants(&self) -> Option<ColorVariants> {
+        let get = |key: &str| {
+            self.extra
+                .get(key)
+                .and_then(|v| v.as_str())
+                .filter(|c| !c.is_empty())
+                .map(str::to_string)
+        };
+        Some(ColorVariants {
+            dark: get("color_dark")?,
+            light: get("color_light")?,
+        })
+    }
+
+    /// Store the dominant collage color and its variants in extra
+    pub fn set_color(&mut self, color: String, variants: ColorVariants) {
+        if !self.extra.is_object() {
+            self.extra = serde_json::json!({});
+        }
+        if let Some(obj) = self.extra.as_object_mut() {
+            obj.insert("color".to_string(), serde_json::Value::String(color));
+            obj.insert(
+                "color_dark".to_string(),
+                serde_json::Value::String(variants.dark),
+            );
+            obj.insert(
+                "color_light".to_string(),
+                serde_json::Value::String(variants.light),
+            );
+        }
+    }
 }
 
 impl Default for Mix {
@@ -4,26 +4,32 @@
 
 mod album;
 mod artist;
+mod collection;
 mod enums;
 mod favorite;
 mod folder;
 mod lastfm;
 mod mix;
 mod playlist;
+mod radio;
 mod plugins;
+mod podcast;
 mod stats;
 mod track;
 mod user;
 
-pub use album::Album;
+pub use album::{Album, AlbumType};
 pub use artist::Artist;
+pub use collection::CollectionItem;
 pub use favorite::{Favorite, FavoriteType};
 pub use folder::Folder;
 pub use mix::Mix;
 pub use playlist::{Playlist, PlaylistSettings};
+pub use podcast::{EpisodeProgress, Podcast, PodcastEpisode};
+pub use radio::RadioStation;
 pub use stats::TrackLog;
-pub use track::Track;
-pub use user::{User, UserRole};
+pub use track::{Track, TrackExtra};
+pub use user::{Permission, User, UserRole};
 
 #[allow(unused_imports)]
 pub use artist::{ArtistRef, SimilarArtist, SimilarArtistEntry};
@@ -49,6 +55,13 @@ impl ArtistRefItem {
     }
 }
 
+/// A dominant color adjusted to stay readable on dark and light backgrounds
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ColorVariants {
+    pub dark: String,
+    pub light: String,
+}
+
 /// Reference to a genre
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct GenreRef {
@@ -43,6 +43,12 @@ pub struct Playlist {
     /// Owner user ID
     #[serde(default)]
     pub userid: Option<i64>,
+    /// Whether every user can see the playlist
+    #[serde(default)]
+    pub shared: bool,
+    /// Users other than the owner allowed to change the tracks
+    #[serde(default)]
+    pub collaborators: Vec<i64>,
     /// Thumbnail path (computed)
     #[serde(skip_serializing_if = "String::is_empty", default)]
     pub thumb: String,
@@ -64,6 +70,15 @@ pub struct Playlist {
     /// Is editable by current user
     #[serde(default)]
     pub is_editable: bool,
+    /// Dominant color of the custom image (computed)
+    #[serde(default)]
+    pub color: String,
+    /// Image color adjusted for dark backgrounds
+    #[serde(default)]
+    pub color_dark: String,
+    /// Image color adjusted for light backgrounds
+    #[serde(default)]
+    pub color_light: String,
 }
 
 impl Playlist {
@@ -78,6 +93,8 @@ impl Playlist {
             extra: serde_json::Value::Null,
             settings: PlaylistSettings::default(),
             userid,
+            shared: false,
+            collaborators: Vec::new(),
             thumb: String::new(),
             count: 0,
             duration: 0,
@@ -85,9 +102,27 @@ impl Playlist {
             has_image: false,
             images: Vec::new(),
             is_editable: false,
+            color: String::new(),
+            color_dark: String::new(),
+            color_light: String::new(),
         }
     }
 
+    /// Whether the user owns the playlist
+    pub fn is_owner(&self, userid: i64) -> bool {
+        self.userid == Some(userid)
+    }
+
+    /// Whether the user can change the tracks of the playlist
+    pub fn can_edit(&self, userid: i64) -> bool {
+        self.is_owner(userid) || self.collaborators.contains(&userid)
+    }
+
+    /// Whether the user can see the playlist
+    pub fn can_view(&self, userid: i64) -> bool {
+        self.shared || self.can_edit(userid)
+    }
+
     /// Initialize computed fields
     pub fn init(&mut self) {
         self.count = self.trackhashes.len() as i32;
@@ -124,6 +159,8 @@ impl Playlist {
             extra,
             settings,
             userid,
+            shared: false,
+            collaborators: Vec::new(),
             thumb: String::new(),
             count: 0,
             duration: 0,
@@ -131,6 +168,9 @@ impl Playlist {
             has_image: false,
             images: Vec::new(),
             is_editable: false,
+            color: String::new(),
+            color_dark: String::new(),
+            color_light: String::new(),
         };
         playlist.init();
         playlist
@@ -150,3 +190,24 @@ impl PartialEq for Playlist {
 }
 
 impl Eq for Playlist {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_playlist_access() {
+        let mut playlist = Playlist::new("Mix".to_string(), Some(1));
+        assert!(playlist.can_edit(1));
+        assert!(!playlist.can_view(2));
+
+        playlist.collaborators = vec![2];
+        assert!(playlist.can_edit(2));
+        assert!(!playlist.is_owner(2));
+        assert!(!playlist.can_view(3));
+
+        playlist.shared = true;
+        assert!(playlist.can_view(3));
+        assert!(!playlist.can_edit(3));
+    }
+}
@@ -0,0 +1,70 @@
+//! Podcast models
+
+use serde::{Deserialize, Serialize};
+
+/// A podcast feed a user subscribed to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Podcast {
+    /// Database ID
+    pub id: i64,
+    /// User who subscribed
+    pub userid: i64,
+    /// RSS feed url
+    pub feed_url: String,
+    pub title: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub description: String,
+    /// Cover art url
+    #[serde(default)]
+    pub image: String,
+    /// Podcast website
+    #[serde(default)]
+    pub link: String,
+    /// Timestamp of the last feed refresh
+    #[serde(default)]
+    pub last_checked: i64,
+    /// Timestamp when the user subscribed
+    #[serde(default)]
+    pub created_at: i64,
+}
+
+/// An episode of a podcast
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PodcastEpisode {
+    /// Database ID
+    pub id: i64,
+    pub podcastid: i64,
+    /// Feed guid, unique within a podcast
+    pub guid: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    /// Enclosure url of the audio
+    pub audio_url: String,
+    #[serde(default)]
+    pub mime_type: String,
+    /// Duration in seconds, 0 when the feed does not say
+    #[serde(default)]
+    pub duration: i64,
+    /// Publication timestamp
+    #[serde(default)]
+    pub published: i64,
+    /// Episode art url, empty when it uses the podcast cover
+    #[serde(default)]
+    pub image: String,
+    /// Downloaded audio file, empty until downloaded
+    #[serde(skip_serializing)]
+    pub filepath: String,
+}
+
+/// How far a user got into an episode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct EpisodeProgress {
+    /// Playback position in seconds
+    pub position: i64,
+    pub completed: bool,
+    /// Timestamp of the last update
+    pub updated: i64,
+}
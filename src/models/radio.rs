@@ -0,0 +1,43 @@
+//! Internet radio station model
+
+use serde::{Deserialize, Serialize};
+
+/// An internet radio station saved by a user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RadioStation {
+    /// Database ID
+    pub id: i64,
+    /// User who added the station
+    pub userid: i64,
+    /// Station name
+    pub name: String,
+    /// Stream url, may point to a .pls or .m3u playlist
+    pub url: String,
+    /// Station website
+    #[serde(default)]
+    pub homepage: String,
+    /// Logo url
+    #[serde(default)]
+    pub image: String,
+    #[serde(default)]
+    pub genre: String,
+    /// Timestamp when the station was added
+    #[serde(default)]
+    pub created_at: i64,
+}
+
+impl RadioStation {
+    /// Create a new station
+    pub fn new(name: String, url: String, userid: i64) -> Self {
+        Self {
+            id: 0,
+            userid,
+            name,
+            url,
+            homepage: String::new(),
+            image: String::new(),
+            genre: String::new(),
+            created_at: chrono::Utc::now().timestamp(),
+        }
+    }
+}
@@ -19,6 +19,9 @@ pub struct TrackLog {
     pub source: String,
     /// User ID
     pub userid: i64,
+    /// Device the track was played on, empty when unknown
+    #[serde(default)]
+    pub device: String,
     /// Extra metadata
     #[serde(default)]
     pub extra: serde_json::Value,
@@ -46,6 +49,7 @@ impl TrackLog {
             duration,
             source,
             userid,
+            device: String::new(),
             extra: serde_json::Value::Null,
             source_type,
             source_id,
@@ -48,7 +48,7 @@ pub struct Track {
     pub track: i32,
     /// Unique track hash
     pub trackhash: String,
-    /// Extra metadata
+    /// Extra metadata, see [`TrackExtra`]
     #[serde(default)]
     pub extra: serde_json::Value,
     /// Last played timestamp
@@ -60,6 +60,15 @@ pub struct Track {
     /// Total play duration in seconds
     #[serde(default)]
     pub playduration: i32,
+    /// Whether embedded or sidecar lyrics were found when the file was read
+    #[serde(default)]
+    pub has_lyrics: bool,
+    /// Star rating from 1 to 5, zero when unrated
+    ///
+    /// holds the rating from the file tags until a user's own rating is
+    /// overlaid onto the track
+    #[serde(default)]
+    pub rating: u8,
 
     // Computed/transient fields
     /// Original album title (before processing)
@@ -95,6 +104,9 @@ pub struct Track {
     /// User IDs who favorited this track
     #[serde(default)]
     pub fav_userids: HashSet<i64>,
+    /// MusicBrainz recording ID
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub mbid: String,
 }
 
 impl Track {
@@ -122,6 +134,8 @@ impl Track {
             lastplayed: 0,
             playcount: 0,
             playduration: 0,
+            has_lyrics: false,
+            rating: 0,
             og_album: String::new(),
             og_title: String::new(),
             artisthashes: Vec::new(),
@@ -133,6 +147,7 @@ impl Track {
             score: 0.0,
             explicit: false,
             fav_userids: HashSet::new(),
+            mbid: String::new(),
         }
     }
 
@@ -215,6 +230,81 @@ impl Track {
     pub fn sort_position(&self) -> i32 {
         self.disc * 1000 + self.track
     }
+
+    /// Audio details and tag totals read from `extra`
+    pub fn extra_info(&self) -> TrackExtra {
+        serde_json::from_value(self.extra.clone()).unwrap_or_default()
+    }
+
+    /// Star rating read from the file tags, zero when the tags had none
+    pub fn tag_rating(&self) -> u8 {
+        self.extra
+            .get("rating")
+            .and_then(|r| r.as_u64())
+            .map_or(0, |r| r.min(5) as u8)
+    }
+}
+
+/// Audio details and tag totals kept in `Track::extra`
+///
+/// tracks indexed before these were recorded have an empty object so every
+/// field falls back to its default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrackExtra {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub track_total: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disc_total: Option<i32>,
+    /// Lowercase codec name, e.g. flac, mp3, aac
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub codec: String,
+    /// Sample rate in Hz
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub samplerate: u32,
+    /// Bits per sample, zero for lossy codecs
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub bitdepth: u32,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub channels: u32,
+    /// File size in bytes
+    #[serde(default, skip_serializing_if = "is_zero_u64")]
+    pub filesize: u64,
+    /// Record label or publisher
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Catalog number of the release the file comes from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub catalog_number: Option<String>,
+    /// MusicBrainz ids of the track and album artists
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artist_mbids: Vec<String>,
+    /// Star rating from 1 to 5 read from POPM or RATING tags
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<u8>,
+    /// Tempo in beats per minute from the BPM tag
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bpm: Option<u16>,
+    /// Musical key as tagged, e.g. "Am", "F#" or the Camelot "8A"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Integrated loudness in LUFS derived from the ReplayGain track gain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loudness: Option<f32>,
+}
+
+impl TrackExtra {
+    /// Serialize into the value stored on the track
+    pub fn to_value(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or(serde_json::Value::Null)
+    }
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+fn is_zero_u64(n: &u64) -> bool {
+    *n == 0
 }
 
 impl Default for Track {
@@ -264,4 +354,23 @@ mod tests {
         track.track = 3;
         assert_eq!(track.sort_position(), 2003);
     }
+
+    #[test]
+    fn test_extra_info_round_trip() {
+        let mut track = Track::new();
+        assert_eq!(track.extra_info(), TrackExtra::default());
+
+        let extra = TrackExtra {
+            track_total: Some(12),
+            codec: "flac".to_string(),
+            samplerate: 44100,
+            bitdepth: 16,
+            filesize: 31_457_280,
+            ..TrackExtra::default()
+        };
+        track.extra = extra.to_value();
+        assert_eq!(track.extra["track_total"], 12);
+        assert!(track.extra.get("label").is_none());
+        assert_eq!(track.extra_info(), extra);
+    }
 }
@@ -2,7 +2,41 @@
 
 use serde::{Deserialize, Serialize};
 
+/// Things a user can be allowed to do beyond browsing and playing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Permission {
+    #[serde(rename = "can_edit_tags")]
+    EditTags,
+    #[serde(rename = "can_delete_files")]
+    DeleteFiles,
+    #[serde(rename = "can_manage_playlists")]
+    ManagePlaylists,
+    #[serde(rename = "can_download")]
+    Download,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 4] = [
+        Permission::EditTags,
+        Permission::DeleteFiles,
+        Permission::ManagePlaylists,
+        Permission::Download,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::EditTags => "can_edit_tags",
+            Permission::DeleteFiles => "can_delete_files",
+            Permission::ManagePlaylists => "can_manage_playlists",
+            Permission::Download => "can_download",
+        }
+    }
+}
+
 /// User roles
+///
+/// the `can_*` roles grant a single permission on top of the other roles, so
+/// a user can be given tag editing without becoming a curator
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum UserRole {
@@ -10,6 +44,14 @@ pub enum UserRole {
     User,
     Guest,
     Curator,
+    #[serde(rename = "can_edit_tags")]
+    CanEditTags,
+    #[serde(rename = "can_delete_files")]
+    CanDeleteFiles,
+    #[serde(rename = "can_manage_playlists")]
+    CanManagePlaylists,
+    #[serde(rename = "can_download")]
+    CanDownload,
 }
 
 impl UserRole {
@@ -19,6 +61,10 @@ impl UserRole {
             UserRole::User => "user",
             UserRole::Guest => "guest",
             UserRole::Curator => "curator",
+            UserRole::CanEditTags => Permission::EditTags.as_str(),
+            UserRole::CanDeleteFiles => Permission::DeleteFiles.as_str(),
+            UserRole::CanManagePlaylists => Permission::ManagePlaylists.as_str(),
+            UserRole::CanDownload => Permission::Download.as_str(),
         }
     }
 
@@ -28,9 +74,31 @@ impl UserRole {
             "user" => Some(UserRole::User),
             "guest" => Some(UserRole::Guest),
             "curator" => Some(UserRole::Curator),
+            "can_edit_tags" => Some(UserRole::CanEditTags),
+            "can_delete_files" => Some(UserRole::CanDeleteFiles),
+            "can_manage_playlists" => Some(UserRole::CanManagePlaylists),
+            "can_download" => Some(UserRole::CanDownload),
             _ => None,
         }
     }
+
+    /// Whether the role allows something, admins are allowed everything and
+    /// curators everything but deleting files
+    pub fn grants(&self, permission: Permission) -> bool {
+        match self {
+            UserRole::Admin => true,
+            UserRole::Curator => permission != Permission::DeleteFiles,
+            UserRole::User => matches!(
+                permission,
+                Permission::ManagePlaylists | Permission::Download
+            ),
+            UserRole::Guest => false,
+            UserRole::CanEditTags => permission == Permission::EditTags,
+            UserRole::CanDeleteFiles => permission == Permission::DeleteFiles,
+            UserRole::CanManagePlaylists => permission == Permission::ManagePlaylists,
+            UserRole::CanDownload => permission == Permission::Download,
+        }
+    }
 }
 
 impl Default for UserRole {
@@ -119,6 +187,19 @@ impl User {
         self.roles.contains(&UserRole::Guest)
     }
 
+    /// Check if any of the user's roles grants a permission
+    pub fn can(&self, permission: Permission) -> bool {
+        self.roles.iter().any(|role| role.grants(permission))
+    }
+
+    /// Every permission the user's roles grant
+    pub fn permissions(&self) -> Vec<Permission> {
+        Permission::ALL
+            .into_iter()
+            .filter(|p| self.can(*p))
+            .collect()
+    }
+
     /// Serialize without password (for API responses)
     pub fn to_public(&self) -> PublicUser {
         PublicUser {
@@ -165,3 +246,32 @@ pub struct MinimalUser {
     pub username: String,
     pub firstname: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_permissions() {
+        let mut user = User::new("listener".to_string(), String::new());
+        assert_eq!(
+            user.permissions(),
+            vec![Permission::ManagePlaylists, Permission::Download]
+        );
+
+        user.roles.push(UserRole::CanEditTags);
+        assert!(user.can(Permission::EditTags));
+        assert!(!user.can(Permission::DeleteFiles));
+
+        assert!(User::guest().permissions().is_empty());
+        assert!(User::admin(String::new(), String::new()).can(Permission::DeleteFiles));
+
+        let roles: Vec<UserRole> =
+            serde_json::from_str(r#"["curator","can_delete_files"]"#).unwrap();
+        assert_eq!(roles, vec![UserRole::Curator, UserRole::CanDeleteFiles]);
+        assert_eq!(
+            UserRole::from_str("CAN_DOWNLOAD"),
+            Some(UserRole::CanDownload)
+        );
+    }
+}
@@ -4,8 +4,10 @@
 
 pub mod lastfm;
 pub mod lyrics;
+pub mod musicbrainz;
 
 pub use lastfm::LastFmPlugin;
+pub use musicbrainz::MusicBrainzPlugin;
 
 #[allow(unused_imports)]
 pub use lyrics::{LyricsPlugin, LyricsSearchResult, MusixmatchProvider};
@@ -82,6 +84,7 @@ impl PluginRegistry {
 /// Register all plugins from the plugins directory
 pub async fn register_plugins() -> Result<()> {
     use crate::config::Paths;
+    use crate::db::tables::PluginTable;
     use crate::db::DbEngine;
 
     let paths = Paths::get()?;
@@ -91,6 +94,10 @@ pub async fn register_plugins() -> Result<()> {
         tokio::fs::create_dir_all(&plugins_dir).await?;
     }
 
+    // Seed built-in plugins so they can be configured and activated
+    let musicbrainz_settings = serde_json::to_string(&musicbrainz::MusicBrainzSettings::default())?;
+    PluginTable::insert_default(musicbrainz::PLUGIN_NAME, &musicbrainz_settings).await?;
+
     // Load plugins from database
     let db = DbEngine::get()?;
     let plugins = sqlx::query_as::<_, (i64, String, bool, String)>(
@@ -0,0 +1,691 @@
+//! MusicBrainz plugin - matches tracks to MusicBrainz recordings
+//!
+//! candidates come from an AcoustID fingerprint lookup when an AcoustID api key
+//! is configured and `fpcalc` (chromaprint) is installed, and from a MusicBrainz
+//! search on the track tags.
+
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tracing::warn;
+
+use crate::core::fingerprint::{self, Fingerprint};
+use crate::core::Tagger;
+use crate::db::tables::PluginTable;
+use crate::models::Track;
+
+/// Name of the plugin row holding the settings
+pub const PLUGIN_NAME: &str = "musicbrainz";
+
+const MUSICBRAINZ_API_URL: &str = "https://musicbrainz.org/ws/2/";
+const ACOUSTID_API_URL: &str = "https://api.acoustid.org/v2/lookup";
+const USER_AGENT: &str = concat!(
+    "SwingMusic/",
+    env!("CARGO_PKG_VERSION"),
+    " ( https://github.com/swingmx/swingmusic )"
+);
+
+/// MusicBrainz allows one request per second per client
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Duration difference in seconds tolerated before a search match loses score
+const DURATION_TOLERANCE: i32 = 3;
+
+/// Time of the last MusicBrainz request, shared by all plugin instances
+static LAST_REQUEST: Lazy<Mutex<Option<Instant>>> = Lazy::new(|| Mutex::new(None));
+
+/// Plugin settings stored in the plugin table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MusicBrainzSettings {
+    /// AcoustID application key, fingerprinting is skipped when empty
+    #[serde(default)]
+    pub acoustid_api_key: String,
+    /// Path to the fpcalc binary
+    #[serde(default = "default_fpcalc_path")]
+    pub fpcalc_path: String,
+}
+
+fn default_fpcalc_path() -> String {
+    "fpcalc".to_string()
+}
+
+impl Default for MusicBrainzSettings {
+    fn default() -> Self {
+        Self {
+            acoustid_api_key: String::new(),
+            fpcalc_path: default_fpcalc_path(),
+        }
+    }
+}
+
+/// Where a match candidate came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchSource {
+    AcoustId,
+    Search,
+}
+
+/// Artist as credited on MusicBrainz
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MbArtist {
+    pub mbid: String,
+    pub name: String,
+}
+
+/// A recording that may correspond to a library track
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchCandidate {
+    pub recording_mbid: String,
+    pub title: String,
+    pub artists: Vec<MbArtist>,
+    pub release_mbid: String,
+    pub release_title: String,
+    pub releasegroup_mbid: String,
+    pub albumartists: Vec<MbArtist>,
+    /// Release date as "YYYY", "YYYY-MM" or "YYYY-MM-DD"
+    pub date: String,
+    /// Duration in seconds
+    pub duration: i32,
+    /// Match confidence (0-100)
+    pub score: u8,
+    pub source: MatchSource,
+}
+
+impl MatchCandidate {
+    /// Release year parsed from the date
+    pub fn year(&self) -> Option<i32> {
+        self.date.get(..4).and_then(|y| y.parse().ok())
+    }
+
+    /// Write the corrected tags and ids to a file
+    pub fn write_tags(&self, path: &Path) -> Result<()> {
+        let artist = join_names(&self.artists);
+        let albumartist = join_names(&self.albumartists);
+
+        Tagger::write_tags(
+            path,
+            non_empty(&self.title),
+            non_empty(&self.release_title),
+            non_empty(&artist),
+            non_empty(&albumartist),
+            None,
+            None,
+            self.year(),
+            None,
+        )?;
+
+        Tagger::write_musicbrainz_ids(
+            path,
+            &self.recording_mbid,
+            &self.release_mbid,
+            &self.releasegroup_mbid,
+            &self.artists.iter().map(|a| a.mbid.clone()).collect::<Vec<_>>(),
+            &self
+                .albumartists
+                .iter()
+                .map(|a| a.mbid.clone())
+                .collect::<Vec<_>>(),
+        )
+    }
+}
+
+fn join_names(artists: &[MbArtist]) -> String {
+    artists
+        .iter()
+        .map(|a| a.name.as_str())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn non_empty(value: &str) -> Option<&str> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ArtistCredit {
+    #[serde(default)]
+    name: String,
+    artist: CreditedArtist,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreditedArtist {
+    id: String,
+    #[serde(default)]
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseGroupRef {
+    #[serde(default)]
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    id: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    date: Option<String>,
+    #[serde(default, rename = "artist-credit")]
+    artist_credit: Vec<ArtistCredit>,
+    #[serde(default, rename = "release-group")]
+    release_group: Option<ReleaseGroupRef>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Recording {
+    id: String,
+    #[serde(default)]
+    score: Option<u8>,
+    #[serde(default)]
+    title: String,
+    /// length in milliseconds
+    #[serde(default)]
+    length: Option<i64>,
+    #[serde(default, rename = "artist-credit")]
+    artist_credit: Vec<ArtistCredit>,
+    #[serde(default)]
+    releases: Vec<Release>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecordingSearchResponse {
+    #[serde(default)]
+    recordings: Vec<Recording>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AcoustIdResponse {
+    status: String,
+    #[serde(default)]
+    error: Option<AcoustIdError>,
+    #[serde(default)]
+    results: Vec<AcoustIdResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AcoustIdError {
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AcoustIdResult {
+    score: f64,
+    #[serde(default)]
+    recordings: Vec<AcoustIdRecording>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AcoustIdRecording {
+    id: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    duration: Option<f64>,
+    #[serde(default)]
+    artists: Vec<CreditedArtist>,
+    #[serde(default)]
+    releasegroups: Vec<AcoustIdReleaseGroup>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AcoustIdReleaseGroup {
+    id: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    artists: Vec<CreditedArtist>,
+}
+
+/// MusicBrainz plugin for matching tracks and fetching their metadata
+pub struct MusicBrainzPlugin {
+    client: Client,
+    settings: MusicBrainzSettings,
+}
+
+impl MusicBrainzPlugin {
+    pub fn new(settings: MusicBrainzSettings) -> Self {
+        let client = Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(Duration::from_secs(20))
+            .build()
+            .unwrap_or_default();
+
+        Self { client, settings }
+    }
+
+    /// Load the plugin with the settings stored in the database
+    ///
+    /// returns `None` when the plugin has not been activated
+    pub async fn load() -> Result<Option<Self>> {
+        let Some(row) = PluginTable::get_by_name(PLUGIN_NAME).await? else {
+            return Ok(None);
+        };
+        if !row.active {
+            return Ok(None);
+        }
+
+        let settings = serde_json::from_str(&row.settings).unwrap_or_default();
+        Ok(Some(Self::new(settings)))
+    }
+
+    /// Find recordings matching a track, best match first
+    pub async fn find_matches(&self, track: &Track, limit: usize) -> Result<Vec<MatchCandidate>> {
+        let mut candidates = Vec::new();
+
+        if !self.settings.acoustid_api_key.is_empty() {
+            match self.lookup_fingerprint(Path::new(&track.filepath)).await {
+                Ok(found) => candidates.extend(found),
+                Err(e) => warn!("acoustid lookup failed for {}: {}", track.filepath, e),
+            }
+        }
+
+        candidates.extend(self.search(track, limit).await?);
+
+        Ok(merge_candidates(candidates, limit))
+    }
+
+    /// Fetch a recording on a given release (or its first release)
+    pub async fn get_recording(
+        &self,
+        recording_mbid: &str,
+        release_mbid: Option<&str>,
+    ) -> Result<MatchCandidate> {
+        let url = format!(
+            "{}recording/{}?inc=artist-credits+releases+release-groups&fmt=json",
+            MUSICBRAINZ_API_URL,
+            urlencode(recording_mbid)
+        );
+        let recording: Recording = self.get_json(&url).await?;
+
+        let release_id = match release_mbid {
+            Some(id) if !id.is_empty() => {
+                if !recording.releases.iter().any(|r| r.id == id) {
+                    return Err(anyhow!("Release {} does not contain this recording", id));
+                }
+                Some(id.to_string())
+            }
+            _ => recording.releases.first().map(|r| r.id.clone()),
+        };
+
+        // the recording lookup does not include release artist credits
+        let release = match release_id {
+            Some(id) => Some(self.get_release(&id).await?),
+            None => None,
+        };
+
+        Ok(candidate_from_recording(
+            recording,
+            release,
+            MatchSource::Search,
+        ))
+    }
+
+    async fn get_release(&self, release_mbid: &str) -> Result<Release> {
+        let url = format!(
+            "{}release/{}?inc=artist-credits+release-groups&fmt=json",
+            MUSICBRAINZ_API_URL,
+            urlencode(release_mbid)
+        );
+        self.get_json(&url).await
+    }
+
+    /// Search recordings by the track tags
+    async fn search(&self, track: &Track, limit: usize) -> Result<Vec<MatchCandidate>> {
+        let query = build_search_query(track);
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let url = format!(
+            "{}recording?query={}&limit={}&fmt=json",
+            MUSICBRAINZ_API_URL,
+            urlencode(&query),
+            limit.clamp(1, 25)
+        );
+        let response: RecordingSearchResponse = self.get_json(&url).await?;
+
+        Ok(response
+            .recordings
+            .into_iter()
+            .map(|mut recording| {
+                let release = pick_release(&mut recording.releases, &track.og_album);
+                let mut candidate =
+                    candidate_from_recording(recording, release, MatchSource::Search);
+                candidate.score =
+                    adjust_for_duration(candidate.score, candidate.duration, track.duration);
+                candidate
+            })
+            .collect())
+    }
+
+    /// Look up a file by its chromaprint fingerprint
+    async fn lookup_fingerprint(&self, path: &Path) -> Result<Vec<MatchCandidate>> {
+        let fingerprint = self.fingerprint(path).await?;
+
+        let params = [
+            ("client", self.settings.acoustid_api_key.clone()),
+            ("meta", "recordings releasegroups".to_string()),
+            (
+                "duration",
+                (fingerprint.duration.round() as i64).to_string(),
+            ),
+            ("fingerprint", fingerprint.fingerprint),
+            ("format", "json".to_string()),
+        ];
+        let response: AcoustIdResponse = self
+            .client
+            .post(ACOUSTID_API_URL)
+            .form(&params)
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        if response.status != "ok" {
+            let message = response.error.map(|e| e.message).unwrap_or_default();
+            return Err(anyhow!("AcoustID error: {}", message));
+        }
+
+        let mut candidates = Vec::new();
+        for result in response.results {
+            let score = (result.score * 100.0).round().clamp(0.0, 100.0) as u8;
+            for recording in result.recordings {
+                let artists: Vec<MbArtist> =
+                    recording.artists.into_iter().map(MbArtist::from).collect();
+                let group = recording.releasegroups.into_iter().next();
+                let albumartists = match &group {
+                    Some(g) if !g.artists.is_empty() => g
+                        .artists
+                        .iter()
+                        .map(|a| MbArtist {
+                            mbid: a.id.clone(),
+                            name: a.name.clone(),
+                        })
+                        .collect(),
+                    _ => artists.clone(),
+                };
+
+                candidates.push(MatchCandidate {
+                    recording_mbid: recording.id,
+                    title: recording.title,
+                    artists,
+                    release_mbid: String::new(),
+                    release_title: group.as_ref().map(|g| g.title.clone()).unwrap_or_default(),
+                    releasegroup_mbid: group.map(|g| g.id).unwrap_or_default(),
+                    albumartists,
+                    date: String::new(),
+                    duration: recording.duration.unwrap_or(0.0).round() as i32,
+                    score,
+                    source: MatchSource::AcoustId,
+                });
+            }
+        }
+
+        Ok(candidates)
+    }
+
+    /// Fingerprint of a file, from the library pass when it has one
+    async fn fingerprint(&self, path: &Path) -> Result<Fingerprint> {
+        if let Some(stored) = fingerprint::stored(&path.to_string_lossy()).await {
+            return Ok(stored);
+        }
+
+        let fpcalc = PathBuf::from(&self.settings.fpcalc_path);
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || fingerprint::compute(&fpcalc, &path)).await?
+    }
+
+    /// Send a throttled GET request to MusicBrainz
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T> {
+        {
+            let mut last = LAST_REQUEST.lock().await;
+            if let Some(previous) = *last {
+                let elapsed = previous.elapsed();
+                if elapsed < REQUEST_INTERVAL {
+                    tokio::time::sleep(REQUEST_INTERVAL - elapsed).await;
+                }
+            }
+            *last = Some(Instant::now());
+        }
+
+        let response = self.client.get(url).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("MusicBrainz returned {}", response.status()));
+        }
+
+        Ok(response.json().await?)
+    }
+}
+
+impl From<CreditedArtist> for MbArtist {
+    fn from(artist: CreditedArtist) -> Self {
+        Self {
+            mbid: artist.id,
+            name: artist.name,
+        }
+    }
+}
+
+fn credited_artists(credits: Vec<ArtistCredit>) -> Vec<MbArtist> {
+    credits
+        .into_iter()
+        .map(|credit| MbArtist {
+            mbid: credit.artist.id,
+            name: if credit.name.is_empty() {
+                credit.artist.name
+            } else {
+                credit.name
+            },
+        })
+        .collect()
+}
+
+fn candidate_from_recording(
+    recording: Recording,
+    release: Option<Release>,
+    source: MatchSource,
+) -> MatchCandidate {
+    let artists = credited_artists(recording.artist_credit);
+    let (release_mbid, release_title, releasegroup_mbid, albumartists, date) = match release {
+        Some(release) => {
+            let albumartists = if release.artist_credit.is_empty() {
+                artists.clone()
+            } else {
+                credited_artists(release.artist_credit)
+            };
+            (
+                release.id,
+                release.title,
+                release.release_group.map(|g| g.id).unwrap_or_default(),
+                albumartists,
+                release.date.unwrap_or_default(),
+            )
+        }
+        None => (
+            String::new(),
+            String::new(),
+            String::new(),
+            artists.clone(),
+            String::new(),
+        ),
+    };
+
+    MatchCandidate {
+        recording_mbid: recording.id,
+        title: recording.title,
+        artists,
+        release_mbid,
+        release_title,
+        releasegroup_mbid,
+        albumartists,
+        date,
+        duration: (recording.length.unwrap_or(0) / 1000) as i32,
+        score: recording.score.unwrap_or(100).min(100),
+        source,
+    }
+}
+
+/// Take the release whose title matches the album, or the first one
+fn pick_release(releases: &mut Vec<Release>, album: &str) -> Option<Release> {
+    if releases.is_empty() {
+        return None;
+    }
+    let album = album.to_lowercase();
+    let index = releases
+        .iter()
+        .position(|r| r.title.to_lowercase() == album)
+        .unwrap_or(0);
+    Some(releases.swap_remove(index))
+}
+
+/// Lower the score of matches whose length differs from the file
+fn adjust_for_duration(score: u8, candidate_duration: i32, track_duration: i32) -> u8 {
+    if candidate_duration == 0 || track_duration == 0 {
+        return score;
+    }
+    let diff = (candidate_duration - track_duration).abs();
+    if diff <= DURATION_TOLERANCE {
+        score
+    } else {
+        score.saturating_sub(diff.min(50) as u8)
+    }
+}
+
+/// Merge duplicate recordings keeping the best score and any release details
+fn merge_candidates(candidates: Vec<MatchCandidate>, limit: usize) -> Vec<MatchCandidate> {
+    let mut merged: Vec<MatchCandidate> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+
+    for candidate in candidates {
+        match index.get(&candidate.recording_mbid) {
+            Some(&i) => {
+                let existing = &mut merged[i];
+                if existing.release_mbid.is_empty() && !candidate.release_mbid.is_empty() {
+                    existing.release_mbid = candidate.release_mbid;
+                    existing.release_title = candidate.release_title;
+                    existing.releasegroup_mbid = candidate.releasegroup_mbid;
+                    existing.albumartists = candidate.albumartists;
+                    existing.date = candidate.date;
+                }
+                existing.score = existing.score.max(candidate.score);
+            }
+            None => {
+                index.insert(candidate.recording_mbid.clone(), merged.len());
+                merged.push(candidate);
+            }
+        }
+    }
+
+    merged.sort_by_key(|c| Reverse(c.score));
+    merged.truncate(limit);
+    merged
+}
+
+/// Build a lucene query for the recording search from the track tags
+fn build_search_query(track: &Track) -> String {
+    let title = if track.og_title.is_empty() {
+        &track.title
+    } else {
+        &track.og_title
+    };
+    if title.trim().is_empty() {
+        return String::new();
+    }
+
+    let mut query = format!("recording:\"{}\"", lucene_escape(title));
+    if let Some(artist) = track.artists.first() {
+        query.push_str(&format!(" AND artist:\"{}\"", lucene_escape(&artist.name)));
+    }
+    // the release only boosts the score so singles and compilations still match
+    let album = if track.og_album.is_empty() {
+        &track.album
+    } else {
+        &track.og_album
+    };
+    if !album.trim().is_empty() {
+        query.push_str(&format!(" release:\"{}\"", lucene_escape(album)));
+    }
+    query
+}
+
+/// Escape lucene special characters inside a quoted term
+fn lucene_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.trim().chars() {
+        if matches!(
+            c,
+            '+' | '-'
+                | '&'
+                | '|'
+                | '!'
+                | '('
+                | ')'
+                | '{'
+                | '}'
+                | '['
+                | ']'
+                | '^'
+                | '"'
+                | '~'
+                | '*'
+                | '?'
+                | ':'
+                | '\\'
+                | '/'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Percent-encode a query string component
+fn urlencode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lucene_escape() {
+        assert_eq!(lucene_escape("AC/DC"), "AC\\/DC");
+        assert_eq!(lucene_escape(" Why? (Live) "), "Why\\? \\(Live\\)");
+        assert_eq!(lucene_escape("plain"), "plain");
+    }
+
+    #[test]
+    fn test_adjust_for_duration() {
+        assert_eq!(adjust_for_duration(90, 200, 202), 90);
+        assert_eq!(adjust_for_duration(90, 200, 230), 60);
+        assert_eq!(adjust_for_duration(90, 0, 230), 90);
+        assert_eq!(adjust_for_duration(20, 100, 400), 0);
+    }
+}
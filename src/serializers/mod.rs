@@ -4,6 +4,7 @@
 //! JSON-friendly structures for API responses.
 
 use crate::models::*;
+use crate::stores::PlayStatsStore;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,6 +26,8 @@ pub struct TrackResponse {
     pub image: Option<String>,
     pub is_favorite: bool,
     pub play_count: i32,
+    /// stars from 1 to 5, zero when unrated
+    pub rating: u8,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,6 +41,8 @@ pub struct AlbumResponse {
     pub trackcount: i32,
     pub image: Option<String>,
     pub color: Option<String>,
+    pub color_dark: Option<String>,
+    pub color_light: Option<String>,
     pub is_favorite: bool,
 }
 
@@ -64,8 +69,12 @@ pub struct PlaylistResponse {
     pub updated_at: String,
 }
 
-impl From<Track> for TrackResponse {
-    fn from(track: Track) -> Self {
+impl TrackResponse {
+    /// Serialize a track with the favorite flag, play count and rating of the
+    /// given user
+    pub fn for_user(mut track: Track, user_id: i64) -> Self {
+        let is_favorite = track.is_favorite(user_id);
+        PlayStatsStore::get().personalize_tracks(user_id, std::slice::from_mut(&mut track));
         let artists: Vec<String> = track.artists.iter().map(|a| a.name.clone()).collect();
         let image = if track.image.is_empty() {
             None
@@ -89,14 +98,17 @@ impl From<Track> for TrackResponse {
             bitrate: track.bitrate,
             samplerate: 0, // Not stored in track model
             image,
-            is_favorite: false,
+            is_favorite,
             play_count: track.playcount,
+            rating: track.rating,
         }
     }
 }
 
-impl From<Album> for AlbumResponse {
-    fn from(album: Album) -> Self {
+impl AlbumResponse {
+    /// Serialize an album with the favorite flag of the given user
+    pub fn for_user(album: Album, user_id: i64) -> Self {
+        let is_favorite = album.is_favorite(user_id);
         let albumartisthash = album.artisthashes.first().cloned().unwrap_or_default();
         let image = if album.image.is_empty() {
             None
@@ -108,6 +120,8 @@ impl From<Album> for AlbumResponse {
         } else {
             Some(album.color.clone())
         };
+        let color_dark = (!album.color_dark.is_empty()).then(|| album.color_dark.clone());
+        let color_light = (!album.color_light.is_empty()).then(|| album.color_light.clone());
         let albumartist = album.albumartist();
         Self {
             albumhash: album.albumhash,
@@ -119,13 +133,17 @@ impl From<Album> for AlbumResponse {
             trackcount: album.trackcount,
             image,
             color,
-            is_favorite: false,
+            color_dark,
+            color_light,
+            is_favorite,
         }
     }
 }
 
-impl From<Artist> for ArtistResponse {
-    fn from(artist: Artist) -> Self {
+impl ArtistResponse {
+    /// Serialize an artist with the favorite flag of the given user
+    pub fn for_user(artist: Artist, user_id: i64) -> Self {
+        let is_favorite = artist.is_favorite(user_id);
         let image = if artist.image.is_empty() {
             None
         } else {
@@ -138,7 +156,7 @@ impl From<Artist> for ArtistResponse {
             trackcount: artist.trackcount,
             duration: artist.duration,
             image,
-            is_favorite: false,
+            is_favorite,
         }
     }
 }
@@ -0,0 +1,57 @@
+//! Integration with os service managers
+//!
+//! on linux the server takes over sockets passed by systemd socket activation
+//! and reports readiness, shutdown and watchdog pings over `sd_notify`. on
+//! windows it can register itself with the service control manager and run
+//! as a native service. everything here is a no-op when the server is started
+//! by hand.
+
+#[cfg(unix)]
+mod systemd;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(unix)]
+pub use systemd::{inherited_listeners, notify_ready, notify_stopping};
+#[cfg(windows)]
+pub use windows::{install, run_as_service, uninstall};
+
+/// resolves when the service manager asks the server to stop
+pub type StopSignal = tokio::sync::oneshot::Receiver<()>;
+
+/// Listening sockets handed over by the service manager, empty when started directly
+#[cfg(not(unix))]
+pub fn inherited_listeners() -> anyhow::Result<Vec<std::net::TcpListener>> {
+    Ok(Vec::new())
+}
+
+/// Tell the service manager the server accepts connections
+#[cfg(not(unix))]
+pub fn notify_ready(_status: &str) {}
+
+/// Tell the service manager the server is shutting down
+#[cfg(not(unix))]
+pub fn notify_stopping() {}
+
+/// Register the server as a windows service launched with `arguments`
+#[cfg(not(windows))]
+pub fn install(_arguments: Vec<std::ffi::OsString>) -> anyhow::Result<()> {
+    anyhow::bail!("--install-service is only supported on Windows, use a systemd unit instead")
+}
+
+/// Remove the windows service registration
+#[cfg(not(windows))]
+pub fn uninstall() -> anyhow::Result<()> {
+    anyhow::bail!("--uninstall-service is only supported on Windows")
+}
+
+/// Hand the main thread to the windows service control manager
+///
+/// `run` starts the server and must return once the stop signal fires
+#[cfg(not(windows))]
+pub fn run_as_service<F>(_run: F) -> anyhow::Result<()>
+where
+    F: FnOnce(StopSignal) -> anyhow::Result<()> + Send + 'static,
+{
+    anyhow::bail!("--run-as-service is only supported on Windows")
+}
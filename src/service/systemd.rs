@@ -0,0 +1,54 @@
+//! systemd socket activation and readiness notification
+//!
+//! with a matching `.socket` unit systemd binds the port itself and passes the
+//! listening sockets through `LISTEN_FDS`, so the server can start on demand
+//! and restart without dropping connections. with `Type=notify` the unit only
+//! counts as started once the server reports ready, and `WatchdogSec=` gets
+//! pinged at half its interval.
+
+use anyhow::{Context, Result};
+use sd_notify::NotifyState;
+use std::net::TcpListener;
+use std::os::fd::FromRawFd;
+use std::time::Duration;
+
+/// Take the tcp sockets passed through `LISTEN_FDS`
+pub fn inherited_listeners() -> Result<Vec<TcpListener>> {
+    let fds = sd_notify::listen_fds().context("invalid socket activation environment")?;
+
+    fds.map(|fd| {
+        // SAFETY: systemd hands each descriptor to this process once and
+        // listen_fds clears the environment so it cannot be taken twice
+        let listener = unsafe { TcpListener::from_raw_fd(fd) };
+        listener
+            .local_addr()
+            .with_context(|| format!("socket {} passed by systemd is not a tcp socket", fd))?;
+        listener.set_nonblocking(true)?;
+        Ok(listener)
+    })
+    .collect()
+}
+
+/// Report readiness and start pinging the watchdog if the unit enables it
+pub fn notify_ready(status: &str) {
+    if let Err(e) = sd_notify::notify(false, &[NotifyState::Ready, NotifyState::Status(status)]) {
+        tracing::warn!("Failed to notify systemd of readiness: {}", e);
+    }
+
+    let mut usec = 0;
+    if sd_notify::watchdog_enabled(false, &mut usec) && usec > 0 {
+        let interval = Duration::from_micros(usec / 2);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let _ = sd_notify::notify(false, &[NotifyState::Watchdog]);
+            }
+        });
+    }
+}
+
+/// Report that the server is shutting down
+pub fn notify_stopping() {
+    let _ = sd_notify::notify(false, &[NotifyState::Stopping]);
+}
@@ -0,0 +1,187 @@
+//! Native windows service support
+//!
+//! `--install-service` registers the running executable with the service
+//! control manager, passing along the server flags it was installed with plus
+//! `--run-as-service`. when started that way the server runs under the
+//! service dispatcher and shuts down gracefully on stop or system shutdown.
+
+use anyhow::{anyhow, Context, Result};
+use parking_lot::Mutex;
+use std::ffi::OsString;
+use std::time::Duration;
+use windows_service::service::{
+    ServiceAccess, ServiceAction, ServiceActionType, ServiceControl, ServiceControlAccept,
+    ServiceErrorControl, ServiceExitCode, ServiceFailureActions, ServiceFailureResetPeriod,
+    ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+
+use super::StopSignal;
+
+const SERVICE_NAME: &str = "SwingMusic";
+const SERVICE_DISPLAY_NAME: &str = "Swing Music";
+const SERVICE_DESCRIPTION: &str =
+    "A beautiful, self-hosted music player for your local audio files";
+const SERVICE_TYPE: ServiceType = ServiceType::OWN_PROCESS;
+
+/// how long the service manager waits for a graceful shutdown
+const STOP_WAIT_HINT: Duration = Duration::from_secs(30);
+
+/// delays before the service manager restarts a crashed server
+const RESTART_DELAYS: [Duration; 3] = [
+    Duration::from_secs(5),
+    Duration::from_secs(30),
+    Duration::from_secs(120),
+];
+
+type ServiceRun = Box<dyn FnOnce(StopSignal) -> Result<()> + Send>;
+
+/// server entry point, picked up by the dispatcher thread
+static SERVICE_RUN: Mutex<Option<ServiceRun>> = Mutex::new(None);
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// Register the server as a windows service launched with `arguments`
+pub fn install(arguments: Vec<OsString>) -> Result<()> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )
+    .context("failed to connect to the service manager, run as administrator")?;
+
+    let mut launch_arguments = arguments;
+    launch_arguments.push(OsString::from("--run-as-service"));
+
+    let info = ServiceInfo {
+        name: OsString::from(SERVICE_NAME),
+        display_name: OsString::from(SERVICE_DISPLAY_NAME),
+        service_type: SERVICE_TYPE,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()?,
+        launch_arguments,
+        dependencies: vec![],
+        account_name: None,
+        account_password: None,
+    };
+    let service = manager
+        .create_service(&info, ServiceAccess::CHANGE_CONFIG | ServiceAccess::START)
+        .context("failed to create the service")?;
+    service.set_description(SERVICE_DESCRIPTION)?;
+    service.update_failure_actions(ServiceFailureActions {
+        reset_period: ServiceFailureResetPeriod::After(Duration::from_secs(86400)),
+        reboot_msg: None,
+        command: None,
+        actions: Some(
+            RESTART_DELAYS
+                .iter()
+                .map(|&delay| ServiceAction {
+                    action_type: ServiceActionType::Restart,
+                    delay,
+                })
+                .collect(),
+        ),
+    })?;
+    service
+        .start::<&str>(&[])
+        .context("service installed but failed to start")?;
+
+    println!("Installed and started the {} service", SERVICE_NAME);
+    Ok(())
+}
+
+/// Stop and remove the windows service registration
+pub fn uninstall() -> Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .context("failed to connect to the service manager, run as administrator")?;
+    let service = manager
+        .open_service(
+            SERVICE_NAME,
+            ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+        )
+        .context("the service is not installed")?;
+
+    // the service is removed once its last handle closes
+    service.delete()?;
+    if service.query_status()?.current_state != ServiceState::Stopped {
+        service.stop()?;
+    }
+
+    println!("Removed the {} service", SERVICE_NAME);
+    Ok(())
+}
+
+/// Hand the main thread to the windows service control manager
+///
+/// `run` starts the server and must return once the stop signal fires
+pub fn run_as_service<F>(run: F) -> Result<()>
+where
+    F: FnOnce(StopSignal) -> Result<()> + Send + 'static,
+{
+    *SERVICE_RUN.lock() = Some(Box::new(run));
+    service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+        .context("not started by the service manager, use --install-service")
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(e) = run_service() {
+        tracing::error!("Windows service error: {:#}", e);
+    }
+}
+
+fn run_service() -> Result<()> {
+    let run = SERVICE_RUN
+        .lock()
+        .take()
+        .ok_or_else(|| anyhow!("service entry point missing"))?;
+
+    let (stop_tx, stop_rx) = tokio::sync::oneshot::channel();
+    let stop_tx = Mutex::new(Some(stop_tx));
+
+    let status_handle =
+        service_control_handler::register(SERVICE_NAME, move |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                if let Some(tx) = stop_tx.lock().take() {
+                    let _ = tx.send(());
+                }
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        })?;
+
+    let set_state = |state: ServiceState, accept: ServiceControlAccept, exit_code: u32| {
+        status_handle.set_service_status(ServiceStatus {
+            service_type: SERVICE_TYPE,
+            current_state: state,
+            controls_accepted: accept,
+            exit_code: ServiceExitCode::Win32(exit_code),
+            checkpoint: 0,
+            wait_hint: STOP_WAIT_HINT,
+            process_id: None,
+        })
+    };
+
+    set_state(
+        ServiceState::Running,
+        ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+        0,
+    )?;
+
+    let result = run(stop_rx);
+    if let Err(e) = &result {
+        tracing::error!("Server stopped with an error: {:#}", e);
+    }
+
+    // a non zero exit code lets the failure actions restart the server
+    let exit_code = if result.is_ok() { 0 } else { 1 };
+    set_state(
+        ServiceState::Stopped,
+        ServiceControlAccept::empty(),
+        exit_code,
+    )?;
+
+    Ok(())
+}
@@ -1,11 +1,12 @@
 //! Album store - in-memory album storage with efficient lookups
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, OnceLock, RwLock};
 
 use crate::core::albums::AlbumLib;
 use crate::db::tables::TrackTable;
-use crate::models::Album;
+use crate::models::{Album, ColorVariants};
+use crate::stores::{SearchStore, TrackStore};
 use anyhow::Result;
 
 /// Global album store instance
@@ -14,9 +15,11 @@ static ALBUM_STORE: OnceLock<Arc<AlbumStore>> = OnceLock::new();
 /// In-memory store for albums
 pub struct AlbumStore {
     /// All albums by albumhash
-    albums: RwLock<HashMap<String, Album>>,
+    albums: RwLock<HashMap<String, Arc<Album>>>,
     /// Albums by artist hash
     albums_by_artist: RwLock<HashMap<String, Vec<String>>>,
+    /// Virtual album hash by the hash of each single grouped into it
+    aliases: RwLock<HashMap<String, String>>,
 }
 
 impl AlbumStore {
@@ -27,6 +30,7 @@ impl AlbumStore {
                 Arc::new(AlbumStore {
                     albums: RwLock::new(HashMap::new()),
                     albums_by_artist: RwLock::new(HashMap::new()),
+                    aliases: RwLock::new(HashMap::new()),
                 })
             })
             .clone()
@@ -36,13 +40,24 @@ impl AlbumStore {
     pub fn load(&self, albums: Vec<Album>) {
         let mut album_map = self.albums.write().unwrap();
         let mut artist_map = self.albums_by_artist.write().unwrap();
+        let mut aliases = self.aliases.write().unwrap();
 
         album_map.clear();
         artist_map.clear();
+        aliases.clear();
 
+        let mut groups = HashMap::new();
         for album in albums {
             let hash = album.albumhash.clone();
 
+            let grouped = album.member_albumhashes();
+            if !grouped.is_empty() {
+                for member in &grouped {
+                    aliases.insert(member.clone(), hash.clone());
+                }
+                groups.insert(hash.clone(), grouped);
+            }
+
             // Index by artists
             for artist in &album.artisthashes {
                 artist_map
@@ -51,8 +66,11 @@ impl AlbumStore {
                     .push(hash.clone());
             }
 
-            album_map.insert(hash, album);
+            album_map.insert(hash, Arc::new(album));
         }
+
+        TrackStore::get().set_album_groups(groups);
+        SearchStore::get().load_albums(album_map.values().map(Arc::as_ref));
     }
 
     /// Get total album count
@@ -62,6 +80,16 @@ impl AlbumStore {
 
     /// Get all albums
     pub fn get_all(&self) -> Vec<Album> {
+        self.albums
+            .read()
+            .unwrap()
+            .values()
+            .map(|a| a.as_ref().clone())
+            .collect()
+    }
+
+    /// Get all albums without copying them
+    pub fn get_all_shared(&self) -> Vec<Arc<Album>> {
         self.albums.read().unwrap().values().cloned().collect()
     }
 
@@ -70,26 +98,52 @@ impl AlbumStore {
         self.albums.read().unwrap().keys().cloned().collect()
     }
 
-    /// Get album by hash
+    /// Get album by hash, a grouped single or merged edition resolves to its
+    /// virtual album
     pub fn get_by_hash(&self, hash: &str) -> Option<Album> {
-        self.albums.read().unwrap().get(hash).cloned()
+        let albums = self.albums.read().unwrap();
+        albums
+            .get(hash)
+            .or_else(|| {
+                let aliases = self.aliases.read().unwrap();
+                aliases.get(hash).and_then(|h| albums.get(h))
+            })
+            .map(|a| a.as_ref().clone())
+    }
+
+    /// Dominant color and its dark and light mode variants of an album
+    pub fn get_color(&self, hash: &str) -> Option<(String, ColorVariants)> {
+        let albums = self.albums.read().unwrap();
+        let album = albums.get(hash)?;
+        Some((
+            album.color.clone(),
+            ColorVariants {
+                dark: album.color_dark.clone(),
+                light: album.color_light.clone(),
+            },
+        ))
     }
 
     /// increment play metrics for an album in place
     pub fn increment_play_stats(&self, albumhash: &str, duration: i32, timestamp: i64) {
         if let Some(album) = self.albums.write().unwrap().get_mut(albumhash) {
+            let album = Arc::make_mut(album);
             album.playcount += 1;
             album.playduration += duration;
             album.lastplayed = timestamp;
         }
     }
 
-    /// Get albums by hashes
+    /// Get albums by hashes, grouped singles resolve to their virtual album once
     pub fn get_by_hashes(&self, hashes: &[String]) -> Vec<Album> {
         let albums = self.albums.read().unwrap();
+        let aliases = self.aliases.read().unwrap();
+        let mut seen = HashSet::new();
         hashes
             .iter()
-            .filter_map(|h| albums.get(h).cloned())
+            .filter_map(|h| albums.get(h).or_else(|| albums.get(aliases.get(h)?)))
+            .filter(|album| seen.insert(album.albumhash.as_str()))
+            .map(|a| a.as_ref().clone())
             .collect()
     }
 
@@ -122,26 +176,38 @@ impl AlbumStore {
                 .push(hash.clone());
         }
 
+        SearchStore::get().index_album(&album);
+
         // Add to main map
-        self.albums.write().unwrap().insert(hash, album);
+        self.albums.write().unwrap().insert(hash, Arc::new(album));
     }
 
-    /// Mark or unmark album as favorite (no user scoping)
-    pub fn mark_favorite(&self, albumhash: &str, favorite: bool) {
-        if let Some(mut album) = self.get_by_hash(albumhash) {
+    /// Mark or unmark an album as favorite for a user
+    pub fn mark_favorite(&self, albumhash: &str, user_id: i64, favorite: bool) {
+        if let Some(album) = self.albums.write().unwrap().get_mut(albumhash) {
+            let album = Arc::make_mut(album);
             if favorite {
-                album.fav_userids.insert(0);
+                album.fav_userids.insert(user_id);
             } else {
-                album.fav_userids.remove(&0);
+                album.fav_userids.remove(&user_id);
             }
-            self.add(album);
         }
     }
 
-    /// Set dominant color for an album
-    pub fn set_color(&self, albumhash: &str, color: &str) {
+    /// Set the MusicBrainz release ID of an album
+    pub fn set_mbid(&self, albumhash: &str, mbid: &str) {
+        if let Some(album) = self.albums.write().unwrap().get_mut(albumhash) {
+            let album = Arc::make_mut(album);
+            album.mbid = mbid.to_string();
+        }
+    }
+
+    /// Set dominant color and its dark and light mode variants for an album
+    pub fn set_color(&self, albumhash: &str, color: &str, variants: &ColorVariants) {
         if let Some(mut album) = self.get_by_hash(albumhash) {
             album.color = color.to_string();
+            album.color_dark = variants.dark.clone();
+            album.color_light = variants.light.clone();
             self.add(album);
         }
     }
@@ -179,13 +245,17 @@ impl AlbumStore {
             }
         }
 
+        SearchStore::get().index_album(&album);
+
         // Update in main map
-        self.albums.write().unwrap().insert(hash, album);
+        self.albums.write().unwrap().insert(hash, Arc::new(album));
     }
 
     /// Remove an album from the store
     pub fn remove(&self, hash: &str) {
         if let Some(album) = self.albums.write().unwrap().remove(hash) {
+            SearchStore::get().remove_album(hash);
+
             let mut artist_map = self.albums_by_artist.write().unwrap();
             for artist in &album.artisthashes {
                 if let Some(artist_albums) = artist_map.get_mut(artist) {
@@ -216,5 +286,6 @@ impl AlbumStore {
     pub fn clear(&self) {
         self.albums.write().unwrap().clear();
         self.albums_by_artist.write().unwrap().clear();
+        SearchStore::get().clear_albums();
     }
 }
@@ -4,8 +4,8 @@ use std::collections::HashMap;
 use std::sync::{Arc, OnceLock, RwLock};
 
 use crate::core::artistlib::ArtistLib;
-use crate::models::Artist;
-use crate::stores::TrackStore;
+use crate::models::{Artist, ColorVariants};
+use crate::stores::{SearchStore, TrackStore};
 use anyhow::Result;
 
 /// Global artist store instance
@@ -14,7 +14,7 @@ static ARTIST_STORE: OnceLock<Arc<ArtistStore>> = OnceLock::new();
 /// In-memory store for artists
 pub struct ArtistStore {
     /// All artists by artisthash
-    artists: RwLock<HashMap<String, Artist>>,
+    artists: RwLock<HashMap<String, Arc<Artist>>>,
     /// Artists by name (lowercase for searching)
     artists_by_name: RwLock<HashMap<String, String>>,
 }
@@ -52,8 +52,10 @@ impl ArtistStore {
             let name = artist.name.to_lowercase();
 
             name_map.insert(name, hash.clone());
-            artist_map.insert(hash, artist);
+            artist_map.insert(hash, Arc::new(artist));
         }
+
+        SearchStore::get().load_artists(artist_map.values().map(Arc::as_ref));
     }
 
     /// Get total artist count
@@ -63,6 +65,16 @@ impl ArtistStore {
 
     /// Get all artists
     pub fn get_all(&self) -> Vec<Artist> {
+        self.artists
+            .read()
+            .unwrap()
+            .values()
+            .map(|a| a.as_ref().clone())
+            .collect()
+    }
+
+    /// Get all artists without copying them
+    pub fn get_all_shared(&self) -> Vec<Arc<Artist>> {
         self.artists.read().unwrap().values().cloned().collect()
     }
 
@@ -73,12 +85,17 @@ impl ArtistStore {
 
     /// Get artist by hash
     pub fn get_by_hash(&self, hash: &str) -> Option<Artist> {
-        self.artists.read().unwrap().get(hash).cloned()
+        self.artists
+            .read()
+            .unwrap()
+            .get(hash)
+            .map(|a| a.as_ref().clone())
     }
 
     /// increment play metrics for an artist in place
     pub fn increment_play_stats(&self, artisthash: &str, duration: i32, timestamp: i64) {
         if let Some(artist) = self.artists.write().unwrap().get_mut(artisthash) {
+            let artist = Arc::make_mut(artist);
             artist.playcount += 1;
             artist.playduration += duration;
             artist.lastplayed = timestamp;
@@ -90,7 +107,7 @@ impl ArtistStore {
         let artists = self.artists.read().unwrap();
         hashes
             .iter()
-            .filter_map(|h| artists.get(h).cloned())
+            .filter_map(|h| artists.get(h).map(|a| a.as_ref().clone()))
             .collect()
     }
 
@@ -126,7 +143,8 @@ impl ArtistStore {
             .write()
             .unwrap()
             .insert(name, hash.clone());
-        self.artists.write().unwrap().insert(hash, artist);
+        SearchStore::get().index_artist(&artist);
+        self.artists.write().unwrap().insert(hash, Arc::new(artist));
     }
 
     /// Update an artist in the store
@@ -146,8 +164,10 @@ impl ArtistStore {
             .unwrap()
             .insert(name, hash.clone());
 
+        SearchStore::get().index_artist(&artist);
+
         // Update main map
-        self.artists.write().unwrap().insert(hash, artist);
+        self.artists.write().unwrap().insert(hash, Arc::new(artist));
     }
 
     /// Remove an artist from the store
@@ -155,6 +175,7 @@ impl ArtistStore {
         if let Some(artist) = self.artists.write().unwrap().remove(hash) {
             let name = artist.name.to_lowercase();
             self.artists_by_name.write().unwrap().remove(&name);
+            SearchStore::get().remove_artist(hash);
         }
     }
 
@@ -175,15 +196,15 @@ impl ArtistStore {
         }
     }
 
-    /// Mark or unmark an artist as favorite (no user scoping)
-    pub fn mark_favorite(&self, artisthash: &str, favorite: bool) {
-        if let Some(mut artist) = self.get_by_hash(artisthash) {
+    /// Mark or unmark an artist as favorite for a user
+    pub fn mark_favorite(&self, artisthash: &str, user_id: i64, favorite: bool) {
+        if let Some(artist) = self.artists.write().unwrap().get_mut(artisthash) {
+            let artist = Arc::make_mut(artist);
             if favorite {
-                artist.fav_userids.insert(0);
+                artist.fav_userids.insert(user_id);
             } else {
-                artist.fav_userids.remove(&0);
+                artist.fav_userids.remove(&user_id);
             }
-            self.add(artist);
         }
     }
 
@@ -195,10 +216,20 @@ impl ArtistStore {
         }
     }
 
-    /// Set color for an artist
-    pub fn set_color(&self, artisthash: &str, color: &str) {
+    /// Set the MusicBrainz artist ID of an artist
+    pub fn set_mbid(&self, artisthash: &str, mbid: &str) {
+        if let Some(artist) = self.artists.write().unwrap().get_mut(artisthash) {
+            let artist = Arc::make_mut(artist);
+            artist.mbid = mbid.to_string();
+        }
+    }
+
+    /// Set color and its dark and light mode variants for an artist
+    pub fn set_color(&self, artisthash: &str, color: &str, variants: &ColorVariants) {
         if let Some(mut artist) = self.get_by_hash(artisthash) {
             artist.color = color.to_string();
+            artist.color_dark = variants.dark.clone();
+            artist.color_light = variants.light.clone();
             self.add(artist);
         }
     }
@@ -215,18 +246,17 @@ impl ArtistStore {
     pub fn clear(&self) {
         self.artists.write().unwrap().clear();
         self.artists_by_name.write().unwrap().clear();
+        SearchStore::get().clear_artists();
     }
 
-    /// Search artists by name (case-insensitive prefix match)
+    /// Search artists by name using the search index
     pub fn search_by_name(&self, query: &str, limit: usize) -> Vec<Artist> {
-        let query_lower = query.to_lowercase();
-        let artists = self.artists.read().unwrap();
+        let hashes: Vec<String> = SearchStore::get()
+            .search_artists(query, limit)
+            .into_iter()
+            .map(|hit| hit.key)
+            .collect();
 
-        artists
-            .values()
-            .filter(|a| a.name.to_lowercase().contains(&query_lower))
-            .take(limit)
-            .cloned()
-            .collect()
+        self.get_by_hashes(&hashes)
     }
 }
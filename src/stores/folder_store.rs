@@ -1,7 +1,8 @@
 //! Folder store - in-memory folder storage for browsing
 
 use anyhow::Result;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::{Arc, OnceLock, RwLock};
 
 use crate::config::UserConfig;
@@ -78,6 +79,54 @@ impl FolderStore {
         *self.root_dirs.write().unwrap() = root_dirs.to_vec();
     }
 
+    /// Bring folders up to date after tracks in them were added or removed,
+    /// adding their parents up to the root and dropping folders left empty
+    pub fn refresh_folders(&self, paths: &HashSet<String>) {
+        let root_dirs = self.get_root_dirs();
+        let track_store = TrackStore::get();
+        let mut folders = self.folders.write().unwrap();
+
+        for path in paths {
+            let count = track_store.count_by_folder(path);
+            if count > 0 {
+                let mut folder = Self::make_folder(path, &[]);
+                folder.trackcount = count as i32;
+                folders.insert(path.clone(), folder);
+
+                let mut current = PathBuf::from(path);
+                while let Some(parent) = current.parent() {
+                    let parent_str = parent.to_string_lossy().to_string();
+                    if !root_dirs.iter().any(|r| parent_str.starts_with(r)) {
+                        break;
+                    }
+                    folders
+                        .entry(parent_str.clone())
+                        .or_insert_with(|| Self::make_folder(&parent_str, &[]));
+                    current = parent.to_path_buf();
+                }
+                continue;
+            }
+
+            // parents go too once they hold neither tracks nor folders
+            if let Some(folder) = folders.get_mut(path) {
+                folder.trackcount = 0;
+            }
+            let mut current = Some(PathBuf::from(path));
+            while let Some(dir) = current {
+                let dir_str = dir.to_string_lossy().to_string();
+                let prefix = format!("{}/", dir_str.trim_end_matches('/'));
+                let emptied = folders.get(&dir_str).is_some_and(|f| {
+                    f.trackcount == 0 && !folders.keys().any(|k| k.starts_with(&prefix))
+                });
+                if !emptied {
+                    break;
+                }
+                folders.remove(&dir_str);
+                current = dir.parent().map(PathBuf::from);
+            }
+        }
+    }
+
     /// Load folders from the track store and user config
     pub async fn load_filepaths() -> Result<()> {
         let config = UserConfig::load()?;
@@ -4,10 +4,17 @@ mod album_store;
 mod artist_store;
 mod folder_store;
 mod homepage_store;
+mod play_stats_store;
+mod playlist_membership_store;
+pub mod readiness;
+mod search_store;
 mod track_store;
 
 pub use album_store::AlbumStore;
 pub use artist_store::ArtistStore;
 pub use folder_store::FolderStore;
 pub use homepage_store::HomepageStore;
+pub use play_stats_store::{PlayRecord, PlayStatsStore};
+pub use playlist_membership_store::PlaylistMembershipStore;
+pub use search_store::SearchStore;
 pub use track_store::TrackStore;
@@ -0,0 +1,299 @@
+//! Play stats store - per-user play counts for tracks, albums and artists
+//!
+//! the library stores hold one copy of each item shared by every user, so the
+//! play metrics they carry are library-wide. this store keeps each user's own
+//! counts and track ratings and overlays them onto items before they are
+//! sorted or serialized.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, OnceLock, RwLock};
+
+use crate::models::{Album, Artist, Track};
+
+/// Global play stats store instance
+static PLAY_STATS_STORE: OnceLock<Arc<PlayStatsStore>> = OnceLock::new();
+
+/// Play metrics for a single item
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PlayStats {
+    pub playcount: i32,
+    pub playduration: i32,
+    pub lastplayed: i64,
+}
+
+impl PlayStats {
+    fn record(&mut self, duration: i32, timestamp: i64) {
+        self.playcount += 1;
+        self.playduration += duration;
+        self.lastplayed = self.lastplayed.max(timestamp);
+    }
+}
+
+/// Play metrics of one user keyed by item hash
+#[derive(Debug, Default)]
+struct UserStats {
+    tracks: HashMap<String, PlayStats>,
+    albums: HashMap<String, PlayStats>,
+    artists: HashMap<String, PlayStats>,
+    /// distinct trackhashes played of each album
+    album_tracks: HashMap<String, HashSet<String>>,
+}
+
+/// A single play used to build the store
+#[derive(Debug, Clone)]
+pub struct PlayRecord<'a> {
+    pub userid: i64,
+    pub trackhash: &'a str,
+    pub albumhash: &'a str,
+    pub artisthashes: &'a [String],
+    pub duration: i32,
+    pub timestamp: i64,
+}
+
+/// In-memory store for per-user play metrics
+pub struct PlayStatsStore {
+    users: RwLock<HashMap<i64, UserStats>>,
+    /// star ratings per user keyed by trackhash
+    ratings: RwLock<HashMap<i64, HashMap<String, u8>>>,
+}
+
+impl PlayStatsStore {
+    /// Get or initialize the global play stats store
+    pub fn get() -> Arc<PlayStatsStore> {
+        PLAY_STATS_STORE
+            .get_or_init(|| {
+                Arc::new(PlayStatsStore {
+                    users: RwLock::new(HashMap::new()),
+                    ratings: RwLock::new(HashMap::new()),
+                })
+            })
+            .clone()
+    }
+
+    /// Replace all stats with the given plays
+    pub fn load<'a>(&self, plays: impl IntoIterator<Item = PlayRecord<'a>>) {
+        let mut users: HashMap<i64, UserStats> = HashMap::new();
+        for play in plays {
+            Self::apply(users.entry(play.userid).or_default(), &play);
+        }
+        *self.users.write().unwrap() = users;
+    }
+
+    /// Replace the stats of one user with the given plays
+    pub fn load_user<'a>(&self, user_id: i64, plays: impl IntoIterator<Item = PlayRecord<'a>>) {
+        let mut stats = UserStats::default();
+        for play in plays.into_iter().filter(|p| p.userid == user_id) {
+            Self::apply(&mut stats, &play);
+        }
+        self.users.write().unwrap().insert(user_id, stats);
+    }
+
+    /// Record a single play
+    pub fn record(&self, play: PlayRecord<'_>) {
+        let mut users = self.users.write().unwrap();
+        Self::apply(users.entry(play.userid).or_default(), &play);
+    }
+
+    fn apply(stats: &mut UserStats, play: &PlayRecord<'_>) {
+        stats
+            .tracks
+            .entry(play.trackhash.to_string())
+            .or_default()
+            .record(play.duration, play.timestamp);
+        stats
+            .albums
+            .entry(play.albumhash.to_string())
+            .or_default()
+            .record(play.duration, play.timestamp);
+        stats
+            .album_tracks
+            .entry(play.albumhash.to_string())
+            .or_default()
+            .insert(play.trackhash.to_string());
+        for artisthash in play.artisthashes {
+            stats
+                .artists
+                .entry(artisthash.clone())
+                .or_default()
+                .record(play.duration, play.timestamp);
+        }
+    }
+
+    /// Replace all ratings with the given (userid, trackhash, rating) rows
+    pub fn load_ratings(&self, ratings: impl IntoIterator<Item = (i64, String, u8)>) {
+        let mut users: HashMap<i64, HashMap<String, u8>> = HashMap::new();
+        for (userid, trackhash, rating) in ratings {
+            users.entry(userid).or_default().insert(trackhash, rating);
+        }
+        *self.ratings.write().unwrap() = users;
+    }
+
+    /// Set or clear the rating a user gave a track
+    pub fn set_rating(&self, user_id: i64, trackhash: &str, rating: Option<u8>) {
+        let mut ratings = self.ratings.write().unwrap();
+        match rating {
+            Some(rating) => {
+                ratings
+                    .entry(user_id)
+                    .or_default()
+                    .insert(trackhash.to_string(), rating);
+            }
+            None => {
+                if let Some(user) = ratings.get_mut(&user_id) {
+                    user.remove(trackhash);
+                }
+            }
+        }
+    }
+
+    /// The rating a user gave a track, None when they have not rated it
+    pub fn rating(&self, user_id: i64, trackhash: &str) -> Option<u8> {
+        self.ratings
+            .read()
+            .unwrap()
+            .get(&user_id)
+            .and_then(|m| m.get(trackhash).copied())
+    }
+
+    /// The albums a user played with the distinct tracks they played of each
+    /// and when the album was last played
+    pub fn played_album_tracks(&self, user_id: i64) -> Vec<(String, HashSet<String>, i64)> {
+        let users = self.users.read().unwrap();
+        let Some(stats) = users.get(&user_id) else {
+            return Vec::new();
+        };
+
+        stats
+            .album_tracks
+            .iter()
+            .map(|(albumhash, tracks)| {
+                let lastplayed = stats.albums.get(albumhash).map_or(0, |s| s.lastplayed);
+                (albumhash.clone(), tracks.clone(), lastplayed)
+            })
+            .collect()
+    }
+
+    /// Overlay a user's stats and ratings onto tracks
+    ///
+    /// tracks the user has not rated keep the rating from their tags
+    pub fn personalize_tracks(&self, user_id: i64, tracks: &mut [Track]) {
+        let users = self.users.read().unwrap();
+        let ratings = self.ratings.read().unwrap();
+        let stats = users.get(&user_id).map(|u| &u.tracks);
+        let rated = ratings.get(&user_id);
+        for track in tracks {
+            let s = stats
+                .and_then(|m| m.get(&track.trackhash).copied())
+                .unwrap_or_default();
+            track.playcount = s.playcount;
+            track.playduration = s.playduration;
+            track.lastplayed = s.lastplayed;
+            track.rating = rated
+                .and_then(|m| m.get(&track.trackhash).copied())
+                .unwrap_or_else(|| track.tag_rating());
+        }
+    }
+
+    /// Overlay a user's stats onto albums
+    pub fn personalize_albums(&self, user_id: i64, albums: &mut [Album]) {
+        let users = self.users.read().unwrap();
+        let stats = users.get(&user_id).map(|u| &u.albums);
+        for album in albums {
+            let s = stats
+                .and_then(|m| m.get(&album.albumhash).copied())
+                .unwrap_or_default();
+            album.playcount = s.playcount;
+            album.playduration = s.playduration;
+            album.lastplayed = s.lastplayed;
+        }
+    }
+
+    /// Overlay a user's stats onto artists
+    pub fn personalize_artists(&self, user_id: i64, artists: &mut [Artist]) {
+        let users = self.users.read().unwrap();
+        let stats = users.get(&user_id).map(|u| &u.artists);
+        for artist in artists {
+            let s = stats
+                .and_then(|m| m.get(&artist.artisthash).copied())
+                .unwrap_or_default();
+            artist.playcount = s.playcount;
+            artist.playduration = s.playduration;
+            artist.lastplayed = s.lastplayed;
+        }
+    }
+
+    /// Overlay a user's stats onto shared tracks, copying only the tracks
+    /// whose stats differ from the ones they carry
+    pub fn personalize_shared_tracks(&self, user_id: i64, tracks: &mut [Arc<Track>]) {
+        let users = self.users.read().unwrap();
+        let ratings = self.ratings.read().unwrap();
+        let stats = users.get(&user_id).map(|u| &u.tracks);
+        let rated = ratings.get(&user_id);
+        for track in tracks {
+            let s = stats
+                .and_then(|m| m.get(&track.trackhash).copied())
+                .unwrap_or_default();
+            let rating = rated
+                .and_then(|m| m.get(&track.trackhash).copied())
+                .unwrap_or_else(|| track.tag_rating());
+            let current = PlayStats {
+                playcount: track.playcount,
+                playduration: track.playduration,
+                lastplayed: track.lastplayed,
+            };
+            if s != current || rating != track.rating {
+                let track = Arc::make_mut(track);
+                track.playcount = s.playcount;
+                track.playduration = s.playduration;
+                track.lastplayed = s.lastplayed;
+                track.rating = rating;
+            }
+        }
+    }
+
+    /// Overlay a user's stats onto shared albums, copying only the albums
+    /// whose stats differ from the ones they carry
+    pub fn personalize_shared_albums(&self, user_id: i64, albums: &mut [Arc<Album>]) {
+        let users = self.users.read().unwrap();
+        let stats = users.get(&user_id).map(|u| &u.albums);
+        for album in albums {
+            let s = stats
+                .and_then(|m| m.get(&album.albumhash).copied())
+                .unwrap_or_default();
+            let current = PlayStats {
+                playcount: album.playcount,
+                playduration: album.playduration,
+                lastplayed: album.lastplayed,
+            };
+            if s != current {
+                let album = Arc::make_mut(album);
+                album.playcount = s.playcount;
+                album.playduration = s.playduration;
+                album.lastplayed = s.lastplayed;
+            }
+        }
+    }
+
+    /// Overlay a user's stats onto shared artists, copying only the artists
+    /// whose stats differ from the ones they carry
+    pub fn personalize_shared_artists(&self, user_id: i64, artists: &mut [Arc<Artist>]) {
+        let users = self.users.read().unwrap();
+        let stats = users.get(&user_id).map(|u| &u.artists);
+        for artist in artists {
+            let s = stats
+                .and_then(|m| m.get(&artist.artisthash).copied())
+                .unwrap_or_default();
+            let current = PlayStats {
+                playcount: artist.playcount,
+                playduration: artist.playduration,
+                lastplayed: artist.lastplayed,
+            };
+            if s != current {
+                let artist = Arc::make_mut(artist);
+                artist.playcount = s.playcount;
+                artist.playduration = s.playduration;
+                artist.lastplayed = s.lastplayed;
+            }
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Playlist membership store - which playlists contain a track
+//!
+//! playlists keep their tracks as a list of hashes, so finding the playlists a
+//! track is in would mean reading every playlist. this store keeps the inverted
+//! map and is updated by the playlist table whenever a playlist's tracks change.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, OnceLock, RwLock};
+
+use crate::models::Playlist;
+
+/// Global playlist membership store instance
+static PLAYLIST_MEMBERSHIP_STORE: OnceLock<Arc<PlaylistMembershipStore>> = OnceLock::new();
+
+#[derive(Debug, Default)]
+struct Memberships {
+    /// playlist id to owner and track hashes
+    playlists: HashMap<i64, (i64, HashSet<String>)>,
+    /// track hash to playlist ids
+    tracks: HashMap<String, HashSet<i64>>,
+}
+
+impl Memberships {
+    fn set(&mut self, id: i64, userid: i64, trackhashes: &[String]) {
+        self.remove(id);
+        let hashes: HashSet<String> = trackhashes.iter().cloned().collect();
+        for hash in &hashes {
+            self.tracks.entry(hash.clone()).or_default().insert(id);
+        }
+        self.playlists.insert(id, (userid, hashes));
+    }
+
+    fn remove(&mut self, id: i64) -> Option<i64> {
+        let (userid, hashes) = self.playlists.remove(&id)?;
+        for hash in hashes {
+            if let Some(ids) = self.tracks.get_mut(&hash) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.tracks.remove(&hash);
+                }
+            }
+        }
+        Some(userid)
+    }
+}
+
+/// In-memory inverted map from tracks to the playlists containing them
+pub struct PlaylistMembershipStore {
+    inner: RwLock<Memberships>,
+}
+
+impl PlaylistMembershipStore {
+    /// Get or initialize the global playlist membership store
+    pub fn get() -> Arc<PlaylistMembershipStore> {
+        PLAYLIST_MEMBERSHIP_STORE
+            .get_or_init(|| {
+                Arc::new(PlaylistMembershipStore {
+                    inner: RwLock::new(Memberships::default()),
+                })
+            })
+            .clone()
+    }
+
+    /// Replace all memberships with the given playlists
+    pub fn load(&self, playlists: &[Playlist]) {
+        let mut inner = Memberships::default();
+        for playlist in playlists {
+            inner.set(
+                playlist.id,
+                playlist.userid.unwrap_or(1),
+                &playlist.trackhashes,
+            );
+        }
+        *self.inner.write().unwrap() = inner;
+    }
+
+    /// Record the tracks of a playlist owned by `userid`
+    pub fn set(&self, id: i64, userid: i64, trackhashes: &[String]) {
+        self.inner.write().unwrap().set(id, userid, trackhashes);
+    }
+
+    /// Record the new tracks of a playlist, keeping its known owner
+    pub fn set_tracks(&self, id: i64, userid: Option<i64>, trackhashes: &[String]) {
+        let mut inner = self.inner.write().unwrap();
+        let owner = userid
+            .or_else(|| inner.playlists.get(&id).map(|(owner, _)| *owner))
+            .unwrap_or(1);
+        inner.set(id, owner, trackhashes);
+    }
+
+    /// Forget a deleted playlist
+    pub fn remove(&self, id: i64) {
+        self.inner.write().unwrap().remove(id);
+    }
+
+    /// Ids of the user's playlists containing the track, in ascending order
+    pub fn playlists_with(&self, trackhash: &str, userid: i64) -> Vec<i64> {
+        let inner = self.inner.read().unwrap();
+        let mut ids: Vec<i64> = inner
+            .tracks
+            .get(trackhash)
+            .into_iter()
+            .flatten()
+            .filter(|id| {
+                inner
+                    .playlists
+                    .get(id)
+                    .is_some_and(|(owner, _)| *owner == userid)
+            })
+            .copied()
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hashes(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_memberships_follow_playlist_changes() {
+        let store = PlaylistMembershipStore {
+            inner: RwLock::new(Memberships::default()),
+        };
+        store.set(1, 7, &hashes(&["a", "b", "a"]));
+        store.set(2, 7, &hashes(&["b"]));
+        store.set(3, 8, &hashes(&["a"]));
+
+        assert_eq!(store.playlists_with("a", 7), [1]);
+        assert_eq!(store.playlists_with("b", 7), [1, 2]);
+        assert_eq!(store.playlists_with("a", 8), [3]);
+
+        // the owner is kept when only the tracks are known
+        store.set_tracks(1, None, &hashes(&["c"]));
+        assert!(store.playlists_with("a", 7).is_empty());
+        assert_eq!(store.playlists_with("b", 7), [2]);
+        assert_eq!(store.playlists_with("c", 7), [1]);
+
+        store.remove(2);
+        assert!(store.playlists_with("b", 7).is_empty());
+        assert!(!store.inner.read().unwrap().tracks.contains_key("b"));
+    }
+}
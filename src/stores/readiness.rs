@@ -0,0 +1,64 @@
+//! Store readiness - which in-memory stores finished loading
+//!
+//! the server starts accepting requests before the stores are filled, so
+//! clients can tell a library that is still loading from an empty one. library
+//! routes answer 503 until the track store is loaded, the rest of the flags are
+//! reported by `/about`.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Parts of the in-memory library, in the order they are loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreKind {
+    Tracks,
+    Albums,
+    Artists,
+    Folders,
+    Playlists,
+    /// favorites, colors, musicbrainz ids and play counts mapped onto items
+    Metadata,
+}
+
+const KINDS: usize = 6;
+
+static LOADED: [AtomicBool; KINDS] = [const { AtomicBool::new(false) }; KINDS];
+
+/// How long clients are told to wait before retrying a library route
+pub const RETRY_AFTER_SECS: u64 = 5;
+
+/// Loaded flags of every store
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct StoreStatus {
+    pub tracks: bool,
+    pub albums: bool,
+    pub artists: bool,
+    pub folders: bool,
+    pub playlists: bool,
+    pub metadata: bool,
+}
+
+/// Record that a store finished loading
+pub fn mark_loaded(kind: StoreKind) {
+    LOADED[kind as usize].store(true, Ordering::Release);
+}
+
+pub fn is_loaded(kind: StoreKind) -> bool {
+    LOADED[kind as usize].load(Ordering::Acquire)
+}
+
+/// Whether the minimum data set for library routes is loaded
+pub fn is_ready() -> bool {
+    is_loaded(StoreKind::Tracks)
+}
+
+pub fn status() -> StoreStatus {
+    StoreStatus {
+        tracks: is_loaded(StoreKind::Tracks),
+        albums: is_loaded(StoreKind::Albums),
+        artists: is_loaded(StoreKind::Artists),
+        folders: is_loaded(StoreKind::Folders),
+        playlists: is_loaded(StoreKind::Playlists),
+        metadata: is_loaded(StoreKind::Metadata),
+    }
+}
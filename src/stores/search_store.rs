@@ -0,0 +1,156 @@
+//! Search store - inverted indexes over tracks, albums and artists
+//!
+//! the indexes are rebuilt whenever a store is (re)loaded and kept in sync by the
+//! stores' add/update/remove methods so queries never scan the whole library.
+
+use std::sync::{Arc, OnceLock, RwLock};
+
+use crate::core::search_index::{SearchHit, SearchIndex};
+use crate::models::{Album, Artist, Track};
+
+/// Global search store instance
+static SEARCH_STORE: OnceLock<Arc<SearchStore>> = OnceLock::new();
+
+/// field weights for track documents
+const TRACK_TITLE_WEIGHT: f32 = 1.0;
+const TRACK_ARTIST_WEIGHT: f32 = 0.6;
+const TRACK_ALBUM_WEIGHT: f32 = 0.4;
+
+/// field weights for album documents
+const ALBUM_TITLE_WEIGHT: f32 = 1.0;
+const ALBUM_ARTIST_WEIGHT: f32 = 0.5;
+
+/// In-memory search indexes
+pub struct SearchStore {
+    tracks: RwLock<SearchIndex>,
+    albums: RwLock<SearchIndex>,
+    artists: RwLock<SearchIndex>,
+}
+
+impl SearchStore {
+    /// Get or initialize the global search store
+    pub fn get() -> Arc<SearchStore> {
+        SEARCH_STORE
+            .get_or_init(|| {
+                Arc::new(SearchStore {
+                    tracks: RwLock::new(SearchIndex::new()),
+                    albums: RwLock::new(SearchIndex::new()),
+                    artists: RwLock::new(SearchIndex::new()),
+                })
+            })
+            .clone()
+    }
+
+    /// Rebuild the track index from scratch
+    pub fn load_tracks<'a>(&self, tracks: impl IntoIterator<Item = &'a Track>) {
+        let mut index = self.tracks.write().unwrap();
+        index.clear();
+        for track in tracks {
+            insert_track(&mut index, track);
+        }
+    }
+
+    /// Index or re-index a single track
+    pub fn index_track(&self, track: &Track) {
+        insert_track(&mut self.tracks.write().unwrap(), track);
+    }
+
+    /// Drop a track from the index
+    pub fn remove_track(&self, trackhash: &str) {
+        self.tracks.write().unwrap().remove(trackhash);
+    }
+
+    /// Rebuild the album index from scratch
+    pub fn load_albums<'a>(&self, albums: impl IntoIterator<Item = &'a Album>) {
+        let mut index = self.albums.write().unwrap();
+        index.clear();
+        for album in albums {
+            insert_album(&mut index, album);
+        }
+    }
+
+    /// Index or re-index a single album
+    pub fn index_album(&self, album: &Album) {
+        insert_album(&mut self.albums.write().unwrap(), album);
+    }
+
+    /// Drop an album from the index
+    pub fn remove_album(&self, albumhash: &str) {
+        self.albums.write().unwrap().remove(albumhash);
+    }
+
+    /// Rebuild the artist index from scratch
+    pub fn load_artists<'a>(&self, artists: impl IntoIterator<Item = &'a Artist>) {
+        let mut index = self.artists.write().unwrap();
+        index.clear();
+        for artist in artists {
+            index.insert(&artist.artisthash, &[(&artist.name, 1.0)]);
+        }
+    }
+
+    /// Index or re-index a single artist
+    pub fn index_artist(&self, artist: &Artist) {
+        self.artists
+            .write()
+            .unwrap()
+            .insert(&artist.artisthash, &[(&artist.name, 1.0)]);
+    }
+
+    /// Drop an artist from the index
+    pub fn remove_artist(&self, artisthash: &str) {
+        self.artists.write().unwrap().remove(artisthash);
+    }
+
+    /// Search track hashes
+    pub fn search_tracks(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        self.tracks.read().unwrap().search(query, limit)
+    }
+
+    /// Search album hashes
+    pub fn search_albums(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        self.albums.read().unwrap().search(query, limit)
+    }
+
+    /// Search artist hashes
+    pub fn search_artists(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        self.artists.read().unwrap().search(query, limit)
+    }
+
+    /// Clear the track index
+    pub fn clear_tracks(&self) {
+        self.tracks.write().unwrap().clear();
+    }
+
+    /// Clear the album index
+    pub fn clear_albums(&self) {
+        self.albums.write().unwrap().clear();
+    }
+
+    /// Clear the artist index
+    pub fn clear_artists(&self) {
+        self.artists.write().unwrap().clear();
+    }
+}
+
+fn insert_track(index: &mut SearchIndex, track: &Track) {
+    let artist = track.artist();
+    index.insert(
+        &track.trackhash,
+        &[
+            (&track.title, TRACK_TITLE_WEIGHT),
+            (&artist, TRACK_ARTIST_WEIGHT),
+            (&track.album, TRACK_ALBUM_WEIGHT),
+        ],
+    );
+}
+
+fn insert_album(index: &mut SearchIndex, album: &Album) {
+    let albumartist = album.albumartist();
+    index.insert(
+        &album.albumhash,
+        &[
+            (&album.title, ALBUM_TITLE_WEIGHT),
+            (&albumartist, ALBUM_ARTIST_WEIGHT),
+        ],
+    );
+}
@@ -1,6 +1,9 @@
 //! Track store - in-memory track storage with efficient lookups
+//!
+//! tracks are kept behind `Arc`s so readers can hold on to them without
+//! copying, edits copy a track only while someone else still holds it.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, OnceLock, RwLock};
 
 use crate::db::tables::TrackTable;
@@ -8,22 +11,184 @@ use crate::utils::filesystem::normalize_path;
 use anyhow::Result;
 
 use crate::models::Track;
+use crate::stores::SearchStore;
 
 /// Global track store instance
 static TRACK_STORE: OnceLock<Arc<TrackStore>> = OnceLock::new();
 
+/// Tracks and the indexes over them, behind one lock so they never disagree
+#[derive(Default)]
+struct TrackIndex {
+    /// All tracks by trackhash
+    tracks: HashMap<String, Arc<Track>>,
+    /// Trackhash by filepath, copies of a track in several files all point to it
+    by_path: HashMap<String, String>,
+    /// Trackhashes by album hash
+    by_album: HashMap<String, Vec<String>>,
+    /// Trackhashes by the hash of every track and album artist
+    by_artist: HashMap<String, Vec<String>>,
+    /// Trackhashes by folder path
+    by_folder: HashMap<String, Vec<String>>,
+    /// Trackhashes by genre hash
+    by_genre: HashMap<String, Vec<String>>,
+}
+
+impl TrackIndex {
+    /// Add a track, replacing the track with the same hash
+    fn insert(&mut self, mut track: Track) -> Arc<Track> {
+        // normalize paths so lookups remain consistent across os path separators
+        track.filepath = normalize_path(&track.filepath);
+        track.folder = normalize_path(&track.folder);
+
+        // generate album art image path if not already set
+        if track.image.is_empty() {
+            track.generate_image();
+        }
+
+        let hash = track.trackhash.clone();
+        // the same file again is unindexed first, another copy of the track
+        // keeps its entries and only adds the ones it is missing
+        let replaced = match self.tracks.get(&hash) {
+            Some(old) if old.filepath == track.filepath => {
+                let old = old.clone();
+                self.unindex(&old);
+                false
+            }
+            Some(_) => true,
+            None => false,
+        };
+
+        self.by_path.insert(track.filepath.clone(), hash.clone());
+        let keys = [
+            (&mut self.by_album, vec![track.albumhash.clone()]),
+            (
+                &mut self.by_artist,
+                artist_keys(&track).into_iter().collect(),
+            ),
+            (&mut self.by_folder, vec![track.folder.clone()]),
+            (&mut self.by_genre, genre_keys(&track).into_iter().collect()),
+        ];
+        for (map, keys) in keys {
+            for key in keys {
+                let hashes = map.entry(key).or_default();
+                if !replaced || !hashes.contains(&hash) {
+                    hashes.push(hash.clone());
+                }
+            }
+        }
+
+        let track = Arc::new(track);
+        self.tracks.insert(hash, track.clone());
+        track
+    }
+
+    /// Take a track out of every index but the main map
+    fn unindex(&mut self, track: &Track) {
+        if self.by_path.get(&track.filepath) == Some(&track.trackhash) {
+            self.by_path.remove(&track.filepath);
+        }
+        let hash = &track.trackhash;
+        retain_without(&mut self.by_album, &track.albumhash, |h| h != hash);
+        for key in artist_keys(track) {
+            retain_without(&mut self.by_artist, &key, |h| h != hash);
+        }
+        retain_without(&mut self.by_folder, &track.folder, |h| h != hash);
+        for key in genre_keys(track) {
+            retain_without(&mut self.by_genre, &key, |h| h != hash);
+        }
+    }
+
+    /// Remove tracks by hash, clearing each index entry they are in once
+    fn remove_many(&mut self, hashes: &HashSet<String>) -> Vec<Arc<Track>> {
+        let removed: Vec<Arc<Track>> = hashes
+            .iter()
+            .filter_map(|h| self.tracks.remove(h))
+            .collect();
+
+        let mut albums = HashSet::new();
+        let mut artists = HashSet::new();
+        let mut folders = HashSet::new();
+        let mut genres = HashSet::new();
+        for track in &removed {
+            if self.by_path.get(&track.filepath) == Some(&track.trackhash) {
+                self.by_path.remove(&track.filepath);
+            }
+            albums.insert(track.albumhash.clone());
+            artists.extend(artist_keys(track));
+            folders.insert(track.folder.clone());
+            genres.extend(genre_keys(track));
+        }
+
+        let keep = |h: &String| !hashes.contains(h);
+        for key in albums {
+            retain_without(&mut self.by_album, &key, keep);
+        }
+        for key in artists {
+            retain_without(&mut self.by_artist, &key, keep);
+        }
+        for key in folders {
+            retain_without(&mut self.by_folder, &key, keep);
+        }
+        for key in genres {
+            retain_without(&mut self.by_genre, &key, keep);
+        }
+        removed
+    }
+
+    fn get_many(&self, hashes: &[String]) -> Vec<Arc<Track>> {
+        hashes
+            .iter()
+            .filter_map(|h| self.tracks.get(h).cloned())
+            .collect()
+    }
+
+    fn hash_by_path(&self, path: &str) -> Option<&String> {
+        self.by_path
+            .get(path)
+            .or_else(|| self.by_path.get(&normalize_path(path)))
+    }
+}
+
+/// Every artist a track is indexed under, track and album artists alike
+fn artist_keys(track: &Track) -> HashSet<String> {
+    track
+        .artists
+        .iter()
+        .chain(&track.albumartists)
+        .map(|a| a.artisthash.clone())
+        .chain(track.artisthashes.iter().cloned())
+        .collect()
+}
+
+/// Every genre a track is indexed under
+fn genre_keys(track: &Track) -> HashSet<String> {
+    track
+        .genres
+        .iter()
+        .map(|g| g.genrehash.clone())
+        .chain(track.genrehashes.iter().cloned())
+        .collect()
+}
+
+/// Keep the hashes of an index entry that pass, dropping the entry once empty
+fn retain_without(
+    map: &mut HashMap<String, Vec<String>>,
+    key: &str,
+    keep: impl FnMut(&String) -> bool,
+) {
+    if let Some(hashes) = map.get_mut(key) {
+        hashes.retain(keep);
+        if hashes.is_empty() {
+            map.remove(key);
+        }
+    }
+}
+
 /// In-memory store for tracks
 pub struct TrackStore {
-    /// All tracks by trackhash
-    tracks: RwLock<HashMap<String, Track>>,
-    /// Tracks by filepath
-    tracks_by_path: RwLock<HashMap<String, String>>,
-    /// Tracks by album hash
-    tracks_by_album: RwLock<HashMap<String, Vec<String>>>,
-    /// Tracks by artist hash
-    tracks_by_artist: RwLock<HashMap<String, Vec<String>>>,
-    /// Tracks by folder path
-    tracks_by_folder: RwLock<HashMap<String, Vec<String>>>,
+    index: RwLock<TrackIndex>,
+    /// Albums grouped into each virtual album
+    album_groups: RwLock<HashMap<String, Vec<String>>>,
 }
 
 impl TrackStore {
@@ -32,11 +197,8 @@ impl TrackStore {
         TRACK_STORE
             .get_or_init(|| {
                 Arc::new(TrackStore {
-                    tracks: RwLock::new(HashMap::new()),
-                    tracks_by_path: RwLock::new(HashMap::new()),
-                    tracks_by_album: RwLock::new(HashMap::new()),
-                    tracks_by_artist: RwLock::new(HashMap::new()),
-                    tracks_by_folder: RwLock::new(HashMap::new()),
+                    index: RwLock::new(TrackIndex::default()),
+                    album_groups: RwLock::new(HashMap::new()),
                 })
             })
             .clone()
@@ -44,331 +206,232 @@ impl TrackStore {
 
     /// Load tracks from database into memory
     pub fn load(&self, tracks: Vec<Track>) {
-        let mut track_map = self.tracks.write().unwrap();
-        let mut path_map = self.tracks_by_path.write().unwrap();
-        let mut album_map = self.tracks_by_album.write().unwrap();
-        let mut artist_map = self.tracks_by_artist.write().unwrap();
-        let mut folder_map = self.tracks_by_folder.write().unwrap();
-
-        track_map.clear();
-        path_map.clear();
-        album_map.clear();
-        artist_map.clear();
-        folder_map.clear();
+        let mut index = self.index.write().unwrap();
+        *index = TrackIndex::default();
         for track in tracks {
-            let mut track = track;
-
-            // normalize paths so lookups remain consistent across os path separators
-            track.filepath = normalize_path(&track.filepath);
-            track.folder = normalize_path(&track.folder);
-
-            // generate album art image path if not already set
-            if track.image.is_empty() {
-                track.generate_image();
-            }
-
-            let hash = track.trackhash.clone();
-            let path = track.filepath.clone();
-            let album = track.albumhash.clone();
-            let folder = track.folder.clone();
-
-            // Index by path
-            path_map.insert(path, hash.clone());
-
-            // Index by album
-            album_map
-                .entry(album)
-                .or_insert_with(Vec::new)
-                .push(hash.clone());
-
-            // index by all artists associated with this track (both track artists and album artists)
-            // use a set to avoid duplicate entries when an artist appears in both roles
-            let mut all_artist_hashes: std::collections::HashSet<String> = std::collections::HashSet::new();
-            
-            // include track artists from the artists list
-            for artist_ref in &track.artists {
-                all_artist_hashes.insert(artist_ref.artisthash.clone());
-            }
-            
-            // include album artists
-            for album_artist in &track.albumartists {
-                all_artist_hashes.insert(album_artist.artisthash.clone());
-            }
-            
-            // also include any hashes from the precomputed artisthashes field (in case of discrepancies)
-            for artist_hash in &track.artisthashes {
-                all_artist_hashes.insert(artist_hash.clone());
-            }
-            
-            // index track under all associated artists
-            for artist_hash in all_artist_hashes {
-                artist_map
-                    .entry(artist_hash)
-                    .or_insert_with(Vec::new)
-                    .push(hash.clone());
-            }
-
-            // Index by folder
-            folder_map
-                .entry(folder)
-                .or_insert_with(Vec::new)
-                .push(hash.clone());
-
-            track_map.insert(hash, track);
+            index.insert(track);
         }
+
+        SearchStore::get().load_tracks(index.tracks.values().map(|t| t.as_ref()));
     }
 
     /// Get total track count
     pub fn count(&self) -> usize {
-        self.tracks.read().unwrap().len()
+        self.index.read().unwrap().tracks.len()
     }
 
     /// Get all tracks
     pub fn get_all(&self) -> Vec<Track> {
-        self.tracks.read().unwrap().values().cloned().collect()
+        self.map_all(|t| t.as_ref().clone())
+    }
+
+    /// Get all tracks without copying them
+    pub fn get_all_shared(&self) -> Vec<Arc<Track>> {
+        self.map_all(Arc::clone)
+    }
+
+    fn map_all<T>(&self, f: impl FnMut(&Arc<Track>) -> T) -> Vec<T> {
+        self.index.read().unwrap().tracks.values().map(f).collect()
     }
 
     /// Get all track hashes
     pub fn get_all_hashes(&self) -> Vec<String> {
-        self.tracks.read().unwrap().keys().cloned().collect()
+        self.index.read().unwrap().tracks.keys().cloned().collect()
     }
 
     /// Get track by hash
     pub fn get_by_hash(&self, hash: &str) -> Option<Track> {
-        self.tracks.read().unwrap().get(hash).cloned()
+        self.get_shared(hash).map(Arc::unwrap_or_clone)
+    }
+
+    /// Get track by hash without copying it
+    pub fn get_shared(&self, hash: &str) -> Option<Arc<Track>> {
+        self.index.read().unwrap().tracks.get(hash).cloned()
     }
 
     /// Get only the filepath for a track by hash (avoids cloning full Track)
     pub fn get_filepath_by_hash(&self, hash: &str) -> Option<String> {
-        self.tracks
+        self.index
             .read()
             .unwrap()
+            .tracks
             .get(hash)
             .map(|t| t.filepath.clone())
     }
 
     /// Check if a track exists by hash (no cloning)
     pub fn exists(&self, hash: &str) -> bool {
-        self.tracks.read().unwrap().contains_key(hash)
+        self.index.read().unwrap().tracks.contains_key(hash)
     }
 
     /// increment play metrics for a track in place
     pub fn increment_play_stats(&self, trackhash: &str, duration: i32, timestamp: i64) {
-        if let Some(track) = self.tracks.write().unwrap().get_mut(trackhash) {
+        self.modify(trackhash, |track| {
             track.playcount += 1;
             track.playduration += duration;
             track.lastplayed = timestamp;
+        });
+    }
+
+    /// Edit a stored track, copying it first if a reader still holds it
+    fn modify(&self, trackhash: &str, f: impl FnOnce(&mut Track)) {
+        if let Some(track) = self.index.write().unwrap().tracks.get_mut(trackhash) {
+            f(Arc::make_mut(track));
         }
     }
 
     /// Get tracks by hashes
     pub fn get_by_hashes(&self, hashes: &[String]) -> Vec<Track> {
-        let tracks = self.tracks.read().unwrap();
-        hashes
-            .iter()
-            .filter_map(|h| tracks.get(h).cloned())
-            .collect()
+        owned(self.get_shared_by_hashes(hashes))
+    }
+
+    /// Get tracks by hashes without copying them
+    pub fn get_shared_by_hashes(&self, hashes: &[String]) -> Vec<Arc<Track>> {
+        self.index.read().unwrap().get_many(hashes)
     }
 
     /// Get track by filepath
     pub fn get_by_path(&self, path: &str) -> Option<Track> {
-        let path_map = self.tracks_by_path.read().unwrap();
-        let normalized = normalize_path(path);
+        let index = self.index.read().unwrap();
+        let hash = index.hash_by_path(path)?;
+        index.tracks.get(hash).map(|t| t.as_ref().clone())
+    }
 
-        if let Some(hash) = path_map.get(path).or_else(|| path_map.get(&normalized)) {
-            self.get_by_hash(hash)
-        } else {
-            None
-        }
+    /// Replace the albums grouped into virtual albums
+    pub fn set_album_groups(&self, groups: HashMap<String, Vec<String>>) {
+        *self.album_groups.write().unwrap() = groups;
     }
 
-    /// Get tracks by album hash
+    /// Get tracks by album hash, a virtual album gets the tracks of its albums
     pub fn get_by_album(&self, album_hash: &str) -> Vec<Track> {
-        let album_map = self.tracks_by_album.read().unwrap();
-        if let Some(hashes) = album_map.get(album_hash) {
-            self.get_by_hashes(hashes)
-        } else {
-            Vec::new()
+        owned(self.get_shared_by_album(album_hash))
+    }
+
+    /// Get tracks by album hash without copying them
+    pub fn get_shared_by_album(&self, album_hash: &str) -> Vec<Arc<Track>> {
+        let group = self.album_groups.read().unwrap().get(album_hash).cloned();
+        if let Some(members) = group {
+            return members
+                .iter()
+                .flat_map(|h| self.get_shared_by_album(h))
+                .collect();
+        }
+
+        let index = self.index.read().unwrap();
+        match index.by_album.get(album_hash) {
+            Some(hashes) => index.get_many(hashes),
+            None => Vec::new(),
         }
     }
 
     /// Get tracks by artist hash
     pub fn get_by_artist(&self, artist_hash: &str) -> Vec<Track> {
-        let artist_map = self.tracks_by_artist.read().unwrap();
-        if let Some(hashes) = artist_map.get(artist_hash) {
-            self.get_by_hashes(hashes)
-        } else {
-            Vec::new()
-        }
+        owned(self.get_shared_by_artist(artist_hash))
     }
 
-    /// Get tracks by folder path
-    pub fn get_by_folder(&self, folder: &str) -> Vec<Track> {
-        let folder_map = self.tracks_by_folder.read().unwrap();
-        let normalized = normalize_path(folder);
-
-        if let Some(hashes) = folder_map
-            .get(folder)
-            .or_else(|| folder_map.get(&normalized))
-        {
-            self.get_by_hashes(hashes)
-        } else {
-            Vec::new()
+    /// Get tracks by artist hash without copying them
+    pub fn get_shared_by_artist(&self, artist_hash: &str) -> Vec<Arc<Track>> {
+        let index = self.index.read().unwrap();
+        match index.by_artist.get(artist_hash) {
+            Some(hashes) => index.get_many(hashes),
+            None => Vec::new(),
         }
     }
 
-    /// Check if path exists
-    pub fn path_exists(&self, path: &str) -> bool {
-        let normalized = normalize_path(path);
-        let map = self.tracks_by_path.read().unwrap();
-        map.contains_key(path) || map.contains_key(&normalized)
+    /// Get tracks by genre hash without copying them
+    pub fn get_shared_by_genre(&self, genrehash: &str) -> Vec<Arc<Track>> {
+        let index = self.index.read().unwrap();
+        match index.by_genre.get(genrehash) {
+            Some(hashes) => index.get_many(hashes),
+            None => Vec::new(),
+        }
     }
 
-    /// Get all filepaths
-    pub fn get_all_paths(&self) -> Vec<String> {
-        self.tracks_by_path
+    /// Hashes of every genre with at least one track
+    pub fn get_genre_hashes(&self) -> Vec<String> {
+        self.index
             .read()
             .unwrap()
+            .by_genre
             .keys()
             .cloned()
             .collect()
     }
 
-    /// Add a track to the store
-    pub fn add(&self, mut track: Track) {
-        // normalize paths to match filesystem queries regardless of separator style
-        track.filepath = normalize_path(&track.filepath);
-        track.folder = normalize_path(&track.folder);
-
-        // generate album art image path if not already set
-        if track.image.is_empty() {
-            track.generate_image();
+    /// Get tracks by folder path
+    pub fn get_by_folder(&self, folder: &str) -> Vec<Track> {
+        let index = self.index.read().unwrap();
+        match index
+            .by_folder
+            .get(folder)
+            .or_else(|| index.by_folder.get(&normalize_path(folder)))
+        {
+            Some(hashes) => owned(index.get_many(hashes)),
+            None => Vec::new(),
         }
+    }
 
-        let hash = track.trackhash.clone();
-        let path = track.filepath.clone();
-        let album = track.albumhash.clone();
-        let folder = track.folder.clone();
-
-        // Add to path index
-        self.tracks_by_path
-            .write()
-            .unwrap()
-            .insert(path, hash.clone());
+    /// Count the tracks in a folder without reading them
+    pub fn count_by_folder(&self, folder: &str) -> usize {
+        let index = self.index.read().unwrap();
+        index
+            .by_folder
+            .get(folder)
+            .or_else(|| index.by_folder.get(&normalize_path(folder)))
+            .map_or(0, Vec::len)
+    }
 
-        // Add to album index
-        self.tracks_by_album
-            .write()
-            .unwrap()
-            .entry(album)
-            .or_insert_with(Vec::new)
-            .push(hash.clone());
-
-        // add to artist indices (all artists associated with this track)
-        let mut all_artist_hashes: std::collections::HashSet<String> = std::collections::HashSet::new();
-        for artist_ref in &track.artists {
-            all_artist_hashes.insert(artist_ref.artisthash.clone());
-        }
-        for album_artist in &track.albumartists {
-            all_artist_hashes.insert(album_artist.artisthash.clone());
-        }
-        for artist_hash in &track.artisthashes {
-            all_artist_hashes.insert(artist_hash.clone());
-        }
-        for artist_hash in all_artist_hashes {
-            self.tracks_by_artist
-                .write()
-                .unwrap()
-                .entry(artist_hash)
-                .or_insert_with(Vec::new)
-                .push(hash.clone());
-        }
+    /// Check if path exists
+    pub fn path_exists(&self, path: &str) -> bool {
+        self.index.read().unwrap().hash_by_path(path).is_some()
+    }
 
-        // Add to folder index
-        self.tracks_by_folder
-            .write()
-            .unwrap()
-            .entry(folder)
-            .or_insert_with(Vec::new)
-            .push(hash.clone());
+    /// Get all filepaths
+    pub fn get_all_paths(&self) -> Vec<String> {
+        self.index.read().unwrap().by_path.keys().cloned().collect()
+    }
 
-        // Add to main map
-        self.tracks.write().unwrap().insert(hash, track);
+    /// Add a track to the store
+    pub fn add(&self, track: Track) {
+        let track = self.index.write().unwrap().insert(track);
+        SearchStore::get().index_track(&track);
     }
 
     /// Remove a track by hash and update indices
     pub fn remove(&self, trackhash: &str) -> bool {
-        let mut tracks = self.tracks.write().unwrap();
-        if let Some(track) = tracks.remove(trackhash) {
-            // remove path index
-            self.tracks_by_path
-                .write()
-                .unwrap()
-                .retain(|_, h| h != trackhash);
-            // remove album index
-            if let Some(album_tracks) = self
-                .tracks_by_album
-                .write()
-                .unwrap()
-                .get_mut(&track.albumhash)
-            {
-                album_tracks.retain(|h| h != trackhash);
-            }
-            // remove from all artist indices
-            {
-                let mut artist_map = self.tracks_by_artist.write().unwrap();
-                // remove from track artists
-                for artist_ref in &track.artists {
-                    if let Some(vec) = artist_map.get_mut(&artist_ref.artisthash) {
-                        vec.retain(|h| h != trackhash);
-                    }
-                }
-                // remove from album artists
-                for album_artist in &track.albumartists {
-                    if let Some(vec) = artist_map.get_mut(&album_artist.artisthash) {
-                        vec.retain(|h| h != trackhash);
-                    }
-                }
-                // also check artisthashes field
-                for artist_hash in &track.artisthashes {
-                    if let Some(vec) = artist_map.get_mut(artist_hash) {
-                        vec.retain(|h| h != trackhash);
-                    }
-                }
-            }
-            // remove folder index
-            if let Some(folder_tracks) = self
-                .tracks_by_folder
-                .write()
-                .unwrap()
-                .get_mut(&track.folder)
-            {
-                folder_tracks.retain(|h| h != trackhash);
-            }
-            true
-        } else {
-            false
+        let hashes = HashSet::from([trackhash.to_string()]);
+        let removed = self.index.write().unwrap().remove_many(&hashes);
+        if removed.is_empty() {
+            return false;
         }
+        SearchStore::get().remove_track(trackhash);
+        true
     }
 
-    /// Mark or unmark favorite (no user scoping; toggles flag list)
-    pub fn mark_favorite(&self, trackhash: &str, favorite: bool) {
-        if let Some(mut track) = self.get_by_hash(trackhash) {
+    /// Mark or unmark a track as favorite for a user
+    pub fn mark_favorite(&self, trackhash: &str, user_id: i64, favorite: bool) {
+        self.modify(trackhash, |track| {
             if favorite {
-                track.fav_userids.insert(0);
+                track.fav_userids.insert(user_id);
             } else {
-                track.fav_userids.remove(&0);
+                track.fav_userids.remove(&user_id);
             }
-            self.add(track);
-        }
+        });
+    }
+
+    /// Set the MusicBrainz recording ID of a track
+    pub fn set_mbid(&self, trackhash: &str, mbid: &str) {
+        self.modify(trackhash, |track| track.mbid = mbid.to_string());
+    }
+
+    /// Set whether a track has local lyrics
+    pub fn set_has_lyrics(&self, trackhash: &str, has_lyrics: bool) {
+        self.modify(trackhash, |track| track.has_lyrics = has_lyrics);
     }
 
     /// Set play count and optionally last played timestamp
     pub fn set_play_count(&self, trackhash: &str, playcount: i32) {
-        if let Some(mut track) = self.get_by_hash(trackhash) {
-            track.playcount = playcount;
-            self.add(track);
-        }
+        self.modify(trackhash, |track| track.playcount = playcount);
     }
 
     /// Load all tracks from the database into the in-memory store
@@ -380,57 +443,111 @@ impl TrackStore {
 
     /// Remove tracks by paths
     pub fn remove_by_paths(&self, paths: &[String]) {
-        let mut tracks = self.tracks.write().unwrap();
-        let mut path_map = self.tracks_by_path.write().unwrap();
-        let mut album_map = self.tracks_by_album.write().unwrap();
-        let mut artist_map = self.tracks_by_artist.write().unwrap();
-        let mut folder_map = self.tracks_by_folder.write().unwrap();
+        let mut index = self.index.write().unwrap();
 
+        // only tracks stored from one of the paths go, a copy of a track in
+        // another file keeps it in the store
+        let mut hashes = HashSet::new();
         for path in paths {
             let normalized = normalize_path(path);
-            let hash_opt = path_map
+            let Some(hash) = index
+                .by_path
                 .remove(path)
-                .or_else(|| path_map.remove(&normalized));
-
-            if let Some(hash) = hash_opt {
-                if let Some(track) = tracks.remove(&hash) {
-                    // Remove from album index
-                    if let Some(album_tracks) = album_map.get_mut(&track.albumhash) {
-                        album_tracks.retain(|h| h != &hash);
-                    }
-
-                    // remove from all artist indices
-                    for artist_ref in &track.artists {
-                        if let Some(artist_tracks) = artist_map.get_mut(&artist_ref.artisthash) {
-                            artist_tracks.retain(|h| h != &hash);
-                        }
-                    }
-                    for album_artist in &track.albumartists {
-                        if let Some(artist_tracks) = artist_map.get_mut(&album_artist.artisthash) {
-                            artist_tracks.retain(|h| h != &hash);
-                        }
-                    }
-                    for artist_hash in &track.artisthashes {
-                        if let Some(artist_tracks) = artist_map.get_mut(artist_hash) {
-                            artist_tracks.retain(|h| h != &hash);
-                        }
-                    }
-
-                    // Remove from folder index
-                    if let Some(folder_tracks) = folder_map.get_mut(&track.folder) {
-                        folder_tracks.retain(|h| h != &hash);
-                    }
-                }
+                .or_else(|| index.by_path.remove(&normalized))
+            else {
+                continue;
+            };
+            if index
+                .tracks
+                .get(&hash)
+                .is_some_and(|t| t.filepath == *path || t.filepath == normalized)
+            {
+                hashes.insert(hash);
             }
         }
+
+        let removed = index.remove_many(&hashes);
+        drop(index);
+
+        let search = SearchStore::get();
+        for track in removed {
+            search.remove_track(&track.trackhash);
+        }
     }
 
     /// Clear the store
     pub fn clear(&self) {
-        self.tracks.write().unwrap().clear();
-        self.tracks_by_path.write().unwrap().clear();
-        self.tracks_by_album.write().unwrap().clear();
-        self.tracks_by_artist.write().unwrap().clear();
-        self.tracks_by_folder.write().unwrap().clear();
+        *self.index.write().unwrap() = TrackIndex::default();
+        SearchStore::get().clear_tracks();
+    }
+}
+
+fn owned(tracks: Vec<Arc<Track>>) -> Vec<Track> {
+    tracks.into_iter().map(Arc::unwrap_or_clone).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn track(hash: &str, path: &str, album: &str) -> Track {
+        let mut track = Track::new();
+        track.trackhash = hash.to_string();
+        track.filepath = path.to_string();
+        track.folder = Path::new(path)
+            .parent()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        track.albumhash = album.to_string();
+        track.artisthashes = vec![format!("artist-{}", album)];
+        track.genrehashes = vec![format!("genre-{}", album)];
+        track
+    }
+
+    #[test]
+    fn test_index_replaces_without_duplicates() {
+        let mut index = TrackIndex::default();
+        index.insert(track("a", "/music/x/a.flac", "x"));
+        index.insert(track("b", "/music/x/b.flac", "x"));
+
+        // the same file again moves the track to its new album
+        index.insert(track("a", "/music/x/a.flac", "y"));
+        assert_eq!(index.by_album["x"], ["b"]);
+        assert_eq!(index.by_album["y"], ["a"]);
+        assert!(!index.by_artist["artist-x"].contains(&"a".to_string()));
+        assert_eq!(index.by_genre["genre-x"], ["b"]);
+        assert_eq!(index.by_genre["genre-y"], ["a"]);
+
+        // another copy of the track is indexed once more by path only
+        index.insert(track("a", "/music/z/a.mp3", "y"));
+        assert_eq!(index.by_album["y"], ["a"]);
+        assert_eq!(index.by_path.len(), 3);
+
+        let removed = index.remove_many(&HashSet::from(["b".to_string()]));
+        assert_eq!(removed.len(), 1);
+        assert!(!index.by_album.contains_key("x"));
+        assert!(!index.by_genre.contains_key("genre-x"));
+        assert!(!index.by_path.contains_key("/music/x/b.flac"));
+        assert_eq!(index.tracks.len(), 1);
+    }
+
+    #[test]
+    fn test_edits_copy_shared_tracks() {
+        let store = TrackStore {
+            index: RwLock::new(TrackIndex::default()),
+            album_groups: RwLock::new(HashMap::new()),
+        };
+        store
+            .index
+            .write()
+            .unwrap()
+            .insert(track("a", "/music/x/a.flac", "x"));
+
+        let held = store.get_shared("a").unwrap();
+        store.set_play_count("a", 3);
+        assert_eq!(held.playcount, 0);
+        assert_eq!(store.get_shared("a").unwrap().playcount, 3);
     }
 }
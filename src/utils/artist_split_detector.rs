@@ -580,6 +580,7 @@ pub fn split_artists_smart(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     fn make_separators() -> HashSet<String> {
         [
@@ -724,4 +725,31 @@ mod tests {
         let decision = detector.should_split("Florence", "&", "The Machine", "Florence & The Machine");
         assert_eq!(decision, SplitDecision::KeepTogether);
     }
+
+    proptest! {
+        #[test]
+        fn prop_split_never_panics(
+            src in "\\PC{0,60}",
+            ignore in proptest::collection::hash_set("\\PC{0,12}", 0..3),
+        ) {
+            let seps = make_separators();
+            for part in split_artists_smart(&src, &seps, &ignore) {
+                prop_assert!(!part.is_empty());
+                prop_assert_eq!(part.trim(), part.as_str());
+            }
+        }
+
+        #[test]
+        fn prop_parts_come_from_source(
+            names in proptest::collection::vec("[A-Za-z]{1,10}( [A-Za-z]{1,10})?", 1..5),
+            sep in prop_oneof![Just("; "), Just("/"), Just(", "), Just(" & ")],
+        ) {
+            let src = names.join(sep);
+            let parts = split_artists_smart(&src, &make_separators(), &HashSet::new());
+            prop_assert!(!parts.is_empty());
+            for part in &parts {
+                prop_assert!(src.contains(part.as_str()));
+            }
+        }
+    }
 }
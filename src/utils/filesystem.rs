@@ -164,9 +164,27 @@ pub fn is_child_of(path: &Path, parent: &Path) -> bool {
     path.starts_with(parent) && path != parent
 }
 
+/// Short reason a path could not be read, permission problems are named
+/// plainly so they stand out from other failures
+pub fn describe_io_error(error: &std::io::Error) -> String {
+    match error.kind() {
+        std::io::ErrorKind::PermissionDenied => "permission denied".to_string(),
+        std::io::ErrorKind::NotFound => "not found".to_string(),
+        _ => error.to_string(),
+    }
+}
+
+/// Why a directory cannot be listed, None when it can
+pub fn dir_read_error(path: &Path) -> Option<String> {
+    std::fs::read_dir(path)
+        .err()
+        .map(|e| describe_io_error(&e))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_is_audio_file() {
@@ -195,4 +213,18 @@ mod tests {
         #[cfg(not(windows))]
         assert_eq!(normalized, path);
     }
+
+    proptest! {
+        #[test]
+        fn prop_normalize_path_is_idempotent(path in "\\PC{0,60}") {
+            let normalized = normalize_path(&path);
+            prop_assert_eq!(normalize_path(&normalized), normalized.clone());
+
+            #[cfg(windows)]
+            prop_assert!(!normalized.contains('\\'));
+
+            #[cfg(not(windows))]
+            prop_assert_eq!(normalized, path);
+        }
+    }
 }
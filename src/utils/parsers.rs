@@ -65,7 +65,6 @@ pub fn split_artists(
 
     let mut result = Vec::new();
     let mut last_end = 0;
-    let src_lower = src.to_lowercase();
 
     for mat in re.find_iter(src) {
         if mat.start() < last_end {
@@ -78,7 +77,6 @@ pub fn split_artists(
         if !trimmed.is_empty() {
             // Check if this part + separator is in ignore list
             let potential_combined = format!("{}{}", trimmed.to_lowercase(), mat.as_str());
-            let remaining = &src_lower[mat.end()..];
 
             let mut found_ignored = false;
             for ignored in ignore_list {
@@ -115,6 +113,31 @@ pub fn split_artists(
     result
 }
 
+/// Split a genre tag by the configured separators, dropping empty parts
+pub fn split_genres(src: &str, separators: &std::collections::HashSet<String>) -> Vec<String> {
+    separators
+        .iter()
+        .filter(|sep| !sep.is_empty())
+        .fold(vec![src], |acc, sep| {
+            acc.into_iter().flat_map(|s| s.split(sep.as_str())).collect()
+        })
+        .into_iter()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Year from the leading four digits of a date tag
+///
+/// handles "2025", "2025-01-15" and "2025-01-15T12:34:00"
+pub fn parse_year(value: &str) -> Option<i32> {
+    value
+        .trim()
+        .get(..4)
+        .filter(|year| year.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|year| year.parse().ok())
+}
+
 /// Remove "(prod. by X)" from track title
 pub fn remove_prod_by(title: &str) -> String {
     PROD_BY_PATTERN.replace_all(title, "").trim().to_string()
@@ -255,6 +278,7 @@ pub fn clean_title(title: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
     use std::collections::HashSet;
 
     #[test]
@@ -327,4 +351,74 @@ mod tests {
         let result = split_artists("Tyler, The Creator, Another Artist", &seps, &ignore);
         assert_eq!(result, vec!["Tyler, The Creator", "Another Artist"]);
     }
+
+    #[test]
+    fn test_parse_year() {
+        assert_eq!(parse_year("2025"), Some(2025));
+        assert_eq!(parse_year(" 2025-01-15T12:34:00"), Some(2025));
+        assert_eq!(parse_year("15/01/2025"), None);
+        // multibyte text inside the first four bytes used to panic
+        assert_eq!(parse_year("20é5"), None);
+        assert_eq!(parse_year("٢٠٢٥"), None);
+    }
+
+    fn separators() -> impl Strategy<Value = HashSet<String>> {
+        proptest::collection::hash_set(
+            prop_oneof![
+                Just(";".to_string()),
+                Just("/".to_string()),
+                Just(", ".to_string()),
+                Just(" & ".to_string()),
+                "\\PC{0,3}",
+            ],
+            0..5,
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn prop_split_artists_parts_are_trimmed(
+            src in "\\PC{0,40}",
+            seps in separators(),
+            ignore in proptest::collection::hash_set("\\PC{0,12}", 0..3),
+        ) {
+            for part in split_artists(&src, &seps, &ignore) {
+                prop_assert!(!part.is_empty());
+                prop_assert_eq!(part.trim(), part.as_str());
+            }
+        }
+
+        #[test]
+        fn prop_split_artists_without_separators_keeps_name(name in "\\PC{1,40}") {
+            let parts = split_artists(&name, &HashSet::new(), &HashSet::new());
+            prop_assert_eq!(parts, vec![name.trim().to_string()]);
+        }
+
+        #[test]
+        fn prop_split_genres_parts_are_trimmed(src in "\\PC{0,40}", seps in separators()) {
+            for part in split_genres(&src, &seps) {
+                prop_assert!(!part.is_empty());
+                prop_assert_eq!(part.trim(), part.as_str());
+                for sep in seps.iter().filter(|sep| !sep.is_empty()) {
+                    prop_assert!(!part.contains(sep.as_str()));
+                }
+            }
+        }
+
+        #[test]
+        fn prop_parse_year_never_panics(value in "\\PC{0,12}") {
+            if let Some(year) = parse_year(&value) {
+                prop_assert!((0..=9999).contains(&year));
+            }
+        }
+
+        #[test]
+        fn prop_title_cleaning_never_panics(title in "\\PC{0,60}") {
+            let cleaned = clean_title(&title);
+            prop_assert_eq!(cleaned.trim(), cleaned.as_str());
+            remove_remaster_info(&title);
+            extract_featured_artists(&title);
+            parse_filename(&format!("{}.mp3", title));
+        }
+    }
 }